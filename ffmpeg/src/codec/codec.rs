@@ -1,6 +1,4 @@
-use std::ffi::CStr;
-use std::str::from_utf8_unchecked;
-
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 use super::{Audio, Capabilities, Id, Profile, Video};
@@ -34,7 +32,7 @@ impl Codec {
     }
 
     pub fn name(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).name).to_bytes()) }
+        unsafe { str_from_c_ptr((*self.as_ptr()).name) }
     }
 
     pub fn description(&self) -> &str {
@@ -43,7 +41,7 @@ impl Codec {
             if long_name.is_null() {
                 ""
             } else {
-                from_utf8_unchecked(CStr::from_ptr(long_name).to_bytes())
+                str_from_c_ptr(long_name)
             }
         }
     }
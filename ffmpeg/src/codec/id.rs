@@ -1,7 +1,5 @@
-use std::ffi::CStr;
-use std::str::from_utf8_unchecked;
-
 use crate::util::media;
+use crate::util::str_from_c_ptr;
 use sys::ffi::*;
 
 #[allow(non_camel_case_types)]
@@ -679,7 +677,7 @@ impl Id {
     }
 
     pub fn name(&self) -> &'static str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr(avcodec_get_name((*self).into())).to_bytes()) }
+        unsafe { str_from_c_ptr(avcodec_get_name((*self).into())) }
     }
 }
 
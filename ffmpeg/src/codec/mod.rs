@@ -48,9 +48,7 @@ pub mod decoder;
 pub mod encoder;
 pub mod traits;
 
-use std::ffi::CStr;
-use std::str::from_utf8_unchecked;
-
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 pub fn version() -> u32 {
@@ -58,9 +56,9 @@ pub fn version() -> u32 {
 }
 
 pub fn configuration() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::avcodec_configuration()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::avcodec_configuration()) }
 }
 
 pub fn license() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::avcodec_license()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::avcodec_license()) }
 }
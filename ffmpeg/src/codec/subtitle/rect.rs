@@ -1,8 +1,9 @@
-use std::ffi::CStr;
+use std::borrow::Cow;
 use std::marker::PhantomData;
-use std::str::from_utf8_unchecked;
+use std::slice;
 
 use super::{Flags, Type};
+use crate::util::{bytes_from_c_ptr, string_from_c_ptr_lossy};
 use sys::ffi::*;
 
 // #[cfg(not(feature = "ffmpeg5"))]
@@ -88,6 +89,34 @@ impl Bitmap<'_> {
         unsafe { (*self.as_ptr()).nb_colors as usize }
     }
 
+    pub fn stride(&self) -> usize {
+        unsafe { (*self.as_ptr()).linesize[0].max(0) as usize }
+    }
+}
+
+impl<'a> Bitmap<'a> {
+    /// Palette indices of the pixels, `stride()` bytes per row.
+    pub fn indices(&self) -> &'a [u8] {
+        unsafe {
+            let data = (*self.as_ptr()).data[0];
+            if data.is_null() {
+                return &[];
+            }
+            slice::from_raw_parts(data, self.stride() * self.height() as usize)
+        }
+    }
+
+    /// Palette of `colors()` entries, as ARGB in native byte order.
+    pub fn palette(&self) -> &'a [u32] {
+        unsafe {
+            let data = (*self.as_ptr()).data[1];
+            if data.is_null() {
+                return &[];
+            }
+            slice::from_raw_parts(data as *const u32, self.colors())
+        }
+    }
+
     // XXX: must split Picture and PictureMut
     // #[cfg(not(feature = "ffmpeg5"))]
     // pub fn picture(&self, format: crate::format::Pixel) -> Picture<'a> {
@@ -121,9 +150,14 @@ impl Text<'_> {
     }
 }
 
-impl Text<'_> {
-    pub fn get(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).text).to_bytes()) }
+// Subtitle text comes from the media, and is not necessarily UTF-8.
+impl<'a> Text<'a> {
+    pub fn get(&self) -> Cow<'a, str> {
+        unsafe { string_from_c_ptr_lossy((*self.as_ptr()).text) }
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+        unsafe { bytes_from_c_ptr((*self.as_ptr()).text) }
     }
 }
 
@@ -146,8 +180,12 @@ impl Ass<'_> {
     }
 }
 
-impl Ass<'_> {
-    pub fn get(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).ass).to_bytes()) }
+impl<'a> Ass<'a> {
+    pub fn get(&self) -> Cow<'a, str> {
+        unsafe { string_from_c_ptr_lossy((*self.as_ptr()).ass) }
+    }
+
+    pub fn get_bytes(&self) -> &'a [u8] {
+        unsafe { bytes_from_c_ptr((*self.as_ptr()).ass) }
     }
 }
@@ -2,10 +2,10 @@ pub mod extensions;
 pub mod input;
 pub mod output;
 
-use std::ffi::CStr;
+use std::borrow::Cow;
 use std::marker::PhantomData;
-use std::str::from_utf8_unchecked;
 
+use crate::util::{bytes_from_c_ptr, str_from_c_ptr, string_from_c_ptr_lossy};
 use sys::ffi;
 
 pub struct Info<'a> {
@@ -31,15 +31,23 @@ impl Info<'_> {
     }
 }
 
-impl Info<'_> {
-    pub fn name(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).device_name).to_bytes()) }
+// Device names come from the operating system, and are not necessarily UTF-8 (like names in the
+// local code page on Windows).
+impl<'a> Info<'a> {
+    pub fn name(&self) -> Cow<'a, str> {
+        unsafe { string_from_c_ptr_lossy((*self.as_ptr()).device_name) }
     }
 
-    pub fn description(&self) -> &str {
-        unsafe {
-            from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).device_description).to_bytes())
-        }
+    pub fn name_bytes(&self) -> &'a [u8] {
+        unsafe { bytes_from_c_ptr((*self.as_ptr()).device_name) }
+    }
+
+    pub fn description(&self) -> Cow<'a, str> {
+        unsafe { string_from_c_ptr_lossy((*self.as_ptr()).device_description) }
+    }
+
+    pub fn description_bytes(&self) -> &'a [u8] {
+        unsafe { bytes_from_c_ptr((*self.as_ptr()).device_description) }
     }
 }
 
@@ -54,9 +62,9 @@ pub fn version() -> u32 {
 }
 
 pub fn configuration() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::avdevice_configuration()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::avdevice_configuration()) }
 }
 
 pub fn license() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::avdevice_license()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::avdevice_license()) }
 }
@@ -1,8 +1,7 @@
-use std::ffi::CStr;
 use std::marker::PhantomData;
-use std::str::from_utf8_unchecked;
 
 use super::{Flags, Pad};
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 pub struct Filter {
@@ -23,7 +22,7 @@ impl Filter {
     }
 
     pub fn name(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).name).to_bytes()) }
+        unsafe { str_from_c_ptr((*self.as_ptr()).name) }
     }
 
     pub fn description(&self) -> Option<&str> {
@@ -33,7 +32,7 @@ impl Filter {
             if ptr.is_null() {
                 None
             } else {
-                Some(from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes()))
+                Some(str_from_c_ptr(ptr))
             }
         }
     }
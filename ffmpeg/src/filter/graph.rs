@@ -1,7 +1,8 @@
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use std::ptr;
 
 use super::{Context, Filter};
+use crate::util::string_from_c_ptr_lossy;
 use crate::Error;
 use libc::c_int;
 use sys::ffi;
@@ -85,8 +86,8 @@ impl Graph {
     pub fn dump(&self) -> String {
         unsafe {
             let ptr = ffi::avfilter_graph_dump(self.as_ptr() as *mut _, ptr::null());
-            let cstr = std::str::from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes());
-            let string = cstr.to_owned();
+            // The dump contains the arguments of the filters, which may not be valid UTF-8.
+            let string = string_from_c_ptr_lossy(ptr).into_owned();
 
             ffi::av_free(ptr as *mut _);
 
@@ -13,9 +13,9 @@ pub use self::context::{Context, Sink, Source};
 pub mod graph;
 pub use self::graph::Graph;
 
-use std::ffi::{CStr, CString};
-use std::str::from_utf8_unchecked;
+use std::ffi::CString;
 
+use crate::util::str_from_c_ptr;
 use sys::ffi::*;
 
 // #[cfg(not(feature = "ffmpeg5"))]
@@ -41,11 +41,11 @@ pub fn version() -> u32 {
 }
 
 pub fn configuration() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(avfilter_configuration()).to_bytes()) }
+    unsafe { str_from_c_ptr(avfilter_configuration()) }
 }
 
 pub fn license() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(avfilter_license()).to_bytes()) }
+    unsafe { str_from_c_ptr(avfilter_license()) }
 }
 
 pub fn find(name: &str) -> Option<Filter> {
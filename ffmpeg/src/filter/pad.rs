@@ -1,8 +1,7 @@
-use std::ffi::CStr;
 use std::marker::PhantomData;
-use std::str::from_utf8_unchecked;
 
 use crate::media;
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 pub struct Pad<'a> {
@@ -38,7 +37,7 @@ impl Pad<'_> {
             if ptr.is_null() {
                 None
             } else {
-                Some(from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes()))
+                Some(str_from_c_ptr(ptr))
             }
         }
     }
@@ -1,6 +1,4 @@
-use std::ffi::CStr;
-use std::str::from_utf8_unchecked;
-
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 pub struct Input {
@@ -23,11 +21,11 @@ impl Input {
 
 impl Input {
     pub fn name(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).name).to_bytes()) }
+        unsafe { str_from_c_ptr((*self.as_ptr()).name) }
     }
 
     pub fn description(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).long_name).to_bytes()) }
+        unsafe { str_from_c_ptr((*self.as_ptr()).long_name) }
     }
 
     pub fn extensions(&self) -> Vec<&str> {
@@ -37,9 +35,7 @@ impl Input {
             if ptr.is_null() {
                 Vec::new()
             } else {
-                from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes())
-                    .split(',')
-                    .collect()
+                str_from_c_ptr(ptr).split(',').collect()
             }
         }
     }
@@ -51,9 +47,7 @@ impl Input {
             if ptr.is_null() {
                 Vec::new()
             } else {
-                from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes())
-                    .split(',')
-                    .collect()
+                str_from_c_ptr(ptr).split(',').collect()
             }
         }
     }
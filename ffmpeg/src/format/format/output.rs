@@ -1,8 +1,8 @@
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use std::path::Path;
 use std::ptr;
-use std::str::from_utf8_unchecked;
 
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 use super::Flags;
@@ -28,11 +28,11 @@ impl Output {
 
 impl Output {
     pub fn name(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).name).to_bytes()) }
+        unsafe { str_from_c_ptr((*self.as_ptr()).name) }
     }
 
     pub fn description(&self) -> &str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).long_name).to_bytes()) }
+        unsafe { str_from_c_ptr((*self.as_ptr()).long_name) }
     }
 
     pub fn extensions(&self) -> Vec<&str> {
@@ -42,9 +42,7 @@ impl Output {
             if ptr.is_null() {
                 Vec::new()
             } else {
-                from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes())
-                    .split(',')
-                    .collect()
+                str_from_c_ptr(ptr).split(',').collect()
             }
         }
     }
@@ -56,9 +54,7 @@ impl Output {
             if ptr.is_null() {
                 Vec::new()
             } else {
-                from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes())
-                    .split(',')
-                    .collect()
+                str_from_c_ptr(ptr).split(',').collect()
             }
         }
     }
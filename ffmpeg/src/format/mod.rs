@@ -1,6 +1,7 @@
 pub use crate::util::format::{pixel, Pixel};
 pub use crate::util::format::{sample, Sample};
 use crate::util::interrupt;
+use crate::util::str_from_c_ptr;
 use crate::{Dictionary, Error, Format};
 
 pub mod stream;
@@ -19,10 +20,9 @@ pub use self::format::{Input, Output};
 pub mod network;
 
 use libc::c_int;
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use std::path::Path;
 use std::ptr;
-use std::str::from_utf8_unchecked;
 use sys::ffi::*;
 
 // #[cfg(not(feature = "ffmpeg5"))]
@@ -51,11 +51,11 @@ pub fn version() -> u32 {
 }
 
 pub fn configuration() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(avformat_configuration()).to_bytes()) }
+    unsafe { str_from_c_ptr(avformat_configuration()) }
 }
 
 pub fn license() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(avformat_license()).to_bytes()) }
+    unsafe { str_from_c_ptr(avformat_license()) }
 }
 
 // XXX: use to_cstring when stable
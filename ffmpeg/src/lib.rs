@@ -58,12 +58,12 @@ pub use codec::threading;
 // #[cfg(feature = "codec")]
 pub use codec::{decoder, encoder};
 
-// #[cfg(feature = "device")]
+#[cfg(feature = "device")]
 pub mod device;
 
-// #[cfg(feature = "filter")]
+#[cfg(feature = "filter")]
 pub mod filter;
-// #[cfg(feature = "filter")]
+#[cfg(feature = "filter")]
 pub use filter::Filter;
 
 pub mod software;
@@ -77,19 +77,22 @@ fn init_format() {
     format::register_all();
 }
 
-// #[cfg(feature = "device")]
+#[cfg(feature = "device")]
 fn init_device() {
     device::register_all();
 }
 
-// #[cfg(not(feature = "device"))]
-// fn init_device() {}
+#[cfg(not(feature = "device"))]
+fn init_device() {}
 
-// #[cfg(all(feature = "filter", not(feature = "ffmpeg5")))]
+#[cfg(feature = "filter")]
 fn init_filter() {
     filter::register_all();
 }
 
+#[cfg(not(feature = "filter"))]
+fn init_filter() {}
+
 pub fn init() -> Result<(), Error> {
     init_error();
     // #[cfg(not(feature = "ffmpeg5"))]
@@ -18,9 +18,7 @@ pub use self::context::Context;
 
 mod extensions;
 
-use std::ffi::CStr;
-use std::str::from_utf8_unchecked;
-
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 pub fn version() -> u32 {
@@ -28,9 +26,9 @@ pub fn version() -> u32 {
 }
 
 pub fn configuration() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::swresample_configuration()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::swresample_configuration()) }
 }
 
 pub fn license() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::swresample_license()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::swresample_license()) }
 }
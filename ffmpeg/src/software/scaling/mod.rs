@@ -17,9 +17,7 @@ pub use self::context::Context;
 
 mod extensions;
 
-use std::ffi::CStr;
-use std::str::from_utf8_unchecked;
-
+use crate::util::str_from_c_ptr;
 use sys::ffi;
 
 pub fn version() -> u32 {
@@ -27,9 +25,9 @@ pub fn version() -> u32 {
 }
 
 pub fn configuration() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::swscale_configuration()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::swscale_configuration()) }
 }
 
 pub fn license() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(ffi::swscale_license()).to_bytes()) }
+    unsafe { str_from_c_ptr(ffi::swscale_license()) }
 }
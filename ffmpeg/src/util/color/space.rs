@@ -1,6 +1,4 @@
-use std::ffi::CStr;
-use std::str::from_utf8_unchecked;
-
+use crate::util::str_from_c_ptr;
 use sys::ffi::*;
 
 #[derive(Eq, PartialEq, Clone, Copy, Debug)]
@@ -39,8 +37,7 @@ impl Space {
         }
         unsafe {
             let ptr = av_color_space_name((*self).into());
-            ptr.as_ref()
-                .map(|ptr| from_utf8_unchecked(CStr::from_ptr(ptr).to_bytes()))
+            ptr.as_ref().map(|ptr| str_from_c_ptr(ptr))
         }
     }
 }
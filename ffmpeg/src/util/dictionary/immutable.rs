@@ -1,10 +1,11 @@
-use std::ffi::{CStr, CString};
+use std::borrow::Cow;
+use std::ffi::CString;
 use std::fmt;
 use std::marker::PhantomData;
 use std::ptr;
-use std::str::from_utf8_unchecked;
 
 use super::{Iter, Owned};
+use crate::util::string_from_c_ptr_lossy;
 use sys::ffi;
 
 pub struct Ref<'a> {
@@ -27,7 +28,7 @@ impl Ref<'_> {
 }
 
 impl<'a> Ref<'a> {
-    pub fn get(&'a self, key: &str) -> Option<&'a str> {
+    pub fn get(&'a self, key: &str) -> Option<Cow<'a, str>> {
         unsafe {
             let key = CString::new(key).unwrap();
             let entry = ffi::av_dict_get(self.as_ptr(), key.as_ptr(), ptr::null_mut(), 0);
@@ -35,9 +36,7 @@ impl<'a> Ref<'a> {
             if entry.is_null() {
                 None
             } else {
-                Some(from_utf8_unchecked(
-                    CStr::from_ptr((*entry).value).to_bytes(),
-                ))
+                Some(string_from_c_ptr_lossy((*entry).value))
             }
         }
     }
@@ -52,7 +51,7 @@ impl<'a> Ref<'a> {
 }
 
 impl<'a> IntoIterator for &'a Ref<'a> {
-    type Item = (&'a str, &'a str);
+    type Item = (Cow<'a, str>, Cow<'a, str>);
     type IntoIter = Iter<'a>;
 
     fn into_iter(self) -> Self::IntoIter {
@@ -65,3 +64,28 @@ impl fmt::Debug for Ref<'_> {
         fmt.debug_map().entries(self.iter()).finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_utf8_is_replaced() {
+        unsafe {
+            let mut dictionary = ptr::null_mut();
+            ffi::av_dict_set(
+                &mut dictionary,
+                c"title".as_ptr(),
+                c"caf\xe9 au lait".as_ptr(),
+                0,
+            );
+            let metadata = Ref::wrap(dictionary);
+            assert_eq!(metadata.get("title").unwrap(), "caf\u{fffd} au lait");
+            assert_eq!(
+                metadata.iter().collect::<Vec<_>>(),
+                [(Cow::from("title"), Cow::from("caf\u{fffd} au lait"))],
+            );
+            ffi::av_dict_free(&mut dictionary);
+        }
+    }
+}
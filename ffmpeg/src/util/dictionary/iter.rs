@@ -1,8 +1,9 @@
-use std::ffi::{CStr, CString};
+use std::borrow::Cow;
+use std::ffi::CString;
 use std::marker::PhantomData;
 use std::ptr;
-use std::str::from_utf8_unchecked;
 
+use crate::util::string_from_c_ptr_lossy;
 use sys::ffi;
 
 pub struct Iter<'a> {
@@ -24,7 +25,7 @@ impl Iter<'_> {
 }
 
 impl<'a> Iterator for Iter<'a> {
-    type Item = (&'a str, &'a str);
+    type Item = (Cow<'a, str>, Cow<'a, str>);
 
     fn next(&mut self) -> Option<<Self as Iterator>::Item> {
         unsafe {
@@ -37,8 +38,8 @@ impl<'a> Iterator for Iter<'a> {
             );
 
             if !entry.is_null() {
-                let key = from_utf8_unchecked(CStr::from_ptr((*entry).key).to_bytes());
-                let val = from_utf8_unchecked(CStr::from_ptr((*entry).value).to_bytes());
+                let key = string_from_c_ptr_lossy((*entry).key);
+                let val = string_from_c_ptr_lossy((*entry).value);
 
                 self.cur = entry;
 
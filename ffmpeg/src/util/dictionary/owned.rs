@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt;
 use std::iter::FromIterator;
 use std::ops::{Deref, DerefMut};
@@ -65,6 +66,18 @@ impl<'b> FromIterator<&'b (&'b str, &'b str)> for Owned<'_> {
     }
 }
 
+impl<'b> FromIterator<(Cow<'b, str>, Cow<'b, str>)> for Owned<'_> {
+    fn from_iter<T: IntoIterator<Item = (Cow<'b, str>, Cow<'b, str>)>>(iterator: T) -> Self {
+        let mut result = Owned::new();
+
+        for (key, value) in iterator {
+            result.set(&key, &value);
+        }
+
+        result
+    }
+}
+
 impl FromIterator<(String, String)> for Owned<'_> {
     fn from_iter<T: IntoIterator<Item = (String, String)>>(iterator: T) -> Self {
         let mut result = Owned::new();
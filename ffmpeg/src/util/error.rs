@@ -1,8 +1,8 @@
 use std::error;
-use std::ffi::CStr;
 use std::fmt;
 use std::io;
 
+use crate::util::string_from_c_ptr_lossy;
 use sys::ffi::{
     self, AVERROR_BSF_NOT_FOUND, AVERROR_BUFFER_TOO_SMALL, AVERROR_BUG, AVERROR_BUG2,
     AVERROR_DECODER_NOT_FOUND, AVERROR_DEMUXER_NOT_FOUND, AVERROR_ENCODER_NOT_FOUND, AVERROR_EOF,
@@ -150,14 +150,12 @@ impl From<Error> for io::Error {
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        f.write_str(unsafe {
-            std::str::from_utf8_unchecked(
-                CStr::from_ptr(match *self {
-                    Error::Other { errno } => libc::strerror(errno),
-                    _ => STRINGS[index(self)].as_ptr(),
-                })
-                .to_bytes(),
-            )
+        // Messages of `strerror` are localized, and not necessarily UTF-8.
+        f.write_str(&unsafe {
+            string_from_c_ptr_lossy(match *self {
+                Error::Other { errno } => libc::strerror(errno),
+                _ => STRINGS[index(self)].as_ptr(),
+            })
         })
     }
 }
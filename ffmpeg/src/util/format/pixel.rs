@@ -1,7 +1,9 @@
 use std::error;
-use std::ffi::{CStr, CString, NulError};
+use std::ffi::{CString, NulError};
 use std::fmt;
-use std::str::{from_utf8_unchecked, FromStr};
+use std::str::FromStr;
+
+use crate::util::str_from_c_ptr;
 use sys::ffi::*;
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
@@ -457,7 +459,7 @@ impl Descriptor {
     }
 
     pub fn name(self) -> &'static str {
-        unsafe { from_utf8_unchecked(CStr::from_ptr((*self.as_ptr()).name).to_bytes()) }
+        unsafe { str_from_c_ptr((*self.as_ptr()).name) }
     }
 
     pub fn nb_components(self) -> u8 {
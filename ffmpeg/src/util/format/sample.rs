@@ -1,9 +1,9 @@
-use std::ffi::{CStr, CString};
+use std::ffi::CString;
 use std::ops::Index;
 use std::ptr;
 use std::slice;
-use std::str::from_utf8_unchecked;
 
+use crate::util::str_from_c_ptr;
 use libc::{c_int, c_void};
 use sys::ffi::*;
 
@@ -28,9 +28,7 @@ pub enum Type {
 impl Sample {
     #[inline]
     pub fn name(&self) -> &'static str {
-        unsafe {
-            from_utf8_unchecked(CStr::from_ptr(av_get_sample_fmt_name((*self).into())).to_bytes())
-        }
+        unsafe { str_from_c_ptr(av_get_sample_fmt_name((*self).into())) }
     }
 
     #[inline]
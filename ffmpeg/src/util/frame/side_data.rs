@@ -1,7 +1,7 @@
-use std::ffi::CStr;
 use std::marker::PhantomData;
 use std::slice;
-use std::str::from_utf8_unchecked;
+
+use crate::util::str_from_c_ptr;
 use sys::ffi::*;
 
 use super::Frame;
@@ -72,9 +72,7 @@ pub enum Type {
 impl Type {
     #[inline]
     pub fn name(&self) -> &'static str {
-        unsafe {
-            from_utf8_unchecked(CStr::from_ptr(av_frame_side_data_name((*self).into())).to_bytes())
-        }
+        unsafe { str_from_c_ptr(av_frame_side_data_name((*self).into())) }
     }
 }
 
@@ -19,11 +19,11 @@ pub mod time;
 pub mod channel_layout;
 
 use std::{
+    borrow::Cow,
     ffi::{CStr, CString, OsStr},
-    str::from_utf8_unchecked,
 };
 
-use libc::c_int;
+use libc::{c_char, c_int};
 use sys::ffi::*;
 
 #[inline(always)]
@@ -33,12 +33,51 @@ pub fn version() -> u32 {
 
 #[inline(always)]
 pub fn configuration() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(avutil_configuration()).to_bytes()) }
+    unsafe { str_from_c_ptr(avutil_configuration()) }
 }
 
 #[inline(always)]
 pub fn license() -> &'static str {
-    unsafe { from_utf8_unchecked(CStr::from_ptr(avutil_license()).to_bytes()) }
+    unsafe { str_from_c_ptr(avutil_license()) }
+}
+
+/// Converts a C string from FFmpeg to `str` without assuming that it is valid UTF-8. Invalid UTF-8
+/// is cut off at the first invalid byte, so that the result can still borrow from the C string.
+/// Use [`string_from_c_ptr_lossy`] for strings that come from outside FFmpeg, like metadata and
+/// device names.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a nul-terminated string that lives for `'a`.
+pub(crate) unsafe fn str_from_c_ptr<'a>(ptr: *const c_char) -> &'a str {
+    let bytes = bytes_from_c_ptr(ptr);
+    match std::str::from_utf8(bytes) {
+        Ok(value) => value,
+        Err(err) => std::str::from_utf8(&bytes[..err.valid_up_to()]).unwrap_or_default(),
+    }
+}
+
+/// Converts a C string to `str`, replacing invalid UTF-8 with `U+FFFD`. Borrows from the C string
+/// if it is valid UTF-8.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a nul-terminated string that lives for `'a`.
+pub(crate) unsafe fn string_from_c_ptr_lossy<'a>(ptr: *const c_char) -> Cow<'a, str> {
+    String::from_utf8_lossy(bytes_from_c_ptr(ptr))
+}
+
+/// Gets the bytes of a C string, without the nul terminator. A null pointer is an empty string.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a nul-terminated string that lives for `'a`.
+pub(crate) unsafe fn bytes_from_c_ptr<'a>(ptr: *const c_char) -> &'a [u8] {
+    if ptr.is_null() {
+        &[]
+    } else {
+        CStr::from_ptr(ptr).to_bytes()
+    }
 }
 
 #[cfg(unix)]
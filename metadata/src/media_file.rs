@@ -169,7 +169,7 @@ impl MediaFileMetadata {
         let title = tagdict
             .get("title")
             .or_else(|| tagdict.get("TITLE"))
-            .map(|s| s.to_string());
+            .map(|s| s.into_owned());
 
         let tags = tagdict.to_tags();
         let filtered_tags = tagdict.to_filtered_tags();
@@ -3,6 +3,7 @@ use ffmpeg::codec::{self, Context, Parameters};
 use ffmpeg::util::channel_layout::ChannelLayout;
 use ffmpeg::DictionaryRef;
 use libc;
+use std::borrow::Cow;
 use std::io;
 use std::str::from_utf8_unchecked;
 
@@ -43,7 +44,7 @@ impl AudioMetadata {
         let language = tags
             .get("language")
             .or_else(|| tags.get("LANGUAGE"))
-            .map(str::to_string);
+            .map(Cow::into_owned);
 
         let _codec = codec_par.id();
         let codec_desc = prejudice::codec_description(codec_par);
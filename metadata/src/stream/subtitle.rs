@@ -1,5 +1,6 @@
 use ffmpeg::codec::{self, Parameters};
 use ffmpeg::DictionaryRef;
+use std::borrow::Cow;
 use std::io;
 
 use crate::prejudice;
@@ -24,7 +25,7 @@ impl SubtitleMetadata {
         let language = tags
             .get("language")
             .or_else(|| tags.get("LANGUAGE"))
-            .map(str::to_string);
+            .map(Cow::into_owned);
 
         let _codec = codec_par.id();
         let codec_desc = prejudice::codec_description(codec_par);
@@ -0,0 +1,237 @@
+//! Python bindings for rsmedia.
+//!
+//! Frames are exchanged as `numpy` arrays of shape `(height, width, 3)` with `uint8` RGB24 pixels.
+//! Decoded frames are moved into `numpy` without copying.
+//!
+//! # Example
+//!
+//! ```python
+//! import rsmedia
+//!
+//! decoder = rsmedia.Decoder("video.mp4")
+//! for timestamp, frame in decoder:
+//!     print(timestamp, frame.shape)
+//! ```
+
+use numpy::{IntoPyArray, PyArray3, PyReadonlyArray3};
+use pyo3::exceptions::PyRuntimeError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use ffmpeg::media::Type as AvMediaType;
+
+use rsmedia::encode::Settings;
+use rsmedia::{Location, Resize, Time};
+
+/// Convert an rsmedia error into a Python exception.
+fn to_py_err(err: rsmedia::Error) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+/// Decode video files and streams into `numpy` frames.
+#[pyclass(module = "rsmedia")]
+struct Decoder {
+    inner: rsmedia::Decoder,
+}
+
+#[pymethods]
+impl Decoder {
+    /// Open a decoder for the best video stream of a file or URL.
+    ///
+    /// * `source` - Path or URL to decode.
+    /// * `resize` - Optional `(width, height)` to resize frames to exactly.
+    #[new]
+    #[pyo3(signature = (source, resize=None))]
+    fn new(source: &str, resize: Option<(u32, u32)>) -> PyResult<Self> {
+        let mut builder = rsmedia::DecoderBuilder::new(Location::parse(source));
+        if let Some((width, height)) = resize {
+            builder = builder.with_resize(Resize::Exact(width, height));
+        }
+        Ok(Self {
+            inner: builder.build().map_err(to_py_err)?,
+        })
+    }
+
+    /// Size of the decoded frames as `(width, height)`.
+    #[getter]
+    fn size(&self) -> (u32, u32) {
+        self.inner.size_out()
+    }
+
+    /// Frame rate of the stream, or 0 if it is unknown.
+    #[getter]
+    fn frame_rate(&self) -> f32 {
+        self.inner.frame_rate()
+    }
+
+    /// Duration of the stream in seconds.
+    #[getter]
+    fn duration(&self) -> PyResult<f64> {
+        Ok(self.inner.duration().map_err(to_py_err)?.as_secs_f64())
+    }
+
+    /// Decode the next frame.
+    ///
+    /// Returns a tuple of the timestamp in seconds and the frame, or `None` if the decoder is
+    /// exhausted. The GIL is released while decoding.
+    fn decode<'py>(
+        &mut self,
+        py: Python<'py>,
+    ) -> PyResult<Option<(f64, Bound<'py, PyArray3<u8>>)>> {
+        let inner = &mut self.inner;
+        match py.allow_threads(|| inner.decode()) {
+            Ok((timestamp, frame)) => Ok(Some((
+                timestamp.as_secs_f64(),
+                frame.into_pyarray_bound(py),
+            ))),
+            Err(rsmedia::Error::DecodeExhausted) => Ok(None),
+            Err(err) => Err(to_py_err(err)),
+        }
+    }
+
+    /// Seek to a timestamp in milliseconds.
+    fn seek(&mut self, timestamp_milliseconds: i64) -> PyResult<()> {
+        self.inner.seek(timestamp_milliseconds).map_err(to_py_err)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__<'py>(
+        &mut self,
+        py: Python<'py>,
+    ) -> PyResult<Option<(f64, Bound<'py, PyArray3<u8>>)>> {
+        self.decode(py)
+    }
+}
+
+/// Encode `numpy` frames to an H264 video file or stream.
+#[pyclass(module = "rsmedia")]
+struct Encoder {
+    inner: rsmedia::Encoder,
+}
+
+#[pymethods]
+impl Encoder {
+    /// Open an encoder.
+    ///
+    /// * `destination` - Path or URL to encode to.
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    /// * `realtime` - Whether or not to use the realtime preset.
+    #[new]
+    #[pyo3(signature = (destination, width, height, realtime=false))]
+    fn new(destination: &str, width: usize, height: usize, realtime: bool) -> PyResult<Self> {
+        let settings = Settings::preset_h264_yuv420p(width, height, realtime);
+        Ok(Self {
+            inner: rsmedia::Encoder::new(Location::parse(destination), settings)
+                .map_err(to_py_err)?,
+        })
+    }
+
+    /// Encode a frame of shape `(height, width, 3)`.
+    ///
+    /// * `frame` - RGB24 frame.
+    /// * `timestamp` - Timestamp of the frame in seconds.
+    fn encode(
+        &mut self,
+        py: Python<'_>,
+        frame: PyReadonlyArray3<u8>,
+        timestamp: f64,
+    ) -> PyResult<()> {
+        let frame = frame.as_array().to_owned();
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.encode(&frame, Time::from_secs_f64(timestamp)))
+            .map_err(to_py_err)
+    }
+
+    /// Flush the encoder and write the trailer.
+    fn finish(&mut self, py: Python<'_>) -> PyResult<()> {
+        let inner = &mut self.inner;
+        py.allow_threads(|| inner.finish()).map_err(to_py_err)
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        py: Python<'_>,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> PyResult<()> {
+        self.finish(py)
+    }
+}
+
+/// Probe a file or URL and return its container and stream information as a `dict`.
+///
+/// * `source` - Path or URL to probe.
+#[pyfunction]
+fn probe<'py>(py: Python<'py>, source: &str) -> PyResult<Bound<'py, PyDict>> {
+    let reader = rsmedia::Reader::new(Location::parse(source)).map_err(to_py_err)?;
+    let input = &reader.input;
+
+    let info = PyDict::new_bound(py);
+    info.set_item("format", input.format().name())?;
+    info.set_item(
+        "duration",
+        Time::new(Some(input.duration()), ffmpeg::rescale::TIME_BASE).as_secs_f64(),
+    )?;
+    info.set_item("bit_rate", input.bit_rate())?;
+
+    let mut streams = Vec::new();
+    for stream in input.streams() {
+        let stream_info = PyDict::new_bound(py);
+        let parameters = stream.parameters();
+        stream_info.set_item("index", stream.index())?;
+        stream_info.set_item("codec", parameters.id().name())?;
+        stream_info.set_item("frames", stream.frames())?;
+        stream_info.set_item(
+            "duration",
+            Time::new(Some(stream.duration()), stream.time_base()).as_secs_f64(),
+        )?;
+        let context = ffmpeg::codec::Context::from_parameters(parameters)
+            .map_err(|err| to_py_err(err.into()))?;
+        match context.medium() {
+            AvMediaType::Video => {
+                stream_info.set_item("kind", "video")?;
+                let rate = stream.avg_frame_rate();
+                if rate.denominator() > 0 {
+                    stream_info.set_item("frame_rate", f64::from(rate))?;
+                }
+                if let Ok(video) = context.decoder().video() {
+                    stream_info.set_item("width", video.width())?;
+                    stream_info.set_item("height", video.height())?;
+                    stream_info.set_item("pixel_format", format!("{:?}", video.format()))?;
+                }
+            }
+            AvMediaType::Audio => {
+                stream_info.set_item("kind", "audio")?;
+                if let Ok(audio) = context.decoder().audio() {
+                    stream_info.set_item("sample_rate", audio.rate())?;
+                    stream_info.set_item("channels", audio.channels())?;
+                }
+            }
+            AvMediaType::Subtitle => stream_info.set_item("kind", "subtitle")?,
+            _ => stream_info.set_item("kind", "data")?,
+        }
+        streams.push(stream_info);
+    }
+    info.set_item("streams", streams)?;
+
+    Ok(info)
+}
+
+#[pymodule]
+#[pyo3(name = "rsmedia")]
+fn rsmedia_python(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    rsmedia::init().map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+    m.add_class::<Decoder>()?;
+    m.add_class::<Encoder>()?;
+    m.add_function(wrap_pyfunction!(probe, m)?)?;
+    Ok(())
+}
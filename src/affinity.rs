@@ -0,0 +1,239 @@
+//! CPU affinity and scheduling priority of the worker threads of decoders and encoders, to keep
+//! co-located workloads from stealing their CPU time.
+
+/// Scheduling priority of worker threads. See [`ThreadPolicy`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ThreadPriority {
+    /// Priority of the thread that builds the decoder or encoder.
+    #[default]
+    Inherit,
+    /// Lower priority than other work (a nice value of 10), for background transcodes.
+    Low,
+    /// Higher priority than other work (a nice value of -10). Raising the priority requires the
+    /// `CAP_SYS_NICE` capability or a matching `RLIMIT_NICE`.
+    High,
+    /// Real-time round-robin scheduling, which runs before all regular work. Requires the
+    /// `CAP_SYS_NICE` capability or a matching `RLIMIT_RTPRIO`. Where real-time scheduling is
+    /// not permitted, [`ThreadPriority::High`] is tried instead.
+    Realtime,
+}
+
+/// CPU affinity and priority of the worker threads of a decoder or encoder, set with
+/// [`DecoderBuilder::with_thread_policy`](crate::decode::DecoderBuilder::with_thread_policy) or
+/// [`EncoderBuilder::with_thread_policy`](crate::encode::EncoderBuilder::with_thread_policy).
+///
+/// The codec threads are started by ffmpeg when the codec is opened, and inherit the affinity and
+/// priority of the thread that opens it. The codec is therefore opened on a short-lived helper
+/// thread that the policy is applied to, which leaves the calling thread untouched.
+///
+/// Policies are applied on Linux only. On other platforms, and where the policy is not permitted,
+/// a warning is logged and the threads run with the defaults.
+///
+/// # Example
+///
+/// ```ignore
+/// let decoder = DecoderBuilder::new(Path::new("input.mkv"))
+///     .with_thread_policy(
+///         ThreadPolicy::new()
+///             .with_cpus([4, 5, 6, 7])
+///             .with_priority(ThreadPriority::High),
+///     )
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ThreadPolicy {
+    cpus: Option<Vec<usize>>,
+    priority: ThreadPriority,
+}
+
+impl ThreadPolicy {
+    /// Nice value of [`ThreadPriority::Low`].
+    const NICE_LOW: i32 = 10;
+    /// Nice value of [`ThreadPriority::High`].
+    const NICE_HIGH: i32 = -10;
+    /// Real-time priority of [`ThreadPriority::Realtime`], low in the range of 1 to 99 so that
+    /// kernel threads and audio servers still come first.
+    const REALTIME_PRIORITY: i32 = 10;
+
+    /// Create a policy that keeps the affinity and priority of the calling thread.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pin the worker threads to a set of CPUs.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpus` - Indices of the CPUs to run on, as numbered by the operating system.
+    pub fn with_cpus(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.cpus = Some(cpus.into_iter().collect());
+        self
+    }
+
+    /// Set the scheduling priority of the worker threads.
+    ///
+    /// # Arguments
+    ///
+    /// * `priority` - Priority.
+    pub fn with_priority(mut self, priority: ThreadPriority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Run a function on a helper thread with the policy applied, so that the threads it starts
+    /// inherit the policy. Blocks until the function returns.
+    ///
+    /// # Arguments
+    ///
+    /// * `f` - Function to run, like one that opens a codec.
+    pub(crate) fn run<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let policy = self.clone();
+        std::thread::spawn(move || {
+            policy.apply_to_current_thread();
+            f()
+        })
+        .join()
+        .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    /// Apply the policy to the calling thread, logging what could not be applied.
+    fn apply_to_current_thread(&self) {
+        if let Some(cpus) = self.cpus.as_deref() {
+            if let Err(err) = sys::set_affinity(cpus) {
+                tracing::warn!("cannot pin worker threads to CPUs {cpus:?}: {err}");
+            }
+        }
+        let result = match self.priority {
+            ThreadPriority::Inherit => Ok(()),
+            ThreadPriority::Low => sys::set_nice(Self::NICE_LOW),
+            ThreadPriority::High => sys::set_nice(Self::NICE_HIGH),
+            ThreadPriority::Realtime => sys::set_realtime(Self::REALTIME_PRIORITY).or_else(|err| {
+                tracing::warn!("real-time scheduling is not permitted, trying high: {err}");
+                sys::set_nice(Self::NICE_HIGH)
+            }),
+        };
+        if let Err(err) = result {
+            tracing::warn!(
+                "cannot set worker thread priority to {:?}: {err}",
+                self.priority
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::ffi::c_int;
+
+    /// Number of CPUs a `cpu_set_t` holds.
+    const CPU_SETSIZE: usize = 1024;
+    /// Same value as `SCHED_RR` in glibc and musl.
+    const SCHED_RR: c_int = 2;
+    /// Same value as `PRIO_PROCESS` in glibc and musl.
+    const PRIO_PROCESS: c_int = 0;
+
+    /// Same layout as `cpu_set_t`.
+    #[repr(C)]
+    pub(super) struct CpuSet(pub(super) [u64; CPU_SETSIZE / 64]);
+
+    /// Same layout as `struct sched_param`.
+    #[repr(C)]
+    struct SchedParam {
+        sched_priority: c_int,
+    }
+
+    extern "C" {
+        fn sched_setaffinity(pid: c_int, cpusetsize: usize, mask: *const CpuSet) -> c_int;
+        fn sched_setscheduler(pid: c_int, policy: c_int, param: *const SchedParam) -> c_int;
+        fn setpriority(which: c_int, who: u32, prio: c_int) -> c_int;
+    }
+
+    /// Build a CPU set.
+    ///
+    /// # Arguments
+    ///
+    /// * `cpus` - Indices of the CPUs in the set. Indices beyond the size of the set are ignored.
+    pub(super) fn cpu_set(cpus: &[usize]) -> CpuSet {
+        let mut set = CpuSet([0; CPU_SETSIZE / 64]);
+        for &cpu in cpus.iter().filter(|&&cpu| cpu < CPU_SETSIZE) {
+            set.0[cpu / 64] |= 1 << (cpu % 64);
+        }
+        set
+    }
+
+    /// Pin the calling thread to a set of CPUs.
+    pub(super) fn set_affinity(cpus: &[usize]) -> std::io::Result<()> {
+        let set = cpu_set(cpus);
+        // A pid of 0 is the calling thread.
+        match unsafe { sched_setaffinity(0, std::mem::size_of::<CpuSet>(), &set) } {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    /// Set the nice value of the calling thread. Nice values are per thread on Linux.
+    pub(super) fn set_nice(nice: i32) -> std::io::Result<()> {
+        match unsafe { setpriority(PRIO_PROCESS, 0, nice) } {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+
+    /// Switch the calling thread to real-time round-robin scheduling.
+    pub(super) fn set_realtime(priority: i32) -> std::io::Result<()> {
+        let param = SchedParam {
+            sched_priority: priority,
+        };
+        match unsafe { sched_setscheduler(0, SCHED_RR, &param) } {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod sys {
+    fn unsupported() -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "thread policies are only supported on Linux",
+        ))
+    }
+
+    pub(super) fn set_affinity(_cpus: &[usize]) -> std::io::Result<()> {
+        unsupported()
+    }
+
+    pub(super) fn set_nice(_nice: i32) -> std::io::Result<()> {
+        unsupported()
+    }
+
+    pub(super) fn set_realtime(_priority: i32) -> std::io::Result<()> {
+        unsupported()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_cpu_set() {
+        let set = sys::cpu_set(&[0, 3, 64, 1023, 1024]);
+        assert_eq!(set.0[0], 0b1001);
+        assert_eq!(set.0[1], 1);
+        assert_eq!(set.0[15], 1 << 63);
+        assert!(set.0[2..15].iter().all(|&word| word == 0));
+    }
+
+    #[test]
+    fn test_run_returns_result() {
+        let value = String::from("opened");
+        assert_eq!(ThreadPolicy::new().run(move || value.len()), 6);
+    }
+}
@@ -0,0 +1,244 @@
+//! Keyframe alignment verification for bitrate ladders.
+//!
+//! Players of adaptive bitrate streams (HLS, DASH) switch between renditions at segment
+//! boundaries, which only works if every rendition has its keyframes at the same timestamps. Use
+//! [`verify_keyframe_alignment`] to check the renditions of a ladder, for example in CI for
+//! packaging pipelines.
+
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::io::Reader;
+use crate::location::Location;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Kind of keyframe misalignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MisalignmentKind {
+    /// The reference has a keyframe at the timestamp, but the rendition does not.
+    Missing,
+    /// The rendition has a keyframe at the timestamp, but the reference does not.
+    Unexpected,
+}
+
+/// Keyframe of one rendition that does not align with the reference rendition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyframeMisalignment {
+    /// Index of the rendition (as passed to [`verify_keyframe_alignment`]).
+    pub rendition: usize,
+    /// Kind of misalignment.
+    pub kind: MisalignmentKind,
+    /// Timestamp of the keyframe in seconds. For [`MisalignmentKind::Missing`] this is the
+    /// timestamp in the reference, for [`MisalignmentKind::Unexpected`] in the rendition.
+    pub time: f64,
+    /// Timestamp in seconds of the nearest keyframe on the other side, if there is one.
+    pub nearest: Option<f64>,
+}
+
+impl std::fmt::Display for KeyframeMisalignment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.kind {
+            MisalignmentKind::Missing => write!(
+                f,
+                "rendition {} has no keyframe at {:.3}s",
+                self.rendition, self.time
+            )?,
+            MisalignmentKind::Unexpected => write!(
+                f,
+                "rendition {} has unexpected keyframe at {:.3}s",
+                self.rendition, self.time
+            )?,
+        }
+        if let Some(nearest) = self.nearest {
+            write!(f, " (nearest at {nearest:.3}s)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Result of keyframe alignment verification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyframeAlignmentReport {
+    /// Number of keyframes per rendition.
+    pub keyframe_counts: Vec<usize>,
+    /// Keyframes that do not align, ordered by rendition and timestamp.
+    pub misalignments: Vec<KeyframeMisalignment>,
+}
+
+impl KeyframeAlignmentReport {
+    /// Whether or not the keyframes of all renditions align.
+    pub fn is_aligned(&self) -> bool {
+        self.misalignments.is_empty()
+    }
+}
+
+/// Collect the timestamps of the keyframes in the best video stream of a source.
+///
+/// # Arguments
+///
+/// * `source` - Source to read.
+///
+/// # Return value
+///
+/// Keyframe timestamps in presentation order.
+pub fn keyframe_times(source: impl Into<Location>) -> Result<Vec<Time>> {
+    let mut reader = Reader::new(source)?;
+    let stream_index = reader.best_video_stream_index()?;
+    let mut times = Vec::new();
+    loop {
+        match reader.read(stream_index) {
+            Ok(packet) => {
+                if packet.is_key() {
+                    times.push(packet.pts());
+                }
+            }
+            Err(Error::ReadExhausted) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    times.sort_by(|a, b| a.as_secs_f64().total_cmp(&b.as_secs_f64()));
+    Ok(times)
+}
+
+/// Verify that the keyframes of the best video stream of multiple renditions align.
+///
+/// The first rendition is the reference that all other renditions are compared against.
+///
+/// # Arguments
+///
+/// * `renditions` - Renditions to verify.
+/// * `tolerance` - Maximum difference between timestamps that are considered aligned.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = verify_keyframe_alignment(
+///     &["1080p.mp4".into(), "720p.mp4".into(), "480p.mp4".into()],
+///     Duration::from_millis(1),
+/// )
+/// .unwrap();
+/// for misalignment in &report.misalignments {
+///     eprintln!("{misalignment}");
+/// }
+/// assert!(report.is_aligned());
+/// ```
+pub fn verify_keyframe_alignment(
+    renditions: &[Location],
+    tolerance: Duration,
+) -> Result<KeyframeAlignmentReport> {
+    let keyframes = renditions
+        .iter()
+        .map(|rendition| {
+            keyframe_times(rendition.clone())
+                .map(|times| times.iter().map(Time::as_secs_f64).collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut misalignments = Vec::new();
+    if let Some((reference, others)) = keyframes.split_first() {
+        for (index, rendition) in others.iter().enumerate() {
+            misalignments.extend(compare_keyframes(
+                index + 1,
+                reference,
+                rendition,
+                tolerance.as_secs_f64(),
+            ));
+        }
+    }
+
+    Ok(KeyframeAlignmentReport {
+        keyframe_counts: keyframes.iter().map(Vec::len).collect(),
+        misalignments,
+    })
+}
+
+/// Compare the sorted keyframe timestamps of a rendition against the reference.
+///
+/// # Arguments
+///
+/// * `rendition_index` - Index of the rendition, for reporting.
+/// * `reference` - Sorted keyframe timestamps of the reference in seconds.
+/// * `rendition` - Sorted keyframe timestamps of the rendition in seconds.
+/// * `tolerance` - Maximum difference in seconds between aligned timestamps.
+fn compare_keyframes(
+    rendition_index: usize,
+    reference: &[f64],
+    rendition: &[f64],
+    tolerance: f64,
+) -> Vec<KeyframeMisalignment> {
+    let mut misalignments = Vec::new();
+    for (kind, times, other) in [
+        (MisalignmentKind::Missing, reference, rendition),
+        (MisalignmentKind::Unexpected, rendition, reference),
+    ] {
+        for &time in times {
+            let nearest = nearest(other, time);
+            if !nearest.is_some_and(|nearest| (nearest - time).abs() <= tolerance) {
+                misalignments.push(KeyframeMisalignment {
+                    rendition: rendition_index,
+                    kind,
+                    time,
+                    nearest,
+                });
+            }
+        }
+    }
+    misalignments.sort_by(|a, b| a.time.total_cmp(&b.time));
+    misalignments
+}
+
+/// Find the value nearest to `time` in a sorted list.
+fn nearest(sorted: &[f64], time: f64) -> Option<f64> {
+    let index = sorted.partition_point(|&value| value < time);
+    [index.checked_sub(1), Some(index)]
+        .into_iter()
+        .flatten()
+        .filter_map(|index| sorted.get(index).copied())
+        .min_by(|a, b| (a - time).abs().total_cmp(&(b - time).abs()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nearest() {
+        assert_eq!(nearest(&[], 1.0), None);
+        assert_eq!(nearest(&[0.0, 2.0, 4.0], 1.1), Some(2.0));
+        assert_eq!(nearest(&[0.0, 2.0, 4.0], 0.9), Some(0.0));
+        assert_eq!(nearest(&[0.0, 2.0, 4.0], 9.0), Some(4.0));
+    }
+
+    #[test]
+    fn test_compare_keyframes_aligned() {
+        let reference = [0.0, 2.0, 4.0];
+        let rendition = [0.0, 2.0005, 4.0];
+        assert!(compare_keyframes(1, &reference, &rendition, 0.001).is_empty());
+    }
+
+    #[test]
+    fn test_compare_keyframes_misaligned() {
+        let reference = [0.0, 2.0, 4.0];
+        let rendition = [0.0, 2.5, 4.0];
+        let misalignments = compare_keyframes(1, &reference, &rendition, 0.001);
+        assert_eq!(
+            misalignments,
+            vec![
+                KeyframeMisalignment {
+                    rendition: 1,
+                    kind: MisalignmentKind::Missing,
+                    time: 2.0,
+                    nearest: Some(2.5),
+                },
+                KeyframeMisalignment {
+                    rendition: 1,
+                    kind: MisalignmentKind::Unexpected,
+                    time: 2.5,
+                    nearest: Some(2.0),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,1256 @@
+use ffmpeg::codec::decoder::Audio as AvAudioDecoder;
+use ffmpeg::codec::encoder::audio::Encoder as AvAudioEncoder;
+use ffmpeg::codec::flag::Flags as AvCodecFlags;
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Parameters as AvCodecParameters;
+use ffmpeg::codec::{Context as AvContext, Id as AvCodecId};
+use ffmpeg::format::flag::Flags as AvFormatFlags;
+use ffmpeg::software::resampling::Context as AvResampler;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::format::Sample as AvSample;
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::{ChannelLayout as AvChannelLayout, Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::ffi;
+use crate::frame::RawAudioFrame;
+use crate::io::{Reader, ReaderBuilder, Writer, WriterBuilder};
+use crate::location::Location;
+use crate::mux::{Muxer, MuxerBuilder};
+use crate::options::Options;
+use crate::packet::Packet;
+use crate::stream::StreamInfo;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Re-export internal `AvSample` as `SampleFormat` for callers.
+pub type SampleFormat = AvSample;
+
+/// Builds an [`AudioDecoder`].
+pub struct AudioDecoderBuilder<'a> {
+    source: Location,
+    options: Option<&'a Options>,
+}
+
+impl<'a> AudioDecoderBuilder<'a> {
+    /// Create an audio decoder with the specified source.
+    ///
+    /// * `source` - Source to decode.
+    pub fn new(source: impl Into<Location>) -> Self {
+        Self {
+            source: source.into(),
+            options: None,
+        }
+    }
+
+    /// Set custom options. Options are applied to the input.
+    ///
+    /// * `options` - Custom options.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Build [`AudioDecoder`].
+    pub fn build(self) -> Result<AudioDecoder> {
+        let mut reader_builder = ReaderBuilder::new(self.source);
+        if let Some(options) = self.options {
+            reader_builder = reader_builder.with_options(options);
+        }
+        AudioDecoder::from_reader(reader_builder.build()?)
+    }
+}
+
+/// Decode audio files, streams and devices.
+///
+/// Frames are produced in the native sample format, sample rate and channel layout of the source.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut decoder = AudioDecoder::new(Path::new("audio.mp3")).unwrap();
+/// decoder
+///     .decode_raw_iter()
+///     .take_while(Result::is_ok)
+///     .for_each(|frame| println!("Got {} samples!", frame.unwrap().samples()));
+/// ```
+pub struct AudioDecoder {
+    decoder: AudioDecoderSplit,
+    reader: Reader,
+    reader_stream_index: usize,
+    draining: bool,
+}
+
+impl AudioDecoder {
+    /// Create an audio decoder to decode the specified source.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to decode.
+    #[inline]
+    pub fn new(source: impl Into<Location>) -> Result<Self> {
+        AudioDecoderBuilder::new(source).build()
+    }
+
+    /// Create an audio decoder that decodes the best audio stream of a reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader to decode from.
+    pub(crate) fn from_reader(reader: Reader) -> Result<Self> {
+        let reader_stream_index = reader.best_audio_stream_index()?;
+        let decoder = AudioDecoderSplit::new(&reader, reader_stream_index)?;
+        Ok(Self {
+            decoder,
+            reader,
+            reader_stream_index,
+            draining: false,
+        })
+    }
+
+    /// Get decoder time base.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        self.decoder.time_base()
+    }
+
+    /// Sample rate of the decoded frames.
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.decoder.sample_rate()
+    }
+
+    /// Number of channels of the decoded frames.
+    #[inline]
+    pub fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    /// Sample format of the decoded frames.
+    #[inline]
+    pub fn sample_format(&self) -> SampleFormat {
+        self.decoder.sample_format()
+    }
+
+    /// Decode frames through iterator interface. This is similar to `decode_raw` but it returns
+    /// frames through an infinite iterator.
+    pub fn decode_raw_iter(&mut self) -> impl Iterator<Item = Result<RawAudioFrame>> + '_ {
+        std::iter::from_fn(move || Some(self.decode_raw()))
+    }
+
+    /// Decode a single frame and return the raw ffmpeg audio frame.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawAudioFrame`].
+    pub fn decode_raw(&mut self) -> Result<RawAudioFrame> {
+        Ok(loop {
+            if !self.draining {
+                let packet_result = self.reader.read(self.reader_stream_index);
+                if matches!(packet_result, Err(Error::ReadExhausted)) {
+                    self.draining = true;
+                    continue;
+                }
+                let packet = packet_result?;
+                if let Some(frame) = self.decoder.decode_raw(packet)? {
+                    break frame;
+                }
+            } else {
+                match self.decoder.drain_raw() {
+                    Ok(Some(frame)) => break frame,
+                    Ok(None) | Err(Error::ReadExhausted) => {
+                        self.decoder.reset();
+                        self.draining = false;
+                        return Err(Error::DecodeExhausted);
+                    }
+                    Err(err) => return Err(err),
+                }
+            }
+        })
+    }
+
+    /// Split the decoder into a decoder (of type [`AudioDecoderSplit`]) and a [`Reader`].
+    ///
+    /// # Return value
+    ///
+    /// Tuple of the [`AudioDecoderSplit`], [`Reader`] and the reader stream index.
+    #[inline]
+    pub fn into_parts(self) -> (AudioDecoderSplit, Reader, usize) {
+        (self.decoder, self.reader, self.reader_stream_index)
+    }
+}
+
+/// Decoder part of a split [`AudioDecoder`] and [`Reader`].
+///
+/// Important note: Do not forget to drain the decoder after the reader is exhausted. It may still
+/// contain frames. Run `drain_raw()` in a loop until no more frames are produced.
+pub struct AudioDecoderSplit {
+    decoder: AvAudioDecoder,
+    decoder_time_base: AvRational,
+    draining: bool,
+}
+
+impl AudioDecoderSplit {
+    /// Create a new [`AudioDecoderSplit`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - [`Reader`] to initialize decoder from.
+    /// * `reader_stream_index` - Index of the audio stream to decode.
+    pub fn new(reader: &Reader, reader_stream_index: usize) -> Result<Self> {
+        let reader_stream = reader
+            .input
+            .stream(reader_stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+
+        let mut decoder = AvContext::new();
+        ffi::set_decoder_context_time_base(&mut decoder, reader_stream.time_base());
+        decoder.set_parameters(reader_stream.parameters())?;
+        let decoder = decoder.decoder().audio()?;
+        let decoder_time_base = decoder.time_base();
+
+        if decoder.format() == AvSample::None || decoder.rate() == 0 {
+            return Err(Error::MissingCodecParameters);
+        }
+
+        Ok(Self {
+            decoder,
+            decoder_time_base,
+            draining: false,
+        })
+    }
+
+    /// Get decoder time base.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        self.decoder_time_base
+    }
+
+    /// Sample rate of the decoded frames.
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.decoder.rate()
+    }
+
+    /// Number of channels of the decoded frames.
+    #[inline]
+    pub fn channels(&self) -> u16 {
+        self.decoder.channels()
+    }
+
+    /// Sample format of the decoded frames.
+    #[inline]
+    pub fn sample_format(&self) -> SampleFormat {
+        self.decoder.format()
+    }
+
+    /// Decode a [`Packet`].
+    ///
+    /// Feeds the packet to the decoder and returns a frame if there is one available. The caller
+    /// should keep feeding packets until the decoder returns a frame.
+    ///
+    /// # Panics
+    ///
+    /// Panics if in draining mode.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawAudioFrame`] if the decoder has a frame available, [`None`]
+    /// if not.
+    pub fn decode_raw(&mut self, packet: Packet) -> Result<Option<RawAudioFrame>> {
+        assert!(!self.draining);
+        let (mut packet, packet_time_base) = packet.into_inner_parts();
+        packet.rescale_ts(packet_time_base, self.decoder_time_base);
+        self.decoder
+            .send_packet(&packet)
+            .map_err(Error::BackendError)?;
+        self.decoder_receive_frame()
+    }
+
+    /// Drain one frame from the decoder.
+    ///
+    /// After calling drain once the decoder is in draining mode and the caller may not use normal
+    /// decode anymore or it will panic.
+    ///
+    /// # Return value
+    ///
+    /// The decoded raw frame as [`RawAudioFrame`] if the decoder has a frame available, [`None`]
+    /// if not.
+    pub fn drain_raw(&mut self) -> Result<Option<RawAudioFrame>> {
+        if !self.draining {
+            self.decoder.send_eof().map_err(Error::BackendError)?;
+            self.draining = true;
+        }
+        self.decoder_receive_frame()
+    }
+
+    /// Reset the decoder to be used again after draining.
+    pub fn reset(&mut self) {
+        self.decoder.flush();
+        self.draining = false;
+    }
+
+    /// Get the timestamp of a decoded frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame decoded by this decoder.
+    pub fn frame_time(&self, frame: &RawAudioFrame) -> Time {
+        Time::new(frame.pts(), self.decoder_time_base)
+    }
+
+    /// Pull a decoded frame from the decoder. This function also implements retry mechanism in case
+    /// the decoder signals `EAGAIN`.
+    fn decoder_receive_frame(&mut self) -> Result<Option<RawAudioFrame>> {
+        let mut frame = RawAudioFrame::empty();
+        match self.decoder.receive_frame(&mut frame) {
+            Ok(()) => Ok(Some(frame)),
+            Err(AvError::Eof) => Err(Error::ReadExhausted),
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Drop for AudioDecoderSplit {
+    fn drop(&mut self) {
+        // Maximum number of invocations to `decoder_receive_frame` to drain the items still on the
+        // queue before giving up.
+        const MAX_DRAIN_ITERATIONS: u32 = 100;
+
+        // We need to drain the items still in the decoders queue.
+        if let Ok(()) = self.decoder.send_eof() {
+            for _ in 0..MAX_DRAIN_ITERATIONS {
+                if self.decoder_receive_frame().is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+unsafe impl Send for AudioDecoderSplit {}
+unsafe impl Sync for AudioDecoderSplit {}
+
+/// Regroups audio samples into frames of a fixed number of samples, like the `frame_size` that
+/// encoders such as AAC require.
+///
+/// Frames of any length are pushed in, and frames of exactly the frame size are taken out. The
+/// timestamps of the output frames continue from the timestamp of the first input frame, advanced
+/// by the number of samples that were taken out. All input frames must have the same sample
+/// format, channel layout and sample rate.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut buffer = AudioFrameBuffer::new(1024, decoder.time_base());
+/// while let Ok(frame) = decoder.decode_raw() {
+///     buffer.push(&frame).unwrap();
+///     while let Some(frame) = buffer.pop().unwrap() {
+///         encode(frame);
+///     }
+/// }
+/// if let Some(frame) = buffer.flush().unwrap() {
+///     encode(frame);
+/// }
+/// ```
+pub struct AudioFrameBuffer {
+    frame_size: usize,
+    time_base: AvRational,
+    fifo: Option<ffi::AudioFifo>,
+    sample_rate: u32,
+    start: Option<i64>,
+    samples_out: u64,
+}
+
+impl AudioFrameBuffer {
+    /// Create an empty buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_size` - Number of samples per channel in the output frames. Zero is raised to one.
+    /// * `time_base` - Time base of the timestamps of the input and output frames.
+    pub fn new(frame_size: usize, time_base: AvRational) -> Self {
+        Self {
+            frame_size: frame_size.max(1),
+            time_base,
+            fifo: None,
+            sample_rate: 0,
+            start: None,
+            samples_out: 0,
+        }
+    }
+
+    /// Number of samples per channel in the output frames.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Number of samples per channel in the buffer.
+    pub fn samples(&self) -> usize {
+        self.fifo.as_ref().map_or(0, ffi::AudioFifo::size)
+    }
+
+    /// Add the samples of a frame to the buffer.
+    ///
+    /// Returns [`Error::InvalidFrameFormat`] if the frame has another sample format, channel layout
+    /// or sample rate than the frames before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame with samples.
+    pub fn push(&mut self, frame: &RawAudioFrame) -> Result<()> {
+        let fifo = match self.fifo.as_mut() {
+            Some(fifo) => fifo,
+            None => {
+                // The first frame decides the format and the timestamp of the first sample.
+                self.sample_rate = frame.rate();
+                self.start = Some(frame.pts().unwrap_or(0));
+                self.fifo
+                    .insert(ffi::AudioFifo::new(frame).ok_or(AvError::Bug)?)
+            }
+        };
+        if !fifo.matches(frame) {
+            return Err(Error::InvalidFrameFormat);
+        }
+        fifo.write(frame)?;
+        Ok(())
+    }
+
+    /// Take a frame of exactly the frame size out of the buffer.
+    ///
+    /// # Return value
+    ///
+    /// The frame, or `None` if there are not enough samples in the buffer yet.
+    pub fn pop(&mut self) -> Result<Option<RawAudioFrame>> {
+        if self.samples() < self.frame_size {
+            return Ok(None);
+        }
+        self.take(self.frame_size).map(Some)
+    }
+
+    /// Take the remaining samples out of the buffer at the end of the stream. The last frame may
+    /// have fewer samples than the frame size.
+    ///
+    /// # Return value
+    ///
+    /// The frame, or `None` if the buffer is empty.
+    pub fn flush(&mut self) -> Result<Option<RawAudioFrame>> {
+        match self.samples() {
+            0 => Ok(None),
+            samples => self.take(samples.min(self.frame_size)).map(Some),
+        }
+    }
+
+    /// Take samples out of the buffer into a frame with the next timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Number of samples per channel to take.
+    fn take(&mut self, samples: usize) -> Result<RawAudioFrame> {
+        let fifo = self.fifo.as_mut().ok_or(AvError::Bug)?;
+        let mut frame = fifo.read(samples)?;
+        let pts = self.start.unwrap_or(0) + self.samples_time(self.samples_out);
+        frame.set_pts(Some(pts));
+        self.samples_out += samples as u64;
+        Ok(frame)
+    }
+
+    /// Convert a number of samples to a duration in the time base.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Number of samples per channel.
+    fn samples_time(&self, samples: u64) -> i64 {
+        (samples as i64).rescale(AvRational::new(1, self.sample_rate as i32), self.time_base)
+    }
+}
+
+unsafe impl Send for AudioFrameBuffer {}
+unsafe impl Sync for AudioFrameBuffer {}
+
+/// Builds an [`AudioEncoder`].
+pub struct AudioEncoderBuilder<'a> {
+    destination: Location,
+    sample_rate: u32,
+    channels: u16,
+    codec_id: Option<AvCodecId>,
+    input_format: Option<SampleFormat>,
+    bit_rate: Option<u64>,
+    format: Option<&'a str>,
+    options: Option<&'a Options>,
+}
+
+impl<'a> AudioEncoderBuilder<'a> {
+    /// Create an audio encoder with the specified destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where to encode to.
+    /// * `sample_rate` - Sample rate. The closest rate the encoder supports is used.
+    /// * `channels` - Number of channels, in the default layout for that number.
+    pub fn new(destination: impl Into<Location>, sample_rate: u32, channels: u16) -> Self {
+        Self {
+            destination: destination.into(),
+            sample_rate,
+            channels,
+            codec_id: None,
+            input_format: None,
+            bit_rate: None,
+            format: None,
+            options: None,
+        }
+    }
+
+    /// Set the codec to encode with. By default, the default audio codec of the container format
+    /// is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec to encode with.
+    pub fn with_codec(mut self, codec_id: AvCodecId) -> Self {
+        self.codec_id = Some(codec_id);
+        self
+    }
+
+    /// Set the sample format of the frames that will be encoded. The encoder uses this format if
+    /// it supports it, so that no conversion is needed. See [`AudioEncoder::negotiation`].
+    ///
+    /// # Arguments
+    ///
+    /// * `input_format` - Sample format of the frames.
+    pub fn with_input_format(mut self, input_format: SampleFormat) -> Self {
+        self.input_format = Some(input_format);
+        self
+    }
+
+    /// Set the bit rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `bit_rate` - Bit rate in bits per second.
+    pub fn with_bit_rate(mut self, bit_rate: u64) -> Self {
+        self.bit_rate = Some(bit_rate);
+        self
+    }
+
+    /// Set the container format.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Container format to use.
+    pub fn with_format(mut self, format: &'a str) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Set the options of the output.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to the output.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Build [`AudioEncoder`].
+    pub fn build(self) -> Result<AudioEncoder> {
+        let mut writer_builder = WriterBuilder::new(self.destination);
+        if let Some(format) = self.format {
+            writer_builder = writer_builder.with_format(format);
+        }
+        if let Some(options) = self.options {
+            writer_builder = writer_builder.with_options(options);
+        }
+        let writer = writer_builder.build()?;
+        let encoder = AudioStreamEncoder::new(
+            &writer,
+            self.codec_id,
+            self.sample_rate,
+            self.input_format,
+            self.channels,
+            self.bit_rate,
+        )?;
+        let muxer = MuxerBuilder::new(writer)
+            .with_stream(StreamInfo::from_params(
+                encoder.parameters(),
+                encoder.time_base(),
+                0,
+            )?)?
+            .build();
+        Ok(AudioEncoder {
+            encoder,
+            muxer,
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+        })
+    }
+}
+
+/// Encode audio to files and streams.
+///
+/// Frames may have any sample format, sample rate and channel layout. When they do not match what
+/// the encoder takes (like interleaved `f32` samples for an AAC encoder, which takes planar
+/// `fltp`), they are converted automatically. [`AudioEncoder::negotiation`] reports what was
+/// negotiated.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut encoder = AudioEncoderBuilder::new(Path::new("tone.m4a"), 48_000, 2)
+///     .with_input_format(SampleFormat::F32(SampleType::Packed))
+///     .build()
+///     .unwrap();
+/// encoder.encode_interleaved_f32(&samples, Time::zero()).unwrap();
+/// println!("{:?}", encoder.negotiation());
+/// encoder.finish().unwrap();
+/// ```
+pub struct AudioEncoder {
+    encoder: AudioStreamEncoder,
+    muxer: Muxer<Writer>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl AudioEncoder {
+    /// Create an audio encoder with the specified destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where to encode to.
+    /// * `sample_rate` - Sample rate.
+    /// * `channels` - Number of channels.
+    #[inline]
+    pub fn new(destination: impl Into<Location>, sample_rate: u32, channels: u16) -> Result<Self> {
+        AudioEncoderBuilder::new(destination, sample_rate, channels).build()
+    }
+
+    /// Time base of the timestamps of the frames given to [`AudioEncoder::encode_raw`]: one over
+    /// the sample rate the encoder was created with.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        AvRational::new(1, self.sample_rate as i32)
+    }
+
+    /// Encode a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode, with a timestamp in [`AudioEncoder::time_base`].
+    pub fn encode_raw(&mut self, frame: &RawAudioFrame) -> Result<()> {
+        let packets = self.encoder.encode(frame, self.time_base())?;
+        self.write(packets)
+    }
+
+    /// Encode interleaved `f32` samples, the format most audio APIs produce.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Samples of all channels, interleaved. The length must be a multiple of the
+    ///   number of channels.
+    /// * `timestamp` - Time of the first sample.
+    pub fn encode_interleaved_f32(&mut self, samples: &[f32], timestamp: Time) -> Result<()> {
+        let channels = usize::from(self.channels.max(1));
+        if samples.len() % channels != 0 {
+            return Err(Error::InvalidFrameFormat);
+        }
+        let mut frame = RawAudioFrame::new(
+            AvSample::F32(ffmpeg::util::format::sample::Type::Packed),
+            samples.len() / channels,
+            AvChannelLayout::default(i32::from(self.channels)),
+        );
+        frame.set_rate(self.sample_rate);
+        frame.set_pts(timestamp.with_time_base(self.time_base()).into_value());
+        frame
+            .data_mut(0)
+            .chunks_exact_mut(std::mem::size_of::<f32>())
+            .zip(samples)
+            .for_each(|(bytes, sample)| bytes.copy_from_slice(&sample.to_ne_bytes()));
+        self.encode_raw(&frame)
+    }
+
+    /// How the format of the frames was matched to the encoder, or `None` before the first frame.
+    pub fn negotiation(&self) -> Option<SampleFormatNegotiation> {
+        self.encoder.negotiation()
+    }
+
+    /// Flush the encoder and write the trailer. Frames cannot be encoded afterwards.
+    pub fn finish(&mut self) -> Result<()> {
+        let packets = self.encoder.finish()?;
+        self.write(packets)?;
+        self.muxer.finish()?;
+        Ok(())
+    }
+
+    /// Write encoded packets.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets` - Encoded packets.
+    fn write(&mut self, packets: Vec<Packet>) -> Result<()> {
+        for mut packet in packets {
+            packet.set_stream_index(0);
+            self.muxer.mux(packet)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AudioEncoder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+unsafe impl Send for AudioEncoder {}
+unsafe impl Sync for AudioEncoder {}
+
+/// How the format of the frames given to an audio encoder was matched to the format the encoder
+/// takes. See [`AudioEncoder::negotiation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleFormatNegotiation {
+    /// Sample format of the frames.
+    pub input_format: SampleFormat,
+    /// Sample rate of the frames.
+    pub input_sample_rate: u32,
+    /// Number of channels of the frames.
+    pub input_channels: u16,
+    /// Sample format the encoder takes.
+    pub encoder_format: SampleFormat,
+    /// Sample rate the encoder takes.
+    pub encoder_sample_rate: u32,
+    /// Number of channels the encoder takes.
+    pub encoder_channels: u16,
+}
+
+impl SampleFormatNegotiation {
+    /// Whether the frames are converted before encoding.
+    pub fn is_converted(&self) -> bool {
+        self.input_format != self.encoder_format
+            || self.input_sample_rate != self.encoder_sample_rate
+            || self.input_channels != self.encoder_channels
+    }
+}
+
+/// Settings of an audio stream that is encoded next to other streams. See
+/// [`MuxerBuilder::with_audio_encoder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioSettings {
+    sample_rate: u32,
+    channels: u16,
+    codec_id: Option<AvCodecId>,
+    input_format: Option<SampleFormat>,
+    bit_rate: Option<u64>,
+}
+
+impl AudioSettings {
+    /// Create settings with the default codec of the container format.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate. The closest rate the encoder supports is used.
+    /// * `channels` - Number of channels, in the default layout for that number.
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            codec_id: None,
+            input_format: None,
+            bit_rate: None,
+        }
+    }
+
+    /// Set the codec to encode with.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec to encode with.
+    pub fn with_codec(mut self, codec_id: AvCodecId) -> Self {
+        self.codec_id = Some(codec_id);
+        self
+    }
+
+    /// Set the sample format of the frames that will be encoded. See
+    /// [`AudioEncoderBuilder::with_input_format`].
+    ///
+    /// # Arguments
+    ///
+    /// * `input_format` - Sample format of the frames.
+    pub fn with_input_format(mut self, input_format: SampleFormat) -> Self {
+        self.input_format = Some(input_format);
+        self
+    }
+
+    /// Set the bit rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `bit_rate` - Bit rate in bits per second.
+    pub fn with_bit_rate(mut self, bit_rate: u64) -> Self {
+        self.bit_rate = Some(bit_rate);
+        self
+    }
+
+    /// Create an encoder with these settings for a stream of a writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Writer the packets go to.
+    pub(crate) fn encoder(&self, writer: &Writer) -> Result<AudioStreamEncoder> {
+        AudioStreamEncoder::new(
+            writer,
+            self.codec_id,
+            self.sample_rate,
+            self.input_format,
+            self.channels,
+            self.bit_rate,
+        )
+    }
+}
+
+/// Encodes audio frames into the packets of one output stream, for helpers that produce an audio
+/// stream next to other streams in the same writer.
+///
+/// Frames are resampled to the sample format, sample rate and channel layout of the encoder if
+/// they differ from it, and regrouped into frames of the encoder frame size. Packets have the time
+/// base of the encoder and no stream index; the caller maps them to the output stream.
+pub(crate) struct AudioStreamEncoder {
+    encoder: AvAudioEncoder,
+    time_base: AvRational,
+    channel_layout: AvChannelLayout,
+    resampler: Option<AvResampler>,
+    negotiation: Option<SampleFormatNegotiation>,
+    buffer: AudioFrameBuffer,
+    /// End of the samples sent so far in the encoder time base.
+    end: Option<i64>,
+    draining: bool,
+}
+
+impl AudioStreamEncoder {
+    /// Create an encoder for a stream of a writer. The writer decides the codec if none is given.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Writer the packets go to.
+    /// * `codec_id` - Codec to encode with, or `None` for the default audio codec of the writer.
+    /// * `sample_rate` - Preferred sample rate. The closest rate the encoder supports is used.
+    /// * `sample_format` - Preferred sample format, like the format of the frames to encode. The
+    ///   first format the encoder supports is used if it does not support this one.
+    /// * `channels` - Number of channels, in the default layout for that number.
+    /// * `bit_rate` - Bit rate in bits per second, or `None` for the encoder default.
+    pub(crate) fn new(
+        writer: &Writer,
+        codec_id: Option<AvCodecId>,
+        sample_rate: u32,
+        sample_format: Option<SampleFormat>,
+        channels: u16,
+        bit_rate: Option<u64>,
+    ) -> Result<Self> {
+        let codec_id = codec_id
+            .or_else(|| writer.default_codec(ffmpeg::media::Type::Audio))
+            .ok_or(Error::UnsupportedCodec)?;
+        let codec = ffmpeg::encoder::find(codec_id).ok_or(Error::UnsupportedCodec)?;
+        let audio_codec = codec.audio()?;
+        let sample_format = match audio_codec
+            .formats()
+            .map(|formats| formats.collect::<Vec<_>>())
+        {
+            Some(formats) => sample_format
+                .filter(|sample_format| formats.contains(sample_format))
+                .or_else(|| formats.first().copied()),
+            // Encoders that do not list their formats take any.
+            None => sample_format,
+        }
+        .unwrap_or(AvSample::F32(ffmpeg::util::format::sample::Type::Planar));
+        let sample_rate = audio_codec
+            .rates()
+            .and_then(|rates| {
+                rates.min_by_key(|rate| (i64::from(*rate) - i64::from(sample_rate)).abs())
+            })
+            .map_or(sample_rate, |rate| rate as u32);
+        let channel_layout = AvChannelLayout::default(i32::from(channels));
+
+        let mut encoder_context = ffi::codec_context_as(&codec)?;
+        if writer
+            .output
+            .format()
+            .flags()
+            .contains(AvFormatFlags::GLOBAL_HEADER)
+        {
+            encoder_context.set_flags(AvCodecFlags::GLOBAL_HEADER);
+        }
+        let mut encoder = encoder_context.encoder().audio()?;
+        encoder.set_format(sample_format);
+        encoder.set_rate(sample_rate as i32);
+        encoder.set_channel_layout(channel_layout.clone());
+        if let Some(bit_rate) = bit_rate {
+            encoder.set_bit_rate(bit_rate as usize);
+        }
+        let time_base = AvRational::new(1, sample_rate as i32);
+        encoder.set_time_base(time_base);
+        let encoder = encoder.open_as(codec)?;
+
+        let frame_size = encoder.frame_size() as usize;
+        Ok(Self {
+            encoder,
+            time_base,
+            channel_layout,
+            resampler: None,
+            negotiation: None,
+            // Encoders that take frames of any size report a frame size of zero.
+            buffer: AudioFrameBuffer::new(
+                if frame_size > 0 { frame_size } else { 1024 },
+                time_base,
+            ),
+            end: None,
+            draining: false,
+        })
+    }
+
+    /// Codec parameters of the output stream.
+    pub(crate) fn parameters(&self) -> AvCodecParameters {
+        AvCodecParameters::from(&self.encoder)
+    }
+
+    /// Time base of the packets.
+    pub(crate) fn time_base(&self) -> AvRational {
+        self.time_base
+    }
+
+    /// End of the samples encoded so far, or an empty time before the first frame.
+    pub(crate) fn end(&self) -> Time {
+        Time::new(self.end, self.time_base)
+    }
+
+    /// Encode a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode, with a timestamp in `time_base`.
+    /// * `time_base` - Time base of the timestamp of the frame.
+    ///
+    /// # Return value
+    ///
+    /// Packets that became available.
+    pub(crate) fn encode(
+        &mut self,
+        frame: &RawAudioFrame,
+        time_base: AvRational,
+    ) -> Result<Vec<Packet>> {
+        let pts = frame
+            .pts()
+            .map(|pts| pts.rescale(time_base, self.time_base));
+        // The format of the frames may change mid-stream, like when the source switches between
+        // stereo and surround programs, so it is negotiated again for the new format.
+        let mut packets = Vec::new();
+        if let Some(negotiation) = self.negotiation.filter(|negotiation| {
+            (
+                negotiation.input_format,
+                negotiation.input_sample_rate,
+                negotiation.input_channels,
+            ) != (frame.format(), frame.rate(), frame.channels())
+        }) {
+            tracing::debug!(
+                "audio changed from {:?} at {} Hz with {} channels to {:?} at {} Hz with {} \
+                 channels, negotiating the conversion again",
+                negotiation.input_format,
+                negotiation.input_sample_rate,
+                negotiation.input_channels,
+                frame.format(),
+                frame.rate(),
+                frame.channels(),
+            );
+            packets.extend(self.flush_resampler()?);
+            self.negotiation = None;
+        }
+        let negotiation = *self.negotiation.get_or_insert_with(|| {
+            let negotiation = SampleFormatNegotiation {
+                input_format: frame.format(),
+                input_sample_rate: frame.rate(),
+                input_channels: frame.channels(),
+                encoder_format: self.encoder.format(),
+                encoder_sample_rate: self.encoder.rate(),
+                encoder_channels: self.encoder.channels(),
+            };
+            if negotiation.is_converted() {
+                tracing::debug!(
+                    "converting audio from {:?} at {} Hz to {:?} at {} Hz for the encoder",
+                    negotiation.input_format,
+                    negotiation.input_sample_rate,
+                    negotiation.encoder_format,
+                    negotiation.encoder_sample_rate,
+                );
+            }
+            negotiation
+        });
+        if !negotiation.is_converted() {
+            let mut frame = frame.clone();
+            frame.set_rate(self.encoder.rate());
+            // The frames have as many channels as the encoder, and take its layout so that they can
+            // be grouped with the frames resampled before a format change.
+            frame.set_channel_layout(self.channel_layout.clone());
+            frame.set_pts(pts);
+            packets.extend(self.push(&frame)?);
+            return Ok(packets);
+        }
+
+        let resampler = match self.resampler.as_mut() {
+            Some(resampler) => resampler,
+            None => {
+                let input_layout = if frame.channel_layout().bits() != 0 {
+                    frame.channel_layout()
+                } else {
+                    AvChannelLayout::default(i32::from(frame.channels()))
+                };
+                self.resampler.insert(ffmpeg::software::resampler(
+                    (frame.format(), input_layout, frame.rate()),
+                    (
+                        self.encoder.format(),
+                        self.channel_layout.clone(),
+                        self.encoder.rate(),
+                    ),
+                )?)
+            }
+        };
+        // Leave room for the samples the resampler holds back.
+        let capacity = (frame.samples() as u64 * u64::from(self.encoder.rate())
+            / u64::from(frame.rate().max(1))) as usize
+            + 256;
+        let mut resampled =
+            RawAudioFrame::new(self.encoder.format(), capacity, self.channel_layout.clone());
+        resampler.run(frame, &mut resampled)?;
+        resampled.set_pts(pts);
+        packets.extend(self.push(&resampled)?);
+        Ok(packets)
+    }
+
+    /// How the format of the frames was matched to the encoder, or `None` before the first frame.
+    pub(crate) fn negotiation(&self) -> Option<SampleFormatNegotiation> {
+        self.negotiation
+    }
+
+    /// Encode silence up to a point in time, to pad the stream. Does nothing if the stream already
+    /// reaches it.
+    ///
+    /// # Arguments
+    ///
+    /// * `end` - Time to pad up to.
+    ///
+    /// # Return value
+    ///
+    /// Packets that became available.
+    pub(crate) fn pad_to(&mut self, end: Time) -> Result<Vec<Packet>> {
+        let Some(end) = end.with_time_base(self.time_base).into_value() else {
+            return Ok(Vec::new());
+        };
+        let start = self.end.unwrap_or(0);
+        if end <= start {
+            return Ok(Vec::new());
+        }
+        // The time base is one over the sample rate, so the difference is the number of samples.
+        let mut silence = RawAudioFrame::new(
+            self.encoder.format(),
+            (end - start) as usize,
+            self.channel_layout.clone(),
+        );
+        silence.set_rate(self.encoder.rate());
+        for plane in 0..silence.planes() {
+            silence.data_mut(plane).fill(0);
+        }
+        silence.set_pts(Some(start));
+        self.push(&silence)
+    }
+
+    /// Flush the resampler and the encoder at the end of the stream.
+    ///
+    /// # Return value
+    ///
+    /// The remaining packets.
+    pub(crate) fn finish(&mut self) -> Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+        if self.draining {
+            return Ok(packets);
+        }
+        packets.extend(self.flush_resampler()?);
+        if let Some(frame) = self.buffer.flush()? {
+            packets.extend(self.send(&frame)?);
+        }
+        self.encoder.send_eof()?;
+        self.draining = true;
+        while let Some(packet) = self.receive_packet()? {
+            packets.push(packet);
+        }
+        Ok(packets)
+    }
+
+    /// Take the samples the resampler holds back, and drop the resampler, like when the format of
+    /// the frames changes or at the end of the stream.
+    ///
+    /// # Return value
+    ///
+    /// Packets that became available.
+    fn flush_resampler(&mut self) -> Result<Vec<Packet>> {
+        let Some(mut resampler) = self.resampler.take() else {
+            return Ok(Vec::new());
+        };
+        let capacity = resampler
+            .delay()
+            .map_or(0, |delay| delay.output.max(0) as usize)
+            + 256;
+        let mut flushed =
+            RawAudioFrame::new(self.encoder.format(), capacity, self.channel_layout.clone());
+        resampler.flush(&mut flushed)?;
+        self.push(&flushed)
+    }
+
+    /// Regroup resampled samples into encoder frames and encode the frames that are complete.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame in the encoder format.
+    fn push(&mut self, frame: &RawAudioFrame) -> Result<Vec<Packet>> {
+        if frame.samples() == 0 {
+            return Ok(Vec::new());
+        }
+        self.buffer.push(frame)?;
+        let mut packets = Vec::new();
+        while let Some(frame) = self.buffer.pop()? {
+            packets.extend(self.send(&frame)?);
+        }
+        Ok(packets)
+    }
+
+    /// Send a frame of the encoder frame size to the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to send.
+    fn send(&mut self, frame: &RawAudioFrame) -> Result<Vec<Packet>> {
+        // The time base is one over the sample rate.
+        self.end = frame.pts().map(|pts| pts + frame.samples() as i64);
+        self.encoder.send_frame(frame)?;
+        let mut packets = Vec::new();
+        while let Some(packet) = self.receive_packet()? {
+            packets.push(packet);
+        }
+        Ok(packets)
+    }
+
+    /// Pull an encoded packet from the encoder, or `None` if it needs more frames.
+    fn receive_packet(&mut self) -> Result<Option<Packet>> {
+        let mut packet = AvPacket::empty();
+        match self.encoder.receive_packet(&mut packet) {
+            Ok(()) => Ok(Some(Packet::new(packet, self.time_base))),
+            Err(AvError::Eof) => Ok(None),
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+unsafe impl Send for AudioStreamEncoder {}
+unsafe impl Sync for AudioStreamEncoder {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ffmpeg::util::format::sample::Type as AvSampleType;
+
+    /// Create a mono `s16` frame at 48 kHz whose samples count up from `first`.
+    fn frame(samples: usize, first: i16, pts: i64) -> RawAudioFrame {
+        let mut frame = RawAudioFrame::new(
+            AvSample::I16(AvSampleType::Packed),
+            samples,
+            AvChannelLayout::MONO,
+        );
+        frame.set_rate(48_000);
+        frame.set_pts(Some(pts));
+        for (i, sample) in frame
+            .data_mut(0)
+            .chunks_exact_mut(2)
+            .take(samples)
+            .enumerate()
+        {
+            sample.copy_from_slice(&(first + i as i16).to_ne_bytes());
+        }
+        frame
+    }
+
+    /// Read the samples of a mono `s16` frame.
+    fn samples(frame: &RawAudioFrame) -> Vec<i16> {
+        frame
+            .data(0)
+            .chunks_exact(2)
+            .take(frame.samples())
+            .map(|sample| i16::from_ne_bytes([sample[0], sample[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn test_frame_buffer_partial_frames() {
+        let mut buffer = AudioFrameBuffer::new(1024, AvRational::new(1, 48_000));
+        buffer.push(&frame(300, 0, 100)).unwrap();
+        assert!(buffer.pop().unwrap().is_none());
+        buffer.push(&frame(300, 300, 400)).unwrap();
+        buffer.push(&frame(300, 600, 700)).unwrap();
+        assert!(buffer.pop().unwrap().is_none());
+        assert_eq!(buffer.samples(), 900);
+
+        buffer.push(&frame(300, 900, 1000)).unwrap();
+        let output = buffer.pop().unwrap().unwrap();
+        assert_eq!(output.samples(), 1024);
+        assert_eq!(output.rate(), 48_000);
+        assert_eq!(output.pts(), Some(100));
+        assert_eq!(samples(&output), (0..1024).collect::<Vec<i16>>());
+        assert!(buffer.pop().unwrap().is_none());
+        assert_eq!(buffer.samples(), 176);
+    }
+
+    #[test]
+    fn test_frame_buffer_exact_multiples() {
+        let mut buffer = AudioFrameBuffer::new(1024, AvRational::new(1, 1_000));
+        buffer.push(&frame(2048, 0, 0)).unwrap();
+
+        let first = buffer.pop().unwrap().unwrap();
+        let second = buffer.pop().unwrap().unwrap();
+        assert_eq!(first.samples(), 1024);
+        assert_eq!(second.samples(), 1024);
+        // 1024 samples at 48 kHz are 21.3 ms.
+        assert_eq!(first.pts(), Some(0));
+        assert_eq!(second.pts(), Some(21));
+        assert_eq!(samples(&second), (1024..2048).collect::<Vec<i16>>());
+        assert!(buffer.pop().unwrap().is_none());
+        assert!(buffer.flush().unwrap().is_none());
+        assert_eq!(buffer.samples(), 0);
+    }
+
+    #[test]
+    fn test_frame_buffer_flush_remainder() {
+        let mut buffer = AudioFrameBuffer::new(1024, AvRational::new(1, 48_000));
+        buffer.push(&frame(1500, 0, 0)).unwrap();
+        assert_eq!(buffer.pop().unwrap().unwrap().samples(), 1024);
+        assert!(buffer.pop().unwrap().is_none());
+
+        let last = buffer.flush().unwrap().unwrap();
+        assert_eq!(last.samples(), 476);
+        assert_eq!(last.pts(), Some(1024));
+        assert_eq!(samples(&last), (1024..1500).collect::<Vec<i16>>());
+        assert!(buffer.flush().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_frame_buffer_rejects_other_format() {
+        let mut buffer = AudioFrameBuffer::new(1024, AvRational::new(1, 48_000));
+        buffer.push(&frame(300, 0, 0)).unwrap();
+        let mut other = frame(300, 300, 300);
+        other.set_rate(44_100);
+        assert!(matches!(
+            buffer.push(&other),
+            Err(Error::InvalidFrameFormat)
+        ));
+        assert_eq!(buffer.samples(), 300);
+    }
+}
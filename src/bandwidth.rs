@@ -0,0 +1,300 @@
+//! Receive bandwidth estimation for live sources, and switching to fallback sources when the
+//! connection cannot keep up.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::io::ReadRecovery;
+use crate::limits::ResourceLimits;
+use crate::location::Location;
+use crate::multicast::Multicast;
+use crate::options::Options;
+use crate::stream::ResolutionPreference;
+
+/// Receive statistics of a [`Reader`](crate::io::Reader). See
+/// [`Reader::receive_stats`](crate::io::Reader::receive_stats).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReceiveStats {
+    /// Number of bytes of packets received since the source was opened.
+    pub bytes: u64,
+    /// Number of packets received since the source was opened.
+    pub packets: u64,
+    /// Receive bitrate over the last few seconds, in bits per second.
+    pub bitrate: u64,
+    /// Number of times reading blocked for longer than the stall threshold since the source was
+    /// opened.
+    pub stalls: u64,
+    /// Number of times the reader switched to a fallback source.
+    pub switches: u32,
+}
+
+/// When a [`Reader`](crate::io::Reader) switches to the next fallback source. See
+/// [`ReaderBuilder::with_fallback_sources`](crate::io::ReaderBuilder::with_fallback_sources).
+///
+/// Reading stalls when waiting for a single packet takes longer than the stall threshold. The
+/// reader switches when the number of stalls within the window reaches the maximum, so that a
+/// single hiccup does not cause a switch but a connection that keeps underrunning does. Waiting
+/// for a packet counts as a stall for every stall threshold it lasts, so a source that stops
+/// sending altogether is switched away from once waiting reaches the maximum number of stalls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FallbackPolicy {
+    /// How long waiting for a packet may take before it counts as a stall.
+    pub stall_threshold: Duration,
+    /// Number of stalls within the window that triggers a switch.
+    pub max_stalls: u32,
+    /// Period over which stalls are counted.
+    pub window: Duration,
+}
+
+impl FallbackPolicy {
+    /// Create a policy that switches after `max_stalls` stalls of `stall_threshold` within
+    /// `window`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stall_threshold` - How long waiting for a packet may take before it counts as a stall.
+    /// * `max_stalls` - Number of stalls within the window that triggers a switch.
+    /// * `window` - Period over which stalls are counted.
+    pub fn new(stall_threshold: Duration, max_stalls: u32, window: Duration) -> Self {
+        Self {
+            stall_threshold,
+            max_stalls: max_stalls.max(1),
+            window,
+        }
+    }
+}
+
+impl Default for FallbackPolicy {
+    /// Switch after 3 stalls of a second within 30 seconds.
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1), 3, Duration::from_secs(30))
+    }
+}
+
+/// Sources to switch to, in order, when to switch, and how to open them. See
+/// [`ReaderBuilder::with_fallback_sources`](crate::io::ReaderBuilder::with_fallback_sources).
+pub(crate) struct Fallback {
+    pub(crate) sources: VecDeque<Location>,
+    pub(crate) policy: FallbackPolicy,
+    /// Options of the main source, which the fallback sources are opened with as well.
+    pub(crate) options: Option<Options>,
+    pub(crate) input_format: Option<String>,
+    pub(crate) recovery: Option<ReadRecovery>,
+    pub(crate) multicast: Option<Multicast>,
+    pub(crate) rtsp_keepalive_interval: Option<Duration>,
+    pub(crate) resolution_preference: Option<ResolutionPreference>,
+    /// Limits of the main source, which start over for every fallback source.
+    pub(crate) resource_limits: Option<ResourceLimits>,
+}
+
+/// Measures the bytes received over time and the stalls of a source.
+#[derive(Debug)]
+pub(crate) struct ReceiveMeter {
+    stall_threshold: Duration,
+    stall_window: Duration,
+    /// Arrival time and size of the packets received within the bitrate window.
+    arrivals: VecDeque<(Instant, usize)>,
+    /// Times of the stalls within the stall window.
+    recent_stalls: VecDeque<Instant>,
+    stats: ReceiveStats,
+}
+
+impl ReceiveMeter {
+    /// Period over which the bitrate is averaged.
+    const BITRATE_WINDOW: Duration = Duration::from_secs(5);
+
+    /// Create a meter.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Stall threshold and window. The default policy is used when not switching.
+    pub(crate) fn new(policy: FallbackPolicy) -> Self {
+        Self {
+            stall_threshold: policy.stall_threshold,
+            stall_window: policy.window,
+            arrivals: VecDeque::new(),
+            recent_stalls: VecDeque::new(),
+            stats: ReceiveStats::default(),
+        }
+    }
+
+    /// Record a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time the packet arrived.
+    /// * `waited` - How long reading the packet blocked.
+    /// * `size` - Size of the packet in bytes.
+    pub(crate) fn record(&mut self, now: Instant, waited: Duration, size: usize) {
+        self.stats.bytes += size as u64;
+        self.stats.packets += 1;
+        self.arrivals.push_back((now, size));
+        while self
+            .arrivals
+            .front()
+            .is_some_and(|(arrival, _)| now.duration_since(*arrival) > Self::BITRATE_WINDOW)
+        {
+            self.arrivals.pop_front();
+        }
+        self.stalled(now, usize::from(waited > self.stall_threshold));
+    }
+
+    /// Record that reading was aborted without receiving a packet. It counts as a stall for every
+    /// stall threshold that it blocked.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time reading was aborted.
+    /// * `waited` - How long reading blocked.
+    pub(crate) fn record_blocked(&mut self, now: Instant, waited: Duration) {
+        let stalls = waited.as_nanos() / self.stall_threshold.as_nanos().max(1);
+        self.stalled(now, stalls.max(1) as usize);
+    }
+
+    /// Get how long reading may block before it reaches a number of stalls within the window.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_stalls` - Number of stalls.
+    pub(crate) fn time_to_stalls(&self, max_stalls: u32) -> Duration {
+        let remaining = (max_stalls as usize)
+            .saturating_sub(self.recent_stalls())
+            .max(1);
+        self.stall_threshold
+            .saturating_mul(remaining.min(u32::MAX as usize) as u32)
+    }
+
+    /// Record stalls, and forget the ones that left the stall window.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time of the stalls.
+    /// * `count` - Number of stalls.
+    fn stalled(&mut self, now: Instant, count: usize) {
+        self.stats.stalls += count as u64;
+        self.recent_stalls
+            .extend(std::iter::repeat(now).take(count));
+        while self
+            .recent_stalls
+            .front()
+            .is_some_and(|stall| now.duration_since(*stall) > self.stall_window)
+        {
+            self.recent_stalls.pop_front();
+        }
+    }
+
+    /// Get the number of stalls within the stall window.
+    pub(crate) fn recent_stalls(&self) -> usize {
+        self.recent_stalls.len()
+    }
+
+    /// Get the statistics.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Current time, which is the end of the bitrate window.
+    pub(crate) fn stats(&self, now: Instant) -> ReceiveStats {
+        let bytes = self
+            .arrivals
+            .iter()
+            .filter(|(arrival, _)| now.duration_since(*arrival) <= Self::BITRATE_WINDOW)
+            .map(|(_, size)| *size as u64)
+            .sum::<u64>();
+        // Average over the whole window once it is full, and over the time since the first
+        // packet before that, so the estimate is not too low right after opening.
+        let elapsed = self
+            .arrivals
+            .front()
+            .map(|(arrival, _)| now.duration_since(*arrival))
+            .unwrap_or_default()
+            .max(Duration::from_millis(100))
+            .min(Self::BITRATE_WINDOW);
+        ReceiveStats {
+            bitrate: (bytes as f64 * 8.0 / elapsed.as_secs_f64()) as u64,
+            ..self.stats
+        }
+    }
+
+    /// Start measuring a new source, keeping only the number of switches.
+    pub(crate) fn switched(&mut self) {
+        let switches = self.stats.switches + 1;
+        self.arrivals.clear();
+        self.recent_stalls.clear();
+        self.stats = ReceiveStats {
+            switches,
+            ..ReceiveStats::default()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitrate() {
+        let start = Instant::now();
+        let mut meter = ReceiveMeter::new(FallbackPolicy::default());
+        for i in 0..10 {
+            meter.record(start + Duration::from_secs(i), Duration::ZERO, 1000);
+        }
+        // The window holds the packets of the last 5 seconds: 6 packets of 8000 bits.
+        let stats = meter.stats(start + Duration::from_secs(9));
+        assert_eq!(stats.bytes, 10_000);
+        assert_eq!(stats.packets, 10);
+        assert_eq!(stats.bitrate, 9600);
+        assert_eq!(stats.stalls, 0);
+    }
+
+    #[test]
+    fn test_stalls_leave_window() {
+        let start = Instant::now();
+        let policy = FallbackPolicy::new(Duration::from_secs(1), 2, Duration::from_secs(10));
+        let mut meter = ReceiveMeter::new(policy);
+        meter.record(start, Duration::from_secs(2), 100);
+        meter.record(
+            start + Duration::from_secs(5),
+            Duration::from_millis(500),
+            100,
+        );
+        meter.record(start + Duration::from_secs(8), Duration::from_secs(3), 100);
+        assert_eq!(meter.recent_stalls(), 2);
+        meter.record(start + Duration::from_secs(15), Duration::ZERO, 100);
+        assert_eq!(meter.recent_stalls(), 1);
+        assert_eq!(meter.stats(start + Duration::from_secs(15)).stalls, 2);
+    }
+
+    #[test]
+    fn test_blocked_read_counts_stalls() {
+        let start = Instant::now();
+        let policy = FallbackPolicy::new(Duration::from_secs(1), 3, Duration::from_secs(30));
+        let mut meter = ReceiveMeter::new(policy);
+        meter.record(start, Duration::from_secs(2), 100);
+        assert_eq!(
+            meter.time_to_stalls(policy.max_stalls),
+            Duration::from_secs(2)
+        );
+        meter.record_blocked(start + Duration::from_secs(3), Duration::from_millis(2500));
+        assert_eq!(meter.recent_stalls(), 3);
+        assert_eq!(meter.stats(start).stalls, 3);
+        // A read is never aborted immediately.
+        assert_eq!(
+            meter.time_to_stalls(policy.max_stalls),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn test_switched_keeps_switch_count() {
+        let start = Instant::now();
+        let mut meter = ReceiveMeter::new(FallbackPolicy::default());
+        meter.record(start, Duration::from_secs(2), 100);
+        meter.switched();
+        meter.switched();
+        let stats = meter.stats(start);
+        assert_eq!(stats.switches, 2);
+        assert_eq!(stats.stalls, 0);
+        assert_eq!(stats.bitrate, 0);
+        assert_eq!(meter.recent_stalls(), 0);
+    }
+}
@@ -0,0 +1,536 @@
+//! Encoder benchmarks, to choose a codec and device for a deployment. Measures the throughput,
+//! per-frame latency and bit rate of encoding a generated test source, with the software encoder
+//! and every available hardware device. [`run_downloads`] compares the ways to download frames
+//! decoded with hardware acceleration, and with the `reference-scaler` feature, [`run_scalers`]
+//! compares the scaler backends the same way.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let results = bench::run(&BenchConfig::new(1920, 1080));
+//! print!("{}", bench::to_csv(&results));
+//! ```
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::decode::{CodecStatus, DecoderBuilder};
+use crate::encode::{Encoder, Settings};
+use crate::error::Error;
+use crate::frame::{RawFrame, FRAME_PIXEL_FORMAT};
+use crate::hwaccel::{HardwareAccelerationDeviceType, HardwareDownload};
+#[cfg(feature = "reference-scaler")]
+use crate::resize::ScalerBackend;
+#[cfg(feature = "reference-scaler")]
+use crate::scaler::Scaler;
+use crate::time::Time;
+use crate::topology::StageKind;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Frame rate of the generated test source.
+const FRAME_RATE: f64 = 30.0;
+
+/// Configuration of a benchmark run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchConfig {
+    /// Width of the test source.
+    pub width: usize,
+    /// Height of the test source.
+    pub height: usize,
+    /// Number of frames to encode per encoder.
+    pub frames: usize,
+    /// Whether to use the real-time (low latency) encoder presets.
+    pub realtime: bool,
+    /// Devices to benchmark. `None` is the software encoder.
+    pub devices: Vec<Option<HardwareAccelerationDeviceType>>,
+}
+
+impl BenchConfig {
+    /// Create a configuration for a resolution that benchmarks the software encoder and every
+    /// available hardware device, with 300 frames (10 seconds) each.
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - Width of the test source.
+    /// * `height` - Height of the test source.
+    pub fn new(width: usize, height: usize) -> Self {
+        let mut devices = vec![None];
+        devices.extend(
+            HardwareAccelerationDeviceType::list_available()
+                .into_iter()
+                .filter(|device_type| device_type.supports_encoding(ffmpeg::codec::Id::H264))
+                .map(Some),
+        );
+        Self {
+            width,
+            height,
+            frames: 300,
+            realtime: false,
+            devices,
+        }
+    }
+
+    /// Set the number of frames to encode per encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames.
+    pub fn with_frames(mut self, frames: usize) -> Self {
+        self.frames = frames;
+        self
+    }
+
+    /// Use the real-time (low latency) encoder presets.
+    pub fn realtime(mut self) -> Self {
+        self.realtime = true;
+        self
+    }
+}
+
+/// Distribution of the time from sending a frame to the encoder until its packet comes out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LatencyStats {
+    /// Lowest latency.
+    pub min: Duration,
+    /// Average latency.
+    pub mean: Duration,
+    /// Median latency.
+    pub p50: Duration,
+    /// 95th percentile of the latency.
+    pub p95: Duration,
+    /// 99th percentile of the latency.
+    pub p99: Duration,
+    /// Highest latency.
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    /// Compute the distribution of latency samples.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Latency of every frame.
+    pub fn from_samples(samples: &[Duration]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut samples = samples.to_vec();
+        samples.sort_unstable();
+        let percentile = |percentile: usize| {
+            samples[((samples.len() * percentile).div_ceil(100)).clamp(1, samples.len()) - 1]
+        };
+        Self {
+            min: samples[0],
+            mean: samples.iter().sum::<Duration>() / samples.len() as u32,
+            p50: percentile(50),
+            p95: percentile(95),
+            p99: percentile(99),
+            max: samples[samples.len() - 1],
+        }
+    }
+}
+
+/// Result of benchmarking one encoder.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct BenchResult {
+    /// Name of the encoder, like "libx264" or "h264_nvenc".
+    pub encoder: String,
+    /// Hardware device, or `None` for the software encoder.
+    pub device: Option<String>,
+    /// Number of frames encoded.
+    pub frames: usize,
+    /// Encoded frames per second.
+    pub fps: f64,
+    /// Per-frame latency.
+    pub latency: LatencyStats,
+    /// Bit rate of the output in bits per second, at the frame rate of the test source.
+    pub bit_rate: u64,
+    /// Error that stopped the benchmark of this encoder, if any.
+    pub error: Option<String>,
+}
+
+/// Benchmark the encoders of a configuration, one after the other. Encoders that fail (for
+/// instance because the device is busy) are reported with an error instead of failing the run.
+///
+/// # Arguments
+///
+/// * `config` - Benchmark configuration.
+pub fn run(config: &BenchConfig) -> Vec<BenchResult> {
+    config
+        .devices
+        .iter()
+        .enumerate()
+        .map(|(index, device)| {
+            let path = std::env::temp_dir()
+                .join(format!("rsmedia-bench-{}-{index}.mp4", std::process::id()));
+            let result = run_one(config, *device, &path).unwrap_or_else(|err| BenchResult {
+                encoder: String::new(),
+                device: device.map(|device| format!("{device:?}")),
+                frames: 0,
+                fps: 0.0,
+                latency: LatencyStats::default(),
+                bit_rate: 0,
+                error: Some(err.to_string()),
+            });
+            let _ = std::fs::remove_file(&path);
+            result
+        })
+        .collect()
+}
+
+/// Format benchmark results as CSV, with a header row. Latencies are in microseconds.
+///
+/// # Arguments
+///
+/// * `results` - Benchmark results.
+pub fn to_csv(results: &[BenchResult]) -> String {
+    let mut csv = String::from(
+        "encoder,device,frames,fps,latency_min_us,latency_mean_us,latency_p50_us,latency_p95_us,\
+         latency_p99_us,latency_max_us,bit_rate,error\n",
+    );
+    for result in results {
+        let latency = &result.latency;
+        csv.push_str(&format!(
+            "{},{},{},{:.2},{},{},{},{},{},{},{},{}\n",
+            result.encoder,
+            result.device.as_deref().unwrap_or(""),
+            result.frames,
+            result.fps,
+            latency.min.as_micros(),
+            latency.mean.as_micros(),
+            latency.p50.as_micros(),
+            latency.p95.as_micros(),
+            latency.p99.as_micros(),
+            latency.max.as_micros(),
+            result.bit_rate,
+            result
+                .error
+                .as_deref()
+                .unwrap_or("")
+                .replace([',', '\n'], " "),
+        ));
+    }
+    csv
+}
+
+/// Benchmark one encoder.
+///
+/// # Arguments
+///
+/// * `config` - Benchmark configuration.
+/// * `device` - Hardware device, or `None` for the software encoder.
+/// * `path` - Temporary file to encode to.
+fn run_one(
+    config: &BenchConfig,
+    device: Option<HardwareAccelerationDeviceType>,
+    path: &std::path::Path,
+) -> Result<BenchResult> {
+    let mut settings = Settings::preset_h264_yuv420p(config.width, config.height, config.realtime);
+    if let Some(device) = device {
+        settings.set_hardware_acceleration(device);
+    }
+    let mut encoder = Encoder::new(path, settings)?;
+    let encoder_name = encoder
+        .describe()
+        .stages
+        .into_iter()
+        .find(|stage| stage.kind == StageKind::Encoder)
+        .map(|stage| stage.name)
+        .unwrap_or_default();
+    let time_base = encoder.time_base();
+
+    let mut sent = HashMap::new();
+    let mut latencies = Vec::with_capacity(config.frames);
+    let mut receive = |encoder: &mut Encoder, sent: &mut HashMap<i64, Instant>| -> Result<()> {
+        while let CodecStatus::Ready(packet) = encoder.receive_packet()? {
+            if let Some(sent_at) = packet.pts().into_value().and_then(|pts| sent.remove(&pts)) {
+                latencies.push(sent_at.elapsed());
+            }
+            encoder.write_packet(packet)?;
+        }
+        Ok(())
+    };
+
+    let start = Instant::now();
+    for index in 0..config.frames {
+        let mut frame = test_frame(config.width as u32, config.height as u32, index);
+        let pts = Time::from_secs_f64(index as f64 / FRAME_RATE)
+            .aligned_with_rational(time_base)
+            .into_value();
+        frame.set_pts(pts);
+        if let Some(pts) = pts {
+            sent.insert(pts, Instant::now());
+        }
+        while let CodecStatus::Again = encoder.send_frame(frame.clone())? {
+            receive(&mut encoder, &mut sent)?;
+        }
+        receive(&mut encoder, &mut sent)?;
+    }
+    encoder.send_eof()?;
+    receive(&mut encoder, &mut sent)?;
+    let elapsed = start.elapsed();
+    encoder.finish()?;
+    drop(encoder);
+
+    let size = std::fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    let duration = config.frames as f64 / FRAME_RATE;
+    Ok(BenchResult {
+        encoder: encoder_name,
+        device: device.map(|device| format!("{device:?}")),
+        frames: config.frames,
+        fps: config.frames as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        latency: LatencyStats::from_samples(&latencies),
+        bit_rate: if duration > 0.0 {
+            (size as f64 * 8.0 / duration) as u64
+        } else {
+            0
+        },
+        error: None,
+    })
+}
+
+/// Result of benchmarking one way to download frames decoded with hardware acceleration.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DownloadBenchResult {
+    /// Hardware device the frames were decoded on.
+    pub device: String,
+    /// Download mode, like "Staged". See [`HardwareDownload`].
+    pub download: String,
+    /// Number of frames decoded and downloaded.
+    pub frames: usize,
+    /// Decoded and downloaded frames per second.
+    pub fps: f64,
+    /// Per-frame latency of decoding and downloading.
+    pub latency: LatencyStats,
+    /// Error that stopped the benchmark of this mode, if any.
+    pub error: Option<String>,
+}
+
+/// Benchmark the ways to download frames decoded with hardware acceleration to system memory (see
+/// [`HardwareDownload`]) on every hardware device of the configuration. The test source is encoded
+/// with the software encoder first. Modes that fail are reported with an error instead of failing
+/// the run.
+///
+/// # Arguments
+///
+/// * `config` - Benchmark configuration.
+pub fn run_downloads(config: &BenchConfig) -> Result<Vec<DownloadBenchResult>> {
+    let path = std::env::temp_dir().join(format!(
+        "rsmedia-bench-{}-downloads.mp4",
+        std::process::id()
+    ));
+    let encoded = run_one(config, None, &path);
+    let results = encoded.map(|_| {
+        let mut results = Vec::new();
+        for device in config.devices.iter().flatten() {
+            for download in [
+                HardwareDownload::Transfer,
+                HardwareDownload::Staged,
+                HardwareDownload::Mapped,
+            ] {
+                let result = run_download(&path, *device, download).unwrap_or_else(|err| {
+                    DownloadBenchResult {
+                        device: format!("{device:?}"),
+                        download: format!("{download:?}"),
+                        frames: 0,
+                        fps: 0.0,
+                        latency: LatencyStats::default(),
+                        error: Some(err.to_string()),
+                    }
+                });
+                results.push(result);
+            }
+        }
+        results
+    });
+    let _ = std::fs::remove_file(&path);
+    results
+}
+
+/// Benchmark one way to download frames decoded with hardware acceleration.
+///
+/// # Arguments
+///
+/// * `path` - Test source.
+/// * `device` - Hardware device to decode on.
+/// * `download` - Download mode.
+fn run_download(
+    path: &std::path::Path,
+    device: HardwareAccelerationDeviceType,
+    download: HardwareDownload,
+) -> Result<DownloadBenchResult> {
+    let mut decoder = DecoderBuilder::new(path)
+        .with_hardware_acceleration(device)
+        .with_hardware_download(download)
+        .build()?;
+    let mut latencies = Vec::new();
+    let start = Instant::now();
+    loop {
+        let decoded_at = Instant::now();
+        match decoder.decode_raw() {
+            Ok(_) => latencies.push(decoded_at.elapsed()),
+            Err(Error::DecodeExhausted) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    let elapsed = start.elapsed();
+    Ok(DownloadBenchResult {
+        device: format!("{device:?}"),
+        download: format!("{download:?}"),
+        frames: latencies.len(),
+        fps: latencies.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        latency: LatencyStats::from_samples(&latencies),
+        error: None,
+    })
+}
+
+/// Result of benchmarking one scaler backend on one conversion.
+#[cfg(feature = "reference-scaler")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ScalerBenchResult {
+    /// Scaler backend.
+    pub backend: String,
+    /// Conversion, like "yuv420p 1920x1080 -> rgb24 1920x1080".
+    pub conversion: String,
+    /// Number of frames converted.
+    pub frames: usize,
+    /// Converted frames per second.
+    pub fps: f64,
+    /// Per-frame latency.
+    pub latency: LatencyStats,
+}
+
+/// Benchmark the scaler backends on the conversions of decoding (YUV to RGB), encoding (RGB to
+/// YUV) and thumbnailing (RGB downscaled to half the size), at the size of the configuration.
+///
+/// # Arguments
+///
+/// * `config` - Benchmark configuration. The devices are ignored.
+#[cfg(feature = "reference-scaler")]
+pub fn run_scalers(config: &BenchConfig) -> Result<Vec<ScalerBenchResult>> {
+    let (width, height) = (config.width as u32, config.height as u32);
+    let rgb = test_frame(width, height, 0);
+    let mut yuv = RawFrame::empty();
+    Scaler::get(
+        ScalerBackend::Swscale,
+        (rgb.format(), width, height),
+        (ffmpeg::format::Pixel::YUV420P, width, height),
+        ffmpeg::software::scaling::flag::Flags::AREA,
+    )?
+    .run(&rgb, &mut yuv)?;
+    let conversions = [
+        (&yuv, (FRAME_PIXEL_FORMAT, width, height)),
+        (&rgb, (ffmpeg::format::Pixel::YUV420P, width, height)),
+        (&rgb, (FRAME_PIXEL_FORMAT, width / 2, height / 2)),
+    ];
+    let mut results = Vec::new();
+    for (input, output) in conversions {
+        for backend in [ScalerBackend::Swscale, ScalerBackend::Reference] {
+            let mut scaler = Scaler::get(
+                backend,
+                (input.format(), input.width(), input.height()),
+                output,
+                ffmpeg::software::scaling::flag::Flags::AREA,
+            )?;
+            let mut frame = RawFrame::empty();
+            let mut latencies = Vec::with_capacity(config.frames);
+            let start = Instant::now();
+            for _ in 0..config.frames {
+                let converted_at = Instant::now();
+                scaler.run(input, &mut frame)?;
+                latencies.push(converted_at.elapsed());
+            }
+            let elapsed = start.elapsed();
+            results.push(ScalerBenchResult {
+                backend: format!("{backend:?}"),
+                conversion: format!(
+                    "{} {}x{} -> {} {}x{}",
+                    input.format().descriptor().map_or("?", |d| d.name()),
+                    input.width(),
+                    input.height(),
+                    output.0.descriptor().map_or("?", |d| d.name()),
+                    output.1,
+                    output.2,
+                ),
+                frames: config.frames,
+                fps: config.frames as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+                latency: LatencyStats::from_samples(&latencies),
+            });
+        }
+    }
+    Ok(results)
+}
+
+/// Generate a frame of the test source: a gradient that moves with every frame, with some detail so
+/// that the encoder has work to do.
+///
+/// # Arguments
+///
+/// * `width` - Width of the frame.
+/// * `height` - Height of the frame.
+/// * `index` - Index of the frame.
+fn test_frame(width: u32, height: u32, index: usize) -> RawFrame {
+    let mut frame = RawFrame::new(FRAME_PIXEL_FORMAT, width, height);
+    let stride = frame.stride(0);
+    let data = frame.data_mut(0);
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let offset = y * stride + x * 3;
+            let moved = x + index * 4;
+            data[offset] = moved as u8;
+            data[offset + 1] = (y + index * 2) as u8;
+            data[offset + 2] = ((x ^ y) + index) as u8;
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_stats() {
+        let samples = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        let stats = LatencyStats::from_samples(&samples);
+        assert_eq!(stats.min, Duration::from_millis(1));
+        assert_eq!(stats.p50, Duration::from_millis(50));
+        assert_eq!(stats.p95, Duration::from_millis(95));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+        assert_eq!(stats.max, Duration::from_millis(100));
+        assert_eq!(stats.mean, Duration::from_micros(50_500));
+
+        assert_eq!(LatencyStats::from_samples(&[]), LatencyStats::default());
+    }
+
+    #[test]
+    fn test_run_downloads() {
+        let config = BenchConfig::new(64, 48).with_frames(10);
+        let devices = config.devices.iter().flatten().count();
+        let results = run_downloads(&config).unwrap();
+        assert_eq!(results.len(), devices * 3);
+        assert!(results
+            .iter()
+            .all(|result| result.error.is_some() || result.frames == 10));
+    }
+
+    #[test]
+    #[cfg(feature = "reference-scaler")]
+    fn test_run_scalers() {
+        let results = run_scalers(&BenchConfig::new(64, 48).with_frames(2)).unwrap();
+        assert_eq!(results.len(), 6);
+        assert!(results
+            .iter()
+            .all(|result| result.frames == 2 && result.fps > 0.0));
+        assert_eq!(results[0].conversion, "yuv420p 64x48 -> rgb24 64x48");
+        assert_eq!(results[0].backend, "Swscale");
+        assert_eq!(results[1].backend, "Reference");
+    }
+}
@@ -0,0 +1,434 @@
+//! C API for the high-level decode and encode pipeline.
+//!
+//! All functions use opaque handles and plain C types so that the ABI stays stable across
+//! releases. A C header can be generated with `cbindgen` (see `cbindgen.toml` in the repository
+//! root), and the library can be built as a shared library with:
+//!
+//! ```text
+//! cargo rustc --release --features capi --crate-type cdylib
+//! ```
+//!
+//! Frames are exchanged as tightly packed RGB24 buffers of `width * height * 3` bytes.
+//!
+//! # Example
+//!
+//! ```c
+//! rsmedia_init();
+//! RsmediaDecoder *decoder = rsmedia_decoder_open("video.mp4");
+//! uint32_t width, height;
+//! rsmedia_decoder_size(decoder, &width, &height);
+//! uint8_t *buffer = malloc(width * height * 3);
+//! double timestamp;
+//! while (rsmedia_decoder_decode(decoder, buffer, width * height * 3, &timestamp) == RSMEDIA_OK) {
+//!     // Do something with frame...
+//! }
+//! rsmedia_decoder_close(decoder);
+//! ```
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::AssertUnwindSafe;
+
+use ffmpeg::util::format::Pixel as AvPixel;
+
+use crate::decode::Decoder;
+use crate::encode::{Encoder, Settings};
+use crate::error::Error;
+use crate::frame::RawFrame;
+use crate::location::Location;
+use crate::time::Time;
+
+/// Operation succeeded.
+pub const RSMEDIA_OK: c_int = 0;
+/// The decoder is exhausted, there are no more frames.
+pub const RSMEDIA_END: c_int = 1;
+/// Operation failed. Call [`rsmedia_last_error`] for a description of the error.
+pub const RSMEDIA_ERROR: c_int = -1;
+
+/// Opaque decoder handle.
+pub struct RsmediaDecoder(Decoder);
+
+/// Opaque encoder handle.
+pub struct RsmediaEncoder(Encoder);
+
+thread_local! {
+    /// Description of the last error that occurred on this thread.
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Store error as the last error for this thread.
+///
+/// # Arguments
+///
+/// * `error` - Error to store.
+fn set_last_error(error: impl std::fmt::Display) {
+    let description = CString::new(error.to_string().replace('\0', ""))
+        .expect("string without nul bytes converts to CString");
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(description));
+}
+
+/// Run the body of an exported function, so that a panic does not unwind into the caller, which
+/// is undefined behavior. A panic is stored as the last error.
+///
+/// # Arguments
+///
+/// * `on_panic` - Value to return if the body panics.
+/// * `body` - Body of the function.
+fn catch_panic<T>(on_panic: T, body: impl FnOnce() -> T) -> T {
+    match std::panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(value) => value,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            set_last_error(format!("panic: {message}"));
+            on_panic
+        }
+    }
+}
+
+/// Get a reference to the value behind a pointer, or store an error if it is null.
+///
+/// # Arguments
+///
+/// * `pointer` - Pointer that must be valid or null.
+/// * `name` - Name of the argument, for the error.
+unsafe fn non_null<'a, T>(pointer: *const T, name: &str) -> Option<&'a T> {
+    let value = pointer.as_ref();
+    if value.is_none() {
+        set_last_error(format!("{name} is null"));
+    }
+    value
+}
+
+/// Get a mutable reference to the value behind a pointer, or store an error if it is null.
+///
+/// # Arguments
+///
+/// * `pointer` - Pointer that must be valid or null.
+/// * `name` - Name of the argument, for the error.
+unsafe fn non_null_mut<'a, T>(pointer: *mut T, name: &str) -> Option<&'a mut T> {
+    let value = pointer.as_mut();
+    if value.is_none() {
+        set_last_error(format!("{name} is null"));
+    }
+    value
+}
+
+/// Convert a C string holding a path or URL to a [`Location`].
+///
+/// # Arguments
+///
+/// * `location` - Path or URL. Must be a valid nul-terminated string or null.
+unsafe fn location_from_c_str(location: *const c_char) -> Option<Location> {
+    if location.is_null() {
+        set_last_error("location is null");
+        return None;
+    }
+    match CStr::from_ptr(location).to_str() {
+        Ok(location) => Some(Location::parse(location)),
+        Err(err) => {
+            set_last_error(err);
+            None
+        }
+    }
+}
+
+/// Initialize global ffmpeg settings and logging. Should be called once before any other function.
+///
+/// Returns [`RSMEDIA_OK`] on success and [`RSMEDIA_ERROR`] otherwise.
+#[no_mangle]
+pub extern "C" fn rsmedia_init() -> c_int {
+    catch_panic(RSMEDIA_ERROR, || match crate::init::init() {
+        Ok(()) => RSMEDIA_OK,
+        Err(err) => {
+            set_last_error(err);
+            RSMEDIA_ERROR
+        }
+    })
+}
+
+/// Description of the last error that occurred on the calling thread, or null if no error occurred.
+///
+/// The returned string is owned by the library and valid until the next call that fails on the
+/// same thread.
+#[no_mangle]
+pub extern "C" fn rsmedia_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match last_error.borrow().as_ref() {
+        Some(description) => description.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+/// Open a decoder for the best video stream of a file or URL.
+///
+/// Returns null on failure. The decoder must be closed with [`rsmedia_decoder_close`].
+///
+/// # Safety
+///
+/// `source` must be a valid nul-terminated string or null.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_decoder_open(source: *const c_char) -> *mut RsmediaDecoder {
+    catch_panic(std::ptr::null_mut(), || {
+        let Some(source) = location_from_c_str(source) else {
+            return std::ptr::null_mut();
+        };
+        match Decoder::new(source) {
+            Ok(decoder) => Box::into_raw(Box::new(RsmediaDecoder(decoder))),
+            Err(err) => {
+                set_last_error(err);
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Get the size of the frames produced by the decoder.
+///
+/// Returns [`RSMEDIA_OK`] on success and [`RSMEDIA_ERROR`] if any of the pointers is null.
+///
+/// # Safety
+///
+/// `decoder` must be a handle returned by [`rsmedia_decoder_open`] or null. `width` and `height`
+/// must be valid pointers or null.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_decoder_size(
+    decoder: *const RsmediaDecoder,
+    width: *mut u32,
+    height: *mut u32,
+) -> c_int {
+    catch_panic(RSMEDIA_ERROR, || {
+        let (Some(decoder), Some(width), Some(height)) = (
+            non_null(decoder, "decoder"),
+            non_null_mut(width, "width"),
+            non_null_mut(height, "height"),
+        ) else {
+            return RSMEDIA_ERROR;
+        };
+        (*width, *height) = decoder.0.size_out();
+        RSMEDIA_OK
+    })
+}
+
+/// Get the frame rate of the decoder stream, or 0 if it is unknown or `decoder` is null.
+///
+/// # Safety
+///
+/// `decoder` must be a handle returned by [`rsmedia_decoder_open`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_decoder_frame_rate(decoder: *const RsmediaDecoder) -> f32 {
+    catch_panic(0.0, || {
+        non_null(decoder, "decoder").map_or(0.0, |decoder| decoder.0.frame_rate())
+    })
+}
+
+/// Decode the next frame into `buffer` as packed RGB24.
+///
+/// Returns [`RSMEDIA_OK`] if a frame was decoded, [`RSMEDIA_END`] if the decoder is exhausted and
+/// [`RSMEDIA_ERROR`] otherwise.
+///
+/// # Safety
+///
+/// `decoder` must be a handle returned by [`rsmedia_decoder_open`] or null. `buffer` must point
+/// to at least `buffer_size` writable bytes or be null. `timestamp` must be a valid pointer or
+/// null, it receives the frame timestamp in seconds.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_decoder_decode(
+    decoder: *mut RsmediaDecoder,
+    buffer: *mut u8,
+    buffer_size: usize,
+    timestamp: *mut f64,
+) -> c_int {
+    catch_panic(RSMEDIA_ERROR, || {
+        decode(decoder, buffer, buffer_size, timestamp)
+    })
+}
+
+/// Body of [`rsmedia_decoder_decode`].
+unsafe fn decode(
+    decoder: *mut RsmediaDecoder,
+    buffer: *mut u8,
+    buffer_size: usize,
+    timestamp: *mut f64,
+) -> c_int {
+    let Some(RsmediaDecoder(decoder)) = non_null_mut(decoder, "decoder") else {
+        return RSMEDIA_ERROR;
+    };
+    if buffer.is_null() {
+        set_last_error("buffer is null");
+        return RSMEDIA_ERROR;
+    }
+    let frame = match decoder.decode_raw() {
+        Ok(frame) => frame,
+        Err(Error::DecodeExhausted) => return RSMEDIA_END,
+        Err(err) => {
+            set_last_error(err);
+            return RSMEDIA_ERROR;
+        }
+    };
+
+    let row_size = frame.width() as usize * 3;
+    let rows = frame.height() as usize;
+    if buffer_size < row_size * rows {
+        set_last_error(Error::InvalidFrameFormat);
+        return RSMEDIA_ERROR;
+    }
+    let stride = frame.stride(0);
+    let data = frame.data(0);
+    let buffer = std::slice::from_raw_parts_mut(buffer, row_size * rows);
+    for (row, buffer_row) in buffer.chunks_exact_mut(row_size).enumerate() {
+        buffer_row.copy_from_slice(&data[row * stride..row * stride + row_size]);
+    }
+
+    if !timestamp.is_null() {
+        // Use the packet DTS for consistency with `Decoder::decode`.
+        *timestamp = Time::new(Some(frame.packet().dts), decoder.time_base()).as_secs_f64();
+    }
+
+    RSMEDIA_OK
+}
+
+/// Close a decoder and free all resources held by it.
+///
+/// # Safety
+///
+/// `decoder` must be a handle returned by [`rsmedia_decoder_open`] or null. It may not be used
+/// after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_decoder_close(decoder: *mut RsmediaDecoder) {
+    if !decoder.is_null() {
+        catch_panic((), || drop(Box::from_raw(decoder)));
+    }
+}
+
+/// Open an H264 encoder that writes to a file or URL.
+///
+/// Returns null on failure. The encoder must be closed with [`rsmedia_encoder_close`].
+///
+/// # Safety
+///
+/// `destination` must be a valid nul-terminated string or null.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_encoder_open(
+    destination: *const c_char,
+    width: u32,
+    height: u32,
+    realtime: bool,
+) -> *mut RsmediaEncoder {
+    catch_panic(std::ptr::null_mut(), || {
+        let Some(destination) = location_from_c_str(destination) else {
+            return std::ptr::null_mut();
+        };
+        let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, realtime);
+        match Encoder::new(destination, settings) {
+            Ok(encoder) => Box::into_raw(Box::new(RsmediaEncoder(encoder))),
+            Err(err) => {
+                set_last_error(err);
+                std::ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// Encode a packed RGB24 frame of the size the encoder was opened with.
+///
+/// Returns [`RSMEDIA_OK`] on success and [`RSMEDIA_ERROR`] otherwise.
+///
+/// # Safety
+///
+/// `encoder` must be a handle returned by [`rsmedia_encoder_open`] or null. `buffer` must point
+/// to at least `buffer_size` readable bytes or be null.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_encoder_encode(
+    encoder: *mut RsmediaEncoder,
+    buffer: *const u8,
+    buffer_size: usize,
+    timestamp: f64,
+) -> c_int {
+    catch_panic(RSMEDIA_ERROR, || {
+        encode(encoder, buffer, buffer_size, timestamp)
+    })
+}
+
+/// Body of [`rsmedia_encoder_encode`].
+unsafe fn encode(
+    encoder: *mut RsmediaEncoder,
+    buffer: *const u8,
+    buffer_size: usize,
+    timestamp: f64,
+) -> c_int {
+    let Some(RsmediaEncoder(encoder)) = non_null_mut(encoder, "encoder") else {
+        return RSMEDIA_ERROR;
+    };
+    if buffer.is_null() {
+        set_last_error("buffer is null");
+        return RSMEDIA_ERROR;
+    }
+    let (width, height) = encoder.size();
+
+    let row_size = width as usize * 3;
+    let rows = height as usize;
+    if buffer_size < row_size * rows {
+        set_last_error(Error::InvalidFrameFormat);
+        return RSMEDIA_ERROR;
+    }
+    let buffer = std::slice::from_raw_parts(buffer, row_size * rows);
+    let mut frame = RawFrame::new(AvPixel::RGB24, width, height);
+    let stride = frame.stride(0);
+    let data = frame.data_mut(0);
+    for (row, buffer_row) in buffer.chunks_exact(row_size).enumerate() {
+        data[row * stride..row * stride + row_size].copy_from_slice(buffer_row);
+    }
+    frame.set_pts(
+        Time::from_secs_f64(timestamp)
+            .aligned_with_rational(encoder.time_base())
+            .into_value(),
+    );
+
+    match encoder.encode_raw(frame) {
+        Ok(()) => RSMEDIA_OK,
+        Err(err) => {
+            set_last_error(err);
+            RSMEDIA_ERROR
+        }
+    }
+}
+
+/// Flush the encoder and write the trailer.
+///
+/// Returns [`RSMEDIA_OK`] on success and [`RSMEDIA_ERROR`] otherwise.
+///
+/// # Safety
+///
+/// `encoder` must be a handle returned by [`rsmedia_encoder_open`] or null.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_encoder_finish(encoder: *mut RsmediaEncoder) -> c_int {
+    catch_panic(RSMEDIA_ERROR, || {
+        let Some(RsmediaEncoder(encoder)) = non_null_mut(encoder, "encoder") else {
+            return RSMEDIA_ERROR;
+        };
+        match encoder.finish() {
+            Ok(()) => RSMEDIA_OK,
+            Err(err) => {
+                set_last_error(err);
+                RSMEDIA_ERROR
+            }
+        }
+    })
+}
+
+/// Close an encoder and free all resources held by it. Finishes the encoder if that did not happen
+/// yet.
+///
+/// # Safety
+///
+/// `encoder` must be a handle returned by [`rsmedia_encoder_open`] or null. It may not be used
+/// after this call.
+#[no_mangle]
+pub unsafe extern "C" fn rsmedia_encoder_close(encoder: *mut RsmediaEncoder) {
+    if !encoder.is_null() {
+        catch_panic((), || drop(Box::from_raw(encoder)));
+    }
+}
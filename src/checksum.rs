@@ -0,0 +1,187 @@
+//! Hashes of decoded media content, computed while remuxing.
+//!
+//! Hashing the decoded content (like the `framemd5` muxer of ffmpeg does) instead of the file
+//! makes it possible to check that two files contain the same media, even if they use different
+//! containers. See [`MuxerBuilder::with_content_hash`](crate::mux::MuxerBuilder::with_content_hash).
+
+use ffmpeg::codec::decoder::Opened as AvDecoder;
+use ffmpeg::codec::Context as AvContext;
+use ffmpeg::codec::Parameters as AvCodecParameters;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::frame::Frame as AvFrame;
+use ffmpeg::Error as AvError;
+
+use crate::error::Error;
+use crate::ffi;
+use crate::stream::MediaType;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Hash function for content hashes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    /// MD5, like the `framemd5` muxer of ffmpeg.
+    #[default]
+    Md5,
+    /// SHA-256.
+    Sha256,
+    /// CRC-32.
+    Crc32,
+}
+
+impl HashAlgorithm {
+    /// Name of the hash function in `libavutil`.
+    fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "MD5",
+            HashAlgorithm::Sha256 => "SHA256",
+            HashAlgorithm::Crc32 => "CRC32",
+        }
+    }
+}
+
+/// Hash of the content of one stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamHash {
+    /// Index of the stream in the source.
+    pub stream_index: usize,
+    /// Type of media in the stream.
+    pub media_type: MediaType,
+    /// Hash function used.
+    pub algorithm: HashAlgorithm,
+    /// Hash as lowercase hexadecimal string.
+    pub hash: String,
+    /// Number of frames that were hashed. For streams that are not decoded, like subtitles and
+    /// streams without a decoder, the number of packets.
+    pub frames: u64,
+    /// Number of packets that failed to decode. A hash with decode errors does not describe the
+    /// full content of the stream.
+    pub decode_errors: u64,
+}
+
+/// Hashes the content of one stream. Audio and video packets are decoded and the decoded samples
+/// are hashed. Packets of other streams (like subtitles), and of streams that no decoder is
+/// available for, are hashed as they are.
+pub(crate) struct ContentHasher {
+    stream_index: usize,
+    media_type: MediaType,
+    algorithm: HashAlgorithm,
+    decoder: Option<AvDecoder>,
+    hash: ffi::HashContext,
+    frames: u64,
+    decode_errors: u64,
+}
+
+impl ContentHasher {
+    /// Create a hasher for a stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream in the source.
+    /// * `parameters` - Codec parameters of the stream.
+    /// * `algorithm` - Hash function to use.
+    pub(crate) fn new(
+        stream_index: usize,
+        parameters: AvCodecParameters,
+        algorithm: HashAlgorithm,
+    ) -> Result<Self> {
+        let media_type = parameters.medium();
+        let decoder = match media_type {
+            MediaType::Video | MediaType::Audio => match ffmpeg::decoder::find(parameters.id()) {
+                Some(codec) => Some(
+                    AvContext::from_parameters(parameters)?
+                        .decoder()
+                        .open_as(codec)?,
+                ),
+                None => {
+                    tracing::warn!(
+                        "no decoder for stream {stream_index} ({}), hashing its packets instead",
+                        parameters.id().name(),
+                    );
+                    None
+                }
+            },
+            _ => None,
+        };
+        let hash = ffi::HashContext::new(algorithm.name())
+            .ok_or(Error::BackendError(AvError::OptionNotFound))?;
+        Ok(Self {
+            stream_index,
+            media_type,
+            algorithm,
+            decoder,
+            hash,
+            frames: 0,
+            decode_errors: 0,
+        })
+    }
+
+    /// Add a packet of the stream to the hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet of the stream.
+    pub(crate) fn update(&mut self, packet: &ffmpeg::Packet) -> Result<()> {
+        match self.decoder.as_mut() {
+            Some(decoder) => {
+                if let Err(err) = decoder.send_packet(packet) {
+                    tracing::warn!(
+                        "failed to decode packet of stream {} for hashing: {err}",
+                        self.stream_index
+                    );
+                    self.decode_errors += 1;
+                }
+                self.receive_frames()
+            }
+            None => {
+                if let Some(data) = packet.data() {
+                    self.hash.update(data);
+                }
+                self.frames += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Drain the decoder and finish the hash.
+    pub(crate) fn finish(mut self) -> Result<StreamHash> {
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder.send_eof()?;
+            self.receive_frames()?;
+        }
+        Ok(StreamHash {
+            stream_index: self.stream_index,
+            media_type: self.media_type,
+            algorithm: self.algorithm,
+            hash: self.hash.finish_hex(),
+            frames: self.frames,
+            decode_errors: self.decode_errors,
+        })
+    }
+
+    /// Hash the frames that the decoder has available.
+    fn receive_frames(&mut self) -> Result<()> {
+        let Some(decoder) = self.decoder.as_mut() else {
+            return Ok(());
+        };
+        let mut frame = unsafe { AvFrame::empty() };
+        loop {
+            match decoder.receive_frame(&mut frame) {
+                Ok(()) => {
+                    ffi::frame_content(&frame, |data| self.hash.update(data));
+                    self.frames += 1;
+                }
+                Err(AvError::Eof) => return Ok(()),
+                Err(AvError::Other { errno }) if errno == EAGAIN => return Ok(()),
+                Err(err) => {
+                    tracing::warn!(
+                        "failed to decode frame of stream {} for hashing: {err}",
+                        self.stream_index
+                    );
+                    self.decode_errors += 1;
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::time::Time;
+
+/// Clock that drives presentation in live pipelines.
+///
+/// Readers and encoders that mix live sources compare the timestamps of their frames against a
+/// single clock, and use [`MediaClock::sync`] to decide whether to present, drop or duplicate a
+/// frame. This keeps all streams consistent with each other.
+pub trait MediaClock: Send + Sync {
+    /// Current time of the clock.
+    fn now(&self) -> Time;
+
+    /// Decide what to do with a frame, by comparing its timestamp against the clock.
+    ///
+    /// A frame that is late by more than one frame duration is dropped. A frame that is early by
+    /// more than one frame duration is held back, and the previous frame is duplicated to fill the
+    /// gap.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_time` - Presentation timestamp of the frame.
+    /// * `frame_duration` - Duration of a single frame.
+    fn sync(&self, frame_time: Time, frame_duration: Time) -> SyncAction {
+        SyncAction::decide(
+            frame_time.as_secs_f64() - self.now().as_secs_f64(),
+            frame_duration.as_secs_f64(),
+        )
+    }
+}
+
+/// What to do with a frame to keep it in sync with the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Frame is on time and should be presented.
+    Present,
+    /// Frame is late and should be dropped.
+    Drop,
+    /// Frame is early. The previous frame should be duplicated the contained number of times
+    /// before presenting the frame.
+    Duplicate(u32),
+}
+
+impl SyncAction {
+    /// Decide on an action by the difference between a frame and the clock.
+    ///
+    /// # Arguments
+    ///
+    /// * `diff` - Frame time minus clock time in seconds.
+    /// * `frame_duration` - Duration of a single frame in seconds.
+    fn decide(diff: f64, frame_duration: f64) -> Self {
+        if frame_duration <= 0.0 {
+            SyncAction::Present
+        } else if diff < -frame_duration {
+            SyncAction::Drop
+        } else if diff > frame_duration {
+            SyncAction::Duplicate((diff / frame_duration) as u32)
+        } else {
+            SyncAction::Present
+        }
+    }
+}
+
+/// Clock that follows the system monotonic clock.
+#[derive(Debug, Clone, Copy)]
+pub struct WallClock {
+    start: Instant,
+    offset: Duration,
+}
+
+impl WallClock {
+    /// Create a wallclock that starts at zero now.
+    pub fn new() -> Self {
+        Self::starting_at(Duration::ZERO)
+    }
+
+    /// Create a wallclock that starts at the specified time now.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Time of the clock now.
+    pub fn starting_at(offset: Duration) -> Self {
+        Self {
+            start: Instant::now(),
+            offset,
+        }
+    }
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MediaClock for WallClock {
+    fn now(&self) -> Time {
+        (self.offset + self.start.elapsed()).into()
+    }
+}
+
+/// Clock that is driven by the audio samples that are played or captured.
+///
+/// Audio devices consume and produce samples at a fixed rate, which makes audio the natural master
+/// clock: the audio thread reports its progress with [`AudioClock::advance`] (or
+/// [`AudioClock::set`] with the timestamp of an audio frame), and the video side synchronizes
+/// against it. In between reports the clock is interpolated with the wallclock, up to the length of
+/// the last reported block, so that the clock stalls when audio stalls.
+#[derive(Debug)]
+pub struct AudioClock {
+    sample_rate: u32,
+    state: Mutex<AudioClockState>,
+}
+
+#[derive(Debug)]
+struct AudioClockState {
+    position: Duration,
+    last_block: Duration,
+    updated_at: Option<Instant>,
+}
+
+impl AudioClock {
+    /// Create an audio clock at zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate of the audio that drives the clock.
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            state: Mutex::new(AudioClockState {
+                position: Duration::ZERO,
+                last_block: Duration::ZERO,
+                updated_at: None,
+            }),
+        }
+    }
+
+    /// Sample rate of the audio that drives the clock.
+    #[inline]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Advance the clock by a number of samples (per channel) that were played or captured.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Number of samples.
+    pub fn advance(&self, samples: usize) {
+        if self.sample_rate == 0 {
+            return;
+        }
+        let block = Duration::from_secs_f64(samples as f64 / self.sample_rate as f64);
+        let mut state = self.state.lock().unwrap();
+        state.position += block;
+        state.last_block = block;
+        state.updated_at = Some(Instant::now());
+    }
+
+    /// Set the clock to the timestamp of the audio frame that is being played or was captured.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Timestamp of the audio frame.
+    pub fn set(&self, time: Time) {
+        let mut state = self.state.lock().unwrap();
+        state.position = time.into();
+        state.updated_at = Some(Instant::now());
+    }
+}
+
+impl MediaClock for AudioClock {
+    fn now(&self) -> Time {
+        let state = self.state.lock().unwrap();
+        let interpolated = state
+            .updated_at
+            .map(|updated_at| updated_at.elapsed().min(state.last_block))
+            .unwrap_or_default();
+        (state.position + interpolated).into()
+    }
+}
+
+/// Master clock that all streams in a live mix synchronize against.
+#[derive(Debug)]
+pub enum MasterClock {
+    /// Audio drives the clock.
+    Audio(AudioClock),
+    /// The system clock drives the clock, for mixes without audio.
+    Wall(WallClock),
+}
+
+impl MasterClock {
+    /// Create a master clock driven by audio.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate of the audio that drives the clock.
+    pub fn audio(sample_rate: u32) -> Self {
+        MasterClock::Audio(AudioClock::new(sample_rate))
+    }
+
+    /// Create a master clock driven by the system clock, starting at zero now.
+    pub fn wall() -> Self {
+        MasterClock::Wall(WallClock::new())
+    }
+
+    /// Get the audio clock, if the master clock is driven by audio.
+    pub fn as_audio(&self) -> Option<&AudioClock> {
+        match self {
+            MasterClock::Audio(clock) => Some(clock),
+            MasterClock::Wall(_) => None,
+        }
+    }
+}
+
+impl MediaClock for MasterClock {
+    fn now(&self) -> Time {
+        match self {
+            MasterClock::Audio(clock) => clock.now(),
+            MasterClock::Wall(clock) => clock.now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sync_action_decide() {
+        assert_eq!(SyncAction::decide(0.0, 0.04), SyncAction::Present);
+        assert_eq!(SyncAction::decide(0.03, 0.04), SyncAction::Present);
+        assert_eq!(SyncAction::decide(-0.03, 0.04), SyncAction::Present);
+        assert_eq!(SyncAction::decide(-0.05, 0.04), SyncAction::Drop);
+        assert_eq!(SyncAction::decide(0.09, 0.04), SyncAction::Duplicate(2));
+    }
+
+    #[test]
+    fn test_sync_action_decide_without_frame_duration() {
+        assert_eq!(SyncAction::decide(-1.0, 0.0), SyncAction::Present);
+    }
+
+    #[test]
+    fn test_audio_clock_advance() {
+        let clock = AudioClock::new(48_000);
+        clock.advance(48_000);
+        clock.advance(24_000);
+        let now = clock.now().as_secs_f64();
+        assert!((1.5..=2.0).contains(&now));
+    }
+
+    #[test]
+    fn test_audio_clock_set() {
+        let clock = AudioClock::new(48_000);
+        clock.set(Time::from_secs(3.0));
+        assert_eq!(clock.now(), Time::from_secs(3.0));
+    }
+
+    #[test]
+    fn test_master_clock_sync() {
+        let clock = MasterClock::audio(1_000);
+        clock.as_audio().unwrap().set(Time::from_secs(1.0));
+        let frame_duration = Time::from_nth_of_a_second(25);
+        assert_eq!(
+            clock.sync(Time::from_secs(0.5), frame_duration),
+            SyncAction::Drop
+        );
+        assert_eq!(
+            clock.sync(Time::from_secs(1.0), frame_duration),
+            SyncAction::Present
+        );
+        assert!(matches!(
+            clock.sync(Time::from_secs(2.0), frame_duration),
+            SyncAction::Duplicate(_)
+        ));
+    }
+}
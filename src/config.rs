@@ -0,0 +1,314 @@
+//! Declarative configuration of readers, writers, decoders, encoders and transcoding pipelines,
+//! which can be stored or sent to another service (it is serializable with the `serde` feature)
+//! and turned into the builders with [`FromConfig`].
+
+use crate::decode::DecoderBuilder;
+use crate::encode::{EncoderBuilder, Settings, SettingsSnapshot};
+use crate::error::Error;
+#[cfg(feature = "filter")]
+use crate::filter::Filter;
+use crate::hwaccel::HardwareAccelerationDeviceType;
+use crate::io::{ReaderBuilder, WriterBuilder};
+use crate::location::{Location, Url};
+use crate::options::Options;
+use crate::resize::Resize;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Builders that can be created from a configuration. The builder borrows the options and format
+/// of the configuration, like it borrows them when they are set on the builder directly.
+///
+/// # Example
+///
+/// ```ignore
+/// let config: DecoderConfig = serde_json::from_str(&job)?;
+/// let decoder = DecoderBuilder::from_config(&config)?.build()?;
+/// ```
+pub trait FromConfig<'a>: Sized {
+    /// Configuration the builder is created from.
+    type Config;
+
+    /// Create a builder from a configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration.
+    fn from_config(config: &'a Self::Config) -> Result<Self>;
+}
+
+/// Source to read from. See [`ReaderBuilder`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SourceConfig {
+    /// Path of a file, or URL of a network source like `rtsp://camera/stream`.
+    pub location: String,
+    /// Input format, like `rawvideo`, to use instead of probing the source.
+    pub format: Option<String>,
+    /// Options of the demuxer and protocol.
+    pub options: Option<Options>,
+}
+
+impl SourceConfig {
+    /// Create the configuration of a source.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - Path of a file, or URL of a network source.
+    pub fn new(location: impl Into<String>) -> Self {
+        Self {
+            location: location.into(),
+            format: None,
+            options: None,
+        }
+    }
+
+    /// Get the location of the source.
+    pub fn location(&self) -> Location {
+        parse_location(&self.location)
+    }
+}
+
+/// Destination to write to. See [`WriterBuilder`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SinkConfig {
+    /// Path of a file, or URL of a network destination like `rtmp://server/live/key`.
+    pub location: String,
+    /// Container format, like `mp4`, to use instead of guessing it from the location.
+    pub format: Option<String>,
+    /// Options of the muxer and protocol.
+    pub options: Option<Options>,
+}
+
+impl SinkConfig {
+    /// Create the configuration of a destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - Path of a file, or URL of a network destination.
+    pub fn new(location: impl Into<String>) -> Self {
+        Self {
+            location: location.into(),
+            format: None,
+            options: None,
+        }
+    }
+
+    /// Get the location of the destination.
+    pub fn location(&self) -> Location {
+        parse_location(&self.location)
+    }
+}
+
+/// Decoder of the video stream of a source. See [`DecoderBuilder`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DecoderConfig {
+    /// Source to decode. Decoders probe the input format, so the format must not be set.
+    pub source: SourceConfig,
+    /// How to resize the decoded frames.
+    pub resize: Option<Resize>,
+    /// Filter graph to pass the decoded frames through, like `hflip`. Requires the `filter`
+    /// feature.
+    pub filter: Option<String>,
+    /// Hardware device type to decode with.
+    pub hardware_acceleration: Option<HardwareAccelerationDeviceType>,
+}
+
+impl DecoderConfig {
+    /// Create the configuration of a decoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to decode.
+    pub fn new(source: SourceConfig) -> Self {
+        Self {
+            source,
+            resize: None,
+            filter: None,
+            hardware_acceleration: None,
+        }
+    }
+}
+
+/// Encoder of a video stream to a destination. See [`EncoderBuilder`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EncoderConfig {
+    /// Destination to write to.
+    pub sink: SinkConfig,
+    /// Encoder settings, including the encoder, the hardware device type and the rate control.
+    pub settings: SettingsSnapshot,
+    /// Filter graph to pass the frames through before encoding, like `hflip`. Requires the
+    /// `filter` feature.
+    pub filter: Option<String>,
+}
+
+impl EncoderConfig {
+    /// Create the configuration of an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Destination to write to.
+    /// * `settings` - Encoder settings. See [`Settings::snapshot`].
+    pub fn new(sink: SinkConfig, settings: &Settings) -> Self {
+        Self {
+            sink,
+            settings: settings.snapshot(),
+            filter: None,
+        }
+    }
+}
+
+/// Transcoding pipeline from a decoder to an encoder. See
+/// [`Transcoder::from_config`](crate::transcode::Transcoder::from_config).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut source = SourceConfig::new("rtsp://camera/stream");
+/// source.options = Some(Options::preset_rtsp_transport_tcp());
+/// let settings = Settings::preset_h264_yuv420p(1280, 720, false).with_bitrate(2_000_000);
+/// let config = PipelineConfig {
+///     decoder: DecoderConfig::new(source),
+///     encoder: EncoderConfig::new(SinkConfig::new("output.mp4"), &settings),
+/// };
+/// let job = serde_json::to_string(&config)?;
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PipelineConfig {
+    /// Decoder of the source.
+    pub decoder: DecoderConfig,
+    /// Encoder of the destination.
+    pub encoder: EncoderConfig,
+}
+
+impl<'a> FromConfig<'a> for ReaderBuilder<'a> {
+    type Config = SourceConfig;
+
+    fn from_config(config: &'a SourceConfig) -> Result<Self> {
+        let mut builder = ReaderBuilder::new(config.location());
+        if let Some(format) = config.format.as_deref() {
+            builder = builder.with_input_format(format);
+        }
+        if let Some(options) = config.options.as_ref() {
+            builder = builder.with_options(options);
+        }
+        Ok(builder)
+    }
+}
+
+impl<'a> FromConfig<'a> for WriterBuilder<'a> {
+    type Config = SinkConfig;
+
+    fn from_config(config: &'a SinkConfig) -> Result<Self> {
+        let mut builder = WriterBuilder::new(config.location());
+        if let Some(format) = config.format.as_deref() {
+            builder = builder.with_format(format);
+        }
+        if let Some(options) = config.options.as_ref() {
+            builder = builder.with_options(options);
+        }
+        Ok(builder)
+    }
+}
+
+impl<'a> FromConfig<'a> for DecoderBuilder<'a> {
+    type Config = DecoderConfig;
+
+    fn from_config(config: &'a DecoderConfig) -> Result<Self> {
+        if config.source.format.is_some() {
+            return Err(Error::InvalidConfig(
+                "decoders do not take an input format".to_string(),
+            ));
+        }
+        let mut builder = DecoderBuilder::new(config.source.location());
+        if let Some(options) = config.source.options.as_ref() {
+            builder = builder.with_options(options);
+        }
+        if let Some(resize) = config.resize {
+            builder = builder.with_resize(resize);
+        }
+        if let Some(filter) = config.filter.as_deref() {
+            #[cfg(feature = "filter")]
+            {
+                builder = builder.with_filter(Filter::new(filter));
+            }
+            #[cfg(not(feature = "filter"))]
+            return Err(filter_unavailable(filter));
+        }
+        if let Some(device_type) = config.hardware_acceleration {
+            builder = builder.with_hardware_acceleration(device_type);
+        }
+        Ok(builder)
+    }
+}
+
+impl<'a> FromConfig<'a> for EncoderBuilder<'a> {
+    type Config = EncoderConfig;
+
+    fn from_config(config: &'a EncoderConfig) -> Result<Self> {
+        let settings = Settings::from_snapshot(&config.settings)?;
+        let mut builder = EncoderBuilder::new(config.sink.location(), settings);
+        if let Some(format) = config.sink.format.as_deref() {
+            builder = builder.with_format(format);
+        }
+        if let Some(options) = config.sink.options.as_ref() {
+            builder = builder.with_options(options);
+        }
+        if let Some(filter) = config.filter.as_deref() {
+            #[cfg(feature = "filter")]
+            {
+                builder = builder.with_filter(Filter::new(filter));
+            }
+            #[cfg(not(feature = "filter"))]
+            return Err(filter_unavailable(filter));
+        }
+        Ok(builder)
+    }
+}
+
+/// Get the error for a filter in a configuration when filters are not available.
+///
+/// # Arguments
+///
+/// * `spec` - Description of the filter graph.
+#[cfg(not(feature = "filter"))]
+fn filter_unavailable(spec: &str) -> Error {
+    Error::InvalidConfig(format!("filter `{spec}` requires the `filter` feature"))
+}
+
+/// Parse the location of a source or destination: a URL if it has a scheme, and a path otherwise.
+/// Single letter schemes are taken for Windows drive letters, like in `C:\video.mp4`.
+///
+/// # Arguments
+///
+/// * `location` - Path or URL.
+fn parse_location(location: &str) -> Location {
+    match Url::parse(location) {
+        Ok(url) if url.scheme().len() > 1 => Location::Network(url),
+        _ => Location::File(location.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_location() {
+        assert_eq!(
+            parse_location("rtsp://camera/stream"),
+            Location::Network(Url::parse("rtsp://camera/stream").unwrap()),
+        );
+        assert_eq!(
+            parse_location("videos/input.mp4"),
+            Location::File("videos/input.mp4".into()),
+        );
+        assert_eq!(
+            parse_location(r"C:\videos\input.mp4"),
+            Location::File(r"C:\videos\input.mp4".into()),
+        );
+    }
+}
@@ -1,35 +1,230 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
 use ffmpeg::codec::decoder::Video as AvDecoder;
 use ffmpeg::codec::Context as AvContext;
 use ffmpeg::format::pixel::Pixel as AvPixel;
-use ffmpeg::software::scaling::{context::Context as AvScaler, flag::Flags as AvScalerFlags};
+use ffmpeg::util::color::Range as AvColorRange;
 use ffmpeg::util::error::EAGAIN;
 use ffmpeg::{Error as AvError, Rational as AvRational};
 
+use crate::affinity::ThreadPolicy;
+use crate::clock::{MediaClock, SyncAction};
+use crate::encode::{Deadline, ParallelismSupport, ShutdownReport};
 use crate::error::Error;
 use crate::ffi;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::ffi_hwaccel;
-#[cfg(feature = "ndarray")]
-use crate::frame::Frame;
+#[cfg(feature = "filter")]
+use crate::filter::Filter;
 use crate::frame::RawFrame;
-use crate::hwaccel::{HardwareAccelerationContext, HardwareAccelerationDeviceType};
+#[cfg(feature = "filter")]
+use crate::frame::FRAME_PIXEL_FORMAT;
+#[cfg(feature = "ndarray")]
+use crate::frame::{Frame, FrameBatch};
+#[cfg(target_os = "android")]
+use crate::hwaccel::MediaCodecSurface;
+use crate::hwaccel::{
+    self, HardwareAccelerationContext, HardwareAccelerationDeviceType, HardwareDownload,
+};
+use crate::interrupt::Interrupt;
 use crate::io::{Reader, ReaderBuilder};
+use crate::limits::{ResourceLimit, ResourceLimits};
 use crate::location::Location;
+use crate::memory::{MemoryCategory, MemoryReservation};
 use crate::options::Options;
 use crate::packet::Packet;
-use crate::resize::Resize;
+use crate::queue::{DropPolicy, FrameQueue, FrameQueueStats};
+use crate::resize::{Resize, ScalerBackend, ScalerProfile};
+use crate::scaler::Scaler;
+use crate::stream::{MediaDescription, ResolutionPreference};
+use crate::thumbnail::ThumbnailTap;
 use crate::time::Time;
+use crate::topology::{PipelineDescription, Stage, StageKind};
 
 type Result<T> = std::result::Result<T, Error>;
 
 /// Always use NV12 pixel format with hardware acceleration, then rescale later.
 static HWACCEL_PIXEL_FORMAT: AvPixel = AvPixel::NV12;
 
+/// Result of a step of the send/receive codec API, like [`Decoder::send_packet`] and
+/// [`Decoder::receive_frame`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecStatus<T> {
+    /// The step succeeded.
+    Ready(T),
+    /// The codec cannot make progress in this direction right now. After a failed send, receive
+    /// output first. After a failed receive, send more input first.
+    Again,
+    /// The codec was drained (after sending end of stream) and does not produce more output, or
+    /// does not accept more input.
+    Eof,
+}
+
+impl<T> CodecStatus<T> {
+    /// Get the value if the step succeeded.
+    pub fn ready(self) -> Option<T> {
+        match self {
+            CodecStatus::Ready(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Change of the stream parameters in the middle of a stream, like a camera that switches
+/// resolution or a new SPS in a transport stream. See [`Decoder::take_parameter_change`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParameterChange {
+    /// Decoded size before the change.
+    pub previous_size: (u32, u32),
+    /// Decoded pixel format before the change.
+    pub previous_format: AvPixel,
+    /// Decoded size after the change.
+    pub size: (u32, u32),
+    /// Decoded pixel format after the change.
+    pub format: AvPixel,
+    /// Output size after the change, with resizing applied.
+    pub size_out: (u32, u32),
+}
+
+/// Range of a stream that was skipped to resynchronize after corrupt data. See
+/// [`DecoderBuilder::with_resync`] and [`Decoder::take_skipped_ranges`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedRange {
+    /// Timestamp of the packet that failed.
+    pub start: Time,
+    /// Timestamp of the keyframe decoding resumed at, or of the last packet if the stream ended
+    /// before the next keyframe.
+    pub end: Time,
+    /// Number of packets skipped, including the packet that failed.
+    pub packets: u64,
+    /// Error that caused the skip.
+    pub error: AvError,
+}
+
+/// State of resynchronization after corrupt data.
+struct Resync {
+    max_errors: u64,
+    errors: u64,
+    last_time: Time,
+    skipping: Option<SkippedRange>,
+    skipped: Vec<SkippedRange>,
+}
+
+impl Resync {
+    fn new(max_errors: u64) -> Self {
+        Self {
+            max_errors,
+            errors: 0,
+            last_time: Time::zero(),
+            skipping: None,
+            skipped: Vec::new(),
+        }
+    }
+
+    /// Record a failed packet and start skipping up to the next keyframe.
+    fn fail(&mut self, time: Time, error: AvError) -> Result<()> {
+        self.errors += 1;
+        if self.errors > self.max_errors {
+            return Err(Error::ResourceLimitExceeded(ResourceLimit::DecodeErrors(
+                self.max_errors,
+            )));
+        }
+        match self.skipping.as_mut() {
+            Some(range) => range.packets += 1,
+            None => {
+                self.skipping = Some(SkippedRange {
+                    start: time,
+                    end: time,
+                    packets: 1,
+                    error,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether to decode the packet. Packets are skipped after a failure until the next keyframe.
+    fn accept(&mut self, packet: &Packet) -> bool {
+        let time = packet.pts();
+        if time.has_value() {
+            self.last_time = time;
+        }
+        match self.skipping.as_mut() {
+            Some(_) if packet.is_key() => {
+                self.finish();
+                true
+            }
+            Some(range) => {
+                range.packets += 1;
+                false
+            }
+            None => true,
+        }
+    }
+
+    /// Close the range being skipped, if any, at the last packet seen.
+    fn finish(&mut self) {
+        if let Some(mut range) = self.skipping.take() {
+            range.end = self.last_time;
+            self.skipped.push(range);
+        }
+    }
+
+    /// Whether an error is caused by corrupt data that can be skipped. Other errors, like an
+    /// interrupted read or the end of the stream, are not recovered from.
+    ///
+    /// # Arguments
+    ///
+    /// * `error` - Error of reading or decoding a packet.
+    fn is_recoverable(error: &AvError) -> bool {
+        matches!(error, AvError::InvalidData)
+    }
+}
+
+/// Number of frames a decoder is estimated to keep around for reference, reordering and frame
+/// threading, used to account decoder memory.
+const ESTIMATED_DECODER_FRAMES: usize = 8;
+
+/// What to do with sources that exceed the maximum dimensions set with
+/// [`DecoderBuilder::max_dimensions`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Refuse to decode the source and return [`Error::MaxDimensionsExceeded`].
+    #[default]
+    Reject,
+    /// Decode the source at reduced resolution (`lowres`) if the decoder supports it, and scale the
+    /// frames down to fit within the maximum dimensions.
+    ///
+    /// Note that most modern decoders (like H.264, HEVC and AV1) do not support `lowres`, in which
+    /// case frames are decoded at full resolution before they are scaled.
+    Downscale,
+}
+
 /// Builds a [`Decoder`].
 pub struct DecoderBuilder<'a> {
     source: Location,
+    custom_source: Option<Box<dyn ffi::InputSource>>,
     options: Option<&'a Options>,
     resize: Option<Resize>,
+    scaler_profile: ScalerProfile,
+    scaler_backend: ScalerBackend,
+    resolution_preference: Option<ResolutionPreference>,
+    max_dimensions: Option<(u32, u32)>,
+    oversize_policy: OversizePolicy,
+    resource_limits: Option<ResourceLimits>,
+    resync_max_errors: Option<u64>,
+    #[cfg(feature = "filter")]
+    filter: Option<Filter>,
     hardware_acceleration_device_type: Option<HardwareAccelerationDeviceType>,
+    hardware_frames: bool,
+    hardware_download: HardwareDownload,
+    thread_policy: Option<ThreadPolicy>,
+    interrupt: Option<Interrupt>,
+    clock: Option<Arc<dyn MediaClock>>,
+    #[cfg(target_os = "android")]
+    mediacodec_surface: Option<MediaCodecSurface>,
 }
 
 impl<'a> DecoderBuilder<'a> {
@@ -39,12 +234,39 @@ impl<'a> DecoderBuilder<'a> {
     pub fn new(source: impl Into<Location>) -> Self {
         Self {
             source: source.into(),
+            custom_source: None,
             options: None,
             resize: None,
+            scaler_profile: ScalerProfile::default(),
+            scaler_backend: ScalerBackend::default(),
+            resolution_preference: None,
+            max_dimensions: None,
+            oversize_policy: OversizePolicy::default(),
+            resource_limits: None,
+            resync_max_errors: None,
+            #[cfg(feature = "filter")]
+            filter: None,
             hardware_acceleration_device_type: None,
+            hardware_frames: false,
+            hardware_download: HardwareDownload::default(),
+            thread_policy: None,
+            interrupt: None,
+            clock: None,
+            #[cfg(target_os = "android")]
+            mediacodec_surface: None,
         }
     }
 
+    /// Create a decoder that decodes from an arbitrary byte source. See
+    /// [`ReaderBuilder::from_reader`].
+    ///
+    /// * `source` - Source to decode.
+    pub fn from_reader(source: impl std::io::Read + std::io::Seek + Send + 'static) -> Self {
+        let mut builder = Self::new(crate::io::custom_source_location());
+        builder.custom_source = Some(Box::new(source));
+        builder
+    }
+
     /// Set custom options. Options are applied to the input.
     ///
     /// * `options` - Custom options.
@@ -61,7 +283,95 @@ impl<'a> DecoderBuilder<'a> {
         self
     }
 
-    /// Enable hardware acceleration with the specified device type.
+    /// Set the scaler profile to use when converting frames.
+    ///
+    /// Use [`ScalerProfile::Exact`] to get pixel-faithful RGB frames, for example for QR code or
+    /// barcode detection.
+    ///
+    /// * `scaler_profile` - Scaler profile to use.
+    pub fn with_scaler_profile(mut self, scaler_profile: ScalerProfile) -> Self {
+        self.scaler_profile = scaler_profile;
+        self
+    }
+
+    /// Set the backend to use when converting frames. By default, frames are converted with
+    /// swscale.
+    ///
+    /// * `scaler_backend` - Scaler backend to use.
+    pub fn with_scaler_backend(mut self, scaler_backend: ScalerBackend) -> Self {
+        self.scaler_backend = scaler_backend;
+        self
+    }
+
+    /// Select the video stream to decode by resolution, when the source has multiple video streams.
+    ///
+    /// See [`ReaderBuilder::select_stream`] for more information.
+    ///
+    /// * `preference` - Resolution preference to select stream by.
+    pub fn select_stream(mut self, preference: ResolutionPreference) -> Self {
+        self.resolution_preference = Some(preference);
+        self
+    }
+
+    /// Set the maximum dimensions of the frames to decode. This protects against decompression
+    /// bombs: sources that declare huge dimensions (like 16000x16000) to exhaust memory.
+    ///
+    /// Sources that exceed the maximum are handled according to the [`OversizePolicy`] (by default
+    /// they are rejected). The decoder also refuses frames that exceed the maximum after a
+    /// resolution change in the middle of the stream.
+    ///
+    /// * `width` - Maximum width.
+    /// * `height` - Maximum height.
+    pub fn max_dimensions(mut self, width: u32, height: u32) -> Self {
+        self.max_dimensions = Some((width, height));
+        self
+    }
+
+    /// Set what to do with sources that exceed the maximum dimensions.
+    ///
+    /// * `policy` - Policy for oversized sources.
+    pub fn with_oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.oversize_policy = policy;
+        self
+    }
+
+    /// Set limits on the resources the decoder may consume. See [`ResourceLimits`].
+    ///
+    /// * `limits` - Resource limits.
+    pub fn with_resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.resource_limits = Some(limits);
+        self
+    }
+
+    /// Resynchronize after corrupt data instead of failing. When a packet fails to decode (or the
+    /// demuxer fails to read one), the codec is reset and packets are skipped up to the next
+    /// keyframe, after which decoding continues. Skipped ranges are reported through
+    /// [`Decoder::take_skipped_ranges`].
+    ///
+    /// Packets are skipped by reading forward rather than seeking, so this also works with sources
+    /// that cannot seek, like live streams.
+    ///
+    /// * `max_errors` - Maximum number of errors to recover from. After that decoding fails with
+    ///   [`Error::ResourceLimitExceeded`].
+    pub fn with_resync(mut self, max_errors: u64) -> Self {
+        self.resync_max_errors = Some(max_errors);
+        self
+    }
+
+    /// Pass the decoded frames through a [`Filter`]. The filter gets frames as the decoder would
+    /// return them otherwise (in RGB24, after resizing), and its output is converted back to RGB24.
+    /// The filter may change the size of the frames, in which case [`Decoder::size_out`] does not
+    /// match the size of the frames.
+    ///
+    /// * `filter` - Filter to apply.
+    #[cfg(feature = "filter")]
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Enable hardware acceleration with the specified device type. Decoded frames are downloaded
+    /// to system memory, unless [`DecoderBuilder::with_hardware_frames`] is used.
     ///
     /// * `device_type` - Device to use for hardware acceleration.
     pub fn with_hardware_acceleration(
@@ -72,24 +382,145 @@ impl<'a> DecoderBuilder<'a> {
         self
     }
 
+    /// Keep frames decoded with hardware acceleration in device memory, instead of downloading
+    /// them to system memory and scaling them. The frames are returned as-is by
+    /// [`Decoder::decode_raw`], and can be encoded without a round trip through system memory by an
+    /// encoder set up with
+    /// [`Settings::with_hardware_frames`](crate::encode::Settings::with_hardware_frames) and the
+    /// pool of [`HardwareFrames::of`](crate::hwaccel::HardwareFrames::of).
+    ///
+    /// Frames the decoder falls back to decoding in software are downloaded and scaled as usual.
+    /// Has no effect without [`DecoderBuilder::with_hardware_acceleration`]. Filters, thumbnail taps
+    /// and conversion to ndarray do not work with hardware frames.
+    pub fn with_hardware_frames(mut self) -> Self {
+        self.hardware_frames = true;
+        self
+    }
+
+    /// Set how frames decoded with hardware acceleration are downloaded to system memory. Reusing
+    /// a staging buffer or mapping the frames speeds up pipelines that decode on the GPU and
+    /// process the frames on the CPU, like inference on ndarray frames, where the download is
+    /// often the bottleneck. See [`HardwareDownload`].
+    ///
+    /// Has no effect without [`DecoderBuilder::with_hardware_acceleration`].
+    ///
+    /// * `download` - How to download frames.
+    pub fn with_hardware_download(mut self, download: HardwareDownload) -> Self {
+        self.hardware_download = download;
+        self
+    }
+
+    /// Pin the decoding threads to CPUs and set their priority. See [`ThreadPolicy`].
+    ///
+    /// # Arguments
+    ///
+    /// * `thread_policy` - Affinity and priority of the decoding threads.
+    pub fn with_thread_policy(mut self, thread_policy: ThreadPolicy) -> Self {
+        self.thread_policy = Some(thread_policy);
+        self
+    }
+
+    /// Interrupt blocking operations of the reader with a handle, including opening the source.
+    /// See [`ReaderBuilder::with_interrupt`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interrupt` - Interrupt handle.
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Drop decoded frames that are late against a clock, like the master clock of a live mix.
+    /// See [`ReaderBuilder::with_clock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to synchronize against.
+    pub fn with_clock(mut self, clock: Arc<dyn MediaClock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    /// Enable MediaCodec hardware decoding and render decoded frames to an Android surface.
+    ///
+    /// Frames decoded to a surface stay in MediaCodec buffers. They are returned as-is by
+    /// [`Decoder::decode_raw`] and must be released with
+    /// [`hwaccel::android::release_frame`](crate::hwaccel::android::release_frame).
+    ///
+    /// * `surface` - Surface to render to.
+    #[cfg(target_os = "android")]
+    pub fn with_mediacodec_surface(mut self, surface: MediaCodecSurface) -> Self {
+        self.hardware_acceleration_device_type = Some(HardwareAccelerationDeviceType::MediaCodec);
+        self.mediacodec_surface = Some(surface);
+        self
+    }
+
     /// Build [`Decoder`].
     pub fn build(self) -> Result<Decoder> {
-        let mut reader_builder = ReaderBuilder::new(self.source);
+        let mut reader_builder = match self.custom_source {
+            Some(custom_source) => ReaderBuilder::from_reader(custom_source),
+            None => ReaderBuilder::new(self.source),
+        };
         if let Some(options) = self.options {
             reader_builder = reader_builder.with_options(options);
         }
+        if let Some(preference) = self.resolution_preference {
+            reader_builder = reader_builder.select_stream(preference);
+        }
+        if let Some(limits) = self.resource_limits {
+            reader_builder = reader_builder.with_resource_limits(limits);
+        }
+        if let Some(interrupt) = self.interrupt {
+            reader_builder = reader_builder.with_interrupt(interrupt);
+        }
+        if let Some(clock) = self.clock {
+            reader_builder = reader_builder.with_clock(clock);
+        }
         let reader = reader_builder.build()?;
         let reader_stream_index = reader.best_video_stream_index()?;
-        Ok(Decoder {
-            decoder: DecoderSplit::new(
+        // The codec threads are started when the decoder is opened. The reader is moved in and
+        // out, so that the decoder can be opened on the helper thread of the thread policy.
+        let open = move || {
+            #[cfg(target_os = "android")]
+            if let Some(surface) = self.mediacodec_surface {
+                let decoder = DecoderSplit::new_with_mediacodec_surface(
+                    &reader,
+                    reader_stream_index,
+                    surface,
+                );
+                return (reader, decoder);
+            }
+            let decoder = DecoderSplit::new_with_max_dimensions(
                 &reader,
                 reader_stream_index,
                 self.resize,
+                self.scaler_profile,
+                self.scaler_backend,
                 self.hardware_acceleration_device_type,
-            )?,
+                self.max_dimensions
+                    .map(|(width, height)| (width, height, self.oversize_policy)),
+            );
+            (reader, decoder)
+        };
+        let (reader, decoder) = match self.thread_policy.as_ref() {
+            Some(thread_policy) => thread_policy.run(open),
+            None => open(),
+        };
+        let decoder = decoder?;
+        let decoder = decoder
+            .with_hardware_frames(self.hardware_frames)
+            .with_hardware_download(self.hardware_download);
+        #[cfg(feature = "filter")]
+        let decoder = decoder.with_filter(self.filter);
+        Ok(Decoder {
+            decoder,
             reader,
             reader_stream_index,
             draining: false,
+            resync: self.resync_max_errors.map(Resync::new),
+            pending_error: None,
+            shut_down: false,
         })
     }
 }
@@ -111,6 +542,11 @@ pub struct Decoder {
     reader: Reader,
     reader_stream_index: usize,
     draining: bool,
+    resync: Option<Resync>,
+    /// Error that ended a batch early. It is returned by the next call, after the partial batch.
+    pending_error: Option<Error>,
+    /// Whether [`Decoder::shutdown`] was called, after which no more packets are read.
+    shut_down: bool,
 }
 
 impl Decoder {
@@ -124,6 +560,42 @@ impl Decoder {
         DecoderBuilder::new(source).build()
     }
 
+    /// Create a decoder that decodes the best video stream of a reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader to decode from.
+    /// * `resize` - Optional resize strategy to apply to frames.
+    /// * `passthrough` - Whether or not to pass frames on without downloading nor scaling them. See
+    ///   [`DecoderSplit::new_passthrough`].
+    pub(crate) fn from_reader(
+        reader: Reader,
+        resize: Option<Resize>,
+        passthrough: bool,
+    ) -> Result<Self> {
+        let reader_stream_index = reader.best_video_stream_index()?;
+        let decoder = if passthrough {
+            DecoderSplit::new_passthrough(&reader, reader_stream_index)?
+        } else {
+            DecoderSplit::new(
+                &reader,
+                reader_stream_index,
+                resize,
+                ScalerProfile::default(),
+                None,
+            )?
+        };
+        Ok(Self {
+            decoder,
+            reader,
+            reader_stream_index,
+            draining: false,
+            resync: None,
+            pending_error: None,
+            shut_down: false,
+        })
+    }
+
     /// Get decoder time base.
     #[inline]
     pub fn time_base(&self) -> AvRational {
@@ -191,20 +663,29 @@ impl Decoder {
     /// ```
     #[cfg(feature = "ndarray")]
     pub fn decode(&mut self) -> Result<(Time, Frame)> {
+        if self.shut_down {
+            return Err(Error::DecodeExhausted);
+        }
         Ok(loop {
             if !self.draining {
-                let packet_result = self.reader.read(self.reader_stream_index);
-                if matches!(packet_result, Err(Error::ReadExhausted)) {
-                    self.draining = true;
-                    continue;
-                }
-                let packet = packet_result?;
-                if let Some(frame) = self.decoder.decode(packet)? {
-                    break frame;
+                let packet = match self.read_packet_to_decode()? {
+                    Some(packet) => packet,
+                    None => {
+                        self.draining = true;
+                        continue;
+                    }
+                };
+                let time = packet.pts();
+                match self.decoder.decode(packet) {
+                    Ok(Some(frame)) if !self.is_late(frame.0) => break frame,
+                    Ok(Some(_)) => {}
+                    Ok(None) => {}
+                    Err(err) => self.recover(err, time)?,
                 }
             } else {
                 match self.decoder.drain() {
-                    Ok(Some(frame)) => break frame,
+                    Ok(Some(frame)) if !self.is_late(frame.0) => break frame,
+                    Ok(Some(_)) => {}
                     Ok(None) | Err(Error::ReadExhausted) => {
                         self.decoder.reset();
                         self.draining = false;
@@ -216,34 +697,72 @@ impl Decoder {
         })
     }
 
+    /// Whether a decoded frame is late against the clock of the reader, and must be dropped. See
+    /// [`ReaderBuilder::with_clock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Timestamp of the frame.
+    fn is_late(&self, time: Time) -> bool {
+        let Some(clock) = self.reader.clock.as_ref() else {
+            return false;
+        };
+        if !time.has_value() {
+            return false;
+        }
+        let frame_rate = self.frame_rate();
+        let frame_duration = if frame_rate > 0.0 {
+            Time::from_secs_f64(1.0 / frame_rate as f64)
+        } else {
+            Time::zero()
+        };
+        clock.sync(time, frame_duration) == SyncAction::Drop
+    }
+
     /// Decode frames through iterator interface. This is similar to `decode_raw` but it returns
     /// frames through an infinite iterator.
     pub fn decode_raw_iter(&mut self) -> impl Iterator<Item = Result<RawFrame>> + '_ {
         std::iter::from_fn(move || Some(self.decode_raw()))
     }
 
-    /// Decode a single frame and return the raw ffmpeg `AvFrame`.
+    /// Decode a single frame and return the raw ffmpeg `AvFrame`. The frame keeps its side data,
+    /// like HDR metadata and closed captions, which can be read with
+    /// [`FrameSideData::from_frame`](crate::sidedata::FrameSideData::from_frame).
     ///
     /// # Return value
     ///
     /// The decoded raw frame as [`RawFrame`].
     pub fn decode_raw(&mut self) -> Result<RawFrame> {
+        if let Some(err) = self.pending_error.take() {
+            return Err(err);
+        }
+        if self.shut_down {
+            return Err(Error::DecodeExhausted);
+        }
         Ok(loop {
             if !self.draining {
-                let packet_result = self.reader.read(self.reader_stream_index);
-                if matches!(packet_result, Err(Error::ReadExhausted)) {
-                    self.draining = true;
-                    continue;
-                }
-                let packet = packet_result?;
-                if let Some(frame) = self.decoder.decode_raw(packet)? {
-                    break frame;
+                let packet = match self.read_packet_to_decode()? {
+                    Some(packet) => packet,
+                    None => {
+                        self.draining = true;
+                        continue;
+                    }
+                };
+                let time = packet.pts();
+                match self.decoder.decode_raw(packet) {
+                    Ok(Some(frame)) if !self.is_late(Time::new(frame.pts(), self.time_base())) => {
+                        break frame
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => {}
+                    Err(err) => self.recover(err, time)?,
                 }
-            } else if let Some(frame) = self.decoder.drain_raw()? {
-                break frame;
             } else {
                 match self.decoder.drain_raw() {
-                    Ok(Some(frame)) => break frame,
+                    Ok(Some(frame)) if !self.is_late(Time::new(frame.pts(), self.time_base())) => {
+                        break frame
+                    }
+                    Ok(Some(_)) => {}
                     Ok(None) | Err(Error::ReadExhausted) => {
                         self.decoder.reset();
                         self.draining = false;
@@ -255,6 +774,77 @@ impl Decoder {
         })
     }
 
+    /// Decode a batch of frames, for consumers that work in fixed-size batches like machine
+    /// learning data loaders.
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames in the batch.
+    ///
+    /// # Return value
+    ///
+    /// The frames with their timestamps. The batch only has fewer frames at the end of the stream,
+    /// or when decoding fails after some frames of the batch were decoded, in which case the error
+    /// is returned by the next call. [`Error::DecodeExhausted`] is returned when no frames are
+    /// left.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_batch(&mut self, frames: usize) -> Result<Vec<(Time, Frame)>> {
+        let time_base = self.time_base();
+        self.decode_raw_batch(frames)?
+            .into_iter()
+            .map(|mut frame| raw_frame_to_time_and_frame(&mut frame, time_base))
+            .collect()
+    }
+
+    /// Decode a batch of frames into a single array with dimensions `(N, H, W, C)`, which saves
+    /// allocating an array per frame. All frames must have the same size, which is the case unless
+    /// the stream changes resolution without [`DecoderBuilder::with_resize`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames in the batch.
+    ///
+    /// # Return value
+    ///
+    /// The timestamps of the frames and the frames. The batch only has fewer frames at the end of
+    /// the stream. [`Error::DecodeExhausted`] is returned when no frames are left.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// while let Ok((timestamps, batch)) = decoder.decode_batch_array(32) {
+    ///     let tensor = batch.mapv(|value| value as f32 / 255.0);
+    ///     model.run(tensor);
+    /// }
+    /// ```
+    #[cfg(feature = "ndarray")]
+    pub fn decode_batch_array(&mut self, frames: usize) -> Result<(Vec<Time>, FrameBatch)> {
+        let time_base = self.time_base();
+        raw_frames_to_batch(self.decode_raw_batch(frames)?, time_base)
+    }
+
+    /// Decode a batch of raw frames. See [`Decoder::decode_batch`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames in the batch.
+    pub fn decode_raw_batch(&mut self, frames: usize) -> Result<Vec<RawFrame>> {
+        let mut batch = Vec::with_capacity(frames);
+        while batch.len() < frames {
+            match self.decode_raw() {
+                Ok(frame) => batch.push(frame),
+                Err(Error::DecodeExhausted) if !batch.is_empty() => break,
+                // Return the frames that were decoded, and the error on the next call.
+                Err(err) if !batch.is_empty() => {
+                    self.pending_error = Some(err);
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(batch)
+    }
+
     /// Seek in reader.
     ///
     /// See [`Reader::seek`](crate::io::Reader::seek) for more information.
@@ -298,6 +888,127 @@ impl Decoder {
         (self.decoder, self.reader, self.reader_stream_index)
     }
 
+    /// Read the next packet of the decoded stream, to pass to [`Decoder::send_packet`].
+    ///
+    /// This and the functions below expose the send/receive state machine of the codec, for
+    /// callers that schedule decoding themselves instead of using [`Decoder::decode_raw`]:
+    ///
+    /// ```ignore
+    /// loop {
+    ///     match decoder.receive_frame()? {
+    ///         CodecStatus::Ready(frame) => handle(frame),
+    ///         CodecStatus::Again => match decoder.read_packet() {
+    ///             Ok(packet) => {
+    ///                 decoder.send_packet(packet)?;
+    ///             }
+    ///             Err(Error::ReadExhausted) => {
+    ///                 decoder.send_eof()?;
+    ///             }
+    ///             Err(err) => return Err(err),
+    ///         },
+    ///         CodecStatus::Eof => break,
+    ///     }
+    /// }
+    /// ```
+    pub fn read_packet(&mut self) -> Result<Packet> {
+        self.reader.read(self.reader_stream_index)
+    }
+
+    /// Send a packet to the decoder. See [`DecoderSplit::send_packet`].
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to decode.
+    #[inline]
+    pub fn send_packet(&mut self, packet: Packet) -> Result<CodecStatus<()>> {
+        self.decoder.send_packet(packet)
+    }
+
+    /// Signal the end of the stream to the decoder. See [`DecoderSplit::send_eof`].
+    #[inline]
+    pub fn send_eof(&mut self) -> Result<CodecStatus<()>> {
+        self.decoder.send_eof()
+    }
+
+    /// Receive a decoded frame. See [`DecoderSplit::receive_frame`].
+    #[inline]
+    pub fn receive_frame(&mut self) -> Result<CodecStatus<RawFrame>> {
+        self.decoder.receive_frame()
+    }
+
+    /// Drain the decoder: signal the end of the stream and return the frames it still holds, then
+    /// reset it to decode the packets that follow. This empties the internal queue of the codec at
+    /// a deterministic point, like when switching to a new output segment mid-stream. See also
+    /// [`Encoder::flush`](crate::encode::Encoder::flush).
+    ///
+    /// # Return value
+    ///
+    /// The frames the decoder held, downloaded and scaled like with [`Decoder::decode_raw`].
+    pub fn drain(&mut self) -> Result<Vec<RawFrame>> {
+        let mut frames = Vec::new();
+        loop {
+            match self.decoder.drain_raw() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) | Err(Error::ReadExhausted) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        self.decoder.reset();
+        self.draining = false;
+        Ok(frames)
+    }
+
+    /// Shut the decoder down gracefully, for instance when a service receives `SIGTERM`: stop
+    /// reading packets, and drain the frames still in the decoder until the deadline.
+    ///
+    /// After this, [`Decoder::decode_raw`] returns [`Error::DecodeExhausted`].
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - When to stop draining the decoder.
+    ///
+    /// # Return value
+    ///
+    /// The frames the decoder held, downloaded and scaled like with [`Decoder::decode_raw`], and
+    /// how many frames were flushed and how many were dropped.
+    pub fn shutdown(&mut self, deadline: Deadline) -> Result<(Vec<RawFrame>, ShutdownReport)> {
+        let in_flight = self.decoder.frames_in_flight();
+        let frames_received = self.decoder.frames_received;
+        let mut report = ShutdownReport {
+            frames_in_flight: in_flight,
+            ..ShutdownReport::default()
+        };
+        let mut frames = Vec::new();
+        if self.shut_down {
+            return Ok((frames, report));
+        }
+
+        self.shut_down = true;
+        self.pending_error = None;
+        let mut drained = false;
+        while !deadline.has_passed() {
+            match self.decoder.drain_raw() {
+                Ok(Some(frame)) => frames.push(frame),
+                Ok(None) | Err(Error::ReadExhausted) => {
+                    drained = true;
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let flushed = self.decoder.frames_received - frames_received;
+        report.frames_flushed = flushed.min(in_flight);
+        report.frames_dropped = if drained {
+            0
+        } else {
+            in_flight - report.frames_flushed
+        };
+        self.decoder.reset();
+        self.draining = false;
+        Ok((frames, report))
+    }
+
     /// Get the decoders input size (resolution dimensions): width and height.
     #[inline(always)]
     pub fn size(&self) -> (u32, u32) {
@@ -311,13 +1022,122 @@ impl Decoder {
         self.decoder.size_out
     }
 
-    /// Get the decoders input frame rate as floating-point value.
-    pub fn frame_rate(&self) -> f32 {
-        let frame_rate = self
-            .reader
-            .input
-            .stream(self.reader_stream_index)
-            .map(|stream| stream.rate());
+    /// Get the approximate native memory held by the decoder in bytes. See [`crate::memory`].
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.decoder.memory_usage()
+    }
+
+    /// Get the kinds of parallelism the decoder supports.
+    #[inline]
+    pub fn parallelism_support(&self) -> Option<ParallelismSupport> {
+        self.decoder.parallelism_support()
+    }
+
+    /// Describe the stages of the decoder, from the reader to the scaler, with their formats and
+    /// time bases. See [`PipelineDescription`].
+    pub fn describe(&self) -> PipelineDescription {
+        let format = self.reader.input.format();
+        let mut reader_stage = Stage::new(StageKind::Reader, format.name())
+            .with_detail(self.reader.source.to_string());
+        if let Some(stream) = self.reader.input.stream(self.reader_stream_index) {
+            reader_stage = reader_stage.with_time_base(stream.time_base());
+        }
+        PipelineDescription::default()
+            .with_stage(reader_stage)
+            .then(self.decoder.describe())
+    }
+
+    /// Get the keys of the options given with [`DecoderBuilder::with_options`] that were not used
+    /// when opening the source. See [`Reader::unused_options`].
+    #[inline]
+    pub fn unused_options(&self) -> &[String] {
+        self.reader.unused_options()
+    }
+
+    /// Take the oldest change of the stream parameters (like a camera switching resolution) that
+    /// was not taken yet. The decoder adapts to changes by itself, after which [`Decoder::size`]
+    /// and [`Decoder::size_out`] report the new sizes. See
+    /// [`DecoderSplit::take_parameter_change`].
+    #[inline]
+    pub fn take_parameter_change(&mut self) -> Option<ParameterChange> {
+        self.decoder.take_parameter_change()
+    }
+
+    /// Attach a [`ThumbnailTap`] that takes thumbnails of the decoded frames. See
+    /// [`DecoderSplit::attach_thumbnail_tap`].
+    ///
+    /// * `tap` - Thumbnail tap.
+    #[inline]
+    pub fn attach_thumbnail_tap(&mut self, tap: ThumbnailTap) {
+        self.decoder.attach_thumbnail_tap(tap);
+    }
+
+    /// Detach the [`ThumbnailTap`], if any.
+    #[inline]
+    pub fn detach_thumbnail_tap(&mut self) -> Option<ThumbnailTap> {
+        self.decoder.detach_thumbnail_tap()
+    }
+
+    /// Take the ranges that were skipped to resynchronize after corrupt data since the last call.
+    /// Always empty unless resynchronization was enabled with [`DecoderBuilder::with_resync`].
+    pub fn take_skipped_ranges(&mut self) -> Vec<SkippedRange> {
+        self.resync
+            .as_mut()
+            .map(|resync| std::mem::take(&mut resync.skipped))
+            .unwrap_or_default()
+    }
+
+    /// Read the next packet to decode, or [`None`] if the reader is exhausted. With
+    /// resynchronization enabled, packets are skipped up to the next keyframe after a failure.
+    fn read_packet_to_decode(&mut self) -> Result<Option<Packet>> {
+        loop {
+            match (
+                self.reader.read(self.reader_stream_index),
+                self.resync.as_mut(),
+            ) {
+                (Ok(packet), Some(resync)) => {
+                    if resync.accept(&packet) {
+                        return Ok(Some(packet));
+                    }
+                }
+                (Ok(packet), None) => return Ok(Some(packet)),
+                (Err(Error::ReadExhausted), resync) => {
+                    if let Some(resync) = resync {
+                        resync.finish();
+                    }
+                    return Ok(None);
+                }
+                (Err(Error::BackendError(err)), Some(resync)) if Resync::is_recoverable(&err) => {
+                    resync.fail(resync.last_time, err)?
+                }
+                (Err(err), _) => return Err(err),
+            }
+        }
+    }
+
+    /// Recover from a packet that failed to decode, or return the error if that is not possible.
+    fn recover(&mut self, err: Error, time: Time) -> Result<()> {
+        match (err, self.resync.as_mut()) {
+            (Error::BackendError(err), Some(resync)) if Resync::is_recoverable(&err) => {
+                self.decoder.reset();
+                resync.fail(time, err)
+            }
+            // Skip packets that fail to decode while within the decode error limit.
+            (Error::BackendError(_), None) if self.reader.guard.tolerates_decode_errors() => {
+                self.reader.guard.count_decode_error()
+            }
+            (err, _) => Err(err),
+        }
+    }
+
+    /// Get the decoders input frame rate as floating-point value.
+    pub fn frame_rate(&self) -> f32 {
+        let frame_rate = self
+            .reader
+            .input
+            .stream(self.reader_stream_index)
+            .map(|stream| stream.rate());
 
         if let Some(frame_rate) = frame_rate {
             if frame_rate.denominator() > 0 {
@@ -329,6 +1149,266 @@ impl Decoder {
             0.0
         }
     }
+
+    /// Decode ahead on a worker thread into a bounded buffer, so that decode times that vary per
+    /// frame (like slow keyframes) are smoothed out for the consumer. See [`PrefetchDecoder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Maximum number of decoded frames to buffer. Zero is raised to one.
+    pub fn with_prefetch(self, frames: usize) -> PrefetchDecoder {
+        PrefetchDecoder::new(self, frames)
+    }
+}
+
+/// Decoder that decodes ahead on a worker thread into a bounded buffer. Created with
+/// [`Decoder::with_prefetch`].
+///
+/// The worker blocks when the buffer is full, so at most the requested number of frames is held
+/// in memory. Errors of the decoder (including [`Error::DecodeExhausted`] at the end of the
+/// stream) are returned after the buffered frames were consumed. To seek, get the decoder back
+/// with [`PrefetchDecoder::into_decoder`] first.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut decoder = Decoder::new(Path::new("video.mp4")).unwrap().with_prefetch(16);
+/// for (timestamp, frame) in decoder.decode_iter().map_while(Result::ok) {
+///     run_inference(&frame);
+/// }
+/// ```
+pub struct PrefetchDecoder {
+    queue: Arc<FrameQueue<RawFrame>>,
+    error: Arc<Mutex<Option<Error>>>,
+    worker: Option<JoinHandle<Decoder>>,
+    time_base: AvRational,
+    size: (u32, u32),
+    size_out: (u32, u32),
+}
+
+impl PrefetchDecoder {
+    /// Start decoding ahead on a worker thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - Decoder to decode with.
+    /// * `frames` - Maximum number of decoded frames to buffer.
+    fn new(mut decoder: Decoder, frames: usize) -> Self {
+        let queue = Arc::new(FrameQueue::new(frames, DropPolicy::Block));
+        let error = Arc::new(Mutex::new(None));
+        let time_base = decoder.time_base();
+        let size = decoder.size();
+        let size_out = decoder.size_out();
+        let worker = {
+            let queue = queue.clone();
+            let error = error.clone();
+            std::thread::spawn(move || {
+                loop {
+                    match decoder.decode_raw() {
+                        Ok(frame) => {
+                            // The queue is closed when the consumer is done.
+                            if !queue.push(frame) {
+                                break;
+                            }
+                        }
+                        Err(err) => {
+                            *error.lock().unwrap() = Some(err);
+                            break;
+                        }
+                    }
+                }
+                queue.close();
+                decoder
+            })
+        };
+        Self {
+            queue,
+            error,
+            worker: Some(worker),
+            time_base,
+            size,
+            size_out,
+        }
+    }
+
+    /// Get decoder time base.
+    #[inline]
+    pub fn time_base(&self) -> AvRational {
+        self.time_base
+    }
+
+    /// Get the size of the frames in the stream: width and height. Changes of the size during the
+    /// stream are not reflected.
+    #[inline]
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Get the size of the decoded frames after resizing: width and height.
+    #[inline]
+    pub fn size_out(&self) -> (u32, u32) {
+        self.size_out
+    }
+
+    /// Get the number of decoded frames that are buffered.
+    #[inline]
+    pub fn buffered(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Get the statistics of the buffer, like the time the worker spent waiting for the consumer.
+    #[inline]
+    pub fn stats(&self) -> FrameQueueStats {
+        self.queue.stats()
+    }
+
+    /// Decode frames through iterator interface. See [`Decoder::decode_iter`].
+    #[cfg(feature = "ndarray")]
+    pub fn decode_iter(&mut self) -> impl Iterator<Item = Result<(Time, Frame)>> + '_ {
+        std::iter::from_fn(move || Some(self.decode()))
+    }
+
+    /// Get the next decoded frame, and block until it is available. See [`Decoder::decode`].
+    #[cfg(feature = "ndarray")]
+    pub fn decode(&mut self) -> Result<(Time, Frame)> {
+        raw_frame_to_time_and_frame(&mut self.decode_raw()?, self.time_base)
+    }
+
+    /// Decode frames through iterator interface. See [`Decoder::decode_raw_iter`].
+    pub fn decode_raw_iter(&mut self) -> impl Iterator<Item = Result<RawFrame>> + '_ {
+        std::iter::from_fn(move || Some(self.decode_raw()))
+    }
+
+    /// Get the next decoded raw frame, and block until it is available. See
+    /// [`Decoder::decode_raw`].
+    pub fn decode_raw(&mut self) -> Result<RawFrame> {
+        self.queue.pop().ok_or_else(|| self.error())
+    }
+
+    /// Get a batch of decoded frames. The buffer is locked once for all frames that are already
+    /// available. See [`Decoder::decode_batch`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames in the batch.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_batch(&mut self, frames: usize) -> Result<Vec<(Time, Frame)>> {
+        self.decode_raw_batch(frames)?
+            .into_iter()
+            .map(|mut frame| raw_frame_to_time_and_frame(&mut frame, self.time_base))
+            .collect()
+    }
+
+    /// Get a batch of decoded frames as a single array. See [`Decoder::decode_batch_array`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames in the batch.
+    #[cfg(feature = "ndarray")]
+    pub fn decode_batch_array(&mut self, frames: usize) -> Result<(Vec<Time>, FrameBatch)> {
+        raw_frames_to_batch(self.decode_raw_batch(frames)?, self.time_base)
+    }
+
+    /// Get a batch of decoded raw frames. See [`Decoder::decode_raw_batch`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frames` - Number of frames in the batch.
+    pub fn decode_raw_batch(&mut self, frames: usize) -> Result<Vec<RawFrame>> {
+        let mut batch = Vec::with_capacity(frames);
+        while batch.len() < frames {
+            let available = self.queue.pop_many(frames - batch.len());
+            if available.is_empty() {
+                break;
+            }
+            batch.extend(available);
+        }
+        if batch.is_empty() && frames > 0 {
+            return Err(self.error());
+        }
+        Ok(batch)
+    }
+
+    /// Stop decoding ahead and get the decoder back, for example to seek. Frames that were
+    /// buffered are discarded, so the decoder is ahead of the last frame that was returned.
+    pub fn into_decoder(mut self) -> Decoder {
+        self.stop().expect("prefetch worker should not panic")
+    }
+
+    /// Get the error that stopped the worker, once the buffer is drained.
+    fn error(&self) -> Error {
+        self.error
+            .lock()
+            .unwrap()
+            .clone()
+            .unwrap_or(Error::DecodeExhausted)
+    }
+
+    /// Close the buffer and wait for the worker to stop.
+    fn stop(&mut self) -> Option<Decoder> {
+        self.queue.close();
+        self.worker.take()?.join().ok()
+    }
+}
+
+impl Drop for PrefetchDecoder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Convert a decoded RGB24 frame to an ndarray with its timestamp.
+///
+/// # Arguments
+///
+/// * `frame` - Decoded frame.
+/// * `time_base` - Time base of the decoder.
+#[cfg(feature = "ndarray")]
+fn raw_frame_to_time_and_frame(
+    frame: &mut RawFrame,
+    time_base: AvRational,
+) -> Result<(Time, Frame)> {
+    // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the encoder
+    // will use when encoding for the `PTS` field.
+    let timestamp = Time::new(Some(frame.packet().dts), time_base);
+    let frame = ffi::convert_frame_to_ndarray_rgb24(frame).map_err(Error::BackendError)?;
+    Ok((timestamp, frame))
+}
+
+/// Copy decoded RGB24 frames of the same size into a single array with dimensions
+/// `(N, H, W, C)`.
+///
+/// # Arguments
+///
+/// * `frames` - Decoded frames.
+/// * `time_base` - Time base of the decoder.
+#[cfg(feature = "ndarray")]
+fn raw_frames_to_batch(
+    frames: Vec<RawFrame>,
+    time_base: AvRational,
+) -> Result<(Vec<Time>, FrameBatch)> {
+    let (width, height) = frames
+        .first()
+        .map_or((0, 0), |frame| (frame.width(), frame.height()));
+    if frames
+        .iter()
+        .any(|frame| frame.width() != width || frame.height() != height)
+    {
+        return Err(Error::InvalidFrameFormat);
+    }
+    let mut batch = FrameBatch::default((frames.len(), height as usize, width as usize, 3));
+    let frame_size = (height * width * 3) as usize;
+    let buffer = batch.as_slice_mut().unwrap();
+    let mut timestamps = Vec::with_capacity(frames.len());
+    // Chunks must not be empty, even for an empty batch.
+    for (frame, buffer) in frames
+        .iter()
+        .zip(buffer.chunks_exact_mut(frame_size.max(1)))
+    {
+        timestamps.push(Time::new(Some(frame.packet().dts), time_base));
+        ffi::copy_frame_rgb24_to_buffer(frame, buffer).map_err(Error::BackendError)?;
+    }
+    Ok((timestamps, batch))
 }
 
 /// Decoder part of a split [`Decoder`] and [`Reader`].
@@ -339,10 +1419,26 @@ pub struct DecoderSplit {
     decoder: AvDecoder,
     decoder_time_base: AvRational,
     hwaccel_context: Option<HardwareAccelerationContext>,
-    scaler: Option<AvScaler>,
+    scaler: Option<Scaler>,
+    scaler_input_format: AvPixel,
+    scaler_profile: ScalerProfile,
+    scaler_backend: ScalerBackend,
+    resize: Option<Resize>,
     size: (u32, u32),
     size_out: (u32, u32),
     draining: bool,
+    memory: MemoryReservation,
+    parameter_changes: VecDeque<ParameterChange>,
+    thumbnail_tap: Option<ThumbnailTap>,
+    hardware_download: HardwareDownload,
+    /// Buffer that hardware frames are downloaded to with [`HardwareDownload::Staged`].
+    staging: Option<RawFrame>,
+    /// Number of packets sent to the codec, to tell how many frames it holds.
+    packets_sent: u64,
+    /// Number of frames received from the codec.
+    frames_received: u64,
+    #[cfg(feature = "filter")]
+    filter: Option<Filter>,
 }
 
 impl DecoderSplit {
@@ -352,24 +1448,103 @@ impl DecoderSplit {
     ///
     /// * `reader` - [`Reader`] to initialize decoder from.
     /// * `resize` - Optional resize strategy to apply to frames.
+    /// * `scaler_profile` - Scaler profile to use when converting frames.
     pub fn new(
         reader: &Reader,
         reader_stream_index: usize,
         resize: Option<Resize>,
+        scaler_profile: ScalerProfile,
+        hwaccel_device_type: Option<HardwareAccelerationDeviceType>,
+    ) -> Result<Self> {
+        Self::new_with_max_dimensions(
+            reader,
+            reader_stream_index,
+            resize,
+            scaler_profile,
+            ScalerBackend::default(),
+            hwaccel_device_type,
+            None,
+        )
+    }
+
+    /// Create a new [`DecoderSplit`] that limits the dimensions of the decoded frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_dimensions` - Maximum width and height, and what to do with sources that exceed
+    ///   them. See [`DecoderBuilder::max_dimensions`].
+    pub(crate) fn new_with_max_dimensions(
+        reader: &Reader,
+        reader_stream_index: usize,
+        mut resize: Option<Resize>,
+        scaler_profile: ScalerProfile,
+        scaler_backend: ScalerBackend,
         hwaccel_device_type: Option<HardwareAccelerationDeviceType>,
+        max_dimensions: Option<(u32, u32, OversizePolicy)>,
     ) -> Result<Self> {
         let reader_stream = reader
             .input
             .stream(reader_stream_index)
             .ok_or(AvError::StreamNotFound)?;
 
-        let mut decoder = AvContext::new();
+        // Some hardware device types are implemented by dedicated wrapper decoders.
+        let wrapper_decoder = hwaccel_device_type.and_then(|device_type| {
+            hwaccel::find_wrapper_decoder(reader_stream.parameters().id(), device_type)
+        });
+        let mut decoder = match wrapper_decoder.as_ref() {
+            Some(codec) => ffi::codec_context_as(codec)?,
+            None => AvContext::new(),
+        };
         ffi::set_decoder_context_time_base(&mut decoder, reader_stream.time_base());
         decoder.set_parameters(reader_stream.parameters())?;
+        if let Some((max_width, max_height, policy)) = max_dimensions {
+            let description = MediaDescription::from_stream(&reader_stream);
+            let (width, height) = (description.width, description.height);
+            let mut max_pixels = max_width as u64 * max_height as u64;
+            if width > max_width || height > max_height {
+                if policy == OversizePolicy::Reject {
+                    return Err(Error::MaxDimensionsExceeded);
+                }
+                // Decoders that support it can skip decoding the full resolution entirely.
+                let max_lowres = match (wrapper_decoder.as_ref(), hwaccel_device_type) {
+                    (None, None) => ffmpeg::decoder::find(reader_stream.parameters().id())
+                        .map(|codec| codec.max_lowres())
+                        .unwrap_or(0),
+                    _ => 0,
+                };
+                let lowres = (0..=max_lowres.max(0))
+                    .find(|lowres| width >> lowres <= max_width && height >> lowres <= max_height)
+                    .unwrap_or(max_lowres.max(0));
+                if lowres > 0 {
+                    ffi::set_decoder_context_lowres(&mut decoder, lowres);
+                }
+                max_pixels = max_pixels.max((width >> lowres) as u64 * (height >> lowres) as u64);
+                let fits = |resize: Resize| {
+                    resize
+                        .compute_for((width >> lowres, height >> lowres))
+                        .is_some_and(|(width, height)| width <= max_width && height <= max_height)
+                };
+                resize = match resize {
+                    Some(resize) if fits(resize) => Some(resize),
+                    _ => Some(Resize::Fit(max_width, max_height)),
+                };
+            }
+            ffi::set_decoder_context_max_pixels(&mut decoder, max_pixels);
+        }
+        // Browsers only support threads with cross-origin isolation and ffmpeg.wasm style builds
+        // are usually configured without them, so decode on the caller thread.
+        #[cfg(target_arch = "wasm32")]
+        decoder.set_threading(ffmpeg::codec::threading::Config::count(1));
 
         let hwaccel_context = match hwaccel_device_type {
-            Some(device_type) => Some(HardwareAccelerationContext::new(&mut decoder, device_type)?),
-            None => None,
+            Some(device_type) if device_type.requires_device_context() => {
+                Some(HardwareAccelerationContext::new(&mut decoder, device_type)?)
+            }
+            // Wrapper decoders without device context produce frames in system memory.
+            Some(_) if wrapper_decoder.is_none() => {
+                return Err(Error::UnsupportedCodecHardwareAccelerationDeviceType)
+            }
+            _ => None,
         };
 
         let decoder = decoder.decoder().video()?;
@@ -392,37 +1567,151 @@ impl DecoderSplit {
             decoder.format()
         };
 
-        let is_scaler_needed = !(scaler_input_format == crate::frame::FRAME_PIXEL_FORMAT
-            && decoder.width() == resize_width
-            && decoder.height() == resize_height);
-        let scaler = if is_scaler_needed {
-            Some(
-                AvScaler::get(
-                    scaler_input_format,
-                    decoder.width(),
-                    decoder.height(),
-                    crate::frame::FRAME_PIXEL_FORMAT,
-                    resize_width,
-                    resize_height,
-                    AvScalerFlags::AREA,
-                )
-                .map_err(Error::BackendError)?,
-            )
-        } else {
-            None
-        };
+        let scaler = Self::create_scaler(
+            scaler_input_format,
+            (decoder.width(), decoder.height()),
+            (resize_width, resize_height),
+            scaler_profile,
+            scaler_backend,
+            decoder.color_range() == AvColorRange::JPEG,
+        )?;
 
         let size = (decoder.width(), decoder.height());
         let size_out = (resize_width, resize_height);
 
+        let memory = MemoryReservation::new(
+            MemoryCategory::Decoders,
+            Self::estimate_memory(scaler_input_format, size, size_out),
+        )?;
+
         Ok(Self {
             decoder,
             decoder_time_base,
             hwaccel_context,
             scaler,
+            scaler_input_format,
+            scaler_profile,
+            scaler_backend,
+            resize,
             size,
             size_out,
             draining: false,
+            memory,
+            parameter_changes: VecDeque::new(),
+            thumbnail_tap: None,
+            hardware_download: HardwareDownload::default(),
+            staging: None,
+            packets_sent: 0,
+            frames_received: 0,
+            #[cfg(feature = "filter")]
+            filter: None,
+        })
+    }
+
+    /// Create a new [`DecoderSplit`] that decodes with MediaCodec and renders frames to an Android
+    /// surface. Frames are not downloaded nor scaled.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - [`Reader`] to initialize decoder from.
+    /// * `surface` - Surface to render to.
+    #[cfg(target_os = "android")]
+    pub fn new_with_mediacodec_surface(
+        reader: &Reader,
+        reader_stream_index: usize,
+        surface: MediaCodecSurface,
+    ) -> Result<Self> {
+        let reader_stream = reader
+            .input
+            .stream(reader_stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+
+        let codec = hwaccel::find_wrapper_decoder(
+            reader_stream.parameters().id(),
+            HardwareAccelerationDeviceType::MediaCodec,
+        )
+        .ok_or(Error::UnsupportedCodecHardwareAccelerationDeviceType)?;
+        let mut decoder = ffi::codec_context_as(&codec)?;
+        ffi::set_decoder_context_time_base(&mut decoder, reader_stream.time_base());
+        decoder.set_parameters(reader_stream.parameters())?;
+
+        let hwaccel_context =
+            HardwareAccelerationContext::new_with_mediacodec_surface(&mut decoder, surface)?;
+
+        let decoder = decoder.decoder().video()?;
+        let decoder_time_base = decoder.time_base();
+        let size = (decoder.width(), decoder.height());
+        let scaler_input_format = decoder.format();
+
+        Ok(Self {
+            decoder,
+            decoder_time_base,
+            hwaccel_context: Some(hwaccel_context),
+            scaler: None,
+            scaler_input_format,
+            scaler_profile: ScalerProfile::default(),
+            scaler_backend: ScalerBackend::default(),
+            resize: None,
+            size,
+            size_out: size,
+            draining: false,
+            // Frames are rendered to the surface and never held in memory by the decoder.
+            memory: MemoryReservation::empty(MemoryCategory::Decoders),
+            parameter_changes: VecDeque::new(),
+            thumbnail_tap: None,
+            hardware_download: HardwareDownload::default(),
+            staging: None,
+            packets_sent: 0,
+            frames_received: 0,
+            #[cfg(feature = "filter")]
+            filter: None,
+        })
+    }
+
+    /// Create a new [`DecoderSplit`] that passes frames on as they come out of the decoder, without
+    /// downloading nor scaling them. This keeps hardware frames produced by the source (like those
+    /// of desktop duplication capture) on the device, so they can be encoded directly.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - [`Reader`] to initialize decoder from.
+    pub(crate) fn new_passthrough(reader: &Reader, reader_stream_index: usize) -> Result<Self> {
+        let reader_stream = reader
+            .input
+            .stream(reader_stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+
+        let mut decoder = AvContext::new();
+        ffi::set_decoder_context_time_base(&mut decoder, reader_stream.time_base());
+        decoder.set_parameters(reader_stream.parameters())?;
+
+        let decoder = decoder.decoder().video()?;
+        let decoder_time_base = decoder.time_base();
+        let size = (decoder.width(), decoder.height());
+        let scaler_input_format = decoder.format();
+
+        Ok(Self {
+            decoder,
+            decoder_time_base,
+            hwaccel_context: None,
+            scaler: None,
+            scaler_input_format,
+            scaler_profile: ScalerProfile::default(),
+            scaler_backend: ScalerBackend::default(),
+            resize: None,
+            size,
+            size_out: size,
+            draining: false,
+            // Frames stay in device memory, which the source owns.
+            memory: MemoryReservation::empty(MemoryCategory::Decoders),
+            parameter_changes: VecDeque::new(),
+            thumbnail_tap: None,
+            hardware_download: HardwareDownload::default(),
+            staging: None,
+            packets_sent: 0,
+            frames_received: 0,
+            #[cfg(feature = "filter")]
+            filter: None,
         })
     }
 
@@ -504,10 +1793,67 @@ impl DecoderSplit {
         self.receive_frame_from_decoder()
     }
 
+    /// Send a packet to the decoder, without receiving frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to decode.
+    ///
+    /// # Return value
+    ///
+    /// [`CodecStatus::Again`] if the decoder does not accept input until frames are received, and
+    /// [`CodecStatus::Eof`] if the end of the stream was already sent.
+    pub fn send_packet(&mut self, packet: Packet) -> Result<CodecStatus<()>> {
+        match self.send_packet_to_decoder(packet) {
+            Ok(()) => Ok(CodecStatus::Ready(())),
+            Err(Error::BackendError(AvError::Other { errno })) if errno == EAGAIN => {
+                Ok(CodecStatus::Again)
+            }
+            Err(Error::BackendError(AvError::Eof)) => Ok(CodecStatus::Eof),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Signal the end of the stream to the decoder, after which it outputs the frames it still
+    /// holds. Use [`DecoderSplit::reset`] to decode again after that.
+    ///
+    /// # Return value
+    ///
+    /// [`CodecStatus::Eof`] if the end of the stream was already sent.
+    pub fn send_eof(&mut self) -> Result<CodecStatus<()>> {
+        if self.draining {
+            return Ok(CodecStatus::Eof);
+        }
+        self.decoder.send_eof().map_err(Error::BackendError)?;
+        self.draining = true;
+        Ok(CodecStatus::Ready(()))
+    }
+
+    /// Receive a decoded frame from the decoder, without sending packets. Frames are downloaded
+    /// from the hardware acceleration device and scaled like with [`DecoderSplit::decode_raw`].
+    ///
+    /// # Return value
+    ///
+    /// [`CodecStatus::Again`] if the decoder needs more packets to output a frame, and
+    /// [`CodecStatus::Eof`] if the decoder was drained.
+    pub fn receive_frame(&mut self) -> Result<CodecStatus<RawFrame>> {
+        match self.receive_frame_from_decoder() {
+            Ok(Some(frame)) => Ok(CodecStatus::Ready(frame)),
+            Ok(None) => Ok(CodecStatus::Again),
+            Err(Error::ReadExhausted) => Ok(CodecStatus::Eof),
+            Err(err) => Err(err),
+        }
+    }
+
     /// Reset the decoder to be used again after draining.
     pub fn reset(&mut self) {
         self.decoder.flush();
         self.draining = false;
+        self.packets_sent = self.frames_received;
+        #[cfg(feature = "filter")]
+        if let Some(filter) = self.filter.as_mut() {
+            filter.reset();
+        }
     }
 
     /// Get the decoders input size (resolution dimensions): width and height.
@@ -523,6 +1869,75 @@ impl DecoderSplit {
         self.size_out
     }
 
+    /// Get the approximate native memory held by the decoder in bytes. See [`crate::memory`].
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory.bytes()
+    }
+
+    /// Get the kinds of parallelism the decoder supports.
+    pub fn parallelism_support(&self) -> Option<ParallelismSupport> {
+        self.decoder
+            .codec()
+            .map(|codec| ParallelismSupport::of(&codec))
+    }
+
+    /// Describe the decoder and scaler stages, with their formats and time bases. See
+    /// [`PipelineDescription`].
+    pub fn describe(&self) -> PipelineDescription {
+        let codec_name = self
+            .decoder
+            .codec()
+            .map(|codec| codec.name().to_string())
+            .unwrap_or_default();
+        let mut decoder_stage = Stage::new(StageKind::Decoder, codec_name)
+            .with_pixel_format(self.decoder.format())
+            .with_size(self.size)
+            .with_time_base(self.decoder_time_base);
+        if self.hwaccel_context.is_some() {
+            decoder_stage = decoder_stage.with_detail("hardware accelerated");
+        }
+        let mut description = PipelineDescription::default().with_stage(decoder_stage);
+        if self.scaler.is_some() {
+            let detail = match &self.resize {
+                Some(resize) => format!("{:?}, {resize:?}", self.scaler_profile),
+                None => format!("{:?}", self.scaler_profile),
+            };
+            description = description.with_stage(
+                Stage::new(StageKind::Scaler, "swscale")
+                    .with_pixel_format(crate::frame::FRAME_PIXEL_FORMAT)
+                    .with_size(self.size_out)
+                    .with_detail(detail),
+            );
+        }
+        description
+    }
+
+    /// Take the oldest change of the stream parameters that was not taken yet. Call this until it
+    /// returns `None` to get all changes since the last call, in order.
+    ///
+    /// Decoders pick up in-band parameter changes (like a new SPS) by themselves. When the decoded
+    /// frames change size or pixel format, the scaler is reinitialized for the new parameters
+    /// before the first frame with the new parameters is returned, and the change is recorded here.
+    /// The output size is recomputed from the resize strategy, or follows the decoded size if there
+    /// is none.
+    pub fn take_parameter_change(&mut self) -> Option<ParameterChange> {
+        self.parameter_changes.pop_front()
+    }
+
+    /// Attach a [`ThumbnailTap`] that takes thumbnails of the decoded frames, replacing the tap
+    /// attached before, if any. The tap sees frames before resizing and conversion.
+    ///
+    /// * `tap` - Thumbnail tap.
+    pub fn attach_thumbnail_tap(&mut self, tap: ThumbnailTap) {
+        self.thumbnail_tap = Some(tap);
+    }
+
+    /// Detach the [`ThumbnailTap`], if any.
+    pub fn detach_thumbnail_tap(&mut self) -> Option<ThumbnailTap> {
+        self.thumbnail_tap.take()
+    }
+
     /// Send packet to decoder. Includes rescaling timestamps accordingly.
     fn send_packet_to_decoder(&mut self, packet: Packet) -> Result<()> {
         let (mut packet, packet_time_base) = packet.into_inner_parts();
@@ -531,21 +1946,124 @@ impl DecoderSplit {
         self.decoder
             .send_packet(&packet)
             .map_err(Error::BackendError)?;
+        self.packets_sent += 1;
 
         Ok(())
     }
 
+    /// Number of frames the codec holds, estimated from the packets sent to it and the frames
+    /// received from it.
+    fn frames_in_flight(&self) -> u64 {
+        self.packets_sent.saturating_sub(self.frames_received)
+    }
+
     /// Receive packet from decoder. Will handle hwaccel conversions and scaling as well.
     fn receive_frame_from_decoder(&mut self) -> Result<Option<RawFrame>> {
+        #[cfg(feature = "filter")]
+        if self.filter.is_some() {
+            return self.receive_filtered_frame();
+        }
+        self.receive_decoded_frame()
+    }
+
+    /// Keep frames decoded with hardware acceleration in device memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `keep_frames` - Whether or not to keep the frames in device memory.
+    fn with_hardware_frames(mut self, keep_frames: bool) -> Self {
+        if let Some(hwaccel_context) = self.hwaccel_context.as_mut() {
+            hwaccel_context.set_keep_frames(keep_frames);
+        }
+        self
+    }
+
+    /// Set how frames decoded with hardware acceleration are downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `download` - How to download frames.
+    fn with_hardware_download(mut self, download: HardwareDownload) -> Self {
+        self.hardware_download = download;
+        self
+    }
+
+    /// Pass the decoded frames through a filter, with its output converted to RGB24.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Filter to apply, if any.
+    #[cfg(feature = "filter")]
+    fn with_filter(mut self, filter: Option<Filter>) -> Self {
+        self.filter = filter.map(|mut filter| {
+            filter.set_output(FRAME_PIXEL_FORMAT, None);
+            filter
+        });
+        self
+    }
+
+    /// Receive a frame from the filter, and feed it decoded frames until it has one.
+    #[cfg(feature = "filter")]
+    fn receive_filtered_frame(&mut self) -> Result<Option<RawFrame>> {
+        let time_base = self.decoder_time_base;
+        loop {
+            let filter = self.filter.as_mut().ok_or(AvError::Bug)?;
+            if let Some(frame) = filter.pull()? {
+                return Ok(Some(frame));
+            }
+            let decoded = self.receive_decoded_frame();
+            let filter = self.filter.as_mut().ok_or(AvError::Bug)?;
+            match decoded {
+                Ok(Some(frame)) => filter.push(&frame, time_base)?,
+                Ok(None) => return Ok(None),
+                // Drain the filter once the decoder is drained.
+                Err(Error::ReadExhausted) => {
+                    filter.finish()?;
+                    return filter.pull()?.map(Some).ok_or(Error::ReadExhausted);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Receive a decoded frame, converted to the output format and size.
+    fn receive_decoded_frame(&mut self) -> Result<Option<RawFrame>> {
         match self.decoder_receive_frame()? {
             Some(frame) => {
+                // Frames rendered to a surface cannot be downloaded, and frames that are kept in
+                // device memory must not be, so pass them on untouched.
+                if self
+                    .hwaccel_context
+                    .as_ref()
+                    .is_some_and(|hwaccel_context| {
+                        hwaccel_context.is_surface_output()
+                            || (hwaccel_context.keeps_frames()
+                                && hwaccel_context.format() == frame.format())
+                    })
+                {
+                    return Ok(Some(frame));
+                }
+
                 let frame = match self.hwaccel_context.as_ref() {
                     Some(hwaccel_context) if hwaccel_context.format() == frame.format() => {
-                        Self::download_frame(&frame)?
+                        self.download(&frame)?
                     }
                     _ => frame,
                 };
 
+                if (frame.width(), frame.height()) != self.size
+                    || frame.format() != self.scaler_input_format
+                {
+                    self.reconfigure(&frame)?;
+                }
+
+                // The tap must not disturb decoding, so its failures are only logged.
+                if let Some(tap) = self.thumbnail_tap.as_mut() {
+                    if let Err(err) = tap.process(&frame, self.decoder_time_base) {
+                        tracing::warn!("failed to take thumbnail: {err}");
+                    }
+                }
+
                 let frame = match self.scaler.as_mut() {
                     Some(scaler) => Self::rescale_frame(&frame, scaler)?,
                     _ => frame,
@@ -557,20 +2075,173 @@ impl DecoderSplit {
         }
     }
 
+    /// Reinitialize the scaler for frames with new parameters, and record the change.
+    fn reconfigure(&mut self, frame: &RawFrame) -> Result<()> {
+        let size = (frame.width(), frame.height());
+        let size_out = match self.resize {
+            Some(resize) => resize
+                .compute_for(size)
+                .ok_or(Error::InvalidResizeParameters)?,
+            None => size,
+        };
+        self.scaler = Self::create_scaler(
+            frame.format(),
+            size,
+            size_out,
+            self.scaler_profile,
+            self.scaler_backend,
+            frame.color_range() == AvColorRange::JPEG,
+        )?;
+
+        let memory = Self::estimate_memory(frame.format(), size, size_out);
+        let reserved = self.memory.bytes();
+        if memory > reserved {
+            self.memory.grow(memory - reserved);
+        } else {
+            self.memory.shrink(reserved - memory);
+        }
+
+        tracing::warn!(
+            "stream parameters changed from {}x{} {:?} to {}x{} {:?}, reinitialized scaler",
+            self.size.0,
+            self.size.1,
+            self.scaler_input_format,
+            size.0,
+            size.1,
+            frame.format(),
+        );
+        self.parameter_changes.push_back(ParameterChange {
+            previous_size: self.size,
+            previous_format: self.scaler_input_format,
+            size,
+            format: frame.format(),
+            size_out,
+        });
+        self.scaler_input_format = frame.format();
+        self.size = size;
+        self.size_out = size_out;
+        Ok(())
+    }
+
+    /// Create a scaler that converts decoded frames to the output format and size, or [`None`] if
+    /// the frames are already in the output format and size.
+    fn create_scaler(
+        format: AvPixel,
+        size: (u32, u32),
+        size_out: (u32, u32),
+        scaler_profile: ScalerProfile,
+        scaler_backend: ScalerBackend,
+        full_range: bool,
+    ) -> Result<Option<Scaler>> {
+        if format == crate::frame::FRAME_PIXEL_FORMAT && size == size_out {
+            return Ok(None);
+        }
+        let mut scaler = Scaler::get(
+            scaler_backend,
+            (format, size.0, size.1),
+            (crate::frame::FRAME_PIXEL_FORMAT, size_out.0, size_out.1),
+            scaler_profile.flags(),
+        )?;
+        // The exact profile must honor the source color range, or full range sources will come
+        // out with crushed blacks and clipped whites.
+        if let Some(scaler) = scaler
+            .swscale_mut()
+            .filter(|_| scaler_profile == ScalerProfile::Exact)
+        {
+            ffi::set_scaler_color_range(scaler, full_range, true)?;
+        }
+        Ok(Some(scaler))
+    }
+
+    /// Estimate the memory held by a decoder for frames of a format and size.
+    fn estimate_memory(format: AvPixel, size: (u32, u32), size_out: (u32, u32)) -> usize {
+        ffi::image_buffer_size(format, size.0, size.1) * ESTIMATED_DECODER_FRAMES
+            + ffi::image_buffer_size(crate::frame::FRAME_PIXEL_FORMAT, size_out.0, size_out.1)
+    }
+
     /// Pull a decoded frame from the decoder. This function also implements retry mechanism in case
     /// the decoder signals `EAGAIN`.
     fn decoder_receive_frame(&mut self) -> Result<Option<RawFrame>> {
         let mut frame = RawFrame::empty();
         let decode_result = self.decoder.receive_frame(&mut frame);
         match decode_result {
-            Ok(()) => Ok(Some(frame)),
+            Ok(()) => {
+                self.frames_received += 1;
+                Ok(Some(frame))
+            }
             Err(AvError::Eof) => Err(Error::ReadExhausted),
             Err(AvError::Other { errno }) if errno == EAGAIN => Ok(None),
             Err(err) => Err(err.into()),
         }
     }
 
+    /// Download frame from hardware acceleration device as set with
+    /// [`DecoderBuilder::with_hardware_download`].
+    #[cfg(not(target_arch = "wasm32"))]
+    fn download(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        match self.hardware_download {
+            HardwareDownload::Transfer => Self::download_frame(frame),
+            HardwareDownload::Staged => self.download_frame_staged(frame),
+            HardwareDownload::Mapped => {
+                let mut frame_mapped = RawFrame::empty();
+                match ffi_hwaccel::hwdevice_map_frame(&mut frame_mapped, frame) {
+                    Ok(()) => {
+                        ffi::copy_frame_props(frame, &mut frame_mapped);
+                        Ok(frame_mapped)
+                    }
+                    Err(err) => {
+                        tracing::debug!("cannot map hardware frames, staging them instead: {err}");
+                        self.hardware_download = HardwareDownload::Staged;
+                        self.download_frame_staged(frame)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Download frame from hardware acceleration device. There are no hardware acceleration
+    /// devices on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
+    fn download(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        Self::download_frame(frame)
+    }
+
+    /// Download frame from hardware acceleration device into the staging buffer, and return a
+    /// reference to it. The buffer is reused for the next frame once the reference is dropped.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn download_frame_staged(&mut self, frame: &RawFrame) -> Result<RawFrame> {
+        let reusable = self.staging.as_mut().is_some_and(|staging| {
+            (staging.width(), staging.height()) == (frame.width(), frame.height())
+                && ffi::frame_is_writable(staging)
+        });
+        if !reusable {
+            self.staging = Some(Self::staging_frame(frame));
+        }
+        let staging = self.staging.as_mut().expect("staging buffer is allocated");
+        ffi_hwaccel::hwdevice_transfer_frame(staging, frame)?;
+        ffi::copy_frame_props(frame, staging);
+        ffi::frame_ref(staging).map_err(Error::BackendError)
+    }
+
+    /// Allocate a staging buffer to download a hardware frame to. CUDA frames are downloaded to
+    /// pinned host memory where the driver allows, which the device copies to much faster than to
+    /// pageable memory.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn staging_frame(frame: &RawFrame) -> RawFrame {
+        #[cfg(any(target_os = "linux", target_os = "windows"))]
+        if let Some(staging) = ffi_hwaccel::cuda_pinned_frame(
+            frame,
+            HWACCEL_PIXEL_FORMAT,
+            frame.width(),
+            frame.height(),
+        ) {
+            return staging;
+        }
+        RawFrame::new(HWACCEL_PIXEL_FORMAT, frame.width(), frame.height())
+    }
+
     /// Download frame from foreign hardware acceleration device.
+    #[cfg(not(target_arch = "wasm32"))]
     fn download_frame(frame: &RawFrame) -> Result<RawFrame> {
         let mut frame_downloaded = RawFrame::empty();
         frame_downloaded.set_format(HWACCEL_PIXEL_FORMAT);
@@ -579,24 +2250,31 @@ impl DecoderSplit {
         Ok(frame_downloaded)
     }
 
+    /// Download frame from foreign hardware acceleration device. There are no hardware
+    /// acceleration devices on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
+    fn download_frame(_frame: &RawFrame) -> Result<RawFrame> {
+        Err(Error::UnsupportedCodecHardwareAccelerationDeviceType)
+    }
+
     /// Rescale frame with the scaler.
-    fn rescale_frame(frame: &RawFrame, scaler: &mut AvScaler) -> Result<RawFrame> {
+    fn rescale_frame(frame: &RawFrame, scaler: &mut Scaler) -> Result<RawFrame> {
         let mut frame_scaled = RawFrame::empty();
-        scaler
-            .run(frame, &mut frame_scaled)
-            .map_err(Error::BackendError)?;
+        scaler.run(frame, &mut frame_scaled)?;
         ffi::copy_frame_props(frame, &mut frame_scaled);
         Ok(frame_scaled)
     }
 
     #[cfg(feature = "ndarray")]
     fn raw_frame_to_time_and_frame(&self, frame: &mut RawFrame) -> Result<(Time, Frame)> {
-        // We use the packet DTS here (which is `frame->pkt_dts`) because that is what the
-        // encoder will use when encoding for the `PTS` field.
-        let timestamp = Time::new(Some(frame.packet().dts), self.decoder_time_base);
-        let frame = ffi::convert_frame_to_ndarray_rgb24(frame).map_err(Error::BackendError)?;
-
-        Ok((timestamp, frame))
+        // Filtered frames do not keep the timestamps of their packets.
+        #[cfg(feature = "filter")]
+        if self.filter.is_some() {
+            let timestamp = Time::new(frame.pts(), self.decoder_time_base);
+            let frame = ffi::convert_frame_to_ndarray_rgb24(frame).map_err(Error::BackendError)?;
+            return Ok((timestamp, frame));
+        }
+        raw_frame_to_time_and_frame(frame, self.decoder_time_base)
     }
 }
 
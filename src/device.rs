@@ -0,0 +1,764 @@
+use ffmpeg::util::error::ENODEV;
+use ffmpeg::Error as AvError;
+use ffmpeg::Format as AvFormat;
+
+use crate::audio::AudioDecoder;
+use crate::decode::Decoder;
+use crate::error::Error;
+use crate::ffi;
+use crate::interrupt::Interrupt;
+use crate::io::Reader;
+use crate::location::Location;
+use crate::options::Options;
+use crate::resize::Resize;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Capture backend (`libavdevice` input format) to open audio input devices with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioCaptureBackend {
+    /// ALSA on Linux. Devices are named like `default` or `hw:0`.
+    Alsa,
+    /// PulseAudio (or PipeWire through its PulseAudio server) on Linux. Devices are named like
+    /// `default` or by source name, as listed by `pactl list sources`.
+    PulseAudio,
+    /// AVFoundation on macOS. Devices are named by index or name, like `0` or
+    /// `MacBook Pro Microphone`.
+    AvFoundation,
+    /// DirectShow on Windows. Devices are named by their friendly name, like
+    /// `Microphone (Realtek Audio)`.
+    DirectShow,
+}
+
+impl AudioCaptureBackend {
+    /// Default backend for the current platform.
+    pub fn platform_default() -> Self {
+        if cfg!(target_os = "macos") {
+            AudioCaptureBackend::AvFoundation
+        } else if cfg!(windows) {
+            AudioCaptureBackend::DirectShow
+        } else {
+            AudioCaptureBackend::PulseAudio
+        }
+    }
+
+    /// Whether or not ffmpeg was built with support for the backend.
+    pub fn is_available(self) -> bool {
+        find_input_format(self.format_name()).is_some()
+    }
+
+    /// Name of the `libavdevice` input format.
+    fn format_name(self) -> &'static str {
+        match self {
+            AudioCaptureBackend::Alsa => "alsa",
+            AudioCaptureBackend::PulseAudio => "pulse",
+            AudioCaptureBackend::AvFoundation => "avfoundation",
+            AudioCaptureBackend::DirectShow => "dshow",
+        }
+    }
+
+    /// Convert a device name to the URL the backend expects.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Device name.
+    fn device_url(self, device: &str) -> String {
+        match self {
+            // AVFoundation uses `<video>:<audio>`, only capture audio.
+            AudioCaptureBackend::AvFoundation => format!(":{device}"),
+            AudioCaptureBackend::DirectShow => format!("audio={device}"),
+            AudioCaptureBackend::Alsa | AudioCaptureBackend::PulseAudio => device.to_string(),
+        }
+    }
+
+    /// Resolve the name of the device that captures what is played on an output device.
+    ///
+    /// # Arguments
+    ///
+    /// * `output_device` - Name of the output device, or `None` for the default output device.
+    fn loopback_device(self, output_device: Option<&str>) -> Result<String> {
+        match (self, output_device) {
+            // Every PulseAudio sink has a monitor source that carries what the sink plays.
+            (AudioCaptureBackend::PulseAudio, Some(sink)) if sink.ends_with(".monitor") => {
+                Ok(sink.to_string())
+            }
+            (AudioCaptureBackend::PulseAudio, Some(sink)) => Ok(format!("{sink}.monitor")),
+            (AudioCaptureBackend::PulseAudio, None) => Ok("@DEFAULT_MONITOR@".to_string()),
+            // DirectShow has no access to WASAPI loopback, so a loopback capture device must be
+            // installed. Look for the well-known ones.
+            (AudioCaptureBackend::DirectShow, None) => {
+                let format = find_input_format(self.format_name())
+                    .ok_or(Error::BackendError(AvError::DemuxerNotFound))?;
+                let AvFormat::Input(format) = format else {
+                    return Err(Error::BackendError(AvError::DemuxerNotFound));
+                };
+                ffi::list_input_sources(&format)?
+                    .into_iter()
+                    .map(|(name, _description)| name)
+                    .find(|name| {
+                        let name = name.to_lowercase();
+                        DIRECTSHOW_LOOPBACK_DEVICES
+                            .iter()
+                            .any(|loopback| name.contains(loopback))
+                    })
+                    .ok_or(Error::BackendError(AvError::Other { errno: ENODEV }))
+            }
+            // ALSA and AVFoundation have no loopback of their own. A loopback device (like
+            // `snd-aloop` or BlackHole) must be named explicitly.
+            (_, Some(device)) => Ok(device.to_string()),
+            (_, None) => Err(Error::BackendError(AvError::Other { errno: ENODEV })),
+        }
+    }
+}
+
+/// Names (lowercase) of well-known DirectShow devices that capture desktop audio.
+const DIRECTSHOW_LOOPBACK_DEVICES: [&str; 4] = [
+    "virtual-audio-capturer",
+    "stereo mix",
+    "what u hear",
+    "wave out mix",
+];
+
+/// Audio device to capture from.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AudioCaptureDevice {
+    /// Input device, like a microphone. See [`AudioCaptureBackend`] for naming per backend.
+    Input(String),
+    /// Loopback of an output device, to capture desktop audio (what is being played) like screen
+    /// recorders do. Holds the name of the output device, or `None` for the default one.
+    ///
+    /// * PulseAudio: Captures the monitor source of the sink, like `<sink>.monitor`, or
+    ///   `@DEFAULT_MONITOR@` for the default sink.
+    /// * DirectShow: ffmpeg cannot capture through WASAPI loopback directly, so this needs a
+    ///   loopback capture device like `virtual-audio-capturer` or `Stereo Mix`. Without a name the
+    ///   first well-known loopback device that is present is used.
+    /// * ALSA and AVFoundation: Requires a loopback device (like `snd-aloop` or BlackHole) to be
+    ///   named explicitly.
+    Loopback(Option<String>),
+}
+
+impl From<String> for AudioCaptureDevice {
+    fn from(device: String) -> Self {
+        AudioCaptureDevice::Input(device)
+    }
+}
+
+impl From<&str> for AudioCaptureDevice {
+    fn from(device: &str) -> Self {
+        AudioCaptureDevice::Input(device.to_string())
+    }
+}
+
+/// Builds an [`AudioDecoder`] that captures audio from an input device, like a microphone.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut capture = AudioCaptureBuilder::new("default")
+///     .with_sample_rate(48_000)
+///     .with_channels(1)
+///     .build()
+///     .unwrap();
+/// let frame = capture.decode_raw().unwrap();
+/// ```
+///
+/// Capture desktop audio instead:
+///
+/// ```ignore
+/// let mut capture = AudioCaptureBuilder::loopback().build().unwrap();
+/// ```
+pub struct AudioCaptureBuilder<'a> {
+    device: AudioCaptureDevice,
+    backend: AudioCaptureBackend,
+    sample_rate: Option<u32>,
+    channels: Option<u16>,
+    options: Option<&'a Options>,
+}
+
+impl<'a> AudioCaptureBuilder<'a> {
+    /// Create a new capture builder for the specified device, using the default backend of the
+    /// platform.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Device name (see [`AudioCaptureBackend`] for naming per backend) or
+    ///   [`AudioCaptureDevice`].
+    pub fn new(device: impl Into<AudioCaptureDevice>) -> Self {
+        Self {
+            device: device.into(),
+            backend: AudioCaptureBackend::platform_default(),
+            sample_rate: None,
+            channels: None,
+            options: None,
+        }
+    }
+
+    /// Create a new capture builder for the loopback of the default output device, using the
+    /// default backend of the platform. See [`AudioCaptureDevice::Loopback`] for requirements per
+    /// backend.
+    pub fn loopback() -> Self {
+        Self::new(AudioCaptureDevice::Loopback(None))
+    }
+
+    /// Set the backend to capture with.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Capture backend.
+    pub fn with_backend(mut self, backend: AudioCaptureBackend) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Set the sample rate to capture at. Not supported by AVFoundation, which always captures at
+    /// the rate of the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate in Hz.
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Set the number of channels to capture. Not supported by AVFoundation, which always captures
+    /// all channels of the device.
+    ///
+    /// # Arguments
+    ///
+    /// * `channels` - Number of channels.
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = Some(channels);
+        self
+    }
+
+    /// Set custom options for the backend, like `audio_buffer_size` for DirectShow.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to input.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Open the device and build the [`AudioDecoder`] that decodes the captured audio.
+    pub fn build(self) -> Result<AudioDecoder> {
+        let format = find_input_format(self.backend.format_name())
+            .ok_or(Error::BackendError(AvError::DemuxerNotFound))?;
+
+        let mut options = self.options.cloned().unwrap_or_default();
+        if let Some(sample_rate) = self.sample_rate {
+            options.set("sample_rate", &sample_rate.to_string());
+        }
+        if let Some(channels) = self.channels {
+            options.set("channels", &channels.to_string());
+        }
+
+        let device = match self.device {
+            AudioCaptureDevice::Input(device) => device,
+            AudioCaptureDevice::Loopback(output_device) => {
+                self.backend.loopback_device(output_device.as_deref())?
+            }
+        };
+        let url = self.backend.device_url(&device);
+        let AvFormat::Input(format) = format else {
+            return Err(Error::BackendError(AvError::DemuxerNotFound));
+        };
+        let interrupt = Interrupt::new();
+        let (input, _) = ffi::input_with_options(
+            std::path::Path::new(&url),
+            Some(&format),
+            options.to_dict(),
+            &interrupt,
+        )?;
+        let reader = Reader::from_input(
+            Location::File(url.into()),
+            input,
+            interrupt,
+            None,
+            Vec::new(),
+        );
+
+        AudioDecoder::from_reader(reader)
+    }
+}
+
+/// Find a `libavdevice` audio input format by name.
+///
+/// # Arguments
+///
+/// * `name` - Name of the input format.
+fn find_input_format(name: &str) -> Option<AvFormat> {
+    ffmpeg::device::input::audio().find(|format| format.name() == name)
+}
+
+/// Capture backend (`libavdevice` input format) to capture the screen with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScreenCaptureBackend {
+    /// AVFoundation on macOS. Captures whole displays only.
+    AvFoundation,
+    /// Desktop Duplication API on Windows (the `ddagrab` filter). Captures whole displays only.
+    /// Frames are produced on the GPU as Direct3D 11 textures, and can be encoded without reading
+    /// them back (see [`ScreenCaptureBuilder::with_hardware_frames`]).
+    DdaGrab,
+    /// X11 on Linux and other Unix systems. Captures displays (screens of the X server named by
+    /// the `DISPLAY` environment variable) and windows, by X11 window identifier.
+    X11Grab,
+    /// PipeWire through the desktop portal (the `pipewiregrab` filter), for Wayland sessions. The
+    /// user picks the display in the dialog of the portal, so the display index is ignored.
+    /// Requires ffmpeg to be built with the `pipewiregrab` filter.
+    PipeWire,
+}
+
+impl ScreenCaptureBackend {
+    /// Default backend for the current platform, if the platform has one. On Linux, this is
+    /// PipeWire in Wayland sessions if it is available, and X11 otherwise.
+    pub fn platform_default() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            Some(ScreenCaptureBackend::AvFoundation)
+        } else if cfg!(windows) {
+            Some(ScreenCaptureBackend::DdaGrab)
+        } else if cfg!(unix) {
+            if std::env::var_os("WAYLAND_DISPLAY").is_some()
+                && ScreenCaptureBackend::PipeWire.is_available()
+            {
+                Some(ScreenCaptureBackend::PipeWire)
+            } else {
+                Some(ScreenCaptureBackend::X11Grab)
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Whether or not ffmpeg was built with support for the backend.
+    pub fn is_available(self) -> bool {
+        ffi::find_input_format(self.format_name()).is_some()
+            && self
+                .filter_name()
+                .is_none_or(|name| ffmpeg::filter::find(name).is_some())
+    }
+
+    /// Name of the `libavdevice` input format.
+    fn format_name(self) -> &'static str {
+        match self {
+            ScreenCaptureBackend::AvFoundation => "avfoundation",
+            ScreenCaptureBackend::X11Grab => "x11grab",
+            // Source filters are read through a `lavfi` filter graph.
+            ScreenCaptureBackend::DdaGrab | ScreenCaptureBackend::PipeWire => "lavfi",
+        }
+    }
+
+    /// Name of the source filter, for backends that are implemented as a filter.
+    fn filter_name(self) -> Option<&'static str> {
+        match self {
+            ScreenCaptureBackend::DdaGrab => Some("ddagrab"),
+            ScreenCaptureBackend::PipeWire => Some("pipewiregrab"),
+            ScreenCaptureBackend::AvFoundation | ScreenCaptureBackend::X11Grab => None,
+        }
+    }
+
+    /// List the displays the backend can capture.
+    ///
+    /// Not supported by PipeWire, where the user picks the display.
+    pub fn list_displays(self) -> Result<Vec<CaptureDisplay>> {
+        match self {
+            ScreenCaptureBackend::AvFoundation => {
+                Ok(ffi::list_avfoundation_video_devices()?
+                    .into_iter()
+                    .filter_map(|(device_index, name)| {
+                        // Screens are listed after the cameras as `Capture screen <index>`.
+                        let index = name.strip_prefix("Capture screen ")?.parse().ok()?;
+                        Some(CaptureDisplay {
+                            index,
+                            name,
+                            device: device_index.to_string(),
+                        })
+                    })
+                    .collect())
+            }
+            // Neither desktop duplication nor X11 can list outputs, so probe them in order until
+            // one fails.
+            ScreenCaptureBackend::DdaGrab => {
+                self.probe_displays(|index| format!("ddagrab=output_idx={index}"))
+            }
+            ScreenCaptureBackend::X11Grab => {
+                let display = x11_display();
+                self.probe_displays(|index| format!("{display}.{index}"))
+            }
+            ScreenCaptureBackend::PipeWire => Err(Error::BackendError(AvError::PatchWelcome)),
+        }
+    }
+
+    /// Probe displays by opening them in order until opening fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `device` - Device URL of the display with the given index.
+    fn probe_displays(self, device: impl Fn(usize) -> String) -> Result<Vec<CaptureDisplay>> {
+        let format = ffi::find_input_format(self.format_name())
+            .ok_or(Error::BackendError(AvError::DemuxerNotFound))?;
+        let mut displays = Vec::new();
+        for index in 0..MAX_PROBED_DISPLAYS {
+            let device = device(index);
+            let Ok((input, _)) = ffi::input_with_options(
+                std::path::Path::new(&device),
+                Some(&format),
+                Default::default(),
+                &Interrupt::new(),
+            ) else {
+                break;
+            };
+            let name = match input
+                .streams()
+                .next()
+                .map(|stream| stream.parameters())
+                .and_then(|parameters| ffmpeg::codec::Context::from_parameters(parameters).ok())
+                .and_then(|context| context.decoder().video().ok())
+            {
+                Some(video) => format!("Display {index} ({}x{})", video.width(), video.height()),
+                None => format!("Display {index}"),
+            };
+            displays.push(CaptureDisplay {
+                index,
+                name,
+                device,
+            });
+        }
+        Ok(displays)
+    }
+}
+
+/// Number of displays probed by [`ScreenCaptureBackend::list_displays`] for backends that cannot
+/// list them.
+const MAX_PROBED_DISPLAYS: usize = 16;
+
+/// Name of the X11 display to capture from, without screen number, like `:0`.
+fn x11_display() -> String {
+    let display = std::env::var("DISPLAY").unwrap_or_else(|_| ":0".to_string());
+    // Strip the screen number, as in `:0.1`.
+    match display.rsplit_once(':') {
+        Some((host, number)) => match number.split_once('.') {
+            Some((number, _screen)) => format!("{host}:{number}"),
+            None => display,
+        },
+        None => display,
+    }
+}
+
+/// Region of a display to capture, in pixels relative to the top-left corner of the display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CaptureRegion {
+    /// Horizontal offset.
+    pub x: u32,
+    /// Vertical offset.
+    pub y: u32,
+    /// Width of the region.
+    pub width: u32,
+    /// Height of the region.
+    pub height: u32,
+}
+
+impl CaptureRegion {
+    /// Create a capture region.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset.
+    /// * `y` - Vertical offset.
+    /// * `width` - Width of the region.
+    /// * `height` - Height of the region.
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+/// Display that can be captured, as listed by [`ScreenCaptureBackend::list_displays`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CaptureDisplay {
+    /// Index of the display, to capture it with [`ScreenCaptureTarget::Display`].
+    pub index: usize,
+    /// Human-readable name of the display.
+    pub name: String,
+    /// Name of the device as the backend knows it.
+    pub device: String,
+}
+
+/// What to capture from the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ScreenCaptureTarget {
+    /// Whole display, by index. See [`ScreenCaptureBackend::list_displays`].
+    Display(usize),
+    /// Single window, by native window identifier. Only supported by X11.
+    Window(u64),
+}
+
+impl From<CaptureDisplay> for ScreenCaptureTarget {
+    fn from(display: CaptureDisplay) -> Self {
+        ScreenCaptureTarget::Display(display.index)
+    }
+}
+
+/// Builds a [`Decoder`] that captures the screen.
+///
+/// # Example
+///
+/// ```ignore
+/// let display = ScreenCaptureBackend::AvFoundation.list_displays()?.remove(0);
+/// let mut capture = ScreenCaptureBuilder::new(display)
+///     .with_frame_rate(30)
+///     .with_cursor(true)
+///     .build()?;
+/// let (time, frame) = capture.decode()?;
+/// ```
+pub struct ScreenCaptureBuilder<'a> {
+    target: ScreenCaptureTarget,
+    backend: Option<ScreenCaptureBackend>,
+    frame_rate: Option<u32>,
+    cursor: Option<bool>,
+    mouse_clicks: Option<bool>,
+    region: Option<CaptureRegion>,
+    resize: Option<Resize>,
+    hardware_frames: bool,
+    options: Option<&'a Options>,
+}
+
+impl<'a> ScreenCaptureBuilder<'a> {
+    /// Create a new screen capture builder for the specified target, using the default backend of
+    /// the platform.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - What to capture.
+    pub fn new(target: impl Into<ScreenCaptureTarget>) -> Self {
+        Self {
+            target: target.into(),
+            backend: ScreenCaptureBackend::platform_default(),
+            frame_rate: None,
+            cursor: None,
+            mouse_clicks: None,
+            region: None,
+            resize: None,
+            hardware_frames: false,
+            options: None,
+        }
+    }
+
+    /// Set the backend to capture with.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Capture backend.
+    pub fn with_backend(mut self, backend: ScreenCaptureBackend) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Set the frame rate to capture at.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frames per second.
+    pub fn with_frame_rate(mut self, frame_rate: u32) -> Self {
+        self.frame_rate = Some(frame_rate);
+        self
+    }
+
+    /// Set whether or not to draw the mouse cursor in the captured frames. Not supported by
+    /// PipeWire, where the portal decides.
+    ///
+    /// # Arguments
+    ///
+    /// * `cursor` - Whether or not to capture the cursor.
+    pub fn with_cursor(mut self, cursor: bool) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
+
+    /// Set whether or not to highlight mouse clicks in the captured frames. Only supported by
+    /// AVFoundation.
+    ///
+    /// # Arguments
+    ///
+    /// * `mouse_clicks` - Whether or not to capture mouse clicks.
+    pub fn with_mouse_clicks(mut self, mouse_clicks: bool) -> Self {
+        self.mouse_clicks = Some(mouse_clicks);
+        self
+    }
+
+    /// Capture a region of the display instead of all of it. Not supported by AVFoundation. With
+    /// PipeWire, the region is cropped from the frames after capture.
+    ///
+    /// # Arguments
+    ///
+    /// * `region` - Region to capture.
+    pub fn with_region(mut self, region: CaptureRegion) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Set resizing to apply to captured frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `resize` - Resizing to apply.
+    pub fn with_resize(mut self, resize: Resize) -> Self {
+        self.resize = Some(resize);
+        self
+    }
+
+    /// Keep captured frames on the GPU instead of reading them back to system memory. Only
+    /// supported by [`ScreenCaptureBackend::DdaGrab`], which then produces Direct3D 11 frames.
+    ///
+    /// Decode the frames with [`Decoder::decode_raw`] and encode them with an encoder that takes
+    /// them directly, which avoids the readback entirely:
+    ///
+    /// ```ignore
+    /// let mut capture = ScreenCaptureBuilder::new(ScreenCaptureTarget::Display(0))
+    ///     .with_frame_rate(120)
+    ///     .with_hardware_frames(true)
+    ///     .build()?;
+    /// let frame = capture.decode_raw()?;
+    /// let settings = Settings::preset_h264_yuv420p(1920, 1080, true)
+    ///     .with_hardware_acceleration(HardwareAccelerationDeviceType::Cuda)
+    ///     .with_hardware_frames(HardwareFrames::of(&frame).unwrap());
+    /// let mut encoder = Encoder::new(Path::new("screen.mp4"), settings)?;
+    /// encoder.encode_raw(frame)?;
+    /// ```
+    ///
+    /// Resizing does not apply to hardware frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `hardware_frames` - Whether or not to keep frames on the GPU.
+    pub fn with_hardware_frames(mut self, hardware_frames: bool) -> Self {
+        self.hardware_frames = hardware_frames;
+        self
+    }
+
+    /// Set custom options for the backend, like `pixel_format` for AVFoundation.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to input.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Open the screen and build the [`Decoder`] that decodes the captured frames.
+    pub fn build(self) -> Result<Decoder> {
+        let backend = self
+            .backend
+            .ok_or(Error::BackendError(AvError::DemuxerNotFound))?;
+        let format = ffi::find_input_format(backend.format_name())
+            .ok_or(Error::BackendError(AvError::DemuxerNotFound))?;
+
+        if self.hardware_frames && backend != ScreenCaptureBackend::DdaGrab {
+            return Err(Error::UnsupportedCodecHardwareAccelerationDeviceType);
+        }
+
+        let mut options = self.options.cloned().unwrap_or_default();
+        let url = match (backend, self.target) {
+            (ScreenCaptureBackend::AvFoundation, ScreenCaptureTarget::Display(index)) => {
+                if self.region.is_some() {
+                    return Err(Error::BackendError(AvError::PatchWelcome));
+                }
+                if let Some(frame_rate) = self.frame_rate {
+                    options.set("framerate", &frame_rate.to_string());
+                }
+                if let Some(cursor) = self.cursor {
+                    options.set("capture_cursor", if cursor { "1" } else { "0" });
+                }
+                if let Some(mouse_clicks) = self.mouse_clicks {
+                    options.set("capture_mouse_clicks", if mouse_clicks { "1" } else { "0" });
+                }
+                // AVFoundation uses `<video>:<audio>`, only capture video.
+                format!("Capture screen {index}:none")
+            }
+            // Filter options are part of the filter graph.
+            (ScreenCaptureBackend::DdaGrab, ScreenCaptureTarget::Display(index)) => {
+                let mut url = format!("ddagrab=output_idx={index}");
+                if let Some(frame_rate) = self.frame_rate {
+                    url.push_str(&format!(":framerate={frame_rate}"));
+                }
+                if let Some(cursor) = self.cursor {
+                    url.push_str(&format!(":draw_mouse={}", cursor as u8));
+                }
+                if let Some(region) = self.region {
+                    url.push_str(&format!(
+                        ":offset_x={}:offset_y={}:video_size={}x{}",
+                        region.x, region.y, region.width, region.height
+                    ));
+                }
+                if !self.hardware_frames {
+                    url.push_str(",hwdownload,format=bgra");
+                }
+                url
+            }
+            (ScreenCaptureBackend::X11Grab, target) => {
+                if let Some(frame_rate) = self.frame_rate {
+                    options.set("framerate", &frame_rate.to_string());
+                }
+                if let Some(cursor) = self.cursor {
+                    options.set("draw_mouse", if cursor { "1" } else { "0" });
+                }
+                if let Some(region) = self.region {
+                    options.set("video_size", &format!("{}x{}", region.width, region.height));
+                }
+                let offset = self
+                    .region
+                    .map(|region| format!("+{},{}", region.x, region.y))
+                    .unwrap_or_default();
+                match target {
+                    ScreenCaptureTarget::Display(index) => {
+                        format!("{}.{index}{offset}", x11_display())
+                    }
+                    ScreenCaptureTarget::Window(window_id) => {
+                        options.set("window_id", &window_id.to_string());
+                        format!("{}{offset}", x11_display())
+                    }
+                }
+            }
+            (ScreenCaptureBackend::PipeWire, ScreenCaptureTarget::Display(_)) => {
+                let mut url = "pipewiregrab".to_string();
+                if let Some(frame_rate) = self.frame_rate {
+                    url.push_str(&format!("=framerate={frame_rate}"));
+                }
+                if let Some(region) = self.region {
+                    url.push_str(&format!(
+                        ",crop={}:{}:{}:{}",
+                        region.width, region.height, region.x, region.y
+                    ));
+                }
+                url
+            }
+            (_, ScreenCaptureTarget::Window(_)) => {
+                return Err(Error::BackendError(AvError::PatchWelcome))
+            }
+        };
+
+        let interrupt = Interrupt::new();
+        let (input, unused_options) = ffi::input_with_options(
+            std::path::Path::new(&url),
+            Some(&format),
+            options.to_dict(),
+            &interrupt,
+        )?;
+        let reader = Reader::from_input(
+            Location::File(url.into()),
+            input,
+            interrupt,
+            None,
+            Options::unused_keys(unused_options, "screen capture"),
+        );
+
+        Decoder::from_reader(reader, self.resize, self.hardware_frames)
+    }
+}
@@ -0,0 +1,422 @@
+//! Visual comparison of two inputs.
+//!
+//! [`DiffBuilder`] decodes two inputs frame by frame and renders a video that shows where they
+//! differ: a heatmap of the per-pixel difference, and optionally a map of the structural
+//! similarity (SSIM). This helps to find color shifts, scaling artifacts and other regressions
+//! introduced by a pipeline, by comparing its output to its input (or to a known good output).
+//!
+//! Frames are paired in order, so both inputs must have the same frames. The distorted input is
+//! scaled to the size of the reference.
+
+use ffmpeg::util::format::Pixel as AvPixel;
+
+use crate::decode::{Decoder, DecoderBuilder};
+use crate::encode::{EncoderBuilder, Settings};
+use crate::error::Error;
+use crate::frame::RawFrame;
+use crate::location::Location;
+use crate::options::Options;
+use crate::resize::Resize;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Size in pixels of the square blocks SSIM is computed over.
+const SSIM_BLOCK_SIZE: usize = 8;
+
+/// SSIM stabilization constant `(0.01 * 255)^2`.
+const SSIM_C1: f64 = 6.5025;
+
+/// SSIM stabilization constant `(0.03 * 255)^2`.
+const SSIM_C2: f64 = 58.5225;
+
+/// Summary of the differences between two inputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiffReport {
+    /// Number of compared frames.
+    pub frames: u64,
+    /// Mean absolute difference per color component over all frames, from 0 to 255.
+    pub mean_abs_diff: f64,
+    /// Largest absolute difference of any color component in any frame.
+    pub max_abs_diff: u8,
+    /// Mean SSIM of the luma over all frames, from 0 (different) to 1 (identical), if the SSIM map
+    /// was enabled.
+    pub mean_ssim: Option<f64>,
+    /// Index of the frame with the largest mean absolute difference, if any frames were compared.
+    pub worst_frame: Option<u64>,
+}
+
+/// Builds a visual diff of two inputs.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = DiffBuilder::new(Path::new("input.mp4"), Path::new("output.mp4"))
+///     .with_ssim_map()
+///     .render(Path::new("diff.mp4"))
+///     .unwrap();
+/// println!("mean SSIM: {:?}", report.mean_ssim);
+/// ```
+pub struct DiffBuilder<'a> {
+    reference: Location,
+    distorted: Location,
+    ssim: bool,
+    gain: f32,
+    options: Option<&'a Options>,
+}
+
+impl<'a> DiffBuilder<'a> {
+    /// Create a new [`DiffBuilder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reference` - Reference input.
+    /// * `distorted` - Input to compare to the reference.
+    pub fn new(reference: impl Into<Location>, distorted: impl Into<Location>) -> Self {
+        Self {
+            reference: reference.into(),
+            distorted: distorted.into(),
+            ssim: false,
+            gain: 4.0,
+            options: None,
+        }
+    }
+
+    /// Render a map of the local SSIM next to the heatmap, where dark areas are structurally
+    /// different. Also reports the mean SSIM.
+    pub fn with_ssim_map(mut self) -> Self {
+        self.ssim = true;
+        self
+    }
+
+    /// Set the amplification of differences in the heatmap. Small differences are invisible
+    /// without amplification. Defaults to 4.
+    ///
+    /// # Arguments
+    ///
+    /// * `gain` - Factor to multiply differences by.
+    pub fn with_gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Set the options for the encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Encoder options.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Compare the inputs and render the diff video.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where to write the diff video to.
+    pub fn render(self, destination: impl Into<Location>) -> Result<DiffReport> {
+        let mut reference = Decoder::new(self.reference)?;
+        let (width, height) = reference.size();
+        let mut distorted = DecoderBuilder::new(self.distorted)
+            .with_resize(Resize::Exact(width, height))
+            .build()?;
+
+        // Cut off odd rows and columns, which the encoder does not accept.
+        let size = ((width & !1) as usize, (height & !1) as usize);
+        let canvas_width = if self.ssim { size.0 * 2 } else { size.0 };
+        let mut encoder_builder = EncoderBuilder::new(
+            destination,
+            Settings::preset_h264_yuv420p(canvas_width, size.1, false),
+        );
+        if let Some(options) = self.options {
+            encoder_builder = encoder_builder.with_options(options);
+        }
+        let mut encoder = encoder_builder.build()?;
+
+        let time_base = reference.time_base();
+        let mut report = DiffReport::default();
+        let mut total_abs_diff = 0.0;
+        let mut total_ssim = 0.0;
+        let mut worst_abs_diff = -1.0;
+        loop {
+            let (reference_frame, distorted_frame) =
+                match (next_frame(&mut reference)?, next_frame(&mut distorted)?) {
+                    (Some(reference_frame), Some(distorted_frame)) => {
+                        (reference_frame, distorted_frame)
+                    }
+                    _ => break,
+                };
+            let reference_image = pack(&reference_frame, size);
+            let distorted_image = pack(&distorted_frame, size);
+
+            let (heatmap, stats) = heatmap(&reference_image, &distorted_image, self.gain);
+            total_abs_diff += stats.mean;
+            report.max_abs_diff = report.max_abs_diff.max(stats.max);
+            if stats.mean > worst_abs_diff {
+                worst_abs_diff = stats.mean;
+                report.worst_frame = Some(report.frames);
+            }
+
+            let image = if self.ssim {
+                let (ssim_map, mean_ssim) = ssim_map(
+                    &luma(&reference_image),
+                    &luma(&distorted_image),
+                    size.0,
+                    size.1,
+                );
+                total_ssim += mean_ssim;
+                side_by_side(&heatmap, &ssim_map, size)
+            } else {
+                heatmap
+            };
+
+            let mut frame = unpack(&image, (canvas_width, size.1));
+            frame.set_pts(
+                Time::new(reference_frame.timestamp(), time_base)
+                    .with_time_base(encoder.time_base())
+                    .into_value(),
+            );
+            encoder.encode_raw(frame)?;
+            report.frames += 1;
+        }
+        encoder.finish()?;
+
+        if report.frames > 0 {
+            report.mean_abs_diff = total_abs_diff / report.frames as f64;
+            if self.ssim {
+                report.mean_ssim = Some(total_ssim / report.frames as f64);
+            }
+        }
+        Ok(report)
+    }
+}
+
+/// Decode the next frame, or `None` if the decoder is exhausted.
+fn next_frame(decoder: &mut Decoder) -> Result<Option<RawFrame>> {
+    match decoder.decode_raw() {
+        Ok(frame) => Ok(Some(frame)),
+        Err(Error::DecodeExhausted | Error::ReadExhausted) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Copy the top left part of an RGB24 frame into a packed RGB24 image.
+fn pack(frame: &RawFrame, (width, height): (usize, usize)) -> Vec<u8> {
+    let stride = frame.stride(0);
+    let data = frame.data(0);
+    let mut image = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        image.extend_from_slice(&data[y * stride..y * stride + width * 3]);
+    }
+    image
+}
+
+/// Copy a packed RGB24 image into a frame.
+fn unpack(image: &[u8], (width, height): (usize, usize)) -> RawFrame {
+    let mut frame = RawFrame::new(AvPixel::RGB24, width as u32, height as u32);
+    let stride = frame.stride(0);
+    let row = width * 3;
+    let data = frame.data_mut(0);
+    for y in 0..height {
+        data[y * stride..y * stride + row].copy_from_slice(&image[y * row..(y + 1) * row]);
+    }
+    frame
+}
+
+/// Absolute difference statistics of one frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DiffStats {
+    mean: f64,
+    max: u8,
+}
+
+/// Render a heatmap of the per-pixel difference between two packed RGB24 images. The difference
+/// of a pixel is the largest difference of its color components.
+///
+/// # Arguments
+///
+/// * `reference` - Reference image.
+/// * `distorted` - Distorted image of the same size.
+/// * `gain` - Factor to multiply differences by.
+fn heatmap(reference: &[u8], distorted: &[u8], gain: f32) -> (Vec<u8>, DiffStats) {
+    let mut image = Vec::with_capacity(reference.len());
+    let mut total = 0_u64;
+    let mut max = 0_u8;
+    for (reference, distorted) in reference.chunks_exact(3).zip(distorted.chunks_exact(3)) {
+        let mut pixel_max = 0_u8;
+        for (&a, &b) in reference.iter().zip(distorted) {
+            let diff = a.abs_diff(b);
+            total += diff as u64;
+            pixel_max = pixel_max.max(diff);
+        }
+        max = max.max(pixel_max);
+        image.extend_from_slice(&heat_color(pixel_max as f32 * gain / 255.0));
+    }
+    let mean = if reference.is_empty() {
+        0.0
+    } else {
+        total as f64 / reference.len() as f64
+    };
+    (image, DiffStats { mean, max })
+}
+
+/// Map a value from 0 to 1 to a color from black through blue, red and yellow to white.
+fn heat_color(value: f32) -> [u8; 3] {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 255.0],
+        [255.0, 0.0, 0.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 255.0, 255.0],
+    ];
+    let position = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let index = (position.floor() as usize).min(STOPS.len() - 2);
+    let fraction = position - index as f32;
+    let (from, to) = (STOPS[index], STOPS[index + 1]);
+    [0, 1, 2].map(|c| (from[c] + (to[c] - from[c]) * fraction).round() as u8)
+}
+
+/// Compute the (BT.601) luma of a packed RGB24 image.
+fn luma(image: &[u8]) -> Vec<u8> {
+    image
+        .chunks_exact(3)
+        .map(|pixel| {
+            (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32).round()
+                as u8
+        })
+        .collect()
+}
+
+/// Compute the SSIM of two luma planes over blocks, and render it as a packed RGB24 grayscale
+/// image where identical blocks are white.
+///
+/// # Arguments
+///
+/// * `reference` - Reference luma.
+/// * `distorted` - Distorted luma.
+/// * `width` - Width of the planes.
+/// * `height` - Height of the planes.
+///
+/// # Return value
+///
+/// Rendered SSIM map and mean SSIM.
+fn ssim_map(reference: &[u8], distorted: &[u8], width: usize, height: usize) -> (Vec<u8>, f64) {
+    let mut image = vec![0; width * height * 3];
+    let mut total = 0.0;
+    let mut blocks = 0;
+    for block_y in (0..height).step_by(SSIM_BLOCK_SIZE) {
+        for block_x in (0..width).step_by(SSIM_BLOCK_SIZE) {
+            let rows = block_y..(block_y + SSIM_BLOCK_SIZE).min(height);
+            let columns = block_x..(block_x + SSIM_BLOCK_SIZE).min(width);
+            let pixels = || {
+                rows.clone().flat_map({
+                    let columns = columns.clone();
+                    move |y| columns.clone().map(move |x| y * width + x)
+                })
+            };
+            let ssim = ssim(
+                pixels().map(|index| reference[index]),
+                pixels().map(|index| distorted[index]),
+            );
+            total += ssim;
+            blocks += 1;
+
+            let value = (ssim.clamp(0.0, 1.0) * 255.0).round() as u8;
+            for index in pixels() {
+                image[index * 3..index * 3 + 3].fill(value);
+            }
+        }
+    }
+    let mean = if blocks > 0 {
+        total / blocks as f64
+    } else {
+        1.0
+    };
+    (image, mean)
+}
+
+/// Compute the SSIM of two blocks of samples.
+fn ssim(reference: impl Iterator<Item = u8>, distorted: impl Iterator<Item = u8>) -> f64 {
+    let (mut n, mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) =
+        (0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for (a, b) in reference.zip(distorted) {
+        let (a, b) = (a as f64, b as f64);
+        n += 1.0;
+        sum_a += a;
+        sum_b += b;
+        sum_aa += a * a;
+        sum_bb += b * b;
+        sum_ab += a * b;
+    }
+    if n == 0.0 {
+        return 1.0;
+    }
+    let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+    let variance_a = sum_aa / n - mean_a * mean_a;
+    let variance_b = sum_bb / n - mean_b * mean_b;
+    let covariance = sum_ab / n - mean_a * mean_b;
+    ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (variance_a + variance_b + SSIM_C2))
+}
+
+/// Place two packed RGB24 images of the same size next to each other.
+fn side_by_side(left: &[u8], right: &[u8], (width, height): (usize, usize)) -> Vec<u8> {
+    let row = width * 3;
+    let mut image = Vec::with_capacity(row * height * 2);
+    for y in 0..height {
+        image.extend_from_slice(&left[y * row..(y + 1) * row]);
+        image.extend_from_slice(&right[y * row..(y + 1) * row]);
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heat_color() {
+        assert_eq!(heat_color(0.0), [0, 0, 0]);
+        assert_eq!(heat_color(0.25), [0, 0, 255]);
+        assert_eq!(heat_color(0.5), [255, 0, 0]);
+        assert_eq!(heat_color(1.0), [255, 255, 255]);
+        assert_eq!(heat_color(2.0), [255, 255, 255]);
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let reference = [10, 20, 30, 0, 0, 0];
+        let distorted = [10, 20, 30, 0, 255, 0];
+        let (image, stats) = heatmap(&reference, &distorted, 1.0);
+        assert_eq!(image, vec![0, 0, 0, 255, 255, 255]);
+        assert_eq!(stats.max, 255);
+        assert_eq!(stats.mean, 255.0 / 6.0);
+    }
+
+    #[test]
+    fn test_ssim_identical() {
+        let plane = (0..16 * 16).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let (image, mean) = ssim_map(&plane, &plane, 16, 16);
+        assert!((mean - 1.0).abs() < 1e-9);
+        assert!(image.iter().all(|&value| value == 255));
+    }
+
+    #[test]
+    fn test_ssim_different() {
+        let reference = (0..16 * 16).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let distorted = reference.iter().map(|&v| 255 - v).collect::<Vec<_>>();
+        let (_, mean) = ssim_map(&reference, &distorted, 16, 16);
+        assert!(mean < 0.5);
+    }
+
+    #[test]
+    fn test_side_by_side() {
+        let left = [1, 1, 1, 2, 2, 2];
+        let right = [3, 3, 3, 4, 4, 4];
+        assert_eq!(
+            side_by_side(&left, &right, (1, 2)),
+            vec![1, 1, 1, 3, 3, 3, 2, 2, 2, 4, 4, 4]
+        );
+    }
+}
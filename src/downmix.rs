@@ -0,0 +1,419 @@
+//! Downmixing of surround audio to stereo or mono, like a 5.1 movie soundtrack for playback on
+//! headphones.
+
+use ffmpeg::filter::Graph as AvFilterGraph;
+use ffmpeg::util::format::Sample as AvSample;
+use ffmpeg::{ChannelLayout as AvChannelLayout, Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::frame::RawAudioFrame;
+use crate::mixer::{add_frame, end_source, receive_frame};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Channel masks of the channels that take part in a downmix, as in the channel layout of a frame.
+const FRONT_LEFT: u64 = 0x1;
+const FRONT_RIGHT: u64 = 0x2;
+const FRONT_CENTER: u64 = 0x4;
+const LOW_FREQUENCY: u64 = 0x8;
+const BACK_LEFT: u64 = 0x10;
+const BACK_RIGHT: u64 = 0x20;
+const BACK_CENTER: u64 = 0x100;
+const SIDE_LEFT: u64 = 0x200;
+const SIDE_RIGHT: u64 = 0x400;
+
+/// Gain of -3 dB, which keeps the power of a channel that is spread over two outputs.
+const MINUS_3_DB: f32 = std::f32::consts::FRAC_1_SQRT_2;
+
+/// Channel layout a [`Downmix`] produces.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DownmixTarget {
+    /// Two channels, front left and front right.
+    #[default]
+    Stereo,
+    /// One channel, front center.
+    Mono,
+}
+
+/// How surround channels are mixed into fewer channels, applied with the `pan` filter by a
+/// [`Downmixer`].
+///
+/// Channels are mixed at fixed levels relative to the front channels, which pass through at unity
+/// gain. This keeps the loudness of the front channels, unlike averaging the channels, which makes
+/// the mix quieter and buries the dialogue of the center channel under the effects. The levels
+/// default to those of ITU-R BS.775: the center and surround channels are mixed in at -3 dB and
+/// the LFE channel is dropped.
+///
+/// Only channels that are present in the channel layout of the source take part, so the same
+/// downmix can be applied to 5.1, 5.1 (side), 7.1 and stereo sources.
+///
+/// # Example
+///
+/// ```ignore
+/// let downmix = Downmix::stereo_dialogue().with_lfe_level(0.5);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Downmix {
+    target: DownmixTarget,
+    center_level: f32,
+    surround_level: f32,
+    lfe_level: f32,
+    dialogue_boost: f32,
+    preserve_loudness: bool,
+}
+
+impl Downmix {
+    /// Dialogue boost of [`Downmix::stereo_dialogue`] in decibels.
+    const DIALOGUE_BOOST: f32 = 4.0;
+
+    /// Downmix to stereo with the levels of ITU-R BS.775.
+    pub fn stereo() -> Self {
+        Self {
+            target: DownmixTarget::Stereo,
+            center_level: MINUS_3_DB,
+            surround_level: MINUS_3_DB,
+            lfe_level: 0.0,
+            dialogue_boost: 0.0,
+            preserve_loudness: true,
+        }
+    }
+
+    /// Downmix to stereo with the center channel boosted and the surround channels lowered, which
+    /// makes dialogue easier to follow on small speakers and at low volume.
+    pub fn stereo_dialogue() -> Self {
+        Self {
+            surround_level: 0.5,
+            dialogue_boost: Self::DIALOGUE_BOOST,
+            ..Self::stereo()
+        }
+    }
+
+    /// Downmix to mono. The channels are first downmixed to stereo with the levels of ITU-R
+    /// BS.775, after which left and right are mixed at -3 dB.
+    pub fn mono() -> Self {
+        Self {
+            target: DownmixTarget::Mono,
+            ..Self::stereo()
+        }
+    }
+
+    /// Set the channel layout to produce.
+    ///
+    /// # Arguments
+    ///
+    /// * `target` - Channel layout to produce.
+    pub fn with_target(mut self, target: DownmixTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Set the level the center channel is mixed in at, relative to the front channels.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Linear gain, where `1.0` is as loud as the front channels.
+    pub fn with_center_level(mut self, level: f32) -> Self {
+        self.center_level = level;
+        self
+    }
+
+    /// Set the level the surround (back and side) channels are mixed in at, relative to the front
+    /// channels.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Linear gain, where `1.0` is as loud as the front channels.
+    pub fn with_surround_level(mut self, level: f32) -> Self {
+        self.surround_level = level;
+        self
+    }
+
+    /// Set the level the LFE channel is mixed in at, relative to the front channels. Most
+    /// downmixes drop it, since small speakers cannot reproduce it anyway.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - Linear gain, where `0.0` drops the channel.
+    pub fn with_lfe_level(mut self, level: f32) -> Self {
+        self.lfe_level = level;
+        self
+    }
+
+    /// Boost the center channel, which carries the dialogue in surround mixes, on top of the
+    /// center level. Has no effect on sources without a center channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `decibels` - Boost in decibels.
+    pub fn with_dialogue_boost(mut self, decibels: f32) -> Self {
+        self.dialogue_boost = decibels;
+        self
+    }
+
+    /// Set whether the loudness of the front channels is preserved (the default). The mixed
+    /// channels can then exceed full scale, so a limiter is applied to the output to prevent
+    /// clipping. Otherwise, the levels are scaled down so that no output channel can exceed full
+    /// scale, which makes the output quieter.
+    ///
+    /// # Arguments
+    ///
+    /// * `preserve_loudness` - Whether to preserve loudness.
+    pub fn with_loudness_preservation(mut self, preserve_loudness: bool) -> Self {
+        self.preserve_loudness = preserve_loudness;
+        self
+    }
+
+    /// Get the `pan` filter arguments of the downmix, to use the downmix in a custom filter graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel_mask` - Channel mask of the layout of the source.
+    pub fn pan_args(&self, channel_mask: u64) -> String {
+        let (layout, outputs) = match self.target {
+            DownmixTarget::Stereo => (
+                "stereo",
+                vec![
+                    ("FL", self.gains(channel_mask, true)),
+                    ("FR", self.gains(channel_mask, false)),
+                ],
+            ),
+            DownmixTarget::Mono => {
+                let mut gains: Vec<(&str, f32)> = Vec::new();
+                let left = self.gains(channel_mask, true);
+                let right = self.gains(channel_mask, false);
+                for (channel, gain) in left.into_iter().chain(right) {
+                    match gains.iter_mut().find(|(known, _)| *known == channel) {
+                        Some((_, sum)) => *sum += gain * MINUS_3_DB,
+                        None => gains.push((channel, gain * MINUS_3_DB)),
+                    }
+                }
+                ("mono", vec![("FC", gains)])
+            }
+        };
+        // With `<` instead of `=`, the pan filter scales the gains of an output down so that they
+        // add up to at most one.
+        let operator = if self.preserve_loudness { '=' } else { '<' };
+        let mut args = layout.to_string();
+        for (output, gains) in outputs {
+            let terms = gains
+                .iter()
+                .filter(|(_, gain)| *gain != 0.0)
+                .map(|(channel, gain)| format!("{gain:.4}*{channel}"))
+                .collect::<Vec<_>>();
+            args.push_str(&format!("|{output}{operator}{}", terms.join("+")));
+        }
+        args
+    }
+
+    /// Get the gain of every channel of the source that is present in the layout, for the left or
+    /// the right output of a stereo downmix.
+    ///
+    /// # Arguments
+    ///
+    /// * `channel_mask` - Channel mask of the layout of the source.
+    /// * `left` - Whether to get the gains of the left output.
+    fn gains(&self, channel_mask: u64, left: bool) -> Vec<(&'static str, f32)> {
+        let center = self.center_level * 10.0_f32.powf(self.dialogue_boost / 20.0);
+        let (front, back, side) = if left {
+            ((FRONT_LEFT, "FL"), (BACK_LEFT, "BL"), (SIDE_LEFT, "SL"))
+        } else {
+            ((FRONT_RIGHT, "FR"), (BACK_RIGHT, "BR"), (SIDE_RIGHT, "SR"))
+        };
+        [
+            (front, 1.0),
+            ((FRONT_CENTER, "FC"), center),
+            ((LOW_FREQUENCY, "LFE"), self.lfe_level),
+            (back, self.surround_level),
+            (side, self.surround_level),
+            ((BACK_CENTER, "BC"), self.surround_level * MINUS_3_DB),
+        ]
+        .into_iter()
+        .filter(|((mask, _), _)| channel_mask & mask != 0)
+        .map(|((_, channel), gain)| (channel, gain))
+        .collect()
+    }
+}
+
+impl Default for Downmix {
+    fn default() -> Self {
+        Self::stereo()
+    }
+}
+
+/// Applies a [`Downmix`] to audio frames with a filter graph.
+///
+/// The output has the sample format and sample rate of the input. The filter graph is configured
+/// with the first frame, since it depends on the channel layout of the source. Sources with
+/// multiple language tracks can be downmixed after selecting a track with
+/// [`Reader::select_audio`](crate::io::Reader::select_audio).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut downmixer = Downmixer::new(Downmix::stereo(), decoder.time_base());
+/// while let Ok(frame) = decoder.decode_raw() {
+///     downmixer.push(frame)?;
+///     while let Some(frame) = downmixer.pull()? {
+///         encoder.encode_raw(&frame)?;
+///     }
+/// }
+/// downmixer.finish()?;
+/// while let Some(frame) = downmixer.pull()? {
+///     encoder.encode_raw(&frame)?;
+/// }
+/// ```
+pub struct Downmixer {
+    downmix: Downmix,
+    time_base: AvRational,
+    format: Option<(AvSample, u32, u64)>,
+    graph: Option<AvFilterGraph>,
+}
+
+impl Downmixer {
+    /// Create a downmixer.
+    ///
+    /// # Arguments
+    ///
+    /// * `downmix` - Downmix to apply.
+    /// * `time_base` - Time base of the timestamps of the frames.
+    pub fn new(downmix: Downmix, time_base: AvRational) -> Self {
+        Self {
+            downmix,
+            time_base,
+            format: None,
+            graph: None,
+        }
+    }
+
+    /// Push a frame.
+    ///
+    /// Returns [`Error::InvalidFrameFormat`] if the frame has another sample format, channel layout
+    /// or sample rate than the frames before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame with samples, with a timestamp in the time base of the downmixer.
+    pub fn push(&mut self, frame: RawAudioFrame) -> Result<()> {
+        let mask = match frame.channel_layout().bits() {
+            0 => AvChannelLayout::default(i32::from(frame.channels())).bits(),
+            mask => mask,
+        };
+        let format = (frame.format(), frame.rate(), mask);
+        match self.format {
+            Some(known) if known != format => return Err(Error::InvalidFrameFormat),
+            Some(_) => {}
+            None => self.format = Some(format),
+        }
+        let graph = match self.graph.as_mut() {
+            Some(graph) => graph,
+            None => self.graph.insert(self.configure(format)?),
+        };
+        add_frame(graph, 0, &frame)
+    }
+
+    /// Signal the end of the input, after which the remaining samples can be pulled.
+    pub fn finish(&mut self) -> Result<()> {
+        match self.graph.as_mut() {
+            Some(graph) => end_source(graph, 0),
+            None => Ok(()),
+        }
+    }
+
+    /// Pull a downmixed frame.
+    ///
+    /// # Return value
+    ///
+    /// The frame, or `None` if more frames need to be pushed first, or the input has ended.
+    pub fn pull(&mut self) -> Result<Option<RawAudioFrame>> {
+        match self.graph.as_mut() {
+            Some(graph) => receive_frame(graph),
+            None => Ok(None),
+        }
+    }
+
+    /// Configure the filter graph for the format of the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Sample format, sample rate and channel mask of the frames.
+    fn configure(
+        &self,
+        (sample_format, sample_rate, mask): (AvSample, u32, u64),
+    ) -> Result<AvFilterGraph> {
+        let mut graph = AvFilterGraph::new();
+        let abuffer = ffmpeg::filter::find("abuffer").ok_or(AvError::FilterNotFound)?;
+        let abuffersink = ffmpeg::filter::find("abuffersink").ok_or(AvError::FilterNotFound)?;
+        graph.add(
+            &abuffer,
+            "in0",
+            &format!(
+                "time_base={}/{}:sample_rate={sample_rate}:sample_fmt={}:channel_layout=0x{mask:x}",
+                self.time_base.numerator(),
+                self.time_base.denominator(),
+                sample_format.name(),
+            ),
+        )?;
+        graph.add(&abuffersink, "out", "")?;
+
+        let mut spec = format!("pan={}", self.downmix.pan_args(mask));
+        if self.downmix.preserve_loudness {
+            spec.push_str(",alimiter=limit=1:level=disabled");
+        }
+        spec.push_str(&format!(
+            ",aresample={sample_rate},aformat=sample_fmts={}",
+            sample_format.name(),
+        ));
+        graph.output("in0", 0)?.input("out", 0)?.parse(&spec)?;
+        graph.validate()?;
+        Ok(graph)
+    }
+}
+
+unsafe impl Send for Downmixer {}
+unsafe impl Sync for Downmixer {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEREO: u64 = FRONT_LEFT | FRONT_RIGHT;
+    const SURROUND_5_1: u64 = STEREO | FRONT_CENTER | LOW_FREQUENCY | BACK_LEFT | BACK_RIGHT;
+
+    #[test]
+    fn test_stereo_from_5_1() {
+        assert_eq!(
+            Downmix::stereo().pan_args(SURROUND_5_1),
+            "stereo|FL=1.0000*FL+0.7071*FC+0.7071*BL|FR=1.0000*FR+0.7071*FC+0.7071*BR",
+        );
+    }
+
+    #[test]
+    fn test_stereo_from_stereo() {
+        assert_eq!(
+            Downmix::stereo().pan_args(STEREO),
+            "stereo|FL=1.0000*FL|FR=1.0000*FR",
+        );
+    }
+
+    #[test]
+    fn test_dialogue_boost_and_lfe() {
+        assert_eq!(
+            Downmix::stereo()
+                .with_dialogue_boost(6.0)
+                .with_lfe_level(0.5)
+                .pan_args(SURROUND_5_1),
+            "stereo|FL=1.0000*FL+1.4109*FC+0.5000*LFE+0.7071*BL\
+             |FR=1.0000*FR+1.4109*FC+0.5000*LFE+0.7071*BR",
+        );
+    }
+
+    #[test]
+    fn test_mono_from_5_1_without_loudness_preservation() {
+        assert_eq!(
+            Downmix::mono()
+                .with_loudness_preservation(false)
+                .pan_args(SURROUND_5_1),
+            "mono|FC<0.7071*FL+1.0000*FC+0.5000*BL+0.7071*FR+0.5000*BR",
+        );
+    }
+}
@@ -0,0 +1,539 @@
+//! Edit decision lists.
+//!
+//! An [`Edl`] describes a sequence of clips, each cut from a source with an in and out point, played
+//! back at a speed, and joined to the previous clip with a [`Transition`]. [`EdlExecutor`] renders
+//! an EDL to a single output.
+//!
+//! Only video is rendered. Clips are scaled to fit the output while retaining their aspect ratio,
+//! and letterboxed with black bars.
+
+use std::time::Duration;
+
+use ffmpeg::util::format::Pixel as AvPixel;
+use ffmpeg::Rational as AvRational;
+
+use crate::decode::{Decoder, DecoderBuilder};
+use crate::encode::{EncoderBuilder, Settings};
+use crate::error::Error;
+use crate::frame::RawFrame;
+use crate::location::Location;
+use crate::options::Options;
+use crate::resize::Resize;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Transition from the previous clip into a clip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// Hard cut.
+    Cut,
+    /// Cross-dissolve between the clips. The clips overlap for the duration of the transition.
+    Dissolve(Duration),
+    /// Fade the previous clip to black, then fade in the clip. The clips overlap for the duration
+    /// of the transition.
+    FadeThroughBlack(Duration),
+}
+
+impl Transition {
+    /// Duration of the transition.
+    pub fn duration(&self) -> Duration {
+        match *self {
+            Transition::Cut => Duration::ZERO,
+            Transition::Dissolve(duration) | Transition::FadeThroughBlack(duration) => duration,
+        }
+    }
+}
+
+/// Clip in an [`Edl`].
+#[derive(Debug, Clone)]
+pub struct EdlClip {
+    source: Location,
+    in_point: Time,
+    out_point: Time,
+    speed: f64,
+    transition: Transition,
+}
+
+impl EdlClip {
+    /// Create a clip of a range of a source, played back at normal speed and joined to the
+    /// previous clip with a cut.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to cut the clip from.
+    /// * `in_point` - Start of the clip in the source (inclusive).
+    /// * `out_point` - End of the clip in the source (exclusive).
+    pub fn new(source: impl Into<Location>, in_point: Time, out_point: Time) -> Self {
+        Self {
+            source: source.into(),
+            in_point,
+            out_point,
+            speed: 1.0,
+            transition: Transition::Cut,
+        }
+    }
+
+    /// Set the playback speed, like `2.0` for double speed or `0.5` for slow motion. Frames are
+    /// dropped or repeated to retime the clip.
+    ///
+    /// # Arguments
+    ///
+    /// * `speed` - Playback speed. Must be positive.
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Set the transition from the previous clip into this clip. The transition of the first clip
+    /// is ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `transition` - Transition.
+    pub fn with_transition(mut self, transition: Transition) -> Self {
+        self.transition = transition;
+        self
+    }
+
+    /// Duration of the clip in the output.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.duration_secs())
+    }
+
+    fn duration_secs(&self) -> f64 {
+        if self.speed > 0.0 {
+            ((self.out_point.as_secs_f64() - self.in_point.as_secs_f64()) / self.speed).max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Edit decision list: sequence of clips that make up an edit.
+///
+/// # Example
+///
+/// ```ignore
+/// let edl = Edl::new()
+///     .with_clip(EdlClip::new("a.mp4", Time::from_secs(10.0), Time::from_secs(20.0)))
+///     .with_clip(
+///         EdlClip::new("b.mp4", Time::zero(), Time::from_secs(8.0))
+///             .with_speed(2.0)
+///             .with_transition(Transition::Dissolve(Duration::from_secs(1))),
+///     );
+/// EdlExecutor::new(&edl, Settings::preset_h264_yuv420p(1280, 720, false))
+///     .render(Path::new("edit.mp4"))
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Edl {
+    clips: Vec<EdlClip>,
+}
+
+impl Edl {
+    /// Create an empty EDL.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a clip.
+    ///
+    /// # Arguments
+    ///
+    /// * `clip` - Clip to append.
+    pub fn with_clip(mut self, clip: EdlClip) -> Self {
+        self.push(clip);
+        self
+    }
+
+    /// Append a clip.
+    ///
+    /// # Arguments
+    ///
+    /// * `clip` - Clip to append.
+    pub fn push(&mut self, clip: EdlClip) {
+        self.clips.push(clip);
+    }
+
+    /// Clips in the EDL.
+    pub fn clips(&self) -> &[EdlClip] {
+        &self.clips
+    }
+
+    /// Duration of the rendered EDL.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(
+            layout(&self.durations())
+                .last()
+                .map(|span| span.start + span.duration)
+                .unwrap_or(0.0),
+        )
+    }
+
+    /// Output durations of the clips and of the transitions into them, in seconds.
+    fn durations(&self) -> Vec<(f64, f64)> {
+        self.clips
+            .iter()
+            .map(|clip| {
+                (
+                    clip.duration_secs(),
+                    clip.transition.duration().as_secs_f64(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Position of a clip on the output timeline, in seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Span {
+    start: f64,
+    duration: f64,
+    /// Duration of the transition into the clip, during which it overlaps with the previous clip.
+    transition: f64,
+}
+
+/// Lay out clips on the output timeline.
+///
+/// # Arguments
+///
+/// * `durations` - Output durations of the clips and of the transitions into them.
+fn layout(durations: &[(f64, f64)]) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::with_capacity(durations.len());
+    for &(duration, transition) in durations {
+        let span = match spans.last() {
+            // A transition cannot be longer than either of the clips it joins.
+            Some(previous) => {
+                let transition = transition.min(previous.duration).min(duration).max(0.0);
+                Span {
+                    start: previous.start + previous.duration - transition,
+                    duration,
+                    transition,
+                }
+            }
+            None => Span {
+                start: 0.0,
+                duration,
+                transition: 0.0,
+            },
+        };
+        spans.push(span);
+    }
+    spans
+}
+
+/// Renders an [`Edl`] to a single output.
+pub struct EdlExecutor<'a> {
+    edl: &'a Edl,
+    settings: Settings,
+    frame_rate: f64,
+    options: Option<&'a Options>,
+}
+
+impl<'a> EdlExecutor<'a> {
+    /// Default output frame rate.
+    const FRAME_RATE: f64 = 30.0;
+
+    /// Create an executor for an EDL. The output resolution is taken from the encoder settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `edl` - EDL to render.
+    /// * `settings` - Encoder settings.
+    pub fn new(edl: &'a Edl, settings: Settings) -> Self {
+        Self {
+            edl,
+            settings,
+            frame_rate: Self::FRAME_RATE,
+            options: None,
+        }
+    }
+
+    /// Set the output frame rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frame rate in frames per second.
+    pub fn with_frame_rate(mut self, frame_rate: f64) -> Self {
+        self.frame_rate = frame_rate;
+        self
+    }
+
+    /// Set the options for the output.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to output.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Render the EDL.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where to write the output to.
+    pub fn render(self, destination: impl Into<Location>) -> Result<()> {
+        let mut encoder_builder = EncoderBuilder::new(destination, self.settings);
+        if let Some(options) = self.options {
+            encoder_builder = encoder_builder.with_options(options);
+        }
+        let mut encoder = encoder_builder.build()?;
+        let canvas = encoder.size();
+
+        let spans = layout(&self.edl.durations());
+        let end = spans
+            .last()
+            .map(|span| span.start + span.duration)
+            .unwrap_or(0.0);
+        let frame_duration = 1.0 / self.frame_rate.max(1.0);
+
+        // Clips are opened when they enter the timeline and closed when they leave it.
+        let mut cursors: Vec<Option<ClipCursor>> = self.edl.clips.iter().map(|_| None).collect();
+        // Letterboxed images of the clips on the timeline, reused from frame to frame. Only the
+        // last two clips are shown, so the images alternate between two buffers.
+        let mut images = [black(canvas), black(canvas)];
+        let mut frame_index = 0_u64;
+        loop {
+            let time = frame_index as f64 * frame_duration;
+            if time >= end {
+                break;
+            }
+
+            let mut layers = 0;
+            let mut transition = None;
+            for (index, (clip, span)) in self.edl.clips.iter().zip(&spans).enumerate() {
+                if time < span.start {
+                    break;
+                }
+                if time >= span.start + span.duration {
+                    cursors[index] = None;
+                    continue;
+                }
+                if cursors[index].is_none() {
+                    cursors[index] = Some(ClipCursor::open(clip, canvas)?);
+                }
+                let source_time = clip.in_point.as_secs_f64() + (time - span.start) * clip.speed;
+                let image = &mut images[layers % 2];
+                match cursors[index].as_mut().unwrap().frame_at(source_time)? {
+                    Some(frame) => letterbox(frame, canvas, image),
+                    None => image.fill(0),
+                }
+                let progress = if span.transition > 0.0 {
+                    ((time - span.start) / span.transition).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                transition = Some((clip.transition, progress));
+                layers += 1;
+            }
+
+            let [first, second] = &mut images;
+            let (previous, next) = if layers % 2 == 0 {
+                (&*first, second)
+            } else {
+                (&*second, first)
+            };
+            match (layers, transition) {
+                (0, _) => next.fill(0),
+                (1, _) | (_, None) | (_, Some((Transition::Cut, _))) => {}
+                (_, Some((Transition::Dissolve(_), progress))) => blend(previous, next, progress),
+                (_, Some((Transition::FadeThroughBlack(_), progress))) if progress < 0.5 => {
+                    next.copy_from_slice(previous);
+                    fade(next, 1.0 - progress * 2.0);
+                }
+                (_, Some((Transition::FadeThroughBlack(_), progress))) => {
+                    fade(next, progress * 2.0 - 1.0)
+                }
+            }
+
+            let mut frame = to_frame(next, canvas);
+            frame.set_pts(
+                Time::from_secs_f64(time)
+                    .with_time_base(encoder.time_base())
+                    .into_value(),
+            );
+            encoder.encode_raw(frame)?;
+            frame_index += 1;
+        }
+
+        encoder.finish()
+    }
+}
+
+/// Decoder of a clip that produces the frame to show at a source time.
+struct ClipCursor {
+    decoder: Decoder,
+    time_base: AvRational,
+    current: Option<(f64, RawFrame)>,
+    pending: Option<(f64, RawFrame)>,
+    exhausted: bool,
+}
+
+impl ClipCursor {
+    /// Open the source of a clip and seek to its in point.
+    fn open(clip: &EdlClip, canvas: (u32, u32)) -> Result<Self> {
+        let mut decoder = DecoderBuilder::new(clip.source.clone())
+            .with_resize(Resize::Fit(canvas.0, canvas.1))
+            .build()?;
+        let in_point_milliseconds = (clip.in_point.as_secs_f64() * 1000.0) as i64;
+        // Seek one second early, such that the seek range (which extends one second either way)
+        // ends at the in point and the reader lands on a keyframe before it.
+        if in_point_milliseconds > 1000 {
+            decoder.seek(in_point_milliseconds - 1000)?;
+        }
+        let time_base = decoder.time_base();
+        Ok(Self {
+            decoder,
+            time_base,
+            current: None,
+            pending: None,
+            exhausted: false,
+        })
+    }
+
+    /// Get the frame to show at a source time: the last frame at or before the time.
+    fn frame_at(&mut self, time: f64) -> Result<Option<&RawFrame>> {
+        loop {
+            match self.pending.as_ref().map(|(timestamp, _)| *timestamp) {
+                Some(timestamp) if timestamp > time => break,
+                Some(_) => self.current = self.pending.take(),
+                None if self.exhausted => break,
+                None => match self.decoder.decode_raw() {
+                    Ok(frame) => {
+                        let timestamp = Time::new(frame.timestamp(), self.time_base).as_secs_f64();
+                        self.pending = Some((timestamp, frame));
+                    }
+                    Err(Error::DecodeExhausted) => self.exhausted = true,
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+        // Before the first frame, show the first frame.
+        Ok(self
+            .current
+            .as_ref()
+            .or(self.pending.as_ref())
+            .map(|(_, frame)| frame))
+    }
+}
+
+/// Black packed RGB24 image.
+fn black((width, height): (u32, u32)) -> Vec<u8> {
+    vec![0; width as usize * height as usize * 3]
+}
+
+/// Center an RGB24 frame on a black packed RGB24 image of the canvas size.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to center.
+/// * `canvas` - Size of the image.
+/// * `image` - Image to draw on, of the canvas size.
+fn letterbox(frame: &RawFrame, (width, height): (u32, u32), image: &mut [u8]) {
+    let frame_width = frame.width().min(width) as usize;
+    let frame_height = frame.height().min(height) as usize;
+    let offset_x = (width as usize - frame_width) / 2;
+    let offset_y = (height as usize - frame_height) / 2;
+    if frame_width < width as usize || frame_height < height as usize {
+        image.fill(0);
+    }
+    let stride = frame.stride(0);
+    let data = frame.data(0);
+    for y in 0..frame_height {
+        let source = &data[y * stride..y * stride + frame_width * 3];
+        let start = ((offset_y + y) * width as usize + offset_x) * 3;
+        image[start..start + frame_width * 3].copy_from_slice(source);
+    }
+}
+
+/// Weight of a blend factor in fixed point, from 0 to 256.
+fn weight(factor: f64) -> u32 {
+    (factor.clamp(0.0, 1.0) * 256.0).round() as u32
+}
+
+/// Blend a packed image into another one.
+///
+/// # Arguments
+///
+/// * `from` - Image at progress `0.0`.
+/// * `to` - Image at progress `1.0`, which receives the blend.
+/// * `progress` - Blend factor.
+fn blend(from: &[u8], to: &mut [u8], progress: f64) {
+    let weight = weight(progress);
+    for (to, &from) in to.iter_mut().zip(from) {
+        *to = ((u32::from(from) * (256 - weight) + u32::from(*to) * weight + 128) >> 8) as u8;
+    }
+}
+
+/// Fade a packed image to black.
+///
+/// # Arguments
+///
+/// * `image` - Image to fade.
+/// * `level` - Brightness, from `0.0` for black to `1.0` for unchanged.
+fn fade(image: &mut [u8], level: f64) {
+    let weight = weight(level);
+    for value in image.iter_mut() {
+        *value = ((u32::from(*value) * weight + 128) >> 8) as u8;
+    }
+}
+
+/// Copy a packed RGB24 image into a frame.
+fn to_frame(image: &[u8], (width, height): (u32, u32)) -> RawFrame {
+    let mut frame = RawFrame::new(AvPixel::RGB24, width, height);
+    let stride = frame.stride(0);
+    let row = width as usize * 3;
+    let data = frame.data_mut(0);
+    for y in 0..height as usize {
+        data[y * stride..y * stride + row].copy_from_slice(&image[y * row..(y + 1) * row]);
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_cuts() {
+        let spans = layout(&[(2.0, 0.0), (3.0, 0.0)]);
+        assert_eq!(spans[0].start, 0.0);
+        assert_eq!(spans[1].start, 2.0);
+        assert_eq!(spans[1].start + spans[1].duration, 5.0);
+    }
+
+    #[test]
+    fn test_layout_transitions_overlap() {
+        let spans = layout(&[(2.0, 1.0), (3.0, 1.0), (1.0, 4.0)]);
+        // The transition of the first clip is ignored.
+        assert_eq!(spans[0].transition, 0.0);
+        assert_eq!(spans[1].start, 1.0);
+        // The transition is limited to the duration of the shortest clip.
+        assert_eq!(spans[2].transition, 1.0);
+        assert_eq!(spans[2].start, 3.0);
+    }
+
+    #[test]
+    fn test_blend() {
+        let blended = |progress| {
+            let mut to = [200, 0];
+            blend(&[0, 100], &mut to, progress);
+            to
+        };
+        assert_eq!(blended(0.0), [0, 100]);
+        assert_eq!(blended(0.5), [100, 50]);
+        assert_eq!(blended(1.0), [200, 0]);
+    }
+
+    #[test]
+    fn test_fade() {
+        let mut image = [0, 100, 255];
+        fade(&mut image, 0.5);
+        assert_eq!(image, [0, 50, 128]);
+        fade(&mut image, 0.0);
+        assert_eq!(image, [0, 0, 0]);
+    }
+}
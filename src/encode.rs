@@ -1,8 +1,13 @@
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use ffmpeg::codec::codec::Codec as AvCodec;
 use ffmpeg::codec::encoder::video::Encoder as AvEncoder;
 use ffmpeg::codec::encoder::video::Video as AvVideo;
 use ffmpeg::codec::flag::Flags as AvCodecFlags;
 use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Capabilities as AvCapabilities;
 use ffmpeg::codec::{Context as AvContext, Id as AvCodecId};
 use ffmpeg::format::flag::Flags as AvFormatFlags;
 use ffmpeg::software::scaling::context::Context as AvScaler;
@@ -14,20 +19,75 @@ use ffmpeg::util::picture::Type as AvFrameType;
 use ffmpeg::Error as AvError;
 use ffmpeg::Rational as AvRational;
 
+use crate::affinity::ThreadPolicy;
+use crate::clock::{MediaClock, SyncAction};
+use crate::decode::CodecStatus;
 use crate::error::Error;
+use crate::events::{Event, EventKind, EventSink};
 use crate::ffi;
+#[cfg(feature = "filter")]
+use crate::filter::Filter;
 #[cfg(feature = "ndarray")]
 use crate::frame::Frame;
 use crate::frame::{PixelFormat, RawFrame, FRAME_PIXEL_FORMAT};
+use crate::hwaccel::{self, HardwareAccelerationDeviceType, HardwareFrames};
+use crate::interrupt::Interrupt;
 use crate::io::private::Write;
 use crate::io::{Writer, WriterBuilder};
+use crate::license::EncoderLicense;
 use crate::location::Location;
+use crate::memory::{MemoryCategory, MemoryReservation};
 use crate::options::Options;
-#[cfg(feature = "ndarray")]
+use crate::packet::Packet;
+use crate::ratecontrol::{EncodePass, EncoderFamily, RateControl, RateControlSettings};
+use crate::resize::ScalerBackend;
+use crate::scaler::Scaler;
+use crate::sidedata::{FrameSideData, SideDataPolicy};
 use crate::time::Time;
+use crate::topology::{PipelineDescription, Stage, StageKind};
+use crate::validate::{PacketTimestamps, TimestampValidator};
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Number of frames an encoder is estimated to keep around for lookahead, reordering and frame
+/// threading, used to account encoder memory.
+const ESTIMATED_ENCODER_FRAMES: usize = 8;
+
+/// Encoders that split frames into the number of slices set by the generic `slices` option.
+const SLICE_ENCODERS: [&str; 9] = [
+    "libx264",
+    "libx264rgb",
+    "h264_nvenc",
+    "hevc_nvenc",
+    "h264_qsv",
+    "hevc_qsv",
+    "h264_vaapi",
+    "hevc_vaapi",
+    "mpeg2video",
+];
+
+/// Names of the private options that set the number of tile columns.
+const TILE_OPTIONS: [&str; 2] = ["tile-columns", "tile_columns"];
+
+/// Maximum number of slices or tile columns set by [`Settings::with_parallelism`]. More slices
+/// cost compression efficiency for little gain.
+const MAX_PARALLEL_UNITS: usize = 8;
+
+/// Maximum number of times receiving a packet is retried when flushing. Encoders do not ask for
+/// more frames after the end of the stream, so this only guards against misbehaving encoders.
+const MAX_FLUSH_RETRIES: u32 = 100;
+
+/// Encoders that pick up rate control changes between frames: `libx264` reconfigures itself when
+/// the rate control fields of the codec context change, and NVENC does so on GPUs that support
+/// dynamic bitrate.
+const RECONFIGURABLE_ENCODERS: [&str; 5] = [
+    "libx264",
+    "libx264rgb",
+    "h264_nvenc",
+    "hevc_nvenc",
+    "av1_nvenc",
+];
+
 /// Builds an [`Encoder`].
 pub struct EncoderBuilder<'a> {
     destination: Location,
@@ -35,6 +95,15 @@ pub struct EncoderBuilder<'a> {
     options: Option<&'a Options>,
     format: Option<&'a str>,
     interleaved: bool,
+    realtime: Option<RealtimeMode>,
+    side_data_policy: SideDataPolicy,
+    strict_timestamps: bool,
+    scaler_backend: ScalerBackend,
+    thread_policy: Option<ThreadPolicy>,
+    interrupt: Option<Interrupt>,
+    clock: Option<Arc<dyn MediaClock>>,
+    #[cfg(feature = "filter")]
+    filter: Option<Filter>,
 }
 
 impl<'a> EncoderBuilder<'a> {
@@ -49,6 +118,15 @@ impl<'a> EncoderBuilder<'a> {
             options: None,
             format: None,
             interleaved: false,
+            realtime: None,
+            side_data_policy: SideDataPolicy::default(),
+            strict_timestamps: false,
+            scaler_backend: ScalerBackend::default(),
+            thread_policy: None,
+            interrupt: None,
+            clock: None,
+            #[cfg(feature = "filter")]
+            filter: None,
         }
     }
 
@@ -79,6 +157,95 @@ impl<'a> EncoderBuilder<'a> {
         self
     }
 
+    /// Encode in real-time mode: when encoding falls behind the timestamps of the frames, frames
+    /// are dropped instead of letting the latency grow. See [`RealtimeMode`].
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Latency limit and drop policy.
+    pub fn with_realtime(mut self, mode: RealtimeMode) -> Self {
+        self.realtime = Some(mode);
+        self
+    }
+
+    /// Set which side data of the frames (like HDR metadata and closed captions) is passed to the
+    /// encoder. By default, all side data is passed.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Side data policy.
+    pub fn with_side_data_policy(mut self, policy: SideDataPolicy) -> Self {
+        self.side_data_policy = policy;
+        self
+    }
+
+    /// Validate the timestamps of every frame and packet, for debugging. Encoding fails with
+    /// [`Error::TimestampViolation`] at the first frame with a presentation timestamp that does
+    /// not increase, and at the first packet with invalid timestamps or with timestamps that were
+    /// rescaled incorrectly to the time base of the output stream.
+    pub fn with_strict_timestamps(mut self) -> Self {
+        self.strict_timestamps = true;
+        self
+    }
+
+    /// Set the backend to use when converting frames to the pixel format of the encoder. By
+    /// default, frames are converted with swscale.
+    ///
+    /// # Arguments
+    ///
+    /// * `scaler_backend` - Scaler backend to use.
+    pub fn with_scaler_backend(mut self, scaler_backend: ScalerBackend) -> Self {
+        self.scaler_backend = scaler_backend;
+        self
+    }
+
+    /// Pass the frames through a [`Filter`] before encoding them. The output of the filter is
+    /// converted to the pixel format and scaled to the size the encoder takes. Frames sent with
+    /// [`Encoder::send_frame`] bypass the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Filter to apply.
+    #[cfg(feature = "filter")]
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Pin the encoding threads to CPUs and set their priority. See [`ThreadPolicy`].
+    ///
+    /// # Arguments
+    ///
+    /// * `thread_policy` - Affinity and priority of the encoding threads.
+    pub fn with_thread_policy(mut self, thread_policy: ThreadPolicy) -> Self {
+        self.thread_policy = Some(thread_policy);
+        self
+    }
+
+    /// Interrupt blocking operations of the writer with a handle, including opening the
+    /// destination. See [`WriterBuilder::with_interrupt`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interrupt` - Interrupt handle.
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Drop frames that are late against a clock, like the master clock of a live mix, except
+    /// frames that must be keyframes. Dropped frames are counted in [`Encoder::drop_stats`]. Early
+    /// frames are encoded as they are, since their timestamps keep them in place. See
+    /// [`WriterBuilder::with_clock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to synchronize against.
+    pub fn with_clock(mut self, clock: Arc<dyn MediaClock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
     /// Build an [`Encoder`].
     pub fn build(self) -> Result<Encoder> {
         let mut writer_builder = WriterBuilder::new(self.destination);
@@ -88,7 +255,35 @@ impl<'a> EncoderBuilder<'a> {
         if let Some(format) = self.format {
             writer_builder = writer_builder.with_format(format);
         }
-        Encoder::from_writer(writer_builder.build()?, self.interleaved, self.settings)
+        if let Some(interrupt) = self.interrupt {
+            writer_builder = writer_builder.with_interrupt(interrupt);
+        }
+        if let Some(clock) = self.clock {
+            writer_builder = writer_builder.with_clock(clock);
+        }
+        let writer = writer_builder.build()?;
+        // The codec threads are started when the encoder is opened.
+        let open = move || {
+            Encoder::from_writer(writer, self.interleaved, self.settings, self.scaler_backend)
+        };
+        let mut encoder = match self.thread_policy.as_ref() {
+            Some(thread_policy) => thread_policy.run(open),
+            None => open(),
+        }?;
+        encoder.realtime = self.realtime.map(RealtimeState::new);
+        encoder.side_data_policy = self.side_data_policy;
+        if self.strict_timestamps {
+            encoder.validator = Some(TimestampValidator::new(StageKind::Encoder));
+        }
+        #[cfg(feature = "filter")]
+        if let Some(mut filter) = self.filter {
+            filter.set_output(
+                encoder.input_format,
+                Some((encoder.scaler_width, encoder.scaler_height)),
+            );
+            encoder.filter = Some(filter);
+        }
+        Ok(encoder)
     }
 }
 
@@ -119,12 +314,33 @@ pub struct Encoder {
     encoder_time_base: AvRational,
     keyframe_interval: u64,
     interleaved: bool,
-    scaler: AvScaler,
+    scaler: Option<Scaler>,
+    scaler_backend: ScalerBackend,
+    input_format: AvPixel,
     scaler_width: u32,
     scaler_height: u32,
     frame_count: u64,
+    packet_count: u64,
     have_written_header: bool,
     have_written_trailer: bool,
+    have_sent_eof: bool,
+    memory: MemoryReservation,
+    unused_options: Vec<String>,
+    settings_snapshot: SettingsSnapshot,
+    realtime: Option<RealtimeState>,
+    drop_stats: FrameDropStats,
+    side_data_policy: SideDataPolicy,
+    validator: Option<TimestampValidator>,
+    /// Statistics file of the first pass of a two-pass encode, if the encoder does not write it.
+    pass_stats: Option<std::fs::File>,
+    events: EventSink,
+    force_keyframe: bool,
+    /// Timestamp of the last frame, to synchronize against the clock of the writer.
+    last_frame_pts: Option<i64>,
+    /// Side data to attach to the next frame.
+    side_data: Vec<FrameSideData>,
+    #[cfg(feature = "filter")]
+    filter: Option<Filter>,
 }
 
 impl Encoder {
@@ -171,26 +387,56 @@ impl Encoder {
     ///
     /// * `frame` - Frame to encode.
     pub fn encode_raw(&mut self, frame: RawFrame) -> Result<()> {
+        #[cfg(feature = "filter")]
+        if let Some(filter) = self.filter.as_mut() {
+            filter.push(&frame, self.encoder_time_base)?;
+            return self.encode_filtered();
+        }
+        self.encode_frame(frame)
+    }
+
+    /// Encode the frames that are available from the filter.
+    #[cfg(feature = "filter")]
+    fn encode_filtered(&mut self) -> Result<()> {
+        while let Some(frame) = self
+            .filter
+            .as_mut()
+            .map(Filter::pull)
+            .transpose()?
+            .flatten()
+        {
+            self.encode_frame(frame)?;
+        }
+        Ok(())
+    }
+
+    /// Encode a single raw frame, after filtering.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    fn encode_frame(&mut self, frame: RawFrame) -> Result<()> {
         if frame.width() != self.scaler_width
             || frame.height() != self.scaler_height
-            || frame.format() != FRAME_PIXEL_FORMAT
+            || frame.format() != self.input_format
         {
             return Err(Error::InvalidFrameFormat);
         }
 
-        // Write file header if we hadn't done that yet.
-        if !self.have_written_header {
-            self.writer.write_header()?;
-            self.have_written_header = true;
+        self.drop_stats.submitted += 1;
+        if self.should_drop(&frame) {
+            self.drop_stats.dropped += 1;
+            return Ok(());
         }
 
-        // Reformat frame to target pixel format.
-        let mut frame = self.scale(frame)?;
-        // Producer key frame every once in a while
-        if self.frame_count % self.keyframe_interval == 0 {
-            frame.set_kind(AvFrameType::I);
+        let frame = self.prepare_frame(frame)?;
+        if let Some(validator) = self.validator.as_mut() {
+            validator.check_frame(
+                self.writer_stream_index,
+                frame.pts(),
+                self.encoder_time_base,
+            )?;
         }
-
         self.encoder
             .send_frame(&frame)
             .map_err(Error::BackendError)?;
@@ -205,6 +451,105 @@ impl Encoder {
         Ok(())
     }
 
+    /// Send a raw frame to the encoder, without receiving or writing packets.
+    ///
+    /// This and the functions below expose the send/receive state machine of the codec, for
+    /// callers that schedule encoding themselves instead of using [`Encoder::encode_raw`]:
+    ///
+    /// ```ignore
+    /// while let CodecStatus::Again = encoder.send_frame(frame.clone())? {
+    ///     while let CodecStatus::Ready(packet) = encoder.receive_packet()? {
+    ///         encoder.write_packet(packet)?;
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    ///
+    /// # Return value
+    ///
+    /// [`CodecStatus::Again`] if the encoder does not accept input until packets are received, in
+    /// which case the frame must be sent again, and [`CodecStatus::Eof`] if the end of the stream
+    /// was already sent.
+    pub fn send_frame(&mut self, frame: RawFrame) -> Result<CodecStatus<()>> {
+        if frame.width() != self.scaler_width
+            || frame.height() != self.scaler_height
+            || frame.format() != self.input_format
+        {
+            return Err(Error::InvalidFrameFormat);
+        }
+        if self.have_sent_eof {
+            return Ok(CodecStatus::Eof);
+        }
+
+        let frame = self.prepare_frame(frame)?;
+        match self.encoder.send_frame(&frame) {
+            Ok(()) => {
+                self.frame_count += 1;
+                // Validated once accepted, since rejected frames are sent again.
+                if let Some(validator) = self.validator.as_mut() {
+                    validator.check_frame(
+                        self.writer_stream_index,
+                        frame.pts(),
+                        self.encoder_time_base,
+                    )?;
+                }
+                Ok(CodecStatus::Ready(()))
+            }
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(CodecStatus::Again),
+            Err(AvError::Eof) => Ok(CodecStatus::Eof),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Signal the end of the stream to the encoder, after which it outputs the packets it still
+    /// holds.
+    ///
+    /// # Return value
+    ///
+    /// [`CodecStatus::Eof`] if the end of the stream was already sent.
+    pub fn send_eof(&mut self) -> Result<CodecStatus<()>> {
+        if self.have_sent_eof {
+            return Ok(CodecStatus::Eof);
+        }
+        self.encoder.send_eof().map_err(Error::BackendError)?;
+        self.have_sent_eof = true;
+        Ok(CodecStatus::Ready(()))
+    }
+
+    /// Receive an encoded packet from the encoder, without writing it. Write it with
+    /// [`Encoder::write_packet`].
+    ///
+    /// # Return value
+    ///
+    /// [`CodecStatus::Again`] if the encoder needs more frames to output a packet, and
+    /// [`CodecStatus::Eof`] if the encoder was drained.
+    pub fn receive_packet(&mut self) -> Result<CodecStatus<Packet>> {
+        let mut packet = AvPacket::empty();
+        match self.encoder.receive_packet(&mut packet) {
+            Ok(()) => Ok(CodecStatus::Ready(Packet::new(
+                packet,
+                self.encoder_time_base,
+            ))),
+            Err(AvError::Other { errno }) if errno == EAGAIN => Ok(CodecStatus::Again),
+            Err(AvError::Eof) => Ok(CodecStatus::Eof),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Write a packet received with [`Encoder::receive_packet`] to the output.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Encoded packet.
+    pub fn write_packet(&mut self, packet: Packet) -> Result<()> {
+        let (mut packet, time_base) = packet.into_inner_parts();
+        packet.rescale_ts(time_base, self.encoder_time_base);
+        self.write(packet)
+    }
+
     /// Signal to the encoder that writing has finished. This will cause any packets in the encoder
     /// to be flushed and a trailer to be written if the container format has one.
     ///
@@ -212,12 +557,239 @@ impl Encoder {
     /// automatically. This will block the caller thread. Any errors cannot be propagated in this
     /// case.
     pub fn finish(&mut self) -> Result<()> {
+        #[cfg(feature = "filter")]
+        if !self.have_written_trailer {
+            if let Some(filter) = self.filter.as_mut() {
+                filter.finish()?;
+                self.encode_filtered()?;
+            }
+        }
+        if (self.have_written_header || self.frame_count > 0) && !self.have_written_trailer {
+            self.have_written_trailer = true;
+            self.drain()?;
+            // Write the header in case the encoder did not output any packets.
+            self.write_header()?;
+            self.writer.write_trailer()?;
+            self.events.emit(EventKind::Eof);
+        }
+
+        Ok(())
+    }
+
+    /// Flush the encoder: signal the end of the stream and receive the packets it still holds,
+    /// without writing them and without finishing the output. Together with
+    /// [`Decoder::drain`](crate::decode::Decoder::drain), this empties the internal queues at a
+    /// deterministic point, like when switching to a new output segment mid-stream.
+    ///
+    /// Encoders that support it (see [`Encoder::supports_flush`]) are then reset to take frames
+    /// again, starting with a keyframe. Other encoders cannot encode after flushing, and
+    /// [`Encoder::send_frame`] returns [`CodecStatus::Eof`]. Frames held by a filter are not
+    /// flushed.
+    ///
+    /// # Return value
+    ///
+    /// The packets the encoder held, to write with [`Encoder::write_packet`] or to another output.
+    pub fn flush(&mut self) -> Result<Vec<Packet>> {
+        self.send_eof()?;
+        let mut packets = Vec::new();
+        let mut retries = 0;
+        loop {
+            match self.receive_packet()? {
+                CodecStatus::Ready(packet) => packets.push(packet),
+                CodecStatus::Again if retries < MAX_FLUSH_RETRIES => retries += 1,
+                CodecStatus::Again | CodecStatus::Eof => break,
+            }
+        }
+        if self.supports_flush() {
+            ffi::flush_encoder(&mut self.encoder);
+            self.have_sent_eof = false;
+            self.force_keyframe = true;
+        }
+        Ok(packets)
+    }
+
+    /// Whether the encoder can take frames again after [`Encoder::flush`].
+    pub fn supports_flush(&self) -> bool {
+        self.encoder
+            .codec()
+            .is_some_and(|codec| codec.capabilities().contains(AvCapabilities::ENCODER_FLUSH))
+    }
+
+    /// Flush the current fragment of a fragmented output (like MP4 with `movflags` set to
+    /// `frag_custom`), and the IO buffer, so that everything encoded so far reaches the destination.
+    /// Frames still in the encoder are not flushed.
+    pub fn flush_fragment(&mut self) -> Result<()> {
         if self.have_written_header && !self.have_written_trailer {
+            self.writer.flush_fragment()?;
+        }
+        Ok(())
+    }
+
+    /// Shut the encoder down gracefully, for instance when a service receives `SIGTERM`: stop
+    /// taking frames, drain the frames still in the encoder until the deadline, and write the
+    /// trailer so that the output is a complete file even if not all frames made it.
+    ///
+    /// After this, [`Encoder::send_frame`] returns [`CodecStatus::Eof`] and
+    /// [`Encoder::encode_raw`] fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - When to stop draining the encoder.
+    ///
+    /// # Return value
+    ///
+    /// How many frames were flushed and how many were dropped.
+    pub fn shutdown(&mut self, deadline: Deadline) -> Result<ShutdownReport> {
+        // The frames still in the filter graph are in flight too.
+        #[cfg(feature = "filter")]
+        if !self.have_written_trailer {
+            if let Some(filter) = self.filter.as_mut() {
+                filter.finish()?;
+                self.encode_filtered()?;
+            }
+        }
+        let in_flight = self.frame_count.saturating_sub(self.packet_count);
+        let packet_count = self.packet_count;
+        let mut report = ShutdownReport {
+            frames_in_flight: in_flight,
+            ..ShutdownReport::default()
+        };
+        if self.have_written_trailer {
+            return Ok(report);
+        }
+
+        self.send_eof()?;
+        let mut drained = false;
+        while !deadline.has_passed() {
+            match self.receive_packet()? {
+                CodecStatus::Ready(packet) => self.write_packet(packet)?,
+                CodecStatus::Again => continue,
+                CodecStatus::Eof => {
+                    drained = true;
+                    break;
+                }
+            }
+        }
+
+        let flushed = self.packet_count - packet_count;
+        report.frames_flushed = flushed.min(in_flight);
+        report.frames_dropped = if drained {
+            0
+        } else {
+            in_flight - report.frames_flushed
+        };
+        if self.have_written_header || self.frame_count > 0 {
             self.have_written_trailer = true;
-            self.flush()?;
+            self.write_header()?;
             self.writer.write_trailer()?;
+            self.events.emit(EventKind::Eof);
+            report.trailer_written = true;
+        }
+        Ok(report)
+    }
+
+    /// Encode frames pulled from a callback at a fixed frame rate, instead of pushing them. This
+    /// suits render loops that produce frames at their own pace (like on vsync): at every tick of
+    /// the frame rate, the encoder asks for the frame to show at that time.
+    ///
+    /// If the callback has no new frame, the previous frame is encoded again. If encoding falls
+    /// behind, the ticks that passed are skipped, leaving a gap in the timestamps rather than
+    /// drifting away from the wall clock. Returns when the callback signals the end.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let stats = encoder.encode_pull(60, |_timestamp| match renderer.take_frame() {
+    ///     Some(frame) => PulledFrame::New(frame),
+    ///     None if renderer.is_running() => PulledFrame::Unchanged,
+    ///     None => PulledFrame::End,
+    /// })?;
+    /// println!("repeated {} frames, skipped {} ticks", stats.frames_repeated, stats.ticks_skipped);
+    /// ```
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frames per second to pull at.
+    /// * `pull` - Callback that gets the timestamp of the tick and returns the frame for it.
+    ///
+    /// # Return value
+    ///
+    /// How many frames were pulled, repeated and skipped.
+    pub fn encode_pull(
+        &mut self,
+        frame_rate: u32,
+        mut pull: impl FnMut(Time) -> PulledFrame,
+    ) -> Result<PullStats> {
+        let frame_rate = frame_rate.max(1) as f64;
+        let start = Instant::now();
+        let mut stats = PullStats::default();
+        let mut last_frame: Option<RawFrame> = None;
+        let mut tick: u64 = 0;
+        loop {
+            let due = start + Duration::from_secs_f64(tick as f64 / frame_rate);
+            let now = Instant::now();
+            if now < due {
+                std::thread::sleep(due - now);
+            } else {
+                // Skip the ticks that passed while encoding was behind.
+                let current_tick = (now.duration_since(start).as_secs_f64() * frame_rate) as u64;
+                if current_tick > tick {
+                    stats.ticks_skipped += current_tick - tick;
+                    tick = current_tick;
+                }
+            }
+
+            let timestamp = Time::from_secs_f64(tick as f64 / frame_rate);
+            let mut frame = match pull(timestamp) {
+                PulledFrame::New(frame) => {
+                    stats.frames_pulled += 1;
+                    frame
+                }
+                PulledFrame::Unchanged => match last_frame.as_ref() {
+                    Some(frame) => {
+                        stats.frames_repeated += 1;
+                        frame.clone()
+                    }
+                    // Nothing to repeat before the first frame.
+                    None => {
+                        tick += 1;
+                        continue;
+                    }
+                },
+                PulledFrame::End => break,
+            };
+            frame.set_pts(
+                timestamp
+                    .aligned_with_rational(self.encoder_time_base)
+                    .into_value(),
+            );
+            self.encode_raw(frame.clone())?;
+            last_frame = Some(frame);
+            tick += 1;
         }
+        Ok(stats)
+    }
 
+    /// Write the container header. This happens automatically before the first encoded packet is
+    /// written, so that the stream parameters that are only known once the encoder is running (like
+    /// the actual pixel format after hardware negotiation, and the extra data) end up in the
+    /// header. Call this to write the header earlier, for example to start a live stream before
+    /// the first frame is available. Does nothing if the header was already written.
+    pub fn write_header(&mut self) -> Result<()> {
+        if self.have_written_header {
+            return Ok(());
+        }
+        let mut writer_stream = self
+            .writer
+            .output
+            .stream_mut(self.writer_stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+        writer_stream.set_parameters(&self.encoder);
+        // The muxer may pick another time base for the stream when writing the header, packets are
+        // rescaled to whatever it picks.
+        writer_stream.set_time_base(self.encoder_time_base);
+        self.writer.write_header()?;
+        self.have_written_header = true;
         Ok(())
     }
 
@@ -227,6 +799,213 @@ impl Encoder {
         self.encoder_time_base
     }
 
+    /// Get the size of the frames the encoder accepts (resolution dimensions): width and height.
+    #[inline(always)]
+    pub fn size(&self) -> (u32, u32) {
+        (self.scaler_width, self.scaler_height)
+    }
+
+    /// Get the backend the encoder converts frames with, see
+    /// [`EncoderBuilder::with_scaler_backend`].
+    pub(crate) fn scaler_backend(&self) -> ScalerBackend {
+        self.scaler_backend
+    }
+
+    /// Get the approximate native memory held by the encoder in bytes. See [`crate::memory`].
+    #[inline]
+    pub fn memory_usage(&self) -> usize {
+        self.memory.bytes()
+    }
+
+    /// Get the kinds of parallelism the encoder supports.
+    pub fn parallelism_support(&self) -> Option<ParallelismSupport> {
+        self.encoder
+            .codec()
+            .map(|codec| ParallelismSupport::of(&codec))
+    }
+
+    /// Get the license of the encoder. See [`LicenseReport`](crate::license::LicenseReport) for
+    /// the license of the build.
+    pub fn license(&self) -> Option<EncoderLicense> {
+        self.encoder
+            .codec()
+            .map(|codec| EncoderLicense::of(codec.name()))
+    }
+
+    /// Describe the stages of the encoder, from the scaler to the writer, with their formats and
+    /// time bases. See [`PipelineDescription`].
+    pub fn describe(&self) -> PipelineDescription {
+        let codec_name = self
+            .encoder
+            .codec()
+            .map(|codec| codec.name().to_string())
+            .unwrap_or_default();
+        let format = self.writer.output.format();
+        let mut writer_stage = Stage::new(StageKind::Writer, format.name())
+            .with_detail(self.writer.destination.to_string());
+        if let Some(stream) = self.writer.output.stream(self.writer_stream_index) {
+            writer_stage = writer_stage.with_time_base(stream.time_base());
+        }
+        let mut description = PipelineDescription::default();
+        if self.scaler.is_some() {
+            description = description.with_stage(
+                Stage::new(StageKind::Scaler, "swscale")
+                    .with_pixel_format(self.encoder.format())
+                    .with_size(self.size()),
+            );
+        }
+        description
+            .with_stage(
+                Stage::new(StageKind::Encoder, codec_name)
+                    .with_pixel_format(self.encoder.format())
+                    .with_size(self.size())
+                    .with_time_base(self.encoder_time_base),
+            )
+            .with_stage(writer_stage)
+    }
+
+    /// Whether the encoder applies rate control changes while encoding. See
+    /// [`Encoder::set_bitrate`].
+    pub fn supports_reconfiguration(&self) -> bool {
+        self.encoder
+            .codec()
+            .is_some_and(|codec| RECONFIGURABLE_ENCODERS.contains(&codec.name()))
+    }
+
+    /// Change the target bitrate while encoding, for example to adapt a live stream to the
+    /// available bandwidth. The encoder applies the change from the next frame on. The encoder
+    /// must have been configured for a target bitrate (like `b` in the options), not for constant
+    /// quality.
+    ///
+    /// Returns [`Error::UnsupportedReconfiguration`] if the encoder does not support changing the
+    /// bitrate. See [`Encoder::supports_reconfiguration`].
+    ///
+    /// # Arguments
+    ///
+    /// * `bitrate` - Target bitrate in bits per second.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// if congested {
+    ///     encoder.set_bitrate(1_000_000)?;
+    ///     encoder.set_max_bitrate(1_200_000, 1_200_000)?;
+    /// }
+    /// ```
+    pub fn set_bitrate(&mut self, bitrate: usize) -> Result<()> {
+        if !self.supports_reconfiguration() {
+            return Err(Error::UnsupportedReconfiguration);
+        }
+        self.encoder.set_bit_rate(bitrate);
+        Ok(())
+    }
+
+    /// Change the maximum bitrate and the rate control buffer size while encoding. The encoder
+    /// applies the change from the next frame on.
+    ///
+    /// Returns [`Error::UnsupportedReconfiguration`] if the encoder does not support changing the
+    /// bitrate. See [`Encoder::supports_reconfiguration`].
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bitrate` - Maximum bitrate in bits per second.
+    /// * `buffer_size` - Size of the rate control buffer in bits.
+    pub fn set_max_bitrate(&mut self, max_bitrate: usize, buffer_size: usize) -> Result<()> {
+        if !self.supports_reconfiguration() {
+            return Err(Error::UnsupportedReconfiguration);
+        }
+        self.encoder.set_max_bit_rate(max_bitrate);
+        ffi::set_encoder_buffer_size(&mut self.encoder, buffer_size);
+        Ok(())
+    }
+
+    /// Change the keyframe interval while encoding. The next keyframe is forced at the next frame
+    /// number that is a multiple of the new interval. This works with every encoder, because the
+    /// keyframes are forced by the encoder wrapper.
+    ///
+    /// # Arguments
+    ///
+    /// * `keyframe_interval` - Keyframe interval in frames. Zero is treated as one.
+    pub fn set_keyframe_interval(&mut self, keyframe_interval: u64) {
+        self.keyframe_interval = keyframe_interval.max(1);
+        self.encoder
+            .set_gop(u32::try_from(self.keyframe_interval).unwrap_or(u32::MAX));
+    }
+
+    /// Subscribe to the events of the encoder: an [`EventKind::PacketWritten`] for every packet
+    /// written to the output, and [`EventKind::Eof`] once the trailer is written. Can be called
+    /// multiple times to subscribe multiple receivers. Events are never waited for: a receiver that
+    /// falls behind misses events and gets an [`EventKind::Lagged`] instead.
+    pub fn events(&self) -> Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Get the event sink of the encoder, to emit the events of a pipeline around it into the same
+    /// stream.
+    pub(crate) fn event_sink(&self) -> &EventSink {
+        &self.events
+    }
+
+    /// Make the next frame that is encoded a keyframe, like at a scene change or at a segment
+    /// boundary that the application decides on. Encoders that support it (like x264, x265 and
+    /// NVENC) make it an IDR frame that a segment can start at. The keyframe interval continues
+    /// as before, so keyframes are still forced at every multiple of it.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// for (frame, time) in frames {
+    ///     if scene_changed(&frame) {
+    ///         encoder.force_keyframe();
+    ///     }
+    ///     encoder.encode(&frame, time)?;
+    /// }
+    /// ```
+    pub fn force_keyframe(&mut self) {
+        self.force_keyframe = true;
+    }
+
+    /// Attach side data, like HDR metadata or closed captions, to the next frame that is encoded.
+    /// Frames passed to [`Encoder::encode_raw`] keep their own side data as well, so side data
+    /// can also be attached to them directly with [`FrameSideData::attach`]. Side data is subject
+    /// to the side data policy (see [`EncoderBuilder::with_side_data_policy`]).
+    ///
+    /// # Arguments
+    ///
+    /// * `side_data` - Side data to attach.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// encoder.attach_side_data([FrameSideData::ContentLightLevel(ContentLightLevel {
+    ///     max_content: 1000,
+    ///     max_frame_average: 400,
+    /// })]);
+    /// encoder.encode(&frame, time)?;
+    /// ```
+    pub fn attach_side_data(&mut self, side_data: impl IntoIterator<Item = FrameSideData>) {
+        self.side_data.extend(side_data);
+    }
+
+    /// Get the frame drop statistics of real-time mode. See [`EncoderBuilder::with_realtime`].
+    pub fn drop_stats(&self) -> FrameDropStats {
+        self.drop_stats
+    }
+
+    /// Get the keys of the encoder options in [`Settings`] that the encoder did not use, like
+    /// options with typos ("perset") or options of another encoder. A warning is logged for each.
+    pub fn unused_options(&self) -> &[String] {
+        &self.unused_options
+    }
+
+    /// Get a snapshot of the settings of the opened encoder, to reproduce the encode later with
+    /// [`Settings::from_snapshot`]. Unlike [`Settings::snapshot`], the options are the options in
+    /// effect after opening the encoder, including the defaults that the encoder chose, and the
+    /// number of threads is the number that the encoder resolved.
+    pub fn settings_snapshot(&self) -> &SettingsSnapshot {
+        &self.settings_snapshot
+    }
+
     /// Create an encoder from a `FileWriter` instance.
     ///
     /// # Arguments
@@ -234,7 +1013,13 @@ impl Encoder {
     /// * `writer` - [`Writer`] to create encoder from.
     /// * `interleaved` - Whether or not to use interleaved write.
     /// * `settings` - Encoder settings to use.
-    fn from_writer(mut writer: Writer, interleaved: bool, settings: Settings) -> Result<Self> {
+    /// * `scaler_backend` - Backend to use when converting frames.
+    fn from_writer(
+        mut writer: Writer,
+        interleaved: bool,
+        settings: Settings,
+        scaler_backend: ScalerBackend,
+    ) -> Result<Self> {
         let global_header = writer
             .output
             .format()
@@ -255,6 +1040,10 @@ impl Encoder {
             encoder_context.set_flags(AvCodecFlags::GLOBAL_HEADER);
         }
 
+        if let Some(hardware_frames) = settings.hardware_frames.as_ref() {
+            hardware_frames.apply_to(&mut encoder_context);
+        }
+
         let mut encoder = encoder_context.encoder().video()?;
         settings.apply_to(&mut encoder);
 
@@ -262,21 +1051,67 @@ impl Encoder {
         // that we should never get in trouble.
         encoder.set_time_base(TIME_BASE);
 
-        let encoder = encoder.open_with(settings.options().to_dict())?;
+        // Encoders without a statistics file of their own take the statistics of the first pass
+        // as a string, and output them packet by packet.
+        let pass_stats = match settings.rate_control.stats_file(settings.encoder_family()) {
+            Some((path, EncodePass::First)) => Some(
+                std::fs::File::create(path).map_err(|err| stats_file_error(path, "create", err))?,
+            ),
+            Some((path, EncodePass::Second)) => {
+                let stats = std::fs::read_to_string(path)
+                    .map_err(|err| stats_file_error(path, "read", err))?;
+                ffi::set_encoder_stats_in(&mut encoder, &stats)?;
+                None
+            }
+            None => None,
+        };
+
+        let (encoder, unused_options) =
+            ffi::open_video_encoder(encoder, settings.options()?.to_dict())?;
+        let unused_options = Options::unused_keys(unused_options, "encoder");
         let encoder_time_base = ffi::get_encoder_time_base(&encoder);
+        let mut settings_snapshot = settings.snapshot();
+        let mut effective_options = ffi::encoder_effective_options(&encoder);
+        // Encoders like `libx264` resolve an automatic number of threads themselves, and leave it
+        // at zero in the codec context.
+        if effective_options
+            .last()
+            .is_some_and(|(key, value)| key == "threads" && value == "0")
+        {
+            effective_options.pop();
+            effective_options.extend(
+                settings_snapshot
+                    .options
+                    .iter()
+                    .find(|(key, _)| key == "threads")
+                    .cloned(),
+            );
+        }
+        settings_snapshot.options = effective_options;
 
         writer_stream.set_parameters(&encoder);
 
         let scaler_width = encoder.width();
         let scaler_height = encoder.height();
-        let scaler = AvScaler::get(
-            FRAME_PIXEL_FORMAT,
-            scaler_width,
-            scaler_height,
-            encoder.format(),
-            scaler_width,
-            scaler_height,
-            AvScalerFlags::empty(),
+        // Hardware frames go to the encoder as they are.
+        let (scaler, input_format, memory_format) = match settings.hardware_frames.as_ref() {
+            Some(hardware_frames) => (None, hardware_frames.format(), hardware_frames.sw_format()),
+            None => (
+                Some(Scaler::get(
+                    scaler_backend,
+                    (FRAME_PIXEL_FORMAT, scaler_width, scaler_height),
+                    (encoder.format(), scaler_width, scaler_height),
+                    AvScalerFlags::empty(),
+                )?),
+                FRAME_PIXEL_FORMAT,
+                encoder.format(),
+            ),
+        };
+
+        let memory = MemoryReservation::new(
+            MemoryCategory::Encoders,
+            ffi::image_buffer_size(memory_format, scaler_width, scaler_height)
+                * ESTIMATED_ENCODER_FRAMES,
         )?;
 
         Ok(Self {
@@ -287,27 +1122,142 @@ impl Encoder {
             keyframe_interval: settings.keyframe_interval,
             interleaved,
             scaler,
+            scaler_backend,
+            input_format,
             scaler_width,
             scaler_height,
             frame_count: 0,
+            packet_count: 0,
             have_written_header: false,
             have_written_trailer: false,
+            have_sent_eof: false,
+            memory,
+            unused_options,
+            settings_snapshot,
+            realtime: None,
+            drop_stats: FrameDropStats::default(),
+            side_data_policy: SideDataPolicy::default(),
+            validator: None,
+            pass_stats,
+            events: EventSink::new(),
+            force_keyframe: false,
+            last_frame_pts: None,
+            side_data: Vec::new(),
+            #[cfg(feature = "filter")]
+            filter: None,
         })
     }
 
-    /// Apply scaling (or pixel reformatting in this case) on the frame with the scaler we
+    /// Append the statistics the encoder output to the statistics file of the first pass of a
+    /// two-pass encode.
+    fn write_pass_stats(&mut self) -> Result<()> {
+        use std::io::Write as _;
+
+        let Some(file) = self.pass_stats.as_mut() else {
+            return Ok(());
+        };
+        if let Some(stats) = ffi::encoder_stats_out(&self.encoder) {
+            if let Err(err) = file.write_all(stats.as_bytes()) {
+                tracing::error!("failed to write first pass statistics: {err}");
+                return Err(Error::BackendError(AvError::External));
+            }
+        }
+        Ok(())
+    }
+
+    /// Prepare a frame for the encoder: convert it to the encoder pixel format, attach side data
+    /// and force keyframes at the keyframe interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to prepare.
+    fn prepare_frame(&mut self, frame: RawFrame) -> Result<RawFrame> {
+        // Reformat frame to target pixel format.
+        let mut frame = self.scale(frame)?;
+        for side_data in self.side_data.drain(..) {
+            side_data.attach(&mut frame)?;
+        }
+        if !self.side_data_policy.is_copy_all() {
+            ffi::retain_frame_side_data(&mut frame, |kind| {
+                self.side_data_policy.keeps_frame_side_data(kind)
+            });
+        }
+        // Producer key frame every once in a while
+        if self.is_keyframe_due() {
+            frame.set_kind(AvFrameType::I);
+            self.force_keyframe = false;
+        }
+        Ok(frame)
+    }
+
+    /// Whether the next frame must be a keyframe, because it is at the keyframe interval or a
+    /// keyframe was forced.
+    fn is_keyframe_due(&self) -> bool {
+        self.force_keyframe || self.frame_count % self.keyframe_interval == 0
+    }
+
+    /// Decide whether to drop a frame because it is late against the clock, or in real-time mode
+    /// because encoding fell behind. Frames that are due to be keyframes are never dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    fn should_drop(&mut self, frame: &RawFrame) -> bool {
+        if self.is_late(frame) {
+            return true;
+        }
+        let is_keyframe = self.is_keyframe_due();
+        let Some(realtime) = self.realtime.as_mut() else {
+            return false;
+        };
+        let Some(lag) = realtime.lag(frame.pts(), self.encoder_time_base) else {
+            return false;
+        };
+        self.drop_stats.max_lag = self.drop_stats.max_lag.max(lag);
+        realtime.should_drop(lag, is_keyframe)
+    }
+
+    /// Whether a frame is late against the clock of the writer, and must be dropped. Frames that
+    /// must be keyframes are never late. See [`EncoderBuilder::with_clock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    fn is_late(&mut self, frame: &RawFrame) -> bool {
+        let Some(clock) = self.writer.clock.clone() else {
+            return false;
+        };
+        let Some(pts) = frame.pts() else {
+            return false;
+        };
+        // The frame duration follows from the timestamps, since the frame rate of the source is
+        // not known to the encoder.
+        let frame_duration = self
+            .last_frame_pts
+            .replace(pts)
+            .map_or(0, |last_pts| (pts - last_pts).max(0));
+        !self.is_keyframe_due()
+            && clock.sync(
+                Time::new(Some(pts), self.encoder_time_base),
+                Time::new(Some(frame_duration), self.encoder_time_base),
+            ) == SyncAction::Drop
+    }
+
+    /// Apply scaling (or pixel reformatting in this case) on the frame with the scaler we
     /// initialized earlier.
     ///
     /// # Arguments
     ///
     /// * `frame` - Frame to rescale.
     fn scale(&mut self, frame: RawFrame) -> Result<RawFrame> {
+        let Some(scaler) = self.scaler.as_mut() else {
+            return Ok(frame);
+        };
         let mut frame_scaled = RawFrame::empty();
-        self.scaler
-            .run(&frame, &mut frame_scaled)
-            .map_err(Error::BackendError)?;
-        // Copy over PTS from old frame.
+        scaler.run(&frame, &mut frame_scaled)?;
+        // Copy over PTS and side data from old frame.
         frame_scaled.set_pts(frame.pts());
+        ffi::copy_frame_side_data(&frame, &mut frame_scaled)?;
 
         Ok(frame_scaled)
     }
@@ -333,32 +1283,54 @@ impl Encoder {
             .time_base()
     }
 
-    /// Write encoded packet to output stream.
+    /// Write encoded packet to output stream, after the header if that was not written yet.
     ///
     /// # Arguments
     ///
     /// * `packet` - Encoded packet.
     fn write(&mut self, mut packet: AvPacket) -> Result<()> {
+        self.write_pass_stats()?;
+        self.write_header()?;
         packet.set_stream(self.writer_stream_index);
         packet.set_position(-1);
-        packet.rescale_ts(self.encoder_time_base, self.stream_time_base());
+        let source = PacketTimestamps::of(&packet);
+        let stream_time_base = self.stream_time_base();
+        packet.rescale_ts(self.encoder_time_base, stream_time_base);
+        if let Some(validator) = self.validator.as_mut() {
+            validator.check_packet(self.writer_stream_index, source, self.encoder_time_base)?;
+            validator.check_rescaled(
+                self.writer_stream_index,
+                source,
+                self.encoder_time_base,
+                PacketTimestamps::of(&packet),
+                stream_time_base,
+            )?;
+        }
+        // Writing takes the data of the packet.
+        let written = EventKind::PacketWritten {
+            stream_index: self.writer_stream_index,
+            size: packet.size(),
+            timestamp: Time::new(packet.pts(), stream_time_base),
+        };
         if self.interleaved {
             self.writer.write_interleaved(&mut packet)?;
         } else {
             self.writer.write(&mut packet)?;
         };
+        self.packet_count += 1;
+        self.events.emit(written);
 
         Ok(())
     }
 
-    /// Flush the encoder, drain any packets that still need processing.
-    fn flush(&mut self) -> Result<()> {
+    /// Drain the encoder and write the packets that still need processing.
+    fn drain(&mut self) -> Result<()> {
         // Maximum number of invocations to `encoder_receive_packet`
         // to drain the items still on the queue before giving up.
         const MAX_DRAIN_ITERATIONS: u32 = 100;
 
-        // Notify the encoder that the last frame has been sent.
-        self.encoder.send_eof()?;
+        // Notify the encoder that the last frame has been sent, unless the caller did already.
+        self.send_eof()?;
 
         // We need to drain the items still in the encoders queue.
         for _ in 0..MAX_DRAIN_ITERATIONS {
@@ -368,6 +1340,8 @@ impl Encoder {
                 Err(_) => break,
             }
         }
+        // Encoders output the statistics of the whole stream when drained.
+        self.write_pass_stats()?;
 
         Ok(())
     }
@@ -379,13 +1353,412 @@ impl Drop for Encoder {
     }
 }
 
+/// Encodes video frames into the packets of one output stream, for helpers that produce a video
+/// stream next to other streams in the same writer. The counterpart of
+/// [`crate::audio::AudioStreamEncoder`].
+///
+/// Frames of any pixel format and size are scaled to the pixel format and size of the settings.
+/// Packets have the time base of the encoder and no stream index; the caller maps them to the
+/// output stream.
+pub(crate) struct VideoStreamEncoder {
+    encoder: AvEncoder,
+    time_base: AvRational,
+    keyframe_interval: u64,
+    scaler: Option<AvScaler>,
+    frame_count: u64,
+    draining: bool,
+}
+
+impl VideoStreamEncoder {
+    /// Create an encoder for a stream of a writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer` - Writer the packets go to.
+    /// * `settings` - Encoding settings. Hardware frames are not supported.
+    /// * `frame_rate` - Frame rate of the stream.
+    pub(crate) fn new(
+        writer: &Writer,
+        settings: &Settings,
+        frame_rate: AvRational,
+    ) -> Result<Self> {
+        let mut encoder_context = match settings.codec() {
+            Some(codec) => ffi::codec_context_as(&codec)?,
+            None => AvContext::new(),
+        };
+        if writer
+            .output
+            .format()
+            .flags()
+            .contains(AvFormatFlags::GLOBAL_HEADER)
+        {
+            encoder_context.set_flags(AvCodecFlags::GLOBAL_HEADER);
+        }
+        let mut encoder = encoder_context.encoder().video()?;
+        settings.apply_to(&mut encoder);
+        encoder.set_frame_rate(Some(frame_rate));
+        let time_base = frame_rate.invert();
+        encoder.set_time_base(time_base);
+        let (encoder, unused_options) =
+            ffi::open_video_encoder(encoder, settings.options()?.to_dict())?;
+        Options::unused_keys(unused_options, "encoder");
+        let time_base = ffi::get_encoder_time_base(&encoder);
+
+        Ok(Self {
+            encoder,
+            time_base,
+            keyframe_interval: settings.keyframe_interval,
+            scaler: None,
+            frame_count: 0,
+            draining: false,
+        })
+    }
+
+    /// Codec parameters of the output stream.
+    pub(crate) fn parameters(&self) -> ffmpeg::codec::Parameters {
+        ffmpeg::codec::Parameters::from(&self.encoder)
+    }
+
+    /// Time base of the packets, and of the timestamps of the frames to encode.
+    pub(crate) fn time_base(&self) -> AvRational {
+        self.time_base
+    }
+
+    /// Encode a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode, with a timestamp in the time base of the encoder.
+    ///
+    /// # Return value
+    ///
+    /// Packets that became available.
+    pub(crate) fn encode(&mut self, frame: &RawFrame) -> Result<Vec<Packet>> {
+        let scaler = match self.scaler.as_mut() {
+            Some(scaler) => scaler,
+            None => self.scaler.insert(AvScaler::get(
+                frame.format(),
+                frame.width(),
+                frame.height(),
+                self.encoder.format(),
+                self.encoder.width(),
+                self.encoder.height(),
+                AvScalerFlags::BICUBIC,
+            )?),
+        };
+        let mut scaled = RawFrame::empty();
+        scaler.run(frame, &mut scaled)?;
+        scaled.set_pts(frame.pts());
+        if self.frame_count % self.keyframe_interval == 0 {
+            scaled.set_kind(AvFrameType::I);
+        }
+        self.frame_count += 1;
+        self.encoder.send_frame(&scaled)?;
+        self.receive_packets()
+    }
+
+    /// Flush the encoder at the end of the stream.
+    ///
+    /// # Return value
+    ///
+    /// The remaining packets.
+    pub(crate) fn finish(&mut self) -> Result<Vec<Packet>> {
+        if self.draining {
+            return Ok(Vec::new());
+        }
+        self.encoder.send_eof()?;
+        self.draining = true;
+        self.receive_packets()
+    }
+
+    /// Pull the packets that are available from the encoder.
+    fn receive_packets(&mut self) -> Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+        loop {
+            let mut packet = AvPacket::empty();
+            match self.encoder.receive_packet(&mut packet) {
+                Ok(()) => packets.push(Packet::new(packet, self.time_base)),
+                Err(AvError::Eof) => break,
+                Err(AvError::Other { errno }) if errno == EAGAIN => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(packets)
+    }
+}
+
+unsafe impl Send for VideoStreamEncoder {}
+unsafe impl Sync for VideoStreamEncoder {}
+
+/// Until when [`Encoder::shutdown`], [`Decoder::shutdown`](crate::decode::Decoder::shutdown) and
+/// [`Transcoder::shutdown`](crate::transcode::Transcoder::shutdown) wait for in-flight frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    /// Wait until all frames are flushed.
+    Unbounded,
+    /// Stop waiting at an instant. Frames that are not flushed by then are dropped.
+    At(Instant),
+}
+
+impl Deadline {
+    /// Create a deadline some time from now.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Time from now.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline::At(Instant::now() + timeout)
+    }
+
+    /// Whether the deadline has passed.
+    pub fn has_passed(&self) -> bool {
+        match self {
+            Deadline::Unbounded => false,
+            Deadline::At(instant) => Instant::now() >= *instant,
+        }
+    }
+}
+
+/// Accounting of the frames that were in flight when an encoder, a decoder or a transcoder was
+/// shut down with [`Encoder::shutdown`] and the like.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Number of frames that had been sent to the codecs but not written or decoded yet.
+    pub frames_in_flight: u64,
+    /// Number of in-flight frames that were flushed out of the codecs before the deadline.
+    pub frames_flushed: u64,
+    /// Number of in-flight frames that were dropped because the deadline passed.
+    pub frames_dropped: u64,
+    /// Whether the trailer was written, so the output is complete. Always `false` for a decoder.
+    pub trailer_written: bool,
+}
+
+/// Frame returned by the callback of [`Encoder::encode_pull`].
+#[derive(Debug)]
+pub enum PulledFrame {
+    /// New frame to encode.
+    New(RawFrame),
+    /// No new frame since the last tick, encode the previous frame again.
+    Unchanged,
+    /// Stop encoding.
+    End,
+}
+
+/// Accounting of the frames of [`Encoder::encode_pull`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PullStats {
+    /// Number of new frames pulled and encoded.
+    pub frames_pulled: u64,
+    /// Number of times the previous frame was encoded again because there was no new frame.
+    pub frames_repeated: u64,
+    /// Number of ticks that were skipped because encoding fell behind.
+    pub ticks_skipped: u64,
+}
+
+/// Which frames an encoder in real-time mode drops while it is behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FrameDropPolicy {
+    /// Drop every frame until encoding has caught up.
+    DropWhileBehind,
+    /// Drop every other frame until encoding has caught up, which halves the frame rate instead of
+    /// freezing the video.
+    DropAlternate,
+}
+
+/// Real-time mode of an encoder. See [`EncoderBuilder::with_realtime`].
+///
+/// The lag of a frame is how much later it is submitted than its timestamp says, relative to the
+/// first frame. While the lag exceeds the maximum latency, incoming frames are dropped according
+/// to the policy. Frames are dropped before they are encoded, so no frame that other frames
+/// reference is lost, and frames that are due to be keyframes are always encoded. Frames without
+/// a timestamp are never dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut encoder = EncoderBuilder::new(Url::parse("rtmp://live.example.com/app/key")?, settings)
+///     .with_format("flv")
+///     .with_realtime(RealtimeMode::new(Duration::from_millis(200)))
+///     .build()?;
+/// loop {
+///     let (timestamp, frame) = capture.next()?;
+///     encoder.encode(&frame, timestamp)?;
+///     let stats = encoder.drop_stats();
+///     println!("dropped {} of {} frames", stats.dropped, stats.submitted);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RealtimeMode {
+    max_latency: Duration,
+    policy: FrameDropPolicy,
+}
+
+impl RealtimeMode {
+    /// Create a real-time mode that drops every frame while behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_latency` - Lag after which frames are dropped.
+    pub fn new(max_latency: Duration) -> Self {
+        Self {
+            max_latency,
+            policy: FrameDropPolicy::DropWhileBehind,
+        }
+    }
+
+    /// Set which frames are dropped while behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Drop policy.
+    pub fn with_policy(mut self, policy: FrameDropPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+}
+
+/// Frame drop statistics of an encoder in real-time mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameDropStats {
+    /// Number of frames submitted to the encoder.
+    pub submitted: u64,
+    /// Number of frames dropped because encoding fell behind.
+    pub dropped: u64,
+    /// Largest lag of a submitted frame.
+    pub max_lag: Duration,
+}
+
+/// State of real-time mode: the wall clock time and timestamp of the first frame, against which
+/// the lag of later frames is measured.
+struct RealtimeState {
+    mode: RealtimeMode,
+    origin: Option<(Instant, i64)>,
+    dropped_last: bool,
+}
+
+impl RealtimeState {
+    fn new(mode: RealtimeMode) -> Self {
+        Self {
+            mode,
+            origin: None,
+            dropped_last: false,
+        }
+    }
+
+    /// Get the lag of a frame, or `None` if it has no timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `pts` - Timestamp of the frame.
+    /// * `time_base` - Time base of the timestamp.
+    fn lag(&mut self, pts: Option<i64>, time_base: AvRational) -> Option<Duration> {
+        self.lag_at(Instant::now(), pts, time_base)
+    }
+
+    /// Get the lag of a frame at a point in time, or `None` if it has no timestamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Time at which the frame is submitted.
+    /// * `pts` - Timestamp of the frame.
+    /// * `time_base` - Time base of the timestamp.
+    fn lag_at(
+        &mut self,
+        now: Instant,
+        pts: Option<i64>,
+        time_base: AvRational,
+    ) -> Option<Duration> {
+        let pts = pts?;
+        let (origin_instant, origin_pts) = *self.origin.get_or_insert((now, pts));
+        let media_time = Time::new(Some(pts - origin_pts), time_base).as_secs_f64();
+        let wall_time = now.duration_since(origin_instant).as_secs_f64();
+        Some(Duration::from_secs_f64((wall_time - media_time).max(0.0)))
+    }
+
+    /// Decide whether to drop a frame with a lag. Frames that are due to be keyframes are never
+    /// dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `lag` - Lag of the frame, see [`RealtimeState::lag`].
+    /// * `is_keyframe` - Whether the frame is due to be a keyframe.
+    fn should_drop(&mut self, lag: Duration, is_keyframe: bool) -> bool {
+        let drop = lag > self.mode.max_latency
+            && !is_keyframe
+            && match self.mode.policy {
+                FrameDropPolicy::DropWhileBehind => true,
+                FrameDropPolicy::DropAlternate => !self.dropped_last,
+            };
+        self.dropped_last = drop;
+        drop
+    }
+}
+
+/// What to optimize the parallelism of an encoder for. See [`Settings::with_parallelism`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParallelismHint {
+    /// Split frames into slices or tiles that are encoded in parallel, which does not add delay.
+    /// For real-time encoding.
+    LowLatency,
+    /// Encode multiple frames in parallel, which adds a frame of delay per thread but compresses
+    /// better. For offline encoding.
+    Throughput,
+}
+
+/// Kinds of parallelism that a codec supports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParallelismSupport {
+    /// Whether the codec can process the slices of a frame in parallel.
+    pub slice_threads: bool,
+    /// Whether the codec can process multiple frames in parallel.
+    pub frame_threads: bool,
+    /// Whether the encoder can split frames into slices with the `slices` option.
+    pub slices: bool,
+    /// Name of the private option that sets the number of tile columns, if the encoder has one.
+    pub tile_option: Option<&'static str>,
+}
+
+impl ParallelismSupport {
+    /// Get the kinds of parallelism that a codec supports. Works for decoders as well as encoders,
+    /// although `slices` and `tile_option` only apply to encoders.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec` - Codec to check.
+    pub fn of(codec: &AvCodec) -> Self {
+        let capabilities = codec.capabilities();
+        let slice_threads = capabilities.contains(AvCapabilities::SLICE_THREADS);
+        Self {
+            slice_threads,
+            frame_threads: capabilities.contains(AvCapabilities::FRAME_THREADS),
+            slices: codec.is_encoder() && (slice_threads || SLICE_ENCODERS.contains(&codec.name())),
+            tile_option: TILE_OPTIONS
+                .into_iter()
+                .find(|name| ffi::codec_has_private_option(codec, name)),
+        }
+    }
+
+    /// Whether the encoder supports slices or tiles.
+    pub fn has_slices_or_tiles(&self) -> bool {
+        self.slices || self.tile_option.is_some()
+    }
+}
+
 /// Holds a logical combination of encoder settings.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Settings {
     width: u32,
     height: u32,
     pixel_format: AvPixel,
+    /// Codec to encode with, or `None` for H.264 with the encoder picked from the hardware
+    /// acceleration settings.
+    codec_id: Option<AvCodecId>,
     keyframe_interval: u64,
+    hardware_acceleration_device_type: Option<HardwareAccelerationDeviceType>,
+    hardware_frames: Option<HardwareFrames>,
+    rate_control: RateControlSettings,
+    max_b_frames: Option<u32>,
+    closed_gop: bool,
     options: Options,
 }
 
@@ -411,7 +1784,13 @@ impl Settings {
             width: width as u32,
             height: height as u32,
             pixel_format: AvPixel::YUV420P,
+            codec_id: None,
             keyframe_interval: Self::KEY_FRAME_INTERVAL,
+            hardware_acceleration_device_type: None,
+            hardware_frames: None,
+            rate_control: RateControlSettings::default(),
+            max_b_frames: None,
+            closed_gop: false,
             options,
         }
     }
@@ -440,22 +1819,423 @@ impl Settings {
             width: width as u32,
             height: height as u32,
             pixel_format,
+            codec_id: None,
             keyframe_interval: Self::KEY_FRAME_INTERVAL,
+            hardware_acceleration_device_type: None,
+            hardware_frames: None,
+            rate_control: RateControlSettings::default(),
+            max_b_frames: None,
+            closed_gop: false,
             options,
         }
     }
 
-    /// Set the keyframe interval.
+    /// Create encoder settings for lossless PNG images in RGB24, like for writing an
+    /// [image sequence](crate::location::Location::ImageSequence).
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the images.
+    /// * `height` - The height of the images.
+    pub fn preset_png(width: usize, height: usize) -> Settings {
+        Self {
+            width: width as u32,
+            height: height as u32,
+            pixel_format: AvPixel::RGB24,
+            codec_id: Some(AvCodecId::PNG),
+            // Every image stands on its own.
+            keyframe_interval: 1,
+            hardware_acceleration_device_type: None,
+            hardware_frames: None,
+            rate_control: RateControlSettings::default(),
+            max_b_frames: None,
+            closed_gop: false,
+            options: Options::default(),
+        }
+    }
+
+    /// Create encoder settings for JPEG images, like for writing an
+    /// [image sequence](crate::location::Location::ImageSequence).
+    ///
+    /// # Arguments
+    ///
+    /// * `width` - The width of the images.
+    /// * `height` - The height of the images.
+    /// * `qscale` - Quantizer scale from 2 (best quality) to 31 (smallest size). Values out of
+    ///   range are clamped.
+    pub fn preset_jpeg(width: usize, height: usize, qscale: u32) -> Settings {
+        let mut options = Options::default();
+        options.set("flags", "+qscale");
+        options.set(
+            "global_quality",
+            &(qscale.clamp(2, 31) * ffmpeg::ffi::FF_QP2LAMBDA as u32).to_string(),
+        );
+        Self {
+            width: width as u32,
+            height: height as u32,
+            // The MJPEG encoder takes full range YUV.
+            pixel_format: AvPixel::YUVJ420P,
+            codec_id: Some(AvCodecId::MJPEG),
+            keyframe_interval: 1,
+            hardware_acceleration_device_type: None,
+            hardware_frames: None,
+            rate_control: RateControlSettings::default(),
+            max_b_frames: None,
+            closed_gop: false,
+            options,
+        }
+    }
+
+    /// Set the keyframe interval, which is also the GOP size of the encoder. Keyframes are forced
+    /// at every multiple of the interval, so segments of a fixed duration start with a keyframe.
+    /// Additional keyframes can be forced with [`Encoder::force_keyframe`].
     pub fn set_keyframe_interval(&mut self, keyframe_interval: u64) {
         self.keyframe_interval = keyframe_interval;
     }
 
     /// Set the keyframe interval.
+    ///
+    /// See [`Settings::set_keyframe_interval`] for more information.
     pub fn with_keyframe_interval(mut self, keyframe_interval: u64) -> Self {
         self.set_keyframe_interval(keyframe_interval);
         self
     }
 
+    /// Set the maximum number of consecutive B-frames. B-frames reference later frames, so they
+    /// delay the output of the encoder. Zero disables B-frames, for the lowest latency.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_b_frames` - Maximum number of consecutive B-frames.
+    pub fn set_max_b_frames(&mut self, max_b_frames: u32) {
+        self.max_b_frames = Some(max_b_frames);
+    }
+
+    /// Set the maximum number of consecutive B-frames.
+    ///
+    /// See [`Settings::set_max_b_frames`] for more information.
+    pub fn with_max_b_frames(mut self, max_b_frames: u32) -> Self {
+        self.set_max_b_frames(max_b_frames);
+        self
+    }
+
+    /// Close every GOP, so no frame references a frame before the keyframe that starts its GOP.
+    /// Segments that start at a keyframe (like the segments of HLS and the fragments of fragmented
+    /// MP4) can then be decoded on their own.
+    pub fn set_closed_gop(&mut self) {
+        self.closed_gop = true;
+    }
+
+    /// Close every GOP.
+    ///
+    /// See [`Settings::set_closed_gop`] for more information.
+    pub fn with_closed_gop(mut self) -> Self {
+        self.set_closed_gop();
+        self
+    }
+
+    /// Set the rate control mode. Encoders take rate control options differently, so the mode is
+    /// mapped to the options of the encoder that is used, like `crf` and `nal-hrd` for x264 or
+    /// `rc` and `cq` for NVENC. Options of the same kind that were set directly are replaced.
+    ///
+    /// Bitrate modes require a target bitrate, set with [`Settings::set_bitrate`].
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Rate control mode.
+    pub fn set_rc_mode(&mut self, mode: RateControl) {
+        self.rate_control.mode = Some(mode);
+    }
+
+    /// Set the rate control mode.
+    ///
+    /// See [`Settings::set_rc_mode`] for more information.
+    pub fn with_rc_mode(mut self, mode: RateControl) -> Self {
+        self.set_rc_mode(mode);
+        self
+    }
+
+    /// Encode with constant quality. See [`RateControl::Crf`].
+    ///
+    /// # Arguments
+    ///
+    /// * `crf` - Constant rate factor, lower is better quality.
+    pub fn with_crf(self, crf: u8) -> Self {
+        self.with_rc_mode(RateControl::Crf(crf))
+    }
+
+    /// Set the target bitrate. Without a rate control mode, the encoder encodes with
+    /// [`RateControl::Vbr`].
+    ///
+    /// # Arguments
+    ///
+    /// * `bitrate` - Target bitrate in bits per second.
+    pub fn set_bitrate(&mut self, bitrate: u64) {
+        self.rate_control.bitrate = Some(bitrate);
+    }
+
+    /// Set the target bitrate.
+    ///
+    /// See [`Settings::set_bitrate`] for more information.
+    pub fn with_bitrate(mut self, bitrate: u64) -> Self {
+        self.set_bitrate(bitrate);
+        self
+    }
+
+    /// Set the maximum bitrate, which caps variable bitrate and constant quality modes. The rate
+    /// control buffer is sized to two seconds at the maximum bitrate.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_bitrate` - Maximum bitrate in bits per second.
+    pub fn set_max_bitrate(&mut self, max_bitrate: u64) {
+        self.rate_control.max_bitrate = Some(max_bitrate);
+    }
+
+    /// Set the maximum bitrate.
+    ///
+    /// See [`Settings::set_max_bitrate`] for more information.
+    pub fn with_max_bitrate(mut self, max_bitrate: u64) -> Self {
+        self.set_max_bitrate(max_bitrate);
+        self
+    }
+
+    /// Set the pass to encode of a [two-pass](RateControl::TwoPass) encode.
+    ///
+    /// # Arguments
+    ///
+    /// * `pass` - Pass to encode.
+    pub fn set_pass(&mut self, pass: EncodePass) {
+        self.rate_control.pass = Some(pass);
+    }
+
+    /// Set the pass to encode of a two-pass encode.
+    ///
+    /// See [`Settings::set_pass`] for more information.
+    pub fn with_pass(mut self, pass: EncodePass) -> Self {
+        self.set_pass(pass);
+        self
+    }
+
+    /// Whether the encoder that these settings select needs a first pass before the encode, which
+    /// is the case for [`RateControl::TwoPass`] unless the encoder does both passes internally.
+    pub fn requires_first_pass(&self) -> bool {
+        self.rate_control.requires_first_pass(self.encoder_family())
+    }
+
+    /// Set the hardware device type to encode with. The encoder will use the hardware encoder for
+    /// the device type (like `h264_mediacodec` on Android, `h264_v4l2m2m` on the Raspberry Pi or
+    /// `h264_nvenc` for CUDA) if it is available, and fall back to software encoding otherwise.
+    ///
+    /// Note that the pixel format must be supported by the hardware encoder. Most hardware encoders
+    /// accept YUV420p or NV12.
+    pub fn set_hardware_acceleration(&mut self, device_type: HardwareAccelerationDeviceType) {
+        self.hardware_acceleration_device_type = Some(device_type);
+    }
+
+    /// Set the hardware device type to encode with.
+    ///
+    /// See [`Settings::set_hardware_acceleration`] for more information.
+    pub fn with_hardware_acceleration(
+        mut self,
+        device_type: HardwareAccelerationDeviceType,
+    ) -> Self {
+        self.set_hardware_acceleration(device_type);
+        self
+    }
+
+    /// Encode hardware frames from a frame pool without downloading them to system memory, like
+    /// the Direct3D 11 frames of desktop duplication capture encoded with NVENC, AMF or QSV.
+    ///
+    /// Frames passed to [`Encoder::encode_raw`] must then be hardware frames from the pool, instead
+    /// of RGB frames. Also set the hardware device type with
+    /// [`Settings::set_hardware_acceleration`] to select a hardware encoder that accepts the
+    /// frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `hardware_frames` - Pool of the frames to encode. See [`HardwareFrames::of`].
+    pub fn set_hardware_frames(&mut self, hardware_frames: HardwareFrames) {
+        self.hardware_frames = Some(hardware_frames);
+    }
+
+    /// Encode hardware frames from a frame pool.
+    ///
+    /// See [`Settings::set_hardware_frames`] for more information.
+    pub fn with_hardware_frames(mut self, hardware_frames: HardwareFrames) -> Self {
+        self.set_hardware_frames(hardware_frames);
+        self
+    }
+
+    /// Get the kinds of parallelism the encoder that these settings select supports.
+    pub fn parallelism_support(&self) -> Option<ParallelismSupport> {
+        self.codec().map(|codec| ParallelismSupport::of(&codec))
+    }
+
+    /// Get the license of the encoder that these settings select, to check it against a licensing
+    /// policy before encoding. For example, H.264 selects `libx264` (GPL) when it is available.
+    pub fn license(&self) -> Option<EncoderLicense> {
+        self.codec().map(|codec| EncoderLicense::of(codec.name()))
+    }
+
+    /// Configure threading, slices and tiles of the encoder for latency or throughput. The number
+    /// of slices and tile columns follows the number of CPUs, up to eight. Options that the
+    /// encoder does not support are not set.
+    ///
+    /// # Arguments
+    ///
+    /// * `hint` - What to optimize for.
+    pub fn set_parallelism(&mut self, hint: ParallelismHint) {
+        let Some(codec) = self.codec() else {
+            return;
+        };
+        let support = ParallelismSupport::of(&codec);
+        let units = std::thread::available_parallelism()
+            .map_or(1, std::num::NonZeroUsize::get)
+            .min(MAX_PARALLEL_UNITS);
+        match hint {
+            ParallelismHint::LowLatency => {
+                if support.slice_threads {
+                    self.options.set("thread_type", "slice");
+                }
+                if support.slices {
+                    self.options.set("slices", &units.to_string());
+                }
+            }
+            ParallelismHint::Throughput => {
+                if support.frame_threads {
+                    self.options.set("thread_type", "frame");
+                }
+            }
+        }
+        if let Some(tile_option) = support.tile_option {
+            // The libvpx and libaom wrappers take the base two logarithm of the number of columns.
+            let columns =
+                if codec.name().starts_with("libvpx") || codec.name().starts_with("libaom") {
+                    units.ilog2() as usize
+                } else {
+                    units
+                };
+            self.options.set(tile_option, &columns.to_string());
+        }
+    }
+
+    /// Configure threading, slices and tiles of the encoder for latency or throughput.
+    ///
+    /// See [`Settings::set_parallelism`] for more information.
+    pub fn with_parallelism(mut self, hint: ParallelismHint) -> Self {
+        self.set_parallelism(hint);
+        self
+    }
+
+    /// Take a snapshot of the settings as they are resolved for encoding: the encoder that will be
+    /// used, every codec option, including the options of presets and parallelism hints, and the
+    /// rate control, B-frame and GOP settings that further codec options are derived from when
+    /// the encoder is opened. Store it (it is serializable with the `serde` feature) to reproduce
+    /// an encode later with [`Settings::from_snapshot`].
+    ///
+    /// The snapshot always sets the number of threads, since encoders like `libx264` encode
+    /// differently with another number of threads: to the libavcodec default of one thread if it
+    /// is not set, and to the number of CPUs of this system if it is automatic. Encode with the
+    /// settings restored from the snapshot the first time too, or take the snapshot of the opened
+    /// encoder with [`Encoder::settings_snapshot`], which has every option in effect.
+    pub fn snapshot(&self) -> SettingsSnapshot {
+        let mut options: Vec<(String, String)> = self
+            .options
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        let threads = match options.iter().position(|(key, _)| key == "threads") {
+            Some(index) => options.remove(index).1,
+            None => "1".to_string(),
+        };
+        let threads = match threads.as_str() {
+            "auto" | "0" => std::thread::available_parallelism()
+                .map_or(1, std::num::NonZeroUsize::get)
+                .to_string(),
+            _ => threads,
+        };
+        options.push(("threads".to_string(), threads));
+        SettingsSnapshot {
+            encoder: self
+                .codec()
+                .map(|codec| codec.name().to_string())
+                .unwrap_or_default(),
+            avcodec_version: ffmpeg::codec::version(),
+            width: self.width,
+            height: self.height,
+            pixel_format: self
+                .pixel_format
+                .descriptor()
+                .map(|descriptor| descriptor.name().to_string())
+                .unwrap_or_default(),
+            keyframe_interval: self.keyframe_interval,
+            hardware_acceleration: self.hardware_acceleration_device_type,
+            rate_control: self.rate_control.mode.clone(),
+            bitrate: self.rate_control.bitrate,
+            max_bitrate: self.rate_control.max_bitrate,
+            pass: self.rate_control.pass,
+            max_b_frames: self.max_b_frames,
+            closed_gop: self.closed_gop,
+            options,
+        }
+    }
+
+    /// Restore settings from a snapshot taken with [`Settings::snapshot`].
+    ///
+    /// A different version of libavcodec may encode differently, which is logged as a warning.
+    ///
+    /// # Arguments
+    ///
+    /// * `snapshot` - Snapshot of settings.
+    ///
+    /// # Return value
+    ///
+    /// The settings, or [`Error::UnsupportedCodec`] if the encoder of the snapshot is not the
+    /// encoder that these settings would use on this system (for instance because a hardware
+    /// encoder is not available), since the encode would not be reproduced.
+    pub fn from_snapshot(snapshot: &SettingsSnapshot) -> Result<Settings> {
+        let pixel_format = snapshot
+            .pixel_format
+            .parse::<AvPixel>()
+            .map_err(|_| Error::InvalidFrameFormat)?;
+        // H.264 encoders are picked from the hardware acceleration settings like for the settings
+        // the snapshot was taken of, which is checked below.
+        let codec_id = ffmpeg::encoder::find_by_name(&snapshot.encoder)
+            .map(|codec| codec.id())
+            .filter(|codec_id| *codec_id != AvCodecId::H264);
+        let settings = Self {
+            width: snapshot.width,
+            height: snapshot.height,
+            pixel_format,
+            codec_id,
+            keyframe_interval: snapshot.keyframe_interval,
+            hardware_acceleration_device_type: snapshot.hardware_acceleration,
+            hardware_frames: None,
+            rate_control: RateControlSettings {
+                mode: snapshot.rate_control.clone(),
+                bitrate: snapshot.bitrate,
+                max_bitrate: snapshot.max_bitrate,
+                pass: snapshot.pass,
+            },
+            max_b_frames: snapshot.max_b_frames,
+            closed_gop: snapshot.closed_gop,
+            options: Options::from(snapshot.options.clone()),
+        };
+        let encoder = settings.codec().map(|codec| codec.name().to_string());
+        if encoder.as_deref() != Some(snapshot.encoder.as_str()) {
+            return Err(Error::UnsupportedCodec);
+        }
+        if snapshot.avcodec_version != ffmpeg::codec::version() {
+            tracing::warn!(
+                "snapshot was taken with libavcodec version {:#x}, encoding with {:#x}",
+                snapshot.avcodec_version,
+                ffmpeg::codec::version()
+            );
+        }
+        Ok(settings)
+    }
+
     /// Apply the settings to an encoder.
     ///
     /// # Arguments
@@ -468,12 +2248,74 @@ impl Settings {
     fn apply_to(&self, encoder: &mut AvVideo) {
         encoder.set_width(self.width);
         encoder.set_height(self.height);
-        encoder.set_format(self.pixel_format);
+        match self.hardware_frames.as_ref() {
+            Some(hardware_frames) => encoder.set_format(hardware_frames.format()),
+            None => encoder.set_format(self.pixel_format),
+        }
         encoder.set_frame_rate(Some((Self::FRAME_RATE, 1)));
+        encoder.set_gop(u32::try_from(self.keyframe_interval.max(1)).unwrap_or(u32::MAX));
+    }
+
+    /// Check whether an encoder with these settings can take frames from a pool of hardware
+    /// frames as they are, without downloading them.
+    ///
+    /// # Arguments
+    ///
+    /// * `hardware_frames` - Pool of the frames.
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    ///
+    /// # Return value
+    ///
+    /// `None` if it can, or the reason it cannot.
+    pub(crate) fn hardware_frames_mismatch(
+        &self,
+        hardware_frames: &HardwareFrames,
+        width: u32,
+        height: u32,
+    ) -> Option<String> {
+        let Some(codec) = self.codec() else {
+            return Some("no encoder is available".to_string());
+        };
+        if !hardware_frames.is_supported_by(&codec) {
+            return Some(format!(
+                "encoder {} does not take {:?} frames",
+                codec.name(),
+                hardware_frames.format(),
+            ));
+        }
+        if (width, height) != (self.width, self.height) {
+            return Some(format!(
+                "frames are {width}x{height} but the encoder is {}x{}",
+                self.width, self.height,
+            ));
+        }
+        None
     }
 
     /// Get codec.
     fn codec(&self) -> Option<AvCodec> {
+        if let Some(codec_id) = self.codec_id {
+            return ffmpeg::encoder::find(codec_id);
+        }
+        // Prefer the hardware encoder if one was requested and it is available, and it takes the
+        // hardware frames if there are any.
+        if let Some(codec) = self
+            .hardware_acceleration_device_type
+            .and_then(|device_type| hwaccel::find_wrapper_encoder(AvCodecId::H264, device_type))
+            .filter(|codec| {
+                self.hardware_frames
+                    .as_ref()
+                    .is_none_or(|hardware_frames| hardware_frames.is_supported_by(codec))
+            })
+        {
+            return Some(codec);
+        }
+        if let Some(codec) = self.hardware_frames.as_ref().and_then(|hardware_frames| {
+            hwaccel::find_hardware_frames_encoder(AvCodecId::H264, hardware_frames)
+        }) {
+            return Some(codec);
+        }
         // Try to use the libx264 decoder. If it is not available, then use use whatever default
         // h264 decoder we have.
         Some(
@@ -482,11 +2324,163 @@ impl Settings {
         )
     }
 
-    /// Get encoder options.
-    fn options(&self) -> &Options {
-        &self.options
+    /// Get the family of the encoder, which determines how it takes rate control options.
+    pub(crate) fn encoder_family(&self) -> EncoderFamily {
+        self.codec()
+            .map(|codec| EncoderFamily::of(&codec))
+            .unwrap_or(EncoderFamily::Other { crf: false })
+    }
+
+    /// Get encoder options, including the options of the rate control mode and the GOP structure.
+    fn options(&self) -> Result<Options> {
+        let mut options = self.options.clone();
+        if let Some(codec) = self.codec() {
+            self.encoder_family()
+                .translate_x264_options(&mut options, |key| {
+                    ffi::codec_has_private_option(&codec, key)
+                });
+        }
+        self.rate_control
+            .apply_to(self.encoder_family(), &mut options)?;
+        if let Some(max_b_frames) = self.max_b_frames {
+            options.set("bf", &max_b_frames.to_string());
+        }
+        if self.closed_gop {
+            let flags = format!("{}+cgop", options.get("flags").unwrap_or_default());
+            options.set("flags", &flags);
+        }
+        // Make the keyframes that are forced instantaneous decoder refresh (IDR) frames, which
+        // segments can start at, instead of plain intra frames.
+        if self
+            .codec()
+            .is_some_and(|codec| ffi::codec_has_private_option(&codec, "forced-idr"))
+            && options.get("forced-idr").is_none()
+        {
+            options.set("forced-idr", "1");
+        }
+        Ok(options)
     }
 }
 
+/// Encoder settings as they were resolved for encoding. See [`Settings::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SettingsSnapshot {
+    /// Name of the encoder, like "libx264".
+    pub encoder: String,
+    /// Version of libavcodec, as returned by `avcodec_version`.
+    pub avcodec_version: u32,
+    /// Width of the frames.
+    pub width: u32,
+    /// Height of the frames.
+    pub height: u32,
+    /// Name of the pixel format, like "yuv420p".
+    pub pixel_format: String,
+    /// Keyframe interval.
+    pub keyframe_interval: u64,
+    /// Hardware device type to encode with.
+    pub hardware_acceleration: Option<HardwareAccelerationDeviceType>,
+    /// Rate control mode. See [`Settings::with_rc_mode`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rate_control: Option<RateControl>,
+    /// Target bitrate in bits per second. See [`Settings::with_bitrate`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bitrate: Option<u64>,
+    /// Maximum bitrate in bits per second. See [`Settings::with_max_bitrate`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_bitrate: Option<u64>,
+    /// Pass of a two-pass encode. See [`Settings::with_pass`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub pass: Option<EncodePass>,
+    /// Maximum number of consecutive B-frames. See [`Settings::with_max_b_frames`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub max_b_frames: Option<u32>,
+    /// Whether GOPs are closed. See [`Settings::with_closed_gop`].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closed_gop: bool,
+    /// Codec options in the order they are applied, as key and value.
+    pub options: Vec<(String, String)>,
+}
+
+/// Log an error accessing the statistics file of a two-pass encode.
+///
+/// # Arguments
+///
+/// * `path` - Path of the statistics file.
+/// * `action` - What failed, like "read".
+/// * `err` - Error.
+fn stats_file_error(path: &std::path::Path, action: &str, err: std::io::Error) -> Error {
+    tracing::error!(
+        "failed to {action} statistics file {}: {err}",
+        path.display()
+    );
+    Error::BackendError(AvError::External)
+}
+
 unsafe impl Send for Encoder {}
 unsafe impl Sync for Encoder {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_LATENCY: Duration = Duration::from_millis(200);
+
+    #[test]
+    fn test_realtime_under_budget() {
+        let mut realtime = RealtimeState::new(RealtimeMode::new(MAX_LATENCY));
+        assert!(!realtime.should_drop(Duration::ZERO, false));
+        assert!(!realtime.should_drop(MAX_LATENCY, false));
+    }
+
+    #[test]
+    fn test_realtime_over_budget() {
+        let mut realtime = RealtimeState::new(RealtimeMode::new(MAX_LATENCY));
+        let behind = MAX_LATENCY * 2;
+        assert!(realtime.should_drop(behind, false));
+        assert!(realtime.should_drop(behind, false));
+        // Keyframes are encoded even when behind.
+        assert!(!realtime.should_drop(behind, true));
+
+        let mut realtime = RealtimeState::new(
+            RealtimeMode::new(MAX_LATENCY).with_policy(FrameDropPolicy::DropAlternate),
+        );
+        let drops = (0..4)
+            .map(|_| realtime.should_drop(behind, false))
+            .collect::<Vec<_>>();
+        assert_eq!(drops, [true, false, true, false]);
+    }
+
+    #[test]
+    fn test_realtime_recovers_after_drop() {
+        let mut realtime = RealtimeState::new(
+            RealtimeMode::new(MAX_LATENCY).with_policy(FrameDropPolicy::DropAlternate),
+        );
+        assert!(realtime.should_drop(MAX_LATENCY * 2, false));
+        assert!(!realtime.should_drop(Duration::ZERO, false));
+        // Once caught up, the next late frame is dropped again.
+        assert!(realtime.should_drop(MAX_LATENCY * 2, false));
+    }
+
+    #[test]
+    fn test_realtime_lag() {
+        let mut realtime = RealtimeState::new(RealtimeMode::new(MAX_LATENCY));
+        let time_base = AvRational::new(1, 1000);
+        let origin = Instant::now();
+        assert_eq!(
+            realtime.lag_at(origin, Some(5000), time_base),
+            Some(Duration::ZERO)
+        );
+        // One second of media submitted one and a half seconds later.
+        let lag = realtime
+            .lag_at(origin + Duration::from_millis(1500), Some(6000), time_base)
+            .unwrap();
+        assert!((lag.as_secs_f64() - 0.5).abs() < 0.001);
+        // Frames ahead of the wall clock have no lag.
+        assert_eq!(
+            realtime.lag_at(origin + Duration::from_secs(1), Some(8000), time_base),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(realtime.lag_at(origin, None, time_base), None);
+    }
+}
@@ -1,5 +1,8 @@
 use ffmpeg::Error as FfmpegError;
 
+use crate::limits::ResourceLimit;
+use crate::validate::TimestampViolation;
+
 /// Represents video I/O Errors. Some errors are generated by the ffmpeg backend, and are wrapped in
 /// `BackendError`.
 #[derive(Debug, Clone)]
@@ -14,6 +17,14 @@ pub enum Error {
     InvalidResizeParameters,
     UninitializedCodec,
     UnsupportedCodecHardwareAccelerationDeviceType,
+    MaxDimensionsExceeded,
+    ResourceLimitExceeded(ResourceLimit),
+    UnsupportedCodec,
+    UnsupportedReconfiguration,
+    InvalidRateControl,
+    InvalidRange,
+    InvalidConfig(String),
+    TimestampViolation(TimestampViolation),
     BackendError(FfmpegError),
 }
 
@@ -30,6 +41,14 @@ impl std::error::Error for Error {
             Error::InvalidResizeParameters => None,
             Error::UninitializedCodec => None,
             Error::UnsupportedCodecHardwareAccelerationDeviceType => None,
+            Error::MaxDimensionsExceeded => None,
+            Error::ResourceLimitExceeded(_) => None,
+            Error::UnsupportedCodec => None,
+            Error::UnsupportedReconfiguration => None,
+            Error::InvalidRateControl => None,
+            Error::InvalidRange => None,
+            Error::InvalidConfig(_) => None,
+            Error::TimestampViolation(_) => None,
             Error::BackendError(ref internal) => Some(internal),
         }
     }
@@ -62,6 +81,30 @@ impl std::fmt::Display for Error {
             Error::UnsupportedCodecHardwareAccelerationDeviceType => {
                 write!(f, "codec does not supported hardware acceleration device")
             }
+            Error::MaxDimensionsExceeded => {
+                write!(f, "frame dimensions exceed the configured maximum")
+            }
+            Error::ResourceLimitExceeded(ref limit) => {
+                write!(f, "resource limit exceeded: {limit}")
+            }
+            Error::UnsupportedCodec => {
+                write!(f, "codec is not supported by the output format")
+            }
+            Error::UnsupportedReconfiguration => {
+                write!(
+                    f,
+                    "encoder does not support changing parameters while encoding"
+                )
+            }
+            Error::InvalidRateControl => write!(
+                f,
+                "rate control mode requires a target bitrate, or an encode pass for two passes"
+            ),
+            Error::InvalidRange => write!(f, "time range is empty or outside of the source"),
+            Error::InvalidConfig(ref reason) => {
+                write!(f, "invalid pipeline configuration: {reason}")
+            }
+            Error::TimestampViolation(ref violation) => write!(f, "{violation}"),
             Error::BackendError(ref internal) => internal.fmt(f),
         }
     }
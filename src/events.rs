@@ -0,0 +1,294 @@
+//! Structured events of decoding and encoding pipelines, for observability and progress reporting
+//! without polling or parsing logs.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::time::Time;
+
+/// Event of a pipeline. Subscribe with [`Transcoder::events`](crate::transcode::Transcoder::events)
+/// or [`Encoder::events`](crate::encode::Encoder::events).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Identifier of the pipeline, unique within the process, to tell the events of concurrent
+    /// pipelines apart.
+    pub pipeline: u64,
+    /// Sequence number of the event within the pipeline, starting at 0.
+    pub sequence: u64,
+    /// Wall clock time of the event.
+    pub time: SystemTime,
+    /// Time since the pipeline was created.
+    pub elapsed: Duration,
+    /// What happened.
+    pub kind: EventKind,
+}
+
+/// What happened in a pipeline. See [`Event`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    /// A frame was decoded.
+    FrameDecoded {
+        /// Number of the frame, counting from 0.
+        frame: u64,
+        /// Timestamp of the frame.
+        timestamp: Time,
+    },
+    /// A frame was sent to the encoder.
+    FrameEncoded {
+        /// Number of the frame, counting from 0.
+        frame: u64,
+        /// Timestamp of the frame.
+        timestamp: Time,
+    },
+    /// A packet was written to the output.
+    PacketWritten {
+        /// Index of the output stream.
+        stream_index: usize,
+        /// Size of the packet in bytes.
+        size: usize,
+        /// Presentation timestamp of the packet.
+        timestamp: Time,
+    },
+    /// The pipeline failed. No more events follow.
+    Error {
+        /// Description of the error.
+        message: String,
+    },
+    /// The end of the stream was reached and the output was finished. No more events follow.
+    Eof,
+    /// Events were dropped because the subscriber did not keep up, and the events it had not
+    /// received yet filled its queue. The event has the sequence number of the first event that
+    /// was dropped.
+    Lagged {
+        /// Number of events dropped.
+        events: u64,
+    },
+}
+
+/// Number of events a subscriber can fall behind before events are dropped.
+const EVENT_CAPACITY: usize = 1024;
+
+/// Delivers events to subscribers. Clones share the pipeline identifier, sequence and
+/// subscribers, so the stages of a pipeline emit into one stream.
+#[derive(Clone)]
+pub(crate) struct EventSink {
+    inner: Arc<EventSinkInner>,
+}
+
+struct EventSinkInner {
+    pipeline: u64,
+    start: (Instant, SystemTime),
+    sequence: AtomicU64,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+/// Queue of the events of one subscriber.
+struct Subscriber {
+    sender: SyncSender<Event>,
+    /// Sequence number of the first event dropped since the last event delivered, and the number
+    /// of events dropped.
+    lagged: Option<(u64, u64)>,
+}
+
+impl Subscriber {
+    /// Deliver an event, or drop it if the queue is full. Dropped events are reported with an
+    /// [`EventKind::Lagged`] once there is room again.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - Event to deliver.
+    ///
+    /// # Return value
+    ///
+    /// Whether the subscriber is still receiving events.
+    fn send(&mut self, event: &Event) -> bool {
+        if let Some((sequence, events)) = self.lagged {
+            let lagged = Event {
+                sequence,
+                kind: EventKind::Lagged { events },
+                ..event.clone()
+            };
+            match self.sender.try_send(lagged) {
+                Ok(()) => self.lagged = None,
+                Err(TrySendError::Full(_)) => {
+                    self.lagged = Some((sequence, events + 1));
+                    return true;
+                }
+                Err(TrySendError::Disconnected(_)) => return false,
+            }
+        }
+        match self.sender.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.lagged = Some((event.sequence, 1));
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+impl EventSink {
+    /// Create a sink for a new pipeline.
+    pub(crate) fn new() -> Self {
+        static NEXT_PIPELINE: AtomicU64 = AtomicU64::new(0);
+
+        Self {
+            inner: Arc::new(EventSinkInner {
+                pipeline: NEXT_PIPELINE.fetch_add(1, Ordering::Relaxed),
+                start: (Instant::now(), SystemTime::now()),
+                sequence: AtomicU64::new(0),
+                subscribers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Subscribe to the events emitted from now on. A subscriber that falls more than
+    /// [`EVENT_CAPACITY`] events behind misses events, see [`EventKind::Lagged`].
+    pub(crate) fn subscribe(&self) -> Receiver<Event> {
+        self.subscribe_with_capacity(EVENT_CAPACITY)
+    }
+
+    /// Subscribe to the events emitted from now on, with a queue of a capacity.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Number of events the subscriber can fall behind before events are dropped.
+    fn subscribe_with_capacity(&self, capacity: usize) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        self.subscribers().push(Subscriber {
+            sender,
+            lagged: None,
+        });
+        receiver
+    }
+
+    /// Emit an event to the subscribers without blocking. Subscribers that dropped their receiver
+    /// are removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - What happened.
+    pub(crate) fn emit(&self, kind: EventKind) {
+        let mut subscribers = self.subscribers();
+        if subscribers.is_empty() {
+            return;
+        }
+        let (start, start_time) = self.inner.start;
+        let elapsed = start.elapsed();
+        let event = Event {
+            pipeline: self.inner.pipeline,
+            sequence: self.inner.sequence.fetch_add(1, Ordering::Relaxed),
+            time: start_time + elapsed,
+            elapsed,
+            kind,
+        };
+        subscribers.retain_mut(|subscriber| subscriber.send(&event));
+    }
+
+    /// Lock the subscribers. Emitting never panics while holding the lock, so a poisoned lock
+    /// still holds a valid list.
+    fn subscribers(&self) -> std::sync::MutexGuard<'_, Vec<Subscriber>> {
+        self.inner
+            .subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_sequence() {
+        let sink = EventSink::new();
+        let events = sink.subscribe();
+        sink.emit(EventKind::Eof);
+        sink.clone().emit(EventKind::Error {
+            message: "failed".to_string(),
+        });
+        let events = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].pipeline, events[1].pipeline);
+        assert_eq!((events[0].sequence, events[1].sequence), (0, 1));
+        assert!(events[0].elapsed <= events[1].elapsed);
+    }
+
+    #[test]
+    fn test_dropped_subscribers_are_removed() {
+        let sink = EventSink::new();
+        drop(sink.subscribe());
+        let events = sink.subscribe();
+        sink.emit(EventKind::Eof);
+        assert_eq!(sink.subscribers().len(), 1);
+        assert_eq!(events.try_recv().unwrap().kind, EventKind::Eof);
+    }
+
+    #[test]
+    fn test_slow_subscriber_lags() {
+        let sink = EventSink::new();
+        let events = sink.subscribe_with_capacity(2);
+        for frame in 0..5 {
+            sink.emit(EventKind::FrameDecoded {
+                frame,
+                timestamp: Time::zero(),
+            });
+        }
+        // The queue is full after two events, and the next three are dropped.
+        let received = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(received.len(), 2);
+        assert_eq!((received[0].sequence, received[1].sequence), (0, 1));
+
+        // The subscriber learns about the dropped events before the next one.
+        sink.emit(EventKind::Eof);
+        let received = events.try_iter().collect::<Vec<_>>();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].kind, EventKind::Lagged { events: 3 });
+        assert_eq!(received[0].sequence, 2);
+        assert_eq!(received[1].kind, EventKind::Eof);
+        assert_eq!(received[1].sequence, 5);
+    }
+
+    #[test]
+    fn test_lagged_events_are_counted_until_delivered() {
+        let sink = EventSink::new();
+        let events = sink.subscribe_with_capacity(1);
+        for _ in 0..3 {
+            sink.emit(EventKind::Eof);
+        }
+        assert_eq!(events.try_recv().unwrap().sequence, 0);
+        // The report of the lag takes the free slot, and the event after it is dropped as well.
+        sink.emit(EventKind::Eof);
+        assert_eq!(
+            events.try_recv().unwrap().kind,
+            EventKind::Lagged { events: 2 }
+        );
+        sink.emit(EventKind::Eof);
+        let lagged = events.try_recv().unwrap();
+        assert_eq!(lagged.kind, EventKind::Lagged { events: 1 });
+        assert_eq!(lagged.sequence, 3);
+    }
+
+    #[test]
+    fn test_abandoned_subscriber_is_removed_when_full() {
+        let sink = EventSink::new();
+        let events = sink.subscribe_with_capacity(1);
+        sink.emit(EventKind::Eof);
+        sink.emit(EventKind::Eof);
+        assert_eq!(sink.subscribers().len(), 1);
+        drop(events);
+        sink.emit(EventKind::Eof);
+        assert!(sink.subscribers().is_empty());
+    }
+
+    #[test]
+    fn test_pipelines_are_unique() {
+        assert_ne!(
+            EventSink::new().inner.pipeline,
+            EventSink::new().inner.pipeline
+        );
+    }
+}
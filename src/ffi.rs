@@ -3,16 +3,21 @@ use ndarray::Array3;
 
 use ffmpeg::codec::codec::Codec;
 use ffmpeg::codec::context::Context;
+use ffmpeg::codec::packet::Mut as _;
 use ffmpeg::encoder::video::Video;
-use ffmpeg::format::context::Output;
+use ffmpeg::format::context::{Input, Output};
+use ffmpeg::software::scaling::context::Context as Scaler;
 use ffmpeg::util::frame::video::Video as Frame;
-use ffmpeg::{Error, Rational};
+use ffmpeg::{Dictionary, Error, Rational};
 
 #[cfg(feature = "ndarray")]
 use ffmpeg::util::format::Pixel;
 
 use ffmpeg::ffi;
 
+use crate::interrupt::{Interrupt, InterruptState};
+use crate::sidedata::{ContentLightLevel, DisplayPrimaries, FrameSideData, MasteringDisplay};
+
 /// This function is similar to the existing bindings in ffmpeg like `output` and `output_as`,
 /// but does not assume that it is opening a file-like context. Instead, it opens a raw output,
 /// without a file attached.
@@ -161,96 +166,2009 @@ pub fn output_raw_packetized_buf_start(
     }
 }
 
-/// This function cleans up the IO context used for packetized writing created by
-/// `output_raw_packetized_buf_start`.
+/// This function cleans up the IO context used for packetized writing created by
+/// `output_raw_packetized_buf_start`.
+///
+/// # Arguments
+///
+/// * `output` - Output context to end write on.
+pub fn output_raw_packetized_buf_end(output: &mut Output) {
+    unsafe {
+        let output_pb = (*output.as_mut_ptr()).pb;
+
+        // One last flush (might incur write, most likely won't).
+        ffi::avio_flush(output_pb);
+
+        // Note: No need for handling `opaque` as it is managed by Rust code anyway and will be
+        // freed by it.
+
+        // We do need to free the buffer itself though (we allocatd it manually earlier).
+        ffi::av_free((*output_pb).buffer as *mut std::ffi::c_void);
+        // And deallocate the entire IO context.
+        ffi::av_free(output_pb as *mut std::ffi::c_void);
+
+        // Reset the `pb` field or `avformat_close` will try to free it!
+        ((*output.as_mut_ptr()).pb) = std::ptr::null_mut::<ffi::AVIOContext>();
+    }
+}
+
+/// Size of the IO buffer used when reading through a custom IO context.
+const INPUT_RAW_IO_SIZE: usize = 32 * 1024;
+
+/// Source of bytes for a custom input IO context. Sources that cannot seek should return an error
+/// of kind [`std::io::ErrorKind::Unsupported`] from `seek`.
+pub trait InputSource: std::io::Read + std::io::Seek + Send {}
+
+impl<T: std::io::Read + std::io::Seek + Send> InputSource for T {}
+
+/// Custom IO context that reads from an [`InputSource`]. Created by `input_raw_io`.
+///
+/// The IO context must outlive the `Input` it was created for. Dropping it frees the IO context.
+pub struct InputIo {
+    io: *mut ffi::AVIOContext,
+    // Double boxed so that the thin pointer passed to `libavformat` as `opaque` stays valid.
+    _source: Box<Box<dyn InputSource>>,
+}
+
+impl Drop for InputIo {
+    fn drop(&mut self) {
+        unsafe {
+            // The IO context may have replaced the buffer we passed it, so free whatever it holds
+            // now rather than the original buffer.
+            ffi::av_freep(&mut (*self.io).buffer as *mut *mut u8 as *mut std::ffi::c_void);
+            ffi::avio_context_free(&mut self.io);
+        }
+    }
+}
+
+/// This function is similar to the existing bindings in ffmpeg like `input`, but reads the input
+/// from an arbitrary source through a custom IO context instead of opening a file or URL. This
+/// works in environments without a file system, like the browser, and with sources that are not
+/// files at all.
+///
+/// The returned `InputIo` must be dropped after the `Input`.
+///
+/// # Arguments
+///
+/// * `source` - Source to read the input from.
+/// * `seekable` - Whether or not the source supports seeking.
+/// * `format` - Input format to use instead of probing, if any.
+/// * `options` - Options to pass on to input.
+/// * `interrupt` - Interrupt to install on the input, which must outlive it.
+pub fn input_raw_io(
+    source: Box<dyn InputSource>,
+    seekable: bool,
+    format: Option<&ffmpeg::format::format::Input>,
+    options: Option<Dictionary>,
+    interrupt: &Interrupt,
+) -> Result<(Input, InputIo, Dictionary<'static>), Error> {
+    unsafe {
+        let mut source = Box::new(source);
+        let buffer = ffi::av_malloc(INPUT_RAW_IO_SIZE) as *mut u8;
+        if buffer.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+
+        // Create a custom IO context around our buffer.
+        let io: *mut ffi::AVIOContext = ffi::avio_alloc_context(
+            buffer,
+            INPUT_RAW_IO_SIZE.try_into().unwrap(),
+            // Set stream to READ.
+            0,
+            // Pass on a pointer to the source, which lives as long as the `InputIo`.
+            &mut *source as *mut Box<dyn InputSource> as *mut std::ffi::c_void,
+            Some(input_raw_io_read_callback),
+            // No `write_packet`.
+            None,
+            if seekable {
+                Some(input_raw_io_seek_callback)
+            } else {
+                None
+            },
+        );
+        if io.is_null() {
+            ffi::av_free(buffer as *mut std::ffi::c_void);
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+        (*io).seekable = if seekable {
+            ffi::AVIO_SEEKABLE_NORMAL as std::ffi::c_int
+        } else {
+            0
+        };
+        let input_io = InputIo {
+            io,
+            _source: source,
+        };
+
+        let mut ps = ffi::avformat_alloc_context();
+        if ps.is_null() {
+            // Dropping the `InputIo` frees the IO context and its buffer.
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+        (*ps).pb = io;
+        (*ps).interrupt_callback = interrupt_callback(interrupt);
+        // Let `avformat_close_input` know that it must not free the IO context.
+        (*ps).flags |= ffi::AVFMT_FLAG_CUSTOM_IO as std::ffi::c_int;
+
+        let mut opts = match options {
+            Some(options) => options.disown(),
+            None => std::ptr::null_mut(),
+        };
+        let format = format.map_or(std::ptr::null(), |format| format.as_ptr());
+        let ret = ffi::avformat_open_input(&mut ps, std::ptr::null(), format, &mut opts);
+        let unused_options = Dictionary::own(opts);
+        // Note: `avformat_open_input` frees the format context on failure.
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+
+        match ffi::avformat_find_stream_info(ps, std::ptr::null_mut()) {
+            r if r >= 0 => Ok((Input::wrap(ps), input_io, unused_options)),
+            e => {
+                ffi::avformat_close_input(&mut ps);
+                Err(Error::from(e))
+            }
+        }
+    }
+}
+
+/// Codec parser context, which splits an elementary stream into packets. Wraps
+/// `AVCodecParserContext`.
+pub struct ParserContext {
+    parser: *mut ffi::AVCodecParserContext,
+    codec_context: Context,
+    // Input copied with zeroed padding, since parsers may read past the end of the input.
+    buffer: Vec<u8>,
+}
+
+impl ParserContext {
+    /// Create a parser for a codec.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec of the elementary stream.
+    ///
+    /// # Return value
+    ///
+    /// Parser, or `None` if there is no parser for the codec.
+    pub fn new(codec_id: ffmpeg::codec::Id) -> Option<Self> {
+        unsafe {
+            let parser = ffi::av_parser_init(ffi::AVCodecID::from(codec_id) as std::ffi::c_int);
+            if parser.is_null() {
+                return None;
+            }
+            let codec_context = match ffmpeg::decoder::find(codec_id) {
+                Some(codec) => Context::new_with_codec(codec),
+                None => Context::new(),
+            };
+            Some(Self {
+                parser,
+                codec_context,
+                buffer: Vec::new(),
+            })
+        }
+    }
+
+    /// Parse (part of) an elementary stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data to parse, or an empty slice to flush the parser.
+    /// * `on_packet` - Called with the data and keyframe flag of every complete packet.
+    pub fn parse(
+        &mut self,
+        data: &[u8],
+        mut on_packet: impl FnMut(&[u8], bool),
+    ) -> Result<(), Error> {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(data);
+        self.buffer
+            .resize(data.len() + ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize, 0);
+
+        let mut offset = 0;
+        loop {
+            let mut packet_data: *mut u8 = std::ptr::null_mut();
+            let mut packet_size: std::ffi::c_int = 0;
+            let consumed = unsafe {
+                ffi::av_parser_parse2(
+                    self.parser,
+                    self.codec_context.as_mut_ptr(),
+                    &mut packet_data,
+                    &mut packet_size,
+                    self.buffer.as_ptr().add(offset),
+                    (data.len() - offset) as std::ffi::c_int,
+                    ffi::AV_NOPTS_VALUE,
+                    ffi::AV_NOPTS_VALUE,
+                    0,
+                )
+            };
+            if consumed < 0 {
+                return Err(Error::from(consumed));
+            }
+            // A parser that neither consumes data nor outputs a packet would loop forever.
+            if consumed == 0 && packet_size == 0 && offset < data.len() {
+                return Err(Error::InvalidData);
+            }
+            offset += consumed as usize;
+            if packet_size > 0 {
+                let packet =
+                    unsafe { std::slice::from_raw_parts(packet_data, packet_size as usize) };
+                on_packet(packet, unsafe { (*self.parser).key_frame == 1 });
+            }
+            // When flushing, the parser outputs the remaining packet in one call.
+            if offset >= data.len() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Width and height of the last parsed video frame, if known.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        let (width, height) = unsafe { ((*self.parser).width, (*self.parser).height) };
+        (width > 0 && height > 0).then_some((width as u32, height as u32))
+    }
+}
+
+impl Drop for ParserContext {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::av_parser_close(self.parser);
+        }
+    }
+}
+
+/// Open an input with options, and keep the options that were not consumed.
+///
+/// # Arguments
+///
+/// * `path` - Path or URL to open.
+/// * `format` - Input format to use instead of probing, if any.
+/// * `options` - Options for the demuxer and protocol.
+/// * `interrupt` - Interrupt to install on the input before opening it, which must outlive it.
+///
+/// # Return value
+///
+/// The input, and the options that neither the demuxer nor the protocol consumed.
+pub fn input_with_options(
+    path: &std::path::Path,
+    format: Option<&ffmpeg::format::format::Input>,
+    options: Dictionary,
+    interrupt: &Interrupt,
+) -> Result<(Input, Dictionary<'static>), Error> {
+    let path = std::ffi::CString::new(path.to_string_lossy().as_bytes())
+        .map_err(|_| Error::InvalidData)?;
+    unsafe {
+        let mut ps = ffi::avformat_alloc_context();
+        if ps.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+        // The callback is also polled while connecting, so it must be set before opening.
+        (*ps).interrupt_callback = interrupt_callback(interrupt);
+        let mut opts = options.disown();
+        let format = format.map_or(std::ptr::null(), |format| format.as_ptr());
+        let ret = ffi::avformat_open_input(&mut ps, path.as_ptr(), format, &mut opts);
+        let unused_options = Dictionary::own(opts);
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+
+        match ffi::avformat_find_stream_info(ps, std::ptr::null_mut()) {
+            r if r >= 0 => Ok((Input::wrap(ps), unused_options)),
+            e => {
+                ffi::avformat_close_input(&mut ps);
+                Err(Error::from(e))
+            }
+        }
+    }
+}
+
+/// Open an output with options, and keep the options that were not consumed.
+///
+/// # Arguments
+///
+/// * `path` - Path or URL to open.
+/// * `format` - Name of the output format, or `None` to guess it from the path.
+/// * `options` - Options for the protocol.
+/// * `interrupt` - Interrupt to install on the output before opening it, which must outlive it.
+///
+/// # Return value
+///
+/// The output, and the options that the protocol did not consume.
+pub fn output_with_options(
+    path: &std::path::Path,
+    format: Option<&str>,
+    options: Dictionary,
+    interrupt: &Interrupt,
+) -> Result<(Output, Dictionary<'static>), Error> {
+    let path = std::ffi::CString::new(path.to_string_lossy().as_bytes())
+        .map_err(|_| Error::InvalidData)?;
+    let format = format
+        .map(std::ffi::CString::new)
+        .transpose()
+        .map_err(|_| Error::InvalidData)?;
+    unsafe {
+        let mut ps = std::ptr::null_mut();
+        let ret = ffi::avformat_alloc_output_context2(
+            &mut ps,
+            std::ptr::null_mut(),
+            format
+                .as_ref()
+                .map_or(std::ptr::null(), |format| format.as_ptr()),
+            path.as_ptr(),
+        );
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+        (*ps).interrupt_callback = interrupt_callback(interrupt);
+        // Formats like RTP and image sequences open their files themselves.
+        if (*(*ps).oformat).flags & ffi::AVFMT_NOFILE as std::ffi::c_int != 0 {
+            return Ok((Output::wrap(ps), options));
+        }
+        let mut opts = options.disown();
+        let ret = ffi::avio_open2(
+            &mut (*ps).pb,
+            path.as_ptr(),
+            ffi::AVIO_FLAG_WRITE as std::ffi::c_int,
+            &(*ps).interrupt_callback,
+            &mut opts,
+        );
+        let unused_options = Dictionary::own(opts);
+        if ret < 0 {
+            ffi::avformat_free_context(ps);
+            return Err(Error::from(ret));
+        }
+        Ok((Output::wrap(ps), unused_options))
+    }
+}
+
+/// Open a video encoder with options, and keep the options that were not consumed.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to open.
+/// * `options` - Options for the encoder.
+///
+/// # Return value
+///
+/// The opened encoder, and the options that the encoder did not consume.
+pub fn open_video_encoder(
+    mut encoder: Video,
+    options: Dictionary,
+) -> Result<(ffmpeg::encoder::video::Encoder, Dictionary<'static>), Error> {
+    unsafe {
+        let mut opts = options.disown();
+        let ret = ffi::avcodec_open2(encoder.as_mut_ptr(), std::ptr::null(), &mut opts);
+        let unused_options = Dictionary::own(opts);
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+        Ok((ffmpeg::encoder::video::Encoder(encoder), unused_options))
+    }
+}
+
+/// Set the statistics of the first pass that an encoder reads in the second pass of a two-pass
+/// encode. Must be called before opening the encoder.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to configure.
+/// * `stats` - Statistics written by the encoder in the first pass.
+pub fn set_encoder_stats_in(encoder: &mut Video, stats: &str) -> Result<(), Error> {
+    let stats = std::ffi::CString::new(stats).map_err(|_| Error::InvalidData)?;
+    unsafe {
+        let context = encoder.as_mut_ptr();
+        ffi::av_freep(std::ptr::addr_of_mut!((*context).stats_in) as *mut std::ffi::c_void);
+        // The codec context frees the statistics when it is freed.
+        (*context).stats_in = ffi::av_strdup(stats.as_ptr());
+        if (*context).stats_in.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Reset an encoder after it was drained, so it takes frames again. Only encoders with the
+/// `AV_CODEC_CAP_ENCODER_FLUSH` capability support this.
+///
+/// # Arguments
+///
+/// * `encoder` - Opened encoder.
+pub fn flush_encoder(encoder: &mut ffmpeg::encoder::video::Encoder) {
+    unsafe {
+        ffi::avcodec_flush_buffers(encoder.as_mut_ptr());
+    }
+}
+
+/// Get the statistics that an encoder output in the first pass of a two-pass encode, after it
+/// output a packet or was drained.
+///
+/// # Arguments
+///
+/// * `encoder` - Opened encoder.
+pub fn encoder_stats_out(encoder: &ffmpeg::encoder::video::Encoder) -> Option<String> {
+    unsafe {
+        let stats = (*encoder.as_ptr()).stats_out;
+        (!stats.is_null()).then(|| {
+            std::ffi::CStr::from_ptr(stats)
+                .to_string_lossy()
+                .into_owned()
+        })
+    }
+}
+
+/// Hash context, used to hash media content. Wraps `AVHashContext`.
+pub struct HashContext {
+    context: *mut ffi::AVHashContext,
+}
+
+impl HashContext {
+    /// Create a hash context.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the hash function in `libavutil`, like "MD5".
+    ///
+    /// # Return value
+    ///
+    /// Hash context, or `None` if the hash function does not exist.
+    pub fn new(name: &str) -> Option<Self> {
+        let name = std::ffi::CString::new(name).ok()?;
+        unsafe {
+            let mut context = std::ptr::null_mut();
+            if ffi::av_hash_alloc(&mut context, name.as_ptr()) < 0 || context.is_null() {
+                return None;
+            }
+            ffi::av_hash_init(context);
+            Some(Self { context })
+        }
+    }
+
+    /// Add data to the hash.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Data to hash.
+    pub fn update(&mut self, data: &[u8]) {
+        unsafe {
+            ffi::av_hash_update(self.context, data.as_ptr(), data.len());
+        }
+    }
+
+    /// Finish the hash and reset the context.
+    ///
+    /// # Return value
+    ///
+    /// The hash as lowercase hexadecimal string.
+    pub fn finish_hex(&mut self) -> String {
+        let mut buffer = [0_u8; 2 * ffi::AV_HASH_MAX_SIZE as usize + 1];
+        unsafe {
+            ffi::av_hash_final_hex(
+                self.context,
+                buffer.as_mut_ptr(),
+                buffer.len() as std::ffi::c_int,
+            );
+            ffi::av_hash_init(self.context);
+        }
+        std::ffi::CStr::from_bytes_until_nul(&buffer)
+            .map(|hash| hash.to_string_lossy().to_lowercase())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for HashContext {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::av_hash_freep(&mut self.context);
+        }
+    }
+}
+
+/// Audio sample FIFO, used to regroup audio samples into frames of another size. Wraps
+/// `AVAudioFifo`.
+pub struct AudioFifo {
+    fifo: *mut ffi::AVAudioFifo,
+    template: ffmpeg::util::frame::Audio,
+}
+
+impl AudioFifo {
+    /// Create a FIFO for frames with the format, channel layout and sample rate of a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame with the format of the samples.
+    ///
+    /// # Return value
+    ///
+    /// FIFO, or `None` if it could not be allocated.
+    pub fn new(frame: &ffmpeg::util::frame::Audio) -> Option<Self> {
+        unsafe {
+            let frame_ptr = frame.as_ptr();
+            let fifo = ffi::av_audio_fifo_alloc(
+                (*frame_ptr).format as ffi::AVSampleFormat,
+                (*frame_ptr).ch_layout.nb_channels,
+                (*frame_ptr).nb_samples.max(1),
+            );
+            if fifo.is_null() {
+                return None;
+            }
+            let mut template = ffmpeg::util::frame::Audio::empty();
+            if copy_audio_format(frame_ptr, template.as_mut_ptr()) < 0 {
+                ffi::av_audio_fifo_free(fifo);
+                return None;
+            }
+            Some(Self { fifo, template })
+        }
+    }
+
+    /// Whether a frame has the format, channel layout and sample rate of the FIFO.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to check.
+    pub fn matches(&self, frame: &ffmpeg::util::frame::Audio) -> bool {
+        unsafe {
+            let frame_ptr = frame.as_ptr();
+            let template_ptr = self.template.as_ptr();
+            (*frame_ptr).format == (*template_ptr).format
+                && (*frame_ptr).sample_rate == (*template_ptr).sample_rate
+                && ffi::av_channel_layout_compare(
+                    &(*frame_ptr).ch_layout,
+                    &(*template_ptr).ch_layout,
+                ) == 0
+        }
+    }
+
+    /// Add the samples of a frame to the FIFO.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame with the samples. Must match the FIFO, see [`AudioFifo::matches`].
+    pub fn write(&mut self, frame: &ffmpeg::util::frame::Audio) -> Result<(), Error> {
+        unsafe {
+            let frame_ptr = frame.as_ptr();
+            let ret = ffi::av_audio_fifo_write(
+                self.fifo,
+                (*frame_ptr).extended_data as _,
+                (*frame_ptr).nb_samples,
+            );
+            if ret < 0 {
+                return Err(Error::from(ret));
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of samples per channel in the FIFO.
+    pub fn size(&self) -> usize {
+        unsafe { ffi::av_audio_fifo_size(self.fifo).max(0) as usize }
+    }
+
+    /// Take samples from the FIFO into a new frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `samples` - Number of samples per channel to take. Must not exceed [`AudioFifo::size`].
+    pub fn read(&mut self, samples: usize) -> Result<ffmpeg::util::frame::Audio, Error> {
+        let mut frame = ffmpeg::util::frame::Audio::empty();
+        unsafe {
+            let frame_ptr = frame.as_mut_ptr();
+            let ret = copy_audio_format(self.template.as_ptr(), frame_ptr);
+            if ret < 0 {
+                return Err(Error::from(ret));
+            }
+            (*frame_ptr).nb_samples = samples as std::ffi::c_int;
+            let ret = ffi::av_frame_get_buffer(frame_ptr, 0);
+            if ret < 0 {
+                return Err(Error::from(ret));
+            }
+            let ret = ffi::av_audio_fifo_read(
+                self.fifo,
+                (*frame_ptr).extended_data as _,
+                samples as std::ffi::c_int,
+            );
+            if ret < 0 {
+                return Err(Error::from(ret));
+            }
+        }
+        Ok(frame)
+    }
+}
+
+impl Drop for AudioFifo {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::av_audio_fifo_free(self.fifo);
+        }
+    }
+}
+
+/// Copy the sample format, channel layout and sample rate of an audio frame.
+///
+/// # Arguments
+///
+/// * `src` - Frame to copy from.
+/// * `dst` - Frame to copy to.
+unsafe fn copy_audio_format(src: *const ffi::AVFrame, dst: *mut ffi::AVFrame) -> std::ffi::c_int {
+    (*dst).format = (*src).format;
+    (*dst).sample_rate = (*src).sample_rate;
+    ffi::av_channel_layout_copy(&mut (*dst).ch_layout, &(*src).ch_layout)
+}
+
+/// Call a function with the content of a decoded frame, without padding. Video frames yield the
+/// visible part of every plane, audio frames the samples of every plane.
+///
+/// # Arguments
+///
+/// * `frame` - Decoded audio or video frame.
+/// * `on_data` - Called with every piece of content, in order.
+pub fn frame_content(frame: &ffmpeg::util::frame::Frame, mut on_data: impl FnMut(&[u8])) {
+    unsafe {
+        let frame_ptr = frame.as_ptr();
+        if (*frame_ptr).width > 0 && (*frame_ptr).height > 0 {
+            let format = (*frame_ptr).format as ffi::AVPixelFormat;
+            let size =
+                ffi::av_image_get_buffer_size(format, (*frame_ptr).width, (*frame_ptr).height, 1);
+            if size <= 0 {
+                return;
+            }
+            let mut buffer = vec![0_u8; size as usize];
+            let copied = ffi::av_image_copy_to_buffer(
+                buffer.as_mut_ptr(),
+                size,
+                (*frame_ptr).data.as_ptr() as *const *const u8,
+                (*frame_ptr).linesize.as_ptr(),
+                format,
+                (*frame_ptr).width,
+                (*frame_ptr).height,
+                1,
+            );
+            if copied > 0 {
+                on_data(&buffer[..copied as usize]);
+            }
+        } else if (*frame_ptr).nb_samples > 0 {
+            let format = (*frame_ptr).format as ffi::AVSampleFormat;
+            let channels = (*frame_ptr).ch_layout.nb_channels.max(1) as usize;
+            let bytes_per_sample = ffi::av_get_bytes_per_sample(format).max(0) as usize;
+            let (planes, plane_size) = if ffi::av_sample_fmt_is_planar(format) != 0 {
+                (
+                    channels,
+                    (*frame_ptr).nb_samples as usize * bytes_per_sample,
+                )
+            } else {
+                (
+                    1,
+                    (*frame_ptr).nb_samples as usize * bytes_per_sample * channels,
+                )
+            };
+            for plane in 0..planes {
+                let data = *(*frame_ptr).extended_data.add(plane);
+                if !data.is_null() {
+                    on_data(std::slice::from_raw_parts(data, plane_size));
+                }
+            }
+        }
+    }
+}
+
+/// Add an entry to a dictionary without replacing entries with the same key.
+///
+/// # Arguments
+///
+/// * `dictionary` - Dictionary to add to.
+/// * `key` - Key of the entry.
+/// * `value` - Value of the entry.
+pub fn dictionary_append(
+    dictionary: Dictionary<'static>,
+    key: &str,
+    value: &str,
+) -> Dictionary<'static> {
+    let key = std::ffi::CString::new(key).unwrap();
+    let value = std::ffi::CString::new(value).unwrap();
+    unsafe {
+        let mut dictionary = dictionary.disown();
+        ffi::av_dict_set(
+            &mut dictionary,
+            key.as_ptr(),
+            value.as_ptr(),
+            ffi::AV_DICT_MULTIKEY as std::ffi::c_int,
+        );
+        Dictionary::own(dictionary)
+    }
+}
+
+/// Find an input format (demuxer or input device) by name.
+///
+/// # Arguments
+///
+/// * `name` - Name of the input format, like `rawvideo`.
+pub fn find_input_format(name: &str) -> Option<ffmpeg::format::format::Input> {
+    let name = std::ffi::CString::new(name).ok()?;
+    unsafe {
+        let format = ffi::av_find_input_format(name.as_ptr());
+        if format.is_null() {
+            None
+        } else {
+            Some(ffmpeg::format::format::Input::wrap(format as *mut _))
+        }
+    }
+}
+
+/// Find an output format (muxer) by name.
+///
+/// # Arguments
+///
+/// * `name` - Name of the output format, like `mp4`.
+pub fn find_output_format(name: &str) -> Option<ffmpeg::format::format::Output> {
+    let name = std::ffi::CString::new(name).ok()?;
+    unsafe {
+        let format = ffi::av_guess_format(name.as_ptr(), std::ptr::null(), std::ptr::null());
+        if format.is_null() {
+            None
+        } else {
+            Some(ffmpeg::format::format::Output::wrap(format as *mut _))
+        }
+    }
+}
+
+/// Check whether an output format can store a codec. Wraps `avformat_query_codec`.
+///
+/// # Arguments
+///
+/// * `format` - Output format to check.
+/// * `codec_id` - Codec to check.
+/// * `experimental` - Whether or not to allow experimental support (`FF_COMPLIANCE_EXPERIMENTAL`
+///   instead of `FF_COMPLIANCE_NORMAL`).
+///
+/// # Return value
+///
+/// `Some(true)` if the format can store the codec, `Some(false)` if it cannot, and `None` if the
+/// format does not tell.
+pub fn query_codec(
+    format: &ffmpeg::format::format::Output,
+    codec_id: ffmpeg::codec::Id,
+    experimental: bool,
+) -> Option<bool> {
+    let compliance = if experimental {
+        ffi::FF_COMPLIANCE_EXPERIMENTAL as std::ffi::c_int
+    } else {
+        ffi::FF_COMPLIANCE_NORMAL as std::ffi::c_int
+    };
+    match unsafe { ffi::avformat_query_codec(format.as_ptr(), codec_id.into(), compliance) } {
+        1 => Some(true),
+        0 => Some(false),
+        _ => None,
+    }
+}
+
+/// Get the codec an output format uses for a media type by default.
+///
+/// # Arguments
+///
+/// * `format` - Output format.
+/// * `media_type` - Media type, only video, audio and subtitles have defaults.
+pub fn default_codec(
+    format: &ffmpeg::format::format::Output,
+    media_type: ffmpeg::media::Type,
+) -> ffmpeg::codec::Id {
+    unsafe {
+        let format = format.as_ptr();
+        match media_type {
+            ffmpeg::media::Type::Video => (*format).video_codec.into(),
+            ffmpeg::media::Type::Audio => (*format).audio_codec.into(),
+            ffmpeg::media::Type::Subtitle => (*format).subtitle_codec.into(),
+            _ => ffmpeg::codec::Id::None,
+        }
+    }
+}
+
+/// Write the header of an output, and override container metadata after the muxer initialized.
+/// Some metadata, like `encoder`, is set by ffmpeg when initializing the muxer and cannot be
+/// overridden before that.
+///
+/// # Arguments
+///
+/// * `output` - Output to write header of.
+/// * `options` - Muxer options.
+/// * `metadata` - Container metadata to set after initializing the muxer.
+pub fn write_header_with_metadata(
+    output: &mut Output,
+    options: Dictionary,
+    metadata: &[(String, String)],
+) -> Result<(), Error> {
+    unsafe {
+        let output_ptr = output.as_mut_ptr();
+        let mut opts = options.disown();
+        let ret = ffi::avformat_init_output(output_ptr, &mut opts);
+        Dictionary::own(opts);
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+
+        for (key, value) in metadata {
+            let key = std::ffi::CString::new(key.as_str()).map_err(|_| Error::InvalidData)?;
+            let value = std::ffi::CString::new(value.as_str()).map_err(|_| Error::InvalidData)?;
+            ffi::av_dict_set(&mut (*output_ptr).metadata, key.as_ptr(), value.as_ptr(), 0);
+        }
+
+        match ffi::avformat_write_header(output_ptr, std::ptr::null_mut()) {
+            ret if ret < 0 => Err(Error::from(ret)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Set the advertised bit rate of an output stream. The maximum rate and buffer size are stored as
+/// CPB properties, which muxers like `mp4` write to the container.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+/// * `stream_index` - Index of the stream in the output.
+/// * `bit_rate` - Average bit rate in bits per second.
+/// * `max_rate` - Maximum bit rate in bits per second, if known.
+/// * `buffer_size` - Decoder buffer size in bits, if known.
+pub fn set_stream_bit_rate(
+    output: &mut Output,
+    stream_index: usize,
+    bit_rate: u64,
+    max_rate: Option<u64>,
+    buffer_size: Option<u64>,
+) -> Result<(), Error> {
+    unsafe {
+        let output_ptr = output.as_mut_ptr();
+        if stream_index >= (*output_ptr).nb_streams as usize {
+            return Err(Error::StreamNotFound);
+        }
+        let stream = *(*output_ptr).streams.add(stream_index);
+        let codecpar = (*stream).codecpar;
+        (*codecpar).bit_rate = bit_rate.min(i64::MAX as u64) as i64;
+        if max_rate.is_none() && buffer_size.is_none() {
+            return Ok(());
+        }
+        let properties = new_stream_side_data(
+            stream,
+            ffi::AV_PKT_DATA_CPB_PROPERTIES,
+            std::mem::size_of::<ffi::AVCPBProperties>(),
+        ) as *mut ffi::AVCPBProperties;
+        if properties.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+        std::ptr::write_bytes(properties, 0, 1);
+        (*properties).avg_bitrate = (*codecpar).bit_rate;
+        (*properties).max_bitrate = max_rate.map_or(0, |rate| rate.min(i64::MAX as u64) as i64);
+        (*properties).buffer_size = buffer_size.map_or(0, |size| size.min(i64::MAX as u64) as i64);
+        (*properties).vbv_delay = u64::MAX;
+    }
+    Ok(())
+}
+
+/// Add side data to a stream, replacing any side data of the same type. FFmpeg 6.1 moved stream
+/// side data to the codec parameters, where the muxer takes it from. Older versions only have the
+/// side data of the stream itself.
+///
+/// # Arguments
+///
+/// * `stream` - Stream to add side data to.
+/// * `kind` - Type of side data.
+/// * `size` - Size of the side data in bytes.
+///
+/// # Return value
+///
+/// The uninitialized payload of the side data, or null if it could not be allocated.
+#[cfg(feature = "ffmpeg7")]
+unsafe fn new_stream_side_data(
+    stream: *mut ffi::AVStream,
+    kind: ffi::AVPacketSideDataType,
+    size: usize,
+) -> *mut u8 {
+    let codecpar = (*stream).codecpar;
+    ffi::av_packet_side_data_remove(
+        (*codecpar).coded_side_data,
+        &mut (*codecpar).nb_coded_side_data,
+        kind,
+    );
+    let side_data = ffi::av_packet_side_data_new(
+        &mut (*codecpar).coded_side_data,
+        &mut (*codecpar).nb_coded_side_data,
+        kind,
+        size,
+        0,
+    );
+    if side_data.is_null() {
+        std::ptr::null_mut()
+    } else {
+        (*side_data).data
+    }
+}
+
+/// Add side data to a stream, replacing any side data of the same type. See the FFmpeg 7 version.
+#[cfg(not(feature = "ffmpeg7"))]
+unsafe fn new_stream_side_data(
+    stream: *mut ffi::AVStream,
+    kind: ffi::AVPacketSideDataType,
+    size: usize,
+) -> *mut u8 {
+    ffi::av_stream_new_side_data(stream, kind, size as _)
+}
+
+/// Make the payload of a packet writable, copying it if the buffer is shared with other packets.
+///
+/// # Arguments
+///
+/// * `packet` - Packet to make writable.
+pub fn make_packet_writable(packet: &mut ffmpeg::Packet) -> Result<(), Error> {
+    unsafe {
+        match ffi::av_packet_make_writable(packet.as_mut_ptr()) {
+            0 => Ok(()),
+            e => Err(Error::from(e)),
+        }
+    }
+}
+
+/// Replace the payload of a packet, keeping its timestamps, flags, stream index and side data.
+///
+/// # Arguments
+///
+/// * `packet` - Packet to replace the payload of.
+/// * `data` - New payload.
+pub fn replace_packet_data(packet: &mut ffmpeg::Packet, data: &[u8]) -> Result<(), Error> {
+    let mut replacement = ffmpeg::Packet::copy(data);
+    unsafe {
+        match ffi::av_packet_copy_props(replacement.as_mut_ptr(), packet.as_ptr()) {
+            0 => {}
+            e => return Err(Error::from(e)),
+        }
+    }
+    *packet = replacement;
+    Ok(())
+}
+
+/// Mark samples at the start of an audio packet to be discarded after decoding, with
+/// `AV_PKT_DATA_SKIP_SAMPLES` side data, to trim audio to the sample.
+///
+/// # Arguments
+///
+/// * `packet` - Audio packet.
+/// * `skip_start` - Number of samples (per channel) to discard from the start of the packet.
+pub fn set_packet_skip_samples(packet: &mut ffmpeg::Packet, skip_start: u32) -> Result<(), Error> {
+    // Skip from the start and from the end as 32-bit little endian integers, followed by the
+    // reasons for both, which are not set.
+    const SKIP_SAMPLES_SIZE: usize = 10;
+    unsafe {
+        // Side data of the same type is replaced.
+        let data = ffi::av_packet_new_side_data(
+            packet.as_mut_ptr(),
+            ffi::AVPacketSideDataType::AV_PKT_DATA_SKIP_SAMPLES,
+            SKIP_SAMPLES_SIZE as _,
+        );
+        if data.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+        let data = std::slice::from_raw_parts_mut(data, SKIP_SAMPLES_SIZE);
+        data.fill(0);
+        data[..4].copy_from_slice(&skip_start.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Get the sample rate of an audio stream from its codec parameters, or zero for other streams.
+///
+/// # Arguments
+///
+/// * `parameters` - Codec parameters of the stream.
+pub fn parameters_sample_rate(parameters: &ffmpeg::codec::Parameters) -> u32 {
+    unsafe { (*parameters.as_ptr()).sample_rate.max(0) as u32 }
+}
+
+/// Remove the side data of a packet of which the type is not kept.
+///
+/// # Arguments
+///
+/// * `packet` - Packet to remove side data from.
+/// * `keep` - Whether to keep side data of a type.
+pub fn retain_packet_side_data(
+    packet: &mut ffmpeg::Packet,
+    keep: impl Fn(ffi::AVPacketSideDataType) -> bool,
+) {
+    unsafe {
+        let packet_ptr = packet.as_mut_ptr();
+        retain_side_data(
+            (*packet_ptr).side_data,
+            &mut (*packet_ptr).side_data_elems,
+            keep,
+        );
+    }
+}
+
+/// Remove the side data of an output stream of which the type is not kept.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+/// * `stream_index` - Index of the stream in the output.
+/// * `keep` - Whether to keep side data of a type.
+pub fn retain_stream_side_data(
+    output: &mut Output,
+    stream_index: usize,
+    keep: impl Fn(ffi::AVPacketSideDataType) -> bool,
+) {
+    unsafe {
+        let output_ptr = output.as_mut_ptr();
+        if stream_index >= (*output_ptr).nb_streams as usize {
+            return;
+        }
+        let stream = *(*output_ptr).streams.add(stream_index);
+        #[cfg(feature = "ffmpeg7")]
+        retain_side_data(
+            (*(*stream).codecpar).coded_side_data,
+            &mut (*(*stream).codecpar).nb_coded_side_data,
+            keep,
+        );
+        // Before FFmpeg 6.1, stream side data is kept on the stream itself.
+        #[cfg(not(feature = "ffmpeg7"))]
+        retain_side_data((*stream).side_data, &mut (*stream).nb_side_data, keep);
+    }
+}
+
+/// Remove the side data of a frame of which the type is not kept.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to remove side data from.
+/// * `keep` - Whether to keep side data of a type.
+pub fn retain_frame_side_data(
+    frame: &mut ffmpeg::util::frame::Frame,
+    keep: impl Fn(ffi::AVFrameSideDataType) -> bool,
+) {
+    unsafe {
+        let frame_ptr = frame.as_mut_ptr();
+        let removed = (0..(*frame_ptr).nb_side_data.max(0) as usize)
+            .map(|index| (**(*frame_ptr).side_data.add(index)).type_)
+            .filter(|kind| !keep(*kind))
+            .collect::<Vec<_>>();
+        for kind in removed {
+            ffi::av_frame_remove_side_data(frame_ptr, kind);
+        }
+    }
+}
+
+/// Read the side data of a frame, skipping types that [`FrameSideData`] does not cover.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to read side data from.
+pub fn frame_side_data(frame: &ffmpeg::util::frame::Frame) -> Vec<FrameSideData> {
+    unsafe {
+        let frame_ptr = frame.as_ptr();
+        (0..(*frame_ptr).nb_side_data.max(0) as usize)
+            .filter_map(|index| {
+                let side_data = *(*frame_ptr).side_data.add(index);
+                #[allow(clippy::unnecessary_cast)]
+                let data = if (*side_data).data.is_null() {
+                    &[][..]
+                } else {
+                    std::slice::from_raw_parts((*side_data).data, (*side_data).size as usize)
+                };
+                match (*side_data).type_ {
+                    ffi::AV_FRAME_DATA_A53_CC => Some(FrameSideData::ClosedCaptions(data.to_vec())),
+                    ffi::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA => {
+                        let metadata = read_side_data::<ffi::AVMasteringDisplayMetadata>(data)?;
+                        let point = |point: [ffi::AVRational; 2]| {
+                            (Rational::from(point[0]), Rational::from(point[1]))
+                        };
+                        Some(FrameSideData::MasteringDisplay(MasteringDisplay {
+                            primaries: (metadata.has_primaries != 0).then(|| DisplayPrimaries {
+                                red: point(metadata.display_primaries[0]),
+                                green: point(metadata.display_primaries[1]),
+                                blue: point(metadata.display_primaries[2]),
+                                white_point: point(metadata.white_point),
+                            }),
+                            luminance: (metadata.has_luminance != 0).then(|| {
+                                (
+                                    Rational::from(metadata.min_luminance),
+                                    Rational::from(metadata.max_luminance),
+                                )
+                            }),
+                        }))
+                    }
+                    ffi::AV_FRAME_DATA_CONTENT_LIGHT_LEVEL => {
+                        let metadata = read_side_data::<ffi::AVContentLightMetadata>(data)?;
+                        Some(FrameSideData::ContentLightLevel(ContentLightLevel {
+                            max_content: metadata.MaxCLL,
+                            max_frame_average: metadata.MaxFALL,
+                        }))
+                    }
+                    ffi::AV_FRAME_DATA_SEI_UNREGISTERED => {
+                        FrameSideData::parse_sei_unregistered(data)
+                    }
+                    ffi::AV_FRAME_DATA_S12M_TIMECODE => FrameSideData::parse_timecodes(data),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Read a struct from side data, or `None` if the side data is too small to hold one, like a
+/// truncated entry of a corrupt file.
+///
+/// # Arguments
+///
+/// * `data` - Side data.
+fn read_side_data<T: Copy>(data: &[u8]) -> Option<T> {
+    (data.len() >= std::mem::size_of::<T>())
+        // The slice holds enough bytes for a `T`, which is plain old data.
+        .then(|| unsafe { std::ptr::read_unaligned(data.as_ptr().cast::<T>()) })
+}
+
+/// Attach side data to a frame. Side data of the same type is replaced, except for unregistered
+/// SEI messages, of which a frame can have several.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to attach side data to.
+/// * `side_data` - Side data to attach.
+pub fn set_frame_side_data(
+    frame: &mut ffmpeg::util::frame::Frame,
+    side_data: &FrameSideData,
+) -> Result<(), Error> {
+    let out_of_memory = Error::Other {
+        errno: ffmpeg::util::error::ENOMEM,
+    };
+    unsafe {
+        let frame_ptr = frame.as_mut_ptr();
+        match side_data {
+            FrameSideData::MasteringDisplay(display) => {
+                ffi::av_frame_remove_side_data(
+                    frame_ptr,
+                    ffi::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA,
+                );
+                let metadata = ffi::av_mastering_display_metadata_create_side_data(frame_ptr);
+                if metadata.is_null() {
+                    return Err(out_of_memory);
+                }
+                let point = |point: (Rational, Rational)| [point.0.into(), point.1.into()];
+                if let Some(primaries) = display.primaries {
+                    (*metadata).display_primaries = [
+                        point(primaries.red),
+                        point(primaries.green),
+                        point(primaries.blue),
+                    ];
+                    (*metadata).white_point = point(primaries.white_point);
+                    (*metadata).has_primaries = 1;
+                }
+                if let Some((min, max)) = display.luminance {
+                    (*metadata).min_luminance = min.into();
+                    (*metadata).max_luminance = max.into();
+                    (*metadata).has_luminance = 1;
+                }
+            }
+            FrameSideData::ContentLightLevel(level) => {
+                ffi::av_frame_remove_side_data(frame_ptr, ffi::AV_FRAME_DATA_CONTENT_LIGHT_LEVEL);
+                let metadata = ffi::av_content_light_metadata_create_side_data(frame_ptr);
+                if metadata.is_null() {
+                    return Err(out_of_memory);
+                }
+                (*metadata).MaxCLL = level.max_content;
+                (*metadata).MaxFALL = level.max_frame_average;
+            }
+            FrameSideData::ClosedCaptions(_)
+            | FrameSideData::SeiUnregistered { .. }
+            | FrameSideData::Timecodes(_) => {
+                let kind = match side_data {
+                    FrameSideData::ClosedCaptions(_) => ffi::AV_FRAME_DATA_A53_CC,
+                    FrameSideData::SeiUnregistered { .. } => ffi::AV_FRAME_DATA_SEI_UNREGISTERED,
+                    _ => ffi::AV_FRAME_DATA_S12M_TIMECODE,
+                };
+                if kind != ffi::AV_FRAME_DATA_SEI_UNREGISTERED {
+                    ffi::av_frame_remove_side_data(frame_ptr, kind);
+                }
+                let bytes = side_data.to_bytes().unwrap_or_default();
+                let new_side_data = ffi::av_frame_new_side_data(frame_ptr, kind, bytes.len() as _);
+                if new_side_data.is_null() {
+                    return Err(out_of_memory);
+                }
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), (*new_side_data).data, bytes.len());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy the side data of a frame to another frame, referencing the buffers of the side data
+/// instead of copying them.
+///
+/// # Arguments
+///
+/// * `src` - Frame to get side data from.
+/// * `dst` - Frame to copy side data to.
+pub fn copy_frame_side_data(src: &Frame, dst: &mut Frame) -> Result<(), Error> {
+    unsafe {
+        let src_ptr = src.as_ptr();
+        for index in 0..(*src_ptr).nb_side_data.max(0) as usize {
+            let side_data = *(*src_ptr).side_data.add(index);
+            let mut buf = ffi::av_buffer_ref((*side_data).buf);
+            let copy = if buf.is_null() {
+                std::ptr::null_mut()
+            } else {
+                ffi::av_frame_new_side_data_from_buf(dst.as_mut_ptr(), (*side_data).type_, buf)
+            };
+            if copy.is_null() {
+                ffi::av_buffer_unref(&mut buf);
+                return Err(Error::Other {
+                    errno: ffmpeg::util::error::ENOMEM,
+                });
+            }
+            ffi::av_dict_copy(&mut (*copy).metadata, (*side_data).metadata, 0);
+        }
+    }
+    Ok(())
+}
+
+/// Remove the entries of a side data array of which the type is not kept.
+///
+/// # Arguments
+///
+/// * `side_data` - Side data array.
+/// * `count` - Number of entries in the array.
+/// * `keep` - Whether to keep side data of a type.
+unsafe fn retain_side_data(
+    side_data: *mut ffi::AVPacketSideData,
+    count: *mut std::ffi::c_int,
+    keep: impl Fn(ffi::AVPacketSideDataType) -> bool,
+) {
+    let removed = (0..(*count).max(0) as usize)
+        .map(|index| (*side_data.add(index)).type_)
+        .filter(|kind| !keep(*kind))
+        .collect::<Vec<_>>();
+    for kind in removed {
+        remove_side_data(side_data, count, kind);
+    }
+}
+
+/// Remove the entries of a type from a side data array.
+///
+/// # Arguments
+///
+/// * `side_data` - Side data array.
+/// * `count` - Number of entries in the array.
+/// * `kind` - Type of side data to remove.
+#[cfg(feature = "ffmpeg7")]
+unsafe fn remove_side_data(
+    side_data: *mut ffi::AVPacketSideData,
+    count: *mut std::ffi::c_int,
+    kind: ffi::AVPacketSideDataType,
+) {
+    ffi::av_packet_side_data_remove(side_data, count, kind);
+}
+
+/// Remove the entries of a type from a side data array. `av_packet_side_data_remove` only exists
+/// since FFmpeg 6.1, so this does the same: free the payload and move the last entry into its
+/// place.
+#[cfg(not(feature = "ffmpeg7"))]
+unsafe fn remove_side_data(
+    side_data: *mut ffi::AVPacketSideData,
+    count: *mut std::ffi::c_int,
+    kind: ffi::AVPacketSideDataType,
+) {
+    let mut index = 0;
+    while index < (*count).max(0) as usize {
+        let entry = side_data.add(index);
+        if (*entry).type_ != kind {
+            index += 1;
+            continue;
+        }
+        ffi::av_freep(&mut (*entry).data as *mut *mut u8 as *mut std::ffi::c_void);
+        *count -= 1;
+        *entry = *side_data.add(*count as usize);
+    }
+}
+
+/// Set the overall bit rate of an output, for muxers that write it to the container.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+/// * `bit_rate` - Bit rate in bits per second.
+pub fn set_output_bit_rate(output: &mut Output, bit_rate: u64) {
+    unsafe {
+        (*output.as_mut_ptr()).bit_rate = bit_rate.min(i64::MAX as u64) as i64;
+    }
+}
+
+/// Get the position of the IO context of an output, which is the number of bytes written for
+/// outputs that are not seeked.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+///
+/// # Return value
+///
+/// Position in bytes, or `None` if the output does not have an IO context.
+pub fn output_position(output: &Output) -> Option<u64> {
+    // Same value as `SEEK_CUR` in the C library on all supported platforms.
+    const SEEK_CUR: std::ffi::c_int = 1;
+
+    unsafe {
+        let io = (*output.as_ptr()).pb;
+        if io.is_null() {
+            return None;
+        }
+        // Seeking zero bytes from the current position returns the position without seeking.
+        u64::try_from(ffi::avio_seek(io, 0, SEEK_CUR)).ok()
+    }
+}
+
+/// Mark the IO context of an output as not seekable, so that muxers write strictly sequentially
+/// instead of seeking back to patch headers.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+pub fn set_output_unseekable(output: &mut Output) {
+    unsafe {
+        let io = (*output.as_mut_ptr()).pb;
+        if !io.is_null() {
+            (*io).seekable = 0;
+        }
+    }
+}
+
+/// Flush the IO buffer of an output, so that everything the muxer wrote so far reaches the
+/// destination. Does nothing for outputs without IO context.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+pub fn flush_output_io(output: &mut Output) {
+    unsafe {
+        let io = (*output.as_mut_ptr()).pb;
+        if !io.is_null() {
+            ffi::avio_flush(io);
+        }
+    }
+}
+
+/// Make the muxer flush the IO buffer of an output after every packet, like the `flush_packets`
+/// option.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+pub fn set_output_flush_packets(output: &mut Output) {
+    unsafe {
+        (*output.as_mut_ptr()).flush_packets = 1;
+    }
+}
+
+/// Make the IO context of an output write directly instead of going through its buffer, like the
+/// `avioflags direct` option. Does nothing for outputs without IO context.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+pub fn set_output_direct_io(output: &mut Output) {
+    unsafe {
+        let io = (*output.as_mut_ptr()).pb;
+        if !io.is_null() {
+            (*io).direct = 1;
+        }
+    }
+}
+
+/// Replace the buffer of the IO context of an output with a buffer of another size. Must be called
+/// before anything is written. Does nothing for outputs without IO context.
+///
+/// # Arguments
+///
+/// * `output` - Output context.
+/// * `size` - Size of the buffer in bytes.
+pub fn set_output_io_buffer_size(output: &mut Output, size: usize) -> Result<(), Error> {
+    let size = std::ffi::c_int::try_from(size)
+        .ok()
+        .filter(|size| *size > 0)
+        .ok_or(Error::InvalidData)?;
+    unsafe {
+        let io = (*output.as_mut_ptr()).pb;
+        if io.is_null() {
+            return Ok(());
+        }
+        let buffer = ffi::av_malloc(size as usize) as *mut u8;
+        if buffer.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+        // Nothing was written yet, so the old buffer holds no data.
+        ffi::av_free((*io).buffer as *mut std::ffi::c_void);
+        (*io).buffer = buffer;
+        (*io).buffer_size = size;
+        (*io).buf_ptr = buffer;
+        (*io).buf_ptr_max = buffer;
+        (*io).buf_end = buffer.add(size as usize);
+        // The IO context goes back to its original size after seeking and when shrinking a buffer
+        // that grew, so that size must follow the new buffer or the old size comes back.
+        (*(io as *mut FFIOContext)).orig_buffer_size = size;
+    }
+    Ok(())
+}
+
+/// Rust version of the `FFIOContext` struct in `libavformat`, which every `AVIOContext` is the
+/// public part of. Only the fields up to `orig_buffer_size` are declared, the struct is always
+/// allocated by ffmpeg.
+#[repr(C)]
+struct FFIOContext {
+    _public: ffi::AVIOContext,
+    _short_seek_get: Option<unsafe extern "C" fn(*mut std::ffi::c_void) -> std::ffi::c_int>,
+    _short_seek_threshold: std::ffi::c_int,
+    _current_type: ffi::AVIODataMarkerType,
+    _last_time: i64,
+    _maxsize: i64,
+    _bytes_read: i64,
+    _seek_count: std::ffi::c_int,
+    _writeout_count: std::ffi::c_int,
+    pub orig_buffer_size: std::ffi::c_int,
+}
+
+/// Callback of a format context that opens IO streams.
+type IoOpenCallback = unsafe extern "C" fn(
+    *mut ffi::AVFormatContext,
+    *mut *mut ffi::AVIOContext,
+    *const std::ffi::c_char,
+    std::ffi::c_int,
+    *mut *mut ffi::AVDictionary,
+) -> std::ffi::c_int;
+
+/// Callback of a format context that closes IO streams opened by [`IoOpenCallback`].
+type IoCloseCallback =
+    unsafe extern "C" fn(*mut ffi::AVFormatContext, *mut ffi::AVIOContext) -> std::ffi::c_int;
+
+/// Files that a muxer wrote and closed, recorded by hooks on the IO callbacks of its format
+/// context. Created by `track_output_files`.
+///
+/// Muxers that write more than one file, like HLS, open and close each of them through these
+/// callbacks, and nested muxers share them with their parent. The tracker must outlive the
+/// `Output` it was installed on.
+pub struct OutputFiles {
+    io_open: Option<IoOpenCallback>,
+    io_close2: Option<IoCloseCallback>,
+    /// Files that are open for writing, by IO context.
+    open: Vec<(*mut ffi::AVIOContext, String)>,
+    /// Files that were closed since the last call to `take_closed`, in order.
+    closed: Vec<String>,
+}
+
+impl OutputFiles {
+    /// Take the URLs of the files that were closed since the last time.
+    pub fn take_closed(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.closed)
+    }
+}
+
+/// Install hooks on the IO callbacks of an output that record the files it writes. The hooks
+/// pass everything on to the callbacks that were set before, so must be installed before the
+/// header is written.
+///
+/// The returned `OutputFiles` must be dropped after the `Output`.
+///
+/// # Arguments
+///
+/// * `output` - Output to track the files of.
+pub fn track_output_files(output: &mut Output) -> Box<OutputFiles> {
+    unsafe {
+        let output_ptr = output.as_mut_ptr();
+        let mut files = Box::new(OutputFiles {
+            io_open: (*output_ptr).io_open,
+            io_close2: (*output_ptr).io_close2,
+            open: Vec::new(),
+            closed: Vec::new(),
+        });
+        // The box keeps the address stable while it moves around.
+        (*output_ptr).opaque = files.as_mut() as *mut OutputFiles as *mut std::ffi::c_void;
+        (*output_ptr).io_open = Some(output_files_io_open_callback);
+        (*output_ptr).io_close2 = Some(output_files_io_close_callback);
+        files
+    }
+}
+
+/// Flush the output. This can be useful in some circumstances.options
+///
+/// For example: It is used to flush fragments when outputting fragmented mp4 packets in combination
+/// with the `frag_custom` option.
+///
+/// # Arguments
+///
+/// * `output` - Output context to flush.
+pub fn flush_output(output: &mut Output) -> Result<(), Error> {
+    unsafe {
+        match ffi::av_write_frame(output.as_mut_ptr(), std::ptr::null_mut()) {
+            0 => Ok(()),
+            1 => Ok(()),
+            e => Err(Error::from(e)),
+        }
+    }
+}
+
+/// Initialize a new codec context using a specific codec.
+///
+/// # Arguments
+///
+/// * `codec` - Codec to initialize with.
+pub fn codec_context_as(codec: &Codec) -> Result<Context, Error> {
+    unsafe {
+        let context_ptr = ffmpeg::ffi::avcodec_alloc_context3(codec.as_ptr());
+        if !context_ptr.is_null() {
+            Ok(Context::wrap(context_ptr, None))
+        } else {
+            Err(Error::Unknown)
+        }
+    }
+}
+
+/// Set the `time_base` field of a decoder. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context.
+/// * `time_base` - Time base to assign.
+pub fn set_decoder_context_time_base(decoder_context: &mut Context, time_base: Rational) {
+    unsafe {
+        (*decoder_context.as_mut_ptr()).time_base = time_base.into();
+    }
+}
+
+/// Set the `lowres` field of a decoder context, to decode at `1 / 2^lowres` of the resolution. Only
+/// decoders with `max_lowres` greater than zero support this.
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context.
+/// * `lowres` - Power of two to divide the resolution by.
+pub fn set_decoder_context_lowres(decoder_context: &mut Context, lowres: i32) {
+    unsafe {
+        (*decoder_context.as_mut_ptr()).lowres = lowres;
+    }
+}
+
+/// Set the `max_pixels` field of a decoder context. The decoder refuses to allocate frames with more
+/// pixels than this, including after a resolution change in the middle of the stream.
+///
+/// # Arguments
+///
+/// * `decoder_context` - Decoder context.
+/// * `max_pixels` - Maximum number of pixels per frame.
+pub fn set_decoder_context_max_pixels(decoder_context: &mut Context, max_pixels: u64) {
+    unsafe {
+        (*decoder_context.as_mut_ptr()).max_pixels = max_pixels.min(i64::MAX as u64) as i64;
+    }
+}
+
+/// Get the `time_base` field of an encoder. (Not natively supported in the public API.)
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to get `time_base` of.
+pub fn get_encoder_time_base(encoder: &Video) -> Rational {
+    unsafe { (*encoder.0.as_ptr()).time_base.into() }
+}
+
+/// Match the profile, level, color properties and bit rate of an encoder to the codec parameters
+/// of a source stream, such that the encoded bitstream is compatible with the source.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to configure before opening.
+/// * `parameters` - Codec parameters of the source stream.
+pub fn match_encoder_to_parameters(encoder: &mut Video, parameters: &ffmpeg::codec::Parameters) {
+    unsafe {
+        let encoder_ptr = encoder.as_mut_ptr();
+        let parameters_ptr = parameters.as_ptr();
+        (*encoder_ptr).profile = (*parameters_ptr).profile;
+        (*encoder_ptr).level = (*parameters_ptr).level;
+        (*encoder_ptr).color_range = (*parameters_ptr).color_range;
+        (*encoder_ptr).color_primaries = (*parameters_ptr).color_primaries;
+        (*encoder_ptr).color_trc = (*parameters_ptr).color_trc;
+        (*encoder_ptr).colorspace = (*parameters_ptr).color_space;
+        (*encoder_ptr).chroma_sample_location = (*parameters_ptr).chroma_location;
+        (*encoder_ptr).sample_aspect_ratio = (*parameters_ptr).sample_aspect_ratio;
+        (*encoder_ptr).field_order = (*parameters_ptr).field_order;
+        if (*parameters_ptr).bit_rate > 0 {
+            (*encoder_ptr).bit_rate = (*parameters_ptr).bit_rate;
+        }
+    }
+}
+
+/// Check whether a codec has a private option, like `tile-columns` of `libvpx-vp9`.
+///
+/// # Arguments
+///
+/// * `codec` - Codec to check.
+/// * `name` - Name of the option.
+pub fn codec_has_private_option(codec: &Codec, name: &str) -> bool {
+    let Ok(name) = std::ffi::CString::new(name) else {
+        return false;
+    };
+    unsafe {
+        let class = (*codec.as_ptr()).priv_class;
+        if class.is_null() {
+            return false;
+        }
+        // Searching the class with a fake object finds options without an allocated context.
+        let mut class = class;
+        !ffi::av_opt_find(
+            &mut class as *mut _ as *mut std::ffi::c_void,
+            name.as_ptr(),
+            std::ptr::null(),
+            0,
+            ffi::AV_OPT_SEARCH_FAKE_OBJ as std::ffi::c_int,
+        )
+        .is_null()
+    }
+}
+
+/// Get the options of an opened video encoder that differ from their defaults, as they are in
+/// effect after opening: the options of the codec context first, then the private options of the
+/// encoder. The number of threads is always included, since encoders like `libx264` encode
+/// differently with another number of threads.
+///
+/// # Arguments
+///
+/// * `encoder` - Opened encoder.
+pub fn encoder_effective_options(
+    encoder: &ffmpeg::encoder::video::Encoder,
+) -> Vec<(String, String)> {
+    const KEY_VALUE_SEPARATOR: u8 = b'=';
+    const PAIRS_SEPARATOR: u8 = b':';
+    let opt_flags = (ffi::AV_OPT_FLAG_ENCODING_PARAM | ffi::AV_OPT_FLAG_VIDEO_PARAM) as i32;
+    let mut options = Vec::new();
+    unsafe {
+        let context = encoder.as_ptr();
+        for object in [context as *mut std::ffi::c_void, (*context).priv_data] {
+            if object.is_null() {
+                continue;
+            }
+            let mut buffer: *mut std::ffi::c_char = std::ptr::null_mut();
+            let ret = ffi::av_opt_serialize(
+                object,
+                opt_flags,
+                ffi::AV_OPT_SERIALIZE_SKIP_DEFAULTS as i32,
+                &mut buffer,
+                KEY_VALUE_SEPARATOR as std::ffi::c_char,
+                PAIRS_SEPARATOR as std::ffi::c_char,
+            );
+            if ret < 0 || buffer.is_null() {
+                continue;
+            }
+            let serialized = std::ffi::CStr::from_ptr(buffer)
+                .to_string_lossy()
+                .into_owned();
+            ffi::av_free(buffer as *mut std::ffi::c_void);
+            // Read-only options cannot be set when restoring the options.
+            options.extend(
+                parse_serialized_options(
+                    &serialized,
+                    KEY_VALUE_SEPARATOR as char,
+                    PAIRS_SEPARATOR as char,
+                )
+                .into_iter()
+                .filter(|(key, _)| {
+                    let Ok(name) = std::ffi::CString::new(key.as_str()) else {
+                        return false;
+                    };
+                    let option = ffi::av_opt_find(object, name.as_ptr(), std::ptr::null(), 0, 0);
+                    !option.is_null()
+                        && (*option).flags & ffi::AV_OPT_FLAG_READONLY as std::ffi::c_int == 0
+                }),
+            );
+        }
+        options.retain(|(key, _)| key != "threads");
+        options.push(("threads".to_string(), (*context).thread_count.to_string()));
+    }
+    options
+}
+
+/// Parse options serialized by `av_opt_serialize`, which escapes separators in keys and values
+/// with a backslash.
+///
+/// # Arguments
+///
+/// * `serialized` - Serialized options.
+/// * `key_value_separator` - Separator of keys and values.
+/// * `pairs_separator` - Separator of options.
+fn parse_serialized_options(
+    serialized: &str,
+    key_value_separator: char,
+    pairs_separator: char,
+) -> Vec<(String, String)> {
+    let mut options = Vec::new();
+    let (mut key, mut value, mut in_value) = (String::new(), String::new(), false);
+    let mut chars = serialized.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    if in_value {
+                        value.push(escaped);
+                    } else {
+                        key.push(escaped);
+                    }
+                }
+            }
+            c if c == key_value_separator && !in_value => in_value = true,
+            c if c == pairs_separator => {
+                options.push((std::mem::take(&mut key), std::mem::take(&mut value)));
+                in_value = false;
+            }
+            c if in_value => value.push(c),
+            c => key.push(c),
+        }
+    }
+    if !key.is_empty() {
+        options.push((key, value));
+    }
+    options
+}
+
+/// Set the size of the rate control buffer (VBV) of an encoder.
+///
+/// # Arguments
+///
+/// * `encoder` - Encoder to configure.
+/// * `buffer_size` - Buffer size in bits.
+pub fn set_encoder_buffer_size(encoder: &mut Video, buffer_size: usize) {
+    unsafe {
+        (*encoder.as_mut_ptr()).rc_buffer_size = buffer_size as std::ffi::c_int;
+    }
+}
+
+/// Maximum size of an encoded subtitle, the same as the `ffmpeg` command line tool uses.
+const SUBTITLE_MAX_SIZE: usize = 1024 * 1024;
+
+/// Copy the ASS header (`subtitle_header`) of a subtitle decoder to a subtitle encoder. Text
+/// subtitle encoders use it to convert the styles of the source.
+///
+/// # Arguments
+///
+/// * `decoder` - Opened subtitle decoder.
+/// * `encoder` - Subtitle encoder to configure before opening.
+pub fn copy_subtitle_header(decoder: &Context, encoder: &mut Context) -> Result<(), Error> {
+    unsafe {
+        let decoder_ptr = decoder.as_ptr();
+        let encoder_ptr = encoder.as_mut_ptr();
+        let size = (*decoder_ptr).subtitle_header_size;
+        if (*decoder_ptr).subtitle_header.is_null() || size <= 0 {
+            return Ok(());
+        }
+        // The header is used as string by some encoders, so it gets a nul terminator.
+        let header = ffi::av_mallocz(size as usize + 1) as *mut u8;
+        if header.is_null() {
+            return Err(Error::Other {
+                errno: ffmpeg::util::error::ENOMEM,
+            });
+        }
+        std::ptr::copy_nonoverlapping((*decoder_ptr).subtitle_header, header, size as usize);
+        ffi::av_freep(&mut (*encoder_ptr).subtitle_header as *mut _ as *mut std::ffi::c_void);
+        (*encoder_ptr).subtitle_header = header;
+        (*encoder_ptr).subtitle_header_size = size;
+    }
+    Ok(())
+}
+
+/// Get the ASS header (`subtitle_header`) of a subtitle decoder, with the styles that the ASS
+/// rects of the decoded subtitles refer to.
+///
+/// # Arguments
+///
+/// * `decoder` - Opened subtitle decoder.
+pub fn subtitle_header(decoder: &Context) -> Option<String> {
+    unsafe {
+        let decoder_ptr = decoder.as_ptr();
+        let size = (*decoder_ptr).subtitle_header_size;
+        if (*decoder_ptr).subtitle_header.is_null() || size <= 0 {
+            return None;
+        }
+        let header = std::slice::from_raw_parts((*decoder_ptr).subtitle_header, size as usize);
+        Some(
+            String::from_utf8_lossy(header)
+                .trim_end_matches('\0')
+                .to_string(),
+        )
+    }
+}
+
+/// Encode a subtitle. (The bindings pass the wrong type to `avcodec_encode_subtitle`.)
+///
+/// # Arguments
+///
+/// * `encoder` - Opened subtitle encoder.
+/// * `subtitle` - Subtitle to encode.
+pub fn encode_subtitle(
+    encoder: &mut Context,
+    subtitle: &ffmpeg::Subtitle,
+) -> Result<Vec<u8>, Error> {
+    let mut buffer = vec![0_u8; SUBTITLE_MAX_SIZE];
+    unsafe {
+        let ret = ffi::avcodec_encode_subtitle(
+            encoder.as_mut_ptr(),
+            buffer.as_mut_ptr(),
+            buffer.len() as std::ffi::c_int,
+            subtitle.as_ptr(),
+        );
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+        buffer.truncate(ret as usize);
+    }
+    Ok(buffer)
+}
+
+/// Free the rectangles of a decoded subtitle, which the bindings do not free on drop.
 ///
 /// # Arguments
 ///
-/// * `output` - Output context to end write on.
-pub fn output_raw_packetized_buf_end(output: &mut Output) {
+/// * `subtitle` - Decoded subtitle.
+pub fn free_subtitle(subtitle: &mut ffmpeg::Subtitle) {
     unsafe {
-        let output_pb = (*output.as_mut_ptr()).pb;
-
-        // One last flush (might incur write, most likely won't).
-        ffi::avio_flush(output_pb);
-
-        // Note: No need for handling `opaque` as it is managed by Rust code anyway and will be
-        // freed by it.
-
-        // We do need to free the buffer itself though (we allocatd it manually earlier).
-        ffi::av_free((*output_pb).buffer as *mut std::ffi::c_void);
-        // And deallocate the entire IO context.
-        ffi::av_free(output_pb as *mut std::ffi::c_void);
-
-        // Reset the `pb` field or `avformat_close` will try to free it!
-        ((*output.as_mut_ptr()).pb) = std::ptr::null_mut::<ffi::AVIOContext>();
+        ffi::avsubtitle_free(subtitle.as_mut_ptr());
     }
 }
 
-/// Flush the output. This can be useful in some circumstances.options
+/// Copy frame properties from `src` to `dst`.
 ///
-/// For example: It is used to flush fragments when outputting fragmented mp4 packets in combination
-/// with the `frag_custom` option.
+/// # Arguments
+///
+/// * `src` - Frame to get properties from.
+/// * `dst` - Frame to copy properties to.
+pub fn copy_frame_props(src: &Frame, dst: &mut Frame) {
+    unsafe {
+        ffi::av_frame_copy_props(dst.as_mut_ptr(), src.as_ptr());
+    }
+}
+
+/// Create a new reference to the buffers of a frame, without copying them.
 ///
 /// # Arguments
 ///
-/// * `output` - Output context to flush.
-pub fn flush_output(output: &mut Output) -> Result<(), Error> {
+/// * `frame` - Frame to reference.
+pub fn frame_ref(frame: &Frame) -> Result<Frame, Error> {
+    let mut frame_ref = Frame::empty();
     unsafe {
-        match ffi::av_write_frame(output.as_mut_ptr(), std::ptr::null_mut()) {
-            0 => Ok(()),
-            1 => Ok(()),
+        match ffi::av_frame_ref(frame_ref.as_mut_ptr(), frame.as_ptr()) {
+            0 => Ok(frame_ref),
             e => Err(Error::from(e)),
         }
     }
 }
 
-/// Initialize a new codec context using a specific codec.
+/// Check whether the buffers of a frame are not referenced by any other frame, so that they can
+/// be written to.
 ///
 /// # Arguments
 ///
-/// * `codec` - Codec to initialize with.
-pub fn codec_context_as(codec: &Codec) -> Result<Context, Error> {
+/// * `frame` - Frame to check.
+pub fn frame_is_writable(frame: &mut Frame) -> bool {
+    unsafe { ffi::av_frame_is_writable(frame.as_mut_ptr()) > 0 }
+}
+
+/// Set the `quality` field of a frame, which encoders with a fixed quantizer scale (like MJPEG)
+/// encode the frame with.
+///
+/// # Arguments
+///
+/// * `frame` - Frame to set quality of.
+/// * `qscale` - Quantizer scale.
+pub fn set_frame_qscale(frame: &mut Frame, qscale: u32) {
     unsafe {
-        let context_ptr = ffmpeg::ffi::avcodec_alloc_context3(codec.as_ptr());
-        if !context_ptr.is_null() {
-            Ok(Context::wrap(context_ptr, None))
-        } else {
-            Err(Error::Unknown)
-        }
+        (*frame.as_mut_ptr()).quality = qscale as i32 * ffi::FF_QP2LAMBDA as i32;
     }
 }
 
-/// Set the `time_base` field of a decoder. (Not natively supported in the public API.)
+/// Get the size in bytes of an image with the specified pixel format and dimensions.
 ///
 /// # Arguments
 ///
-/// * `decoder_context` - Decoder context.
-/// * `time_base` - Time base to assign.
-pub fn set_decoder_context_time_base(decoder_context: &mut Context, time_base: Rational) {
+/// * `format` - Pixel format.
+/// * `width` - Image width.
+/// * `height` - Image height.
+pub fn image_buffer_size(format: ffmpeg::util::format::Pixel, width: u32, height: u32) -> usize {
     unsafe {
-        (*decoder_context.as_mut_ptr()).time_base = time_base.into();
+        ffi::av_image_get_buffer_size(format.into(), width as i32, height as i32, 1).max(0) as usize
     }
 }
 
-/// Get the `time_base` field of an encoder. (Not natively supported in the public API.)
+/// Get the total size in bytes of the buffers backing a (video or audio) frame. Frames that share
+/// buffers are counted in full for each frame.
 ///
 /// # Arguments
 ///
-/// * `encoder` - Encoder to get `time_base` of.
-pub fn get_encoder_time_base(encoder: &Video) -> Rational {
-    unsafe { (*encoder.0.as_ptr()).time_base.into() }
+/// * `frame` - Frame to get size of.
+pub fn frame_buffer_size(frame: &ffmpeg::util::frame::Frame) -> usize {
+    unsafe {
+        let frame_ptr = frame.as_ptr();
+        let buffer_size = |buffer: *mut ffi::AVBufferRef| {
+            if buffer.is_null() {
+                0
+            } else {
+                (*buffer).size as usize
+            }
+        };
+        let mut size: usize = (*frame_ptr)
+            .buf
+            .iter()
+            .map(|&buffer| buffer_size(buffer))
+            .sum();
+        for index in 0..(*frame_ptr).nb_extended_buf.max(0) as usize {
+            size += buffer_size(*(*frame_ptr).extended_buf.add(index));
+        }
+        size
+    }
 }
 
-/// Copy frame properties from `src` to `dst`.
+/// Configure the color range of the scaler input and output. The scaler assumes limited range
+/// input unless told otherwise, which shifts colors of full range sources.
 ///
 /// # Arguments
 ///
-/// * `src` - Frame to get properties from.
-/// * `dst` - Frame to copy properties to.
-pub fn copy_frame_props(src: &Frame, dst: &mut Frame) {
+/// * `scaler` - Scaler to configure.
+/// * `src_full_range` - Whether or not the source uses full (JPEG) range.
+/// * `dst_full_range` - Whether or not the destination uses full (JPEG) range.
+pub fn set_scaler_color_range(
+    scaler: &mut Scaler,
+    src_full_range: bool,
+    dst_full_range: bool,
+) -> Result<(), Error> {
     unsafe {
-        ffi::av_frame_copy_props(dst.as_mut_ptr(), src.as_ptr());
+        let mut inv_table: *mut std::ffi::c_int = std::ptr::null_mut();
+        let mut table: *mut std::ffi::c_int = std::ptr::null_mut();
+        let mut src_range: std::ffi::c_int = 0;
+        let mut dst_range: std::ffi::c_int = 0;
+        let mut brightness: std::ffi::c_int = 0;
+        let mut contrast: std::ffi::c_int = 0;
+        let mut saturation: std::ffi::c_int = 0;
+
+        let ret = ffi::sws_getColorspaceDetails(
+            scaler.as_mut_ptr(),
+            &mut inv_table,
+            &mut src_range,
+            &mut table,
+            &mut dst_range,
+            &mut brightness,
+            &mut contrast,
+            &mut saturation,
+        );
+        if ret < 0 {
+            return Err(Error::from(ret));
+        }
+
+        match ffi::sws_setColorspaceDetails(
+            scaler.as_mut_ptr(),
+            inv_table,
+            src_full_range as std::ffi::c_int,
+            table,
+            dst_full_range as std::ffi::c_int,
+            brightness,
+            contrast,
+            saturation,
+        ) {
+            e if e < 0 => Err(Error::from(e)),
+            _ => Ok(()),
+        }
     }
 }
 
@@ -325,19 +2243,31 @@ pub fn convert_ndarray_to_frame_rgb24(frame_array: &FrameArray) -> Result<Frame,
 /// A three-dimensional `ndarray` with dimensions `(H, W, C)` and type byte.
 #[cfg(feature = "ndarray")]
 pub fn convert_frame_to_ndarray_rgb24(frame: &mut Frame) -> Result<FrameArray, Error> {
+    let mut frame_array =
+        FrameArray::default((frame.height() as usize, frame.width() as usize, 3_usize));
+    copy_frame_rgb24_to_buffer(frame, frame_array.as_slice_mut().unwrap())?;
+    Ok(frame_array)
+}
+
+/// Copies the pixels of an RGB24 video `AVFrame` into a buffer without padding, for example into
+/// one frame of a batch.
+///
+/// # Arguments
+///
+/// * `frame` - Video frame to copy.
+/// * `buffer` - Buffer to copy into, of exactly `H * W * 3` bytes.
+#[cfg(feature = "ndarray")]
+pub fn copy_frame_rgb24_to_buffer(frame: &Frame, buffer: &mut [u8]) -> Result<(), Error> {
     unsafe {
-        let frame_ptr = frame.as_mut_ptr();
+        let frame_ptr = frame.as_ptr();
         let frame_width: i32 = (*frame_ptr).width;
         let frame_height: i32 = (*frame_ptr).height;
         let frame_format = (*frame_ptr).format as ffi::AVPixelFormat;
         assert_eq!(frame_format, ffi::AV_PIX_FMT_RGB24);
 
-        let mut frame_array =
-            FrameArray::default((frame_height as usize, frame_width as usize, 3_usize));
-
         let bytes_copied = ffi::av_image_copy_to_buffer(
-            frame_array.as_mut_ptr(),
-            frame_array.len() as i32,
+            buffer.as_mut_ptr(),
+            buffer.len() as i32,
             (*frame_ptr).data.as_ptr() as *const *const u8,
             (*frame_ptr).linesize.as_ptr(),
             frame_format,
@@ -346,8 +2276,8 @@ pub fn convert_frame_to_ndarray_rgb24(frame: &mut Frame) -> Result<FrameArray, E
             1,
         );
 
-        if bytes_copied == frame_array.len() as i32 {
-            Ok(frame_array)
+        if bytes_copied == buffer.len() as i32 {
+            Ok(())
         } else {
             Err(Error::from(bytes_copied))
         }
@@ -399,6 +2329,305 @@ pub fn rtp_seq_and_timestamp(output: &Output) -> (u16, u32) {
     }
 }
 
+/// Set whether or not all packets of a stream are discarded by the demuxer.
+///
+/// # Arguments
+///
+/// * `input` - Input that holds the stream.
+/// * `stream_index` - Index of the stream.
+/// * `discard` - Whether or not to discard the packets.
+pub fn set_stream_discard(input: &mut Input, stream_index: usize, discard: bool) {
+    if let Some(mut stream) = input.stream_mut(stream_index) {
+        unsafe {
+            (*stream.as_mut_ptr()).discard = if discard {
+                ffi::AVDISCARD_ALL
+            } else {
+                ffi::AVDISCARD_DEFAULT
+            };
+        }
+    }
+}
+
+/// Set the disposition flags of a stream of an output.
+///
+/// # Arguments
+///
+/// * `output` - Output that holds the stream.
+/// * `stream_index` - Index of the stream.
+/// * `disposition` - Disposition flags.
+pub fn set_stream_disposition(
+    output: &mut Output,
+    stream_index: usize,
+    disposition: ffmpeg::format::stream::Disposition,
+) {
+    if let Some(mut stream) = output.stream_mut(stream_index) {
+        unsafe {
+            (*stream.as_mut_ptr()).disposition = disposition.bits();
+        }
+    }
+}
+
+/// Set the duration of a stream of an input.
+///
+/// # Arguments
+///
+/// * `input` - Input that holds the stream.
+/// * `stream_index` - Index of the stream.
+/// * `duration` - Duration in the time base of the stream.
+pub fn set_stream_duration(input: &mut Input, stream_index: usize, duration: i64) {
+    if let Some(mut stream) = input.stream_mut(stream_index) {
+        unsafe {
+            (*stream.as_mut_ptr()).duration = duration;
+        }
+    }
+}
+
+/// Set the duration of an input.
+///
+/// # Arguments
+///
+/// * `input` - Input to set the duration of.
+/// * `duration` - Duration in microseconds.
+pub fn set_input_duration(input: &mut Input, duration: i64) {
+    unsafe {
+        (*input.as_mut_ptr()).duration = duration;
+    }
+}
+
+/// List the sources of an input device format, like the audio devices of DirectShow.
+///
+/// # Arguments
+///
+/// * `format` - Input device format.
+///
+/// # Return value
+///
+/// List of device names and descriptions.
+#[cfg(all(feature = "device", not(target_arch = "wasm32")))]
+pub fn list_input_sources(
+    format: &ffmpeg::format::format::Input,
+) -> Result<Vec<(String, String)>, Error> {
+    unsafe {
+        let mut device_list = std::ptr::null_mut();
+        match ffi::avdevice_list_input_sources(
+            format.as_ptr(),
+            std::ptr::null(),
+            std::ptr::null_mut(),
+            &mut device_list,
+        ) {
+            n if n < 0 => Err(Error::from(n)),
+            n => {
+                let devices = (0..n as isize)
+                    .map(|i| {
+                        let info = ffmpeg::device::Info::wrap(*(*device_list).devices.offset(i));
+                        (info.name().to_string(), info.description().to_string())
+                    })
+                    .collect();
+                ffi::avdevice_free_list_devices(&mut device_list);
+                Ok(devices)
+            }
+        }
+    }
+}
+
+/// Send a command to a filter in a configured filter graph, like changing the `volume` of a
+/// `volume` filter while the graph is running.
+///
+/// # Arguments
+///
+/// * `graph` - Filter graph.
+/// * `target` - Name of the filter instance to send the command to.
+/// * `command` - Command, usually the name of the option to change.
+/// * `argument` - Argument of the command, usually the new value of the option.
+#[cfg(feature = "filter")]
+pub fn filter_graph_send_command(
+    graph: &mut ffmpeg::filter::Graph,
+    target: &str,
+    command: &str,
+    argument: &str,
+) -> Result<(), Error> {
+    let target = std::ffi::CString::new(target).map_err(|_| Error::InvalidData)?;
+    let command = std::ffi::CString::new(command).map_err(|_| Error::InvalidData)?;
+    let argument = std::ffi::CString::new(argument).map_err(|_| Error::InvalidData)?;
+    unsafe {
+        match ffi::avfilter_graph_send_command(
+            graph.as_mut_ptr(),
+            target.as_ptr(),
+            command.as_ptr(),
+            argument.as_ptr(),
+            std::ptr::null_mut(),
+            0,
+            0,
+        ) {
+            n if n >= 0 => Ok(()),
+            e => Err(Error::from(e)),
+        }
+    }
+}
+
+/// List the video devices of the AVFoundation input format.
+///
+/// AVFoundation does not implement device listing through `avdevice_list_input_sources`. Instead,
+/// it prints the devices to the log when opened with the `list_devices` option, so the log lines
+/// are captured and parsed.
+///
+/// # Return value
+///
+/// Index and name of each video device, like `(1, "Capture screen 0")`.
+#[cfg(feature = "device")]
+pub fn list_avfoundation_video_devices() -> Result<Vec<(usize, String)>, Error> {
+    let format = find_input_format("avfoundation").ok_or(Error::DemuxerNotFound)?;
+    let mut options = Dictionary::new();
+    options.set("list_devices", "true");
+    // Opening always fails after listing the devices.
+    let (_, lines) =
+        capture_log_lines(|| input_with_options(std::path::Path::new(""), Some(&format), options));
+
+    let mut devices = Vec::new();
+    let mut video_section = false;
+    for line in lines {
+        let line = line.trim();
+        if line.starts_with("AVFoundation video devices") {
+            video_section = true;
+        } else if line.starts_with("AVFoundation audio devices") {
+            video_section = false;
+        } else if video_section {
+            let Some((index, name)) = line
+                .strip_prefix('[')
+                .and_then(|line| line.split_once("] "))
+            else {
+                continue;
+            };
+            if let Ok(index) = index.parse() {
+                devices.push((index, name.to_string()));
+            }
+        }
+    }
+    Ok(devices)
+}
+
+thread_local! {
+    /// Log lines captured on the current thread, if capturing (see [`capture_log_lines`]).
+    static CAPTURED_LOG_LINES: std::cell::RefCell<Option<Vec<String>>> =
+        const { std::cell::RefCell::new(None) };
+}
+
+/// Run a function and capture the log lines ffmpeg emits on the current thread while it runs,
+/// instead of passing them on to `tracing`. Lines are captured without prefix.
+///
+/// This installs the logging handler (see [`init_logging`]) if it was not installed yet.
+///
+/// # Arguments
+///
+/// * `f` - Function to run.
+fn capture_log_lines<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    init_logging();
+    CAPTURED_LOG_LINES.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+    let result = f();
+    let lines = CAPTURED_LOG_LINES
+        .with(|captured| captured.borrow_mut().take())
+        .unwrap_or_default();
+    (result, lines)
+}
+
+/// Override the interval at which the RTSP demuxer sends keepalive requests (`GET_PARAMETER` if the
+/// server supports it, `OPTIONS` otherwise). The demuxer sends keepalive requests while reading
+/// packets only.
+///
+/// Does nothing if the input is not an RTSP input.
+///
+/// # Arguments
+///
+/// * `input` - RTSP input.
+/// * `interval` - Keepalive interval in seconds.
+pub fn rtsp_set_keepalive_interval(input: &mut Input, interval: u32) {
+    if !is_rtsp_input(input) {
+        return;
+    }
+    unsafe {
+        let rtsp_state = &mut *((*input.as_mut_ptr()).priv_data as *mut RTSPState);
+        // The demuxer sends a keepalive request after half of the session timeout has passed.
+        rtsp_state.timeout = interval.max(1).saturating_mul(2).min(i32::MAX as u32) as i32;
+    }
+}
+
+/// Collect RTCP receiver statistics for all RTP streams of an RTSP, RTP or SDP input. The RTP and
+/// SDP demuxers share their state with the RTSP demuxer.
+///
+/// Returns an empty list if the input is not one of those, or if the streams are not transported
+/// over RTP (RealMedia RDT and raw transports).
+///
+/// # Arguments
+///
+/// * `input` - RTSP, RTP or SDP input.
+pub fn rtsp_rtcp_statistics(input: &Input) -> Vec<crate::rtp::RtcpStatistics> {
+    if !is_rtp_input(input) {
+        return Vec::new();
+    }
+    unsafe {
+        let rtsp_state = &*((*input.as_ptr()).priv_data as *const RTSPState);
+        if rtsp_state.transport != RTSP_TRANSPORT_RTP || rtsp_state.rtsp_streams.is_null() {
+            return Vec::new();
+        }
+
+        let now = ffi::av_gettime_relative();
+        let rtsp_streams = std::slice::from_raw_parts(
+            rtsp_state.rtsp_streams,
+            rtsp_state.nb_rtsp_streams.max(0) as usize,
+        );
+        rtsp_streams
+            .iter()
+            .filter(|rtsp_stream| !rtsp_stream.is_null())
+            .map(|rtsp_stream| &**rtsp_stream)
+            .filter(|rtsp_stream| {
+                rtsp_stream.stream_index >= 0 && !rtsp_stream.transport_priv.is_null()
+            })
+            .filter_map(|rtsp_stream| {
+                let demux_context = &*(rtsp_stream.transport_priv as *const RTPDemuxContext);
+                let stream = input.stream(rtsp_stream.stream_index as usize)?;
+                let statistics = &demux_context.statistics;
+
+                // Same computations as the receiver reports ffmpeg sends, see `rtpdec.c`.
+                let extended_max = statistics.cycles as i64 + statistics.max_seq as i64;
+                let expected = extended_max - statistics.base_seq as i64;
+                let lost = expected - statistics.received as i64;
+                // Jitter is kept scaled by 16 and expressed in RTP timestamp units, which share
+                // the time base of the stream.
+                let jitter = crate::time::Time::new(
+                    Some((statistics.jitter >> 4) as i64),
+                    stream.time_base(),
+                );
+                let last_sender_report =
+                    (demux_context.last_rtcp_reception_time != ffi::AV_NOPTS_VALUE).then(|| {
+                        std::time::Duration::from_micros(
+                            (now - demux_context.last_rtcp_reception_time).max(0) as u64,
+                        )
+                    });
+
+                Some(crate::rtp::RtcpStatistics {
+                    stream_index: rtsp_stream.stream_index as usize,
+                    ssrc: demux_context.ssrc,
+                    packets_received: statistics.received as u64,
+                    packets_expected: expected.max(0) as u64,
+                    packets_lost: lost,
+                    jitter: jitter.into(),
+                    last_sender_report,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Whether or not the input is demuxed by the RTSP demuxer.
+fn is_rtsp_input(input: &Input) -> bool {
+    input.format().name() == "rtsp"
+}
+
+/// Whether the input is demuxed by one of the demuxers that share the state of the RTSP demuxer.
+fn is_rtp_input(input: &Input) -> bool {
+    matches!(input.format().name(), "rtsp" | "rtp" | "sdp")
+}
+
 /// Create SDP file contents for the given output. Useful for RTP muxers.
 ///
 /// A media entry will be created for each stream in the output. This function will take care of all
@@ -434,6 +2663,23 @@ pub fn sdp(output: &Output) -> Result<String, Error> {
     }
 }
 
+/// Get the interrupt callback of a format context that aborts blocking operations with
+/// `AVERROR_EXIT` once the interrupt is cancelled.
+///
+/// The callback must be set before the format context is opened, since protocols copy it when they
+/// are opened. The handle (or a clone of it) must outlive the format context, which keeps a
+/// pointer to its state.
+///
+/// # Arguments
+///
+/// * `interrupt` - Interrupt to poll.
+pub fn interrupt_callback(interrupt: &Interrupt) -> ffi::AVIOInterruptCB {
+    ffi::AVIOInterruptCB {
+        callback: Some(interrupt_state_callback),
+        opaque: interrupt.state() as *const InterruptState as *mut std::ffi::c_void,
+    }
+}
+
 /// Initialize the logging handler. This will redirect all ffmpeg logging to the Rust `tracing`
 /// crate and any subscribers to it.
 pub fn init_logging() {
@@ -461,6 +2707,105 @@ extern "C" fn output_raw_buf_start_callback(
     buffer_size
 }
 
+/// IO open callback installed by [`track_output_files`], which opens the stream through the
+/// callback it replaced and records files that are opened for writing.
+unsafe extern "C" fn output_files_io_open_callback(
+    s: *mut ffi::AVFormatContext,
+    pb: *mut *mut ffi::AVIOContext,
+    url: *const std::ffi::c_char,
+    flags: std::ffi::c_int,
+    options: *mut *mut ffi::AVDictionary,
+) -> std::ffi::c_int {
+    // Nested format contexts share the `opaque` of the output.
+    let files = &mut *((*s).opaque as *mut OutputFiles);
+    let Some(io_open) = files.io_open else {
+        return ffi::AVERROR_BUG;
+    };
+    let ret = io_open(s, pb, url, flags, options);
+    if ret >= 0 && flags & ffi::AVIO_FLAG_WRITE as std::ffi::c_int != 0 && !url.is_null() {
+        let url = std::ffi::CStr::from_ptr(url).to_string_lossy().into_owned();
+        files.open.push((*pb, url));
+    }
+    ret
+}
+
+/// IO close callback installed by [`track_output_files`], which records the file that is closed
+/// and closes the stream through the callback it replaced.
+unsafe extern "C" fn output_files_io_close_callback(
+    s: *mut ffi::AVFormatContext,
+    pb: *mut ffi::AVIOContext,
+) -> std::ffi::c_int {
+    let files = &mut *((*s).opaque as *mut OutputFiles);
+    if let Some(index) = files.open.iter().position(|(open, _)| *open == pb) {
+        let (_, url) = files.open.remove(index);
+        files.closed.push(url);
+    }
+    match files.io_close2 {
+        Some(io_close2) => io_close2(s, pb),
+        None => ffi::AVERROR_BUG,
+    }
+}
+
+/// Interrupt callback returned by [`interrupt_callback`], which polls the state held in `opaque`.
+unsafe extern "C" fn interrupt_state_callback(opaque: *mut std::ffi::c_void) -> std::ffi::c_int {
+    let state = &*(opaque as *const InterruptState);
+    state.is_interrupted() as std::ffi::c_int
+}
+
+/// Passthrough function that is passed to `libavformat` in `avio_alloc_context` and reads from the
+/// source held in `opaque`.
+unsafe extern "C" fn input_raw_io_read_callback(
+    opaque: *mut std::ffi::c_void,
+    buffer: *mut u8,
+    buffer_size: std::ffi::c_int,
+) -> std::ffi::c_int {
+    // Acquire a reference to the source transmuted from the `opaque` gotten through `libavformat`.
+    let source: &mut Box<dyn InputSource> = &mut *(opaque as *mut Box<dyn InputSource>);
+    let buffer = std::slice::from_raw_parts_mut(buffer, buffer_size as usize);
+    loop {
+        match source.read(buffer) {
+            Ok(0) => break ffi::AVERROR_EOF,
+            // Number of bytes read.
+            Ok(read) => break read as std::ffi::c_int,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => {
+                tracing::error!("reading from input source failed: {err}");
+                break ffi::AVERROR_EXTERNAL;
+            }
+        }
+    }
+}
+
+/// Passthrough function that is passed to `libavformat` in `avio_alloc_context` and seeks in the
+/// source held in `opaque`.
+unsafe extern "C" fn input_raw_io_seek_callback(
+    opaque: *mut std::ffi::c_void,
+    offset: i64,
+    whence: std::ffi::c_int,
+) -> i64 {
+    use std::io::SeekFrom;
+
+    let source: &mut Box<dyn InputSource> = &mut *(opaque as *mut Box<dyn InputSource>);
+    // `AVSEEK_FORCE` is only a hint.
+    let whence = whence & !(ffi::AVSEEK_FORCE as std::ffi::c_int);
+    let result = match whence as u32 {
+        ffi::AVSEEK_SIZE => source.stream_position().and_then(|position| {
+            let size = source.seek(SeekFrom::End(0))?;
+            source.seek(SeekFrom::Start(position))?;
+            Ok(size)
+        }),
+        // Same values as `SEEK_SET`, `SEEK_CUR` and `SEEK_END`.
+        0 => source.seek(SeekFrom::Start(offset as u64)),
+        1 => source.seek(SeekFrom::Current(offset)),
+        2 => source.seek(SeekFrom::End(offset)),
+        _ => return -1,
+    };
+    match result {
+        Ok(position) => position as i64,
+        Err(_) => -1,
+    }
+}
+
 /// Internal function with C-style callback behavior that receives all log messages from ffmpeg and
 /// handles them with the `log` crate, the Rust way.
 ///
@@ -477,6 +2822,34 @@ unsafe extern "C" fn log_callback(
     #[cfg(all(target_arch = "x86_64", target_family = "unix"))] vl: *mut ffi::__va_list_tag,
     #[cfg(not(all(target_arch = "x86_64", target_family = "unix")))] vl: ffi::va_list,
 ) {
+    // Divert the message if log lines are being captured on this thread.
+    let captured = CAPTURED_LOG_LINES.with(|captured| {
+        let mut captured = captured.borrow_mut();
+        let Some(lines) = captured.as_mut() else {
+            return false;
+        };
+        let mut line = [0; 1024];
+        let mut print_prefix: std::ffi::c_int = 0;
+        let ret = ffi::av_log_format_line2(
+            avcl,
+            level_no,
+            fmt,
+            vl,
+            line.as_mut_ptr(),
+            (line.len()) as std::ffi::c_int,
+            (&mut print_prefix) as *mut std::ffi::c_int,
+        );
+        if ret > 0 {
+            if let Ok(line) = std::ffi::CStr::from_ptr(line.as_mut_ptr()).to_str() {
+                lines.push(line.trim_end().to_string());
+            }
+        }
+        true
+    });
+    if captured {
+        return;
+    }
+
     // Check whether or not the message would be printed at all.
     let val_u32 = level_no as u32;
     let event_would_log = match val_u32 {
@@ -552,6 +2925,94 @@ fn log_filter_hacks(line: &str) -> bool {
     true
 }
 
+/// Value of `RTSP_TRANSPORT_RTP` in the `RTSPTransport` enum in `libavformat`.
+const RTSP_TRANSPORT_RTP: std::ffi::c_int = 0;
+
+/// Rust version of the first fields of the `RTSPState` struct in `libavformat`.
+#[repr(C)]
+struct RTSPState {
+    _av_class: *const ffi::AVClass,
+    _rtsp_hd: *mut std::ffi::c_void,
+    pub nb_rtsp_streams: std::ffi::c_int,
+    pub rtsp_streams: *mut *mut RTSPStream,
+    _state: std::ffi::c_int,
+    _seek_timestamp: i64,
+    _seq: std::ffi::c_int,
+    _session_id: [std::ffi::c_char; 512],
+    pub timeout: std::ffi::c_int,
+    _last_cmd_time: i64,
+    pub transport: std::ffi::c_int,
+}
+
+/// Rust version of the first fields of the `RTSPStream` struct in `libavformat`.
+#[repr(C)]
+struct RTSPStream {
+    _rtp_handle: *mut std::ffi::c_void,
+    pub transport_priv: *mut std::ffi::c_void,
+    pub stream_index: std::ffi::c_int,
+}
+
+/// Rust version of the `SRTPContext` struct in `libavformat`.
+#[repr(C)]
+struct SRTPContext {
+    _aes: *mut std::ffi::c_void,
+    _hmac: *mut std::ffi::c_void,
+    _rtp_hmac_size: std::ffi::c_int,
+    _rtcp_hmac_size: std::ffi::c_int,
+    _master_key: [u8; 16],
+    _master_salt: [u8; 14],
+    _rtp_key: [u8; 16],
+    _rtcp_key: [u8; 16],
+    _rtp_salt: [u8; 14],
+    _rtcp_salt: [u8; 14],
+    _rtp_auth: [u8; 20],
+    _rtcp_auth: [u8; 20],
+    _seq_largest: std::ffi::c_int,
+    _seq_initialized: std::ffi::c_int,
+    _roc: u32,
+    _rtcp_index: u32,
+}
+
+/// Rust version of the `RTPStatistic` struct in `libavformat`.
+#[repr(C)]
+struct RTPStatistic {
+    pub max_seq: u16,
+    pub cycles: u32,
+    pub base_seq: u32,
+    _bad_seq: u32,
+    _probation: std::ffi::c_int,
+    pub received: u32,
+    _expected_prior: u32,
+    _received_prior: u32,
+    _transit: u32,
+    pub jitter: u32,
+}
+
+/// Rust version of the first fields of the `RTPDemuxContext` struct in `libavformat`.
+#[repr(C)]
+struct RTPDemuxContext {
+    _ic: *mut ffi::AVFormatContext,
+    _st: *mut ffi::AVStream,
+    _payload_type: std::ffi::c_int,
+    pub ssrc: u32,
+    _seq: u16,
+    _timestamp: u32,
+    _base_timestamp: u32,
+    _unwrapped_timestamp: i64,
+    _range_start_offset: i64,
+    _max_payload_size: std::ffi::c_int,
+    _hostname: [std::ffi::c_char; 256],
+    _srtp_enabled: std::ffi::c_int,
+    _srtp: SRTPContext,
+    pub statistics: RTPStatistic,
+    _prev_ret: std::ffi::c_int,
+    _queue: *mut std::ffi::c_void,
+    _queue_len: std::ffi::c_int,
+    _queue_size: std::ffi::c_int,
+    _last_rtcp_ntp_time: u64,
+    pub last_rtcp_reception_time: i64,
+}
+
 /// Rust version of the `RTPMuxContext` struct in `libavformat`.
 #[repr(C)]
 struct RTPMuxContext {
@@ -8,12 +8,18 @@ impl HardwareDeviceContext {
     pub fn new(
         device_type: HardwareAccelerationDeviceType,
     ) -> Result<HardwareDeviceContext, ffmpeg::error::Error> {
+        // Device types implemented by wrapper codecs only have no device context.
+        let Some(hwdevice_type) = device_type.hwdevice_type() else {
+            return Err(ffmpeg::error::Error::Other {
+                errno: ffmpeg::util::error::EINVAL,
+            });
+        };
         let mut ptr: *mut ffmpeg::ffi::AVBufferRef = std::ptr::null_mut();
 
         unsafe {
             match ffmpeg::ffi::av_hwdevice_ctx_create(
                 (&mut ptr) as *mut *mut ffmpeg::ffi::AVBufferRef,
-                device_type.into(),
+                hwdevice_type,
                 std::ptr::null(),
                 std::ptr::null_mut(),
                 0,
@@ -24,6 +30,45 @@ impl HardwareDeviceContext {
         }
     }
 
+    /// Create a MediaCodec device context that renders decoded frames to the given surface.
+    #[cfg(target_os = "android")]
+    pub fn new_mediacodec(
+        surface: crate::hwaccel::MediaCodecSurface,
+    ) -> Result<HardwareDeviceContext, ffmpeg::error::Error> {
+        unsafe {
+            let mut ptr =
+                ffmpeg::ffi::av_hwdevice_ctx_alloc(ffmpeg::ffi::AV_HWDEVICE_TYPE_MEDIACODEC);
+            if ptr.is_null() {
+                return Err(ffmpeg::error::Error::Unknown);
+            }
+
+            let device_context = (*ptr).data as *mut ffmpeg::ffi::AVHWDeviceContext;
+            let mediacodec_context = (*device_context).hwctx as *mut AVMediaCodecDeviceContext;
+            match surface {
+                crate::hwaccel::MediaCodecSurface::Surface(surface) => {
+                    (*mediacodec_context).surface = surface;
+                }
+                #[cfg(not(feature = "ffmpeg5"))]
+                crate::hwaccel::MediaCodecSurface::NativeWindow(native_window) => {
+                    (*mediacodec_context).native_window = native_window;
+                }
+                #[cfg(feature = "ffmpeg5")]
+                crate::hwaccel::MediaCodecSurface::NativeWindow(_) => {
+                    ffmpeg::ffi::av_buffer_unref(&mut ptr);
+                    return Err(ffmpeg::error::Error::OptionNotFound);
+                }
+            }
+
+            match ffmpeg::ffi::av_hwdevice_ctx_init(ptr) {
+                0 => Ok(HardwareDeviceContext { ptr }),
+                e => {
+                    ffmpeg::ffi::av_buffer_unref(&mut ptr);
+                    Err(ffmpeg::error::Error::from(e))
+                }
+            }
+        }
+    }
+
     unsafe fn ref_raw(&self) -> *mut ffmpeg::ffi::AVBufferRef {
         ffmpeg::ffi::av_buffer_ref(self.ptr)
     }
@@ -37,6 +82,83 @@ impl Drop for HardwareDeviceContext {
     }
 }
 
+/// Reference to the hardware frames context (`AVHWFramesContext`) that hardware frames are
+/// allocated from.
+pub struct HardwareFramesContext {
+    ptr: *mut ffmpeg::ffi::AVBufferRef,
+}
+
+impl HardwareFramesContext {
+    /// Take a reference to the hardware frames context of a frame, if it is a hardware frame.
+    pub fn from_frame(frame: &ffmpeg::frame::Frame) -> Option<HardwareFramesContext> {
+        unsafe {
+            let hw_frames_ctx = (*frame.as_ptr()).hw_frames_ctx;
+            if hw_frames_ctx.is_null() {
+                return None;
+            }
+            let ptr = ffmpeg::ffi::av_buffer_ref(hw_frames_ctx);
+            (!ptr.is_null()).then_some(HardwareFramesContext { ptr })
+        }
+    }
+
+    /// Pixel format of the frames in hardware memory, like `D3D11`.
+    pub fn format(&self) -> ffmpeg::format::pixel::Pixel {
+        unsafe {
+            (*((*self.ptr).data as *const ffmpeg::ffi::AVHWFramesContext))
+                .format
+                .into()
+        }
+    }
+
+    /// Pixel format of the frames when downloaded to system memory, like `BGRA`.
+    pub fn sw_format(&self) -> ffmpeg::format::pixel::Pixel {
+        unsafe {
+            (*((*self.ptr).data as *const ffmpeg::ffi::AVHWFramesContext))
+                .sw_format
+                .into()
+        }
+    }
+
+    unsafe fn ref_raw(&self) -> *mut ffmpeg::ffi::AVBufferRef {
+        ffmpeg::ffi::av_buffer_ref(self.ptr)
+    }
+}
+
+impl Clone for HardwareFramesContext {
+    fn clone(&self) -> Self {
+        HardwareFramesContext {
+            ptr: unsafe { self.ref_raw() },
+        }
+    }
+}
+
+/// References are equal if they refer to the same hardware frames context.
+impl PartialEq for HardwareFramesContext {
+    fn eq(&self, other: &Self) -> bool {
+        unsafe { (*self.ptr).data == (*other.ptr).data }
+    }
+}
+
+impl std::fmt::Debug for HardwareFramesContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HardwareFramesContext")
+            .field("format", &self.format())
+            .field("sw_format", &self.sw_format())
+            .finish()
+    }
+}
+
+impl Drop for HardwareFramesContext {
+    fn drop(&mut self) {
+        unsafe {
+            ffmpeg::ffi::av_buffer_unref(&mut self.ptr);
+        }
+    }
+}
+
+unsafe impl Send for HardwareFramesContext {}
+unsafe impl Sync for HardwareFramesContext {}
+
 pub fn hwdevice_list_available_device_types() -> Vec<HardwareAccelerationDeviceType> {
     let mut hwdevice_types = Vec::new();
     let mut hwdevice_type =
@@ -64,10 +186,110 @@ pub fn hwdevice_transfer_frame(
     }
 }
 
+/// Map a hardware frame into system memory for reading, without copying it where the device
+/// supports that (like VAAPI surfaces). The mapped frame has the software format of the frames
+/// context and keeps the hardware frame alive.
+pub fn hwdevice_map_frame(
+    target_frame: &mut ffmpeg::frame::Frame,
+    hwdevice_frame: &ffmpeg::frame::Frame,
+) -> Result<(), ffmpeg::error::Error> {
+    unsafe {
+        match ffmpeg::ffi::av_hwframe_map(
+            target_frame.as_mut_ptr(),
+            hwdevice_frame.as_ptr(),
+            ffmpeg::ffi::AV_HWFRAME_MAP_READ as i32,
+        ) {
+            0 => Ok(()),
+            e => Err(ffmpeg::error::Error::from(e)),
+        }
+    }
+}
+
+/// Allocate a frame in pinned (page-locked) host memory of the CUDA device that a hardware frame was
+/// decoded on, to download frames to. The device copies to pinned memory at the full bus speed,
+/// while copies to pageable memory are staged through a buffer of the driver.
+///
+/// # Arguments
+///
+/// * `hwdevice_frame` - Hardware frame that will be downloaded to the frame.
+/// * `format` - Pixel format of the frame.
+/// * `width` - Width of the frame.
+/// * `height` - Height of the frame.
+///
+/// # Return value
+///
+/// The frame, or `None` if the hardware frame is not a CUDA frame or the CUDA driver cannot be
+/// loaded.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+pub fn cuda_pinned_frame(
+    hwdevice_frame: &ffmpeg::frame::Frame,
+    format: ffmpeg::format::pixel::Pixel,
+    width: u32,
+    height: u32,
+) -> Option<ffmpeg::frame::Video> {
+    // Alignment of the planes, to match the frames that ffmpeg allocates.
+    const ALIGN: std::ffi::c_int = 64;
+    unsafe {
+        let hw_frames_ctx = (*hwdevice_frame.as_ptr()).hw_frames_ctx;
+        if hw_frames_ctx.is_null() {
+            return None;
+        }
+        let device_ref =
+            (*((*hw_frames_ctx).data as *const ffmpeg::ffi::AVHWFramesContext)).device_ref;
+        let device_context = (*device_ref).data as *const ffmpeg::ffi::AVHWDeviceContext;
+        if (*device_context).type_ != ffmpeg::ffi::AV_HWDEVICE_TYPE_CUDA {
+            return None;
+        }
+        let driver = cuda::Driver::get()?;
+        let cuda_context = (*((*device_context).hwctx as *const AVCUDADeviceContext)).cuda_ctx;
+
+        let pix_fmt = ffmpeg::ffi::AVPixelFormat::from(format);
+        let size = ffmpeg::ffi::av_image_get_buffer_size(pix_fmt, width as _, height as _, ALIGN);
+        if size <= 0 {
+            return None;
+        }
+        let data = driver.mem_host_alloc(cuda_context, size as usize)?;
+        let owner = Box::into_raw(Box::new(cuda::PinnedBuffer {
+            driver,
+            cuda_context,
+            device_ref: ffmpeg::ffi::av_buffer_ref(device_ref),
+        })) as *mut std::ffi::c_void;
+        let buffer = ffmpeg::ffi::av_buffer_create(
+            data,
+            size as _,
+            Some(cuda::free_pinned_buffer),
+            owner,
+            0,
+        );
+        if buffer.is_null() {
+            cuda::free_pinned_buffer(owner, data);
+            return None;
+        }
+
+        let mut frame = ffmpeg::frame::Video::empty();
+        let frame_ptr = frame.as_mut_ptr();
+        (*frame_ptr).format = pix_fmt as _;
+        (*frame_ptr).width = width as _;
+        (*frame_ptr).height = height as _;
+        (*frame_ptr).buf[0] = buffer;
+        ffmpeg::ffi::av_image_fill_arrays(
+            (*frame_ptr).data.as_mut_ptr(),
+            (*frame_ptr).linesize.as_mut_ptr(),
+            data,
+            pix_fmt,
+            width as _,
+            height as _,
+            ALIGN,
+        );
+        Some(frame)
+    }
+}
+
 pub fn codec_find_corresponding_hwaccel_pixfmt(
     codec: &ffmpeg::codec::codec::Codec,
     hwaccel_type: HardwareAccelerationDeviceType,
 ) -> Option<ffmpeg::format::pixel::Pixel> {
+    let hwdevice_type = hwaccel_type.hwdevice_type()?;
     let mut i = 0;
     loop {
         unsafe {
@@ -76,7 +298,7 @@ pub fn codec_find_corresponding_hwaccel_pixfmt(
                 let hw_config_supports_codec = (((*hw_config).methods) as i32
                     & ffmpeg::ffi::AV_CODEC_HW_CONFIG_METHOD_HW_DEVICE_CTX as i32)
                     != 0;
-                if hw_config_supports_codec && (*hw_config).device_type == hwaccel_type.into() {
+                if hw_config_supports_codec && (*hw_config).device_type == hwdevice_type {
                     break Some((*hw_config).pix_fmt.into());
                 }
             } else {
@@ -87,6 +309,31 @@ pub fn codec_find_corresponding_hwaccel_pixfmt(
     }
 }
 
+/// Check whether a codec can take frames from a hardware frames context of the given pixel format.
+pub fn codec_supports_hw_frames_pixfmt(
+    codec: &ffmpeg::codec::codec::Codec,
+    hw_pixfmt: ffmpeg::format::pixel::Pixel,
+) -> bool {
+    let mut i = 0;
+    loop {
+        unsafe {
+            let hw_config = ffmpeg::ffi::avcodec_get_hw_config(codec.as_ptr(), i);
+            if hw_config.is_null() {
+                break false;
+            }
+            let hw_config_supports_frames = (((*hw_config).methods) as i32
+                & ffmpeg::ffi::AV_CODEC_HW_CONFIG_METHOD_HW_FRAMES_CTX as i32)
+                != 0;
+            if hw_config_supports_frames
+                && (*hw_config).pix_fmt == ffmpeg::ffi::AVPixelFormat::from(hw_pixfmt)
+            {
+                break true;
+            }
+        }
+        i += 1;
+    }
+}
+
 pub fn codec_context_hwaccel_set_get_format(
     codec_context: &mut ffmpeg::codec::context::Context,
     hw_pixfmt: ffmpeg::format::pixel::Pixel,
@@ -102,10 +349,24 @@ pub fn codec_context_hwaccel_set_hw_device_ctx(
     hardware_device_context: &HardwareDeviceContext,
 ) {
     unsafe {
+        ffmpeg::ffi::av_buffer_unref(&mut (*codec_context.as_mut_ptr()).hw_device_ctx);
         (*codec_context.as_mut_ptr()).hw_device_ctx = hardware_device_context.ref_raw();
     }
 }
 
+/// Make an encoder take frames from a hardware frames context, so that hardware frames are encoded
+/// without downloading them.
+pub fn codec_context_set_hw_frames_ctx(
+    codec_context: &mut ffmpeg::codec::context::Context,
+    hardware_frames_context: &HardwareFramesContext,
+) {
+    unsafe {
+        // Release the pool that the encoder was set up with before, if any.
+        ffmpeg::ffi::av_buffer_unref(&mut (*codec_context.as_mut_ptr()).hw_frames_ctx);
+        (*codec_context.as_mut_ptr()).hw_frames_ctx = hardware_frames_context.ref_raw();
+    }
+}
+
 #[no_mangle]
 unsafe extern "C" fn hwaccel_get_format(
     ctx: *mut ffmpeg::ffi::AVCodecContext,
@@ -120,3 +381,172 @@ unsafe extern "C" fn hwaccel_get_format(
     }
     ffmpeg::ffi::AV_PIX_FMT_NONE
 }
+
+/// Rust version of the `AVCUDADeviceContext` struct in `libavutil`. Only the leading field is
+/// declared, the struct is always allocated by ffmpeg.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+#[repr(C)]
+struct AVCUDADeviceContext {
+    cuda_ctx: *mut std::ffi::c_void,
+}
+
+/// Functions of the CUDA driver API, which is loaded at runtime like ffmpeg does, so that there is
+/// no build or link time dependency on CUDA.
+#[cfg(any(target_os = "linux", target_os = "windows"))]
+mod cuda {
+    use std::ffi::{c_char, c_int, c_uint, c_void};
+    use std::sync::OnceLock;
+
+    /// Make pinned memory usable from every CUDA context, not only the one it was allocated in.
+    const CU_MEMHOSTALLOC_PORTABLE: c_uint = 0x01;
+
+    #[cfg(target_os = "linux")]
+    const LIBRARY: &[u8] = b"libcuda.so.1\0";
+    #[cfg(target_os = "windows")]
+    const LIBRARY: &[u8] = b"nvcuda.dll\0";
+
+    #[cfg(target_os = "linux")]
+    extern "C" {
+        fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    #[cfg(target_os = "windows")]
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn LoadLibraryA(filename: *const c_char) -> *mut c_void;
+        fn GetProcAddress(module: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    unsafe fn open_library() -> *mut c_void {
+        #[cfg(target_os = "linux")]
+        {
+            // `RTLD_NOW`
+            dlopen(LIBRARY.as_ptr() as *const c_char, 2)
+        }
+        #[cfg(target_os = "windows")]
+        {
+            LoadLibraryA(LIBRARY.as_ptr() as *const c_char)
+        }
+    }
+
+    /// Look up a function of the driver by its nul-terminated name.
+    unsafe fn symbol(library: *mut c_void, name: &[u8]) -> Option<*mut c_void> {
+        #[cfg(target_os = "linux")]
+        let symbol = dlsym(library, name.as_ptr() as *const c_char);
+        #[cfg(target_os = "windows")]
+        let symbol = GetProcAddress(library, name.as_ptr() as *const c_char);
+        (!symbol.is_null()).then_some(symbol)
+    }
+
+    type CtxPushCurrent = unsafe extern "system" fn(context: *mut c_void) -> c_int;
+    type CtxPopCurrent = unsafe extern "system" fn(context: *mut *mut c_void) -> c_int;
+    type MemHostAlloc =
+        unsafe extern "system" fn(data: *mut *mut c_void, size: usize, flags: c_uint) -> c_int;
+    type MemFreeHost = unsafe extern "system" fn(data: *mut c_void) -> c_int;
+
+    pub(super) struct Driver {
+        ctx_push_current: CtxPushCurrent,
+        ctx_pop_current: CtxPopCurrent,
+        mem_host_alloc: MemHostAlloc,
+        mem_free_host: MemFreeHost,
+    }
+
+    impl Driver {
+        /// Load the driver the first time it is used.
+        ///
+        /// # Return value
+        ///
+        /// The driver, or `None` if it is not installed.
+        pub(super) fn get() -> Option<&'static Driver> {
+            static DRIVER: OnceLock<Option<Driver>> = OnceLock::new();
+            DRIVER
+                .get_or_init(|| unsafe {
+                    let library = open_library();
+                    if library.is_null() {
+                        return None;
+                    }
+                    let ctx_push_current = symbol(library, b"cuCtxPushCurrent_v2\0")?;
+                    let ctx_pop_current = symbol(library, b"cuCtxPopCurrent_v2\0")?;
+                    let mem_host_alloc = symbol(library, b"cuMemHostAlloc\0")?;
+                    let mem_free_host = symbol(library, b"cuMemFreeHost\0")?;
+                    Some(Driver {
+                        ctx_push_current: std::mem::transmute::<*mut c_void, CtxPushCurrent>(
+                            ctx_push_current,
+                        ),
+                        ctx_pop_current: std::mem::transmute::<*mut c_void, CtxPopCurrent>(
+                            ctx_pop_current,
+                        ),
+                        mem_host_alloc: std::mem::transmute::<*mut c_void, MemHostAlloc>(
+                            mem_host_alloc,
+                        ),
+                        mem_free_host: std::mem::transmute::<*mut c_void, MemFreeHost>(
+                            mem_free_host,
+                        ),
+                    })
+                })
+                .as_ref()
+        }
+
+        /// Run `f` with a CUDA context current on the calling thread.
+        unsafe fn with_context<T>(&self, context: *mut c_void, f: impl FnOnce() -> T) -> Option<T> {
+            if (self.ctx_push_current)(context) != 0 {
+                return None;
+            }
+            let result = f();
+            let mut popped = std::ptr::null_mut();
+            (self.ctx_pop_current)(&mut popped);
+            Some(result)
+        }
+
+        /// Allocate pinned host memory.
+        ///
+        /// # Return value
+        ///
+        /// The memory, or `None` if the allocation failed.
+        pub(super) unsafe fn mem_host_alloc(
+            &self,
+            context: *mut c_void,
+            size: usize,
+        ) -> Option<*mut u8> {
+            let mut data = std::ptr::null_mut();
+            let result = self.with_context(context, || {
+                (self.mem_host_alloc)(&mut data, size, CU_MEMHOSTALLOC_PORTABLE)
+            })?;
+            (result == 0 && !data.is_null()).then_some(data as *mut u8)
+        }
+    }
+
+    /// Owner of pinned memory that is wrapped in an `AVBufferRef`. Keeps the CUDA device alive
+    /// until the memory is freed.
+    pub(super) struct PinnedBuffer {
+        pub(super) driver: &'static Driver,
+        pub(super) cuda_context: *mut c_void,
+        pub(super) device_ref: *mut ffmpeg::ffi::AVBufferRef,
+    }
+
+    /// Free pinned memory, as the free callback of its `AVBufferRef`.
+    ///
+    /// # Arguments
+    ///
+    /// * `opaque` - [`PinnedBuffer`] that owns the memory.
+    /// * `data` - Pinned memory.
+    pub(super) unsafe extern "C" fn free_pinned_buffer(opaque: *mut c_void, data: *mut u8) {
+        let mut owner = Box::from_raw(opaque as *mut PinnedBuffer);
+        let driver = owner.driver;
+        driver.with_context(owner.cuda_context, || {
+            (driver.mem_free_host)(data as *mut c_void)
+        });
+        ffmpeg::ffi::av_buffer_unref(&mut owner.device_ref);
+    }
+}
+
+/// Rust version of the `AVMediaCodecDeviceContext` struct in `libavutil`. Only the leading fields
+/// are declared, the struct is always allocated by ffmpeg.
+#[cfg(target_os = "android")]
+#[repr(C)]
+struct AVMediaCodecDeviceContext {
+    surface: *mut std::ffi::c_void,
+    #[cfg(not(feature = "ffmpeg5"))]
+    native_window: *mut std::ffi::c_void,
+}
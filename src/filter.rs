@@ -0,0 +1,284 @@
+//! Per-frame processing of video with filter graphs, like flipping, cropping or drawing text.
+
+use std::collections::VecDeque;
+
+use ffmpeg::filter::Graph as AvFilterGraph;
+use ffmpeg::util::format::Pixel as AvPixel;
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::frame::RawFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Video filter graph with one input and one output, described in the syntax of the `-vf` option
+/// of the `ffmpeg` tool, like `"scale=640:480,hflip"`.
+///
+/// Frames are pushed with [`Filter::push`] and pulled with [`Filter::pull`]. A filter may hold
+/// frames back or produce more or fewer frames than it gets (like `fps`), so pull until it returns
+/// `None` after every push. Frames come out with timestamps in the time base they went in with.
+///
+/// The graph is configured with the first frame, since it depends on the format and size of the
+/// frames. When they change (like after a resolution change in the middle of a stream), the graph
+/// is drained and configured again.
+///
+/// Filters can also be attached to decoders and encoders, see
+/// [`DecoderBuilder::with_filter`](crate::decode::DecoderBuilder::with_filter) and
+/// [`EncoderBuilder::with_filter`](crate::encode::EncoderBuilder::with_filter).
+///
+/// # Example
+///
+/// ```ignore
+/// let mut filter = Filter::new("hflip,drawtext=text='camera 1':x=10:y=10");
+/// filter.push(&frame, decoder.time_base())?;
+/// while let Some(frame) = filter.pull()? {
+///     encoder.encode_raw(frame)?;
+/// }
+/// ```
+pub struct Filter {
+    spec: String,
+    output_format: Option<AvPixel>,
+    output_size: Option<(u32, u32)>,
+    /// Time base of the output frames, instead of the time base of the input frames.
+    output_time_base: Option<AvRational>,
+    input: Option<FilterInput>,
+    graph: Option<AvFilterGraph>,
+    /// Frames drained from the previous filter graph when the input changed.
+    output: VecDeque<RawFrame>,
+    ended: bool,
+}
+
+impl Filter {
+    /// Create a filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - Filter graph description, like `"scale=640:480,hflip"`.
+    pub fn new(spec: impl Into<String>) -> Self {
+        Self {
+            spec: spec.into(),
+            output_format: None,
+            output_size: None,
+            output_time_base: None,
+            input: None,
+            graph: None,
+            output: VecDeque::new(),
+            ended: false,
+        }
+    }
+
+    /// Get the filter graph description.
+    pub fn spec(&self) -> &str {
+        &self.spec
+    }
+
+    /// Convert the output of the filter graph to a pixel format, and optionally scale it to a size,
+    /// for consumers that only take frames of one kind.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Pixel format of the output frames.
+    /// * `size` - Size of the output frames, or `None` to keep the size the filters produce.
+    pub(crate) fn set_output(&mut self, format: AvPixel, size: Option<(u32, u32)>) {
+        self.output_format = Some(format);
+        self.output_size = size;
+    }
+
+    /// Give the output frames timestamps in a time base of their own, for filters that make up
+    /// frames at a finer rate than the input frames have timestamps for (like `minterpolate`).
+    ///
+    /// # Arguments
+    ///
+    /// * `time_base` - Time base of the output frames.
+    pub(crate) fn set_output_time_base(&mut self, time_base: AvRational) {
+        self.output_time_base = Some(time_base);
+    }
+
+    /// Push a frame into the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to filter.
+    /// * `time_base` - Time base of the timestamp of the frame.
+    pub fn push(&mut self, frame: &RawFrame, time_base: AvRational) -> Result<()> {
+        let input = FilterInput::of(frame, time_base);
+        if self.input != Some(input) {
+            self.drain_graph()?;
+            self.input = Some(input);
+        }
+        let graph = match self.graph.as_mut() {
+            Some(graph) => graph,
+            None => {
+                let graph = self.configure(input)?;
+                self.graph.insert(graph)
+            }
+        };
+        graph
+            .get("in")
+            .ok_or(AvError::FilterNotFound)?
+            .source()
+            .add(frame)?;
+        Ok(())
+    }
+
+    /// Signal the end of the input, after which the remaining frames can be pulled.
+    pub fn finish(&mut self) -> Result<()> {
+        if self.ended {
+            return Ok(());
+        }
+        self.ended = true;
+        match self.graph.as_mut() {
+            Some(graph) => {
+                graph
+                    .get("in")
+                    .ok_or(AvError::FilterNotFound)?
+                    .source()
+                    .flush()?;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Pull a filtered frame.
+    ///
+    /// # Return value
+    ///
+    /// The frame, with a timestamp in the time base of the frames that were pushed, or `None` if
+    /// more frames need to be pushed first, or the input has ended.
+    pub fn pull(&mut self) -> Result<Option<RawFrame>> {
+        if let Some(frame) = self.output.pop_front() {
+            return Ok(Some(frame));
+        }
+        let (Some(graph), Some(input)) = (self.graph.as_mut(), self.input) else {
+            return Ok(None);
+        };
+        receive_frame(graph, self.output_time_base.unwrap_or(input.time_base))
+    }
+
+    /// Discard the frames in the filter and start over, for instance after seeking.
+    pub fn reset(&mut self) {
+        self.graph = None;
+        self.input = None;
+        self.output.clear();
+        self.ended = false;
+    }
+
+    /// Signal the end of the input to the current filter graph and keep the frames it still holds,
+    /// so that the graph can be replaced.
+    fn drain_graph(&mut self) -> Result<()> {
+        let (Some(mut graph), Some(input)) = (self.graph.take(), self.input) else {
+            return Ok(());
+        };
+        graph
+            .get("in")
+            .ok_or(AvError::FilterNotFound)?
+            .source()
+            .flush()?;
+        let time_base = self.output_time_base.unwrap_or(input.time_base);
+        while let Some(frame) = receive_frame(&mut graph, time_base)? {
+            self.output.push_back(frame);
+        }
+        Ok(())
+    }
+
+    /// Configure the filter graph for the format and size of the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Format, size and time base of the frames.
+    fn configure(&self, input: FilterInput) -> Result<AvFilterGraph> {
+        let mut graph = AvFilterGraph::new();
+        let buffer = ffmpeg::filter::find("buffer").ok_or(AvError::FilterNotFound)?;
+        let buffersink = ffmpeg::filter::find("buffersink").ok_or(AvError::FilterNotFound)?;
+        graph.add(
+            &buffer,
+            "in",
+            &format!(
+                "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
+                input.width,
+                input.height,
+                input.format.name(),
+                input.time_base.numerator(),
+                input.time_base.denominator(),
+                input.aspect_ratio.numerator(),
+                input.aspect_ratio.denominator(),
+            ),
+        )?;
+        graph.add(&buffersink, "out", "")?;
+
+        let mut spec = self.spec.clone();
+        if let Some((width, height)) = self.output_size {
+            spec.push_str(&format!(",scale={width}:{height}"));
+        }
+        if let Some(format) = self.output_format {
+            spec.push_str(&format!(",format={}", format.name()));
+        }
+        graph.output("in", 0)?.input("out", 0)?.parse(&spec)?;
+        graph.validate()?;
+        Ok(graph)
+    }
+}
+
+unsafe impl Send for Filter {}
+unsafe impl Sync for Filter {}
+
+/// Format, size and time base of the frames that go into a filter graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FilterInput {
+    format: AvPixel,
+    width: u32,
+    height: u32,
+    aspect_ratio: AvRational,
+    time_base: AvRational,
+}
+
+impl FilterInput {
+    /// Get the input of a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to get the input of.
+    /// * `time_base` - Time base of the timestamp of the frame.
+    fn of(frame: &RawFrame, time_base: AvRational) -> Self {
+        let aspect_ratio = frame.aspect_ratio();
+        Self {
+            format: frame.format(),
+            width: frame.width(),
+            height: frame.height(),
+            // Frames without a known aspect ratio have square pixels.
+            aspect_ratio: if aspect_ratio.numerator() > 0 {
+                aspect_ratio
+            } else {
+                AvRational::new(1, 1)
+            },
+            time_base,
+        }
+    }
+}
+
+/// Receive a filtered frame from the sink of the filter graph.
+///
+/// # Arguments
+///
+/// * `graph` - Filter graph.
+/// * `time_base` - Time base to rescale the timestamp of the frame to.
+fn receive_frame(graph: &mut AvFilterGraph, time_base: AvRational) -> Result<Option<RawFrame>> {
+    let mut frame = RawFrame::empty();
+    let mut context = graph.get("out").ok_or(AvError::FilterNotFound)?;
+    let mut sink = context.sink();
+    match sink.frame(&mut frame) {
+        Ok(()) => {
+            frame.set_pts(
+                frame
+                    .pts()
+                    .map(|pts| pts.rescale(sink.time_base(), time_base)),
+            );
+            Ok(Some(frame))
+        }
+        Err(AvError::Eof) => Ok(None),
+        Err(AvError::Other { errno }) if errno == ffmpeg::util::error::EAGAIN => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
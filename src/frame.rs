@@ -1,4 +1,5 @@
 use ffmpeg::util::format::Pixel as AvPixel;
+use ffmpeg::util::frame::Audio as AvAudioFrame;
 use ffmpeg::util::frame::Video as AvFrame;
 
 /// Re-export internal `AvPixel` as `PixelFormat` for callers.
@@ -7,9 +8,16 @@ pub type PixelFormat = AvPixel;
 /// Re-export internal `AvFrame` for caller to use.
 pub type RawFrame = AvFrame;
 
+/// Re-export internal `AvAudioFrame` for caller to use.
+pub type RawAudioFrame = AvAudioFrame;
+
 /// Re-export frame type as ndarray.
 #[cfg(feature = "ndarray")]
 pub type Frame = crate::ffi::FrameArray;
 
+/// Batch of frames as ndarray, with dimensions `(N, H, W, C)`.
+#[cfg(feature = "ndarray")]
+pub type FrameBatch = ndarray::Array4<u8>;
+
 /// Default frame pixel format.
 pub(crate) const FRAME_PIXEL_FORMAT: AvPixel = AvPixel::RGB24;
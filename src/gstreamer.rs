@@ -0,0 +1,291 @@
+//! Bridge between GStreamer pipelines and rsmedia.
+//!
+//! [`AppSinkReaderBuilder`] builds a [`Reader`] that demuxes the container bytes coming out of a
+//! GStreamer `appsink` (for example `... ! mpegtsmux ! appsink`), and [`AppSrcWriter`] muxes
+//! packets into a container and pushes the bytes into a GStreamer `appsrc` (for example
+//! `appsrc ! tsdemux ! ...`). This allows migrating pipelines between the two frameworks one
+//! element at a time.
+
+use gstreamer_app::{AppSink, AppSrc};
+
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::format::context::Output as AvOutput;
+use ffmpeg::Error as AvError;
+
+use crate::error::Error;
+use crate::ffi;
+use crate::interrupt::Interrupt;
+use crate::io::private::{Output, Write as WritePrivate};
+use crate::io::{Buf, Reader, Write};
+use crate::location::{Location, Url};
+use crate::options::Options;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Reads the buffers of samples pulled from an `appsink` as one contiguous byte stream.
+struct AppSinkSource {
+    appsink: AppSink,
+    pending: Buf,
+    offset: usize,
+}
+
+impl std::io::Read for AppSinkSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.offset >= self.pending.len() {
+            // Note: Pulling fails when the appsink reaches EOS, which signals the end of the
+            // stream to the demuxer. It also fails when the pipeline is stopped before EOS, which
+            // is an error since the stream was cut off.
+            let sample = match self.appsink.pull_sample() {
+                Ok(sample) => sample,
+                Err(_) if self.appsink.is_eos() => return Ok(0),
+                Err(err) => return Err(std::io::Error::new(std::io::ErrorKind::Other, err)),
+            };
+            let Some(buffer) = sample.buffer() else {
+                continue;
+            };
+            let map = buffer
+                .map_readable()
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+            self.pending.clear();
+            self.pending.extend_from_slice(map.as_slice());
+            self.offset = 0;
+        }
+
+        let len = buf.len().min(self.pending.len() - self.offset);
+        buf[..len].copy_from_slice(&self.pending[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+impl std::io::Seek for AppSinkSource {
+    fn seek(&mut self, _pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Builds a [`Reader`] that reads from a GStreamer `appsink`.
+///
+/// The appsink must produce a container format that ffmpeg can demux without seeking, like MPEG-TS
+/// or Matroska. The pipeline must be playing before building the reader, since the container
+/// header is read while building.
+///
+/// # Example
+///
+/// ```ignore
+/// let pipeline = gstreamer::parse::launch(
+///     "videotestsrc ! x264enc ! mpegtsmux ! appsink name=sink",
+/// )?;
+/// let appsink = pipeline
+///     .downcast_ref::<gstreamer::Bin>()
+///     .unwrap()
+///     .by_name("sink")
+///     .unwrap()
+///     .downcast::<AppSink>()
+///     .unwrap();
+/// pipeline.set_state(gstreamer::State::Playing)?;
+/// let mut reader = AppSinkReaderBuilder::new(appsink).build()?;
+/// ```
+pub struct AppSinkReaderBuilder<'a> {
+    appsink: AppSink,
+    options: Option<&'a Options>,
+}
+
+impl<'a> AppSinkReaderBuilder<'a> {
+    /// Create a new reader for the specified appsink.
+    ///
+    /// # Arguments
+    ///
+    /// * `appsink` - Appsink to pull samples from.
+    pub fn new(appsink: AppSink) -> Self {
+        Self {
+            appsink,
+            options: None,
+        }
+    }
+
+    /// Specify options for the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to input.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Build [`Reader`].
+    ///
+    /// The source of the reader is set to `appsink://<name>`, with the name of the appsink element.
+    pub fn build(self) -> Result<Reader> {
+        let source = Url::parse(&format!("appsink://{}", self.appsink.name()))
+            .map(Location::Network)
+            .map_err(|_| Error::BackendError(AvError::InvalidData))?;
+        let interrupt = Interrupt::new();
+        let (input, io, unused_options) = ffi::input_raw_io(
+            Box::new(AppSinkSource {
+                appsink: self.appsink,
+                pending: Buf::new(),
+                offset: 0,
+            }),
+            false,
+            None,
+            self.options.map(|options| options.to_dict()),
+            &interrupt,
+        )?;
+        Ok(Reader::from_input(
+            source,
+            input,
+            interrupt,
+            Some(io),
+            Options::unused_keys(unused_options, "reader"),
+        ))
+    }
+}
+
+/// Build an [`AppSrcWriter`].
+pub struct AppSrcWriterBuilder<'a> {
+    appsrc: AppSrc,
+    format: &'a str,
+    options: Option<&'a Options>,
+}
+
+impl<'a> AppSrcWriterBuilder<'a> {
+    /// Create a new writer that pushes into an appsrc.
+    ///
+    /// # Arguments
+    ///
+    /// * `appsrc` - Appsrc to push buffers into.
+    /// * `format` - Container format to use, like "mpegts".
+    pub fn new(appsrc: AppSrc, format: &'a str) -> Self {
+        Self {
+            appsrc,
+            format,
+            options: None,
+        }
+    }
+
+    /// Specify options for the backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to output.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Build [`AppSrcWriter`].
+    pub fn build(self) -> Result<AppSrcWriter> {
+        Ok(AppSrcWriter {
+            output: ffi::output_raw(self.format)?,
+            options: self.options.cloned().unwrap_or_default(),
+            appsrc: self.appsrc,
+        })
+    }
+}
+
+/// Video writer that muxes packets and pushes the resulting bytes into a GStreamer `appsrc`.
+///
+/// Writing the trailer also signals end-of-stream to the appsrc.
+///
+/// # Example
+///
+/// ```ignore
+/// let writer = AppSrcWriter::new(appsrc, "mpegts").unwrap();
+/// let mut muxer = MuxerBuilder::new(writer)
+///     .with_streams(&reader)?
+///     .build();
+/// ```
+pub struct AppSrcWriter {
+    output: AvOutput,
+    options: Options,
+    appsrc: AppSrc,
+}
+
+impl AppSrcWriter {
+    /// Create a video writer that pushes into an appsrc.
+    ///
+    /// # Arguments
+    ///
+    /// * `appsrc` - Appsrc to push buffers into.
+    /// * `format` - Container format to use, like "mpegts".
+    #[inline]
+    pub fn new(appsrc: AppSrc, format: &str) -> Result<Self> {
+        AppSrcWriterBuilder::new(appsrc, format).build()
+    }
+
+    fn begin_write(&mut self) {
+        ffi::output_raw_buf_start(&mut self.output);
+    }
+
+    /// Finish the write and push the bytes that were written into the appsrc.
+    fn end_write(&mut self) -> Result<()> {
+        let buf = ffi::output_raw_buf_end(&mut self.output);
+        if buf.is_empty() {
+            return Ok(());
+        }
+        self.appsrc
+            .push_buffer(::gstreamer::Buffer::from_mut_slice(buf))
+            .map(|_| ())
+            .map_err(|_| Error::BackendError(AvError::External))
+    }
+}
+
+impl Write for AppSrcWriter {}
+
+impl WritePrivate for AppSrcWriter {
+    type Out = ();
+
+    fn write_header(&mut self) -> Result<()> {
+        self.begin_write();
+        let result = self.output.write_header_with(self.options.to_dict());
+        self.end_write()?;
+        result?;
+        Ok(())
+    }
+
+    fn write(&mut self, packet: &mut AvPacket) -> Result<()> {
+        self.begin_write();
+        let result = packet
+            .write(&mut self.output)
+            .and_then(|_| ffi::flush_output(&mut self.output));
+        self.end_write()?;
+        result?;
+        Ok(())
+    }
+
+    fn write_interleaved(&mut self, packet: &mut AvPacket) -> Result<()> {
+        self.begin_write();
+        let result = packet
+            .write_interleaved(&mut self.output)
+            .and_then(|_| ffi::flush_output(&mut self.output));
+        self.end_write()?;
+        result?;
+        Ok(())
+    }
+
+    fn write_trailer(&mut self) -> Result<()> {
+        self.begin_write();
+        let result = self.output.write_trailer();
+        self.end_write()?;
+        result?;
+        self.appsrc
+            .end_of_stream()
+            .map(|_| ())
+            .map_err(|_| Error::BackendError(AvError::External))
+    }
+}
+
+impl Output for AppSrcWriter {
+    fn output(&self) -> &AvOutput {
+        &self.output
+    }
+
+    fn output_mut(&mut self) -> &mut AvOutput {
+        &mut self.output
+    }
+}
+
+unsafe impl Send for AppSrcWriter {}
+unsafe impl Sync for AppSrcWriter {}
@@ -0,0 +1,552 @@
+//! Segmented output for HTTP Live Streaming (HLS), like a live origin that serves a playlist and
+//! its segments from a directory.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::format::context::Output as AvOutput;
+use ffmpeg::media::Type as AvMediaType;
+
+use crate::error::Error;
+use crate::ffi::{track_output_files, OutputFiles};
+use crate::io::private::{Output, Write as PrivateWrite};
+use crate::io::{Write, Writer, WriterBuilder};
+use crate::location::Location;
+use crate::options::Options;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Container format used for HLS.
+const HLS_FORMAT: &str = "hls";
+
+/// File name of the initialization segment of fMP4 segments, unless set with
+/// `hls_fmp4_init_filename`.
+const DEFAULT_INIT_FILENAME: &str = "init.mp4";
+
+/// Type of the playlist of a [`SegmentedWriter`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlaylistType {
+    /// Live playlist with a sliding window of the most recent segments. Segments that fall out of
+    /// the window are deleted.
+    Live {
+        /// Number of segments in the playlist.
+        list_size: u32,
+    },
+    /// Live playlist that keeps all segments, so viewers can seek back to the start.
+    Event,
+    /// Playlist of a finished video. The playlist is only written once the writer finishes.
+    Vod,
+}
+
+impl Default for PlaylistType {
+    fn default() -> Self {
+        PlaylistType::Live { list_size: 5 }
+    }
+}
+
+/// Container format of the segments of a [`SegmentedWriter`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SegmentFormat {
+    /// MPEG-TS segments (`.ts`), which every HLS player supports.
+    #[default]
+    MpegTs,
+    /// Fragmented MP4 segments (`.m4s`) with an initialization segment, which HEVC and AV1
+    /// require.
+    Fmp4,
+}
+
+/// Segment that a [`SegmentedWriter`] completed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// Path of the segment file.
+    pub path: PathBuf,
+    /// Duration of the segment.
+    pub duration: Duration,
+    /// Media sequence number of the segment.
+    pub sequence: u64,
+}
+
+/// Builds a [`SegmentedWriter`].
+pub struct SegmentedWriterBuilder<'a> {
+    playlist: PathBuf,
+    segment_duration: Duration,
+    playlist_type: PlaylistType,
+    segment_format: SegmentFormat,
+    segment_filename: Option<String>,
+    options: Option<&'a Options>,
+    on_segment: Option<Box<dyn FnMut(Segment) + Send>>,
+}
+
+impl<'a> SegmentedWriterBuilder<'a> {
+    /// Default target duration of segments.
+    const SEGMENT_DURATION: Duration = Duration::from_secs(2);
+
+    /// Create a new [`SegmentedWriterBuilder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist` - Path of the playlist, like `live/stream.m3u8`. The segments are written next
+    ///   to it by default.
+    pub fn new(playlist: impl Into<PathBuf>) -> Self {
+        Self {
+            playlist: playlist.into(),
+            segment_duration: Self::SEGMENT_DURATION,
+            playlist_type: PlaylistType::default(),
+            segment_format: SegmentFormat::default(),
+            segment_filename: None,
+            options: None,
+            on_segment: None,
+        }
+    }
+
+    /// Set the target duration of segments. Segments are cut at the first video keyframe after
+    /// the target duration, so the keyframe interval of the encoder should divide it.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - Target duration of segments.
+    pub fn with_segment_duration(mut self, duration: Duration) -> Self {
+        self.segment_duration = duration;
+        self
+    }
+
+    /// Set the type of the playlist. Defaults to a live playlist of five segments.
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist_type` - Type of the playlist.
+    pub fn with_playlist_type(mut self, playlist_type: PlaylistType) -> Self {
+        self.playlist_type = playlist_type;
+        self
+    }
+
+    /// Set the container format of the segments. Defaults to MPEG-TS.
+    ///
+    /// # Arguments
+    ///
+    /// * `segment_format` - Container format of the segments.
+    pub fn with_segment_format(mut self, segment_format: SegmentFormat) -> Self {
+        self.segment_format = segment_format;
+        self
+    }
+
+    /// Set the file name pattern of the segments, like `live/segment-%05d.ts`, where `%d` is
+    /// replaced with the sequence number. Defaults to the name of the playlist followed by the
+    /// sequence number.
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - File name pattern of the segments.
+    pub fn with_segment_filename(mut self, pattern: impl Into<String>) -> Self {
+        self.segment_filename = Some(pattern.into());
+        self
+    }
+
+    /// Set additional options for the HLS muxer, like `hls_base_url` or `hls_key_info_file`.
+    /// Options that the builder sets are only set when the options do not have them, except
+    /// `hls_flags`, of which the flags are combined.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Call a function every time a segment is completed, like to push it to a CDN or to notify
+    /// viewers.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function that receives the completed segments.
+    pub fn with_segment_callback(mut self, callback: impl FnMut(Segment) + Send + 'static) -> Self {
+        self.on_segment = Some(Box::new(callback));
+        self
+    }
+
+    /// Build [`SegmentedWriter`].
+    pub fn build(self) -> Result<SegmentedWriter> {
+        let options = self.hls_options();
+        let mut writer = WriterBuilder::new(Location::File(self.playlist.clone()))
+            .with_format(HLS_FORMAT)
+            .with_options(&options)
+            .build()?;
+        let files = track_output_files(&mut writer.output);
+        let init_filename = options
+            .get("hls_fmp4_init_filename")
+            .unwrap_or(DEFAULT_INIT_FILENAME)
+            .to_string();
+        let next_sequence = options
+            .get("start_number")
+            .and_then(|start_number| start_number.parse().ok())
+            .unwrap_or(0);
+        let segment_duration = options
+            .get("hls_time")
+            .and_then(|hls_time| hls_time.parse().ok())
+            .unwrap_or(self.segment_duration.as_secs_f64());
+        Ok(SegmentedWriter {
+            writer,
+            files,
+            playlist: self.playlist,
+            init_filename,
+            next_sequence,
+            segment_duration,
+            segments: 0,
+            start: None,
+            segment_start: None,
+            cuts: VecDeque::new(),
+            end: None,
+            on_segment: self.on_segment,
+        })
+    }
+
+    /// Get the options of the HLS muxer.
+    fn hls_options(&self) -> Options {
+        let mut options = self.options.cloned().unwrap_or_default();
+        let mut set = |key: &str, value: &str| {
+            match options.get(key) {
+                // Flags the caller set are kept next to the ones that are needed.
+                Some(flags) if key == "hls_flags" => {
+                    let flags = format!("{flags}+{value}");
+                    options.set(key, &flags);
+                }
+                Some(_) => {}
+                None => options.set(key, value),
+            }
+        };
+        set("hls_time", &self.segment_duration.as_secs_f64().to_string());
+        // The playlist is replaced atomically, so it can be read while it is being updated.
+        match self.playlist_type {
+            PlaylistType::Live { list_size } => {
+                set("hls_list_size", &list_size.to_string());
+                set("hls_flags", "delete_segments+temp_file");
+            }
+            PlaylistType::Event => {
+                set("hls_playlist_type", "event");
+                set("hls_list_size", "0");
+                set("hls_flags", "temp_file");
+            }
+            PlaylistType::Vod => {
+                set("hls_playlist_type", "vod");
+                set("hls_list_size", "0");
+                set("hls_flags", "temp_file");
+            }
+        }
+        if self.segment_format == SegmentFormat::Fmp4 {
+            set("hls_segment_type", "fmp4");
+        }
+        if let Some(pattern) = self.segment_filename.as_deref() {
+            set("hls_segment_filename", pattern);
+        }
+        options
+    }
+}
+
+/// Writer that writes HLS: a playlist and the segments it lists. Use it with a
+/// [`Muxer`](crate::mux::Muxer).
+///
+/// Segments are cut at video keyframes. The writer keeps track of the segment files that the muxer
+/// completes, and reports each of them to the callback set with
+/// [`SegmentedWriterBuilder::with_segment_callback`]. The duration of a segment runs from the
+/// keyframe it starts with to the keyframe that starts the next one, or to the end of the last
+/// packet for the final segment.
+///
+/// # Example
+///
+/// ```ignore
+/// let reader = Reader::new("rtsp://camera/stream".parse::<Url>().unwrap()).unwrap();
+/// let writer = SegmentedWriterBuilder::new("/var/www/live/stream.m3u8")
+///     .with_segment_duration(Duration::from_secs(4))
+///     .with_playlist_type(PlaylistType::Live { list_size: 6 })
+///     .with_segment_callback(|segment| {
+///         println!("{} ({:?})", segment.path.display(), segment.duration);
+///     })
+///     .build()
+///     .unwrap();
+/// let mut muxer = MuxerBuilder::new(writer)
+///     .with_streams(&reader)
+///     .unwrap()
+///     .interleaved()
+///     .build();
+/// ```
+pub struct SegmentedWriter {
+    writer: Writer,
+    /// Files that the muxer writes. Declared after the writer, which must be dropped first.
+    files: Box<OutputFiles>,
+    playlist: PathBuf,
+    /// File name of the initialization segment, which is not reported.
+    init_filename: String,
+    /// Sequence number of the next segment to report.
+    next_sequence: u64,
+    /// Target duration of the segments in seconds.
+    segment_duration: f64,
+    /// Number of segments reported.
+    segments: u64,
+    /// Time of the first packet.
+    start: Option<Time>,
+    /// Time at which the current segment starts.
+    segment_start: Option<Time>,
+    /// Times of the packets after the start of the current segment before which the muxer may
+    /// cut a segment, oldest first.
+    cuts: VecDeque<Time>,
+    /// Time at which the last packet ends.
+    end: Option<Time>,
+    on_segment: Option<Box<dyn FnMut(Segment) + Send>>,
+}
+
+impl SegmentedWriter {
+    /// Create a new [`SegmentedWriter`] with a live playlist and default settings.
+    ///
+    /// # Arguments
+    ///
+    /// * `playlist` - Path of the playlist.
+    #[inline]
+    pub fn new(playlist: impl Into<PathBuf>) -> Result<Self> {
+        SegmentedWriterBuilder::new(playlist).build()
+    }
+
+    /// Get the path of the playlist.
+    pub fn playlist(&self) -> &Path {
+        &self.playlist
+    }
+
+    /// Write a packet, and report the segments that were completed.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to write.
+    /// * `interleaved` - Whether or not to use interleaved write.
+    fn write_packet(&mut self, packet: &mut AvPacket, interleaved: bool) -> Result<()> {
+        // Interleaved writes take the packet, so its times are kept before writing.
+        if let Some(time_base) = self
+            .writer
+            .output
+            .stream(packet.stream())
+            .map(|stream| stream.time_base())
+        {
+            let timestamp = packet.pts().or(packet.dts());
+            let start = Time::new(timestamp, time_base);
+            let end = Time::new(timestamp.map(|pts| pts + packet.duration()), time_base);
+            if start.has_value() {
+                self.start.get_or_insert(start);
+                let segment_start = *self.segment_start.get_or_insert(start);
+                // The muxer does not cut before the first packet of a segment.
+                if start.as_secs_f64() > segment_start.as_secs_f64() && self.may_end_segment(packet)
+                {
+                    self.cuts.push_back(start);
+                }
+            }
+            if end.has_value()
+                && !self
+                    .end
+                    .is_some_and(|last| last.as_secs_f64() >= end.as_secs_f64())
+            {
+                self.end = Some(end);
+            }
+        }
+        if interleaved {
+            self.writer.write_interleaved(packet)?;
+        } else {
+            self.writer.write(packet)?;
+        }
+        // Interleaved writes may hold on to a keyframe and cut the segment on a later packet.
+        self.report_segments(false);
+        Ok(())
+    }
+
+    /// Whether or not the muxer may cut a segment before the packet: it must be a video keyframe,
+    /// or any packet if there is no video.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet about to be written.
+    fn may_end_segment(&self, packet: &AvPacket) -> bool {
+        let mut streams = self.writer.output.streams();
+        if streams.any(|stream| stream.parameters().medium() == AvMediaType::Video) {
+            packet.is_key()
+                && self
+                    .writer
+                    .output
+                    .stream(packet.stream())
+                    .is_some_and(|stream| stream.parameters().medium() == AvMediaType::Video)
+        } else {
+            true
+        }
+    }
+
+    /// Report the segments that the muxer completed since the last time.
+    ///
+    /// Each segment runs from its own start to the packet at which the next one starts. The last
+    /// segment that was completed ends at the latest packet that the muxer may cut at, or at the
+    /// end of the last packet once the writer finished. When more than one segment was completed
+    /// at once, like when an interleaved write flushes a backlog of packets, the segments before
+    /// it end where the muxer cut them, see [`SegmentedWriter::next_cut`].
+    ///
+    /// # Arguments
+    ///
+    /// * `finished` - Whether the writer finished, so the last segment was completed.
+    fn report_segments(&mut self, finished: bool) {
+        let paths: Vec<PathBuf> = self
+            .files
+            .take_closed()
+            .iter()
+            .filter_map(|url| segment_path(url, &self.init_filename))
+            .collect();
+        let count = paths.len();
+        for (index, path) in paths.into_iter().enumerate() {
+            let end = if index + 1 < count {
+                self.next_cut()
+            } else if finished {
+                self.end
+            } else {
+                self.cuts.back().copied()
+            };
+            let duration = match (self.segment_start, end) {
+                (Some(start), Some(end)) => {
+                    Duration::from_secs_f64((end.as_secs_f64() - start.as_secs_f64()).max(0.0))
+                }
+                _ => Duration::ZERO,
+            };
+            self.segment_start = end;
+            if let Some(end) = end {
+                self.cuts
+                    .retain(|cut| cut.as_secs_f64() > end.as_secs_f64());
+            }
+            let segment = Segment {
+                path,
+                duration,
+                sequence: self.next_sequence,
+            };
+            self.next_sequence += 1;
+            self.segments += 1;
+            if let Some(on_segment) = self.on_segment.as_mut() {
+                on_segment(segment);
+            }
+        }
+    }
+
+    /// Get the time at which the muxer cut the current segment. Like the muxer, that is the first
+    /// packet it may cut at once the stream has run for the next multiple of the segment
+    /// duration, or the first packet it may cut at if options changed how the muxer cuts.
+    fn next_cut(&self) -> Option<Time> {
+        let start = self.start?.as_secs_f64();
+        let target = start + self.segment_duration * (self.segments + 1) as f64;
+        self.cuts
+            .iter()
+            .find(|cut| cut.as_secs_f64() >= target)
+            .or_else(|| self.cuts.front())
+            .copied()
+    }
+}
+
+impl Write for SegmentedWriter {}
+
+impl PrivateWrite for SegmentedWriter {
+    type Out = ();
+
+    fn write_header(&mut self) -> Result<()> {
+        self.writer.write_header().map(|_| ())
+    }
+
+    fn write(&mut self, packet: &mut AvPacket) -> Result<()> {
+        self.write_packet(packet, false)
+    }
+
+    fn write_interleaved(&mut self, packet: &mut AvPacket) -> Result<()> {
+        self.write_packet(packet, true)
+    }
+
+    fn write_trailer(&mut self) -> Result<()> {
+        self.writer.write_trailer()?;
+        // The last segment runs to the end of the last packet.
+        self.report_segments(true);
+        Ok(())
+    }
+}
+
+impl Output for SegmentedWriter {
+    fn output(&self) -> &AvOutput {
+        &self.writer.output
+    }
+
+    fn output_mut(&mut self) -> &mut AvOutput {
+        &mut self.writer.output
+    }
+}
+
+unsafe impl Send for SegmentedWriter {}
+unsafe impl Sync for SegmentedWriter {}
+
+/// Get the path of a segment from the URL of a file that the muxer wrote, or `None` if the file is
+/// a playlist, a key or the initialization segment. The path does not depend on `hls_base_url`,
+/// which only prefixes the URIs in the playlist.
+///
+/// # Arguments
+///
+/// * `url` - URL of the file that the muxer wrote.
+/// * `init_filename` - File name of the initialization segment.
+fn segment_path(url: &str, init_filename: &str) -> Option<PathBuf> {
+    let url = url.strip_prefix("file:").unwrap_or(url);
+    // Files are written under a temporary name first with `temp_file`, and renamed once closed.
+    let path = PathBuf::from(url.strip_suffix(".tmp").unwrap_or(url));
+    let is_init = path
+        .file_name()
+        .is_some_and(|file_name| file_name == Path::new(init_filename).as_os_str());
+    let is_segment = !matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("m3u8" | "key"),
+    );
+    (is_segment && !is_init).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_path() {
+        assert_eq!(
+            segment_path("live/stream12.ts.tmp", DEFAULT_INIT_FILENAME),
+            Some(PathBuf::from("live/stream12.ts")),
+        );
+        assert_eq!(
+            segment_path("file:live/stream13.m4s", DEFAULT_INIT_FILENAME),
+            Some(PathBuf::from("live/stream13.m4s")),
+        );
+        assert_eq!(
+            segment_path("live/stream.m3u8.tmp", DEFAULT_INIT_FILENAME),
+            None
+        );
+        assert_eq!(segment_path("live/init.mp4", DEFAULT_INIT_FILENAME), None);
+        assert_eq!(segment_path("live/header.mp4", "header.mp4"), None);
+        assert_eq!(segment_path("live/stream.key", DEFAULT_INIT_FILENAME), None);
+    }
+
+    #[test]
+    fn test_hls_options() {
+        let mut custom = Options::default();
+        custom.set("hls_flags", "program_date_time");
+        custom.set("hls_list_size", "10");
+        let options = SegmentedWriterBuilder::new("live/stream.m3u8")
+            .with_segment_duration(Duration::from_millis(2500))
+            .with_options(&custom)
+            .hls_options();
+        assert_eq!(options.get("hls_time"), Some("2.5"));
+        assert_eq!(options.get("hls_list_size"), Some("10"));
+        assert_eq!(
+            options.get("hls_flags"),
+            Some("program_date_time+delete_segments+temp_file"),
+        );
+
+        let options = SegmentedWriterBuilder::new("vod/movie.m3u8")
+            .with_playlist_type(PlaylistType::Vod)
+            .with_segment_format(SegmentFormat::Fmp4)
+            .hls_options();
+        assert_eq!(options.get("hls_playlist_type"), Some("vod"));
+        assert_eq!(options.get("hls_segment_type"), Some("fmp4"));
+    }
+}
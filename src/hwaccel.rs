@@ -1,30 +1,75 @@
 use crate::error::Error;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::ffi_hwaccel;
 
 type Result<T> = std::result::Result<T, Error>;
 
 pub(crate) struct HardwareAccelerationContext {
     pixel_format: ffmpeg::util::format::Pixel,
+    surface_output: bool,
+    keep_frames: bool,
+    #[cfg(not(target_arch = "wasm32"))]
     _hardware_device_context: ffi_hwaccel::HardwareDeviceContext,
 }
 
 impl HardwareAccelerationContext {
+    #[cfg(not(target_arch = "wasm32"))]
     pub(crate) fn new(
         decoder: &mut ffmpeg::codec::Context,
         device_type: HardwareAccelerationDeviceType,
     ) -> Result<Self> {
-        let codec = ffmpeg::codec::decoder::find(decoder.id()).ok_or(Error::UninitializedCodec)?;
+        let hardware_device_context = ffi_hwaccel::HardwareDeviceContext::new(device_type)?;
+        Self::with_device_context(decoder, device_type, hardware_device_context, false)
+    }
+
+    /// Hardware acceleration is not available on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn new(
+        _decoder: &mut ffmpeg::codec::Context,
+        _device_type: HardwareAccelerationDeviceType,
+    ) -> Result<Self> {
+        Err(Error::UnsupportedCodecHardwareAccelerationDeviceType)
+    }
+
+    /// Create a hardware acceleration context for MediaCodec that renders decoded frames to an
+    /// Android surface instead of system memory.
+    #[cfg(target_os = "android")]
+    pub(crate) fn new_with_mediacodec_surface(
+        decoder: &mut ffmpeg::codec::Context,
+        surface: MediaCodecSurface,
+    ) -> Result<Self> {
+        let hardware_device_context = ffi_hwaccel::HardwareDeviceContext::new_mediacodec(surface)?;
+        Self::with_device_context(
+            decoder,
+            HardwareAccelerationDeviceType::MediaCodec,
+            hardware_device_context,
+            true,
+        )
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn with_device_context(
+        decoder: &mut ffmpeg::codec::Context,
+        device_type: HardwareAccelerationDeviceType,
+        hardware_device_context: ffi_hwaccel::HardwareDeviceContext,
+        surface_output: bool,
+    ) -> Result<Self> {
+        // Prefer the codec the context was allocated with, which may be a wrapper decoder.
+        let codec = decoder
+            .codec()
+            .or_else(|| ffmpeg::codec::decoder::find(decoder.id()))
+            .ok_or(Error::UninitializedCodec)?;
         let pixel_format =
             ffi_hwaccel::codec_find_corresponding_hwaccel_pixfmt(&codec, device_type)
                 .ok_or(Error::UnsupportedCodecHardwareAccelerationDeviceType)?;
 
         ffi_hwaccel::codec_context_hwaccel_set_get_format(decoder, pixel_format);
-
-        let hardware_device_context = ffi_hwaccel::HardwareDeviceContext::new(device_type)?;
         ffi_hwaccel::codec_context_hwaccel_set_hw_device_ctx(decoder, &hardware_device_context);
 
         Ok(HardwareAccelerationContext {
             pixel_format,
+            surface_output,
+            keep_frames: false,
             _hardware_device_context: hardware_device_context,
         })
     }
@@ -32,9 +77,269 @@ impl HardwareAccelerationContext {
     pub(crate) fn format(&self) -> ffmpeg::util::format::Pixel {
         self.pixel_format
     }
+
+    /// Whether or not decoded frames are rendered to a surface and must not be downloaded.
+    pub(crate) fn is_surface_output(&self) -> bool {
+        self.surface_output
+    }
+
+    /// Set whether or not decoded frames are kept in device memory instead of being downloaded.
+    pub(crate) fn set_keep_frames(&mut self, keep_frames: bool) {
+        self.keep_frames = keep_frames;
+    }
+
+    /// Whether or not decoded frames are kept in device memory instead of being downloaded.
+    pub(crate) fn keeps_frames(&self) -> bool {
+        self.keep_frames
+    }
+}
+
+/// Find a decoder that implements hardware decoding for the given device type outside of the
+/// hwaccel framework. Some platforms (like Android MediaCodec) expose hardware codecs as separate
+/// wrapper decoders named `<codec>_<suffix>`, for example `h264_mediacodec`.
+///
+/// # Arguments
+///
+/// * `codec_id` - Codec to find a wrapper decoder for.
+/// * `device_type` - Hardware acceleration device type.
+pub(crate) fn find_wrapper_decoder(
+    codec_id: ffmpeg::codec::Id,
+    device_type: HardwareAccelerationDeviceType,
+) -> Option<ffmpeg::codec::codec::Codec> {
+    let suffix = device_type.wrapper_decoder_suffix()?;
+    ffmpeg::codec::decoder::find_by_name(&format!("{}_{}", codec_id.name(), suffix))
+}
+
+/// Find an encoder that implements hardware encoding for the given device type.
+///
+/// # Arguments
+///
+/// * `codec_id` - Codec to find a hardware encoder for.
+/// * `device_type` - Hardware acceleration device type.
+pub(crate) fn find_wrapper_encoder(
+    codec_id: ffmpeg::codec::Id,
+    device_type: HardwareAccelerationDeviceType,
+) -> Option<ffmpeg::codec::codec::Codec> {
+    let suffix = device_type.wrapper_encoder_suffix()?;
+    ffmpeg::codec::encoder::find_by_name(&format!("{}_{}", codec_id.name(), suffix))
+}
+
+/// Find a hardware encoder that takes the frames of a pool as they are, like an AMF encoder for
+/// Direct3D 11 frames.
+///
+/// # Arguments
+///
+/// * `codec_id` - Codec to find a hardware encoder for.
+/// * `hardware_frames` - Pool of the frames to encode.
+pub(crate) fn find_hardware_frames_encoder(
+    codec_id: ffmpeg::codec::Id,
+    hardware_frames: &HardwareFrames,
+) -> Option<ffmpeg::codec::codec::Codec> {
+    const SUFFIXES: [&str; 4] = ["nvenc", "qsv", "amf", "mf"];
+    SUFFIXES
+        .into_iter()
+        .filter_map(|suffix| {
+            ffmpeg::codec::encoder::find_by_name(&format!("{}_{}", codec_id.name(), suffix))
+        })
+        .find(|codec| hardware_frames.is_supported_by(codec))
+}
+
+/// Pool of frames in hardware memory, like the D3D11 textures produced by desktop duplication
+/// capture. Encoders that are set up with [`Settings::with_hardware_frames`] take frames from the
+/// pool directly, without downloading them to system memory.
+///
+/// [`Settings::with_hardware_frames`]: crate::encode::Settings::with_hardware_frames
+#[derive(Debug, Clone, PartialEq)]
+pub struct HardwareFrames {
+    #[cfg(not(target_arch = "wasm32"))]
+    context: ffi_hwaccel::HardwareFramesContext,
+    format: ffmpeg::util::format::Pixel,
+    sw_format: ffmpeg::util::format::Pixel,
+}
+
+impl HardwareFrames {
+    /// Get the pool a hardware frame was allocated from, or `None` if the frame is in system
+    /// memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Hardware frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn of(frame: &crate::frame::RawFrame) -> Option<Self> {
+        ffi_hwaccel::HardwareFramesContext::from_frame(frame).map(|context| Self {
+            format: context.format(),
+            sw_format: context.sw_format(),
+            context,
+        })
+    }
+
+    /// There are no hardware frames on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
+    pub fn of(_frame: &crate::frame::RawFrame) -> Option<Self> {
+        None
+    }
+
+    /// Pixel format of the frames in hardware memory, like `D3D11`.
+    pub fn format(&self) -> ffmpeg::util::format::Pixel {
+        self.format
+    }
+
+    /// Pixel format of the frames when downloaded to system memory, like `BGRA`.
+    pub fn sw_format(&self) -> ffmpeg::util::format::Pixel {
+        self.sw_format
+    }
+
+    /// Check whether an encoder can take frames from the pool as they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec` - Encoder to check.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn is_supported_by(&self, codec: &ffmpeg::codec::codec::Codec) -> bool {
+        ffi_hwaccel::codec_supports_hw_frames_pixfmt(codec, self.format)
+    }
+
+    /// There are no hardware frames on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn is_supported_by(&self, _codec: &ffmpeg::codec::codec::Codec) -> bool {
+        false
+    }
+
+    /// Download a frame from the pool to system memory, in the [`sw_format`](Self::sw_format) of
+    /// the pool.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Hardware frame.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn download(frame: &crate::frame::RawFrame) -> Result<crate::frame::RawFrame> {
+        let mut frame_downloaded = crate::frame::RawFrame::empty();
+        ffi_hwaccel::hwdevice_transfer_frame(&mut frame_downloaded, frame)?;
+        crate::ffi::copy_frame_props(frame, &mut frame_downloaded);
+        Ok(frame_downloaded)
+    }
+
+    /// There are no hardware frames on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn download(_frame: &crate::frame::RawFrame) -> Result<crate::frame::RawFrame> {
+        Err(Error::UnsupportedCodecHardwareAccelerationDeviceType)
+    }
+
+    /// Set up an encoder to take frames from the pool.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn apply_to(&self, encoder: &mut ffmpeg::codec::Context) {
+        ffi_hwaccel::codec_context_set_hw_frames_ctx(encoder, &self.context);
+    }
+
+    /// There are no hardware frames on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn apply_to(&self, _encoder: &mut ffmpeg::codec::Context) {}
+}
+
+/// How a decoder downloads frames decoded with hardware acceleration to system memory. See
+/// [`DecoderBuilder::with_hardware_download`](crate::decode::DecoderBuilder::with_hardware_download).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum HardwareDownload {
+    /// Copy every frame into newly allocated buffers.
+    #[default]
+    Transfer,
+    /// Copy every frame into a staging buffer that is reused for the next frame, which saves
+    /// allocating and faulting in a frame worth of memory for every frame. A new buffer is only
+    /// allocated when the frame size changes, or when the previous frame is still in use because
+    /// it was returned without scaling. For CUDA, the staging buffers are allocated in pinned
+    /// (page-locked) host memory, which the device copies to at the full bus speed.
+    Staged,
+    /// Map the frame into system memory where the device supports it, like VAAPI, which avoids
+    /// the copy altogether. Devices that cannot map frames, like CUDA, fall back to
+    /// [`HardwareDownload::Staged`].
+    Mapped,
+}
+
+/// Output surface for Android MediaCodec decoding.
+///
+/// When decoding to a surface, frames are not copied to system memory. Decoded frames have the
+/// opaque `MEDIACODEC` pixel format and must be rendered (or discarded) with
+/// [`android::release_frame`].
+#[cfg(target_os = "android")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MediaCodecSurface {
+    /// An `android.view.Surface` Java object reference (`jobject`). This requires that the Java VM
+    /// was registered with [`android::set_java_vm`] first.
+    Surface(*mut std::ffi::c_void),
+    /// An NDK `ANativeWindow` pointer. Not supported with ffmpeg 5.
+    NativeWindow(*mut std::ffi::c_void),
+}
+
+// Surfaces and native windows may be used from any thread, and MediaCodec renders to them from
+// threads of its own.
+#[cfg(target_os = "android")]
+unsafe impl Send for MediaCodecSurface {}
+
+/// Android specific plumbing for the MediaCodec hardware codecs.
+#[cfg(target_os = "android")]
+pub mod android {
+    use crate::error::Error;
+    use crate::frame::RawFrame;
+
+    type Result<T> = std::result::Result<T, Error>;
+
+    /// Register the Java VM with ffmpeg. This must be called once before using MediaCodec, for
+    /// example from `JNI_OnLoad`.
+    ///
+    /// # Arguments
+    ///
+    /// * `java_vm` - Pointer to the `JavaVM`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to a valid `JavaVM` that outlives all usage of ffmpeg.
+    pub unsafe fn set_java_vm(java_vm: *mut std::ffi::c_void) -> Result<()> {
+        match ffmpeg::ffi::av_jni_set_java_vm(java_vm, std::ptr::null_mut()) {
+            0 => Ok(()),
+            e => Err(Error::BackendError(ffmpeg::Error::from(e))),
+        }
+    }
+
+    /// Register the Android application context with ffmpeg. This is required by the NDK
+    /// MediaCodec backend on some devices.
+    ///
+    /// # Arguments
+    ///
+    /// * `app_context` - Global reference to the `android.content.Context`.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must be a valid global JNI reference that outlives all usage of ffmpeg.
+    #[cfg(feature = "ffmpeg7")]
+    pub unsafe fn set_android_app_context(app_context: *mut std::ffi::c_void) -> Result<()> {
+        match ffmpeg::ffi::av_jni_set_android_app_ctx(app_context, std::ptr::null_mut()) {
+            0 => Ok(()),
+            e => Err(Error::BackendError(ffmpeg::Error::from(e))),
+        }
+    }
+
+    /// Release a frame that was decoded to a MediaCodec surface.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame with the `MEDIACODEC` pixel format.
+    /// * `render` - Whether to render the frame to the surface or discard it.
+    pub fn release_frame(frame: &RawFrame, render: bool) -> Result<()> {
+        unsafe {
+            let buffer = (*frame.as_ptr()).data[3] as *mut ffmpeg::ffi::AVMediaCodecBuffer;
+            if buffer.is_null() {
+                return Err(Error::InvalidFrameFormat);
+            }
+            match ffmpeg::ffi::av_mediacodec_release_buffer(buffer, render as std::ffi::c_int) {
+                0 => Ok(()),
+                e => Err(Error::BackendError(ffmpeg::Error::from(e))),
+            }
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum HardwareAccelerationDeviceType {
     /// Video Decode and Presentation API for Unix (VDPAU)
     Vdpau,
@@ -58,9 +363,50 @@ pub enum HardwareAccelerationDeviceType {
     MediaCodec,
     /// Direct3D 12 Video Acceleration
     D3D12Va,
+    /// Video4Linux2 memory-to-memory codecs, as found on the Raspberry Pi and other SoCs. These
+    /// codecs do not use a device context, frames are exchanged in system memory.
+    V4l2M2m,
 }
 
 impl HardwareAccelerationDeviceType {
+    /// Suffix of the wrapper decoders that implement this device type, if the device type uses
+    /// dedicated decoders rather than the hwaccel framework.
+    pub(crate) fn wrapper_decoder_suffix(self) -> Option<&'static str> {
+        match self {
+            HardwareAccelerationDeviceType::MediaCodec => Some("mediacodec"),
+            HardwareAccelerationDeviceType::V4l2M2m => Some("v4l2m2m"),
+            _ => None,
+        }
+    }
+
+    /// Whether or not the device type needs a hardware device context. Device types that are
+    /// implemented purely by wrapper codecs do not.
+    pub(crate) fn requires_device_context(self) -> bool {
+        !matches!(self, HardwareAccelerationDeviceType::V4l2M2m)
+    }
+
+    /// Get the `libavutil` device type to create a device context with, if the device type has
+    /// one. V4L2 M2M codecs do not use a device context.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn hwdevice_type(self) -> Option<ffmpeg::ffi::AVHWDeviceType> {
+        match self {
+            HardwareAccelerationDeviceType::V4l2M2m => None,
+            device_type => Some(device_type.into()),
+        }
+    }
+
+    /// Suffix of the hardware encoders that implement this device type.
+    pub(crate) fn wrapper_encoder_suffix(self) -> Option<&'static str> {
+        match self {
+            HardwareAccelerationDeviceType::MediaCodec => Some("mediacodec"),
+            HardwareAccelerationDeviceType::Cuda => Some("nvenc"),
+            HardwareAccelerationDeviceType::Qsv => Some("qsv"),
+            HardwareAccelerationDeviceType::VideoToolbox => Some("videotoolbox"),
+            HardwareAccelerationDeviceType::V4l2M2m => Some("v4l2m2m"),
+            _ => None,
+        }
+    }
+
     /// Whether or not the device type is available on this system.
     pub fn is_available(self) -> bool {
         Self::list_available().contains(&self)
@@ -68,9 +414,67 @@ impl HardwareAccelerationDeviceType {
 
     /// List available hardware acceleration device types on this system.
     ///
-    /// Uses `av_hwdevice_iterate_types` internally.
+    /// Uses `av_hwdevice_iterate_types` internally, and probes every device type ffmpeg was built
+    /// with by creating a device context with `av_hwdevice_ctx_create`, so that device types
+    /// without hardware or driver are not listed. Device types that are implemented by wrapper
+    /// codecs only (V4L2 M2M) are listed if ffmpeg was built with an H.264 codec for them.
+    ///
+    /// Always empty on `wasm32`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn list_available() -> Vec<HardwareAccelerationDeviceType> {
+        let mut device_types = ffi_hwaccel::hwdevice_list_available_device_types();
+        device_types
+            .retain(|device_type| ffi_hwaccel::HardwareDeviceContext::new(*device_type).is_ok());
+        let v4l2m2m = HardwareAccelerationDeviceType::V4l2M2m;
+        if v4l2m2m.supports_decoding(ffmpeg::codec::Id::H264)
+            || v4l2m2m.supports_encoding(ffmpeg::codec::Id::H264)
+        {
+            device_types.push(v4l2m2m);
+        }
+        device_types
+    }
+
+    /// List available hardware acceleration device types on this system.
+    ///
+    /// Always empty on `wasm32`.
+    #[cfg(target_arch = "wasm32")]
     pub fn list_available() -> Vec<HardwareAccelerationDeviceType> {
-        ffi_hwaccel::hwdevice_list_available_device_types()
+        Vec::new()
+    }
+
+    /// Whether or not the device type can be used to decode the given codec.
+    ///
+    /// This probes the codecs ffmpeg was built with. It does not guarantee that the hardware
+    /// supports the codec, or the resolution of the input.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec to check.
+    pub fn supports_decoding(self, codec_id: ffmpeg::codec::Id) -> bool {
+        if find_wrapper_decoder(codec_id, self).is_some() {
+            return true;
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if self.requires_device_context() {
+            return ffmpeg::codec::decoder::find(codec_id)
+                .and_then(|codec| {
+                    ffi_hwaccel::codec_find_corresponding_hwaccel_pixfmt(&codec, self)
+                })
+                .is_some();
+        }
+        false
+    }
+
+    /// Whether or not the device type can be used to encode the given codec.
+    ///
+    /// This probes the codecs ffmpeg was built with. It does not guarantee that the hardware
+    /// supports the codec.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec to check.
+    pub fn supports_encoding(self, codec_id: ffmpeg::codec::Id) -> bool {
+        find_wrapper_encoder(codec_id, self).is_some()
     }
 }
 
@@ -114,6 +518,68 @@ impl From<HardwareAccelerationDeviceType> for ffmpeg::ffi::AVHWDeviceType {
             HardwareAccelerationDeviceType::D3D12Va => {
                 unimplemented!()
             }
+            // V4L2 M2M codecs have no corresponding device type, see
+            // `HardwareAccelerationDeviceType::hwdevice_type`.
+            HardwareAccelerationDeviceType::V4l2M2m => {
+                unimplemented!()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_d3d11va_keeps_encoder_selection() {
+        // Direct3D 11 frames pick their encoder by the frames they take, not by the device type.
+        assert_eq!(
+            HardwareAccelerationDeviceType::D3D11Va.wrapper_encoder_suffix(),
+            None
+        );
+        assert_eq!(
+            HardwareAccelerationDeviceType::Cuda.wrapper_encoder_suffix(),
+            Some("nvenc")
+        );
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn test_v4l2m2m_has_no_device_context() {
+        let v4l2m2m = HardwareAccelerationDeviceType::V4l2M2m;
+        assert_eq!(v4l2m2m.hwdevice_type(), None);
+        assert!(!v4l2m2m.requires_device_context());
+        assert!(ffi_hwaccel::HardwareDeviceContext::new(v4l2m2m).is_err());
+        assert_eq!(
+            HardwareAccelerationDeviceType::Cuda.hwdevice_type(),
+            Some(ffmpeg::ffi::AV_HWDEVICE_TYPE_CUDA)
+        );
+        // Listed device types have a device context, or codecs of their own.
+        for device_type in HardwareAccelerationDeviceType::list_available() {
+            assert!(
+                !device_type.requires_device_context()
+                    || ffi_hwaccel::HardwareDeviceContext::new(device_type).is_ok()
+            );
+        }
+    }
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    fn test_apply_hardware_frames_twice() {
+        unsafe {
+            let mut frame = crate::frame::RawFrame::empty();
+            let size = std::mem::size_of::<ffmpeg::ffi::AVHWFramesContext>();
+            let pool = ffmpeg::ffi::av_buffer_allocz(size as _);
+            (*frame.as_mut_ptr()).hw_frames_ctx = pool;
+            let hardware_frames = HardwareFrames::of(&frame).unwrap();
+            let mut encoder = ffmpeg::codec::Context::new();
+            hardware_frames.apply_to(&mut encoder);
+            hardware_frames.apply_to(&mut encoder);
+            // The frame, the pool and the encoder hold one reference each.
+            assert_eq!(ffmpeg::ffi::av_buffer_get_ref_count(pool), 3);
+            drop(encoder);
+            assert_eq!(ffmpeg::ffi::av_buffer_get_ref_count(pool), 2);
         }
     }
 }
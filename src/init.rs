@@ -2,6 +2,8 @@ use crate::ffi::init_logging;
 
 /// Initialize global ffmpeg settings. This also intializes the
 /// logging capability and redirect it to `tracing`.
+///
+/// Capture devices are only registered with the `device` feature.
 pub fn init() -> Result<(), Box<dyn std::error::Error>> {
     ffmpeg::init()?;
 
@@ -0,0 +1,659 @@
+//! Frame rate upconversion with motion interpolation, like for smooth slow motion.
+
+use std::collections::VecDeque;
+
+use ffmpeg::Rational as AvRational;
+
+use crate::error::Error;
+#[cfg(feature = "filter")]
+use crate::filter::Filter;
+use crate::frame::RawFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Margin in seconds within which an output frame is considered to fall on an input frame.
+const EPSILON: f64 = 1e-6;
+
+/// How `minterpolate` makes up the frames between two input frames.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum InterpolationMode {
+    /// Duplicate the previous frame.
+    Duplicate,
+    /// Blend the previous and the next frame.
+    Blend,
+    /// Estimate the motion between the frames and compensate for it, which gives smooth motion at
+    /// a much higher cost.
+    #[default]
+    MotionCompensated,
+}
+
+impl InterpolationMode {
+    /// Get the value of the `mi_mode` option.
+    fn name(self) -> &'static str {
+        match self {
+            InterpolationMode::Duplicate => "dup",
+            InterpolationMode::Blend => "blend",
+            InterpolationMode::MotionCompensated => "mci",
+        }
+    }
+}
+
+/// Algorithm `minterpolate` estimates motion with, from exhaustive (slowest) to heuristic.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum MotionEstimation {
+    /// Exhaustive search.
+    Exhaustive,
+    /// Three step search.
+    ThreeStep,
+    /// Two dimensional logarithmic search.
+    Logarithmic,
+    /// New three step search.
+    NewThreeStep,
+    /// Four step search.
+    FourStep,
+    /// Diamond search.
+    Diamond,
+    /// Hexagon-based search.
+    Hexagon,
+    /// Enhanced predictive zonal search.
+    #[default]
+    PredictiveZonal,
+    /// Uneven multi-hexagon search.
+    MultiHexagon,
+}
+
+impl MotionEstimation {
+    /// Get the value of the `me` option.
+    fn name(self) -> &'static str {
+        match self {
+            MotionEstimation::Exhaustive => "esa",
+            MotionEstimation::ThreeStep => "tss",
+            MotionEstimation::Logarithmic => "tdls",
+            MotionEstimation::NewThreeStep => "ntss",
+            MotionEstimation::FourStep => "fss",
+            MotionEstimation::Diamond => "ds",
+            MotionEstimation::Hexagon => "hexbs",
+            MotionEstimation::PredictiveZonal => "epzs",
+            MotionEstimation::MultiHexagon => "umh",
+        }
+    }
+}
+
+/// Options of the `minterpolate` filter, which upconverts the frame rate of video by making up
+/// frames between the input frames.
+///
+/// # Example
+///
+/// ```ignore
+/// let options = Minterpolate::new(AvRational::new(120, 1))
+///     .with_motion_estimation(MotionEstimation::MultiHexagon)
+///     .with_variable_block_size(true);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Minterpolate {
+    frame_rate: AvRational,
+    mode: InterpolationMode,
+    motion_estimation: MotionEstimation,
+    bilateral: bool,
+    adaptive_compensation: bool,
+    block_size: u32,
+    search_range: u32,
+    variable_block_size: bool,
+    scene_change_threshold: Option<f64>,
+}
+
+impl Minterpolate {
+    /// Default block size of the motion estimation.
+    const BLOCK_SIZE: u32 = 16;
+    /// Default search range of the motion estimation.
+    const SEARCH_RANGE: u32 = 32;
+    /// Default scene change detection threshold.
+    const SCENE_CHANGE_THRESHOLD: f64 = 10.0;
+
+    /// Create options with motion compensated interpolation and the defaults of the filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frame rate to upconvert to.
+    pub fn new(frame_rate: AvRational) -> Self {
+        Self {
+            frame_rate,
+            mode: InterpolationMode::default(),
+            motion_estimation: MotionEstimation::default(),
+            bilateral: false,
+            adaptive_compensation: false,
+            block_size: Self::BLOCK_SIZE,
+            search_range: Self::SEARCH_RANGE,
+            variable_block_size: false,
+            scene_change_threshold: Some(Self::SCENE_CHANGE_THRESHOLD),
+        }
+    }
+
+    /// Set how frames are made up.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Interpolation mode.
+    pub fn with_mode(mut self, mode: InterpolationMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the motion estimation algorithm.
+    ///
+    /// # Arguments
+    ///
+    /// * `motion_estimation` - Motion estimation algorithm.
+    pub fn with_motion_estimation(mut self, motion_estimation: MotionEstimation) -> Self {
+        self.motion_estimation = motion_estimation;
+        self
+    }
+
+    /// Set whether motion is estimated bilaterally, from the point of view of the frame to make
+    /// up, instead of bidirectionally, from the previous and the next frame (the default).
+    /// Bilateral estimation avoids holes in the made up frames, but is less accurate.
+    ///
+    /// # Arguments
+    ///
+    /// * `bilateral` - Whether to estimate motion bilaterally.
+    pub fn with_bilateral_estimation(mut self, bilateral: bool) -> Self {
+        self.bilateral = bilateral;
+        self
+    }
+
+    /// Set whether overlapped blocks are compensated adaptively, which reduces blocking artifacts
+    /// around object edges.
+    ///
+    /// # Arguments
+    ///
+    /// * `adaptive_compensation` - Whether to use adaptive overlapped block motion compensation.
+    pub fn with_adaptive_compensation(mut self, adaptive_compensation: bool) -> Self {
+        self.adaptive_compensation = adaptive_compensation;
+        self
+    }
+
+    /// Set the size of the blocks that motion is estimated for.
+    ///
+    /// # Arguments
+    ///
+    /// * `block_size` - Block size in pixels.
+    pub fn with_block_size(mut self, block_size: u32) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Set how far blocks are searched for in the other frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `search_range` - Search range in pixels.
+    pub fn with_search_range(mut self, search_range: u32) -> Self {
+        self.search_range = search_range;
+        self
+    }
+
+    /// Set whether blocks are split into smaller blocks where motion varies within them.
+    ///
+    /// # Arguments
+    ///
+    /// * `variable_block_size` - Whether to use variable size block motion compensation.
+    pub fn with_variable_block_size(mut self, variable_block_size: bool) -> Self {
+        self.variable_block_size = variable_block_size;
+        self
+    }
+
+    /// Set the threshold of the scene change detection. Frames are duplicated instead of made up
+    /// across scene changes, since there is no motion to follow.
+    ///
+    /// # Arguments
+    ///
+    /// * `threshold` - Threshold from 0 to 100, or `None` to disable scene change detection.
+    pub fn with_scene_change_threshold(mut self, threshold: Option<f64>) -> Self {
+        self.scene_change_threshold = threshold;
+        self
+    }
+
+    /// Get the frame rate to upconvert to.
+    pub fn frame_rate(&self) -> AvRational {
+        self.frame_rate
+    }
+
+    /// Get the description of the filter, to use it in a custom filter graph.
+    pub fn spec(&self) -> String {
+        let mut spec = format!(
+            "minterpolate=fps={}/{}:mi_mode={}",
+            self.frame_rate.numerator(),
+            self.frame_rate.denominator(),
+            self.mode.name(),
+        );
+        if self.mode == InterpolationMode::MotionCompensated {
+            spec.push_str(&format!(
+                ":mc_mode={}:me_mode={}:me={}:mb_size={}:search_param={}:vsbmc={}",
+                if self.adaptive_compensation {
+                    "aobmc"
+                } else {
+                    "obmc"
+                },
+                if self.bilateral { "bilat" } else { "bidir" },
+                self.motion_estimation.name(),
+                self.block_size,
+                self.search_range,
+                u8::from(self.variable_block_size),
+            ));
+            match self.scene_change_threshold {
+                Some(threshold) => spec.push_str(&format!(":scd=fdiff:scd_threshold={threshold}")),
+                None => spec.push_str(":scd=none"),
+            }
+        }
+        spec
+    }
+}
+
+/// Frame interpolator that can take the place of `minterpolate` in an [`Interpolator`], like one
+/// that runs a neural network such as RIFE.
+///
+/// The interpolator is only asked to make up frames strictly between two input frames; frames that
+/// fall on an input frame are copied from it.
+pub trait FrameInterpolator: Send {
+    /// Make up a frame between two consecutive frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `previous` - Frame before the frame to make up.
+    /// * `next` - Frame after the frame to make up, with the same format and size.
+    /// * `position` - Position of the frame to make up between the two, greater than 0 (at
+    ///   `previous`) and less than 1 (at `next`).
+    ///
+    /// # Return value
+    ///
+    /// The frame, with the format and size of the input frames. Its timestamp is set by the
+    /// interpolator.
+    fn interpolate(
+        &mut self,
+        previous: &RawFrame,
+        next: &RawFrame,
+        position: f64,
+    ) -> Result<RawFrame>;
+
+    /// Discard any state kept between frames, for instance after seeking.
+    fn reset(&mut self) {}
+}
+
+/// Backend of an [`Interpolator`].
+enum Backend {
+    #[cfg(feature = "filter")]
+    Minterpolate {
+        options: Minterpolate,
+        filter: Option<Filter>,
+    },
+    Plugin {
+        interpolator: Box<dyn FrameInterpolator>,
+        previous: Option<(RawFrame, f64)>,
+        next_tick: Option<u64>,
+        output: VecDeque<RawFrame>,
+    },
+}
+
+/// Upconverts the frame rate of video by making up frames between the input frames, with
+/// `minterpolate` or with a [`FrameInterpolator`].
+///
+/// For slow motion, the frame rate is upconverted by the slowdown factor, and the timestamps of
+/// the output are stretched by it, so that the output plays at the frame rate of the interpolator.
+///
+/// Output frames have timestamps in [`Interpolator::time_base`], the inverse of the frame rate,
+/// in which consecutive output frames have consecutive timestamps. The time base of the input
+/// frames is usually too coarse for that, like 1/30 for frames upconverted to 60 fps.
+///
+/// # Example
+///
+/// ```ignore
+/// // Play 30 fps footage four times slower, at 30 fps.
+/// let mut interpolator =
+///     Interpolator::minterpolate(Minterpolate::new(AvRational::new(30, 1))).with_slow_motion(4);
+/// while let Ok(frame) = decoder.decode_raw() {
+///     interpolator.push(&frame, decoder.time_base())?;
+///     while let Some(frame) = interpolator.pull()? {
+///         // The timestamps are in `interpolator.time_base()`.
+///         encoder.encode_raw(frame)?;
+///     }
+/// }
+/// ```
+pub struct Interpolator {
+    backend: Backend,
+    frame_rate: AvRational,
+    slowdown: u32,
+}
+
+impl Interpolator {
+    /// Create an interpolator that makes up frames with the `minterpolate` filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options of the filter.
+    #[cfg(feature = "filter")]
+    pub fn minterpolate(options: Minterpolate) -> Self {
+        Self {
+            frame_rate: options.frame_rate,
+            backend: Backend::Minterpolate {
+                options,
+                filter: None,
+            },
+            slowdown: 1,
+        }
+    }
+
+    /// Create an interpolator that makes up frames with a [`FrameInterpolator`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frame rate to upconvert to.
+    /// * `interpolator` - Interpolator that makes up the frames.
+    pub fn plugin(frame_rate: AvRational, interpolator: impl FrameInterpolator + 'static) -> Self {
+        Self {
+            frame_rate,
+            backend: Backend::Plugin {
+                interpolator: Box::new(interpolator),
+                previous: None,
+                next_tick: None,
+                output: VecDeque::new(),
+            },
+            slowdown: 1,
+        }
+    }
+
+    /// Slow the output down. The frame rate is upconverted by the factor and the timestamps are
+    /// stretched by it, so the output plays at the frame rate of the interpolator.
+    ///
+    /// # Arguments
+    ///
+    /// * `factor` - Slowdown factor, like `4` for a quarter of the speed.
+    pub fn with_slow_motion(mut self, factor: u32) -> Self {
+        self.slowdown = factor.max(1);
+        self
+    }
+
+    /// Get the frame rate the output plays at.
+    pub fn frame_rate(&self) -> AvRational {
+        self.frame_rate
+    }
+
+    /// Get the time base of the timestamps of the output frames, the inverse of the frame rate.
+    pub fn time_base(&self) -> AvRational {
+        self.frame_rate.invert()
+    }
+
+    /// Push a frame. Frames without a timestamp are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to push.
+    /// * `time_base` - Time base of the timestamp of the frame.
+    pub fn push(&mut self, frame: &RawFrame, time_base: AvRational) -> Result<()> {
+        let Some(timestamp) = frame.timestamp() else {
+            return Ok(());
+        };
+        let rate = self.interpolation_rate();
+        match &mut self.backend {
+            #[cfg(feature = "filter")]
+            Backend::Minterpolate { options, filter } => {
+                let filter = filter.get_or_insert_with(|| {
+                    let options = Minterpolate {
+                        frame_rate: rate,
+                        ..*options
+                    };
+                    let mut filter = Filter::new(options.spec());
+                    // One tick of the interpolation rate per output frame, which the time base of
+                    // the input may be too coarse for.
+                    filter.set_output_time_base(rate.invert());
+                    filter
+                });
+                let mut frame = frame.clone();
+                frame.set_pts(Some(timestamp));
+                filter.push(&frame, time_base)
+            }
+            Backend::Plugin {
+                interpolator,
+                previous,
+                next_tick,
+                output,
+            } => {
+                let secs = timestamp as f64 * f64::from(time_base);
+                let rate = f64::from(rate);
+                let Some((previous_frame, previous_secs)) = previous.take() else {
+                    *previous = Some((frame.clone(), secs));
+                    return Ok(());
+                };
+                if secs <= previous_secs {
+                    // Time went backwards, so start over from this frame.
+                    interpolator.reset();
+                    *next_tick = None;
+                    *previous = Some((frame.clone(), secs));
+                    return Ok(());
+                }
+                for (tick, position) in ticks(next_tick, rate, previous_secs, secs) {
+                    let mut made_up = if position < EPSILON {
+                        previous_frame.clone()
+                    } else {
+                        interpolator.interpolate(&previous_frame, frame, position)?
+                    };
+                    made_up.set_pts(Some(tick as i64));
+                    output.push_back(made_up);
+                }
+                *previous = Some((frame.clone(), secs));
+                Ok(())
+            }
+        }
+    }
+
+    /// Signal the end of the input, after which the remaining frames can be pulled.
+    pub fn finish(&mut self) -> Result<()> {
+        let rate = f64::from(self.interpolation_rate());
+        match &mut self.backend {
+            #[cfg(feature = "filter")]
+            Backend::Minterpolate { filter, .. } => match filter.as_mut() {
+                Some(filter) => filter.finish(),
+                None => Ok(()),
+            },
+            Backend::Plugin {
+                previous,
+                next_tick,
+                output,
+                ..
+            } => {
+                let Some((mut frame, secs)) = previous.take() else {
+                    return Ok(());
+                };
+                // The last frame is shown for one output frame.
+                let tick = ticks(next_tick, rate, secs, secs + 1.0 / rate);
+                if let Some((tick, _)) = tick.first() {
+                    frame.set_pts(Some(*tick as i64));
+                    output.push_back(frame);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Pull an output frame.
+    ///
+    /// # Return value
+    ///
+    /// The frame, with a timestamp in [`Interpolator::time_base`], or `None` if more frames need
+    /// to be pushed first, or the input has ended.
+    pub fn pull(&mut self) -> Result<Option<RawFrame>> {
+        // Frames are made up at the interpolation rate, with one tick of it per frame. Stretching
+        // them for slow motion turns a tick into one frame at the output frame rate, so the
+        // timestamps are in the output time base as they are.
+        match &mut self.backend {
+            #[cfg(feature = "filter")]
+            Backend::Minterpolate { filter, .. } => match filter.as_mut() {
+                Some(filter) => filter.pull(),
+                None => Ok(None),
+            },
+            Backend::Plugin { output, .. } => Ok(output.pop_front()),
+        }
+    }
+
+    /// Discard the frames in the interpolator and start over, for instance after seeking.
+    pub fn reset(&mut self) {
+        match &mut self.backend {
+            #[cfg(feature = "filter")]
+            Backend::Minterpolate { filter, .. } => *filter = None,
+            Backend::Plugin {
+                interpolator,
+                previous,
+                next_tick,
+                output,
+            } => {
+                interpolator.reset();
+                *previous = None;
+                *next_tick = None;
+                output.clear();
+            }
+        }
+    }
+
+    /// Get the frame rate frames are made up at, before stretching for slow motion.
+    fn interpolation_rate(&self) -> AvRational {
+        self.frame_rate * AvRational::new(self.slowdown as i32, 1)
+    }
+}
+
+unsafe impl Send for Interpolator {}
+unsafe impl Sync for Interpolator {}
+
+/// Get the output frames that fall between two input frames, and schedule the next one.
+///
+/// # Arguments
+///
+/// * `next_tick` - Index of the next output frame, or `None` to start at the first output frame at
+///   or after `from`.
+/// * `rate` - Output frame rate.
+/// * `from` - Time of the previous input frame in seconds.
+/// * `to` - Time of the next input frame in seconds.
+///
+/// # Return value
+///
+/// The index of every output frame (its timestamp in the time base of the output frame rate), and
+/// its position between the input frames from 0 to 1 (exclusive).
+fn ticks(next_tick: &mut Option<u64>, rate: f64, from: f64, to: f64) -> Vec<(u64, f64)> {
+    let mut tick = next_tick.unwrap_or_else(|| (from * rate - EPSILON).ceil().max(0.0) as u64);
+    let mut ticks = Vec::new();
+    loop {
+        let secs = tick as f64 / rate;
+        if secs >= to - EPSILON {
+            break;
+        }
+        ticks.push((tick, ((secs - from) / (to - from)).max(0.0)));
+        tick += 1;
+    }
+    *next_tick = Some(tick);
+    ticks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_defaults() {
+        assert_eq!(
+            Minterpolate::new(AvRational::new(60, 1)).spec(),
+            "minterpolate=fps=60/1:mi_mode=mci:mc_mode=obmc:me_mode=bidir:me=epzs:mb_size=16:\
+             search_param=32:vsbmc=0:scd=fdiff:scd_threshold=10",
+        );
+    }
+
+    #[test]
+    fn test_spec_blend() {
+        assert_eq!(
+            Minterpolate::new(AvRational::new(60000, 1001))
+                .with_mode(InterpolationMode::Blend)
+                .spec(),
+            "minterpolate=fps=60000/1001:mi_mode=blend",
+        );
+    }
+
+    fn assert_ticks(ticks: Vec<(u64, f64)>, expected: &[(u64, f64)]) {
+        assert_eq!(ticks.len(), expected.len(), "{ticks:?}");
+        for ((tick, position), (expected_tick, expected_position)) in ticks.iter().zip(expected) {
+            assert_eq!(tick, expected_tick, "{ticks:?}");
+            assert!((position - expected_position).abs() < EPSILON, "{ticks:?}");
+        }
+    }
+
+    #[test]
+    fn test_ticks_double_rate() {
+        let mut next_tick = None;
+        assert_ticks(
+            ticks(&mut next_tick, 60.0, 0.0, 1.0 / 30.0),
+            &[(0, 0.0), (1, 0.5)],
+        );
+        assert_eq!(next_tick, Some(2));
+        assert_ticks(
+            ticks(&mut next_tick, 60.0, 1.0 / 30.0, 2.0 / 30.0),
+            &[(2, 0.0), (3, 0.5)],
+        );
+    }
+
+    /// Interpolator that repeats the previous frame.
+    struct Repeat;
+
+    impl FrameInterpolator for Repeat {
+        fn interpolate(
+            &mut self,
+            previous: &RawFrame,
+            _next: &RawFrame,
+            _position: f64,
+        ) -> Result<RawFrame> {
+            Ok(previous.clone())
+        }
+    }
+
+    fn pull_timestamps(interpolator: &mut Interpolator) -> Vec<i64> {
+        let mut timestamps = Vec::new();
+        while let Some(frame) = interpolator.pull().unwrap() {
+            timestamps.push(frame.pts().unwrap());
+        }
+        timestamps
+    }
+
+    #[test]
+    fn test_plugin_timestamps_do_not_collide() {
+        let time_base = AvRational::new(1, 30);
+        let mut interpolator = Interpolator::plugin(AvRational::new(60, 1), Repeat);
+        assert_eq!(interpolator.time_base(), AvRational::new(1, 60));
+        for pts in 0..3 {
+            let mut frame = RawFrame::new(ffmpeg::format::Pixel::YUV420P, 2, 2);
+            frame.set_pts(Some(pts));
+            interpolator.push(&frame, time_base).unwrap();
+        }
+        interpolator.finish().unwrap();
+        assert_eq!(pull_timestamps(&mut interpolator), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_plugin_slow_motion_timestamps() {
+        let time_base = AvRational::new(1, 30);
+        let mut interpolator =
+            Interpolator::plugin(AvRational::new(30, 1), Repeat).with_slow_motion(4);
+        assert_eq!(interpolator.time_base(), AvRational::new(1, 30));
+        for pts in 0..2 {
+            let mut frame = RawFrame::new(ffmpeg::format::Pixel::YUV420P, 2, 2);
+            frame.set_pts(Some(pts));
+            interpolator.push(&frame, time_base).unwrap();
+        }
+        // Four frames between two input frames, one output frame apart each.
+        assert_eq!(pull_timestamps(&mut interpolator), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_ticks_start_between_frames() {
+        let mut next_tick = None;
+        assert_ticks(
+            ticks(&mut next_tick, 10.0, 0.25, 0.5),
+            &[(3, 0.2), (4, 0.6)],
+        );
+        assert_eq!(next_tick, Some(5));
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Handle to interrupt the blocking operations of a [`Reader`](crate::io::Reader) or
+/// [`Writer`](crate::io::Writer), like connecting to a network source or reading from one that
+/// stalls. Handles are cheap to clone, and all clones interrupt the same reader or writer.
+///
+/// The interrupt callback of the format context is installed when the source or destination is
+/// opened, so cancelling also aborts opening it. Interrupted operations fail with
+/// [`AvError::Exit`](ffmpeg::Error::Exit).
+///
+/// # Example
+///
+/// ```ignore
+/// let interrupt = Interrupt::new();
+/// let handle = interrupt.clone();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(Duration::from_secs(5));
+///     handle.cancel();
+/// });
+/// let reader = ReaderBuilder::new(Url::parse("rtsp://camera/stream")?)
+///     .with_interrupt(interrupt)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    state: Arc<InterruptState>,
+}
+
+impl Interrupt {
+    /// Create a handle that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all current and future blocking operations.
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [`Interrupt::cancel`] was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Get the state that the interrupt callback polls. It lives as long as any clone of the
+    /// handle.
+    pub(crate) fn state(&self) -> &InterruptState {
+        &self.state
+    }
+
+    /// Interrupt blocking operations from a point in time on, like when the wall time limit of
+    /// [`ResourceLimits`](crate::limits::ResourceLimits) is reached. A later deadline replaces an
+    /// earlier one.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - Time to interrupt from, or `None` to remove the deadline.
+    pub(crate) fn set_deadline(&self, deadline: Option<Instant>) {
+        let nanos = self.state.nanos_since_created(deadline);
+        self.state.deadline.store(nanos, Ordering::Relaxed);
+    }
+
+    /// Interrupt a blocking read that takes too long, so that the reader can switch to a fallback
+    /// source. Unlike [`Interrupt::set_deadline`], this is set again for every read.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - Time to interrupt the read from, or `None` to remove the deadline.
+    pub(crate) fn set_stall_deadline(&self, deadline: Option<Instant>) {
+        let nanos = self.state.nanos_since_created(deadline);
+        self.state.stall_deadline.store(nanos, Ordering::Relaxed);
+    }
+}
+
+/// Value of [`InterruptState::deadline`] without a deadline.
+const NO_DEADLINE: u64 = u64::MAX;
+
+/// State polled by the interrupt callback of a format context.
+#[derive(Debug)]
+pub(crate) struct InterruptState {
+    cancelled: AtomicBool,
+    created_at: Instant,
+    /// Deadline in nanoseconds since `created_at`, so that the callback does not need a lock.
+    deadline: AtomicU64,
+    /// Deadline of the current read in nanoseconds since `created_at`.
+    stall_deadline: AtomicU64,
+}
+
+impl InterruptState {
+    /// Whether blocking operations should be aborted.
+    pub(crate) fn is_interrupted(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self.is_past_deadline()
+            || self.is_past(self.stall_deadline.load(Ordering::Relaxed))
+    }
+
+    /// Whether the deadline set with [`Interrupt::set_deadline`] has passed.
+    pub(crate) fn is_past_deadline(&self) -> bool {
+        self.is_past(self.deadline.load(Ordering::Relaxed))
+    }
+
+    /// Whether a deadline in nanoseconds since `created_at` has passed.
+    fn is_past(&self, nanos: u64) -> bool {
+        nanos != NO_DEADLINE && self.created_at.elapsed().as_nanos() >= nanos as u128
+    }
+
+    /// Convert a deadline to nanoseconds since `created_at`.
+    fn nanos_since_created(&self, deadline: Option<Instant>) -> u64 {
+        deadline.map_or(NO_DEADLINE, |deadline| {
+            deadline
+                .saturating_duration_since(self.created_at)
+                .as_nanos()
+                .min(NO_DEADLINE as u128 - 1) as u64
+        })
+    }
+}
+
+impl Default for InterruptState {
+    fn default() -> Self {
+        Self {
+            cancelled: AtomicBool::new(false),
+            created_at: Instant::now(),
+            deadline: AtomicU64::new(NO_DEADLINE),
+            stall_deadline: AtomicU64::new(NO_DEADLINE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel() {
+        let interrupt = Interrupt::new();
+        let handle = interrupt.clone();
+        assert!(!interrupt.is_cancelled());
+        assert!(!interrupt.state().is_interrupted());
+        handle.cancel();
+        assert!(interrupt.is_cancelled());
+        assert!(interrupt.state().is_interrupted());
+    }
+
+    #[test]
+    fn test_deadline() {
+        let interrupt = Interrupt::new();
+        interrupt.set_deadline(Some(Instant::now() + std::time::Duration::from_secs(3600)));
+        assert!(!interrupt.state().is_interrupted());
+        interrupt.set_deadline(Some(Instant::now()));
+        assert!(interrupt.state().is_past_deadline());
+        assert!(interrupt.state().is_interrupted());
+        // The deadline does not cancel the handle, and can be removed.
+        assert!(!interrupt.is_cancelled());
+        interrupt.set_deadline(None);
+        assert!(!interrupt.state().is_interrupted());
+    }
+
+    #[test]
+    fn test_stall_deadline() {
+        let interrupt = Interrupt::new();
+        interrupt.set_stall_deadline(Some(Instant::now()));
+        assert!(interrupt.state().is_interrupted());
+        // The stall deadline is independent of the wall time deadline.
+        assert!(!interrupt.state().is_past_deadline());
+        interrupt.set_stall_deadline(None);
+        assert!(!interrupt.state().is_interrupted());
+    }
+}
@@ -1,17 +1,171 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::{DateTime, Utc};
 use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Id as AvCodecId;
 use ffmpeg::format::context::{Input as AvInput, Output as AvOutput};
 use ffmpeg::media::Type as AvMediaType;
-use ffmpeg::Error as AvError;
+use ffmpeg::util::mathematics::rescale::{Rescale, TIME_BASE};
+use ffmpeg::{Error as AvError, Rational as AvRational};
 
+use crate::bandwidth::{Fallback, FallbackPolicy, ReceiveMeter, ReceiveStats};
+use crate::clock::MediaClock;
 use crate::error::Error;
 use crate::ffi;
-use crate::location::Location;
+use crate::frame::PixelFormat;
+use crate::interrupt::Interrupt;
+use crate::limits::{ResourceGuard, ResourceLimit, ResourceLimits};
+use crate::location::{Location, IMAGE_SEQUENCE_FORMAT};
+use crate::mp4::{Mp4Box, ISO_BMFF_FORMATS};
+use crate::multicast::Multicast;
 use crate::options::Options;
-use crate::packet::Packet;
-use crate::stream::StreamInfo;
+use crate::packet::{Packet, PacketTransform};
+use crate::rtp::RtcpStatistics;
+use crate::stream::{select_track, Chapter, MediaDescription, ResolutionPreference, StreamInfo};
+use crate::tags::{DeviceInfo, GeoLocation, LOCATION_KEYS, MAKE_KEYS, MODEL_KEYS, SOFTWARE_KEYS};
+use crate::time::{format_date_time, parse_date_time};
+use crate::topology::StageKind;
+use crate::validate::{PacketTimestamps, TimestampValidator};
 
 type Result<T> = std::result::Result<T, Error>;
 
+#[cfg(feature = "tokio")]
+pub mod r#async;
+
+/// Parameters of raw video input, which does not describe itself. Used by the `rawvideo` input
+/// format and by capture devices that support choosing the capture format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawVideoParameters {
+    /// Pixel format of the frames.
+    pub pixel_format: PixelFormat,
+    /// Width and height of the frames.
+    pub size: (u32, u32),
+    /// Frame rate, if not the input format default (25 fps for `rawvideo`).
+    pub frame_rate: Option<AvRational>,
+}
+
+impl RawVideoParameters {
+    /// Create raw video parameters.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel_format` - Pixel format of the frames.
+    /// * `width` - Width of the frames.
+    /// * `height` - Height of the frames.
+    pub fn new(pixel_format: PixelFormat, width: u32, height: u32) -> Self {
+        Self {
+            pixel_format,
+            size: (width, height),
+            frame_rate: None,
+        }
+    }
+
+    /// Set the frame rate.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frame rate.
+    pub fn with_frame_rate(mut self, frame_rate: AvRational) -> Self {
+        self.frame_rate = Some(frame_rate);
+        self
+    }
+
+    /// Set the corresponding input format options (`pixel_format`, `video_size` and `framerate`).
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to add to.
+    fn apply_to(&self, options: &mut Options) {
+        options.set("pixel_format", self.pixel_format.name());
+        options.set("video_size", &format!("{}x{}", self.size.0, self.size.1));
+        if let Some(frame_rate) = self.frame_rate {
+            options.set(
+                "framerate",
+                &format!("{}/{}", frame_rate.numerator(), frame_rate.denominator()),
+            );
+        }
+    }
+}
+
+/// How the duration of a source and its streams is determined.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DurationEstimation {
+    /// Use the durations in the container, or the estimates of ffmpeg where there are none.
+    #[default]
+    Container,
+    /// Scan the timestamps of all packets when the container has no duration, like an MPEG-TS
+    /// recording or a file of which the index was lost.
+    ScanIfMissing,
+    /// Always scan the timestamps of all packets, and ignore the durations in the container.
+    Scan,
+}
+
+/// Settings to read damaged files, like recordings that were cut off or files with a broken
+/// index. Demuxing becomes more tolerant of errors at the cost of strictness, so the settings are
+/// meant for recovery workflows, not for regular playback.
+///
+/// # Example
+///
+/// ```ignore
+/// let reader = ReaderBuilder::new(Path::new("damaged.mkv"))
+///     .with_recovery(ReadRecovery::tolerant())
+///     .build()?;
+/// ```
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReadRecovery {
+    /// Discard corrupt packets instead of passing them on to the decoder (`fflags`
+    /// `discardcorrupt`).
+    pub discard_corrupt: bool,
+    /// Generate missing presentation timestamps (`fflags` `genpts`).
+    pub generate_pts: bool,
+    /// Ignore decoding timestamps, for files where they are wrong or out of order (`fflags`
+    /// `igndts`).
+    pub ignore_dts: bool,
+    /// Ignore the index of the file and find packets by reading through it, for files of which the
+    /// index is broken (`fflags` `ignidx`). Seeking becomes slower.
+    pub ignore_index: bool,
+    /// How the duration is determined.
+    pub duration_estimation: DurationEstimation,
+}
+
+impl ReadRecovery {
+    /// Settings with all error tolerance enabled, and the duration scanned if the container does
+    /// not have it.
+    pub fn tolerant() -> Self {
+        Self {
+            discard_corrupt: true,
+            generate_pts: true,
+            ignore_dts: true,
+            ignore_index: true,
+            duration_estimation: DurationEstimation::ScanIfMissing,
+        }
+    }
+
+    /// Set the corresponding input format options (`fflags`), keeping any flags that are set
+    /// already.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to add to.
+    fn apply_to(&self, options: &mut Options) {
+        let mut flags = options.get("fflags").unwrap_or_default().to_string();
+        for (enabled, flag) in [
+            (self.discard_corrupt, "+discardcorrupt"),
+            (self.generate_pts, "+genpts"),
+            (self.ignore_dts, "+igndts"),
+            (self.ignore_index, "+ignidx"),
+        ] {
+            if enabled {
+                flags.push_str(flag);
+            }
+        }
+        if !flags.is_empty() {
+            options.set("fflags", &flags);
+        }
+    }
+}
+
 /// Builds a [`Reader`].
 ///
 /// # Example
@@ -29,7 +183,20 @@ type Result<T> = std::result::Result<T, Error>;
 /// ```
 pub struct ReaderBuilder<'a> {
     source: Location,
+    custom_source: Option<Box<dyn ffi::InputSource>>,
     options: Option<&'a Options>,
+    input_format: Option<&'a str>,
+    raw_video_parameters: Option<RawVideoParameters>,
+    recovery: Option<ReadRecovery>,
+    multicast: Option<Multicast>,
+    rtsp_keepalive_interval: Option<std::time::Duration>,
+    resolution_preference: Option<ResolutionPreference>,
+    resource_limits: Option<ResourceLimits>,
+    strict_timestamps: bool,
+    packet_transforms: Vec<PacketTransform>,
+    fallback: Option<Fallback>,
+    interrupt: Option<Interrupt>,
+    clock: Option<Arc<dyn MediaClock>>,
 }
 
 impl<'a> ReaderBuilder<'a> {
@@ -41,10 +208,45 @@ impl<'a> ReaderBuilder<'a> {
     pub fn new(source: impl Into<Location>) -> Self {
         Self {
             source: source.into(),
+            custom_source: None,
             options: None,
+            input_format: None,
+            raw_video_parameters: None,
+            recovery: None,
+            multicast: None,
+            rtsp_keepalive_interval: None,
+            resolution_preference: None,
+            resource_limits: None,
+            strict_timestamps: false,
+            packet_transforms: Vec::new(),
+            fallback: None,
+            interrupt: None,
+            clock: None,
         }
     }
 
+    /// Create a new reader that reads from an arbitrary byte source, like an encrypted file store,
+    /// through a custom IO context. This is the input counterpart of [`BufWriter`].
+    ///
+    /// The source of the reader is set to `io://reader`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to read. Sources that cannot seek should return an error of kind
+    ///   [`std::io::ErrorKind::Unsupported`] from `seek`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let file = DecryptingReader::new(File::open("video.mp4.enc")?, key);
+    /// let reader = ReaderBuilder::from_reader(file).build()?;
+    /// ```
+    pub fn from_reader(source: impl std::io::Read + std::io::Seek + Send + 'static) -> Self {
+        let mut builder = Self::new(custom_source_location());
+        builder.custom_source = Some(Box::new(source));
+        builder
+    }
+
     /// Specify options for the backend.
     ///
     /// # Arguments
@@ -55,28 +257,421 @@ impl<'a> ReaderBuilder<'a> {
         self
     }
 
+    /// Force the input format instead of probing it from the source. This is required for
+    /// sources that cannot be probed, like raw frames read from a pipe.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Name of the input format, like `rawvideo` or `h264`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let reader = ReaderBuilder::new(Location::Fd(0))
+    ///     .with_input_format("rawvideo")
+    ///     .with_raw_video_parameters(
+    ///         RawVideoParameters::new(PixelFormat::RGB24, 1280, 720)
+    ///             .with_frame_rate(AvRational::new(30, 1)),
+    ///     )
+    ///     .build()?;
+    /// ```
+    pub fn with_input_format(mut self, format: &'a str) -> Self {
+        self.input_format = Some(format);
+        self
+    }
+
+    /// Set the parameters of raw video input. If no input format was set with
+    /// [`ReaderBuilder::with_input_format`], the `rawvideo` input format is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `parameters` - Raw video parameters.
+    pub fn with_raw_video_parameters(mut self, parameters: RawVideoParameters) -> Self {
+        self.raw_video_parameters = Some(parameters);
+        self
+    }
+
+    /// Read damaged files more tolerantly. See [`ReadRecovery`].
+    ///
+    /// # Arguments
+    ///
+    /// * `recovery` - Recovery settings.
+    pub fn with_recovery(mut self, recovery: ReadRecovery) -> Self {
+        self.recovery = Some(recovery);
+        self
+    }
+
+    /// Join a multicast group with typed options, like the interface to join on and the sources
+    /// to receive from, instead of URL query parameters. See [`Multicast`].
+    ///
+    /// # Arguments
+    ///
+    /// * `multicast` - Multicast options.
+    pub fn with_multicast(mut self, multicast: Multicast) -> Self {
+        self.multicast = Some(multicast);
+        self
+    }
+
+    /// Set the interval at which keepalive requests are sent to RTSP servers. By default, ffmpeg
+    /// uses half of the session timeout announced by the server, which some cameras set too high
+    /// (or omit) and then silently drop the session.
+    ///
+    /// Keepalive requests are sent while reading packets, so the reader must be read regularly.
+    /// Has no effect on sources other than RTSP.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Keepalive interval. Rounded down to whole seconds, with a minimum of one
+    ///   second.
+    pub fn with_rtsp_keepalive_interval(mut self, interval: std::time::Duration) -> Self {
+        self.rtsp_keepalive_interval = Some(interval);
+        self
+    }
+
+    /// Select one of multiple video streams by resolution, for example the main-stream or
+    /// sub-stream of an IP camera that exposes both over RTSP. The selected stream is returned by
+    /// [`Reader::best_video_stream_index`], and packets of the other video streams are discarded.
+    ///
+    /// Use [`Reader::media_descriptions`] to enumerate the available streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `preference` - Resolution preference to select stream by.
+    pub fn select_stream(mut self, preference: ResolutionPreference) -> Self {
+        self.resolution_preference = Some(preference);
+        self
+    }
+
+    /// Set limits on the resources the reader may consume. See [`ResourceLimits`].
+    ///
+    /// The wall time limit is enforced through the interrupt handle of the reader (see
+    /// [`ReaderBuilder::with_interrupt`]), so it also aborts opening the source and reads that
+    /// block.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - Resource limits.
+    pub fn with_resource_limits(mut self, limits: ResourceLimits) -> Self {
+        self.resource_limits = Some(limits);
+        self
+    }
+
+    /// Validate the timestamps of every packet that is read, for debugging. Reading fails with
+    /// [`Error::TimestampViolation`] at the first packet with a timestamp that is out of range, a
+    /// decoding timestamp that does not increase or a presentation timestamp before its decoding
+    /// timestamp. The violation is logged with the stream, packet number and time base.
+    pub fn with_strict_timestamps(mut self) -> Self {
+        self.strict_timestamps = true;
+        self
+    }
+
+    /// Apply a transform to every packet after it is read, like decryption of a custom DRM scheme
+    /// or removal of a watermark. Transforms are applied in the order they were added, and reading
+    /// fails with the error of a transform that fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - Transform to apply. Use [`Packet::stream_index`] to tell the streams apart.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let reader = ReaderBuilder::new(Path::new("protected.mkv"))
+    ///     .with_packet_transform(move |packet| {
+    ///         cipher.apply_keystream(packet.data_mut()?);
+    ///         Ok(())
+    ///     })
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn with_packet_transform(
+        mut self,
+        transform: impl Fn(&mut Packet) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.packet_transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Switch to fallback sources when the source cannot keep up, like from the main-stream of an
+    /// IP camera to its sub-stream over a congested link. When reading keeps stalling as set by
+    /// the policy, the reader opens the next fallback source in its place and continues reading
+    /// from it. A source that stops sending altogether is switched away from as well, since a
+    /// read that blocks is interrupted once it lasts as long as the stalls that trigger a switch.
+    ///
+    /// Fallback sources are opened with the options, input format, recovery, multicast, RTSP
+    /// keepalive and stream selection settings of the main source. Resource limits start over for
+    /// every fallback source, and packet transforms, timestamp validation and the clock carry over
+    /// to it. A fallback source that cannot be opened is skipped, and the reader stays on the last
+    /// source once all fallback sources are used.
+    ///
+    /// The streams of a fallback source may differ from those of the main source in number and
+    /// parameters, so check [`ReceiveStats::switches`] and set up decoders again after a switch.
+    ///
+    /// # Arguments
+    ///
+    /// * `sources` - Fallback sources, in the order to switch to them.
+    /// * `policy` - When to switch.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let main = Url::parse("rtsp://camera/main").unwrap();
+    /// let sub = Url::parse("rtsp://camera/sub").unwrap();
+    /// let mut reader = ReaderBuilder::new(main)
+    ///     .with_fallback_sources([sub.into()], FallbackPolicy::default())
+    ///     .build()?;
+    /// ```
+    pub fn with_fallback_sources(
+        mut self,
+        sources: impl IntoIterator<Item = Location>,
+        policy: FallbackPolicy,
+    ) -> Self {
+        self.fallback = Some(Fallback {
+            sources: sources.into_iter().collect(),
+            policy,
+            options: None,
+            input_format: None,
+            recovery: None,
+            multicast: None,
+            rtsp_keepalive_interval: None,
+            resolution_preference: None,
+            resource_limits: None,
+        });
+        self
+    }
+
+    /// Interrupt blocking operations of the reader with a handle, including opening the source.
+    /// Without it, the reader gets a handle of its own, see [`Reader::interrupt`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interrupt` - Interrupt handle.
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Synchronize the frames decoded from the reader against a clock, like the master clock of a
+    /// live mix. A [`Decoder`](crate::decode::Decoder) drops frames that are late against the
+    /// clock. See [`MediaClock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to synchronize against.
+    pub fn with_clock(mut self, clock: Arc<dyn MediaClock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
     /// Build [`Reader`].
-    pub fn build(self) -> Result<Reader> {
-        match self.options {
-            None => Ok(Reader {
-                input: ffmpeg::format::input(&self.source.as_path())?,
-                source: self.source,
-            }),
-            Some(options) => Ok(Reader {
-                input: ffmpeg::format::input_with_dictionary(
-                    &self.source.as_path(),
-                    options.to_dict(),
-                )?,
-                source: self.source,
-            }),
+    pub fn build(mut self) -> Result<Reader> {
+        let rtsp_keepalive_interval = self.rtsp_keepalive_interval;
+        let resolution_preference = self.resolution_preference;
+        let resource_limits = self.resource_limits;
+        let clock = self.clock.take();
+        let strict_timestamps = self.strict_timestamps;
+        let duration_estimation = self
+            .recovery
+            .map(|recovery| recovery.duration_estimation)
+            .unwrap_or_default();
+        let packet_transforms = std::mem::take(&mut self.packet_transforms);
+        let fallback = self.fallback.take().map(|fallback| Fallback {
+            options: self.options.cloned(),
+            input_format: self.input_format.map(str::to_string),
+            recovery: self.recovery,
+            multicast: self.multicast.clone(),
+            rtsp_keepalive_interval,
+            resolution_preference,
+            resource_limits,
+            ..fallback
+        });
+        // The wall time counts from opening the source, and the interrupt callback aborts opening
+        // or reading when it runs out.
+        let opened_at = Instant::now();
+        let max_duration = resource_limits.and_then(|limits| limits.max_duration());
+        if let Some(max_duration) = max_duration {
+            self.interrupt
+                .get_or_insert_with(Interrupt::new)
+                .set_deadline(Some(opened_at + max_duration));
+        }
+        let mut reader = match self.build_input() {
+            Err(Error::BackendError(AvError::Exit))
+                if max_duration.is_some_and(|max| opened_at.elapsed() >= max) =>
+            {
+                return Err(Error::ResourceLimitExceeded(ResourceLimit::Duration(
+                    max_duration.unwrap_or_default(),
+                )));
+            }
+            reader => reader?,
+        };
+        if let Some(fallback) = fallback {
+            reader.meter = ReceiveMeter::new(fallback.policy);
+            reader.fallback = Some(fallback);
+        }
+        match duration_estimation {
+            DurationEstimation::Container => {}
+            DurationEstimation::ScanIfMissing if reader.input.duration() > 0 => {}
+            DurationEstimation::ScanIfMissing | DurationEstimation::Scan => {
+                reader.scan_duration()?
+            }
+        }
+        reader.packet_transforms = packet_transforms;
+        reader.clock = clock;
+        if let Some(limits) = resource_limits {
+            reader.guard = ResourceGuard::starting_at(limits, opened_at);
+        }
+        if strict_timestamps {
+            reader.validator = Some(TimestampValidator::new(StageKind::Reader));
+        }
+        if let Some(interval) = rtsp_keepalive_interval {
+            ffi::rtsp_set_keepalive_interval(
+                &mut reader.input,
+                interval.as_secs().min(u32::MAX as u64) as u32,
+            );
+        }
+        if let Some(preference) = resolution_preference {
+            let media_descriptions = reader.media_descriptions();
+            let selected_stream_index = preference
+                .select(&media_descriptions)
+                .ok_or(AvError::StreamNotFound)?;
+            for media_description in media_descriptions {
+                if media_description.media_type == AvMediaType::Video
+                    && media_description.stream_index != selected_stream_index
+                {
+                    ffi::set_stream_discard(
+                        &mut reader.input,
+                        media_description.stream_index,
+                        true,
+                    );
+                }
+            }
+            reader.selected_video_stream_index = Some(selected_stream_index);
+        }
+        Ok(reader)
+    }
+
+    fn build_input(self) -> Result<Reader> {
+        let input_format = match (self.input_format, self.raw_video_parameters, &self.source) {
+            (Some(name), _, _) => Some(name),
+            (None, Some(_), _) => Some("rawvideo"),
+            (None, None, Location::ImageSequence { .. }) => Some(IMAGE_SEQUENCE_FORMAT),
+            (None, None, _) => None,
+        }
+        .map(|name| ffi::find_input_format(name).ok_or(AvError::DemuxerNotFound))
+        .transpose()?;
+        let options = if self.raw_video_parameters.is_some()
+            || self.recovery.is_some()
+            || self.multicast.is_some()
+            || matches!(self.source, Location::ImageSequence { .. })
+        {
+            let mut options = self.options.cloned().unwrap_or_default();
+            if let Location::ImageSequence {
+                frame_rate: (numerator, denominator),
+                ..
+            } = self.source
+            {
+                options.set("framerate", &format!("{numerator}/{denominator}"));
+            }
+            if let Some(parameters) = self.raw_video_parameters {
+                parameters.apply_to(&mut options);
+            }
+            if let Some(recovery) = self.recovery {
+                recovery.apply_to(&mut options);
+            }
+            if let Some(multicast) = self.multicast.as_ref() {
+                multicast.apply_to(&mut options);
+            }
+            Some(options)
+        } else {
+            self.options.cloned()
+        };
+
+        let interrupt = self.interrupt.unwrap_or_default();
+        let custom_source = match &self.source {
+            Location::Buf(data) => Some(Box::new(std::io::Cursor::new(data.clone())) as Box<_>),
+            _ => self.custom_source,
+        };
+        if let Some(custom_source) = custom_source {
+            let (input, io, unused_options) = ffi::input_raw_io(
+                custom_source,
+                true,
+                input_format.as_ref(),
+                options.map(|options| options.to_dict()),
+                &interrupt,
+            )?;
+            return Ok(Reader::from_input(
+                self.source,
+                input,
+                interrupt,
+                Some(io),
+                Options::unused_keys(unused_options, "reader"),
+            ));
+        }
+
+        match self.source.with_protocol_options(options.as_ref()) {
+            None if input_format.is_none() => {
+                let (input, _) = ffi::input_with_options(
+                    &self.source.to_path(),
+                    None,
+                    ffmpeg::Dictionary::new(),
+                    &interrupt,
+                )?;
+                Ok(Reader::from_input(
+                    self.source,
+                    input,
+                    interrupt,
+                    None,
+                    Vec::new(),
+                ))
+            }
+            options => {
+                let (input, unused_options) = ffi::input_with_options(
+                    &self.source.to_path(),
+                    input_format.as_ref(),
+                    options.unwrap_or_default().to_dict(),
+                    &interrupt,
+                )?;
+                Ok(Reader::from_input(
+                    self.source,
+                    input,
+                    interrupt,
+                    None,
+                    Options::unused_keys(unused_options, "reader"),
+                ))
+            }
         }
     }
 }
 
+/// Location of readers that read from a custom source, see [`ReaderBuilder::from_reader`].
+pub(crate) fn custom_source_location() -> Location {
+    Location::Network(url::Url::parse("io://reader").expect("valid url"))
+}
+
 /// Video reader that can read from files.
 pub struct Reader {
     pub source: Location,
     pub input: AvInput,
+    /// Interrupt polled by the input. Must be dropped after `input`.
+    pub(crate) interrupt: Interrupt,
+    /// Custom IO context when not reading from a file or URL. Must be dropped after `input`.
+    pub(crate) _io: Option<ffi::InputIo>,
+    /// Video stream selected with [`ReaderBuilder::select_stream`].
+    pub(crate) selected_video_stream_index: Option<usize>,
+    /// Resource usage and limits set with [`ReaderBuilder::with_resource_limits`].
+    pub(crate) guard: ResourceGuard,
+    /// Keys of options that were not used when opening the source.
+    pub(crate) unused_options: Vec<String>,
+    /// Timestamp validation enabled with [`ReaderBuilder::with_strict_timestamps`].
+    pub(crate) validator: Option<TimestampValidator>,
+    /// Transforms added with [`ReaderBuilder::with_packet_transform`].
+    pub(crate) packet_transforms: Vec<PacketTransform>,
+    /// Receive statistics, see [`Reader::receive_stats`].
+    pub(crate) meter: ReceiveMeter,
+    /// Fallback sources set with [`ReaderBuilder::with_fallback_sources`].
+    pub(crate) fallback: Option<Fallback>,
+    /// Clock set with [`ReaderBuilder::with_clock`].
+    pub(crate) clock: Option<Arc<dyn MediaClock>>,
 }
 
 impl Reader {
@@ -90,6 +685,38 @@ impl Reader {
         ReaderBuilder::new(source).build()
     }
 
+    /// Create a reader around an opened input, with the default settings of [`ReaderBuilder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Location the input was opened from.
+    /// * `input` - Opened input.
+    /// * `interrupt` - Interrupt polled by the input.
+    /// * `io` - Custom IO context of the input, if it does not read from a file or URL.
+    /// * `unused_options` - Keys of options that were not used when opening the input.
+    pub(crate) fn from_input(
+        source: Location,
+        input: AvInput,
+        interrupt: Interrupt,
+        io: Option<ffi::InputIo>,
+        unused_options: Vec<String>,
+    ) -> Self {
+        Self {
+            source,
+            input,
+            interrupt,
+            _io: io,
+            selected_video_stream_index: None,
+            guard: ResourceGuard::default(),
+            unused_options,
+            validator: None,
+            packet_transforms: Vec::new(),
+            meter: ReceiveMeter::new(FallbackPolicy::default()),
+            fallback: None,
+            clock: None,
+        }
+    }
+
     /// Read a single packet from the source video file.
     ///
     /// # Arguments
@@ -108,10 +735,28 @@ impl Reader {
     pub fn read(&mut self, stream_index: usize) -> Result<Packet> {
         let mut error_count = 0;
         loop {
-            match self.input.packets().next() {
-                Some((stream, packet)) => {
-                    if stream.index() == stream_index {
-                        return Ok(Packet::new(packet, stream.time_base()));
+            self.guard.check_duration()?;
+            self.switch_source_if_stalled();
+            let started = Instant::now();
+            match self.read_next()? {
+                Some((packet_stream_index, packet, time_base)) => {
+                    let now = Instant::now();
+                    self.meter
+                        .record(now, now.duration_since(started), packet.size());
+                    self.guard.count_packet()?;
+                    if let Some(validator) = self.validator.as_mut() {
+                        validator.check_packet(
+                            packet_stream_index,
+                            PacketTimestamps::of(&packet),
+                            time_base,
+                        )?;
+                    }
+                    if packet_stream_index == stream_index {
+                        let mut packet = Packet::new(packet, time_base);
+                        for transform in &self.packet_transforms {
+                            transform(&mut packet)?;
+                        }
+                        return Ok(packet);
                     }
                 }
                 None => {
@@ -124,6 +769,185 @@ impl Reader {
         }
     }
 
+    /// Read a single packet from any stream. Use [`Packet::stream_index`] to find out which stream
+    /// the packet belongs to.
+    pub fn read_any(&mut self) -> Result<Packet> {
+        let mut error_count = 0;
+        loop {
+            self.guard.check_duration()?;
+            self.switch_source_if_stalled();
+            let started = Instant::now();
+            match self.read_next()? {
+                Some((stream_index, packet, time_base)) => {
+                    let now = Instant::now();
+                    self.meter
+                        .record(now, now.duration_since(started), packet.size());
+                    self.guard.count_packet()?;
+                    if let Some(validator) = self.validator.as_mut() {
+                        validator.check_packet(
+                            stream_index,
+                            PacketTimestamps::of(&packet),
+                            time_base,
+                        )?;
+                    }
+                    let mut packet = Packet::new(packet, time_base);
+                    for transform in &self.packet_transforms {
+                        transform(&mut packet)?;
+                    }
+                    return Ok(packet);
+                }
+                None => {
+                    error_count += 1;
+                    if error_count > 3 {
+                        return Err(Error::ReadExhausted);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Read the next packet of any stream from the input, with the index and time base of its
+    /// stream. Returns `None` at the end of the input.
+    ///
+    /// Unlike the packet iterator of ffmpeg, which retries on every error, this fails when the
+    /// read was interrupted, so that a cancelled read does not retry forever. A read interrupted
+    /// by the wall time limit fails with [`Error::ResourceLimitExceeded`].
+    ///
+    /// With fallback sources left to switch to, a read that blocks for as long as the stalls that
+    /// trigger a switch is interrupted, and reading continues from the next fallback source.
+    pub(crate) fn read_next(&mut self) -> Result<Option<(usize, AvPacket, AvRational)>> {
+        let mut packet = AvPacket::empty();
+        loop {
+            let started = Instant::now();
+            let stall_deadline = self.stall_deadline(started);
+            self.interrupt.set_stall_deadline(stall_deadline);
+            let result = packet.read(&mut self.input);
+            self.interrupt.set_stall_deadline(None);
+            let now = Instant::now();
+            // Interrupted reads fail with an error of the protocol rather than an exit at times.
+            if !matches!(result, Ok(()) | Err(AvError::Eof))
+                && !self.interrupt.is_cancelled()
+                && stall_deadline.is_some_and(|deadline| now >= deadline)
+            {
+                self.guard.check_duration()?;
+                self.meter.record_blocked(now, now.duration_since(started));
+                self.switch_source_if_stalled();
+                continue;
+            }
+            match result {
+                Ok(()) => {
+                    let stream_index = packet.stream();
+                    let time_base = self
+                        .input
+                        .stream(stream_index)
+                        .ok_or(AvError::StreamNotFound)?
+                        .time_base();
+                    return Ok(Some((stream_index, packet, time_base)));
+                }
+                Err(AvError::Eof) => return Ok(None),
+                Err(AvError::Exit) => {
+                    self.guard.check_duration()?;
+                    return Err(Error::BackendError(AvError::Exit));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    /// Get the time from which a read that started at a point in time is interrupted to switch
+    /// to the next fallback source, if there is one left.
+    ///
+    /// # Arguments
+    ///
+    /// * `started` - Time the read started.
+    fn stall_deadline(&self, started: Instant) -> Option<Instant> {
+        let fallback = self
+            .fallback
+            .as_ref()
+            .filter(|fallback| !fallback.sources.is_empty())?;
+        Some(started + self.meter.time_to_stalls(fallback.policy.max_stalls))
+    }
+
+    /// Get a handle to interrupt blocking operations of the reader from another thread, like a
+    /// read from a network source that stalls. See [`Interrupt`].
+    pub fn interrupt(&self) -> Interrupt {
+        self.interrupt.clone()
+    }
+
+    /// Get the clock that frames are synchronized against. See [`ReaderBuilder::with_clock`].
+    pub fn clock(&self) -> Option<Arc<dyn MediaClock>> {
+        self.clock.clone()
+    }
+
+    /// Get the receive statistics of the source, like the bitrate it is received at and how often
+    /// reading stalled, for example to show the quality of a live connection.
+    pub fn receive_stats(&self) -> ReceiveStats {
+        self.meter.stats(Instant::now())
+    }
+
+    /// Switch to the next fallback source if reading stalled too often. See
+    /// [`ReaderBuilder::with_fallback_sources`].
+    fn switch_source_if_stalled(&mut self) {
+        let Some(fallback) = self.fallback.as_mut() else {
+            return;
+        };
+        if self.meter.recent_stalls() < fallback.policy.max_stalls as usize {
+            return;
+        }
+        while let Some(source) = fallback.sources.pop_front() {
+            let mut builder =
+                ReaderBuilder::new(source.clone()).with_interrupt(self.interrupt.clone());
+            if let Some(options) = fallback.options.as_ref() {
+                builder = builder.with_options(options);
+            }
+            if let Some(input_format) = fallback.input_format.as_deref() {
+                builder = builder.with_input_format(input_format);
+            }
+            if let Some(recovery) = fallback.recovery {
+                builder = builder.with_recovery(recovery);
+            }
+            if let Some(multicast) = fallback.multicast.clone() {
+                builder = builder.with_multicast(multicast);
+            }
+            if let Some(interval) = fallback.rtsp_keepalive_interval {
+                builder = builder.with_rtsp_keepalive_interval(interval);
+            }
+            if let Some(preference) = fallback.resolution_preference {
+                builder = builder.select_stream(preference);
+            }
+            if let Some(limits) = fallback.resource_limits {
+                builder = builder.with_resource_limits(limits);
+            }
+            match builder.build() {
+                Ok(reader) => {
+                    tracing::warn!(
+                        "reading from {} keeps stalling, switching to {source}",
+                        self.source,
+                    );
+                    // The input must be dropped before its IO context. Packet transforms and the
+                    // clock stay with the reader.
+                    self.input = reader.input;
+                    self._io = reader._io;
+                    self.source = reader.source;
+                    self.selected_video_stream_index = reader.selected_video_stream_index;
+                    self.guard = reader.guard;
+                    if self.validator.is_some() {
+                        self.validator = Some(TimestampValidator::new(StageKind::Reader));
+                    }
+                    self.meter.switched();
+                    return;
+                }
+                Err(err) => tracing::warn!("cannot open fallback source {source}: {err}"),
+            }
+        }
+    }
+
+    /// Get the keys of the options given with [`ReaderBuilder::with_options`] that neither the
+    /// demuxer nor the protocol used, like options with typos. A warning is logged for each.
+    pub fn unused_options(&self) -> &[String] {
+        &self.unused_options
+    }
+
     /// Retrieve stream information for a stream. Stream information can be used to set up a
     /// corresponding stream for transmuxing or transcoding.
     ///
@@ -174,8 +998,139 @@ impl Reader {
         self.input.seek(i64::MIN, ..).map_err(Error::BackendError)
     }
 
-    /// Find the best video stream and return the index.
+    /// Determine the duration of the source and its streams from the timestamps of all packets,
+    /// and seek back to the start.
+    fn scan_duration(&mut self) -> Result<()> {
+        // First and last timestamp of every stream, in the time base of the stream.
+        let mut ranges: Vec<Option<(i64, i64)>> = vec![None; self.input.nb_streams() as usize];
+        for (stream, packet) in self.input.packets() {
+            let Some(pts) = packet.pts().or(packet.dts()) else {
+                continue;
+            };
+            let end = pts + packet.duration().max(0);
+            let Some(range) = ranges.get_mut(stream.index()) else {
+                continue;
+            };
+            *range = Some(match *range {
+                Some((first, last)) => (first.min(pts), last.max(end)),
+                None => (pts, end),
+            });
+        }
+        let mut duration = 0;
+        for (stream_index, range) in ranges.into_iter().enumerate() {
+            let Some((first, last)) = range else {
+                continue;
+            };
+            let Some(stream) = self.input.stream(stream_index) else {
+                continue;
+            };
+            let time_base = stream.time_base();
+            ffi::set_stream_duration(&mut self.input, stream_index, last - first);
+            duration = duration.max((last - first).rescale(time_base, TIME_BASE));
+        }
+        ffi::set_input_duration(&mut self.input, duration);
+        self.seek_to_start()
+    }
+
+    /// Pause a network source that supports it, like an RTSP stream, without tearing down the
+    /// session. RTSP sources send a `PAUSE` request, after which the server stops sending packets
+    /// until [`Reader::resume`] is called, so a player can pause without reconnecting.
+    ///
+    /// No keepalive requests are sent while paused, since they are sent while reading. Resume
+    /// before the session timeout of the server passes, or the server may close the session.
+    ///
+    /// # Return value
+    ///
+    /// A backend error with `ENOSYS` if the source cannot be paused. Files and other sources that
+    /// are only read when asked to need no pausing.
+    pub fn pause(&mut self) -> Result<()> {
+        self.input.pause().map_err(Error::BackendError)
+    }
+
+    /// Resume a network source paused with [`Reader::pause`]. RTSP sources send a `PLAY`
+    /// request, after which the server continues sending packets.
+    ///
+    /// # Return value
+    ///
+    /// A backend error with `ENOSYS` if the source cannot be paused and resumed.
+    pub fn resume(&mut self) -> Result<()> {
+        self.input.play().map_err(Error::BackendError)
+    }
+
+    /// Collect RTCP receiver statistics (packet loss and jitter) for each RTP stream of an RTSP
+    /// source, or of an RTP source like a multicast `rtp://` URL or an SDP file.
+    ///
+    /// Returns an empty list for other sources, and for RTSP sources that do not use RTP
+    /// transport.
+    pub fn rtcp_statistics(&self) -> Vec<RtcpStatistics> {
+        ffi::rtsp_rtcp_statistics(&self.input)
+    }
+
+    /// Describe the streams in the source. For RTSP sources, these correspond to the media
+    /// descriptions in the SDP.
+    pub fn media_descriptions(&self) -> Vec<MediaDescription> {
+        self.input
+            .streams()
+            .map(|stream| MediaDescription::from_stream(&stream))
+            .collect()
+    }
+
+    /// Get the chapters of the source, in the order they are stored in, which is usually by start
+    /// time.
+    pub fn chapters(&self) -> Vec<Chapter> {
+        self.input
+            .chapters()
+            .map(|chapter| Chapter::from_chapter(&chapter))
+            .collect()
+    }
+
+    /// Get the time the source was recorded, from the `creation_time` tag of the container (or the
+    /// QuickTime creation date, as written by phones), or else of the first stream that has one.
+    pub fn creation_time(&self) -> Option<DateTime<Utc>> {
+        let metadata = self.input.metadata();
+        ["creation_time", "com.apple.quicktime.creationdate", "date"]
+            .iter()
+            .find_map(|key| metadata.get(key).and_then(|value| parse_date_time(&value)))
+            .or_else(|| {
+                self.media_descriptions()
+                    .into_iter()
+                    .find_map(|description| description.creation_time)
+            })
+    }
+
+    /// Get the location where the source was recorded, from the ISO 6709 location tags that phones
+    /// write (`com.apple.quicktime.location.ISO6709` on iOS, `location` on Android).
+    pub fn geo_location(&self) -> Option<GeoLocation> {
+        let metadata = self.input.metadata();
+        LOCATION_KEYS.iter().find_map(|key| {
+            metadata
+                .get(key)
+                .and_then(|value| GeoLocation::parse_iso6709(&value))
+        })
+    }
+
+    /// Get the make, model and software of the device that recorded the source, from the QuickTime
+    /// and Android device tags.
+    pub fn device_info(&self) -> DeviceInfo {
+        let metadata = self.input.metadata();
+        let find = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| metadata.get(key).filter(|value| !value.is_empty()))
+                .map(|value| value.into_owned())
+        };
+        DeviceInfo {
+            make: find(&MAKE_KEYS),
+            model: find(&MODEL_KEYS),
+            software: find(&SOFTWARE_KEYS),
+        }
+    }
+
+    /// Find the best video stream and return the index. If a stream was selected with
+    /// [`ReaderBuilder::select_stream`], that stream is returned.
     pub fn best_video_stream_index(&self) -> Result<usize> {
+        if let Some(stream_index) = self.selected_video_stream_index {
+            return Ok(stream_index);
+        }
         Ok(self
             .input
             .streams()
@@ -183,6 +1138,77 @@ impl Reader {
             .ok_or(AvError::StreamNotFound)?
             .index())
     }
+
+    /// Find the best audio stream and return the index.
+    pub fn best_audio_stream_index(&self) -> Result<usize> {
+        Ok(self
+            .input
+            .streams()
+            .best(AvMediaType::Audio)
+            .ok_or(AvError::StreamNotFound)?
+            .index())
+    }
+
+    /// Select the audio stream in a language, like a player picks the audio track.
+    ///
+    /// If there is no audio track in the language, this falls back to the audio track flagged as
+    /// default, and then to the best audio stream. See [`Reader::select_subtitle`] for how tracks in
+    /// the language are ranked.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - Preferred language, matched case-insensitively against the language tag of
+    ///   the stream (usually an ISO 639-2 code like "eng") or its title (like "English").
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let reader = Reader::new(Path::new("movie.mkv")).unwrap();
+    /// let audio_stream_index = reader.select_audio("eng").unwrap();
+    /// ```
+    pub fn select_audio(&self, language: &str) -> Result<usize> {
+        let media_descriptions = self.media_descriptions();
+        match select_track(&media_descriptions, AvMediaType::Audio, language) {
+            Some(stream_index) => Ok(stream_index),
+            None => media_descriptions
+                .iter()
+                .find(|description| {
+                    description.media_type == AvMediaType::Audio && description.default
+                })
+                .map(|description| Ok(description.stream_index))
+                .unwrap_or_else(|| self.best_audio_stream_index()),
+        }
+    }
+
+    /// Select the subtitle stream in a language, like a player picks the subtitle track.
+    ///
+    /// Streams with a matching language tag are preferred over streams that only mention the
+    /// language in their title. Among those, regular tracks are preferred over commentary and
+    /// forced tracks, then tracks flagged as default, then the first track.
+    ///
+    /// If there are no subtitles in the language, this falls back to a subtitle track flagged as
+    /// forced, since those cover dialogue that is meant to be shown regardless of the language of
+    /// the viewer.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - Preferred language, matched case-insensitively against the language tag of
+    ///   the stream (usually an ISO 639-2 code like "eng") or its title (like "English").
+    ///
+    /// # Return value
+    ///
+    /// Index of the selected stream, or `None` if no subtitles should be shown.
+    pub fn select_subtitle(&self, language: &str) -> Option<usize> {
+        let media_descriptions = self.media_descriptions();
+        select_track(&media_descriptions, AvMediaType::Subtitle, language).or_else(|| {
+            media_descriptions
+                .iter()
+                .find(|description| {
+                    description.media_type == AvMediaType::Subtitle && description.forced
+                })
+                .map(|description| description.stream_index)
+        })
+    }
 }
 
 unsafe impl Send for Reader {}
@@ -196,6 +1222,18 @@ pub struct WriterBuilder<'a> {
     destination: Location,
     format: Option<&'a str>,
     options: Option<&'a Options>,
+    max_duration: Option<std::time::Duration>,
+    max_size: Option<u64>,
+    creation_time: Option<DateTime<Utc>>,
+    file_time: bool,
+    mp4_boxes: Vec<Mp4Box>,
+    sequential: bool,
+    packet_transforms: Vec<PacketTransform>,
+    io_buffer_size: Option<usize>,
+    direct_io: bool,
+    flush_policy: FlushPolicy,
+    interrupt: Option<Interrupt>,
+    clock: Option<Arc<dyn MediaClock>>,
 }
 
 impl<'a> WriterBuilder<'a> {
@@ -209,6 +1247,18 @@ impl<'a> WriterBuilder<'a> {
             destination: destination.into(),
             format: None,
             options: None,
+            max_duration: None,
+            max_size: None,
+            creation_time: None,
+            file_time: false,
+            mp4_boxes: Vec::new(),
+            sequential: false,
+            packet_transforms: Vec::new(),
+            io_buffer_size: None,
+            direct_io: false,
+            flush_policy: FlushPolicy::default(),
+            interrupt: None,
+            clock: None,
         }
     }
 
@@ -232,33 +1282,275 @@ impl<'a> WriterBuilder<'a> {
         self
     }
 
+    /// Stop writing once the output spans the given duration. Writing continues up to the next
+    /// video keyframe, after which the trailer is written and [`WriteOutcome::LimitReached`] is
+    /// returned. Later packets are discarded.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_duration` - Target duration of the output.
+    pub fn with_max_duration(mut self, max_duration: std::time::Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Stop writing once the output reaches the given size. Writing continues up to the next video
+    /// keyframe, after which the trailer is written and [`WriteOutcome::LimitReached`] is
+    /// returned. Later packets are discarded.
+    ///
+    /// Note that the output overshoots the size by up to one GOP plus the trailer.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_size` - Target size of the output in bytes.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Set the `creation_time` tag of the output, like the recording time of the source from
+    /// [`Reader::creation_time`].
+    ///
+    /// # Arguments
+    ///
+    /// * `creation_time` - Time the media was recorded.
+    pub fn with_creation_time(mut self, creation_time: DateTime<Utc>) -> Self {
+        self.creation_time = Some(creation_time);
+        self
+    }
+
+    /// Set the modification time of the output file to the creation time (see
+    /// [`WriterBuilder::with_creation_time`]) after the trailer is written, so that file browsers
+    /// sort the output by recording time. Does nothing for destinations other than files.
+    pub fn with_file_time(mut self) -> Self {
+        self.file_time = true;
+        self
+    }
+
+    /// Add a custom top-level box to the output, like an XMP packet or a `uuid` box with an asset
+    /// identifier. The boxes are appended to the file after the trailer is written.
+    ///
+    /// Only supported for files in an ISO base media format (like MP4 and MOV). Building the writer
+    /// fails with [`AvError::InvalidData`] for other destinations and formats.
+    ///
+    /// # Arguments
+    ///
+    /// * `mp4_box` - Box to add.
+    pub fn with_mp4_box(mut self, mp4_box: Mp4Box) -> Self {
+        self.mp4_boxes.push(mp4_box);
+        self
+    }
+
+    /// Write strictly sequentially: the muxer never seeks back to patch what it wrote before, so
+    /// the output can go straight into a sink that cannot seek, like the body of an HTTP PUT
+    /// request or a pipe.
+    ///
+    /// The container format is configured for it: MP4 and MOV are fragmented (`movflags` gets
+    /// `frag_keyframe+empty_moov+default_base_moof`), Matroska and WebM are written in live mode,
+    /// FLV and MP3 skip the duration and size fields that are patched at the end. MPEG-TS, ADTS,
+    /// Ogg, NUT and raw bitstreams are sequential as they are. Seeking is disabled on the output to
+    /// enforce it.
+    ///
+    /// Building the writer fails with [`AvError::InvalidData`] for other container formats (like
+    /// AVI), and when the options ask for something that needs seeking, like `movflags` with
+    /// `faststart`.
+    pub fn sequential(mut self) -> Self {
+        self.sequential = true;
+        self
+    }
+
+    /// Apply a transform to every packet before it is written, like encryption for a custom DRM
+    /// scheme, watermark injection or sample obfuscation. Transforms are applied in the order they
+    /// were added, and writing fails with the error of a transform that fails.
+    ///
+    /// # Arguments
+    ///
+    /// * `transform` - Transform to apply. Use [`Packet::stream_index`] to tell the streams apart.
+    pub fn with_packet_transform(
+        mut self,
+        transform: impl Fn(&mut Packet) -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        self.packet_transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Set the size of the IO buffer. A larger buffer means fewer and larger writes, which improves
+    /// throughput on destinations with a high latency per write, like object stores.
+    ///
+    /// Building the writer fails with [`AvError::InvalidData`] if the size is zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Size of the buffer in bytes.
+    pub fn with_io_buffer_size(mut self, size: usize) -> Self {
+        self.io_buffer_size = Some(size);
+        self
+    }
+
+    /// Write directly to the destination instead of going through the IO buffer, like the
+    /// `avioflags direct` option. This minimizes latency at the cost of many small writes.
+    pub fn with_direct_io(mut self) -> Self {
+        self.direct_io = true;
+        self
+    }
+
+    /// Set when the IO buffer is flushed to the destination. See [`FlushPolicy`].
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Flush policy.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush_policy = policy;
+        self
+    }
+
+    /// Interrupt blocking operations of the writer with a handle, including opening the
+    /// destination. Without it, the writer gets a handle of its own, see [`Writer::interrupt`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interrupt` - Interrupt handle.
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Synchronize the frames encoded into the writer against a clock, like the master clock of
+    /// a live mix. An [`Encoder`](crate::encode::Encoder) drops frames that are late against the
+    /// clock. See [`MediaClock`].
+    ///
+    /// # Arguments
+    ///
+    /// * `clock` - Clock to synchronize against.
+    pub fn with_clock(mut self, clock: Arc<dyn MediaClock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
     /// Build [`Writer`].
+    ///
+    /// Note that when writing to a [`Location::Fd`], the container format cannot be guessed from
+    /// the destination and must be set with [`WriterBuilder::with_format`]. Writing to a
+    /// [`Location::Buf`] is not supported, use [`BufWriter`] instead.
     pub fn build(self) -> Result<Writer> {
-        match (self.format, self.options) {
-            (None, None) => Ok(Writer {
-                output: ffmpeg::format::output(&self.destination.as_path())?,
-                destination: self.destination,
-            }),
-            (Some(format), None) => Ok(Writer {
-                output: ffmpeg::format::output_as(&self.destination.as_path(), format)?,
-                destination: self.destination,
-            }),
-            (None, Some(options)) => Ok(Writer {
-                output: ffmpeg::format::output_with(
-                    &self.destination.as_path(),
-                    options.to_dict(),
-                )?,
-                destination: self.destination,
-            }),
-            (Some(format), Some(options)) => Ok(Writer {
-                output: ffmpeg::format::output_as_with(
-                    &self.destination.as_path(),
+        if let Location::Buf(_) = self.destination {
+            return Err(Error::BackendError(AvError::InvalidData));
+        }
+
+        let format = match (self.format, &self.destination) {
+            (None, Location::ImageSequence { .. }) => Some(IMAGE_SEQUENCE_FORMAT),
+            (format, _) => format,
+        };
+        let interrupt = self.interrupt.unwrap_or_default();
+        let (mut output, mut options) = match self.destination.with_protocol_options(self.options) {
+            None => {
+                let (output, _) = ffi::output_with_options(
+                    &self.destination.to_path(),
+                    format,
+                    ffmpeg::Dictionary::new(),
+                    &interrupt,
+                )?;
+                (output, None)
+            }
+            Some(options) => {
+                let (output, unused_options) = ffi::output_with_options(
+                    &self.destination.to_path(),
                     format,
                     options.to_dict(),
-                )?,
-                destination: self.destination,
-            }),
+                    &interrupt,
+                )?;
+                (output, Some(Options::from_dict(unused_options)))
+            }
+        };
+
+        if !self.mp4_boxes.is_empty()
+            && !(matches!(self.destination, Location::File(_))
+                && ISO_BMFF_FORMATS.contains(&output.format().name()))
+        {
+            return Err(Error::BackendError(AvError::InvalidData));
         }
+
+        if self.sequential {
+            let sequential_options = sequential_options(output.format().name())
+                .ok_or(Error::BackendError(AvError::InvalidData))?;
+            let options = options.get_or_insert_with(Options::default);
+            if options
+                .get("movflags")
+                .is_some_and(|movflags| movflags.contains("faststart"))
+            {
+                return Err(Error::BackendError(AvError::InvalidData));
+            }
+            for (key, value) in sequential_options {
+                match options.get(key) {
+                    // Flags the caller set are kept next to the ones that are needed.
+                    Some(flags) if *key == "movflags" => {
+                        let flags = format!("{flags}+{value}");
+                        options.set(key, &flags);
+                    }
+                    _ => options.set(key, value),
+                }
+            }
+            ffi::set_output_unseekable(&mut output);
+        }
+
+        if let Some(size) = self.io_buffer_size {
+            ffi::set_output_io_buffer_size(&mut output, size)?;
+        }
+        if self.direct_io {
+            ffi::set_output_direct_io(&mut output);
+        }
+        if self.flush_policy == FlushPolicy::Packet {
+            ffi::set_output_flush_packets(&mut output);
+        }
+
+        if let Some(creation_time) = self.creation_time {
+            let mut metadata = ffmpeg::Dictionary::new();
+            metadata.set("creation_time", &format_date_time(&creation_time));
+            output.set_metadata(metadata);
+        }
+
+        let file_time = match (&self.destination, self.creation_time) {
+            (Location::File(path), Some(creation_time)) if self.file_time => {
+                Some((path.clone(), creation_time.into()))
+            }
+            _ => None,
+        };
+
+        Ok(Writer {
+            destination: self.destination,
+            output,
+            interrupt,
+            max_duration: self.max_duration,
+            max_size: self.max_size,
+            progress: WriteProgress::default(),
+            file_time,
+            mp4_boxes: self.mp4_boxes,
+            options,
+            unused_options: Vec::new(),
+            packet_transforms: self.packet_transforms,
+            flush_policy: self.flush_policy,
+            last_flush: None,
+            clock: self.clock,
+        })
+    }
+}
+
+/// Muxer options that make a container format write strictly sequentially, or `None` if the
+/// format cannot. See [`WriterBuilder::sequential`].
+///
+/// # Arguments
+///
+/// * `format` - Name of the container format.
+fn sequential_options(format: &str) -> Option<&'static [(&'static str, &'static str)]> {
+    match format {
+        format if ISO_BMFF_FORMATS.contains(&format) => {
+            Some(&[("movflags", "frag_keyframe+empty_moov+default_base_moof")])
+        }
+        "matroska" | "webm" => Some(&[("live", "1")]),
+        "flv" => Some(&[("flvflags", "no_duration_filesize")]),
+        "mp3" => Some(&[("write_xing", "0")]),
+        "mpegts" | "adts" | "ogg" | "opus" | "nut" | "h264" | "hevc" | "rawvideo" => Some(&[]),
+        _ => None,
     }
 }
 
@@ -282,6 +1574,22 @@ impl<'a> WriterBuilder<'a> {
 pub struct Writer {
     pub destination: Location,
     pub(crate) output: AvOutput,
+    /// Interrupt polled by the output. Must be dropped after `output`.
+    interrupt: Interrupt,
+    max_duration: Option<std::time::Duration>,
+    max_size: Option<u64>,
+    progress: WriteProgress,
+    file_time: Option<(std::path::PathBuf, std::time::SystemTime)>,
+    mp4_boxes: Vec<Mp4Box>,
+    /// Options that the protocol did not consume, which are passed on to the muxer.
+    options: Option<Options>,
+    unused_options: Vec<String>,
+    packet_transforms: Vec<PacketTransform>,
+    flush_policy: FlushPolicy,
+    /// Time of the written media at the last flush, in seconds.
+    last_flush: Option<f64>,
+    /// Clock set with [`WriterBuilder::with_clock`].
+    pub(crate) clock: Option<Arc<dyn MediaClock>>,
 }
 
 impl Writer {
@@ -294,10 +1602,354 @@ impl Writer {
     pub fn new(destination: impl Into<Location>) -> Result<Self> {
         WriterBuilder::new(destination).build()
     }
+
+    /// Get a handle to interrupt blocking operations of the writer from another thread, like a
+    /// write to a network destination that stalls. See [`Interrupt`].
+    pub fn interrupt(&self) -> Interrupt {
+        self.interrupt.clone()
+    }
+
+    /// Get the clock that frames are synchronized against. See [`WriterBuilder::with_clock`].
+    pub fn clock(&self) -> Option<Arc<dyn MediaClock>> {
+        self.clock.clone()
+    }
+
+    /// Check whether the container format of the writer can store a codec.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec to check.
+    pub fn supports_codec(&self, codec_id: AvCodecId) -> SupportLevel {
+        SupportLevel::for_format(&self.output.format(), codec_id)
+    }
+
+    /// Get the codec the container format of the writer uses for a media type by default, or
+    /// `None` if it has no default for the media type.
+    ///
+    /// # Arguments
+    ///
+    /// * `media_type` - Media type to get the default codec for.
+    pub fn default_codec(&self, media_type: AvMediaType) -> Option<AvCodecId> {
+        Some(ffi::default_codec(&self.output.format(), media_type))
+            .filter(|codec_id| *codec_id != AvCodecId::None)
+    }
+
+    /// Get the keys of the options given with [`WriterBuilder::with_options`] that neither the
+    /// protocol nor the muxer used, like options with typos. Known after the header was written. A
+    /// warning is logged for each.
+    pub fn unused_options(&self) -> &[String] {
+        &self.unused_options
+    }
+
+    /// Get how much has been written so far.
+    pub fn summary(&self) -> WriteSummary {
+        WriteSummary {
+            duration: std::time::Duration::from_secs_f64(
+                (self.progress.end - self.progress.start).max(0.0),
+            ),
+            bytes: self.bytes_written(),
+            packets: self.progress.packets,
+        }
+    }
+
+    /// Whether or not writing stopped because a limit was reached.
+    #[inline]
+    pub fn is_limit_reached(&self) -> bool {
+        self.progress.finished
+    }
+
+    /// Number of bytes written to the output, or the total packet size if the output position
+    /// cannot be determined.
+    fn bytes_written(&self) -> u64 {
+        ffi::output_position(&self.output).unwrap_or(self.progress.packet_bytes)
+    }
+
+    /// Check the limits before writing a packet. When a limit was reached and the packet is a
+    /// keyframe, the trailer is written and writing stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet about to be written.
+    ///
+    /// # Return value
+    ///
+    /// [`WriteOutcome::Written`] if the packet must be written, or
+    /// [`WriteOutcome::LimitReached`] if it must be discarded.
+    fn check_limits(&mut self, packet: &AvPacket) -> Result<WriteOutcome> {
+        if self.progress.finished {
+            return Ok(WriteOutcome::LimitReached(self.summary()));
+        }
+
+        let limit_reached = self.max_duration.is_some_and(|max_duration| {
+            self.progress.end - self.progress.start >= max_duration.as_secs_f64()
+        }) || self
+            .max_size
+            .is_some_and(|max_size| self.bytes_written() >= max_size);
+        if limit_reached && self.is_cut_point(packet) {
+            self.progress.finished = true;
+            self.output.write_trailer()?;
+            self.finish_file()?;
+            return Ok(WriteOutcome::LimitReached(self.summary()));
+        }
+
+        let time_base = self
+            .output
+            .stream(packet.stream())
+            .map(|stream| stream.time_base())
+            .ok_or(AvError::StreamNotFound)?;
+        if let Some(pts) = packet.pts().or(packet.dts()) {
+            let start = pts as f64 * f64::from(time_base);
+            let end = (pts + packet.duration().max(0)) as f64 * f64::from(time_base);
+            if self.progress.packets == 0 {
+                self.progress.start = start;
+                self.progress.end = end;
+            } else {
+                self.progress.start = self.progress.start.min(start);
+                self.progress.end = self.progress.end.max(end);
+            }
+        }
+        self.progress.packets += 1;
+        self.progress.packet_bytes += packet.size() as u64;
+        Ok(WriteOutcome::Written)
+    }
+
+    /// Apply the transforms added with [`WriterBuilder::with_packet_transform`] to a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet about to be written, with timestamps in the time base of its stream.
+    fn transform(&self, packet: &mut AvPacket) -> Result<()> {
+        if self.packet_transforms.is_empty() {
+            return Ok(());
+        }
+        let time_base = self
+            .output
+            .stream(packet.stream())
+            .map(|stream| stream.time_base())
+            .ok_or(AvError::StreamNotFound)?;
+        let mut transformed = Packet::new(std::mem::replace(packet, AvPacket::empty()), time_base);
+        let result = self
+            .packet_transforms
+            .iter()
+            .try_for_each(|transform| transform(&mut transformed));
+        *packet = transformed.into_inner();
+        result
+    }
+
+    /// Append the custom MP4 boxes and set the modification time of the output file, as requested
+    /// with [`WriterBuilder::with_mp4_box`] and [`WriterBuilder::with_file_time`]. Must be called
+    /// after the trailer is written.
+    fn finish_file(&self) -> Result<()> {
+        if !self.mp4_boxes.is_empty() {
+            if let Location::File(path) = &self.destination {
+                // Top-level boxes may follow the movie box, so they can be appended to the
+                // finished file without rewriting it.
+                let result =
+                    std::fs::File::options()
+                        .append(true)
+                        .open(path)
+                        .and_then(|mut file| {
+                            use std::io::Write as _;
+                            self.mp4_boxes
+                                .iter()
+                                .try_for_each(|mp4_box| file.write_all(&mp4_box.to_bytes()))
+                        });
+                if let Err(err) = result {
+                    tracing::error!("failed to append boxes to {}: {err}", path.display());
+                    return Err(Error::BackendError(AvError::External));
+                }
+            }
+        }
+        // Failure to set the file time is not fatal since the output itself is complete.
+        if let Some((path, file_time)) = self.file_time.as_ref() {
+            let result = std::fs::File::options()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_modified(*file_time));
+            if let Err(err) = result {
+                tracing::warn!(
+                    "failed to set modification time of {}: {err}",
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush the IO buffer before writing a packet if the flush policy asks for it. Must be called
+    /// after [`Writer::check_limits`], which keeps track of the written media time.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet about to be written.
+    fn apply_flush_policy(&mut self, packet: &AvPacket) {
+        let flush = match self.flush_policy {
+            FlushPolicy::Buffered | FlushPolicy::Packet => false,
+            FlushPolicy::Keyframe => self.is_cut_point(packet),
+            FlushPolicy::Interval(interval) => {
+                let last_flush = *self.last_flush.get_or_insert(self.progress.start);
+                self.progress.end - last_flush >= interval.as_secs_f64()
+            }
+        };
+        if flush {
+            ffi::flush_output_io(&mut self.output);
+            self.last_flush = Some(self.progress.end);
+        }
+    }
+
+    /// Flush the current fragment of a fragmented output (like MP4 with `movflags` set to
+    /// `frag_custom`), and the IO buffer, so that everything written so far reaches the
+    /// destination.
+    pub(crate) fn flush_fragment(&mut self) -> Result<()> {
+        ffi::flush_output(&mut self.output)?;
+        ffi::flush_output_io(&mut self.output);
+        Ok(())
+    }
+
+    /// Whether or not the output can be cut before the packet: a keyframe of a video stream, or
+    /// any packet if there are no video streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to check.
+    fn is_cut_point(&self, packet: &AvPacket) -> bool {
+        let is_video = |index: usize| {
+            self.output
+                .stream(index)
+                .is_some_and(|stream| stream.parameters().medium() == AvMediaType::Video)
+        };
+        if (0..self.output.nb_streams() as usize).any(is_video) {
+            packet.is_key() && is_video(packet.stream())
+        } else {
+            true
+        }
+    }
 }
 
 impl Write for Writer {}
 
+/// When a [`Writer`] flushes its IO buffer to the destination. Flushing more often lowers the
+/// latency, which matters for live streaming like LL-HLS, at the cost of more and smaller writes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FlushPolicy {
+    /// Flush when the IO buffer is full.
+    #[default]
+    Buffered,
+    /// Flush after every packet, like the `flush_packets` option.
+    Packet,
+    /// Flush before every keyframe of a video stream, so that each GOP reaches the destination as
+    /// soon as it is complete.
+    Keyframe,
+    /// Flush whenever the written media time grew by the interval since the last flush.
+    Interval(std::time::Duration),
+}
+
+/// How much a [`Writer`] has written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteSummary {
+    /// Duration spanned by the written packets.
+    pub duration: std::time::Duration,
+    /// Number of bytes written, including the header.
+    pub bytes: u64,
+    /// Number of packets written.
+    pub packets: u64,
+}
+
+/// Outcome of a write to a [`Writer`]. See [`WriterBuilder::with_max_duration`] and
+/// [`WriterBuilder::with_max_size`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WriteOutcome {
+    /// The write went through.
+    #[default]
+    Written,
+    /// A limit was reached, so the packet was discarded. The trailer was written when the limit
+    /// was first reached.
+    LimitReached(WriteSummary),
+}
+
+/// How well a container format supports storing a codec. See [`Writer::supports_codec`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SupportLevel {
+    /// The codec is the default codec of the format for its media type.
+    Default,
+    /// The format can store the codec.
+    Supported,
+    /// The format can store the codec, but only with experimental compliance
+    /// (`strict=experimental`), and other tools may not be able to read the output.
+    Experimental,
+    /// The format cannot store the codec.
+    Unsupported,
+    /// The format does not tell which codecs it can store. Writing may still fail when writing the
+    /// header.
+    Unknown,
+}
+
+impl SupportLevel {
+    /// Check whether a container format can store a codec, without creating a writer. Useful to
+    /// check that a combination like HEVC in AVI is valid before doing any work.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Name of the container format, like "mp4".
+    /// * `codec_id` - Codec to check.
+    ///
+    /// # Return value
+    ///
+    /// Support level, or `None` if there is no container format with the name.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// assert_eq!(
+    ///     SupportLevel::query("avi", AvCodecId::HEVC),
+    ///     Some(SupportLevel::Unsupported),
+    /// );
+    /// ```
+    pub fn query(format: &str, codec_id: AvCodecId) -> Option<Self> {
+        ffi::find_output_format(format).map(|format| Self::for_format(&format, codec_id))
+    }
+
+    /// Whether the codec can be stored, possibly with experimental compliance.
+    pub fn is_supported(self) -> bool {
+        matches!(
+            self,
+            SupportLevel::Default | SupportLevel::Supported | SupportLevel::Experimental
+        )
+    }
+
+    fn for_format(format: &ffmpeg::format::format::Output, codec_id: AvCodecId) -> Self {
+        let is_default = [
+            AvMediaType::Video,
+            AvMediaType::Audio,
+            AvMediaType::Subtitle,
+        ]
+        .into_iter()
+        .any(|media_type| ffi::default_codec(format, media_type) == codec_id);
+        match ffi::query_codec(format, codec_id, false) {
+            _ if is_default && codec_id != AvCodecId::None => SupportLevel::Default,
+            Some(true) => SupportLevel::Supported,
+            Some(false) => match ffi::query_codec(format, codec_id, true) {
+                Some(true) => SupportLevel::Experimental,
+                _ => SupportLevel::Unsupported,
+            },
+            None => SupportLevel::Unknown,
+        }
+    }
+}
+
+/// Progress of a [`Writer`] towards its limits.
+#[derive(Debug, Clone, Copy, Default)]
+struct WriteProgress {
+    /// Earliest timestamp written in seconds.
+    start: f64,
+    /// Latest timestamp plus duration written in seconds.
+    end: f64,
+    packets: u64,
+    packet_bytes: u64,
+    /// Whether or not the trailer was written because a limit was reached.
+    finished: bool,
+}
+
 unsafe impl Send for Writer {}
 unsafe impl Sync for Writer {}
 
@@ -494,7 +2146,7 @@ pub(crate) mod private {
     type Result<T> = std::result::Result<T, Error>;
 
     pub trait Write {
-        type Out;
+        type Out: Default;
 
         /// Write the container header.
         fn write_header(&mut self) -> Result<Self::Out>;
@@ -518,24 +2170,47 @@ pub(crate) mod private {
     }
 
     impl Write for Writer {
-        type Out = ();
+        type Out = WriteOutcome;
 
-        fn write_header(&mut self) -> Result<()> {
-            Ok(self.output.write_header()?)
+        fn write_header(&mut self) -> Result<WriteOutcome> {
+            match self.options.take() {
+                Some(options) => {
+                    let unused_options = self.output.write_header_with(options.to_dict())?;
+                    self.unused_options = Options::unused_keys(unused_options, "writer");
+                }
+                None => self.output.write_header()?,
+            }
+            Ok(WriteOutcome::Written)
         }
 
-        fn write(&mut self, packet: &mut AvPacket) -> Result<()> {
-            packet.write(&mut self.output)?;
-            Ok(())
+        fn write(&mut self, packet: &mut AvPacket) -> Result<WriteOutcome> {
+            self.transform(packet)?;
+            let outcome = self.check_limits(packet)?;
+            if outcome == WriteOutcome::Written {
+                self.apply_flush_policy(packet);
+                packet.write(&mut self.output)?;
+            }
+            Ok(outcome)
         }
 
-        fn write_interleaved(&mut self, packet: &mut AvPacket) -> Result<()> {
-            packet.write_interleaved(&mut self.output)?;
-            Ok(())
+        fn write_interleaved(&mut self, packet: &mut AvPacket) -> Result<WriteOutcome> {
+            self.transform(packet)?;
+            let outcome = self.check_limits(packet)?;
+            if outcome == WriteOutcome::Written {
+                self.apply_flush_policy(packet);
+                packet.write_interleaved(&mut self.output)?;
+            }
+            Ok(outcome)
         }
 
-        fn write_trailer(&mut self) -> Result<()> {
-            Ok(self.output.write_trailer()?)
+        fn write_trailer(&mut self) -> Result<WriteOutcome> {
+            // The trailer was already written when a limit was reached.
+            if self.progress.finished {
+                return Ok(WriteOutcome::LimitReached(self.summary()));
+            }
+            self.output.write_trailer()?;
+            self.finish_file()?;
+            Ok(WriteOutcome::Written)
         }
     }
 
@@ -544,7 +2219,10 @@ pub(crate) mod private {
 
         fn write_header(&mut self) -> Result<Buf> {
             self.begin_write();
-            self.output.write_header_with(self.options.to_dict())?;
+            Options::unused_keys(
+                self.output.write_header_with(self.options.to_dict())?,
+                "muxer",
+            );
             Ok(self.end_write())
         }
 
@@ -574,7 +2252,10 @@ pub(crate) mod private {
 
         fn write_header(&mut self) -> Result<Bufs> {
             self.begin_write();
-            self.output.write_header_with(self.options.to_dict())?;
+            Options::unused_keys(
+                self.output.write_header_with(self.options.to_dict())?,
+                "muxer",
+            );
             self.end_write();
             Ok(self.take_buffers())
         }
@@ -0,0 +1,442 @@
+//! Async variants of [`Reader`], [`Writer`], [`Decoder`] and [`Encoder`] for tokio applications.
+//!
+//! Each async object owns a dedicated worker thread on which the underlying object is created,
+//! used and dropped, so that blocking FFmpeg calls never run on the async runtime. Calls are queued
+//! and run in order. Every object is built with an [`Interrupt`] that is installed on its format
+//! context before the source or destination is opened, so that [`AsyncReader::cancel`] (and its
+//! counterparts) abort connecting as well as blocking network reads and writes.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let reader = AsyncReader::new(Url::parse("rtsp://camera/stream")?).await?;
+//! let stream_index = reader.best_video_stream_index().await?;
+//! while let Ok(packet) = reader.read_packet(stream_index).await {
+//!     // Do something with packet...
+//! }
+//! ```
+
+use std::sync::mpsc;
+
+use ffmpeg::Error as AvError;
+use tokio::sync::oneshot;
+
+use crate::decode::{Decoder, DecoderBuilder};
+use crate::encode::{Encoder, EncoderBuilder, Settings};
+use crate::error::Error;
+#[cfg(feature = "ndarray")]
+use crate::frame::Frame;
+use crate::frame::RawFrame;
+use crate::interrupt::Interrupt;
+use crate::io::{Reader, ReaderBuilder, Writer};
+use crate::location::Location;
+use crate::mux::Muxer;
+use crate::packet::Packet;
+use crate::stream::StreamInfo;
+#[cfg(feature = "ndarray")]
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Call to run on the worker thread.
+type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// Owns an object on a dedicated thread and runs calls on it.
+struct Worker<T> {
+    jobs: mpsc::Sender<Job<T>>,
+    interrupt: Interrupt,
+}
+
+impl<T: 'static> Worker<T> {
+    /// Spawn the worker thread and create the object on it.
+    ///
+    /// # Arguments
+    ///
+    /// * `build` - Creates the object, with the interrupt to build it with.
+    async fn spawn(build: impl FnOnce(Interrupt) -> Result<T> + Send + 'static) -> Result<Self> {
+        let interrupt = Interrupt::new();
+        let (jobs, queue) = mpsc::channel::<Job<T>>();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let handle = interrupt.clone();
+        let spawned = std::thread::Builder::new()
+            .name("rsmedia-async".to_string())
+            .spawn(move || {
+                let mut target = match build(handle) {
+                    Ok(target) => target,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(err));
+                        return;
+                    }
+                };
+                let _ = ready_tx.send(Ok(()));
+                for job in queue {
+                    job(&mut target);
+                }
+            });
+        if let Err(err) = spawned {
+            tracing::error!("failed to spawn worker thread: {err}");
+            return Err(Error::BackendError(AvError::External));
+        }
+        ready_rx.await.map_err(|_| interrupted())??;
+        Ok(Self { jobs, interrupt })
+    }
+
+    /// Run a call on the object and wait for the result.
+    ///
+    /// Fails with [`AvError::Exit`] if the worker was cancelled.
+    async fn run<R: Send + 'static>(
+        &self,
+        call: impl FnOnce(&mut T) -> R + Send + 'static,
+    ) -> Result<R> {
+        if self.is_cancelled() {
+            return Err(interrupted());
+        }
+        let (result_tx, result_rx) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move |target| {
+                let _ = result_tx.send(call(target));
+            }))
+            .map_err(|_| interrupted())?;
+        result_rx.await.map_err(|_| interrupted())
+    }
+
+    fn cancel(&self) {
+        self.interrupt.cancel();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.interrupt.is_cancelled()
+    }
+}
+
+impl<T> Drop for Worker<T> {
+    fn drop(&mut self) {
+        // Dropping the sender ends the worker thread after pending calls are done. Interrupt them
+        // so that the thread does not linger on a stalled network source.
+        self.interrupt.cancel();
+    }
+}
+
+/// Error of calls that were cancelled, or that could not run because the worker is gone.
+fn interrupted() -> Error {
+    Error::BackendError(AvError::Exit)
+}
+
+/// Async variant of [`Reader`].
+pub struct AsyncReader {
+    worker: Worker<Reader>,
+}
+
+impl AsyncReader {
+    /// Open the specified source on a dedicated thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to read from.
+    pub async fn new(source: impl Into<Location>) -> Result<Self> {
+        let source = source.into();
+        Self::open_with(move |interrupt| {
+            ReaderBuilder::new(source).with_interrupt(interrupt).build()
+        })
+        .await
+    }
+
+    /// Open a reader with a custom build function, which runs on the dedicated thread. Use this to
+    /// configure the reader with [`ReaderBuilder`].
+    ///
+    /// The build function gets the interrupt of the reader, which it must pass on to
+    /// [`ReaderBuilder::with_interrupt`] for [`AsyncReader::cancel`] to abort opening the source
+    /// and blocking reads.
+    ///
+    /// # Arguments
+    ///
+    /// * `build` - Builds the reader.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let reader = AsyncReader::open_with(move |interrupt| {
+    ///     ReaderBuilder::new(source)
+    ///         .with_options(&options)
+    ///         .with_interrupt(interrupt)
+    ///         .build()
+    /// })
+    /// .await?;
+    /// ```
+    pub async fn open_with(
+        build: impl FnOnce(Interrupt) -> Result<Reader> + Send + 'static,
+    ) -> Result<Self> {
+        Ok(Self {
+            worker: Worker::spawn(build).await?,
+        })
+    }
+
+    /// Read a single packet from the specified stream. See [`Reader::read`].
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of stream to read from.
+    pub async fn read_packet(&self, stream_index: usize) -> Result<Packet> {
+        self.worker
+            .run(move |reader| reader.read(stream_index))
+            .await?
+    }
+
+    /// Read a single packet from any stream. See [`Reader::read_any`].
+    pub async fn read_any_packet(&self) -> Result<Packet> {
+        self.worker.run(|reader| reader.read_any()).await?
+    }
+
+    /// Retrieve stream information for a stream. See [`Reader::stream_info`].
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of stream to produce stream info for.
+    pub async fn stream_info(&self, stream_index: usize) -> Result<StreamInfo> {
+        self.worker
+            .run(move |reader| reader.stream_info(stream_index))
+            .await?
+    }
+
+    /// Find the best video stream. See [`Reader::best_video_stream_index`].
+    pub async fn best_video_stream_index(&self) -> Result<usize> {
+        self.worker
+            .run(|reader| reader.best_video_stream_index())
+            .await?
+    }
+
+    /// Find the best audio stream. See [`Reader::best_audio_stream_index`].
+    pub async fn best_audio_stream_index(&self) -> Result<usize> {
+        self.worker
+            .run(|reader| reader.best_audio_stream_index())
+            .await?
+    }
+
+    /// Seek in reader. See [`Reader::seek`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_milliseconds` - Number of milliseconds from start of video to seek to.
+    pub async fn seek(&self, timestamp_milliseconds: i64) -> Result<()> {
+        self.worker
+            .run(move |reader| reader.seek(timestamp_milliseconds))
+            .await?
+    }
+
+    /// Cancel the reader. A pending read is aborted if the demuxer polls the interrupt callback,
+    /// and all further calls fail with [`AvError::Exit`].
+    pub fn cancel(&self) {
+        self.worker.cancel();
+    }
+
+    /// Whether [`AsyncReader::cancel`] was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.worker.is_cancelled()
+    }
+}
+
+/// Async variant of [`Writer`], which muxes packets through a [`Muxer`].
+///
+/// Call [`AsyncWriter::finish`] before dropping the writer, since dropping it cancels pending
+/// writes to network destinations.
+pub struct AsyncWriter {
+    worker: Worker<Muxer<Writer>>,
+}
+
+impl AsyncWriter {
+    /// Create a muxer over a writer with a build function, which runs on the dedicated thread.
+    ///
+    /// The build function gets the interrupt of the writer, which it must pass on to
+    /// [`WriterBuilder::with_interrupt`](crate::io::WriterBuilder::with_interrupt) for
+    /// [`AsyncWriter::cancel`] to abort opening the destination and blocking writes.
+    ///
+    /// # Arguments
+    ///
+    /// * `build` - Builds the muxer.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let writer = AsyncWriter::open_with(move |interrupt| {
+    ///     let writer = WriterBuilder::new(destination)
+    ///         .with_interrupt(interrupt)
+    ///         .build()?;
+    ///     Ok(MuxerBuilder::new(writer).with_stream(stream_info)?.build())
+    /// })
+    /// .await?;
+    /// ```
+    pub async fn open_with(
+        build: impl FnOnce(Interrupt) -> Result<Muxer<Writer>> + Send + 'static,
+    ) -> Result<Self> {
+        Ok(Self {
+            worker: Worker::spawn(build).await?,
+        })
+    }
+
+    /// Mux a single packet. See [`Muxer::mux`].
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to mux.
+    pub async fn write_packet(&self, packet: Packet) -> Result<()> {
+        self.worker.run(move |muxer| muxer.mux(packet)).await?
+    }
+
+    /// Signal to the muxer that writing has finished. See [`Muxer::finish`].
+    pub async fn finish(&self) -> Result<()> {
+        self.worker.run(|muxer| muxer.finish().map(|_| ())).await?
+    }
+
+    /// Cancel the writer. A pending write is aborted if the muxer polls the interrupt callback, and
+    /// all further calls fail with [`AvError::Exit`].
+    pub fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
+
+/// Async variant of [`Decoder`].
+pub struct AsyncDecoder {
+    worker: Worker<Decoder>,
+}
+
+impl AsyncDecoder {
+    /// Create a decoder for the specified source on a dedicated thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to decode.
+    pub async fn new(source: impl Into<Location>) -> Result<Self> {
+        let source = source.into();
+        Self::open_with(move |interrupt| {
+            DecoderBuilder::new(source)
+                .with_interrupt(interrupt)
+                .build()
+        })
+        .await
+    }
+
+    /// Create a decoder with a custom build function, which runs on the dedicated thread. Use this
+    /// to configure the decoder with [`DecoderBuilder`].
+    ///
+    /// The build function gets the interrupt of the decoder, which it must pass on to
+    /// [`DecoderBuilder::with_interrupt`] for [`AsyncDecoder::cancel`] to abort opening the source
+    /// and blocking reads.
+    ///
+    /// # Arguments
+    ///
+    /// * `build` - Builds the decoder.
+    pub async fn open_with(
+        build: impl FnOnce(Interrupt) -> Result<Decoder> + Send + 'static,
+    ) -> Result<Self> {
+        Ok(Self {
+            worker: Worker::spawn(build).await?,
+        })
+    }
+
+    /// Decode a single frame. See [`Decoder::decode`].
+    #[cfg(feature = "ndarray")]
+    pub async fn decode(&self) -> Result<(Time, Frame)> {
+        self.worker.run(|decoder| decoder.decode()).await?
+    }
+
+    /// Decode a single frame and return the raw ffmpeg `AvFrame`. See [`Decoder::decode_raw`].
+    pub async fn decode_raw(&self) -> Result<RawFrame> {
+        self.worker.run(|decoder| decoder.decode_raw()).await?
+    }
+
+    /// Seek in the decoder. See [`Decoder::seek`].
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp_milliseconds` - Number of milliseconds from start of video to seek to.
+    pub async fn seek(&self, timestamp_milliseconds: i64) -> Result<()> {
+        self.worker
+            .run(move |decoder| decoder.seek(timestamp_milliseconds))
+            .await?
+    }
+
+    /// Cancel the decoder. A pending read is aborted if the demuxer polls the interrupt callback,
+    /// and all further calls fail with [`AvError::Exit`].
+    pub fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
+
+/// Async variant of [`Encoder`].
+///
+/// Call [`AsyncEncoder::finish`] before dropping the encoder, since dropping it cancels pending
+/// writes to network destinations.
+pub struct AsyncEncoder {
+    worker: Worker<Encoder>,
+}
+
+impl AsyncEncoder {
+    /// Create an encoder with the specified destination and settings on a dedicated thread.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where to encode to.
+    /// * `settings` - Encoding settings.
+    pub async fn new(destination: impl Into<Location>, settings: Settings) -> Result<Self> {
+        let destination = destination.into();
+        Self::open_with(move |interrupt| {
+            EncoderBuilder::new(destination, settings)
+                .with_interrupt(interrupt)
+                .build()
+        })
+        .await
+    }
+
+    /// Create an encoder with a custom build function, which runs on the dedicated thread. Use
+    /// this to configure the encoder with [`EncoderBuilder`].
+    ///
+    /// The build function gets the interrupt of the encoder, which it must pass on to
+    /// [`EncoderBuilder::with_interrupt`] for [`AsyncEncoder::cancel`] to abort opening the
+    /// destination and blocking writes.
+    ///
+    /// # Arguments
+    ///
+    /// * `build` - Builds the encoder.
+    pub async fn open_with(
+        build: impl FnOnce(Interrupt) -> Result<Encoder> + Send + 'static,
+    ) -> Result<Self> {
+        Ok(Self {
+            worker: Worker::spawn(build).await?,
+        })
+    }
+
+    /// Encode a single `ndarray` frame. See [`Encoder::encode`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode in `HWC` format and standard layout.
+    /// * `source_timestamp` - Frame timestamp of original source.
+    #[cfg(feature = "ndarray")]
+    pub async fn write_frame(&self, frame: Frame, source_timestamp: Time) -> Result<()> {
+        self.worker
+            .run(move |encoder| encoder.encode(&frame, source_timestamp))
+            .await?
+    }
+
+    /// Encode a single raw frame. See [`Encoder::encode_raw`].
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to encode.
+    pub async fn write_raw_frame(&self, frame: RawFrame) -> Result<()> {
+        self.worker
+            .run(move |encoder| encoder.encode_raw(frame))
+            .await?
+    }
+
+    /// Signal to the encoder that writing has finished. See [`Encoder::finish`].
+    pub async fn finish(&self) -> Result<()> {
+        self.worker.run(|encoder| encoder.finish()).await?
+    }
+
+    /// Cancel the encoder. A pending write is aborted if the muxer polls the interrupt callback,
+    /// and all further calls fail with [`AvError::Exit`].
+    pub fn cancel(&self) {
+        self.worker.cancel();
+    }
+}
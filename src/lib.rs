@@ -1,33 +1,147 @@
+pub mod affinity;
+pub mod alignment;
+pub mod audio;
+pub mod bandwidth;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod checksum;
+pub mod clock;
+pub mod config;
 pub mod decode;
+#[cfg(all(feature = "device", not(target_arch = "wasm32")))]
+pub mod device;
+pub mod diff;
+#[cfg(feature = "filter")]
+pub mod downmix;
+pub mod edl;
 pub mod encode;
 pub mod error;
+pub mod events;
 pub mod extradata;
+#[cfg(feature = "filter")]
+pub mod filter;
 pub mod frame;
+#[cfg(feature = "gstreamer")]
+pub mod gstreamer;
+pub mod hls;
 pub mod hwaccel;
 pub mod init;
+pub mod interpolate;
+pub mod interrupt;
 pub mod io;
+pub mod license;
+pub mod limits;
 pub mod location;
+pub mod memory;
+#[cfg(feature = "filter")]
+pub mod mixer;
+pub mod mp4;
+pub mod multicast;
 pub mod mux;
 pub mod options;
 pub mod packet;
+pub mod parser;
+#[cfg(feature = "filter")]
+pub mod pitch;
+pub mod prerecord;
+pub mod queue;
+pub mod ratecontrol;
+pub mod record;
+pub mod recover;
+pub mod render;
 pub mod resize;
+pub mod rtmp;
 pub mod rtp;
+pub mod sidecar;
+pub mod sidedata;
+#[cfg(feature = "filter")]
+pub mod stabilize;
 pub mod stream;
+pub mod subtitle;
+pub mod tags;
+pub mod thumbnail;
 pub mod time;
+pub mod topology;
+pub mod transcode;
+pub mod validate;
+pub mod visualize;
 
 mod ffi;
+#[cfg(not(target_arch = "wasm32"))]
 mod ffi_hwaccel;
+mod scaler;
 
-pub use decode::{Decoder, DecoderBuilder};
-pub use encode::{Encoder, EncoderBuilder};
+pub use affinity::{ThreadPolicy, ThreadPriority};
+pub use audio::{
+    AudioDecoder, AudioDecoderBuilder, AudioEncoder, AudioEncoderBuilder, AudioFrameBuffer,
+    AudioSettings, SampleFormatNegotiation,
+};
+pub use bandwidth::{FallbackPolicy, ReceiveStats};
+pub use clock::{MasterClock, MediaClock};
+pub use config::{
+    DecoderConfig, EncoderConfig, FromConfig, PipelineConfig, SinkConfig, SourceConfig,
+};
+pub use decode::{
+    CodecStatus, Decoder, DecoderBuilder, OversizePolicy, ParameterChange, PrefetchDecoder,
+    SkippedRange,
+};
+#[cfg(feature = "filter")]
+pub use downmix::{Downmix, DownmixTarget, Downmixer};
+pub use encode::{
+    Deadline, Encoder, EncoderBuilder, FrameDropPolicy, FrameDropStats, PullStats, PulledFrame,
+    RealtimeMode, SettingsSnapshot, ShutdownReport,
+};
 pub use error::Error;
+pub use events::{Event, EventKind};
+#[cfg(feature = "filter")]
+pub use filter::Filter;
 #[cfg(feature = "ndarray")]
-pub use frame::Frame;
+pub use frame::{Frame, FrameBatch};
+pub use hls::{PlaylistType, Segment, SegmentFormat, SegmentedWriter, SegmentedWriterBuilder};
 pub use init::init;
-pub use io::{Reader, ReaderBuilder, Writer, WriterBuilder};
+pub use interpolate::{
+    FrameInterpolator, InterpolationMode, Interpolator, Minterpolate, MotionEstimation,
+};
+pub use interrupt::Interrupt;
+pub use io::{
+    DurationEstimation, FlushPolicy, RawVideoParameters, ReadRecovery, Reader, ReaderBuilder,
+    SupportLevel, WriteOutcome, WriteSummary, Writer, WriterBuilder,
+};
+pub use license::{EncoderLicense, LibraryLicense, License, LicenseReport};
+pub use limits::ResourceLimits;
 pub use location::{Location, Url};
-pub use mux::{Muxer, MuxerBuilder};
+#[cfg(feature = "filter")]
+pub use mixer::AudioMixer;
+pub use multicast::Multicast;
+pub use mux::{BitRate, Muxer, MuxerBuilder};
 pub use options::Options;
-pub use packet::Packet;
-pub use resize::Resize;
+pub use packet::{Packet, PacketTransform};
+#[cfg(feature = "filter")]
+pub use pitch::{PitchEngine, PitchShift, PitchShifter};
+pub use prerecord::PreRecordBuffer;
+pub use queue::{DropPolicy, FrameQueue};
+pub use ratecontrol::{EncodePass, RateControl};
+pub use record::{Recorder, RecorderBuilder};
+pub use recover::{salvage, SalvageReport};
+pub use resize::{Resize, ScalerBackend, ScalerProfile};
+pub use sidecar::AudioReplacement;
+pub use sidedata::{
+    ContentLightLevel, DisplayPrimaries, FrameSideData, MasteringDisplay, SideDataKind,
+    SideDataPolicy, Timecode,
+};
+#[cfg(feature = "filter")]
+pub use stabilize::{Stabilization, StabilizationBorder, StabilizationZoom};
+pub use subtitle::{
+    BitmapSubtitleExporter, DecodedSubtitle, SubtitleContent, SubtitleDecoder, SubtitleManifest,
+    SubtitleTranscoder,
+};
+#[cfg(feature = "ndarray")]
+pub use thumbnail::{extract_thumbnail, extract_thumbnails};
+pub use thumbnail::{Thumbnail, ThumbnailTap};
 pub use time::Time;
+pub use topology::{PipelineDescription, Stage, StageKind};
+pub use transcode::{TranscodeProgress, Transcoder};
+pub use validate::{TimestampViolation, ViolationKind};
+pub use visualize::AudioVisualizer;
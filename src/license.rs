@@ -0,0 +1,321 @@
+//! Licensing of the linked ffmpeg build and of encoders, to enforce a licensing policy at runtime
+//! before enabling features.
+//!
+//! The license of a build follows from how ffmpeg was configured: `--enable-gpl` makes the
+//! libraries GPL, `--enable-version3` moves them to version 3 and `--enable-nonfree` makes them
+//! unredistributable. Static builds that must stay LGPL, like the ones that are shipped in closed
+//! source products, are configured without these flags and linked with the `FFMPEG_INCLUDE_DIR`
+//! and `FFMPEG_LIBS_DIR` environment variables of `rusty_ffmpeg`. Products can check the result
+//! with [`LicenseReport::is_lgpl`] at startup.
+//!
+//! # Example
+//!
+//! ```ignore
+//! let settings = Settings::preset_h264_yuv420p(1280, 720, false);
+//! let encoder = settings.license().map(|license| license.encoder);
+//! let report = LicenseReport::new(encoder.as_deref());
+//! if !report.is_lgpl() {
+//!     // Fall back to a hardware or openh264 encoder, or disable the feature...
+//! }
+//! ```
+
+/// License of a library or encoder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum License {
+    /// LGPL version 2.1 or later.
+    Lgpl,
+    /// LGPL version 3 or later.
+    LgplV3,
+    /// GPL version 2 or later.
+    Gpl,
+    /// GPL version 3 or later.
+    GplV3,
+    /// Nonfree and unredistributable.
+    Nonfree,
+    /// License that is not recognized.
+    Unknown,
+}
+
+impl License {
+    /// Parse a license string as returned by ffmpeg libraries, like `LGPL version 2.1 or later`.
+    ///
+    /// # Arguments
+    ///
+    /// * `license` - License string.
+    pub fn parse(license: &str) -> License {
+        let license = license.to_ascii_lowercase();
+        if license.contains("nonfree") {
+            License::Nonfree
+        } else if license.starts_with("lgpl") {
+            if license.contains("version 3") {
+                License::LgplV3
+            } else {
+                License::Lgpl
+            }
+        } else if license.starts_with("gpl") {
+            if license.contains("version 3") {
+                License::GplV3
+            } else {
+                License::Gpl
+            }
+        } else {
+            License::Unknown
+        }
+    }
+
+    /// Whether the license is LGPL, which allows linking from closed source products.
+    pub fn is_lgpl(&self) -> bool {
+        matches!(self, License::Lgpl | License::LgplV3)
+    }
+
+    /// Whether the license is GPL, which requires products to be distributed under the GPL.
+    pub fn is_gpl(&self) -> bool {
+        matches!(self, License::Gpl | License::GplV3)
+    }
+
+    /// Whether the license does not allow distribution at all.
+    pub fn is_nonfree(&self) -> bool {
+        *self == License::Nonfree
+    }
+}
+
+/// License of a library of the linked ffmpeg build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LibraryLicense {
+    /// Name of the library, like `avcodec`.
+    pub library: &'static str,
+    /// License of the library.
+    pub license: License,
+    /// License string of the library, as returned by ffmpeg.
+    pub license_string: &'static str,
+    /// Options ffmpeg was configured with.
+    pub configuration: &'static str,
+}
+
+impl LibraryLicense {
+    /// Get the licenses of the libraries of the linked ffmpeg build that are in use. libavdevice
+    /// and libavfilter are only listed with the `device` and `filter` features.
+    pub fn all() -> Vec<LibraryLicense> {
+        let library = |library, license_string, configuration| LibraryLicense {
+            library,
+            license: License::parse(license_string),
+            license_string,
+            configuration,
+        };
+        vec![
+            library(
+                "avutil",
+                ffmpeg::util::license(),
+                ffmpeg::util::configuration(),
+            ),
+            library(
+                "avcodec",
+                ffmpeg::codec::license(),
+                ffmpeg::codec::configuration(),
+            ),
+            library(
+                "avformat",
+                ffmpeg::format::license(),
+                ffmpeg::format::configuration(),
+            ),
+            #[cfg(feature = "device")]
+            library(
+                "avdevice",
+                ffmpeg::device::license(),
+                ffmpeg::device::configuration(),
+            ),
+            #[cfg(feature = "filter")]
+            library(
+                "avfilter",
+                ffmpeg::filter::license(),
+                ffmpeg::filter::configuration(),
+            ),
+            library(
+                "swscale",
+                ffmpeg::software::scaling::license(),
+                ffmpeg::software::scaling::configuration(),
+            ),
+            library(
+                "swresample",
+                ffmpeg::software::resampling::license(),
+                ffmpeg::software::resampling::configuration(),
+            ),
+        ]
+    }
+}
+
+/// License of an encoder, which can be stricter than the license of the build, like for encoders
+/// that wrap GPL libraries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EncoderLicense {
+    /// Name of the encoder, like `libx264`.
+    pub encoder: String,
+    /// License of the encoder itself. Encoders built into libavcodec and hardware encoders are
+    /// LGPL, but a build that includes them may still be GPL. See [`LicenseReport`].
+    pub license: License,
+    /// Whether the encoder runs on hardware, like NVENC, Quick Sync and VideoToolbox.
+    pub hardware: bool,
+}
+
+impl EncoderLicense {
+    /// External libraries that make a build GPL (version 2 or later).
+    const GPL: &'static [&'static str] =
+        &["libx264", "libx264rgb", "libx265", "libxvid", "libxavs"];
+    /// External libraries that make a build GPL version 3.
+    const GPL_V3: &'static [&'static str] = &["libxavs2"];
+    /// External libraries that make a build LGPL version 3.
+    const LGPL_V3: &'static [&'static str] = &["libopencore_amrnb", "libvo_amrwbenc"];
+    /// External libraries that make a build nonfree.
+    const NONFREE: &'static [&'static str] = &["libfdk_aac"];
+    /// Suffixes of the names of hardware encoders.
+    const HARDWARE: &'static [&'static str] = &[
+        "_nvenc",
+        "_qsv",
+        "_vaapi",
+        "_videotoolbox",
+        "_amf",
+        "_mediacodec",
+        "_v4l2m2m",
+        "_vulkan",
+        "_d3d12va",
+        "_mf",
+        "_omx",
+        "_rkmpp",
+    ];
+
+    /// Get the license of an encoder by name.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Name of the encoder, like `libx264` or `h264_nvenc`.
+    pub fn of(encoder: &str) -> EncoderLicense {
+        let license = if Self::GPL.contains(&encoder) {
+            License::Gpl
+        } else if Self::GPL_V3.contains(&encoder) {
+            License::GplV3
+        } else if Self::LGPL_V3.contains(&encoder) {
+            License::LgplV3
+        } else if Self::NONFREE.contains(&encoder) {
+            License::Nonfree
+        } else {
+            License::Lgpl
+        };
+        EncoderLicense {
+            encoder: encoder.to_string(),
+            license,
+            hardware: Self::HARDWARE
+                .iter()
+                .any(|suffix| encoder.ends_with(suffix)),
+        }
+    }
+}
+
+/// Licensing report of the linked ffmpeg build and the encoders in use.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct LicenseReport {
+    /// Licenses of the libraries of the build.
+    pub libraries: Vec<LibraryLicense>,
+    /// Licenses of the encoders in use.
+    pub encoders: Vec<EncoderLicense>,
+}
+
+impl LicenseReport {
+    /// Create a report for the linked ffmpeg build and the encoders in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoders` - Names of the encoders in use, like `libx264`. See [`Settings::license`] and
+    ///   [`Encoder::license`].
+    ///
+    /// [`Settings::license`]: crate::encode::Settings::license
+    /// [`Encoder::license`]: crate::encode::Encoder::license
+    pub fn new<'a>(encoders: impl IntoIterator<Item = &'a str>) -> LicenseReport {
+        LicenseReport {
+            libraries: LibraryLicense::all(),
+            encoders: encoders.into_iter().map(EncoderLicense::of).collect(),
+        }
+    }
+
+    /// Get the licenses of the libraries and encoders in the report.
+    fn licenses(&self) -> impl Iterator<Item = License> + '_ {
+        self.libraries
+            .iter()
+            .map(|library| library.license)
+            .chain(self.encoders.iter().map(|encoder| encoder.license))
+    }
+
+    /// Whether the build and the encoders in use are all LGPL, so they can be linked from closed
+    /// source products.
+    pub fn is_lgpl(&self) -> bool {
+        self.licenses().all(|license| license.is_lgpl())
+    }
+
+    /// Whether the build or any of the encoders in use is GPL.
+    pub fn is_gpl(&self) -> bool {
+        self.licenses().any(|license| license.is_gpl())
+    }
+
+    /// Whether the build or any of the encoders in use is nonfree, so the product cannot be
+    /// distributed at all.
+    pub fn is_nonfree(&self) -> bool {
+        self.licenses().any(|license| license.is_nonfree())
+    }
+
+    /// Get the encoders in use that are not LGPL.
+    pub fn restricted_encoders(&self) -> impl Iterator<Item = &EncoderLicense> + '_ {
+        self.encoders
+            .iter()
+            .filter(|encoder| !encoder.license.is_lgpl())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_license() {
+        assert_eq!(License::parse("LGPL version 2.1 or later"), License::Lgpl);
+        assert_eq!(License::parse("LGPL version 3 or later"), License::LgplV3);
+        assert_eq!(License::parse("GPL version 2 or later"), License::Gpl);
+        assert_eq!(License::parse("GPL version 3 or later"), License::GplV3);
+        assert_eq!(
+            License::parse("nonfree and unredistributable"),
+            License::Nonfree
+        );
+        assert_eq!(License::parse("WTFPL"), License::Unknown);
+    }
+
+    #[test]
+    fn test_encoder_license() {
+        assert_eq!(EncoderLicense::of("libx264").license, License::Gpl);
+        assert_eq!(EncoderLicense::of("libfdk_aac").license, License::Nonfree);
+        assert_eq!(EncoderLicense::of("libopenh264").license, License::Lgpl);
+        let nvenc = EncoderLicense::of("hevc_nvenc");
+        assert!(nvenc.hardware && nvenc.license.is_lgpl());
+        assert!(!EncoderLicense::of("libx265").hardware);
+    }
+
+    #[test]
+    fn test_report() {
+        let report = LicenseReport {
+            libraries: Vec::new(),
+            encoders: vec![
+                EncoderLicense::of("h264_videotoolbox"),
+                EncoderLicense::of("libx265"),
+            ],
+        };
+        assert!(report.is_gpl() && !report.is_lgpl() && !report.is_nonfree());
+        assert_eq!(
+            report
+                .restricted_encoders()
+                .map(|encoder| encoder.encoder.as_str())
+                .collect::<Vec<_>>(),
+            ["libx265"],
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use std::time::{Duration, Instant};
+
+use crate::error::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Limits on the resources a [`Reader`](crate::io::Reader) or [`Decoder`](crate::decode::Decoder)
+/// may consume, to guard services against malicious or broken inputs.
+///
+/// When a limit is exceeded, reading or decoding fails with [`Error::ResourceLimitExceeded`].
+///
+/// # Example
+///
+/// ```ignore
+/// let decoder = DecoderBuilder::new(Path::new("untrusted.mp4"))
+///     .with_resource_limits(
+///         ResourceLimits::new()
+///             .with_max_packets(1_000_000)
+///             .with_max_decode_errors(100)
+///             .with_max_duration(Duration::from_secs(60)),
+///     )
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    max_packets: Option<u64>,
+    max_decode_errors: Option<u64>,
+    max_duration: Option<Duration>,
+}
+
+impl ResourceLimits {
+    /// Create limits without any limit set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of packets to read, counting packets of all streams.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_packets` - Maximum number of packets.
+    pub fn with_max_packets(mut self, max_packets: u64) -> Self {
+        self.max_packets = Some(max_packets);
+        self
+    }
+
+    /// Set the maximum number of packets that may fail to decode. Packets that fail to decode are
+    /// skipped until the limit is exceeded. Without this limit, the first decode error is returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_decode_errors` - Maximum number of decode errors.
+    pub fn with_max_decode_errors(mut self, max_decode_errors: u64) -> Self {
+        self.max_decode_errors = Some(max_decode_errors);
+        self
+    }
+
+    /// Set the maximum wall time the source may be read for, counted from opening it.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_duration` - Maximum wall time.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> Self {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Maximum wall time, if set.
+    pub(crate) fn max_duration(&self) -> Option<Duration> {
+        self.max_duration
+    }
+}
+
+/// Resource limit that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceLimit {
+    /// Maximum number of packets read.
+    Packets(u64),
+    /// Maximum number of decode errors.
+    DecodeErrors(u64),
+    /// Maximum wall time.
+    Duration(Duration),
+    /// Maximum accounted native memory in bytes. See [`crate::memory::set_memory_limit`].
+    Memory(usize),
+}
+
+impl std::fmt::Display for ResourceLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ResourceLimit::Packets(max) => write!(f, "read more than {max} packets"),
+            ResourceLimit::DecodeErrors(max) => write!(f, "more than {max} decode errors"),
+            ResourceLimit::Duration(max) => write!(f, "read for longer than {max:?}"),
+            ResourceLimit::Memory(max) => write!(f, "more than {max} bytes of native memory"),
+        }
+    }
+}
+
+/// Keeps track of resource usage against [`ResourceLimits`].
+#[derive(Debug, Clone)]
+pub(crate) struct ResourceGuard {
+    limits: ResourceLimits,
+    packets: u64,
+    decode_errors: u64,
+    opened_at: Instant,
+}
+
+impl ResourceGuard {
+    /// Create a guard that starts counting now.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - Limits to enforce.
+    pub(crate) fn new(limits: ResourceLimits) -> Self {
+        Self::starting_at(limits, Instant::now())
+    }
+
+    /// Create a guard that counts the wall time from an earlier point in time, like from when
+    /// opening the source started.
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - Limits to enforce.
+    /// * `opened_at` - Time to count the wall time from.
+    pub(crate) fn starting_at(limits: ResourceLimits, opened_at: Instant) -> Self {
+        Self {
+            limits,
+            packets: 0,
+            decode_errors: 0,
+            opened_at,
+        }
+    }
+
+    /// Whether or not decode errors are counted instead of returned.
+    pub(crate) fn tolerates_decode_errors(&self) -> bool {
+        self.limits.max_decode_errors.is_some()
+    }
+
+    /// Check the wall time limit.
+    pub(crate) fn check_duration(&self) -> Result<()> {
+        match self.limits.max_duration {
+            Some(max) if self.opened_at.elapsed() > max => {
+                Err(Error::ResourceLimitExceeded(ResourceLimit::Duration(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Count a packet that was read and check the packet limit.
+    pub(crate) fn count_packet(&mut self) -> Result<()> {
+        self.packets += 1;
+        match self.limits.max_packets {
+            Some(max) if self.packets > max => {
+                Err(Error::ResourceLimitExceeded(ResourceLimit::Packets(max)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Count a decode error and check the decode error limit.
+    pub(crate) fn count_decode_error(&mut self) -> Result<()> {
+        self.decode_errors += 1;
+        match self.limits.max_decode_errors {
+            Some(max) if self.decode_errors > max => Err(Error::ResourceLimitExceeded(
+                ResourceLimit::DecodeErrors(max),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Default for ResourceGuard {
+    fn default() -> Self {
+        Self::new(ResourceLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlimited() {
+        let mut guard = ResourceGuard::default();
+        for _ in 0..1000 {
+            assert!(guard.count_packet().is_ok());
+        }
+        assert!(guard.check_duration().is_ok());
+        assert!(!guard.tolerates_decode_errors());
+    }
+
+    #[test]
+    fn test_max_packets() {
+        let mut guard = ResourceGuard::new(ResourceLimits::new().with_max_packets(2));
+        assert!(guard.count_packet().is_ok());
+        assert!(guard.count_packet().is_ok());
+        assert!(matches!(
+            guard.count_packet(),
+            Err(Error::ResourceLimitExceeded(ResourceLimit::Packets(2)))
+        ));
+    }
+
+    #[test]
+    fn test_max_decode_errors() {
+        let mut guard = ResourceGuard::new(ResourceLimits::new().with_max_decode_errors(1));
+        assert!(guard.tolerates_decode_errors());
+        assert!(guard.count_decode_error().is_ok());
+        assert!(matches!(
+            guard.count_decode_error(),
+            Err(Error::ResourceLimitExceeded(ResourceLimit::DecodeErrors(1)))
+        ));
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let guard = ResourceGuard::new(ResourceLimits::new().with_max_duration(Duration::ZERO));
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(matches!(
+            guard.check_duration(),
+            Err(Error::ResourceLimitExceeded(ResourceLimit::Duration(_)))
+        ));
+    }
+}
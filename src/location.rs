@@ -1,6 +1,20 @@
+use std::borrow::Cow;
+
+use crate::options::Options;
+
 /// Re-export [`url::Url`] since it is an input type for callers of the API.
 pub use url::Url;
 
+/// Raw file descriptor type. On Windows this is a C runtime file descriptor, which can be obtained
+/// from a native handle with [`Location::from_raw_handle`].
+#[cfg(unix)]
+pub type RawFd = std::os::fd::RawFd;
+
+/// Raw file descriptor type. On Windows this is a C runtime file descriptor, which can be obtained
+/// from a native handle with [`Location::from_raw_handle`].
+#[cfg(not(unix))]
+pub type RawFd = std::ffi::c_int;
+
 /// Represents a video file or stream location. Can be either a file resource (a path) or a network
 /// resource (a URL).
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -9,19 +23,169 @@ pub enum Location {
     File(std::path::PathBuf),
     /// Network source.
     Network(Url),
+    /// Already opened file descriptor. This is useful in sandboxed environments where the process
+    /// cannot open paths by itself (Android content URIs, systemd socket activation, seccomp'd
+    /// workers).
+    ///
+    /// The caller retains ownership of the descriptor and must keep it open while it is in use.
+    /// Note that with ffmpeg 5 the descriptor is read through the `pipe` protocol, which does not
+    /// support seeking.
+    Fd(RawFd),
+    /// In-memory buffer holding the entire input, like the contents of an mp4 file. The buffer is
+    /// read through a custom IO context, so no file system is required (for example on `wasm32`).
+    ///
+    /// Only supported for reading.
+    Buf(std::sync::Arc<[u8]>),
+    /// Sequence of numbered images, like the PNG or JPEG frames of a scientific camera, read and
+    /// written with the ffmpeg `image2` format. Numbering starts at the first of 0 to 4 that
+    /// exists when reading, and at 1 when writing. When writing, every frame goes to the next
+    /// file. The image format follows from the extension of the pattern, so encode with
+    /// [`Settings::preset_png`](crate::encode::Settings::preset_png) or
+    /// [`Settings::preset_jpeg`](crate::encode::Settings::preset_jpeg).
+    ///
+    /// Create it with [`Location::image_sequence`]. The variant is non-exhaustive so that options
+    /// of the `image2` format, like the number to start at, can be added without breaking callers.
+    #[non_exhaustive]
+    ImageSequence {
+        /// Path in which `%d` (or a padded variant like `%04d`) stands for the frame number, like
+        /// `frames/frame_%04d.png`.
+        pattern: std::path::PathBuf,
+        /// Frame rate as numerator and denominator, like `(30000, 1001)`, which sets the
+        /// timestamps of the frames when reading. It is not used when writing.
+        frame_rate: (u32, u32),
+    },
 }
 
 impl Location {
     /// Coerce underlying location to a path.
     ///
     /// This will create a path with a URL in it (which is kind of weird but we use it to pass on
-    /// URLs to ffmpeg).
+    /// URLs to ffmpeg). File descriptors and buffers do not have a path, an empty path is returned
+    /// (see [`Location::to_path`] for the path ffmpeg opens). Image sequences return their
+    /// pattern.
     pub fn as_path(&self) -> &std::path::Path {
         match self {
             Location::File(path) => path.as_path(),
             Location::Network(url) => std::path::Path::new(url.as_str()),
+            Location::Fd(_) | Location::Buf(_) => std::path::Path::new(""),
+            Location::ImageSequence { pattern, .. } => pattern.as_path(),
+        }
+    }
+
+    /// Get the path ffmpeg opens for this location. This is the same as [`Location::as_path`],
+    /// except that file descriptors are turned into a URL for the ffmpeg `fd` (or `pipe` on
+    /// ffmpeg 5) protocol.
+    pub fn to_path(&self) -> Cow<'_, std::path::Path> {
+        match self {
+            Location::Fd(fd) => Cow::Owned(std::path::PathBuf::from(fd_url(*fd))),
+            location => Cow::Borrowed(location.as_path()),
+        }
+    }
+
+    /// Create a location from a string holding either a path or a URL, like a command line
+    /// argument. Strings that parse as a URL with a scheme of more than one letter are network
+    /// locations. Others (including Windows paths like `C:\video.mp4`) are file locations.
+    ///
+    /// # Arguments
+    ///
+    /// * `location` - Path or URL.
+    pub fn parse(location: &str) -> Self {
+        // Note that single letter schemes are Windows drive letters, not URLs.
+        match Url::parse(location) {
+            Ok(url) if url.scheme().len() > 1 => Location::Network(url),
+            _ => Location::File(location.into()),
+        }
+    }
+
+    /// Create the location of a sequence of numbered images. See [`Location::ImageSequence`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pattern` - Path in which `%d` (or a padded variant like `%04d`) stands for the frame
+    ///   number.
+    /// * `frame_rate` - Frame rate as numerator and denominator.
+    pub fn image_sequence(pattern: impl Into<std::path::PathBuf>, frame_rate: (u32, u32)) -> Self {
+        Location::ImageSequence {
+            pattern: pattern.into(),
+            frame_rate,
+        }
+    }
+
+    /// Create a location from a native Windows handle. The handle is duplicated and the duplicate
+    /// is associated with a new C runtime file descriptor, so the caller keeps ownership of
+    /// `handle`. The descriptor owns the duplicate: close it with `_close` once the location is no
+    /// longer in use.
+    ///
+    /// # Arguments
+    ///
+    /// * `handle` - Native file handle.
+    /// * `read_only` - Whether or not the handle was opened for reading only.
+    #[cfg(windows)]
+    pub fn from_raw_handle(
+        handle: std::os::windows::io::BorrowedHandle<'_>,
+        read_only: bool,
+    ) -> std::io::Result<Location> {
+        use std::os::windows::io::{FromRawHandle, IntoRawHandle, OwnedHandle};
+
+        extern "C" {
+            fn _open_osfhandle(osfhandle: isize, flags: std::ffi::c_int) -> std::ffi::c_int;
+        }
+        // Same value as `_O_RDONLY` in the Windows C runtime.
+        const O_RDONLY: std::ffi::c_int = 0x0000;
+        // Same value as `_O_BINARY` in the Windows C runtime.
+        const O_BINARY: std::ffi::c_int = 0x8000;
+
+        let flags = if read_only {
+            O_RDONLY | O_BINARY
+        } else {
+            O_BINARY
+        };
+        let duplicate = handle.try_clone_to_owned()?.into_raw_handle();
+        match unsafe { _open_osfhandle(duplicate as isize, flags) } {
+            -1 => {
+                let error = std::io::Error::last_os_error();
+                // The descriptor was not created, so the duplicate is still ours to close.
+                drop(unsafe { OwnedHandle::from_raw_handle(duplicate) });
+                Err(error)
+            }
+            fd => Ok(Location::Fd(fd)),
         }
     }
+
+    /// Merge any protocol options required to open this location into the given options.
+    ///
+    /// The ffmpeg `fd` protocol does not accept the descriptor in the URL, it must be passed as
+    /// the `fd` option instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options provided by the caller, if any.
+    pub(crate) fn with_protocol_options(&self, options: Option<&Options>) -> Option<Options> {
+        match self {
+            Location::Fd(fd) if !cfg!(feature = "ffmpeg5") => {
+                let mut options = options.cloned().unwrap_or_default();
+                options.set("fd", &fd.to_string());
+                Some(options)
+            }
+            _ => options.cloned(),
+        }
+    }
+}
+
+/// Format of image sequences, see [`Location::ImageSequence`].
+pub(crate) const IMAGE_SEQUENCE_FORMAT: &str = "image2";
+
+/// Produce the URL ffmpeg uses to access an already opened file descriptor.
+///
+/// # Arguments
+///
+/// * `fd` - File descriptor.
+fn fd_url(fd: RawFd) -> String {
+    if cfg!(feature = "ffmpeg5") {
+        format!("pipe:{fd}")
+    } else {
+        "fd:".to_string()
+    }
 }
 
 impl From<&Location> for Location {
@@ -54,11 +218,48 @@ impl From<&Url> for Location {
     }
 }
 
+impl From<Vec<u8>> for Location {
+    fn from(value: Vec<u8>) -> Location {
+        Location::Buf(value.into())
+    }
+}
+
+impl From<&[u8]> for Location {
+    fn from(value: &[u8]) -> Location {
+        Location::Buf(value.into())
+    }
+}
+
 impl std::fmt::Display for Location {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Location::File(path) => write!(f, "{}", path.display()),
             Location::Network(url) => write!(f, "{url}"),
+            Location::Fd(fd) => write!(f, "fd:{fd}"),
+            Location::Buf(buf) => write!(f, "<buffer of {} bytes>", buf.len()),
+            Location::ImageSequence { pattern, .. } => write!(f, "{}", pattern.display()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fd_path_and_display() {
+        let location = Location::Fd(7);
+        assert_eq!(location.as_path(), std::path::Path::new(""));
+        assert_eq!(location.to_path(), std::path::Path::new(&fd_url(7)));
+        assert_eq!(location.to_string(), "fd:7");
+    }
+
+    #[test]
+    fn test_to_path_borrows() {
+        let location = Location::parse("rtmp://localhost/live/stream");
+        assert!(matches!(location.to_path(), Cow::Borrowed(_)));
+        assert_eq!(location.to_path(), location.as_path());
+        let location = Location::File("video.mp4".into());
+        assert_eq!(location.to_path(), std::path::Path::new("video.mp4"));
+    }
+}
@@ -0,0 +1,307 @@
+//! Accounting of native memory.
+//!
+//! Frames, packets and codec contexts are allocated by ffmpeg and are invisible to the Rust
+//! allocator. This module keeps an approximate account of the native memory held by decoders,
+//! encoders and queues, so that it can be reported with [`memory_stats`] and capped with
+//! [`set_memory_limit`].
+//!
+//! The numbers are estimates: codec contexts are accounted by the size of the frames they are
+//! expected to keep around, not by inspecting the allocations of the codec.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::Error;
+use crate::limits::ResourceLimit;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Kind of allocation that memory is accounted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MemoryCategory {
+    /// Decoder contexts, including the frames they hold for reference and reordering.
+    Decoders,
+    /// Encoder contexts, including the frames they hold for lookahead and conversion.
+    Encoders,
+    /// Frames and packets held in a [`FrameQueue`](crate::queue::FrameQueue) or a
+    /// [`PreRecordBuffer`](crate::prerecord::PreRecordBuffer).
+    Queued,
+}
+
+impl MemoryCategory {
+    const ALL: [MemoryCategory; 3] = [
+        MemoryCategory::Decoders,
+        MemoryCategory::Encoders,
+        MemoryCategory::Queued,
+    ];
+}
+
+/// Snapshot of the accounted native memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Bytes accounted to decoders.
+    pub decoders: usize,
+    /// Bytes accounted to encoders.
+    pub encoders: usize,
+    /// Bytes accounted to queued frames and packets.
+    pub queued: usize,
+    /// Total bytes currently accounted.
+    pub current: usize,
+    /// Largest number of bytes accounted at once since the start of the process, or since the last
+    /// call to [`reset_peak_memory`].
+    pub peak: usize,
+    /// Configured cap, if any. See [`set_memory_limit`].
+    pub limit: Option<usize>,
+}
+
+impl MemoryStats {
+    /// Get the number of bytes accounted to a category.
+    ///
+    /// # Arguments
+    ///
+    /// * `category` - Category to get.
+    pub fn category(&self, category: MemoryCategory) -> usize {
+        match category {
+            MemoryCategory::Decoders => self.decoders,
+            MemoryCategory::Encoders => self.encoders,
+            MemoryCategory::Queued => self.queued,
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicUsize = AtomicUsize::new(0);
+
+/// Counters of accounted memory. The counters of the process are in `LEDGER`.
+#[derive(Debug)]
+struct Ledger {
+    categories: [AtomicUsize; MemoryCategory::ALL.len()],
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    /// Memory cap, where `usize::MAX` means no cap.
+    limit: AtomicUsize,
+}
+
+impl Ledger {
+    const fn new() -> Self {
+        Self {
+            categories: [ZERO; MemoryCategory::ALL.len()],
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            limit: AtomicUsize::new(usize::MAX),
+        }
+    }
+
+    fn counter(&self, category: MemoryCategory) -> &AtomicUsize {
+        &self.categories[category as usize]
+    }
+
+    fn stats(&self) -> MemoryStats {
+        let limit = self.limit.load(Ordering::Relaxed);
+        MemoryStats {
+            decoders: self
+                .counter(MemoryCategory::Decoders)
+                .load(Ordering::Relaxed),
+            encoders: self
+                .counter(MemoryCategory::Encoders)
+                .load(Ordering::Relaxed),
+            queued: self.counter(MemoryCategory::Queued).load(Ordering::Relaxed),
+            current: self.current.load(Ordering::Relaxed),
+            peak: self.peak.load(Ordering::Relaxed),
+            limit: (limit != usize::MAX).then_some(limit),
+        }
+    }
+
+    fn set_limit(&self, limit: Option<usize>) {
+        self.limit
+            .store(limit.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
+    fn account(&self, category: MemoryCategory, bytes: usize) {
+        self.counter(category).fetch_add(bytes, Ordering::Relaxed);
+        self.peak
+            .fetch_max(self.current.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
+
+static LEDGER: Ledger = Ledger::new();
+
+/// Get a snapshot of the native memory currently accounted.
+///
+/// # Example
+///
+/// ```ignore
+/// let decoder = Decoder::new(Path::new("video.mp4")).unwrap();
+/// let stats = memory_stats();
+/// println!("{} bytes in use, peak {} bytes", stats.current, stats.peak);
+/// ```
+pub fn memory_stats() -> MemoryStats {
+    LEDGER.stats()
+}
+
+/// Set a global cap on the accounted native memory. When creating a decoder or encoder would
+/// exceed the cap, it fails with [`Error::ResourceLimitExceeded`]. Memory that is already accounted
+/// is not affected by lowering the cap.
+///
+/// Queued frames and packets count towards the cap, but queues never fail to accept items because
+/// of it. Use a [`DropPolicy`](crate::queue::DropPolicy) to bound queues instead.
+///
+/// # Arguments
+///
+/// * `limit` - Maximum number of bytes, or `None` to remove the cap.
+pub fn set_memory_limit(limit: Option<usize>) {
+    LEDGER.set_limit(limit);
+}
+
+/// Reset the peak memory to the memory currently accounted.
+pub fn reset_peak_memory() {
+    LEDGER
+        .peak
+        .store(LEDGER.current.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Memory accounted to one owner, like a decoder. The memory is released when the reservation is
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct MemoryReservation {
+    ledger: &'static Ledger,
+    category: MemoryCategory,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    /// Create an empty reservation.
+    ///
+    /// # Arguments
+    ///
+    /// * `category` - Category to account memory to.
+    pub(crate) fn empty(category: MemoryCategory) -> Self {
+        Self {
+            ledger: &LEDGER,
+            category,
+            bytes: 0,
+        }
+    }
+
+    /// Reserve memory, and fail if that would exceed the global cap.
+    ///
+    /// # Arguments
+    ///
+    /// * `category` - Category to account memory to.
+    /// * `bytes` - Number of bytes to reserve.
+    pub(crate) fn new(category: MemoryCategory, bytes: usize) -> Result<Self> {
+        Self::reserve(&LEDGER, category, bytes)
+    }
+
+    /// Reserve memory in a ledger, and fail if that would exceed the cap of the ledger.
+    ///
+    /// # Arguments
+    ///
+    /// * `ledger` - Ledger to account memory in.
+    /// * `category` - Category to account memory to.
+    /// * `bytes` - Number of bytes to reserve.
+    fn reserve(ledger: &'static Ledger, category: MemoryCategory, bytes: usize) -> Result<Self> {
+        let limit = ledger.limit.load(Ordering::Relaxed);
+        ledger
+            .current
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                current
+                    .checked_add(bytes)
+                    .filter(|&current| current <= limit)
+            })
+            .map_err(|_| Error::ResourceLimitExceeded(ResourceLimit::Memory(limit)))?;
+        ledger.account(category, bytes);
+        Ok(Self {
+            ledger,
+            category,
+            bytes,
+        })
+    }
+
+    /// Number of bytes reserved.
+    #[inline]
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Grow the reservation, regardless of the global cap.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Number of bytes to add.
+    pub(crate) fn grow(&mut self, bytes: usize) {
+        self.ledger.current.fetch_add(bytes, Ordering::Relaxed);
+        self.ledger.account(self.category, bytes);
+        self.bytes += bytes;
+    }
+
+    /// Shrink the reservation.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Number of bytes to release. Clamped to the reserved number of bytes.
+    pub(crate) fn shrink(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.bytes);
+        self.ledger
+            .counter(self.category)
+            .fetch_sub(bytes, Ordering::Relaxed);
+        self.ledger.current.fetch_sub(bytes, Ordering::Relaxed);
+        self.bytes -= bytes;
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.shrink(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The tests use their own ledger, so that they do not race with the global accounting of
+    // other tests.
+    fn ledger() -> &'static Ledger {
+        Box::leak(Box::new(Ledger::new()))
+    }
+
+    #[test]
+    fn test_memory_accounting() {
+        let ledger = ledger();
+
+        let mut reservation =
+            MemoryReservation::reserve(ledger, MemoryCategory::Queued, 1000).unwrap();
+        reservation.grow(500);
+        let stats = ledger.stats();
+        assert_eq!(stats.queued, 1500);
+        assert_eq!(stats.current, 1500);
+        assert_eq!(stats.peak, 1500);
+
+        reservation.shrink(2000);
+        assert_eq!(reservation.bytes(), 0);
+        reservation.grow(100);
+        drop(reservation);
+        assert_eq!(ledger.stats().current, 0);
+        assert_eq!(ledger.stats().peak, 1500);
+    }
+
+    #[test]
+    fn test_memory_limit() {
+        let ledger = ledger();
+
+        ledger.set_limit(Some(100));
+        assert_eq!(ledger.stats().limit, Some(100));
+        assert!(matches!(
+            MemoryReservation::reserve(ledger, MemoryCategory::Decoders, 101),
+            Err(Error::ResourceLimitExceeded(ResourceLimit::Memory(100)))
+        ));
+        let reservation =
+            MemoryReservation::reserve(ledger, MemoryCategory::Decoders, 100).unwrap();
+        assert_eq!(ledger.stats().decoders, 100);
+        drop(reservation);
+
+        ledger.set_limit(None);
+        assert_eq!(ledger.stats().limit, None);
+        assert_eq!(ledger.stats().current, 0);
+    }
+}
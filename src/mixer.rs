@@ -0,0 +1,606 @@
+//! Mixing of multiple audio inputs into one output, like a voice-over on top of background music.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use ffmpeg::filter::Graph as AvFilterGraph;
+use ffmpeg::util::channel_layout::ChannelLayout as AvChannelLayout;
+use ffmpeg::util::format::sample::Type as AvSampleType;
+use ffmpeg::util::format::Sample as AvSample;
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::ffi;
+use crate::frame::RawAudioFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Identifier of an input of an [`AudioMixer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MixerInputId(usize);
+
+/// Mixes multiple audio inputs into one output with the `amix` filter, with a gain per input.
+///
+/// Inputs are resampled to the sample rate and channel layout of the output, so they may have any
+/// format. Inputs can be added and removed while mixing, for live use. The output has the planar
+/// float sample format.
+///
+/// The mixer starts once every input has received its first frame or has ended, because the filter
+/// graph can only be configured when the format of every input is known. An input that ends
+/// without frames is mixed as silence. For live use, [`AudioMixer::with_max_delay`] pads inputs
+/// that join late or stall with silence, so that the mix does not wait for them.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut mixer = AudioMixer::new(48_000, 2);
+/// let voice = mixer.add_input(voice_decoder.time_base(), 1.0)?;
+/// let music = mixer.add_input(music_decoder.time_base(), 0.3)?;
+/// mixer.push(voice, voice_decoder.decode_raw()?)?;
+/// mixer.push(music, music_decoder.decode_raw()?)?;
+/// while let Some(frame) = mixer.pull()? {
+///     encode(frame);
+/// }
+/// ```
+pub struct AudioMixer {
+    sample_rate: u32,
+    channels: u16,
+    inputs: Vec<MixerInput>,
+    next_id: usize,
+    graph: Option<AvFilterGraph>,
+    /// Mixed frames drained from the previous filter graph when the inputs changed.
+    output: VecDeque<RawAudioFrame>,
+    max_delay: Option<Duration>,
+}
+
+impl AudioMixer {
+    /// Create a mixer without inputs.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate of the output.
+    /// * `channels` - Number of channels of the output, in the default layout for that number.
+    pub fn new(sample_rate: u32, channels: u16) -> Self {
+        Self {
+            sample_rate,
+            channels,
+            inputs: Vec::new(),
+            next_id: 0,
+            graph: None,
+            output: VecDeque::new(),
+            max_delay: None,
+        }
+    }
+
+    /// Pad inputs that fall behind with silence, for live use. When an input has no samples for
+    /// more than `max_delay` behind the input that is furthest ahead, like an input that joins
+    /// late or a source that stalls, it is padded with silence up to that point so that the mix
+    /// goes on. Frames of the input that start before the end of the padding are dropped.
+    ///
+    /// By default, the mix waits for every input, which is what offline mixing needs.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_delay` - How far an input may fall behind before it is padded.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = Some(max_delay);
+        self
+    }
+
+    /// Time base of the timestamps of the output frames.
+    pub fn time_base(&self) -> AvRational {
+        AvRational::new(1, self.sample_rate as i32)
+    }
+
+    /// Number of inputs.
+    pub fn inputs(&self) -> usize {
+        self.inputs.len()
+    }
+
+    /// Add an input.
+    ///
+    /// Samples that are still being mixed are mixed without the new input. The new input is
+    /// mixed in once it receives its first frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `time_base` - Time base of the timestamps of the frames of the input.
+    /// * `gain` - Linear gain of the input, where `1.0` leaves it unchanged.
+    pub fn add_input(&mut self, time_base: AvRational, gain: f32) -> Result<MixerInputId> {
+        self.drain_graph()?;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.inputs.push(MixerInput {
+            id,
+            gain,
+            time_base,
+            format: None,
+            layout: None,
+            format_assumed: false,
+            pending: Vec::new(),
+            ended: false,
+            start: None,
+            end: None,
+            padded_until: None,
+        });
+        Ok(MixerInputId(id))
+    }
+
+    /// Remove an input. Samples of the input that were already pushed are still mixed.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Input to remove.
+    pub fn remove_input(&mut self, id: MixerInputId) -> Result<()> {
+        let index = self.index_of(id)?;
+        self.drain_graph()?;
+        self.inputs.remove(index);
+        self.configure()
+    }
+
+    /// Change the gain of an input while mixing.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Input to change the gain of.
+    /// * `gain` - Linear gain of the input, where `1.0` leaves it unchanged.
+    pub fn set_gain(&mut self, id: MixerInputId, gain: f32) -> Result<()> {
+        let index = self.index_of(id)?;
+        self.inputs[index].gain = gain;
+        if let Some(graph) = self.graph.as_mut() {
+            ffi::filter_graph_send_command(
+                graph,
+                &format!("volume@in{}", id.0),
+                "volume",
+                &gain.to_string(),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Push a frame of an input.
+    ///
+    /// Returns [`Error::InvalidFrameFormat`] if the frame has another sample format, channel layout
+    /// or sample rate than the frames before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Input the frame belongs to.
+    /// * `frame` - Frame with samples, with a timestamp in the time base of the input.
+    pub fn push(&mut self, id: MixerInputId, frame: RawAudioFrame) -> Result<()> {
+        let index = self.index_of(id)?;
+        let time_base = self.time_base();
+        let input = &mut self.inputs[index];
+        let start = frame
+            .pts()
+            .map(|pts| pts.rescale(input.time_base, time_base));
+        if start
+            .zip(input.padded_until)
+            .is_some_and(|(start, padded_until)| start < padded_until)
+        {
+            return Ok(());
+        }
+
+        let format = InputFormat::of(&frame);
+        let mut reconfigure = false;
+        match input.format {
+            Some(input_format) if input_format != format => {
+                // The input was padded with silence in the output format before its first frame.
+                if !input.format_assumed {
+                    return Err(Error::InvalidFrameFormat);
+                }
+                input.format = Some(format);
+                input.layout = Some(frame.channel_layout());
+                input.pending.clear();
+                reconfigure = true;
+            }
+            Some(_) => {}
+            None => {
+                input.format = Some(format);
+                input.layout = Some(frame.channel_layout());
+            }
+        }
+        input.format_assumed = false;
+        let previous_end = input.end;
+        if let Some(start) = start {
+            let samples = (frame.samples() as i64)
+                .rescale(AvRational::new(1, frame.rate() as i32), time_base);
+            input.start.get_or_insert(start);
+            input.end = Some(start + samples);
+        }
+
+        if reconfigure {
+            self.drain_graph()?;
+        }
+        // Fill gaps after padding, so that the samples of the input stay in place in the mix.
+        if let (Some(_), Some(start), Some(previous_end)) = (self.max_delay, start, previous_end) {
+            if let Some(silence) = self.silence(index, previous_end, start) {
+                self.add_or_queue(index, silence)?;
+            }
+        }
+        self.add_or_queue(index, frame)?;
+        self.pad_lagging()
+    }
+
+    /// Signal the end of an input. The input stays in the mix until its samples run out.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Input that ended.
+    pub fn end_input(&mut self, id: MixerInputId) -> Result<()> {
+        let index = self.index_of(id)?;
+        let input = &mut self.inputs[index];
+        if input.ended {
+            return Ok(());
+        }
+        input.ended = true;
+        if let Some(graph) = self.graph.as_mut() {
+            return end_source(graph, id.0);
+        }
+        // An input that ends without frames is silence, so it takes the output format instead of
+        // keeping the mix from starting.
+        if input.format.is_none() {
+            self.assume_output_format(index);
+        }
+        self.configure()
+    }
+
+    /// Signal the end of all inputs, after which the remaining samples can be pulled.
+    pub fn finish(&mut self) -> Result<()> {
+        let ids = self
+            .inputs
+            .iter()
+            .map(|input| MixerInputId(input.id))
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.end_input(id)?;
+        }
+        Ok(())
+    }
+
+    /// Pull a mixed frame.
+    ///
+    /// # Return value
+    ///
+    /// The frame, or `None` if the inputs need more frames first, or the mix has ended.
+    pub fn pull(&mut self) -> Result<Option<RawAudioFrame>> {
+        if let Some(frame) = self.output.pop_front() {
+            return Ok(Some(frame));
+        }
+        match self.graph.as_mut() {
+            Some(graph) => receive_frame(graph),
+            None => Ok(None),
+        }
+    }
+
+    /// Find the index of an input.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - Input to find.
+    fn index_of(&self, id: MixerInputId) -> Result<usize> {
+        self.inputs
+            .iter()
+            .position(|input| input.id == id.0)
+            .ok_or(Error::BackendError(AvError::StreamNotFound))
+    }
+
+    /// Push a frame into the filter graph, or keep it until the graph is configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the input.
+    /// * `frame` - Frame to push.
+    fn add_or_queue(&mut self, index: usize, frame: RawAudioFrame) -> Result<()> {
+        let input = &mut self.inputs[index];
+        if let Some(graph) = self.graph.as_mut() {
+            return add_frame(graph, input.id, &frame);
+        }
+        input.pending.push(frame);
+        self.configure()
+    }
+
+    /// Give an input without frames the format of the output, to pad it with silence.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the input.
+    fn assume_output_format(&mut self, index: usize) {
+        let layout = AvChannelLayout::default(i32::from(self.channels));
+        let input = &mut self.inputs[index];
+        input.format = Some(InputFormat {
+            sample_format: AvSample::F32(AvSampleType::Planar),
+            sample_rate: self.sample_rate,
+            channel_layout: ChannelLayoutName::Mask(layout.bits()),
+        });
+        input.layout = Some(layout);
+        input.format_assumed = true;
+    }
+
+    /// Pad the inputs that fell behind by more than the maximum delay with silence. See
+    /// [`AudioMixer::with_max_delay`].
+    fn pad_lagging(&mut self) -> Result<()> {
+        let Some(max_delay) = self.max_delay else {
+            return Ok(());
+        };
+        let Some(furthest) = self.inputs.iter().filter_map(|input| input.end).max() else {
+            return Ok(());
+        };
+        let Some(mix_start) = self.inputs.iter().filter_map(|input| input.start).min() else {
+            return Ok(());
+        };
+        let pad_to = furthest - (max_delay.as_secs_f64() * self.sample_rate as f64) as i64;
+        for index in 0..self.inputs.len() {
+            let input = &self.inputs[index];
+            let pad_from = input.end.unwrap_or(mix_start);
+            if input.ended || pad_from >= pad_to {
+                continue;
+            }
+            if input.format.is_none() {
+                self.assume_output_format(index);
+            }
+            let silence = self.silence(index, pad_from, pad_to);
+            let input = &mut self.inputs[index];
+            input.start.get_or_insert(pad_from);
+            input.end = Some(pad_to);
+            input.padded_until = Some(pad_to);
+            if let Some(silence) = silence {
+                self.add_or_queue(index, silence)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Create a frame of silence in the format of an input.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the input, which must have a format.
+    /// * `start` - Start of the silence, in the time base of the output.
+    /// * `end` - End of the silence, in the time base of the output.
+    ///
+    /// # Return value
+    ///
+    /// The silence, or `None` if it is shorter than a sample.
+    fn silence(&self, index: usize, start: i64, end: i64) -> Option<RawAudioFrame> {
+        let input = &self.inputs[index];
+        let format = input.format.expect("input has a format");
+        let layout = input
+            .layout
+            .unwrap_or_else(|| AvChannelLayout::default(i32::from(self.channels)));
+        let samples = (end - start).rescale(
+            self.time_base(),
+            AvRational::new(1, format.sample_rate as i32),
+        );
+        if samples <= 0 {
+            return None;
+        }
+        let mut silence = RawAudioFrame::new(format.sample_format, samples as usize, layout);
+        silence.set_rate(format.sample_rate);
+        // Unsigned samples are silent at the middle of their range.
+        let value = match format.sample_format {
+            AvSample::U8(_) => 0x80,
+            _ => 0,
+        };
+        for plane in 0..silence.planes() {
+            silence.data_mut(plane).fill(value);
+        }
+        silence.set_pts(Some(start.rescale(self.time_base(), input.time_base)));
+        Some(silence)
+    }
+
+    /// End all inputs of the current filter graph and keep the samples it still holds, so that the
+    /// graph can be replaced.
+    fn drain_graph(&mut self) -> Result<()> {
+        let Some(mut graph) = self.graph.take() else {
+            return Ok(());
+        };
+        for input in &self.inputs {
+            if !input.ended {
+                end_source(&mut graph, input.id)?;
+            }
+        }
+        while let Some(frame) = receive_frame(&mut graph)? {
+            self.output.push_back(frame);
+        }
+        Ok(())
+    }
+
+    /// Configure the filter graph once the format of every input is known, and push the frames
+    /// that were waiting for it.
+    fn configure(&mut self) -> Result<()> {
+        if self.graph.is_some()
+            || self.inputs.is_empty()
+            || self.inputs.iter().any(|input| input.format.is_none())
+        {
+            return Ok(());
+        }
+
+        let mut graph = AvFilterGraph::new();
+        let abuffer = ffmpeg::filter::find("abuffer").ok_or(AvError::FilterNotFound)?;
+        let abuffersink = ffmpeg::filter::find("abuffersink").ok_or(AvError::FilterNotFound)?;
+        for input in &self.inputs {
+            let Some(format) = input.format else {
+                continue;
+            };
+            graph.add(
+                &abuffer,
+                &format!("in{}", input.id),
+                &format!(
+                    "time_base={}/{}:sample_rate={}:sample_fmt={}:channel_layout={}",
+                    input.time_base.numerator(),
+                    input.time_base.denominator(),
+                    format.sample_rate,
+                    format.sample_format.name(),
+                    format.channel_layout,
+                ),
+            )?;
+        }
+        graph.add(&abuffersink, "out", "")?;
+
+        // Bring every input to the output format, apply its gain, and mix without normalizing so
+        // that the gains are what they say.
+        let output_format = format!(
+            "aformat=sample_fmts=fltp:sample_rates={}:channel_layouts={}c",
+            self.sample_rate, self.channels,
+        );
+        let mut spec = String::new();
+        for (index, input) in self.inputs.iter().enumerate() {
+            spec.push_str(&format!(
+                "[in{id}]aresample={},{output_format},volume@in{id}={}[mix{index}];",
+                self.sample_rate,
+                input.gain,
+                id = input.id,
+            ));
+        }
+        for index in 0..self.inputs.len() {
+            spec.push_str(&format!("[mix{index}]"));
+        }
+        spec.push_str(&format!(
+            "amix=inputs={}:duration=longest:dropout_transition=0:normalize=0,{output_format}[out]",
+            self.inputs.len(),
+        ));
+
+        let mut parser = graph.output(&format!("in{}", self.inputs[0].id), 0)?;
+        for input in &self.inputs[1..] {
+            parser = parser.output(&format!("in{}", input.id), 0)?;
+        }
+        parser.input("out", 0)?.parse(&spec)?;
+        graph.validate()?;
+
+        for input in &mut self.inputs {
+            for frame in input.pending.drain(..) {
+                add_frame(&mut graph, input.id, &frame)?;
+            }
+            if input.ended {
+                end_source(&mut graph, input.id)?;
+            }
+        }
+        self.graph = Some(graph);
+        Ok(())
+    }
+}
+
+unsafe impl Send for AudioMixer {}
+unsafe impl Sync for AudioMixer {}
+
+/// Input of an [`AudioMixer`].
+struct MixerInput {
+    id: usize,
+    gain: f32,
+    time_base: AvRational,
+    /// Format of the frames, known once the first frame is pushed.
+    format: Option<InputFormat>,
+    /// Channel layout of the frames, known once the first frame is pushed.
+    layout: Option<AvChannelLayout>,
+    /// Whether the format is the output format that was assumed to pad the input with silence
+    /// before its first frame.
+    format_assumed: bool,
+    /// Frames pushed before the filter graph was configured.
+    pending: Vec<RawAudioFrame>,
+    ended: bool,
+    /// Start of the first frame, in the time base of the output.
+    start: Option<i64>,
+    /// End of the last frame, in the time base of the output.
+    end: Option<i64>,
+    /// End of the silence the input was padded with, in the time base of the output.
+    padded_until: Option<i64>,
+}
+
+/// Format of the frames of an input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct InputFormat {
+    sample_format: AvSample,
+    sample_rate: u32,
+    channel_layout: ChannelLayoutName,
+}
+
+impl InputFormat {
+    /// Get the format of a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to get the format of.
+    fn of(frame: &RawAudioFrame) -> Self {
+        let mask = frame.channel_layout().bits();
+        Self {
+            sample_format: frame.format(),
+            sample_rate: frame.rate(),
+            channel_layout: if mask != 0 {
+                ChannelLayoutName::Mask(mask)
+            } else {
+                ChannelLayoutName::Channels(frame.channels())
+            },
+        }
+    }
+}
+
+/// Channel layout as the `abuffer` filter takes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChannelLayoutName {
+    /// Native layout, by channel mask.
+    Mask(u64),
+    /// Unspecified layout, by number of channels.
+    Channels(u16),
+}
+
+impl std::fmt::Display for ChannelLayoutName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelLayoutName::Mask(mask) => write!(f, "0x{mask:x}"),
+            ChannelLayoutName::Channels(channels) => write!(f, "{channels}c"),
+        }
+    }
+}
+
+/// Push a frame into the source of an input.
+///
+/// # Arguments
+///
+/// * `graph` - Filter graph.
+/// * `id` - Identifier of the input.
+/// * `frame` - Frame to push.
+pub(crate) fn add_frame(graph: &mut AvFilterGraph, id: usize, frame: &RawAudioFrame) -> Result<()> {
+    graph
+        .get(&format!("in{id}"))
+        .ok_or(AvError::FilterNotFound)?
+        .source()
+        .add(frame)?;
+    Ok(())
+}
+
+/// Signal the end of the source of an input.
+///
+/// # Arguments
+///
+/// * `graph` - Filter graph.
+/// * `id` - Identifier of the input.
+pub(crate) fn end_source(graph: &mut AvFilterGraph, id: usize) -> Result<()> {
+    graph
+        .get(&format!("in{id}"))
+        .ok_or(AvError::FilterNotFound)?
+        .source()
+        .flush()?;
+    Ok(())
+}
+
+/// Receive a mixed frame from the sink of the filter graph.
+///
+/// # Arguments
+///
+/// * `graph` - Filter graph.
+pub(crate) fn receive_frame(graph: &mut AvFilterGraph) -> Result<Option<RawAudioFrame>> {
+    let mut frame = RawAudioFrame::empty();
+    let result = graph
+        .get("out")
+        .ok_or(AvError::FilterNotFound)?
+        .sink()
+        .frame(&mut frame);
+    match result {
+        Ok(()) => Ok(Some(frame)),
+        Err(AvError::Eof) => Ok(None),
+        Err(AvError::Other { errno }) if errno == ffmpeg::util::error::EAGAIN => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
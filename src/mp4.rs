@@ -0,0 +1,110 @@
+//! Custom boxes for MP4 outputs, like XMP packets for asset tracking.
+
+/// UUID of the box that holds an XMP packet, as defined by the XMP specification (part 3).
+pub const XMP_UUID: [u8; 16] = [
+    0xbe, 0x7a, 0xcf, 0xcb, 0x97, 0xa9, 0x42, 0xe8, 0x9c, 0x71, 0x99, 0x94, 0x91, 0xe3, 0xaf, 0xac,
+];
+
+/// Names of the muxers that produce ISO base media files, to which boxes can be added.
+pub(crate) const ISO_BMFF_FORMATS: [&str; 7] = ["mp4", "mov", "ipod", "3gp", "3g2", "ismv", "f4v"];
+
+/// A top-level box to add to an MP4 output. See [`crate::WriterBuilder::with_mp4_box`].
+///
+/// # Example
+///
+/// ```ignore
+/// let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">...</x:xmpmeta>"#;
+/// let writer = WriterBuilder::new(Path::new("delivery.mp4"))
+///     .with_mp4_box(Mp4Box::xmp(xmp))
+///     .with_mp4_box(Mp4Box::uuid(ASSET_UUID, asset_id.as_bytes()))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mp4Box {
+    kind: [u8; 4],
+    uuid: Option<[u8; 16]>,
+    payload: Vec<u8>,
+}
+
+impl Mp4Box {
+    /// Create a box of a type.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Four character code of the box, like `*b"free"`.
+    /// * `payload` - Contents of the box.
+    pub fn new(kind: [u8; 4], payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            kind,
+            uuid: None,
+            payload: payload.into(),
+        }
+    }
+
+    /// Create a `uuid` box, the extension box for custom data.
+    ///
+    /// # Arguments
+    ///
+    /// * `uuid` - UUID that identifies the contents.
+    /// * `payload` - Contents of the box.
+    pub fn uuid(uuid: [u8; 16], payload: impl Into<Vec<u8>>) -> Self {
+        Self {
+            kind: *b"uuid",
+            uuid: Some(uuid),
+            payload: payload.into(),
+        }
+    }
+
+    /// Create a box with an XMP packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Serialized XMP packet.
+    pub fn xmp(packet: &str) -> Self {
+        Self::uuid(XMP_UUID, packet.as_bytes())
+    }
+
+    /// Serialize the box, including its header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let header_size = 8 + self.uuid.map_or(0, |uuid| uuid.len());
+        let size = header_size + self.payload.len();
+        let mut bytes = Vec::with_capacity(size + 8);
+        match u32::try_from(size) {
+            Ok(size) => {
+                bytes.extend_from_slice(&size.to_be_bytes());
+                bytes.extend_from_slice(&self.kind);
+            }
+            // Boxes of 4 GiB and up store their size in a 64-bit field after the type.
+            Err(_) => {
+                bytes.extend_from_slice(&1_u32.to_be_bytes());
+                bytes.extend_from_slice(&self.kind);
+                bytes.extend_from_slice(&(size as u64 + 8).to_be_bytes());
+            }
+        }
+        if let Some(uuid) = self.uuid {
+            bytes.extend_from_slice(&uuid);
+        }
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_bytes() {
+        assert_eq!(
+            Mp4Box::new(*b"free", vec![1, 2]).to_bytes(),
+            vec![0, 0, 0, 10, b'f', b'r', b'e', b'e', 1, 2]
+        );
+
+        let bytes = Mp4Box::xmp("<x/>").to_bytes();
+        assert_eq!(bytes.len(), 28);
+        assert_eq!(&bytes[..8], &[0, 0, 0, 28, b'u', b'u', b'i', b'd']);
+        assert_eq!(&bytes[8..24], &XMP_UUID);
+        assert_eq!(&bytes[24..], b"<x/>");
+    }
+}
@@ -0,0 +1,222 @@
+//! Typed options for receiving multicast UDP and RTP streams, like IPTV channels.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use crate::options::Options;
+
+/// Options for joining a multicast group with a `udp://` or `rtp://` source, which ffmpeg
+/// otherwise takes as URL query parameters or protocol options. Set them on a reader with
+/// [`ReaderBuilder::with_multicast`](crate::io::ReaderBuilder::with_multicast).
+///
+/// Packet loss of RTP sources is reported by
+/// [`Reader::rtcp_statistics`](crate::io::Reader::rtcp_statistics). Raw UDP carries no sequence
+/// numbers, so loss of MPEG-TS over UDP cannot be counted at this level.
+///
+/// # Example
+///
+/// ```ignore
+/// let multicast = Multicast::new()
+///     .with_interface("192.168.1.20".parse().unwrap())
+///     .with_source("10.0.0.1".parse().unwrap())
+///     .with_buffer_size(8 * 1024 * 1024);
+/// let reader = ReaderBuilder::new(Url::parse("rtp://232.1.1.1:5000").unwrap())
+///     .with_multicast(multicast)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Multicast {
+    interface: Option<IpAddr>,
+    sources: Vec<IpAddr>,
+    blocked: Vec<IpAddr>,
+    buffer_size: Option<usize>,
+    fifo_size: Option<usize>,
+    overrun_nonfatal: bool,
+    reorder_queue_size: Option<usize>,
+    reuse: bool,
+    timeout: Option<Duration>,
+}
+
+impl Multicast {
+    /// Size of an MPEG-TS packet, which is the unit of the receive FIFO size of ffmpeg.
+    const FIFO_UNIT: usize = 188;
+
+    /// Create options that join the group on the default interface, from any source.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the address of the local network interface to join the group on. Hosts with multiple
+    /// interfaces otherwise join on the interface of the default route, which is often not the
+    /// one the IPTV network is on.
+    ///
+    /// # Arguments
+    ///
+    /// * `interface` - Address of the local interface.
+    pub fn with_interface(mut self, interface: IpAddr) -> Self {
+        self.interface = Some(interface);
+        self
+    }
+
+    /// Only receive packets from the given source, which joins the group with source-specific
+    /// multicast (SSM). Can be called multiple times to allow multiple sources.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Address of the sender.
+    pub fn with_source(mut self, source: IpAddr) -> Self {
+        self.sources.push(source);
+        self
+    }
+
+    /// Do not receive packets from the given source. Can be called multiple times to block
+    /// multiple sources.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Address of the sender.
+    pub fn with_blocked_source(mut self, source: IpAddr) -> Self {
+        self.blocked.push(source);
+        self
+    }
+
+    /// Set the size of the socket receive buffer. High bitrate streams need a larger buffer than
+    /// the operating system default to not lose packets in bursts. The operating system may cap
+    /// the size (`net.core.rmem_max` on Linux).
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Size in bytes.
+    pub fn with_buffer_size(mut self, bytes: usize) -> Self {
+        self.buffer_size = Some(bytes);
+        self
+    }
+
+    /// Set the size of the queue that a background thread receives packets into, which bridges
+    /// the time the reader is not reading. Has no effect on `rtp://` sources.
+    ///
+    /// # Arguments
+    ///
+    /// * `bytes` - Size in bytes. Rounded up to a whole number of MPEG-TS packets.
+    /// * `overrun_nonfatal` - Whether to keep receiving when the queue overruns, losing the
+    ///   packets that did not fit, instead of failing.
+    pub fn with_fifo_size(mut self, bytes: usize, overrun_nonfatal: bool) -> Self {
+        self.fifo_size = Some(bytes.div_ceil(Self::FIFO_UNIT));
+        self.overrun_nonfatal = overrun_nonfatal;
+        self
+    }
+
+    /// Set how many RTP packets are held to put packets that arrive out of order back in order.
+    /// Larger queues recover from more reordering, at the cost of latency when packets are lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `packets` - Number of packets, or `0` to pass packets on in the order they arrive.
+    pub fn with_reorder_queue_size(mut self, packets: usize) -> Self {
+        self.reorder_queue_size = Some(packets);
+        self
+    }
+
+    /// Allow other sockets, like another reader of the same group, to bind the same address and
+    /// port.
+    pub fn with_address_reuse(mut self) -> Self {
+        self.reuse = true;
+        self
+    }
+
+    /// Fail reading when no packets arrive for the given time, instead of waiting forever when
+    /// the stream stops.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Timeout.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the corresponding protocol and demuxer options.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to add to.
+    pub(crate) fn apply_to(&self, options: &mut Options) {
+        for (key, value) in self.to_pairs() {
+            options.set(key, &value);
+        }
+    }
+
+    /// Get the options as key-value pairs.
+    fn to_pairs(&self) -> Vec<(&'static str, String)> {
+        let join = |addresses: &[IpAddr]| {
+            addresses
+                .iter()
+                .map(IpAddr::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let mut pairs = Vec::new();
+        if let Some(interface) = self.interface {
+            pairs.push(("localaddr", interface.to_string()));
+        }
+        if !self.sources.is_empty() {
+            pairs.push(("sources", join(&self.sources)));
+        }
+        if !self.blocked.is_empty() {
+            pairs.push(("block", join(&self.blocked)));
+        }
+        if let Some(buffer_size) = self.buffer_size {
+            pairs.push(("buffer_size", buffer_size.to_string()));
+        }
+        if let Some(fifo_size) = self.fifo_size {
+            pairs.push(("fifo_size", fifo_size.to_string()));
+            if self.overrun_nonfatal {
+                pairs.push(("overrun_nonfatal", "1".to_string()));
+            }
+        }
+        if let Some(reorder_queue_size) = self.reorder_queue_size {
+            pairs.push(("reorder_queue_size", reorder_queue_size.to_string()));
+        }
+        if self.reuse {
+            pairs.push(("reuse", "1".to_string()));
+        }
+        if let Some(timeout) = self.timeout {
+            pairs.push(("timeout", timeout.as_micros().to_string()));
+        }
+        pairs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_sets_nothing() {
+        assert!(Multicast::new().to_pairs().is_empty());
+    }
+
+    #[test]
+    fn test_pairs() {
+        let multicast = Multicast::new()
+            .with_interface("192.168.1.20".parse().unwrap())
+            .with_source("10.0.0.1".parse().unwrap())
+            .with_source("10.0.0.2".parse().unwrap())
+            .with_buffer_size(1 << 20)
+            .with_fifo_size(1000, true)
+            .with_reorder_queue_size(0)
+            .with_timeout(Duration::from_secs(5));
+        assert_eq!(
+            multicast.to_pairs(),
+            vec![
+                ("localaddr", "192.168.1.20".to_string()),
+                ("sources", "10.0.0.1,10.0.0.2".to_string()),
+                ("buffer_size", "1048576".to_string()),
+                ("fifo_size", "6".to_string()),
+                ("overrun_nonfatal", "1".to_string()),
+                ("reorder_queue_size", "0".to_string()),
+                ("timeout", "5000000".to_string()),
+            ],
+        );
+    }
+}
@@ -1,12 +1,22 @@
 use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::format::stream::Disposition as AvDisposition;
 use ffmpeg::{Error as AvError, Rational as AvRational};
 
+use crate::audio::{AudioSettings, AudioStreamEncoder};
+use crate::checksum::{ContentHasher, HashAlgorithm, StreamHash};
+use crate::encode::{Settings, VideoStreamEncoder};
 use crate::error::Error;
 use crate::extradata::{extract_parameter_sets_h264, Pps, Sps};
+use crate::ffi;
 use crate::ffi::extradata;
-use crate::io::{Reader, Write};
+use crate::frame::{RawAudioFrame, RawFrame};
+use crate::io::{Reader, Write, Writer};
 use crate::packet::Packet;
-use crate::stream::StreamInfo;
+use crate::sidedata::SideDataPolicy;
+use crate::stream::{MediaType, StreamInfo, StreamMap};
+use crate::time::Time;
+use crate::topology::StageKind;
+use crate::validate::{PacketTimestamps, TimestampValidator};
 
 type Result<T> = std::result::Result<T, Error>;
 
@@ -15,6 +25,11 @@ pub struct MuxerBuilder<W: Write> {
     writer: W,
     interleaved: bool,
     mapping: std::collections::HashMap<usize, StreamDescription>,
+    content_hash: Option<HashAlgorithm>,
+    clean_start: bool,
+    side_data_policy: SideDataPolicy,
+    strict_timestamps: bool,
+    encoders: std::collections::HashMap<usize, StreamEncoder>,
 }
 
 impl<W: Write> MuxerBuilder<W> {
@@ -24,6 +39,11 @@ impl<W: Write> MuxerBuilder<W> {
             writer,
             interleaved: false,
             mapping: std::collections::HashMap::new(),
+            content_hash: None,
+            clean_start: false,
+            side_data_policy: SideDataPolicy::default(),
+            strict_timestamps: false,
+            encoders: std::collections::HashMap::new(),
         }
     }
 
@@ -36,17 +56,25 @@ impl<W: Write> MuxerBuilder<W> {
     /// # Arguments
     ///
     /// * `stream_info` - Stream information. Usually this information is retrieved by calling
-    ///   [`Reader::stream_info()`].
+    ///   [`Reader::stream_info()`]. If the stream is an attached picture (like cover art), it is
+    ///   flagged as such in the output as well, and the picture is muxed as the packet of the
+    ///   stream. Other metadata tags and disposition flags are not copied.
     pub fn with_stream(mut self, stream_info: StreamInfo) -> Result<Self> {
+        let disposition = stream_info.raw_disposition() & AvDisposition::ATTACHED_PIC;
         let (index, codec_parameters, reader_stream_time_base) = stream_info.into_parts();
         let mut writer_stream = self
             .writer
             .output_mut()
             .add_stream(ffmpeg::encoder::find(codec_parameters.id()))?;
+        let sample_rate = ffi::parameters_sample_rate(&codec_parameters);
         writer_stream.set_parameters(codec_parameters);
+        let writer_stream_index = writer_stream.index();
+        ffi::set_stream_disposition(self.writer.output_mut(), writer_stream_index, disposition);
         let stream_description = StreamDescription {
-            index: writer_stream.index(),
+            index: writer_stream_index,
             source_time_base: reader_stream_time_base,
+            media_type: codec_parameters.medium(),
+            sample_rate,
         };
         self.mapping.insert(index, stream_description);
         Ok(self)
@@ -66,6 +94,53 @@ impl<W: Write> MuxerBuilder<W> {
         Ok(self)
     }
 
+    /// Add the output streams selected by a [`StreamMap`] from a reader. Packets from streams that
+    /// were not selected can be skipped with [`Muxer::has_stream`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader to add streams from.
+    /// * `stream_map` - Selection of streams to add.
+    pub fn with_stream_map(mut self, reader: &Reader, stream_map: &StreamMap) -> Result<Self> {
+        for index in stream_map.select(reader) {
+            self = self.with_stream(reader.stream_info(index)?)?;
+        }
+        Ok(self)
+    }
+
+    /// Override the bit rate that is advertised for an output stream. When copying streams, the
+    /// source often does not specify a bit rate, while some players rely on it.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream in the reader. The stream must have been added.
+    /// * `bit_rate` - Bit rate to advertise.
+    pub fn with_stream_bit_rate(mut self, stream_index: usize, bit_rate: BitRate) -> Result<Self> {
+        let stream_description = self
+            .mapping
+            .get(&stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+        ffi::set_stream_bit_rate(
+            self.writer.output_mut(),
+            stream_description.index,
+            bit_rate.average,
+            bit_rate.max,
+            bit_rate.buffer_size,
+        )?;
+        Ok(self)
+    }
+
+    /// Override the overall bit rate that is advertised for the container, for formats that store
+    /// it.
+    ///
+    /// # Arguments
+    ///
+    /// * `bit_rate` - Bit rate in bits per second.
+    pub fn with_bit_rate(mut self, bit_rate: u64) -> Self {
+        ffi::set_output_bit_rate(self.writer.output_mut(), bit_rate);
+        self
+    }
+
     /// Set interleaved. This will cause the muxer to use interleaved write instead of normal
     /// write.
     pub fn interleaved(mut self) -> Self {
@@ -73,6 +148,53 @@ impl<W: Write> MuxerBuilder<W> {
         self
     }
 
+    /// Start the output cleanly when recording a live source that is joined mid-stream. Packets are
+    /// dropped until the first video keyframe, packets of other streams that end before that
+    /// keyframe are dropped as well, and the timestamps of all streams are shifted so the output
+    /// starts at zero. The audio packet that spans the keyframe is kept, and the samples before
+    /// the keyframe are marked to be skipped (`AV_PKT_DATA_SKIP_SAMPLES`), so that audio starts
+    /// on the same sample as the video.
+    ///
+    /// Without video streams, the output starts at the first packet with a timestamp. See also
+    /// [`RecorderBuilder::clean_start`](crate::record::RecorderBuilder::clean_start).
+    pub fn clean_start(mut self) -> Self {
+        self.clean_start = true;
+        self
+    }
+
+    /// Set which side data of the streams and packets (like HDR metadata and closed captions) is
+    /// copied to the output. By default, all side data is copied.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Side data policy.
+    pub fn with_side_data_policy(mut self, policy: SideDataPolicy) -> Self {
+        self.side_data_policy = policy;
+        self
+    }
+
+    /// Hash the decoded content of every stream while muxing, in the same pass. Audio and video
+    /// packets are decoded (but not re-encoded) to hash their samples, so the hashes only depend on
+    /// the media and not on the container. Get the hashes with [`Muxer::content_hashes`] after
+    /// muxing.
+    ///
+    /// # Arguments
+    ///
+    /// * `algorithm` - Hash function to use.
+    pub fn with_content_hash(mut self, algorithm: HashAlgorithm) -> Self {
+        self.content_hash = Some(algorithm);
+        self
+    }
+
+    /// Validate the timestamps of every packet, for debugging. Muxing fails with
+    /// [`Error::TimestampViolation`] at the first packet with a timestamp that is out of range, a
+    /// decoding timestamp that does not increase, a presentation timestamp before its decoding
+    /// timestamp, or timestamps that collapse when rescaled to the time base of the output stream.
+    pub fn with_strict_timestamps(mut self) -> Self {
+        self.strict_timestamps = true;
+        self
+    }
+
     /// Build [`Muxer`].
     pub fn build(self) -> Muxer<W> {
         Muxer {
@@ -81,12 +203,111 @@ impl<W: Write> MuxerBuilder<W> {
             interleaved: self.interleaved,
             have_written_header: false,
             have_written_trailer: false,
+            content_hash: self.content_hash,
+            hashers: std::collections::BTreeMap::new(),
+            clean_start: self.clean_start,
+            start: None,
+            side_data_policy: self.side_data_policy,
+            validator: self
+                .strict_timestamps
+                .then(|| TimestampValidator::new(StageKind::Writer)),
+            encoders: self.encoders,
         }
     }
 }
 
+impl MuxerBuilder<Writer> {
+    /// Add an output stream that is encoded from video frames by the muxer, next to the other
+    /// streams. Encode frames with [`Muxer::encode_video`]. Adding an encoder makes the muxer
+    /// interleaved, so packets of all streams are written in decoding order.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the stream, which takes the place of the stream index in the reader. Must
+    ///   be unique among the streams of the muxer.
+    /// * `settings` - Encoding settings. Hardware frames are not supported.
+    /// * `frame_rate` - Frame rate of the stream.
+    pub fn with_video_encoder(
+        mut self,
+        key: usize,
+        settings: &Settings,
+        frame_rate: AvRational,
+    ) -> Result<Self> {
+        let encoder = VideoStreamEncoder::new(&self.writer, settings, frame_rate)?;
+        self = self.with_stream(StreamInfo::from_params(
+            encoder.parameters(),
+            encoder.time_base(),
+            key,
+        )?)?;
+        self.encoders.insert(key, StreamEncoder::Video(encoder));
+        self.interleaved = true;
+        Ok(self)
+    }
+
+    /// Add an output stream that is encoded from audio frames by the muxer, next to the other
+    /// streams, like a soundtrack for a video stream. Encode frames with [`Muxer::encode_audio`].
+    /// Adding an encoder makes the muxer interleaved, so packets of all streams are written in
+    /// decoding order.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the stream, which takes the place of the stream index in the reader. Must
+    ///   be unique among the streams of the muxer.
+    /// * `settings` - Encoding settings.
+    pub fn with_audio_encoder(mut self, key: usize, settings: &AudioSettings) -> Result<Self> {
+        let encoder = settings.encoder(&self.writer)?;
+        self = self.with_stream(StreamInfo::from_params(
+            encoder.parameters(),
+            encoder.time_base(),
+            key,
+        )?)?;
+        self.encoders.insert(key, StreamEncoder::Audio(encoder));
+        self.interleaved = true;
+        Ok(self)
+    }
+}
+
+/// Bit rate to advertise for an output stream. See [`MuxerBuilder::with_stream_bit_rate`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BitRate {
+    /// Average bit rate in bits per second.
+    pub average: u64,
+    /// Maximum bit rate in bits per second.
+    pub max: Option<u64>,
+    /// Decoder buffer size in bits.
+    pub buffer_size: Option<u64>,
+}
+
+impl BitRate {
+    /// Create a bit rate with only an average.
+    ///
+    /// # Arguments
+    ///
+    /// * `average` - Average bit rate in bits per second.
+    pub fn new(average: u64) -> Self {
+        Self {
+            average,
+            ..Default::default()
+        }
+    }
+
+    /// Set the maximum bit rate and the size of the buffer in which it is measured.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum bit rate in bits per second.
+    /// * `buffer_size` - Decoder buffer size in bits.
+    pub fn with_max(mut self, max: u64, buffer_size: u64) -> Self {
+        self.max = Some(max);
+        self.buffer_size = Some(buffer_size);
+        self
+    }
+}
+
 /// Represents a muxer. A muxer allows muxing media packets into a new container format. Muxing does
-/// not require encoding and/or decoding.
+/// not require encoding and/or decoding, but streams can also be encoded from frames by the muxer,
+/// for example to write a video with sound. See [`MuxerBuilder::with_video_encoder`] and
+/// [`MuxerBuilder::with_audio_encoder`].
 ///
 /// # Examples
 ///
@@ -118,12 +339,45 @@ impl<W: Write> MuxerBuilder<W> {
 /// }
 /// muxer.finish()?;
 /// ```
+///
+/// Encode video with two audio tracks to MP4:
+///
+/// ```ignore
+/// const VIDEO: usize = 0;
+/// const AUDIO_EN: usize = 1;
+/// const AUDIO_NL: usize = 2;
+/// let writer = Writer::new(Path::new("movie.mp4")).unwrap();
+/// let mut muxer = MuxerBuilder::new(writer)
+///     .with_video_encoder(
+///         VIDEO,
+///         &Settings::preset_h264_yuv420p(1280, 720, false),
+///         Rational::new(25, 1),
+///     )
+///     .unwrap()
+///     .with_audio_encoder(AUDIO_EN, &AudioSettings::new(48_000, 2))
+///     .unwrap()
+///     .with_audio_encoder(AUDIO_NL, &AudioSettings::new(48_000, 2))
+///     .unwrap()
+///     .build();
+/// // Frames have timestamps in the time base of their stream, see `Muxer::encoder_time_base`.
+/// muxer.encode_video(VIDEO, &video_frame).unwrap();
+/// muxer.encode_audio(AUDIO_EN, &english_frame).unwrap();
+/// muxer.encode_audio(AUDIO_NL, &dutch_frame).unwrap();
+/// muxer.finish().unwrap();
+/// ```
 pub struct Muxer<W: Write> {
     pub(crate) writer: W,
     mapping: std::collections::HashMap<usize, StreamDescription>,
     interleaved: bool,
     have_written_header: bool,
     have_written_trailer: bool,
+    content_hash: Option<HashAlgorithm>,
+    hashers: std::collections::BTreeMap<usize, ContentHasher>,
+    clean_start: bool,
+    start: Option<Time>,
+    side_data_policy: SideDataPolicy,
+    validator: Option<TimestampValidator>,
+    encoders: std::collections::HashMap<usize, StreamEncoder>,
 }
 
 impl<W: Write> Muxer<W> {
@@ -134,24 +388,67 @@ impl<W: Write> Muxer<W> {
     /// * `packet` - [`Packet`] to mux.
     pub fn mux(&mut self, packet: Packet) -> Result<W::Out> {
         if self.have_written_header {
+            let Some((packet, skip_samples)) = self.trim_start(packet) else {
+                return Ok(W::Out::default());
+            };
             let mut packet = packet.into_inner();
+            if !self.side_data_policy.is_copy_all() {
+                ffi::retain_packet_side_data(&mut packet, |kind| {
+                    self.side_data_policy.keeps_packet_side_data(kind)
+                });
+            }
+            if skip_samples > 0 {
+                ffi::set_packet_skip_samples(&mut packet, skip_samples)?;
+            }
             let stream_description = self
                 .mapping
                 .get(&packet.stream())
                 .ok_or(AvError::StreamNotFound)?;
 
+            if let Some(algorithm) = self.content_hash {
+                let hasher = match self.hashers.entry(packet.stream()) {
+                    std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+                    std::collections::btree_map::Entry::Vacant(entry) => {
+                        let parameters = self
+                            .writer
+                            .output()
+                            .stream(stream_description.index)
+                            .ok_or(AvError::StreamNotFound)?
+                            .parameters();
+                        entry.insert(ContentHasher::new(packet.stream(), parameters, algorithm)?)
+                    }
+                };
+                hasher.update(&packet)?;
+            }
+
             let destination_stream = self
                 .writer
                 .output()
                 .stream(stream_description.index)
                 .ok_or(AvError::StreamNotFound)?;
 
+            let source_stream_index = packet.stream();
+            let source = PacketTimestamps::of(&packet);
             packet.set_stream(destination_stream.index());
             packet.set_position(-1);
             packet.rescale_ts(
                 stream_description.source_time_base,
                 destination_stream.time_base(),
             );
+            if let Some(validator) = self.validator.as_mut() {
+                validator.check_packet(
+                    source_stream_index,
+                    source,
+                    stream_description.source_time_base,
+                )?;
+                validator.check_rescaled(
+                    source_stream_index,
+                    source,
+                    stream_description.source_time_base,
+                    PacketTimestamps::of(&packet),
+                    destination_stream.time_base(),
+                )?;
+            }
 
             Ok({
                 if self.interleaved {
@@ -161,15 +458,117 @@ impl<W: Write> Muxer<W> {
                 }
             })
         } else {
-            self.have_written_header = true;
-            self.writer.write_header()?;
+            self.write_header()?;
             self.mux(packet)
         }
     }
 
+    /// Write the container header. This happens automatically before the first packet is muxed.
+    /// Call this to write the header earlier, for example to start a live stream before the first
+    /// packet is available.
+    ///
+    /// # Return value
+    ///
+    /// Output of writing the header, or `None` if the header was already written.
+    pub fn write_header(&mut self) -> Result<Option<W::Out>> {
+        if self.have_written_header {
+            return Ok(None);
+        }
+        self.have_written_header = true;
+        if !self.side_data_policy.is_copy_all() {
+            for stream_description in self.mapping.values() {
+                ffi::retain_stream_side_data(
+                    self.writer.output_mut(),
+                    stream_description.index,
+                    |kind| self.side_data_policy.keeps_packet_side_data(kind),
+                );
+            }
+        }
+        self.writer.write_header().map(Some)
+    }
+
+    /// Drop the packets before the clean start point and shift the timestamps of the packets after
+    /// it. See [`MuxerBuilder::clean_start`].
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to mux.
+    ///
+    /// # Return value
+    ///
+    /// The packet with shifted timestamps and the number of samples to skip at its start, or `None`
+    /// if it should be dropped.
+    fn trim_start(&mut self, mut packet: Packet) -> Option<(Packet, u32)> {
+        if !self.clean_start {
+            return Some((packet, 0));
+        }
+        // Packets of unknown streams are passed on, so that muxing them fails.
+        let Some(stream_description) = self.mapping.get(&packet.stream_index()) else {
+            return Some((packet, 0));
+        };
+        let timestamp = if packet.pts().has_value() {
+            packet.pts()
+        } else {
+            packet.dts()
+        };
+        let start = match self.start {
+            Some(start) => start,
+            None => {
+                let has_video = self
+                    .mapping
+                    .values()
+                    .any(|stream| stream.media_type == MediaType::Video);
+                let is_start = !has_video
+                    || (stream_description.media_type == MediaType::Video && packet.is_key());
+                if !is_start || !timestamp.has_value() {
+                    return None;
+                }
+                *self.start.insert(timestamp)
+            }
+        };
+        let offset = timestamp.aligned_with(start).subtract();
+        let mut skip_samples = 0;
+        if offset.into_value().is_some_and(|offset| offset < 0) {
+            // Audio that spans the start point is kept and trimmed to the sample when decoded.
+            let end = offset.aligned_with(packet.duration()).add();
+            let spans_start = stream_description.media_type == MediaType::Audio
+                && stream_description.sample_rate > 0
+                && end.into_value().is_some_and(|end| end > 0);
+            if !spans_start {
+                return None;
+            }
+            let skip = -offset.as_secs_f64() * stream_description.sample_rate as f64;
+            skip_samples = skip.round() as u32;
+        }
+        packet.set_pts(packet.pts().aligned_with(start).subtract());
+        packet.set_dts(packet.dts().aligned_with(start).subtract());
+        Some((packet, skip_samples))
+    }
+
+    /// Check whether packets from an input stream are muxed, i.e. whether the stream was added to
+    /// the muxer.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream in the reader.
+    pub fn has_stream(&self, stream_index: usize) -> bool {
+        self.mapping.contains_key(&stream_index)
+    }
+
     /// Signal to the muxer that writing has finished. This will cause a trailer to be written if
-    /// the container format has one.
+    /// the container format has one. Streams that are encoded by the muxer are flushed first.
     pub fn finish(&mut self) -> Result<Option<W::Out>> {
+        if !self.have_written_trailer {
+            let mut keys = self.encoders.keys().copied().collect::<Vec<_>>();
+            keys.sort_unstable();
+            for key in keys {
+                let packets = match self.encoders.get_mut(&key) {
+                    Some(encoder) => encoder.finish()?,
+                    None => continue,
+                };
+                self.mux_encoded(key, packets)?;
+            }
+        }
         if self.have_written_header && !self.have_written_trailer {
             self.have_written_trailer = true;
             self.writer.write_trailer().map(Some)
@@ -178,6 +577,60 @@ impl<W: Write> Muxer<W> {
         }
     }
 
+    /// Time base of the timestamps of the frames of a stream that is encoded by the muxer.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the stream.
+    pub fn encoder_time_base(&self, key: usize) -> Option<AvRational> {
+        self.encoders.get(&key).map(StreamEncoder::time_base)
+    }
+
+    /// Mux the packets of a stream that is encoded by the muxer.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the stream.
+    /// * `packets` - Encoded packets.
+    fn mux_encoded(&mut self, key: usize, packets: Vec<Packet>) -> Result<()> {
+        for mut packet in packets {
+            packet.set_stream_index(key);
+            self.mux(packet)?;
+        }
+        Ok(())
+    }
+
+    /// Finish the content hashes requested with [`MuxerBuilder::with_content_hash`]. Call this
+    /// after the last packet was muxed, since the decoders are drained to hash the last frames.
+    ///
+    /// # Return value
+    ///
+    /// Hash of every stream that packets were muxed for, ordered by stream index. Empty if content
+    /// hashing was not enabled.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut muxer = MuxerBuilder::new(writer)
+    ///     .with_streams(&reader)
+    ///     .unwrap()
+    ///     .with_content_hash(HashAlgorithm::Md5)
+    ///     .build();
+    /// while let Ok(packet) = reader.read_any() {
+    ///     muxer.mux(packet).unwrap();
+    /// }
+    /// muxer.finish().unwrap();
+    /// for stream_hash in muxer.content_hashes().unwrap() {
+    ///     println!("stream {}: {}", stream_hash.stream_index, stream_hash.hash);
+    /// }
+    /// ```
+    pub fn content_hashes(&mut self) -> Result<Vec<StreamHash>> {
+        std::mem::take(&mut self.hashers)
+            .into_values()
+            .map(ContentHasher::finish)
+            .collect()
+    }
+
     /// Get parameter sets corresponding to each internal stream. The parameter set contains one SPS
     /// (Sequence Parameter Set) and zero or more PPSs (Picture Parameter Sets).
     ///
@@ -199,13 +652,89 @@ impl<W: Write> Muxer<W> {
     }
 }
 
+impl Muxer<Writer> {
+    /// Encode a video frame for a stream added with [`MuxerBuilder::with_video_encoder`], and mux
+    /// the packets that became available.
+    ///
+    /// Frames of any pixel format and size are scaled to the settings of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the stream.
+    /// * `frame` - Frame to encode, with a timestamp in [`Muxer::encoder_time_base`].
+    pub fn encode_video(&mut self, key: usize, frame: &RawFrame) -> Result<()> {
+        let packets = match self.encoders.get_mut(&key) {
+            Some(StreamEncoder::Video(encoder)) => encoder.encode(frame)?,
+            _ => return Err(AvError::StreamNotFound.into()),
+        };
+        self.mux_encoded(key, packets)
+    }
+
+    /// Encode an audio frame for a stream added with [`MuxerBuilder::with_audio_encoder`], and mux
+    /// the packets that became available.
+    ///
+    /// Frames of any sample format, sample rate and channel layout are converted to the settings
+    /// of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the stream.
+    /// * `frame` - Frame to encode, with a timestamp in [`Muxer::encoder_time_base`].
+    pub fn encode_audio(&mut self, key: usize, frame: &RawAudioFrame) -> Result<()> {
+        let packets = match self.encoders.get_mut(&key) {
+            Some(StreamEncoder::Audio(encoder)) => {
+                let time_base = encoder.time_base();
+                encoder.encode(frame, time_base)?
+            }
+            _ => return Err(AvError::StreamNotFound.into()),
+        };
+        self.mux_encoded(key, packets)
+    }
+
+    /// Flush the current fragment of a fragmented output (like MP4 with `movflags` set to
+    /// `frag_custom`), and the IO buffer, so that everything muxed so far reaches the destination.
+    /// For segmented live streaming like LL-HLS, call this at every part boundary.
+    pub fn flush_fragment(&mut self) -> Result<()> {
+        if self.have_written_header && !self.have_written_trailer {
+            self.writer.flush_fragment()?;
+        }
+        Ok(())
+    }
+}
+
 unsafe impl<W: Write> Send for Muxer<W> {}
 unsafe impl<W: Write> Sync for Muxer<W> {}
 
+/// Encoder of a stream that is encoded by the muxer.
+enum StreamEncoder {
+    Video(VideoStreamEncoder),
+    Audio(AudioStreamEncoder),
+}
+
+impl StreamEncoder {
+    /// Time base of the frames and packets.
+    fn time_base(&self) -> AvRational {
+        match self {
+            StreamEncoder::Video(encoder) => encoder.time_base(),
+            StreamEncoder::Audio(encoder) => encoder.time_base(),
+        }
+    }
+
+    /// Flush the encoder at the end of the stream.
+    fn finish(&mut self) -> Result<Vec<Packet>> {
+        match self {
+            StreamEncoder::Video(encoder) => encoder.finish(),
+            StreamEncoder::Audio(encoder) => encoder.finish(),
+        }
+    }
+}
+
 /// Internal structure that holds the stream index and the time base of the source packet for
 /// rescaling.
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct StreamDescription {
     index: usize,
     source_time_base: AvRational,
+    media_type: MediaType,
+    sample_rate: u32,
 }
@@ -1,11 +1,32 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
 
 use ffmpeg::Dictionary as AvDictionary;
 
-/// A wrapper type for ffmpeg options.
+use crate::ffi;
+
+/// A wrapper type for ffmpeg options. Serialized as a list of key and value pairs.
 #[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(from = "Vec<(String, String)>", into = "Vec<(String, String)>")
+)]
 pub struct Options(AvDictionary<'static>);
 
+/// Options are equal if they have the same keys with the same values. The order of the keys does
+/// not matter, but the order of the values of a key does.
+impl PartialEq for Options {
+    fn eq(&self, other: &Self) -> bool {
+        let by_key = |options: &Options| {
+            let mut pairs = options.iter().collect::<Vec<_>>();
+            pairs.sort_by_key(|(key, _)| *key);
+            pairs
+        };
+        by_key(self) == by_key(other)
+    }
+}
+
 impl Options {
     /// Creates options such that ffmpeg will prefer TCP transport when reading RTSP stream (over
     /// the default UDP format).
@@ -51,6 +72,19 @@ impl Options {
         Self(opts)
     }
 
+    /// Creates options for publishing live to an RTMP server.
+    ///
+    /// This sets `flvflags` to `no_duration_filesize`, since a live stream has neither and the
+    /// muxer cannot seek back to fill them in. It also sets `rw_timeout` to 10 seconds, so that a
+    /// stalled connection fails instead of blocking forever.
+    pub fn preset_rtmp_live() -> Self {
+        let mut opts = AvDictionary::new();
+        opts.set("flvflags", "no_duration_filesize");
+        opts.set("rw_timeout", "10000000");
+
+        Self(opts)
+    }
+
     /// Default options for a H264 encoder.
     pub fn preset_h264() -> Self {
         let mut opts = AvDictionary::new();
@@ -60,6 +94,17 @@ impl Options {
         Self(opts)
     }
 
+    /// Options for a H264 encoder that are tuned for a still image, such as a cover image shown
+    /// for the duration of a podcast episode.
+    pub fn preset_h264_still_image() -> Self {
+        let mut opts = AvDictionary::new();
+        opts.set("preset", "medium");
+        // Tune for content that hardly changes between frames.
+        opts.set("tune", "stillimage");
+
+        Self(opts)
+    }
+
     /// Options for a H264 encoder that are tuned for low-latency encoding such as for real-time
     /// streaming.
     pub fn preset_h264_realtime() -> Self {
@@ -72,6 +117,106 @@ impl Options {
         Self(opts)
     }
 
+    /// Set a single option, overwriting any previous value.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the option.
+    /// * `value` - Value of the option.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.0.set(key, value);
+    }
+
+    /// Add an option without overwriting previous values with the same key. Some options, like
+    /// `headers` of the HTTP protocol, may be given more than once.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the option.
+    /// * `value` - Value of the option.
+    pub fn append(&mut self, key: &str, value: &str) {
+        self.0 = ffi::dictionary_append(std::mem::take(&mut self.0), key, value);
+    }
+
+    /// Remove all values of an option.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the option.
+    pub fn remove(&mut self, key: &str) {
+        let pairs = self
+            .iter()
+            .filter(|(entry_key, _)| *entry_key != key)
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        *self = pairs.into();
+    }
+
+    /// Get the first value of an option.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the option.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.iter()
+            .find(|(entry_key, _)| *entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Get all values of an option, in the order they were added.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the option.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.iter()
+            .filter(|(entry_key, _)| *entry_key == key)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Iterate over the options as key and value, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        // Options are only set from Rust strings, so they are valid UTF-8 and never copied.
+        self.0.iter().filter_map(|entry| match entry {
+            (Cow::Borrowed(key), Cow::Borrowed(value)) => Some((key, value)),
+            _ => None,
+        })
+    }
+
+    /// Number of options, counting every value of options that are given more than once.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Whether there are no options.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Wrap an ffmpeg native dictionary, like the options that ffmpeg did not consume.
+    pub(crate) fn from_dict(dictionary: AvDictionary<'static>) -> Self {
+        Self(dictionary)
+    }
+
+    /// Get the keys of options that ffmpeg did not consume, and warn about them, since they are
+    /// usually typos (like "perset") or options for another component.
+    ///
+    /// # Arguments
+    ///
+    /// * `unused` - Options that were left over after opening a component.
+    /// * `component` - Description of the component for the warning, like "encoder".
+    pub(crate) fn unused_keys(unused: AvDictionary<'static>, component: &str) -> Vec<String> {
+        let keys = Self::from_dict(unused)
+            .iter()
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        for key in &keys {
+            tracing::warn!("option {key} was not used by {component}");
+        }
+        keys
+    }
+
     /// Convert back to ffmpeg native dictionary, which can be used with `ffmpeg` functions.
     pub(super) fn to_dict(&self) -> AvDictionary {
         self.0.clone()
@@ -126,5 +271,94 @@ impl From<Options> for HashMap<String, String> {
     }
 }
 
+impl From<Vec<(String, String)>> for Options {
+    /// Converts from key and value pairs to `Options`. Keeps the order of the pairs, and all values
+    /// of keys that occur more than once.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to convert from.
+    fn from(item: Vec<(String, String)>) -> Self {
+        let mut opts = Self::default();
+        for (k, v) in item {
+            opts.append(&k, &v);
+        }
+
+        opts
+    }
+}
+
+impl From<Options> for Vec<(String, String)> {
+    /// Converts from `Options` to key and value pairs, in the order they were added.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to convert from.
+    fn from(item: Options) -> Self {
+        item.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+}
+
+/// Escape a value for use as option value in a filter description, like the text of `drawtext`.
+/// Escapes the characters that separate options (`:`) and that escape and quote (`\` and `'`).
+///
+/// # Arguments
+///
+/// * `value` - Value to escape.
+///
+/// # Example
+///
+/// ```ignore
+/// let filter = format!("drawtext=text={}", escape_filter_option("12:00"));
+/// assert_eq!(filter, r"drawtext=text=12\:00");
+/// ```
+pub fn escape_filter_option(value: &str) -> String {
+    escape(value, &['\\', '\'', ':'])
+}
+
+/// Escape a filter description for use in a filter graph. Escapes the characters that separate
+/// filters and chains (`,` and `;`), that delimit link labels (`[` and `]`) and that escape and
+/// quote (`\` and `'`).
+///
+/// Option values in a filter description must be escaped with [`escape_filter_option`] first,
+/// since the filter graph parser removes one level of escaping.
+///
+/// # Arguments
+///
+/// * `value` - Filter description to escape.
+pub fn escape_filter_graph(value: &str) -> String {
+    escape(value, &['\\', '\'', '[', ']', ',', ';'])
+}
+
+/// Escape characters with a backslash.
+fn escape(value: &str, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for character in value.chars() {
+        if special.contains(&character) {
+            escaped.push('\\');
+        }
+        escaped.push(character);
+    }
+    escaped
+}
+
 unsafe impl Send for Options {}
 unsafe impl Sync for Options {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape_filter_option("12:00"), r"12\:00");
+        assert_eq!(escape_filter_option(r"it's \o/"), r"it\'s \\o/");
+        assert_eq!(
+            escape_filter_graph(r"drawtext=text=a\:b,scale"),
+            r"drawtext=text=a\\:b\,scale"
+        );
+        assert_eq!(escape_filter_graph("[in];[out]"), r"\[in\]\;\[out\]");
+    }
+}
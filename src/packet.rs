@@ -1,8 +1,15 @@
 use ffmpeg::codec::packet::Packet as AvPacket;
 use ffmpeg::Rational as AvRational;
 
+use crate::error::Error;
+use crate::ffi;
 use crate::time::Time;
 
+/// Transform applied to every packet on the read or write path, like decryption after reading or
+/// encryption before writing. See [`crate::ReaderBuilder::with_packet_transform`] and
+/// [`crate::WriterBuilder::with_packet_transform`].
+pub type PacketTransform = Box<dyn Fn(&mut Packet) -> Result<(), Error> + Send + Sync>;
+
 /// Represents a stream packet.
 #[derive(Clone)]
 pub struct Packet {
@@ -35,6 +42,42 @@ impl Packet {
         self.inner.is_key()
     }
 
+    /// Get the index of the stream the packet belongs to.
+    #[inline]
+    pub fn stream_index(&self) -> usize {
+        self.inner.stream()
+    }
+
+    /// Get packet payload size in bytes.
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.inner.size()
+    }
+
+    /// Get the payload of the packet.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        self.inner.data().unwrap_or_default()
+    }
+
+    /// Get the payload of the packet to change it in place, for transforms that keep the size
+    /// (like stream ciphers). The payload is copied first if it is shared with other packets.
+    pub fn data_mut(&mut self) -> Result<&mut [u8], Error> {
+        ffi::make_packet_writable(&mut self.inner)?;
+        Ok(self.inner.data_mut().unwrap_or_default())
+    }
+
+    /// Replace the payload of the packet, for transforms that change the size (like block ciphers
+    /// with padding). Timestamps, flags and side data are kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - New payload.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        ffi::replace_packet_data(&mut self.inner, data)?;
+        Ok(())
+    }
+
     /// Set packet PTS (presentation timestamp).
     #[inline]
     pub fn set_pts(&mut self, timestamp: Time) {
@@ -57,6 +100,12 @@ impl Packet {
         }
     }
 
+    /// Set the index of the stream the packet belongs to, to route it to another stream.
+    #[inline]
+    pub(crate) fn set_stream_index(&mut self, index: usize) {
+        self.inner.set_stream(index);
+    }
+
     /// Create a new packet.
     ///
     /// # Arguments
@@ -0,0 +1,95 @@
+//! Parsing of elementary streams that are not in a container, like raw H.264 received from a
+//! socket, into packets for decoding or muxing.
+
+use ffmpeg::codec::packet::{flag::Flags as AvPacketFlags, Packet as AvPacket};
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::util::mathematics::rescale::TIME_BASE;
+use ffmpeg::Error as AvError;
+
+use crate::error::Error;
+use crate::ffi;
+use crate::packet::Packet;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Splits an elementary stream (like raw H.264 Annex B, HEVC or ADTS AAC) into packets, for input
+/// that is not in a container, such as data received from a socket.
+///
+/// Feed the stream in chunks of any size with [`Parser::parse`], and call [`Parser::flush`] at the
+/// end of the stream to get the last packet. The packets do not have timestamps.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut parser = Parser::new(AvCodecId::H264).unwrap();
+/// let mut buffer = [0; 4096];
+/// loop {
+///     let read = socket.read(&mut buffer).unwrap();
+///     if read == 0 {
+///         break;
+///     }
+///     for packet in parser.parse(&buffer[..read]).unwrap() {
+///         println!("packet of {} bytes, key: {}", packet.size(), packet.is_key());
+///     }
+/// }
+/// let last_packets = parser.flush().unwrap();
+/// ```
+pub struct Parser {
+    context: ffi::ParserContext,
+}
+
+impl Parser {
+    /// Create a parser for a codec.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec_id` - Codec of the elementary stream.
+    pub fn new(codec_id: AvCodecId) -> Result<Self> {
+        let context = ffi::ParserContext::new(codec_id)
+            .ok_or(Error::BackendError(AvError::DecoderNotFound))?;
+        Ok(Self { context })
+    }
+
+    /// Parse the next chunk of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Next chunk of the stream.
+    ///
+    /// # Return value
+    ///
+    /// Packets that were completed by the chunk. Data of incomplete packets is kept until the
+    /// next call. Fails with [`AvError::InvalidData`] if the parser stops consuming the chunk
+    /// without completing a packet.
+    pub fn parse(&mut self, data: &[u8]) -> Result<Vec<Packet>> {
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.parse_inner(data)
+    }
+
+    /// Signal the end of the stream and get the remaining packets.
+    pub fn flush(&mut self) -> Result<Vec<Packet>> {
+        self.parse_inner(&[])
+    }
+
+    /// Width and height of the most recently parsed video frame, if the parser extracts them from
+    /// the stream.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.context.dimensions()
+    }
+
+    fn parse_inner(&mut self, data: &[u8]) -> Result<Vec<Packet>> {
+        let mut packets = Vec::new();
+        self.context.parse(data, |packet_data, is_key| {
+            let mut packet = AvPacket::copy(packet_data);
+            if is_key {
+                packet.set_flags(AvPacketFlags::KEY);
+            }
+            packets.push(Packet::new(packet, TIME_BASE));
+        })?;
+        Ok(packets)
+    }
+}
+
+unsafe impl Send for Parser {}
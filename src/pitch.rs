@@ -0,0 +1,364 @@
+//! Pitch shifting and tempo changes of audio, like anonymizing a voice or matching the tempo of a
+//! preview to a target length.
+
+use ffmpeg::filter::Graph as AvFilterGraph;
+use ffmpeg::util::format::Sample as AvSample;
+use ffmpeg::{ChannelLayout as AvChannelLayout, Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::frame::RawAudioFrame;
+use crate::mixer::{add_frame, end_source, receive_frame};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// How a [`PitchShift`] is applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PitchEngine {
+    /// Change the sample rate with `asetrate` to shift the pitch, and correct the tempo with
+    /// `atempo`. Always available, but the formants shift along with the pitch, which sounds less
+    /// natural for large shifts (and is what hides the identity of a voice).
+    #[default]
+    Resample,
+    /// Shift pitch and tempo independently with the `rubberband` filter, which sounds more natural
+    /// and can preserve formants. Only available when ffmpeg is built with
+    /// `--enable-librubberband` (which makes the build GPL). Otherwise, configuring the filter
+    /// graph fails.
+    Rubberband,
+}
+
+/// Pitch and tempo change, applied by a [`PitchShifter`].
+///
+/// # Example
+///
+/// Play a preview at 1.25 times the speed without changing the pitch:
+///
+/// ```ignore
+/// let shift = PitchShift::new().with_tempo(1.25);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchShift {
+    pitch: f64,
+    tempo: f64,
+    engine: PitchEngine,
+    preserve_formants: bool,
+}
+
+impl PitchShift {
+    /// Smallest and largest pitch and tempo ratio.
+    const RATIO_RANGE: (f64, f64) = (0.01, 100.0);
+    /// Smallest and largest tempo ratio of a single `atempo` filter.
+    const ATEMPO_RANGE: (f64, f64) = (0.5, 100.0);
+    /// Ratios closer to 1 than this leave the audio unchanged.
+    const EPSILON: f64 = 1e-6;
+
+    /// Create a pitch shift that leaves pitch and tempo unchanged.
+    pub fn new() -> Self {
+        Self {
+            pitch: 1.0,
+            tempo: 1.0,
+            engine: PitchEngine::default(),
+            preserve_formants: false,
+        }
+    }
+
+    /// Create a pitch shift that makes a voice harder to recognize: down by four semitones,
+    /// shifting the formants along.
+    pub fn voice_anonymization() -> Self {
+        Self::new().with_semitones(-4.0)
+    }
+
+    /// Set the pitch as a ratio, where `2.0` is an octave up.
+    ///
+    /// # Arguments
+    ///
+    /// * `pitch` - Pitch ratio, from 0.01 to 100. Values out of range are clamped.
+    pub fn with_pitch(mut self, pitch: f64) -> Self {
+        self.pitch = pitch.clamp(Self::RATIO_RANGE.0, Self::RATIO_RANGE.1);
+        self
+    }
+
+    /// Set the pitch in semitones.
+    ///
+    /// # Arguments
+    ///
+    /// * `semitones` - Number of semitones to shift by, negative to shift down.
+    pub fn with_semitones(self, semitones: f64) -> Self {
+        self.with_pitch(2.0_f64.powf(semitones / 12.0))
+    }
+
+    /// Set the tempo as a ratio, where `2.0` plays twice as fast. The pitch is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `tempo` - Tempo ratio, from 0.01 to 100. Values out of range are clamped.
+    pub fn with_tempo(mut self, tempo: f64) -> Self {
+        self.tempo = tempo.clamp(Self::RATIO_RANGE.0, Self::RATIO_RANGE.1);
+        self
+    }
+
+    /// Set how the pitch shift is applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - Pitch engine.
+    pub fn with_engine(mut self, engine: PitchEngine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Set whether to preserve the formants when shifting the pitch, which keeps voices sounding
+    /// natural. Only [`PitchEngine::Rubberband`] can preserve formants.
+    ///
+    /// # Arguments
+    ///
+    /// * `preserve_formants` - Whether to preserve formants.
+    pub fn with_formant_preservation(mut self, preserve_formants: bool) -> Self {
+        self.preserve_formants = preserve_formants;
+        self
+    }
+
+    /// Get the pitch ratio.
+    pub fn pitch(&self) -> f64 {
+        self.pitch
+    }
+
+    /// Get the tempo ratio.
+    pub fn tempo(&self) -> f64 {
+        self.tempo
+    }
+
+    /// Get the description of the filters that apply the pitch shift.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_rate` - Sample rate of the audio, which the output keeps.
+    pub fn filter_spec(&self, sample_rate: u32) -> String {
+        let unchanged = |ratio: f64| (ratio - 1.0).abs() < Self::EPSILON;
+        if unchanged(self.pitch) && unchanged(self.tempo) {
+            return "anull".to_string();
+        }
+        match self.engine {
+            PitchEngine::Rubberband => {
+                let mut spec =
+                    format!("rubberband=tempo={:.6}:pitch={:.6}", self.tempo, self.pitch);
+                if self.preserve_formants {
+                    spec.push_str(":formant=preserved");
+                }
+                spec
+            }
+            PitchEngine::Resample => {
+                let mut filters = Vec::new();
+                let mut tempo = self.tempo;
+                if !unchanged(self.pitch) {
+                    // Playing at a higher sample rate raises the pitch and the tempo alike, so the
+                    // tempo is corrected by the ratio the sample rate actually changed by.
+                    let rate = (f64::from(sample_rate) * self.pitch).round().max(1.0);
+                    filters.push(format!("asetrate={rate},aresample={sample_rate}"));
+                    tempo /= rate / f64::from(sample_rate);
+                }
+                // A single `atempo` filter only covers a limited range, so larger changes are
+                // chained.
+                let (min, max) = Self::ATEMPO_RANGE;
+                while tempo < min {
+                    filters.push(format!("atempo={min:.6}"));
+                    tempo /= min;
+                }
+                while tempo > max {
+                    filters.push(format!("atempo={max:.6}"));
+                    tempo /= max;
+                }
+                if !unchanged(tempo) {
+                    filters.push(format!("atempo={tempo:.6}"));
+                }
+                if filters.is_empty() {
+                    filters.push("anull".to_string());
+                }
+                filters.join(",")
+            }
+        }
+    }
+}
+
+impl Default for PitchShift {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Applies a [`PitchShift`] to audio frames with a filter graph.
+///
+/// The output has the sample format, sample rate and channel layout of the input. The filter
+/// graph is configured with the first frame, since it depends on the format of the source. With a
+/// tempo change, the timestamps of the output follow the new tempo.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut shifter = PitchShifter::new(PitchShift::voice_anonymization(), decoder.time_base());
+/// while let Ok(frame) = decoder.decode_raw() {
+///     shifter.push(frame)?;
+///     while let Some(frame) = shifter.pull()? {
+///         encoder.encode_raw(&frame)?;
+///     }
+/// }
+/// shifter.finish()?;
+/// while let Some(frame) = shifter.pull()? {
+///     encoder.encode_raw(&frame)?;
+/// }
+/// ```
+pub struct PitchShifter {
+    shift: PitchShift,
+    time_base: AvRational,
+    format: Option<(AvSample, u32, u64)>,
+    graph: Option<AvFilterGraph>,
+}
+
+impl PitchShifter {
+    /// Create a pitch shifter.
+    ///
+    /// # Arguments
+    ///
+    /// * `shift` - Pitch shift to apply.
+    /// * `time_base` - Time base of the timestamps of the frames.
+    pub fn new(shift: PitchShift, time_base: AvRational) -> Self {
+        Self {
+            shift,
+            time_base,
+            format: None,
+            graph: None,
+        }
+    }
+
+    /// Push a frame.
+    ///
+    /// Returns [`Error::InvalidFrameFormat`] if the frame has another sample format, channel layout
+    /// or sample rate than the frames before it.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame with samples, with a timestamp in the time base of the pitch shifter.
+    pub fn push(&mut self, frame: RawAudioFrame) -> Result<()> {
+        let mask = match frame.channel_layout().bits() {
+            0 => AvChannelLayout::default(i32::from(frame.channels())).bits(),
+            mask => mask,
+        };
+        let format = (frame.format(), frame.rate(), mask);
+        match self.format {
+            Some(known) if known != format => return Err(Error::InvalidFrameFormat),
+            Some(_) => {}
+            None => self.format = Some(format),
+        }
+        let graph = match self.graph.as_mut() {
+            Some(graph) => graph,
+            None => self.graph.insert(self.configure(format)?),
+        };
+        add_frame(graph, 0, &frame)
+    }
+
+    /// Signal the end of the input, after which the remaining samples can be pulled.
+    pub fn finish(&mut self) -> Result<()> {
+        match self.graph.as_mut() {
+            Some(graph) => end_source(graph, 0),
+            None => Ok(()),
+        }
+    }
+
+    /// Pull a shifted frame.
+    ///
+    /// # Return value
+    ///
+    /// The frame, or `None` if more frames need to be pushed first, or the input has ended.
+    pub fn pull(&mut self) -> Result<Option<RawAudioFrame>> {
+        match self.graph.as_mut() {
+            Some(graph) => receive_frame(graph),
+            None => Ok(None),
+        }
+    }
+
+    /// Configure the filter graph for the format of the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Sample format, sample rate and channel mask of the frames.
+    fn configure(
+        &self,
+        (sample_format, sample_rate, mask): (AvSample, u32, u64),
+    ) -> Result<AvFilterGraph> {
+        let mut graph = AvFilterGraph::new();
+        let abuffer = ffmpeg::filter::find("abuffer").ok_or(AvError::FilterNotFound)?;
+        let abuffersink = ffmpeg::filter::find("abuffersink").ok_or(AvError::FilterNotFound)?;
+        graph.add(
+            &abuffer,
+            "in0",
+            &format!(
+                "time_base={}/{}:sample_rate={sample_rate}:sample_fmt={}:channel_layout=0x{mask:x}",
+                self.time_base.numerator(),
+                self.time_base.denominator(),
+                sample_format.name(),
+            ),
+        )?;
+        graph.add(&abuffersink, "out", "")?;
+
+        let spec = format!(
+            "{},aresample={sample_rate},aformat=sample_fmts={}:channel_layouts=0x{mask:x}",
+            self.shift.filter_spec(sample_rate),
+            sample_format.name(),
+        );
+        graph.output("in0", 0)?.input("out", 0)?.parse(&spec)?;
+        graph.validate()?;
+        Ok(graph)
+    }
+}
+
+unsafe impl Send for PitchShifter {}
+unsafe impl Sync for PitchShifter {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unchanged() {
+        assert_eq!(PitchShift::new().filter_spec(48_000), "anull");
+        assert_eq!(
+            PitchShift::new()
+                .with_engine(PitchEngine::Rubberband)
+                .filter_spec(48_000),
+            "anull",
+        );
+    }
+
+    #[test]
+    fn test_resample_pitch_keeps_tempo() {
+        assert_eq!(
+            PitchShift::new().with_pitch(1.5).filter_spec(48_000),
+            "asetrate=72000,aresample=48000,atempo=0.666667",
+        );
+        assert_eq!(
+            PitchShift::new()
+                .with_pitch(1.5)
+                .with_tempo(1.5)
+                .filter_spec(48_000),
+            "asetrate=72000,aresample=48000",
+        );
+    }
+
+    #[test]
+    fn test_resample_chains_atempo() {
+        assert_eq!(
+            PitchShift::new().with_tempo(0.2).filter_spec(44_100),
+            "atempo=0.500000,atempo=0.500000,atempo=0.800000",
+        );
+    }
+
+    #[test]
+    fn test_rubberband() {
+        assert_eq!(
+            PitchShift::voice_anonymization()
+                .with_engine(PitchEngine::Rubberband)
+                .with_formant_preservation(true)
+                .filter_spec(48_000),
+            "rubberband=tempo=1.000000:pitch=0.793701:formant=preserved",
+        );
+    }
+}
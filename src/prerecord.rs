@@ -0,0 +1,252 @@
+//! Buffer of the most recent packets of a live source, for recordings that include the footage
+//! before an event.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use crate::memory::{MemoryCategory, MemoryReservation};
+use crate::packet::Packet;
+
+/// Keeps the last seconds of demuxed packets in memory, so that a clip written when an event
+/// triggers includes the footage before the trigger.
+///
+/// The buffer always starts at a keyframe of the video stream, so that the clip can be decoded
+/// from its first packet. To achieve that, it holds at least the configured duration and at most
+/// that duration plus one keyframe interval. Packets are accounted to [`MemoryCategory::Queued`].
+///
+/// # Example
+///
+/// ```ignore
+/// let mut reader = Reader::new(Url::parse("rtsp://camera/stream").unwrap()).unwrap();
+/// let video_stream_index = reader.best_video_stream_index().unwrap();
+/// let mut buffer = PreRecordBuffer::new(Duration::from_secs(10), video_stream_index);
+/// loop {
+///     let packet = reader.read_any().unwrap();
+///     if motion_detected() {
+///         let mut muxer = MuxerBuilder::new(Writer::new(Path::new("event.mp4")).unwrap())
+///             .with_streams(&reader)
+///             .unwrap()
+///             .build();
+///         for packet in buffer.drain() {
+///             muxer.mux(packet).unwrap();
+///         }
+///         // Continue muxing live packets into the clip...
+///     }
+///     buffer.push(packet);
+/// }
+/// ```
+pub struct PreRecordBuffer {
+    duration: Duration,
+    video_stream_index: usize,
+    packets: VecDeque<Packet>,
+    /// Sequence number of the first packet in `packets`.
+    first_sequence: u64,
+    /// Sequence number and time in seconds of the keyframes in the buffer, oldest first.
+    keyframes: VecDeque<(u64, f64)>,
+    memory: MemoryReservation,
+}
+
+impl PreRecordBuffer {
+    /// Create an empty buffer.
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - Minimum duration of footage to keep.
+    /// * `video_stream_index` - Index of the video stream whose keyframes the buffer starts at.
+    pub fn new(duration: Duration, video_stream_index: usize) -> Self {
+        Self {
+            duration,
+            video_stream_index,
+            packets: VecDeque::new(),
+            first_sequence: 0,
+            keyframes: VecDeque::new(),
+            memory: MemoryReservation::empty(MemoryCategory::Queued),
+        }
+    }
+
+    /// Add a packet, and drop the packets that are no longer needed to cover the duration.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet of any stream, in demuxing order.
+    pub fn push(&mut self, packet: Packet) {
+        let time = packet_time(&packet);
+        if packet.stream_index() == self.video_stream_index && packet.is_key() {
+            if let Some(time) = time {
+                let sequence = self.first_sequence + self.packets.len() as u64;
+                self.keyframes.push_back((sequence, time));
+            }
+        }
+        self.memory.grow(packet.size());
+        self.packets.push_back(packet);
+
+        let Some(newest) = time else {
+            return;
+        };
+        // Start at the latest keyframe that still leaves the full duration in the buffer.
+        let start = newest - self.duration.as_secs_f64();
+        while self
+            .keyframes
+            .get(1)
+            .is_some_and(|&(_, keyframe_time)| keyframe_time <= start)
+        {
+            self.keyframes.pop_front();
+        }
+        match self.keyframes.front() {
+            Some(&(sequence, _)) => self.drop_before(sequence),
+            // Without a keyframe, nothing in the buffer can be decoded yet.
+            None => self.drop_before(self.first_sequence + self.packets.len() as u64),
+        }
+    }
+
+    /// Take all packets out of the buffer, starting at a keyframe of the video stream.
+    pub fn drain(&mut self) -> Vec<Packet> {
+        self.first_sequence += self.packets.len() as u64;
+        self.keyframes.clear();
+        self.memory.shrink(self.memory.bytes());
+        self.packets.drain(..).collect()
+    }
+
+    /// Get the packets in the buffer without taking them out, for example to write a clip while
+    /// the buffer keeps running.
+    pub fn packets(&self) -> impl Iterator<Item = &Packet> {
+        self.packets.iter()
+    }
+
+    /// Number of packets in the buffer.
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    /// Whether the buffer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Duration of the footage in the buffer, from the first keyframe to the newest packet.
+    pub fn buffered_duration(&self) -> Duration {
+        let first = self.keyframes.front().map(|&(_, time)| time);
+        let last = self.packets.iter().rev().find_map(packet_time);
+        match (first, last) {
+            (Some(first), Some(last)) => Duration::from_secs_f64((last - first).max(0.0)),
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Approximate size in bytes of the packets in the buffer.
+    pub fn memory_usage(&self) -> usize {
+        self.memory.bytes()
+    }
+
+    /// Drop the packets before a sequence number.
+    ///
+    /// # Arguments
+    ///
+    /// * `sequence` - Sequence number of the first packet to keep.
+    fn drop_before(&mut self, sequence: u64) {
+        while self.first_sequence < sequence {
+            let Some(packet) = self.packets.pop_front() else {
+                break;
+            };
+            self.memory.shrink(packet.size());
+            self.first_sequence += 1;
+        }
+    }
+}
+
+/// Get the time of a packet in seconds: its decoding timestamp, or its presentation timestamp if
+/// it has no decoding timestamp.
+///
+/// # Arguments
+///
+/// * `packet` - Packet to get time of.
+fn packet_time(packet: &Packet) -> Option<f64> {
+    [packet.dts(), packet.pts()]
+        .into_iter()
+        .find(|time| time.has_value())
+        .map(|time| time.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ffmpeg::codec::packet::{Flags as AvPacketFlags, Packet as AvPacket};
+    use ffmpeg::Rational as AvRational;
+
+    const VIDEO: usize = 0;
+    const AUDIO: usize = 1;
+
+    /// Create a packet of 100 bytes with a timestamp in milliseconds.
+    fn packet(stream_index: usize, millis: i64, key: bool) -> Packet {
+        let mut inner = AvPacket::new(100);
+        inner.set_stream(stream_index);
+        inner.set_pts(Some(millis));
+        inner.set_dts(Some(millis));
+        if key {
+            inner.set_flags(AvPacketFlags::KEY);
+        }
+        Packet::new(inner, AvRational::new(1, 1000))
+    }
+
+    /// Push a video packet every 100 ms with a keyframe every second, each followed by an audio
+    /// packet, up to but not including `end` milliseconds.
+    fn push_stream(buffer: &mut PreRecordBuffer, start: i64, end: i64) {
+        for millis in (start..end).step_by(100) {
+            buffer.push(packet(VIDEO, millis, millis % 1000 == 0));
+            buffer.push(packet(AUDIO, millis, true));
+        }
+    }
+
+    #[test]
+    fn test_evicts_by_duration() {
+        let mut buffer = PreRecordBuffer::new(Duration::from_secs(2), VIDEO);
+        push_stream(&mut buffer, 0, 1500);
+        // Less than the duration is buffered, so nothing is evicted yet.
+        assert_eq!(buffer.len(), 30);
+
+        push_stream(&mut buffer, 1500, 5000);
+        // The newest packet is at 4.9s, so the buffer starts at the keyframe at 2s, the latest
+        // one that still covers two seconds.
+        assert_eq!(buffer.len(), 60);
+        assert_eq!(buffer.memory_usage(), 60 * 100);
+        let first = buffer.packets().next().unwrap();
+        assert_eq!(first.pts().as_secs_f64(), 2.0);
+        let buffered = buffer.buffered_duration().as_secs_f64();
+        assert!((2.0..3.0).contains(&buffered), "{buffered}");
+
+        // Pushing on keeps the window moving from keyframe to keyframe.
+        push_stream(&mut buffer, 5000, 6000);
+        let first = buffer.packets().next().unwrap();
+        assert_eq!(first.pts().as_secs_f64(), 3.0);
+        assert_eq!(buffer.len(), 60);
+    }
+
+    #[test]
+    fn test_starts_at_keyframe() {
+        let mut buffer = PreRecordBuffer::new(Duration::from_secs(2), VIDEO);
+        // Audio and video before the first keyframe cannot be used for a clip.
+        push_stream(&mut buffer, 500, 1000);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.buffered_duration(), Duration::ZERO);
+
+        push_stream(&mut buffer, 1000, 4500);
+        let first = buffer.packets().next().unwrap();
+        assert_eq!(first.stream_index(), VIDEO);
+        assert!(first.is_key());
+
+        let packets = buffer.drain();
+        assert_eq!(packets[0].stream_index(), VIDEO);
+        assert!(packets[0].is_key());
+        assert_eq!(packets[0].pts().as_secs_f64(), 2.0);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.memory_usage(), 0);
+
+        // After draining, the buffer waits for the next keyframe again.
+        push_stream(&mut buffer, 4500, 5000);
+        assert!(buffer.is_empty());
+        push_stream(&mut buffer, 5000, 5100);
+        assert!(buffer.packets().next().unwrap().is_key());
+        assert_eq!(buffer.len(), 2);
+    }
+}
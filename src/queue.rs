@@ -0,0 +1,439 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::ffi;
+use crate::frame::{RawAudioFrame, RawFrame};
+use crate::memory::{MemoryCategory, MemoryReservation};
+use crate::packet::Packet;
+use crate::time::Time;
+
+/// Item that can be queued in a [`FrameQueue`].
+pub trait QueueItem {
+    /// Whether or not the item is a keyframe. Keyframes are kept over other frames by
+    /// [`DropPolicy::DropNonKeyframe`].
+    fn is_keyframe(&self) -> bool;
+
+    /// Approximate size in bytes of the native memory held by the item, accounted to
+    /// [`MemoryCategory::Queued`] while the item is queued.
+    fn memory_size(&self) -> usize {
+        0
+    }
+}
+
+impl QueueItem for RawFrame {
+    fn is_keyframe(&self) -> bool {
+        self.is_key()
+    }
+
+    fn memory_size(&self) -> usize {
+        ffi::frame_buffer_size(self)
+    }
+}
+
+impl QueueItem for RawAudioFrame {
+    /// Audio frames do not depend on each other, and are all considered keyframes.
+    fn is_keyframe(&self) -> bool {
+        true
+    }
+
+    fn memory_size(&self) -> usize {
+        ffi::frame_buffer_size(self)
+    }
+}
+
+impl QueueItem for Packet {
+    fn is_keyframe(&self) -> bool {
+        self.is_key()
+    }
+
+    fn memory_size(&self) -> usize {
+        self.size()
+    }
+}
+
+impl<T: QueueItem> QueueItem for (Time, T) {
+    fn is_keyframe(&self) -> bool {
+        self.1.is_keyframe()
+    }
+
+    fn memory_size(&self) -> usize {
+        self.1.memory_size()
+    }
+}
+
+/// What a [`FrameQueue`] does when an item is pushed while the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropPolicy {
+    /// Drop the oldest item in the queue to make room.
+    DropOldest,
+    /// Drop the oldest item in the queue that is not a keyframe to make room. If all items are
+    /// keyframes, the oldest item is dropped.
+    DropNonKeyframe,
+    /// Block the producer until the consumer makes room.
+    Block,
+}
+
+/// Statistics of a [`FrameQueue`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameQueueStats {
+    /// Number of items pushed into the queue.
+    pub pushed: u64,
+    /// Number of items popped from the queue.
+    pub popped: u64,
+    /// Number of items dropped because the queue was full.
+    pub dropped: u64,
+    /// Largest number of items that were in the queue at once.
+    pub high_watermark: usize,
+    /// Total time producers spent blocked on a full queue.
+    pub blocked: Duration,
+    /// Approximate size in bytes of the native memory held by the items currently in the queue.
+    pub memory: usize,
+}
+
+/// Bounded queue of frames (or packets) between threads of a real-time pipeline, like between
+/// capture and encoding.
+///
+/// When the consumer cannot keep up, the [`DropPolicy`] decides what happens, so that hiccups of
+/// the consumer do not grow memory unboundedly. The queue is shared between threads by reference,
+/// for example through an [`std::sync::Arc`].
+///
+/// # Example
+///
+/// ```ignore
+/// let queue = Arc::new(FrameQueue::new(8, DropPolicy::DropOldest));
+///
+/// let producer = queue.clone();
+/// std::thread::spawn(move || {
+///     while let Ok(frame) = decoder.decode_raw() {
+///         producer.push(frame);
+///     }
+///     producer.close();
+/// });
+///
+/// while let Some(frame) = queue.pop() {
+///     encoder.encode_raw(&frame).unwrap();
+/// }
+/// println!("dropped {} frames", queue.stats().dropped);
+/// ```
+pub struct FrameQueue<T> {
+    capacity: usize,
+    policy: DropPolicy,
+    state: Mutex<FrameQueueState<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+struct FrameQueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+    stats: FrameQueueStats,
+    memory: MemoryReservation,
+}
+
+impl<T: QueueItem> FrameQueue<T> {
+    /// Create a new queue.
+    ///
+    /// # Arguments
+    ///
+    /// * `capacity` - Maximum number of items in the queue. A capacity of zero is raised to one.
+    /// * `policy` - What to do when an item is pushed while the queue is full.
+    pub fn new(capacity: usize, policy: DropPolicy) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            policy,
+            state: Mutex::new(FrameQueueState {
+                items: VecDeque::with_capacity(capacity),
+                closed: false,
+                stats: FrameQueueStats::default(),
+                memory: MemoryReservation::empty(MemoryCategory::Queued),
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        }
+    }
+
+    /// Maximum number of items in the queue.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Drop policy of the queue.
+    #[inline]
+    pub fn policy(&self) -> DropPolicy {
+        self.policy
+    }
+
+    /// Number of items currently in the queue.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    /// Whether or not the queue is currently empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Push an item into the queue. If the queue is full, the drop policy is applied.
+    ///
+    /// # Arguments
+    ///
+    /// * `item` - Item to push.
+    ///
+    /// # Return value
+    ///
+    /// `false` if the queue was closed, in which case the item is discarded.
+    pub fn push(&self, item: T) -> bool {
+        let mut state = self.state.lock().unwrap();
+        // A closed queue keeps its items for the consumer to drain, so never evict them.
+        if state.closed {
+            return false;
+        }
+        if state.items.len() >= self.capacity {
+            match self.policy {
+                DropPolicy::DropOldest => {
+                    if let Some(dropped) = state.items.pop_front() {
+                        state.memory.shrink(dropped.memory_size());
+                    }
+                    state.stats.dropped += 1;
+                }
+                DropPolicy::DropNonKeyframe => {
+                    let index = state
+                        .items
+                        .iter()
+                        .position(|item| !item.is_keyframe())
+                        .unwrap_or(0);
+                    if let Some(dropped) = state.items.remove(index) {
+                        state.memory.shrink(dropped.memory_size());
+                    }
+                    state.stats.dropped += 1;
+                }
+                DropPolicy::Block => {
+                    let blocked_since = Instant::now();
+                    while state.items.len() >= self.capacity && !state.closed {
+                        state = self.not_full.wait(state).unwrap();
+                    }
+                    state.stats.blocked += blocked_since.elapsed();
+                }
+            }
+        }
+        if state.closed {
+            return false;
+        }
+        state.memory.grow(item.memory_size());
+        state.items.push_back(item);
+        state.stats.pushed += 1;
+        state.stats.high_watermark = state.stats.high_watermark.max(state.items.len());
+        self.not_empty.notify_one();
+        true
+    }
+
+    /// Pop the oldest item from the queue, and block until one is available.
+    ///
+    /// # Return value
+    ///
+    /// The item, or `None` if the queue was closed and is empty.
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        while state.items.is_empty() && !state.closed {
+            state = self.not_empty.wait(state).unwrap();
+        }
+        self.pop_locked(&mut state)
+    }
+
+    /// Pop the oldest item from the queue, and block until one is available or the timeout
+    /// expires.
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Maximum time to wait for an item.
+    ///
+    /// # Return value
+    ///
+    /// The item, or `None` if the timeout expired or the queue was closed and is empty.
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        let (mut state, _) = self
+            .not_empty
+            .wait_timeout_while(state, timeout, |state| {
+                state.items.is_empty() && !state.closed
+            })
+            .unwrap();
+        self.pop_locked(&mut state)
+    }
+
+    /// Pop up to `max` of the oldest items from the queue at once, and block until at least one is
+    /// available. Taking the lock once per batch keeps the overhead low for consumers that work in
+    /// batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `max` - Maximum number of items to pop.
+    ///
+    /// # Return value
+    ///
+    /// The items in queue order. Empty if the queue was closed and is empty, or if `max` is zero.
+    pub fn pop_many(&self, max: usize) -> Vec<T> {
+        if max == 0 {
+            return Vec::new();
+        }
+        let mut state = self.state.lock().unwrap();
+        while state.items.is_empty() && !state.closed {
+            state = self.not_empty.wait(state).unwrap();
+        }
+        let count = max.min(state.items.len());
+        let items: Vec<T> = state.items.drain(..count).collect();
+        let memory_size = items.iter().map(QueueItem::memory_size).sum();
+        state.memory.shrink(memory_size);
+        state.stats.popped += count as u64;
+        self.not_full.notify_all();
+        items
+    }
+
+    /// Pop the oldest item from the queue without blocking.
+    ///
+    /// # Return value
+    ///
+    /// The item, or `None` if the queue is empty.
+    pub fn try_pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        self.pop_locked(&mut state)
+    }
+
+    /// Close the queue. Producers that push after closing have their items discarded, and
+    /// consumers drain the remaining items after which they receive `None`.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    /// Whether or not the queue was closed.
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    /// Get the statistics of the queue.
+    pub fn stats(&self) -> FrameQueueStats {
+        let state = self.state.lock().unwrap();
+        FrameQueueStats {
+            memory: state.memory.bytes(),
+            ..state.stats
+        }
+    }
+
+    fn pop_locked(&self, state: &mut FrameQueueState<T>) -> Option<T> {
+        let item = state.items.pop_front()?;
+        state.memory.shrink(item.memory_size());
+        state.stats.popped += 1;
+        self.not_full.notify_one();
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Item(u32, bool);
+
+    impl QueueItem for Item {
+        fn is_keyframe(&self) -> bool {
+            self.1
+        }
+    }
+
+    #[test]
+    fn test_drop_oldest() {
+        let queue = FrameQueue::new(2, DropPolicy::DropOldest);
+        assert!(queue.push(Item(0, true)));
+        assert!(queue.push(Item(1, false)));
+        assert!(queue.push(Item(2, false)));
+        assert_eq!(queue.try_pop(), Some(Item(1, false)));
+        assert_eq!(queue.try_pop(), Some(Item(2, false)));
+        assert_eq!(queue.try_pop(), None);
+        let stats = queue.stats();
+        assert_eq!(stats.pushed, 3);
+        assert_eq!(stats.popped, 2);
+        assert_eq!(stats.dropped, 1);
+        assert_eq!(stats.high_watermark, 2);
+    }
+
+    #[test]
+    fn test_drop_non_keyframe() {
+        let queue = FrameQueue::new(3, DropPolicy::DropNonKeyframe);
+        queue.push(Item(0, true));
+        queue.push(Item(1, false));
+        queue.push(Item(2, true));
+        queue.push(Item(3, false));
+        assert_eq!(queue.try_pop(), Some(Item(0, true)));
+        assert_eq!(queue.try_pop(), Some(Item(2, true)));
+        assert_eq!(queue.try_pop(), Some(Item(3, false)));
+    }
+
+    #[test]
+    fn test_drop_non_keyframe_all_keyframes() {
+        let queue = FrameQueue::new(2, DropPolicy::DropNonKeyframe);
+        queue.push(Item(0, true));
+        queue.push(Item(1, true));
+        queue.push(Item(2, true));
+        assert_eq!(queue.try_pop(), Some(Item(1, true)));
+        assert_eq!(queue.stats().dropped, 1);
+    }
+
+    #[test]
+    fn test_block() {
+        let queue = Arc::new(FrameQueue::new(1, DropPolicy::Block));
+        queue.push(Item(0, true));
+        let producer = queue.clone();
+        let handle = std::thread::spawn(move || producer.push(Item(1, true)));
+        assert_eq!(queue.pop(), Some(Item(0, true)));
+        assert!(handle.join().unwrap());
+        assert_eq!(queue.pop(), Some(Item(1, true)));
+        assert_eq!(queue.stats().dropped, 0);
+    }
+
+    #[test]
+    fn test_close() {
+        let queue = FrameQueue::new(2, DropPolicy::Block);
+        queue.push(Item(0, true));
+        queue.close();
+        assert!(!queue.push(Item(1, true)));
+        assert_eq!(queue.pop(), Some(Item(0, true)));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.pop_timeout(Duration::from_millis(1)), None);
+    }
+
+    #[test]
+    fn test_close_full_keeps_items() {
+        for policy in [DropPolicy::DropOldest, DropPolicy::DropNonKeyframe] {
+            let queue = FrameQueue::new(2, policy);
+            queue.push(Item(0, false));
+            queue.push(Item(1, false));
+            queue.close();
+            assert!(!queue.push(Item(2, false)));
+            assert_eq!(queue.pop(), Some(Item(0, false)));
+            assert_eq!(queue.pop(), Some(Item(1, false)));
+            assert_eq!(queue.pop(), None);
+            assert_eq!(queue.stats().dropped, 0);
+        }
+    }
+
+    #[test]
+    fn test_pop_many() {
+        let queue = FrameQueue::new(4, DropPolicy::Block);
+        for index in 0..3 {
+            queue.push(Item(index, true));
+        }
+        assert_eq!(queue.pop_many(2), vec![Item(0, true), Item(1, true)]);
+        assert_eq!(queue.pop_many(2), vec![Item(2, true)]);
+        queue.close();
+        assert!(queue.pop_many(2).is_empty());
+        assert_eq!(queue.stats().popped, 3);
+    }
+}
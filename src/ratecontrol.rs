@@ -0,0 +1,593 @@
+//! Rate control of video encoders: constant quality, constant and variable bitrate, and two-pass
+//! encoding, mapped to the options of the encoder that is used.
+
+use std::path::{Path, PathBuf};
+
+use ffmpeg::codec::codec::Codec as AvCodec;
+
+use crate::error::Error;
+use crate::ffi;
+use crate::options::Options;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// How an encoder spends bits. Set with
+/// [`Settings::with_rc_mode`](crate::encode::Settings::with_rc_mode), and the target bitrate with
+/// [`Settings::with_bitrate`](crate::encode::Settings::with_bitrate).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RateControl {
+    /// Constant quality, with the bitrate following the complexity of the content. Lower values
+    /// are better quality: 0 is lossless for x264 and x265, and 23 is their default. Encoders
+    /// without a CRF mode use the value as quantizer scale.
+    Crf(u8),
+    /// Constant bitrate at the target bitrate, for live streaming over links of fixed capacity.
+    Cbr,
+    /// Variable bitrate that averages the target bitrate, capped by the maximum bitrate if one is
+    /// set.
+    Vbr,
+    /// Variable bitrate in two passes over the same frames: the first pass analyzes the content
+    /// and writes statistics to `stats_file`, and the second pass uses them to distribute the
+    /// target bitrate over the content. Select the pass with
+    /// [`Settings::with_pass`](crate::encode::Settings::with_pass).
+    ///
+    /// NVENC encoders do both passes internally in a single run, so they need no first pass.
+    /// QSV encoders do not support two passes and encode with [`RateControl::Vbr`] instead. See
+    /// [`Settings::requires_first_pass`](crate::encode::Settings::requires_first_pass).
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let settings = Settings::preset_h264_yuv420p(1280, 720, false)
+    ///     .with_bitrate(2_000_000)
+    ///     .with_rc_mode(RateControl::TwoPass {
+    ///         stats_file: PathBuf::from("pass.log"),
+    ///     });
+    /// if settings.requires_first_pass() {
+    ///     // The output of the first pass is not needed.
+    ///     let first_pass = settings.clone().with_pass(EncodePass::First);
+    ///     let mut encoder = EncoderBuilder::new(Path::new("/dev/null"), first_pass)
+    ///         .with_format("null")
+    ///         .build()?;
+    ///     for (frame, time) in frames() {
+    ///         encoder.encode(&frame, time)?;
+    ///     }
+    ///     encoder.finish()?;
+    /// }
+    /// let second_pass = settings.with_pass(EncodePass::Second);
+    /// let mut encoder = Encoder::new(Path::new("output.mp4"), second_pass)?;
+    /// for (frame, time) in frames() {
+    ///     encoder.encode(&frame, time)?;
+    /// }
+    /// encoder.finish()?;
+    /// ```
+    TwoPass {
+        /// File the first pass writes statistics to and the second pass reads them from.
+        stats_file: PathBuf,
+    },
+}
+
+/// Pass of a [two-pass](RateControl::TwoPass) encode.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum EncodePass {
+    /// Analyze the content and write statistics.
+    First,
+    /// Encode using the statistics of the first pass.
+    Second,
+}
+
+impl EncodePass {
+    /// Number of the pass, as encoders take it.
+    fn number(self) -> u8 {
+        match self {
+            EncodePass::First => 1,
+            EncodePass::Second => 2,
+        }
+    }
+}
+
+/// Encoders that take rate control options differently.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum EncoderFamily {
+    X264,
+    X265,
+    Nvenc,
+    Qsv,
+    /// Other encoders, which take the generic options of libavcodec.
+    Other {
+        /// Whether the encoder has a `crf` option, like `libvpx-vp9` and `libaom-av1`.
+        crf: bool,
+    },
+}
+
+impl EncoderFamily {
+    /// Get the family of an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `codec` - Encoder.
+    pub(crate) fn of(codec: &AvCodec) -> Self {
+        match codec.name() {
+            "libx264" | "libx264rgb" => EncoderFamily::X264,
+            "libx265" => EncoderFamily::X265,
+            name if name.ends_with("_nvenc") => EncoderFamily::Nvenc,
+            name if name.ends_with("_qsv") => EncoderFamily::Qsv,
+            _ => EncoderFamily::Other {
+                crf: ffi::codec_has_private_option(codec, "crf"),
+            },
+        }
+    }
+
+    /// Translate the `libx264` options of the H264 presets (like
+    /// [`Options::preset_h264_realtime`]) and of callers that set `preset`, `tune` or `crf`
+    /// themselves into the options of this encoder family. Options the encoder does not have are
+    /// removed, so that hardware encoders open with the presets.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to translate.
+    /// * `has_option` - Whether the encoder has a private option.
+    pub(crate) fn translate_x264_options(
+        self,
+        options: &mut Options,
+        has_option: impl Fn(&str) -> bool,
+    ) {
+        let tune = options.get("tune").map(str::to_string);
+        let zerolatency = tune.as_deref() == Some("zerolatency");
+        match self {
+            EncoderFamily::X264 => return,
+            EncoderFamily::X265 => {
+                if tune.is_some_and(|tune| !Self::X265_TUNES.contains(&tune.as_str())) {
+                    options.remove("tune");
+                }
+                return;
+            }
+            EncoderFamily::Nvenc => {
+                Self::rename_value(options, "preset", &Self::NVENC_PRESETS);
+                match tune.as_deref() {
+                    Some("zerolatency") => options.set("tune", "ull"),
+                    Some(tune) if !Self::NVENC_TUNES.contains(&tune) => options.remove("tune"),
+                    _ => {}
+                }
+                Self::rename_key(options, "crf", "cq");
+            }
+            EncoderFamily::Qsv => {
+                Self::rename_value(options, "preset", &Self::QSV_PRESETS);
+                options.remove("tune");
+                if zerolatency && options.get("async_depth").is_none() {
+                    options.set("async_depth", "1");
+                }
+                Self::rename_key(options, "crf", "global_quality");
+            }
+            EncoderFamily::Other { .. } => {}
+        }
+        for key in ["preset", "tune", "crf"] {
+            if options.get(key).is_some() && !has_option(key) {
+                options.remove(key);
+            }
+        }
+        // Encoders without a low latency tune still delay frames for B-frames.
+        if zerolatency && options.get("tune").is_none() && options.get("bf").is_none() {
+            options.set("bf", "0");
+        }
+    }
+
+    /// Tunes of `libx265`.
+    const X265_TUNES: [&'static str; 6] = [
+        "psnr",
+        "ssim",
+        "grain",
+        "zerolatency",
+        "fastdecode",
+        "animation",
+    ];
+
+    /// Tunes of NVENC.
+    const NVENC_TUNES: [&'static str; 4] = ["hq", "ll", "ull", "lossless"];
+
+    /// NVENC presets of the `libx264` presets, from fastest to slowest.
+    const NVENC_PRESETS: [(&'static str, &'static str); 10] = [
+        ("ultrafast", "p1"),
+        ("superfast", "p1"),
+        ("veryfast", "p2"),
+        ("faster", "p2"),
+        ("fast", "p3"),
+        ("medium", "p4"),
+        ("slow", "p5"),
+        ("slower", "p6"),
+        ("veryslow", "p7"),
+        ("placebo", "p7"),
+    ];
+
+    /// QSV presets of the `libx264` presets that QSV does not have. The others have the same name.
+    const QSV_PRESETS: [(&'static str, &'static str); 3] = [
+        ("ultrafast", "veryfast"),
+        ("superfast", "veryfast"),
+        ("placebo", "veryslow"),
+    ];
+
+    /// Replace the value of an option by the value it maps to, if any.
+    fn rename_value(options: &mut Options, key: &str, values: &[(&str, &str)]) {
+        let renamed = options
+            .get(key)
+            .and_then(|value| values.iter().find(|(from, _)| *from == value))
+            .map(|(_, to)| *to);
+        if let Some(renamed) = renamed {
+            options.set(key, renamed);
+        }
+    }
+
+    /// Move the value of an option to another option, unless the other option is set.
+    fn rename_key(options: &mut Options, from: &str, to: &str) {
+        if let Some(value) = options.get(from).map(str::to_string) {
+            options.remove(from);
+            if options.get(to).is_none() {
+                options.set(to, &value);
+            }
+        }
+    }
+}
+
+/// Rate control part of the encoder settings.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct RateControlSettings {
+    pub(crate) mode: Option<RateControl>,
+    /// Target bitrate in bits per second.
+    pub(crate) bitrate: Option<u64>,
+    /// Maximum bitrate in bits per second.
+    pub(crate) max_bitrate: Option<u64>,
+    pub(crate) pass: Option<EncodePass>,
+}
+
+impl RateControlSettings {
+    /// Options that select constant quality, which would override a target bitrate.
+    const QUALITY_OPTIONS: [&'static str; 4] = ["crf", "qp", "cq", "global_quality"];
+
+    /// Options whose values are lists that are extended instead of overwritten.
+    const LIST_OPTIONS: [(&'static str, &'static str); 2] = [("flags", ""), ("x265-params", ":")];
+
+    /// Get the rate control mode, which defaults to variable bitrate when only a target bitrate
+    /// is set.
+    fn mode(&self) -> Option<RateControl> {
+        self.mode
+            .clone()
+            .or_else(|| self.bitrate.map(|_| RateControl::Vbr))
+    }
+
+    /// Whether the mode needs a first pass before the encode. See
+    /// [`RateControl::TwoPass`].
+    ///
+    /// # Arguments
+    ///
+    /// * `family` - Family of the encoder.
+    pub(crate) fn requires_first_pass(&self, family: EncoderFamily) -> bool {
+        matches!(self.mode(), Some(RateControl::TwoPass { .. }))
+            && !matches!(family, EncoderFamily::Nvenc | EncoderFamily::Qsv)
+    }
+
+    /// Get the statistics file that the encoder wrapper does not read or write itself, and must be
+    /// passed to and from the encoder in the given pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `family` - Family of the encoder.
+    pub(crate) fn stats_file(&self, family: EncoderFamily) -> Option<(&Path, EncodePass)> {
+        match (self.mode.as_ref(), self.pass, family) {
+            (
+                Some(RateControl::TwoPass { stats_file }),
+                Some(pass),
+                EncoderFamily::Other { .. },
+            ) => Some((stats_file.as_path(), pass)),
+            _ => None,
+        }
+    }
+
+    /// Set the options of the rate control mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `family` - Family of the encoder.
+    /// * `options` - Encoder options to add to.
+    ///
+    /// # Return value
+    ///
+    /// [`Error::InvalidRateControl`] if a bitrate mode has no target bitrate, or a two-pass mode
+    /// has no pass.
+    pub(crate) fn apply_to(&self, family: EncoderFamily, options: &mut Options) -> Result<()> {
+        if !matches!(self.mode(), None | Some(RateControl::Crf(_))) {
+            for key in Self::QUALITY_OPTIONS {
+                options.remove(key);
+            }
+        }
+        for (key, value) in self.to_pairs(family)? {
+            let separator = Self::LIST_OPTIONS
+                .iter()
+                .find(|(list_key, _)| *list_key == key)
+                .map(|(_, separator)| *separator);
+            let previous = options.get(key).map(str::to_string);
+            match (separator, previous) {
+                (Some(separator), Some(previous)) => {
+                    let value = format!("{previous}{separator}{value}");
+                    options.set(key, &value);
+                }
+                _ => options.set(key, &value),
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the options of the rate control mode as key-value pairs.
+    ///
+    /// # Arguments
+    ///
+    /// * `family` - Family of the encoder.
+    fn to_pairs(&self, family: EncoderFamily) -> Result<Vec<(&'static str, String)>> {
+        let mut pairs = Vec::new();
+        let Some(mode) = self.mode() else {
+            if let Some(max_bitrate) = self.max_bitrate {
+                pairs.push(("maxrate", max_bitrate.to_string()));
+                pairs.push(("bufsize", (2 * max_bitrate).to_string()));
+            }
+            return Ok(pairs);
+        };
+        let bitrate = match mode {
+            RateControl::Crf(_) => None,
+            _ => Some(self.bitrate.ok_or(Error::InvalidRateControl)?),
+        };
+        let max_bitrate = match mode {
+            RateControl::Cbr => bitrate,
+            _ => self.max_bitrate,
+        };
+
+        match (&mode, family) {
+            (RateControl::Crf(crf), EncoderFamily::Nvenc) => {
+                pairs.push(("rc", "vbr".to_string()));
+                pairs.push(("cq", crf.to_string()));
+                pairs.push(("b", "0".to_string()));
+            }
+            (RateControl::Crf(crf), EncoderFamily::Qsv) => {
+                pairs.push(("global_quality", crf.to_string()));
+            }
+            (RateControl::Crf(crf), EncoderFamily::Other { crf: false }) => {
+                pairs.push(("flags", "+qscale".to_string()));
+                pairs.push((
+                    "global_quality",
+                    (u32::from(*crf) * ffmpeg::ffi::FF_QP2LAMBDA as u32).to_string(),
+                ));
+            }
+            (RateControl::Crf(crf), EncoderFamily::Other { crf: true }) => {
+                pairs.push(("crf", crf.to_string()));
+                // Without a target bitrate, libvpx encodes with constant quality.
+                pairs.push(("b", "0".to_string()));
+            }
+            (RateControl::Crf(crf), _) => pairs.push(("crf", crf.to_string())),
+            (_, EncoderFamily::Nvenc) => {
+                let rc = if mode == RateControl::Cbr {
+                    "cbr"
+                } else {
+                    "vbr"
+                };
+                pairs.push(("rc", rc.to_string()));
+            }
+            _ => {}
+        }
+        if let Some(bitrate) = bitrate {
+            pairs.push(("b", bitrate.to_string()));
+            if mode == RateControl::Cbr && matches!(family, EncoderFamily::Other { .. }) {
+                pairs.push(("minrate", bitrate.to_string()));
+            }
+        }
+        if let Some(max_bitrate) = max_bitrate {
+            let buffer_size = match mode {
+                // A buffer of a second keeps the bitrate constant over short periods.
+                RateControl::Cbr => max_bitrate,
+                _ => 2 * max_bitrate,
+            };
+            pairs.push(("maxrate", max_bitrate.to_string()));
+            pairs.push(("bufsize", buffer_size.to_string()));
+        }
+
+        match (&mode, family) {
+            (RateControl::Cbr, EncoderFamily::X264) => pairs.push(("nal-hrd", "cbr".to_string())),
+            (RateControl::Cbr, EncoderFamily::X265) => {
+                pairs.push(("x265-params", "strict-cbr=1".to_string()))
+            }
+            (RateControl::TwoPass { .. }, EncoderFamily::Nvenc) => {
+                pairs.push(("multipass", "fullres".to_string()))
+            }
+            (RateControl::TwoPass { .. }, EncoderFamily::Qsv) => {
+                tracing::warn!("QSV encoders do not support two passes, encoding with VBR");
+            }
+            (RateControl::TwoPass { stats_file }, family) => {
+                let pass = self.pass.ok_or(Error::InvalidRateControl)?;
+                match family {
+                    EncoderFamily::X265 => pairs.push((
+                        "x265-params",
+                        format!("pass={}:stats={}", pass.number(), stats_file.display()),
+                    )),
+                    _ => {
+                        if family == EncoderFamily::X264 {
+                            pairs.push(("stats", stats_file.display().to_string()));
+                        }
+                        pairs.push(("flags", format!("+pass{}", pass.number())));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(pairs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(mode: RateControl, bitrate: Option<u64>) -> RateControlSettings {
+        RateControlSettings {
+            mode: Some(mode),
+            bitrate,
+            ..RateControlSettings::default()
+        }
+    }
+
+    #[test]
+    fn test_crf() {
+        let crf = settings(RateControl::Crf(20), None);
+        assert_eq!(
+            crf.to_pairs(EncoderFamily::X264).unwrap(),
+            vec![("crf", "20".to_string())],
+        );
+        assert_eq!(
+            crf.to_pairs(EncoderFamily::Nvenc).unwrap(),
+            vec![
+                ("rc", "vbr".to_string()),
+                ("cq", "20".to_string()),
+                ("b", "0".to_string()),
+            ],
+        );
+        assert_eq!(
+            crf.to_pairs(EncoderFamily::Other { crf: false }).unwrap(),
+            vec![
+                ("flags", "+qscale".to_string()),
+                ("global_quality", "2360".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_translate_x264_options() {
+        let translate = |family: EncoderFamily, options: &Options, supported: &[&str]| {
+            let mut options = options.clone();
+            family.translate_x264_options(&mut options, |key| supported.contains(&key));
+            let mut pairs = options
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<Vec<_>>();
+            pairs.sort();
+            pairs
+        };
+        let pairs = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let mut realtime = Options::preset_h264_realtime();
+        realtime.set("crf", "23");
+
+        assert_eq!(
+            translate(EncoderFamily::X264, &realtime, &[]),
+            pairs(&[("crf", "23"), ("preset", "medium"), ("tune", "zerolatency")]),
+        );
+        assert_eq!(
+            translate(EncoderFamily::Nvenc, &realtime, &["preset", "tune"]),
+            pairs(&[("cq", "23"), ("preset", "p4"), ("tune", "ull")]),
+        );
+        assert_eq!(
+            translate(EncoderFamily::Qsv, &realtime, &["preset"]),
+            pairs(&[
+                ("async_depth", "1"),
+                ("bf", "0"),
+                ("global_quality", "23"),
+                ("preset", "medium"),
+            ]),
+        );
+        // VAAPI encoders have none of the options.
+        assert_eq!(
+            translate(EncoderFamily::Other { crf: false }, &realtime, &[]),
+            pairs(&[("bf", "0")]),
+        );
+        assert_eq!(
+            translate(
+                EncoderFamily::X265,
+                &Options::preset_h264_still_image(),
+                &[]
+            ),
+            pairs(&[("preset", "medium")]),
+        );
+    }
+
+    #[test]
+    fn test_cbr() {
+        let cbr = settings(RateControl::Cbr, Some(4_000_000));
+        assert_eq!(
+            cbr.to_pairs(EncoderFamily::X264).unwrap(),
+            vec![
+                ("b", "4000000".to_string()),
+                ("maxrate", "4000000".to_string()),
+                ("bufsize", "4000000".to_string()),
+                ("nal-hrd", "cbr".to_string()),
+            ],
+        );
+        assert!(matches!(
+            settings(RateControl::Cbr, None).to_pairs(EncoderFamily::X264),
+            Err(Error::InvalidRateControl),
+        ));
+    }
+
+    #[test]
+    fn test_bitrate_defaults_to_vbr() {
+        let vbr = RateControlSettings {
+            bitrate: Some(1_000_000),
+            max_bitrate: Some(1_500_000),
+            ..RateControlSettings::default()
+        };
+        assert_eq!(
+            vbr.to_pairs(EncoderFamily::Nvenc).unwrap(),
+            vec![
+                ("rc", "vbr".to_string()),
+                ("b", "1000000".to_string()),
+                ("maxrate", "1500000".to_string()),
+                ("bufsize", "3000000".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_two_pass() {
+        let mut two_pass = settings(
+            RateControl::TwoPass {
+                stats_file: PathBuf::from("pass.log"),
+            },
+            Some(2_000_000),
+        );
+        assert!(two_pass.to_pairs(EncoderFamily::X264).is_err());
+        assert!(two_pass.requires_first_pass(EncoderFamily::X264));
+        assert!(!two_pass.requires_first_pass(EncoderFamily::Nvenc));
+        two_pass.pass = Some(EncodePass::Second);
+        assert_eq!(
+            two_pass.to_pairs(EncoderFamily::X265).unwrap(),
+            vec![
+                ("b", "2000000".to_string()),
+                ("x265-params", "pass=2:stats=pass.log".to_string()),
+            ],
+        );
+        assert_eq!(two_pass.stats_file(EncoderFamily::X264), None);
+        assert_eq!(
+            two_pass.stats_file(EncoderFamily::Other { crf: true }),
+            Some((Path::new("pass.log"), EncodePass::Second)),
+        );
+    }
+
+    #[test]
+    fn test_apply_extends_lists_and_removes_quality() {
+        let mut options = Options::default();
+        options.set("crf", "18");
+        options.set("flags", "+qscale");
+        let mut two_pass = settings(
+            RateControl::TwoPass {
+                stats_file: PathBuf::from("pass.log"),
+            },
+            Some(2_000_000),
+        );
+        two_pass.pass = Some(EncodePass::First);
+        two_pass
+            .apply_to(EncoderFamily::Other { crf: true }, &mut options)
+            .unwrap();
+        assert_eq!(options.get("crf"), None);
+        assert_eq!(options.get("flags"), Some("+qscale+pass1"));
+        assert_eq!(options.get("b"), Some("2000000"));
+    }
+}
@@ -0,0 +1,100 @@
+//! Record the packets of a source, usually a live stream, to a file without re-encoding.
+
+use crate::error::Error;
+use crate::io::{Reader, WriteOutcome, Writer};
+use crate::mux::{Muxer, MuxerBuilder};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Builds a [`Recorder`].
+///
+/// # Example
+///
+/// ```ignore
+/// let reader = Reader::new(Url::parse("rtsp://camera/stream").unwrap()).unwrap();
+/// let writer = Writer::new(Path::new("recording.mp4")).unwrap();
+/// let mut recorder = RecorderBuilder::new(reader, writer)
+///     .clean_start(true)
+///     .build()
+///     .unwrap();
+/// while recorder.record().unwrap() {}
+/// recorder.finish().unwrap();
+/// ```
+pub struct RecorderBuilder {
+    reader: Reader,
+    writer: Writer,
+    clean_start: bool,
+}
+
+impl RecorderBuilder {
+    /// Create a recorder builder that records all streams of `reader` to `writer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Source to record.
+    /// * `writer` - Destination of the recording.
+    pub fn new(reader: Reader, writer: Writer) -> Self {
+        Self {
+            reader,
+            writer,
+            clean_start: false,
+        }
+    }
+
+    /// Start the recording cleanly when the source is joined mid-stream: video is dropped until
+    /// the first keyframe and audio is trimmed to the sample at that keyframe. Without it, the
+    /// recording usually starts with garbage until the first keyframe. See
+    /// [`MuxerBuilder::clean_start`].
+    ///
+    /// # Arguments
+    ///
+    /// * `clean_start` - Whether to start the recording cleanly.
+    pub fn clean_start(mut self, clean_start: bool) -> Self {
+        self.clean_start = clean_start;
+        self
+    }
+
+    /// Build [`Recorder`].
+    pub fn build(self) -> Result<Recorder> {
+        let mut muxer = MuxerBuilder::new(self.writer).with_streams(&self.reader)?;
+        if self.clean_start {
+            muxer = muxer.clean_start();
+        }
+        Ok(Recorder {
+            reader: self.reader,
+            muxer: muxer.build(),
+        })
+    }
+}
+
+/// Records the packets of a [`Reader`] to a [`Writer`].
+pub struct Recorder {
+    reader: Reader,
+    muxer: Muxer<Writer>,
+}
+
+impl Recorder {
+    /// Record the next packet of the source.
+    ///
+    /// # Return value
+    ///
+    /// `false` if the source is exhausted or a limit of the writer was reached, `true` otherwise.
+    pub fn record(&mut self) -> Result<bool> {
+        let packet = match self.reader.read_any() {
+            Ok(packet) => packet,
+            Err(Error::ReadExhausted) => return Ok(false),
+            Err(err) => return Err(err),
+        };
+        Ok(matches!(self.muxer.mux(packet)?, WriteOutcome::Written))
+    }
+
+    /// Get the reader of the source.
+    pub fn reader(&self) -> &Reader {
+        &self.reader
+    }
+
+    /// Finish the recording. This writes the trailer of the container.
+    pub fn finish(&mut self) -> Result<()> {
+        self.muxer.finish().map(|_| ())
+    }
+}
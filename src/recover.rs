@@ -0,0 +1,203 @@
+//! Salvaging of damaged recordings, like the files of a dashcam that lost power or of a screen
+//! recorder that crashed.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ffmpeg::codec::Id as AvCodecId;
+
+use crate::error::Error;
+use crate::io::{DurationEstimation, ReadRecovery, ReaderBuilder, SupportLevel, WriterBuilder};
+use crate::location::Location;
+use crate::mux::MuxerBuilder;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Outcome of [`salvage`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// Number of streams that were copied.
+    pub streams: usize,
+    /// Number of packets that were copied.
+    pub packets: u64,
+    /// Number of packets that were dropped, because they had no timestamp, went back in time or
+    /// could not be written.
+    pub dropped: u64,
+    /// Duration of the longest recovered stream.
+    pub duration: Duration,
+}
+
+/// Copy whatever packets can be read from a damaged recording into a new file, to make it
+/// playable again.
+///
+/// The input is read with all of the error tolerance of [`ReadRecovery::tolerant`]. Packets are
+/// copied without decoding them, so salvaging is fast and does not lose quality. Packets that
+/// would make the output unplayable (without a timestamp, or with a timestamp that goes back in
+/// time) are dropped, and reading stops at the first error that cannot be skipped, with the
+/// packets up to it kept. Streams the output format cannot store are left out.
+///
+/// The input must still be recognizable: an MP4 file that was cut off before its index (the
+/// `moov` box) was written holds no description of its streams, and cannot be opened. Recorders
+/// that write fragmented MP4, Matroska or MPEG-TS do not have this problem.
+///
+/// # Arguments
+///
+/// * `input` - Damaged recording.
+/// * `output` - File to write the salvaged packets to. The format is derived from the extension.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = salvage(Path::new("crashed.mkv"), Path::new("recovered.mkv"))?;
+/// println!(
+///     "recovered {:.1}s, dropped {} packets",
+///     report.duration.as_secs_f64(),
+///     report.dropped,
+/// );
+/// ```
+pub fn salvage(input: impl Into<Location>, output: impl Into<Location>) -> Result<SalvageReport> {
+    let mut reader = ReaderBuilder::new(input)
+        .with_recovery(ReadRecovery {
+            // The duration is measured while copying, which saves reading the input twice.
+            duration_estimation: DurationEstimation::Container,
+            ..ReadRecovery::tolerant()
+        })
+        .build()?;
+    let writer = WriterBuilder::new(output).build()?;
+
+    let stream_indices = reader
+        .input
+        .streams()
+        .filter(|stream| {
+            let codec_id = stream.parameters().id();
+            let supported = codec_id != AvCodecId::None
+                && writer.supports_codec(codec_id) != SupportLevel::Unsupported;
+            if !supported {
+                tracing::warn!(
+                    "leaving out stream {} with codec {codec_id:?}",
+                    stream.index()
+                );
+            }
+            supported
+        })
+        .map(|stream| stream.index())
+        .collect::<Vec<_>>();
+    let mut muxer = MuxerBuilder::new(writer);
+    let mut timelines = HashMap::new();
+    for stream_index in stream_indices {
+        muxer = muxer.with_stream(reader.stream_info(stream_index)?)?;
+        timelines.insert(stream_index, Timeline::default());
+    }
+    if timelines.is_empty() {
+        return Err(Error::UnsupportedCodec);
+    }
+    let mut muxer = muxer.interleaved().build();
+    // Write the header up front, so that an output that cannot be written fails right away.
+    muxer.write_header()?;
+
+    let mut report = SalvageReport {
+        streams: timelines.len(),
+        ..SalvageReport::default()
+    };
+    loop {
+        let packet = match reader.read_any() {
+            Ok(packet) => packet,
+            Err(Error::ReadExhausted) => break,
+            Err(err) => {
+                tracing::warn!("stopping at unreadable data: {err}");
+                break;
+            }
+        };
+        let Some(timeline) = timelines.get_mut(&packet.stream_index()) else {
+            continue;
+        };
+        let dts = match (packet.dts().has_value(), packet.pts().has_value()) {
+            (true, _) => packet.dts(),
+            (false, true) => packet.pts(),
+            (false, false) => {
+                report.dropped += 1;
+                continue;
+            }
+        };
+        let start = packet.pts().has_value().then(|| packet.pts().as_secs_f64());
+        let duration = packet.duration().as_secs_f64().max(0.0);
+        if !timeline.accept(dts.as_secs_f64(), start, duration) {
+            report.dropped += 1;
+            continue;
+        }
+        match muxer.mux(packet) {
+            Ok(_) => report.packets += 1,
+            Err(err) => {
+                tracing::warn!("dropping packet that cannot be written: {err}");
+                report.dropped += 1;
+            }
+        }
+    }
+    muxer.finish()?;
+
+    report.duration = timelines
+        .values()
+        .map(Timeline::duration)
+        .fold(Duration::ZERO, Duration::max);
+    Ok(report)
+}
+
+/// Timestamps of the packets of a stream that were copied.
+#[derive(Debug, Default)]
+struct Timeline {
+    last_dts: Option<f64>,
+    start: Option<f64>,
+    end: Option<f64>,
+}
+
+impl Timeline {
+    /// Check whether a packet keeps the stream playable, and extend the timeline with it if so.
+    ///
+    /// # Arguments
+    ///
+    /// * `dts` - Decoding timestamp of the packet in seconds.
+    /// * `pts` - Presentation timestamp of the packet in seconds, if it has one.
+    /// * `duration` - Duration of the packet in seconds.
+    fn accept(&mut self, dts: f64, pts: Option<f64>, duration: f64) -> bool {
+        if self.last_dts.is_some_and(|last_dts| dts <= last_dts) {
+            return false;
+        }
+        self.last_dts = Some(dts);
+        let pts = pts.unwrap_or(dts);
+        self.start = Some(self.start.map_or(pts, |start| start.min(pts)));
+        self.end = Some(
+            self.end
+                .map_or(pts + duration, |end| end.max(pts + duration)),
+        );
+        true
+    }
+
+    /// Get the time between the earliest and latest presentation time.
+    fn duration(&self) -> Duration {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if end > start => Duration::from_secs_f64(end - start),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_drops_packets_back_in_time() {
+        let mut timeline = Timeline::default();
+        assert!(timeline.accept(1.0, Some(1.0), 0.5));
+        assert!(timeline.accept(1.5, Some(2.0), 0.5));
+        assert!(!timeline.accept(1.5, Some(1.5), 0.5));
+        assert!(!timeline.accept(0.5, Some(0.5), 0.5));
+        assert!(timeline.accept(2.0, Some(1.5), 0.5));
+        assert_eq!(timeline.duration(), Duration::from_secs_f64(1.5));
+    }
+
+    #[test]
+    fn test_timeline_without_packets() {
+        assert_eq!(Timeline::default().duration(), Duration::ZERO);
+    }
+}
@@ -0,0 +1,544 @@
+//! Smart rendering: cut a range out of a source with minimal re-encoding.
+//!
+//! Only the partial GOPs at the cut points are decoded and re-encoded. Everything in between is
+//! copied as-is, so trimming a long file takes about as long as copying the part that is kept.
+
+use std::collections::HashMap;
+
+use ffmpeg::codec::decoder::Video as AvVideoDecoder;
+use ffmpeg::codec::encoder::video::Encoder as AvVideoEncoder;
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Context as AvContext;
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::util::error::EAGAIN;
+use ffmpeg::util::mathematics::rescale::TIME_BASE;
+use ffmpeg::util::picture::Type as AvFrameType;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::ffi;
+use crate::frame::RawFrame;
+use crate::io::private::{Output, Write};
+use crate::io::{Reader, ReaderBuilder, Writer, WriterBuilder};
+use crate::location::Location;
+use crate::options::Options;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Summary of a smart render.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SmartRenderReport {
+    /// Number of video frames that were decoded and re-encoded at the cut points.
+    pub reencoded_frames: u64,
+    /// Number of packets (of all streams) that were copied without re-encoding.
+    pub copied_packets: u64,
+}
+
+/// Builds and runs a smart render of a range of a source.
+///
+/// The best video stream and all audio streams of the source are kept. Video is re-encoded from
+/// the start of the range up to the first keyframe in the range, and from the last keyframe in the
+/// range up to the end of the range. All other packets are copied. The encoder is matched to the
+/// source: same codec, resolution, pixel format, profile, level and color properties.
+///
+/// Re-encoded segments carry their own parameter sets in-band, since they cannot be guaranteed to
+/// be identical to those of the source. Containers that allow in-band parameter sets, like MPEG-TS
+/// and Matroska, are the most robust. Sources with open GOPs lose the leading pictures of the first
+/// copied GOP, since they reference pictures before the cut.
+///
+/// # Example
+///
+/// ```ignore
+/// let report = SmartRenderBuilder::new(
+///     Path::new("recording.mkv"),
+///     Path::new("highlight.mkv"),
+///     Time::from_secs(600.0),
+///     Time::from_secs(900.0),
+/// )
+/// .render()
+/// .unwrap();
+/// println!("re-encoded {} frames", report.reencoded_frames);
+/// ```
+pub struct SmartRenderBuilder<'a> {
+    source: Location,
+    destination: Location,
+    start: Time,
+    end: Time,
+    format: Option<&'a str>,
+    options: Option<&'a Options>,
+    encoder_options: Option<&'a Options>,
+}
+
+impl<'a> SmartRenderBuilder<'a> {
+    /// Create a smart render of a range of the source.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source to cut from.
+    /// * `destination` - Where to write the result to.
+    /// * `start` - Start of the range (inclusive).
+    /// * `end` - End of the range (exclusive).
+    pub fn new(
+        source: impl Into<Location>,
+        destination: impl Into<Location>,
+        start: Time,
+        end: Time,
+    ) -> Self {
+        Self {
+            source: source.into(),
+            destination: destination.into(),
+            start,
+            end,
+            format: None,
+            options: None,
+            encoder_options: None,
+        }
+    }
+
+    /// Set the container format of the destination.
+    ///
+    /// # Arguments
+    ///
+    /// * `format` - Container format to use.
+    pub fn with_format(mut self, format: &'a str) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Set the options for the source.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to input.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Set options for the encoder of the re-encoded segments, like `crf` for libx264. Pick a
+    /// quality that matches the source, or the re-encoded segments will stand out.
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options to pass on to the encoder.
+    pub fn with_encoder_options(mut self, options: &'a Options) -> Self {
+        self.encoder_options = Some(options);
+        self
+    }
+
+    /// Run the smart render.
+    ///
+    /// Fails with [`Error::InvalidRange`] before writing anything if the range is empty, or not
+    /// inside the source. Sources of which the duration is unknown are only checked for an empty
+    /// range.
+    pub fn render(self) -> Result<SmartRenderReport> {
+        let mut reader_builder = ReaderBuilder::new(self.source);
+        if let Some(options) = self.options {
+            reader_builder = reader_builder.with_options(options);
+        }
+        let mut reader = reader_builder.build()?;
+        check_range(&reader, self.start, self.end)?;
+        let video_stream_index = reader.best_video_stream_index()?;
+
+        let mut writer_builder = WriterBuilder::new(self.destination);
+        if let Some(format) = self.format {
+            writer_builder = writer_builder.with_format(format);
+        }
+        let writer = writer_builder.build()?;
+
+        let mut render = SmartRender::new(
+            &reader,
+            writer,
+            video_stream_index,
+            self.start,
+            self.end,
+            self.encoder_options.cloned().unwrap_or_default(),
+        )?;
+        render.last_keyframe =
+            find_keyframe_at_or_before(&mut reader, video_stream_index, self.end)?
+                .unwrap_or(i64::MIN);
+        seek(&mut reader, self.start)?;
+        render.run(&mut reader)?;
+        Ok(render.report)
+    }
+}
+
+/// Check that a range is not empty and lies inside the source.
+///
+/// # Arguments
+///
+/// * `reader` - Source.
+/// * `start` - Start of the range.
+/// * `end` - End of the range.
+fn check_range(reader: &Reader, start: Time, end: Time) -> Result<()> {
+    let (start, end) = (start.as_secs_f64(), end.as_secs_f64());
+    if !(0.0..end).contains(&start) {
+        return Err(Error::InvalidRange);
+    }
+    let duration = reader.input.duration();
+    if duration > 0 && end > Time::new(Some(duration), TIME_BASE).as_secs_f64() {
+        return Err(Error::InvalidRange);
+    }
+    Ok(())
+}
+
+/// Phase of the video stream during a smart render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// Re-encoding up to the first keyframe in the range.
+    Head,
+    /// Copying up to the last keyframe in the range.
+    Copy,
+    /// Re-encoding up to the end of the range.
+    Tail,
+    /// All video in the range was written.
+    Done,
+}
+
+/// State of a running smart render.
+struct SmartRender {
+    writer: Writer,
+    /// Maps source stream indices to output stream indices and source time bases.
+    mapping: HashMap<usize, (usize, AvRational)>,
+    video_stream_index: usize,
+    video_time_base: AvRational,
+    video_frame_rate: AvRational,
+    start: Time,
+    end: Time,
+    /// PTS of the last keyframe at or before the end of the range, in the video time base.
+    last_keyframe: i64,
+    /// PTS of the first copied keyframe, in the video time base.
+    first_keyframe: Option<i64>,
+    phase: Phase,
+    decoder: AvVideoDecoder,
+    encoder: Option<AvVideoEncoder>,
+    encoder_options: Options,
+    /// Re-encoded head packets are held back until the decode delay of the copied packets is
+    /// known, so that decoding timestamps can be made to increase monotonically.
+    head_packets: Vec<AvPacket>,
+    report: SmartRenderReport,
+}
+
+impl SmartRender {
+    fn new(
+        reader: &Reader,
+        mut writer: Writer,
+        video_stream_index: usize,
+        start: Time,
+        end: Time,
+        encoder_options: Options,
+    ) -> Result<Self> {
+        let mut mapping = HashMap::new();
+        for stream in reader.input.streams() {
+            let medium = stream.parameters().medium();
+            if stream.index() == video_stream_index || medium == AvMediaType::Audio {
+                let mut writer_stream = writer
+                    .output
+                    .add_stream(ffmpeg::encoder::find(stream.parameters().id()))?;
+                writer_stream.set_parameters(stream.parameters());
+                mapping.insert(stream.index(), (writer_stream.index(), stream.time_base()));
+            }
+        }
+
+        let video_stream = reader
+            .input
+            .stream(video_stream_index)
+            .ok_or(AvError::StreamNotFound)?;
+        let video_time_base = video_stream.time_base();
+        let video_frame_rate = video_stream.avg_frame_rate();
+        let mut decoder = AvContext::new();
+        ffi::set_decoder_context_time_base(&mut decoder, video_time_base);
+        decoder.set_parameters(video_stream.parameters())?;
+        let decoder = decoder.decoder().video()?;
+
+        Ok(Self {
+            writer,
+            mapping,
+            video_stream_index,
+            video_time_base,
+            video_frame_rate,
+            start,
+            end,
+            last_keyframe: i64::MIN,
+            first_keyframe: None,
+            phase: Phase::Head,
+            decoder,
+            encoder: None,
+            encoder_options,
+            head_packets: Vec::new(),
+            report: SmartRenderReport::default(),
+        })
+    }
+
+    /// Read the source from the current position and write the range.
+    fn run(&mut self, reader: &mut Reader) -> Result<()> {
+        let start = self.ts(self.start, self.video_time_base);
+        let end = self.ts(self.end, self.video_time_base);
+        // Without a keyframe inside the range, the whole range is re-encoded.
+        let full_reencode = self.last_keyframe <= start;
+
+        self.writer.write_header()?;
+        loop {
+            // Read through the reader so that its limits, packet transforms, validation and
+            // interrupt apply.
+            let packet = match reader.read_any() {
+                Ok(packet) => packet,
+                Err(Error::ReadExhausted) => break,
+                Err(err) => return Err(err),
+            };
+            let stream_index = packet.stream_index();
+            let packet = packet.into_inner();
+            let Some(&(_, time_base)) = self.mapping.get(&stream_index) else {
+                continue;
+            };
+            let pts = packet.pts().unwrap_or(i64::MIN);
+
+            if stream_index != self.video_stream_index {
+                if pts >= self.ts(self.start, time_base) && pts < self.ts(self.end, time_base) {
+                    self.write_copy(packet)?;
+                } else if self.phase == Phase::Done && pts >= self.ts(self.end, time_base) {
+                    break;
+                }
+                continue;
+            }
+
+            match self.phase {
+                Phase::Head if packet.is_key() && pts >= start && !full_reencode => {
+                    self.finish_segment()?;
+                    self.first_keyframe = Some(pts);
+                    if pts >= self.last_keyframe {
+                        // There is no complete GOP in the range to copy.
+                        self.write_head_packets(0)?;
+                        self.start_tail(packet, end)?;
+                    } else {
+                        let decode_delay = pts - packet.dts().unwrap_or(pts);
+                        self.write_head_packets(decode_delay)?;
+                        self.phase = Phase::Copy;
+                        self.write_copy(packet)?;
+                    }
+                }
+                Phase::Head => {
+                    if packet.is_key() && pts >= end {
+                        self.finish_segment()?;
+                        self.phase = Phase::Done;
+                    } else {
+                        self.decode(&packet)?;
+                    }
+                }
+                Phase::Copy => self.copy_video(packet, end)?,
+                Phase::Tail => {
+                    if packet.is_key() && pts >= end {
+                        self.finish_segment()?;
+                        self.phase = Phase::Done;
+                    } else {
+                        self.decode(&packet)?;
+                    }
+                }
+                Phase::Done => {}
+            }
+            if self.phase == Phase::Done && self.mapping.len() == 1 {
+                break;
+            }
+        }
+
+        if matches!(self.phase, Phase::Head | Phase::Tail) {
+            self.finish_segment()?;
+            self.phase = Phase::Done;
+        }
+        // Head packets that are still held back were never followed by copied packets.
+        self.write_head_packets(0)?;
+        self.writer.write_trailer()?;
+        Ok(())
+    }
+
+    /// Handle a video packet while copying.
+    fn copy_video(&mut self, packet: AvPacket, end: i64) -> Result<()> {
+        let pts = packet.pts().unwrap_or(i64::MIN);
+        if packet.is_key() && pts >= self.last_keyframe {
+            self.start_tail(packet, end)?;
+        } else if self
+            .first_keyframe
+            .is_some_and(|first_keyframe| pts >= first_keyframe)
+        {
+            // Leading pictures of an open GOP reference pictures before the cut and are skipped.
+            self.write_copy(packet)?;
+        }
+        Ok(())
+    }
+
+    /// Start re-encoding the GOP that contains the end of the range, from its keyframe.
+    fn start_tail(&mut self, keyframe: AvPacket, end: i64) -> Result<()> {
+        if keyframe.pts().unwrap_or(i64::MIN) >= end {
+            self.phase = Phase::Done;
+        } else {
+            self.phase = Phase::Tail;
+            self.decode(&keyframe)?;
+        }
+        Ok(())
+    }
+
+    /// Decode a video packet and encode the frames that fall within the range.
+    fn decode(&mut self, packet: &AvPacket) -> Result<()> {
+        self.decoder.send_packet(packet)?;
+        self.receive_frames()
+    }
+
+    /// Drain the decoder and encoder of the current re-encoded segment.
+    fn finish_segment(&mut self) -> Result<()> {
+        self.decoder.send_eof()?;
+        self.receive_frames()?;
+        self.decoder.flush();
+        if let Some(mut encoder) = self.encoder.take() {
+            encoder.send_eof()?;
+            self.receive_packets(&mut encoder)?;
+        }
+        Ok(())
+    }
+
+    /// Receive decoded frames and encode the ones that fall within the range.
+    fn receive_frames(&mut self) -> Result<()> {
+        let start = self.ts(self.start, self.video_time_base);
+        let end = self.ts(self.end, self.video_time_base);
+        let mut frame = RawFrame::empty();
+        loop {
+            match self.decoder.receive_frame(&mut frame) {
+                Ok(()) => {}
+                Err(AvError::Eof) => return Ok(()),
+                Err(AvError::Other { errno }) if errno == EAGAIN => return Ok(()),
+                Err(err) => return Err(err.into()),
+            }
+            let Some(timestamp) = frame.timestamp() else {
+                continue;
+            };
+            let segment_end = match self.phase {
+                Phase::Head => self.first_keyframe.unwrap_or(end).min(end),
+                _ => end,
+            };
+            if timestamp < start || timestamp >= segment_end {
+                continue;
+            }
+            frame.set_pts(Some(timestamp));
+            frame.set_kind(AvFrameType::None);
+            let mut encoder = match self.encoder.take() {
+                Some(encoder) => encoder,
+                None => self.open_encoder(&frame)?,
+            };
+            encoder.send_frame(&frame)?;
+            self.report.reencoded_frames += 1;
+            self.receive_packets(&mut encoder)?;
+            self.encoder = Some(encoder);
+        }
+    }
+
+    /// Receive encoded packets and write them.
+    fn receive_packets(&mut self, encoder: &mut AvVideoEncoder) -> Result<()> {
+        loop {
+            let mut packet = AvPacket::empty();
+            match encoder.receive_packet(&mut packet) {
+                Ok(()) => {}
+                Err(AvError::Eof) => return Ok(()),
+                Err(AvError::Other { errno }) if errno == EAGAIN => return Ok(()),
+                Err(err) => return Err(err.into()),
+            }
+            packet.set_stream(self.video_stream_index);
+            if self.phase == Phase::Head {
+                self.head_packets.push(packet);
+            } else {
+                self.write(packet)?;
+            }
+        }
+    }
+
+    /// Open an encoder that matches the source for the frames of a re-encoded segment.
+    fn open_encoder(&self, frame: &RawFrame) -> Result<AvVideoEncoder> {
+        let video_stream = self
+            .writer
+            .output()
+            .stream(self.mapping[&self.video_stream_index].0)
+            .ok_or(AvError::StreamNotFound)?;
+        let parameters = video_stream.parameters();
+        let codec = ffmpeg::encoder::find(parameters.id()).ok_or(AvError::EncoderNotFound)?;
+        // Note that the global header flag is not set, so that the re-encoded segments carry their
+        // parameter sets in-band.
+        let mut encoder = ffi::codec_context_as(&codec)?.encoder().video()?;
+        encoder.set_width(frame.width());
+        encoder.set_height(frame.height());
+        encoder.set_format(frame.format());
+        encoder.set_time_base(self.video_time_base);
+        encoder.set_frame_rate(Some(self.video_frame_rate));
+        // Without B-frames the re-encoded segments cannot reorder across the cut points.
+        encoder.set_max_b_frames(0);
+        ffi::match_encoder_to_parameters(&mut encoder, &parameters);
+        Ok(encoder.open_with(self.encoder_options.to_dict())?)
+    }
+
+    /// Write the held back head packets, with their decoding timestamps moved back by the decode
+    /// delay of the copied packets.
+    ///
+    /// # Arguments
+    ///
+    /// * `decode_delay` - Decode delay in the video time base.
+    fn write_head_packets(&mut self, decode_delay: i64) -> Result<()> {
+        for mut packet in std::mem::take(&mut self.head_packets) {
+            packet.set_dts(packet.pts().map(|pts| pts - decode_delay.max(0)));
+            self.write(packet)?;
+        }
+        Ok(())
+    }
+
+    /// Write a copied packet.
+    fn write_copy(&mut self, packet: AvPacket) -> Result<()> {
+        self.report.copied_packets += 1;
+        self.write(packet)
+    }
+
+    /// Shift a packet to the start of the range and write it to the corresponding output stream.
+    fn write(&mut self, mut packet: AvPacket) -> Result<()> {
+        let (index, time_base) = self.mapping[&packet.stream()];
+        let offset = self.ts(self.start, time_base);
+        packet.set_pts(packet.pts().map(|pts| pts - offset));
+        packet.set_dts(packet.dts().map(|dts| dts - offset));
+        let writer_time_base = self
+            .writer
+            .output()
+            .stream(index)
+            .ok_or(AvError::StreamNotFound)?
+            .time_base();
+        packet.rescale_ts(time_base, writer_time_base);
+        packet.set_stream(index);
+        packet.set_position(-1);
+        self.writer.write_interleaved(&mut packet)
+    }
+
+    /// Express a time in the specified time base.
+    fn ts(&self, time: Time, time_base: AvRational) -> i64 {
+        time.with_time_base(time_base).into_value().unwrap_or(0)
+    }
+}
+
+/// Seek a reader to the keyframe at or before a time.
+fn seek(reader: &mut Reader, time: Time) -> Result<()> {
+    let timestamp = time.with_time_base(TIME_BASE).into_value().unwrap_or(0);
+    reader
+        .input
+        .seek(timestamp, ..timestamp.saturating_add(1))
+        .map_err(Error::BackendError)
+}
+
+/// Find the PTS of the keyframe at or before a time, in the time base of the stream.
+fn find_keyframe_at_or_before(
+    reader: &mut Reader,
+    stream_index: usize,
+    time: Time,
+) -> Result<Option<i64>> {
+    seek(reader, time)?;
+    loop {
+        match reader.read(stream_index) {
+            Ok(packet) if packet.is_key() => return Ok(packet.pts().into_value()),
+            Ok(_) => continue,
+            Err(Error::ReadExhausted) => return Ok(None),
+            Err(err) => return Err(err),
+        }
+    }
+}
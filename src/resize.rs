@@ -1,8 +1,11 @@
+use ffmpeg::software::scaling::flag::Flags as AvScalerFlags;
+
 /// Represents width and height in a tuple.
 type Dims = (u32, u32);
 
 /// Represents the possible resize strategies.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Resize {
     /// When resizing with `Resize::Exact`, each frame will be resized to the exact width and height
     /// given, without taking into account aspect ratio.
@@ -38,6 +41,59 @@ impl Resize {
     }
 }
 
+/// Represents the scaler profiles that can be used when converting decoded frames to RGB.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ScalerProfile {
+    /// When scaling with `ScalerProfile::Fast`, the scaler uses area averaging and the default
+    /// swscale shortcuts. This is fast and good enough for display purposes.
+    #[default]
+    Fast,
+    /// When scaling with `ScalerProfile::Exact`, the scaler disables all shortcuts: it uses
+    /// accurate rounding, bit-exact output, full chroma interpolation and honors the color range
+    /// of the source. The produced RGB frames are pixel-faithful to the source, which matters for
+    /// machine readability tasks such as QR code and barcode detection.
+    ///
+    /// Note that this profile is considerably slower than `ScalerProfile::Fast`.
+    Exact,
+}
+
+impl ScalerProfile {
+    /// Get the swscale flags that correspond to the profile.
+    pub(crate) fn flags(self) -> AvScalerFlags {
+        match self {
+            ScalerProfile::Fast => AvScalerFlags::AREA,
+            ScalerProfile::Exact => {
+                AvScalerFlags::BICUBIC
+                    | AvScalerFlags::ACCURATE_RND
+                    | AvScalerFlags::BITEXACT
+                    | AvScalerFlags::FULL_CHR_H_INT
+                    | AvScalerFlags::FULL_CHR_H_INP
+            }
+        }
+    }
+}
+
+/// Represents the backends that can be used to convert and scale frames.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ScalerBackend {
+    /// Convert and scale frames with swscale, which supports all pixel formats and honors the
+    /// [`ScalerProfile`].
+    #[default]
+    Swscale,
+    /// Convert and scale frames with a plain scalar implementation in Rust, whose output depends
+    /// neither on the ffmpeg version nor on the SIMD code paths of the CPU. This makes results
+    /// reproducible across machines and upgrades, like checksums of decoded frames and reference
+    /// images in tests. It is much slower than swscale, so it is not meant for throughput. With
+    /// the `bench` feature, `bench::run_scalers` compares the speed of the backends on a machine.
+    ///
+    /// Supports the `yuv420p`, `yuvj420p`, `nv12` and `rgb24` pixel formats. Frames of other pixel
+    /// formats are converted with swscale. The [`ScalerProfile`] is ignored: the color range and
+    /// matrix of the source are always honored, and frames are scaled with a bilinear filter that
+    /// averages when downscaling.
+    #[cfg(feature = "reference-scaler")]
+    Reference,
+}
+
 /// Calculates the maximum image dimensions `w` and `h` that fit inside `w_max` and `h_max`
 /// retaining the original aspect ratio.
 ///
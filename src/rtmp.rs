@@ -0,0 +1,542 @@
+//! Publishing to RTMP (and RTMPS) servers, like live streaming platforms.
+
+use std::time::{Duration, Instant};
+
+use ffmpeg::codec::packet::Packet as AvPacket;
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::format::context::Output as AvOutput;
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::util::error::{
+    ECONNABORTED, ECONNREFUSED, ECONNRESET, EIO, ENETDOWN, ENETRESET, ENETUNREACH, ENOTCONN, EPIPE,
+    ETIMEDOUT,
+};
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::error::Error;
+use crate::ffi;
+use crate::interrupt::Interrupt;
+use crate::io::private::{Output, Write as PrivateWrite};
+use crate::io::{Write, Writer, WriterBuilder};
+use crate::location::Location;
+use crate::options::Options;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Container format used for RTMP.
+const RTMP_FORMAT: &str = "flv";
+
+/// Version of libavformat in ffmpeg 6.1, which added enhanced RTMP to the FLV muxer.
+const ENHANCED_RTMP_AVFORMAT_VERSION: u32 = (60 << 16) | (16 << 8) | 100;
+
+/// How often the interrupt handle is checked while waiting to reconnect.
+const RECONNECT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How an [`RtmpWriter`] reconnects after the connection to the server was lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    max_attempts: u32,
+    initial_delay: Duration,
+    max_delay: Duration,
+}
+
+impl ReconnectPolicy {
+    /// Create a reconnect policy that tries to reconnect at most `max_attempts` times per lost
+    /// connection.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_attempts` - Maximum number of attempts.
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    /// Set the delay before the first attempt. The delay doubles after every failed attempt, up to
+    /// the maximum delay.
+    ///
+    /// # Arguments
+    ///
+    /// * `initial_delay` - Delay before the first attempt.
+    /// * `max_delay` - Maximum delay between attempts.
+    pub fn with_delay(mut self, initial_delay: Duration, max_delay: Duration) -> Self {
+        self.initial_delay = initial_delay;
+        self.max_delay = max_delay.max(initial_delay);
+        self
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Builds an [`RtmpWriter`].
+pub struct RtmpWriterBuilder<'a> {
+    destination: Location,
+    options: Option<&'a Options>,
+    metadata: Vec<(String, String)>,
+    frame_rate: Option<AvRational>,
+    reconnect: Option<ReconnectPolicy>,
+    interrupt: Option<Interrupt>,
+}
+
+impl<'a> RtmpWriterBuilder<'a> {
+    /// Create a new [`RtmpWriterBuilder`].
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Server URL including the stream key, like
+    ///   `rtmp://live.example.com/app/stream-key`.
+    pub fn new(destination: impl Into<Location>) -> Self {
+        Self {
+            destination: destination.into(),
+            options: None,
+            metadata: Vec::new(),
+            frame_rate: None,
+            reconnect: None,
+            interrupt: None,
+        }
+    }
+
+    /// Set the options for the RTMP protocol and the FLV muxer. Defaults to
+    /// [`Options::preset_rtmp_live`].
+    ///
+    /// # Arguments
+    ///
+    /// * `options` - Options.
+    pub fn with_options(mut self, options: &'a Options) -> Self {
+        self.options = Some(options);
+        self
+    }
+
+    /// Add an entry to the `onMetaData` script tag the server receives when publishing starts.
+    /// Width, height, frame rate, bit rate and codecs are derived from the streams by the muxer and
+    /// cannot be set this way.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Metadata key.
+    /// * `value` - Metadata value.
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.push((key.into(), value.into()));
+        self
+    }
+
+    /// Set the `encoder` entry of the `onMetaData` script tag, which is otherwise set to the
+    /// libavformat version.
+    ///
+    /// # Arguments
+    ///
+    /// * `encoder` - Encoder name, like `my-streamer 1.2`.
+    pub fn with_encoder_name(self, encoder: impl Into<String>) -> Self {
+        self.with_metadata("encoder", encoder)
+    }
+
+    /// Set the frame rate of video streams that do not have an average frame rate, such as streams
+    /// added to a [`Muxer`](crate::mux::Muxer) from a reader. The frame rate is announced in the
+    /// `onMetaData` script tag.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frame rate.
+    pub fn with_frame_rate(mut self, frame_rate: AvRational) -> Self {
+        self.frame_rate = Some(frame_rate);
+        self
+    }
+
+    /// Reconnect when the connection to the server is lost. See [`RtmpWriter`].
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Reconnect policy.
+    pub fn with_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = Some(policy);
+        self
+    }
+
+    /// Set the handle to interrupt connecting, publishing and reconnecting from another thread.
+    /// Without it, the writer gets a handle of its own, see [`RtmpWriter::interrupt`].
+    ///
+    /// # Arguments
+    ///
+    /// * `interrupt` - Interrupt handle.
+    pub fn with_interrupt(mut self, interrupt: Interrupt) -> Self {
+        self.interrupt = Some(interrupt);
+        self
+    }
+
+    /// Build [`RtmpWriter`]. This connects to the server.
+    pub fn build(self) -> Result<RtmpWriter> {
+        let options = self
+            .options
+            .cloned()
+            .unwrap_or_else(Options::preset_rtmp_live);
+        let mut writer_builder = WriterBuilder::new(self.destination)
+            .with_format(RTMP_FORMAT)
+            .with_options(&options);
+        if let Some(interrupt) = self.interrupt {
+            writer_builder = writer_builder.with_interrupt(interrupt);
+        }
+        let writer = writer_builder.build()?;
+        Ok(RtmpWriter {
+            writer,
+            options,
+            metadata: self.metadata,
+            frame_rate: self.frame_rate,
+            reconnect: self.reconnect,
+            awaiting_keyframe: false,
+            reconnects: 0,
+        })
+    }
+}
+
+/// Writer that publishes to an RTMP or RTMPS server. Use it with a [`Muxer`](crate::mux::Muxer).
+///
+/// H.264 video and AAC or MP3 audio are always supported. HEVC, AV1 and VP9 use enhanced RTMP
+/// signaling, which requires ffmpeg 6.1 or later, as well as support by the server.
+///
+/// When reconnecting is enabled with [`RtmpWriterBuilder::with_reconnect`] and writing fails
+/// because the connection was lost, the writer reconnects, publishes the same streams again and
+/// resumes at the next video keyframe. Packets in between are dropped. Timestamps continue where
+/// they left off, so that the server sees one continuous stream. Waiting to reconnect and
+/// reconnecting stop with [`AvError::Exit`] when the writer is interrupted, see
+/// [`RtmpWriter::interrupt`].
+///
+/// # Example
+///
+/// ```ignore
+/// let reader = Reader::new(Path::new("stream.mp4")).unwrap();
+/// let writer = RtmpWriterBuilder::new(
+///     "rtmp://live.example.com/app/stream-key".parse::<Url>().unwrap(),
+/// )
+/// .with_encoder_name("my-streamer")
+/// .with_reconnect(ReconnectPolicy::default())
+/// .build()
+/// .unwrap();
+/// let mut muxer = MuxerBuilder::new(writer)
+///     .with_streams(&reader)
+///     .unwrap()
+///     .interleaved()
+///     .build();
+/// ```
+pub struct RtmpWriter {
+    writer: Writer,
+    options: Options,
+    metadata: Vec<(String, String)>,
+    frame_rate: Option<AvRational>,
+    reconnect: Option<ReconnectPolicy>,
+    awaiting_keyframe: bool,
+    reconnects: u32,
+}
+
+impl RtmpWriter {
+    /// Create a new [`RtmpWriter`] with default settings and without reconnecting.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Server URL including the stream key.
+    #[inline]
+    pub fn new(destination: impl Into<Location>) -> Result<Self> {
+        RtmpWriterBuilder::new(destination).build()
+    }
+
+    /// Number of times the writer reconnected.
+    #[inline]
+    pub fn reconnects(&self) -> u32 {
+        self.reconnects
+    }
+
+    /// Get a handle to interrupt blocking operations of the writer from another thread, like
+    /// writing to a server that stopped responding or waiting to reconnect. The handle stays valid
+    /// across reconnects. See [`Interrupt`].
+    #[inline]
+    pub fn interrupt(&self) -> Interrupt {
+        self.writer.interrupt()
+    }
+
+    /// Write a packet, and reconnect if the connection was lost.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to write.
+    /// * `interleaved` - Whether or not to use interleaved write.
+    fn write_packet(&mut self, packet: &mut AvPacket, interleaved: bool) -> Result<()> {
+        if self.awaiting_keyframe {
+            if !self.resumes_stream(packet) {
+                return Ok(());
+            }
+            self.awaiting_keyframe = false;
+        }
+
+        let result = if interleaved {
+            self.writer.write_interleaved(packet)
+        } else {
+            self.writer.write(packet)
+        };
+        match (result, self.reconnect) {
+            (Err(Error::BackendError(err)), Some(policy)) if is_connection_error(err) => {
+                tracing::warn!("rtmp connection lost: {err}");
+                self.reconnect(policy)?;
+                self.awaiting_keyframe = true;
+                Ok(())
+            }
+            (result, _) => result.map(|_| ()),
+        }
+    }
+
+    /// Whether or not publishing can resume at the packet after reconnecting: it must be a video
+    /// keyframe, or any packet if there is no video.
+    fn resumes_stream(&self, packet: &AvPacket) -> bool {
+        let mut streams = self.writer.output.streams();
+        if streams.any(|stream| stream.parameters().medium() == AvMediaType::Video) {
+            packet.is_key()
+                && self
+                    .writer
+                    .output
+                    .stream(packet.stream())
+                    .is_some_and(|stream| stream.parameters().medium() == AvMediaType::Video)
+        } else {
+            true
+        }
+    }
+
+    /// Reconnect to the server and publish the same streams again.
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - Reconnect policy.
+    fn reconnect(&mut self, policy: ReconnectPolicy) -> Result<()> {
+        let interrupt = self.interrupt();
+        let mut delay = policy.initial_delay;
+        let mut attempt = 1;
+        loop {
+            sleep_unless_interrupted(&interrupt, delay)?;
+            match self.republish() {
+                Ok(writer) => {
+                    self.writer = writer;
+                    self.reconnects += 1;
+                    return Ok(());
+                }
+                Err(err) if attempt >= policy.max_attempts || interrupt.is_cancelled() => {
+                    return Err(err)
+                }
+                Err(err) => {
+                    tracing::warn!("rtmp reconnect attempt {attempt} failed: {err}");
+                    attempt += 1;
+                    delay = (delay * 2).min(policy.max_delay);
+                }
+            }
+        }
+    }
+
+    /// Connect to the server again and write the header for a copy of the current streams. The new
+    /// connection is interrupted by the same handle as the lost one.
+    fn republish(&self) -> Result<Writer> {
+        let mut writer = WriterBuilder::new(self.writer.destination.clone())
+            .with_format(RTMP_FORMAT)
+            .with_options(&self.options)
+            .with_interrupt(self.interrupt())
+            .build()?;
+        for stream in self.writer.output.streams() {
+            let mut copy = writer
+                .output
+                .add_stream(ffmpeg::encoder::find(stream.parameters().id()))?;
+            copy.set_parameters(stream.parameters());
+            copy.set_time_base(stream.time_base());
+            copy.set_avg_frame_rate(stream.avg_frame_rate());
+        }
+        publish(
+            &mut writer.output,
+            &self.options,
+            &self.metadata,
+            self.frame_rate,
+        )?;
+        Ok(writer)
+    }
+}
+
+impl Write for RtmpWriter {}
+
+impl PrivateWrite for RtmpWriter {
+    type Out = ();
+
+    fn write_header(&mut self) -> Result<()> {
+        publish(
+            &mut self.writer.output,
+            &self.options,
+            &self.metadata,
+            self.frame_rate,
+        )
+    }
+
+    fn write(&mut self, packet: &mut AvPacket) -> Result<()> {
+        self.write_packet(packet, false)
+    }
+
+    fn write_interleaved(&mut self, packet: &mut AvPacket) -> Result<()> {
+        self.write_packet(packet, true)
+    }
+
+    fn write_trailer(&mut self) -> Result<()> {
+        self.writer.write_trailer().map(|_| ())
+    }
+}
+
+impl Output for RtmpWriter {
+    fn output(&self) -> &AvOutput {
+        &self.writer.output
+    }
+
+    fn output_mut(&mut self) -> &mut AvOutput {
+        &mut self.writer.output
+    }
+}
+
+unsafe impl Send for RtmpWriter {}
+unsafe impl Sync for RtmpWriter {}
+
+/// Check the codecs, fill in missing video frame rates and write the header with the metadata.
+///
+/// # Arguments
+///
+/// * `output` - Output to write header of.
+/// * `options` - Muxer options.
+/// * `metadata` - Entries for the `onMetaData` script tag.
+/// * `frame_rate` - Frame rate for video streams without an average frame rate.
+fn publish(
+    output: &mut AvOutput,
+    options: &Options,
+    metadata: &[(String, String)],
+    frame_rate: Option<AvRational>,
+) -> Result<()> {
+    for index in 0..output.nb_streams() as usize {
+        let mut stream = output.stream_mut(index).ok_or(AvError::StreamNotFound)?;
+        if !is_supported_codec(stream.parameters().id()) {
+            return Err(Error::UnsupportedCodec);
+        }
+        if stream.parameters().medium() == AvMediaType::Video
+            && stream.avg_frame_rate().numerator() == 0
+        {
+            if let Some(frame_rate) = frame_rate {
+                stream.set_avg_frame_rate(frame_rate);
+            }
+        }
+    }
+    ffi::write_header_with_metadata(output, options.to_dict(), metadata)?;
+    Ok(())
+}
+
+/// Whether or not the FLV muxer can publish a codec over RTMP.
+///
+/// # Arguments
+///
+/// * `id` - Codec ID.
+fn is_supported_codec(id: AvCodecId) -> bool {
+    match id {
+        AvCodecId::H264
+        | AvCodecId::FLV1
+        | AvCodecId::VP6F
+        | AvCodecId::AAC
+        | AvCodecId::MP3
+        | AvCodecId::SPEEX
+        | AvCodecId::NELLYMOSER
+        | AvCodecId::PCM_ALAW
+        | AvCodecId::PCM_MULAW => true,
+        AvCodecId::HEVC | AvCodecId::AV1 | AvCodecId::VP9 => supports_enhanced_rtmp(),
+        _ => false,
+    }
+}
+
+/// Whether or not the FLV muxer supports enhanced RTMP, which adds HEVC, AV1 and VP9. This depends
+/// on the libavformat that is loaded at runtime, which may be newer than the one built against.
+fn supports_enhanced_rtmp() -> bool {
+    ffmpeg::format::version() >= ENHANCED_RTMP_AVFORMAT_VERSION
+}
+
+/// Sleep for a while, or until interrupted.
+///
+/// # Arguments
+///
+/// * `interrupt` - Interrupt handle to check.
+/// * `duration` - Time to sleep.
+///
+/// # Return value
+///
+/// [`AvError::Exit`] if interrupted.
+fn sleep_unless_interrupted(interrupt: &Interrupt, duration: Duration) -> Result<()> {
+    let until = Instant::now() + duration;
+    loop {
+        if interrupt.is_cancelled() {
+            return Err(Error::BackendError(AvError::Exit));
+        }
+        let now = Instant::now();
+        if now >= until {
+            return Ok(());
+        }
+        std::thread::sleep((until - now).min(RECONNECT_POLL_INTERVAL));
+    }
+}
+
+/// Whether or not an error means the connection to the server was lost. An interrupted write
+/// ([`AvError::Exit`]) and the end of the stream ([`AvError::Eof`]) are not.
+///
+/// # Arguments
+///
+/// * `err` - Error returned by writing.
+fn is_connection_error(err: AvError) -> bool {
+    match err {
+        AvError::Other { errno } => [
+            ECONNABORTED,
+            ECONNREFUSED,
+            ECONNRESET,
+            EIO,
+            ENETDOWN,
+            ENETRESET,
+            ENETUNREACH,
+            ENOTCONN,
+            EPIPE,
+            ETIMEDOUT,
+        ]
+        .contains(&errno),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connection_errors() {
+        assert!(is_connection_error(AvError::Other { errno: ECONNRESET }));
+        assert!(is_connection_error(AvError::Other { errno: EPIPE }));
+        assert!(!is_connection_error(AvError::Exit));
+        assert!(!is_connection_error(AvError::Eof));
+        assert!(!is_connection_error(AvError::InvalidData));
+    }
+
+    #[test]
+    fn test_sleep_unless_interrupted() {
+        let interrupt = Interrupt::new();
+        let started = Instant::now();
+        sleep_unless_interrupted(&interrupt, Duration::from_millis(100)).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        let handle = interrupt.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            handle.cancel();
+        });
+        let started = Instant::now();
+        let result = sleep_unless_interrupted(&interrupt, Duration::from_secs(60));
+        assert!(matches!(result, Err(Error::BackendError(AvError::Exit))));
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
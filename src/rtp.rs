@@ -168,3 +168,37 @@ impl From<RtpBuf> for Buf {
         }
     }
 }
+
+/// RTCP receiver statistics for a single RTP stream of an RTSP or RTP input. These are the same
+/// numbers ffmpeg reports back to the server in RTCP receiver reports.
+///
+/// Note that the round-trip time cannot be measured by a receiver, only by the sender. Use
+/// [`RtcpStatistics::last_sender_report`] to check whether the server is still sending reports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtcpStatistics {
+    /// Index of the stream in the reader.
+    pub stream_index: usize,
+    /// Synchronization source identifier of the sender.
+    pub ssrc: u32,
+    /// Number of packets received.
+    pub packets_received: u64,
+    /// Number of packets expected based on the sequence numbers seen.
+    pub packets_expected: u64,
+    /// Number of packets lost. May be negative if duplicate packets were received.
+    pub packets_lost: i64,
+    /// Interarrival jitter.
+    pub jitter: std::time::Duration,
+    /// Time elapsed since the last RTCP sender report was received, if any.
+    pub last_sender_report: Option<std::time::Duration>,
+}
+
+impl RtcpStatistics {
+    /// Fraction of the expected packets that was lost, between `0.0` and `1.0`.
+    pub fn fraction_lost(&self) -> f32 {
+        if self.packets_expected == 0 {
+            0.0
+        } else {
+            (self.packets_lost.max(0) as f32 / self.packets_expected as f32).min(1.0)
+        }
+    }
+}
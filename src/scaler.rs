@@ -0,0 +1,513 @@
+use ffmpeg::format::pixel::Pixel as AvPixel;
+use ffmpeg::software::scaling::context::Context as AvScaler;
+use ffmpeg::software::scaling::flag::Flags as AvScalerFlags;
+#[cfg(feature = "reference-scaler")]
+use ffmpeg::util::color::{Range as AvColorRange, Space as AvColorSpace};
+
+use crate::error::Error;
+use crate::frame::RawFrame;
+use crate::resize::ScalerBackend;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Converts frames to another pixel format and size, with the backend selected with
+/// [`ScalerBackend`].
+pub(crate) enum Scaler {
+    Swscale(AvScaler),
+    #[cfg(feature = "reference-scaler")]
+    Reference(ReferenceScaler),
+}
+
+impl Scaler {
+    /// Create a scaler. Falls back to swscale if the backend does not support the pixel formats.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - Backend to use.
+    /// * `input` - Pixel format, width and height of the input frames.
+    /// * `output` - Pixel format, width and height of the output frames.
+    /// * `flags` - Swscale flags, ignored by other backends.
+    pub(crate) fn get(
+        backend: ScalerBackend,
+        input: (AvPixel, u32, u32),
+        output: (AvPixel, u32, u32),
+        flags: AvScalerFlags,
+    ) -> Result<Self> {
+        #[cfg(feature = "reference-scaler")]
+        if backend == ScalerBackend::Reference
+            && ReferenceScaler::supports(input.0)
+            && ReferenceScaler::supports(output.0)
+        {
+            return Ok(Scaler::Reference(ReferenceScaler {
+                format: output.0,
+                width: output.1,
+                height: output.2,
+            }));
+        }
+        if backend != ScalerBackend::Swscale {
+            tracing::debug!(
+                "converting {:?} to {:?} is not supported by {backend:?} backend, using swscale",
+                input.0,
+                output.0,
+            );
+        }
+        AvScaler::get(
+            input.0, input.1, input.2, output.0, output.1, output.2, flags,
+        )
+        .map(Scaler::Swscale)
+        .map_err(Error::BackendError)
+    }
+
+    /// Get the swscale context, to tune it further. Returns [`None`] for other backends.
+    pub(crate) fn swscale_mut(&mut self) -> Option<&mut AvScaler> {
+        match self {
+            Scaler::Swscale(scaler) => Some(scaler),
+            #[cfg(feature = "reference-scaler")]
+            Scaler::Reference(_) => None,
+        }
+    }
+
+    /// Convert a frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Frame to convert.
+    /// * `output` - Converted frame. Allocated if it is empty.
+    pub(crate) fn run(&mut self, input: &RawFrame, output: &mut RawFrame) -> Result<()> {
+        match self {
+            Scaler::Swscale(scaler) => scaler.run(input, output).map_err(Error::BackendError),
+            #[cfg(feature = "reference-scaler")]
+            Scaler::Reference(scaler) => scaler.run(input, output),
+        }
+    }
+}
+
+/// Scaler with reproducible output, see [`ScalerBackend::Reference`]. Frames are converted to
+/// packed RGB, scaled and converted to the output pixel format.
+#[cfg(feature = "reference-scaler")]
+pub(crate) struct ReferenceScaler {
+    format: AvPixel,
+    width: u32,
+    height: u32,
+}
+
+#[cfg(feature = "reference-scaler")]
+impl ReferenceScaler {
+    /// Whether the scaler supports the pixel format, as input and as output.
+    fn supports(format: AvPixel) -> bool {
+        matches!(
+            format,
+            AvPixel::YUV420P | AvPixel::YUVJ420P | AvPixel::NV12 | AvPixel::RGB24
+        )
+    }
+
+    fn run(&mut self, input: &RawFrame, output: &mut RawFrame) -> Result<()> {
+        let (width, height) = (input.width() as usize, input.height() as usize);
+        let (width_out, height_out) = (self.width as usize, self.height as usize);
+        let mut rgb = to_rgb(input)?;
+        if (width, height) != (width_out, height_out) {
+            rgb = resize_rgb(&rgb, (width, height), (width_out, height_out));
+        }
+        if output.format() != self.format
+            || output.width() != self.width
+            || output.height() != self.height
+        {
+            *output = RawFrame::new(self.format, self.width, self.height);
+        }
+        from_rgb(&rgb, output)
+    }
+}
+
+/// Coefficients to convert between RGB and YUV.
+#[cfg(feature = "reference-scaler")]
+#[derive(Debug, Clone, Copy)]
+struct YuvMatrix {
+    kr: f32,
+    kb: f32,
+    full_range: bool,
+}
+
+#[cfg(feature = "reference-scaler")]
+impl YuvMatrix {
+    /// Get the matrix of a YUV frame. Like swscale, frames without color space use BT.601.
+    fn of(frame: &RawFrame) -> Self {
+        let (kr, kb) = match frame.color_space() {
+            AvColorSpace::BT709 => (0.2126, 0.0722),
+            AvColorSpace::BT2020NCL | AvColorSpace::BT2020CL => (0.2627, 0.0593),
+            _ => (0.299, 0.114),
+        };
+        Self {
+            kr,
+            kb,
+            full_range: frame.format() == AvPixel::YUVJ420P
+                || frame.color_range() == AvColorRange::JPEG,
+        }
+    }
+
+    fn kg(self) -> f32 {
+        1.0 - self.kr - self.kb
+    }
+
+    fn to_rgb(self, y: u8, cb: u8, cr: u8) -> [u8; 3] {
+        let (y, cb, cr) = if self.full_range {
+            (y as f32, cb as f32 - 128.0, cr as f32 - 128.0)
+        } else {
+            (
+                (y as f32 - 16.0) * (255.0 / 219.0),
+                (cb as f32 - 128.0) * (255.0 / 224.0),
+                (cr as f32 - 128.0) * (255.0 / 224.0),
+            )
+        };
+        let r = y + 2.0 * (1.0 - self.kr) * cr;
+        let b = y + 2.0 * (1.0 - self.kb) * cb;
+        let g = y
+            - (2.0 * self.kb * (1.0 - self.kb) / self.kg()) * cb
+            - (2.0 * self.kr * (1.0 - self.kr) / self.kg()) * cr;
+        [clamp(r), clamp(g), clamp(b)]
+    }
+
+    fn luma(self, [r, g, b]: [f32; 3]) -> u8 {
+        let y = self.kr * r + self.kg() * g + self.kb * b;
+        if self.full_range {
+            clamp(y)
+        } else {
+            clamp(16.0 + y * (219.0 / 255.0))
+        }
+    }
+
+    fn chroma(self, [r, g, b]: [f32; 3]) -> (u8, u8) {
+        let y = self.kr * r + self.kg() * g + self.kb * b;
+        let cb = (b - y) / (2.0 * (1.0 - self.kb));
+        let cr = (r - y) / (2.0 * (1.0 - self.kr));
+        let scale = if self.full_range { 1.0 } else { 224.0 / 255.0 };
+        (clamp(128.0 + cb * scale), clamp(128.0 + cr * scale))
+    }
+}
+
+#[cfg(feature = "reference-scaler")]
+fn clamp(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// Convert a frame to packed RGB without padding.
+#[cfg(feature = "reference-scaler")]
+fn to_rgb(frame: &RawFrame) -> Result<Vec<u8>> {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let mut rgb = vec![0; width * height * 3];
+    match frame.format() {
+        AvPixel::RGB24 => {
+            let (data, stride) = (frame.data(0), frame.stride(0));
+            for (y, row) in rgb.chunks_exact_mut(width * 3).enumerate() {
+                row.copy_from_slice(&data[y * stride..y * stride + width * 3]);
+            }
+        }
+        format @ (AvPixel::YUV420P | AvPixel::YUVJ420P | AvPixel::NV12) => {
+            let matrix = YuvMatrix::of(frame);
+            let (luma, luma_stride) = (frame.data(0), frame.stride(0));
+            for (y, row) in rgb.chunks_exact_mut(width * 3).enumerate() {
+                for (x, pixel) in row.chunks_exact_mut(3).enumerate() {
+                    let (cb, cr) = if format == AvPixel::NV12 {
+                        let offset = (y / 2) * frame.stride(1) + (x / 2) * 2;
+                        (frame.data(1)[offset], frame.data(1)[offset + 1])
+                    } else {
+                        (
+                            frame.data(1)[(y / 2) * frame.stride(1) + x / 2],
+                            frame.data(2)[(y / 2) * frame.stride(2) + x / 2],
+                        )
+                    };
+                    pixel.copy_from_slice(&matrix.to_rgb(luma[y * luma_stride + x], cb, cr));
+                }
+            }
+        }
+        _ => return Err(Error::InvalidFrameFormat),
+    }
+    Ok(rgb)
+}
+
+/// Convert packed RGB without padding into an allocated frame of the same size.
+#[cfg(feature = "reference-scaler")]
+fn from_rgb(rgb: &[u8], frame: &mut RawFrame) -> Result<()> {
+    let (width, height) = (frame.width() as usize, frame.height() as usize);
+    let format = frame.format();
+    match format {
+        AvPixel::RGB24 => {
+            let stride = frame.stride(0);
+            let data = frame.data_mut(0);
+            for (y, row) in rgb.chunks_exact(width * 3).enumerate() {
+                data[y * stride..y * stride + width * 3].copy_from_slice(row);
+            }
+        }
+        AvPixel::YUV420P | AvPixel::YUVJ420P | AvPixel::NV12 => {
+            // Like swscale, encode with BT.601 in the range of the pixel format.
+            let matrix = YuvMatrix {
+                kr: 0.299,
+                kb: 0.114,
+                full_range: format == AvPixel::YUVJ420P,
+            };
+            let pixel = |x: usize, y: usize| {
+                let offset = (y * width + x) * 3;
+                [
+                    rgb[offset] as f32,
+                    rgb[offset + 1] as f32,
+                    rgb[offset + 2] as f32,
+                ]
+            };
+            let luma_stride = frame.stride(0);
+            let luma = frame.data_mut(0);
+            for y in 0..height {
+                for x in 0..width {
+                    luma[y * luma_stride + x] = matrix.luma(pixel(x, y));
+                }
+            }
+            for cy in 0..height.div_ceil(2) {
+                for cx in 0..width.div_ceil(2) {
+                    // Average the block of pixels that share the chroma sample.
+                    let mut sum = [0.0; 3];
+                    let mut count = 0.0;
+                    for y in (cy * 2)..(cy * 2 + 2).min(height) {
+                        for x in (cx * 2)..(cx * 2 + 2).min(width) {
+                            let [r, g, b] = pixel(x, y);
+                            sum = [sum[0] + r, sum[1] + g, sum[2] + b];
+                            count += 1.0;
+                        }
+                    }
+                    let (cb, cr) = matrix.chroma(sum.map(|value| value / count));
+                    if format == AvPixel::NV12 {
+                        let offset = cy * frame.stride(1) + cx * 2;
+                        let chroma = frame.data_mut(1);
+                        chroma[offset] = cb;
+                        chroma[offset + 1] = cr;
+                    } else {
+                        let offset = cy * frame.stride(1) + cx;
+                        frame.data_mut(1)[offset] = cb;
+                        let offset = cy * frame.stride(2) + cx;
+                        frame.data_mut(2)[offset] = cr;
+                    }
+                }
+            }
+        }
+        _ => return Err(Error::InvalidFrameFormat),
+    }
+    Ok(())
+}
+
+/// Compute the filter taps to resample a line of `size` samples to `size_out` samples, as the
+/// index of the first sample and the weights. The triangle filter is widened when downscaling, so
+/// that all samples contribute.
+#[cfg(feature = "reference-scaler")]
+fn filter_taps(size: usize, size_out: usize) -> Vec<(usize, Vec<f32>)> {
+    let scale = size as f32 / size_out as f32;
+    let support = scale.max(1.0);
+    (0..size_out)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale;
+            let start = (center - support).floor().max(0.0) as usize;
+            let end = ((center + support).ceil() as usize).min(size);
+            let mut weights = (start..end)
+                .map(|j| (1.0 - ((j as f32 + 0.5 - center) / support).abs()).max(0.0))
+                .collect::<Vec<_>>();
+            let total = weights.iter().sum::<f32>();
+            if total > 0.0 {
+                weights.iter_mut().for_each(|weight| *weight /= total);
+            }
+            (start, weights)
+        })
+        .collect()
+}
+
+/// Resize packed RGB without padding, first horizontally and then vertically.
+#[cfg(feature = "reference-scaler")]
+fn resize_rgb(rgb: &[u8], size: (usize, usize), size_out: (usize, usize)) -> Vec<u8> {
+    let (width, height) = size;
+    let (width_out, height_out) = size_out;
+    let taps_x = filter_taps(width, width_out);
+    let mut horizontal = vec![0.0f32; width_out * height * 3];
+    for y in 0..height {
+        for (x, (start, weights)) in taps_x.iter().enumerate() {
+            for channel in 0..3 {
+                horizontal[(y * width_out + x) * 3 + channel] = weights
+                    .iter()
+                    .enumerate()
+                    .map(|(i, weight)| rgb[(y * width + start + i) * 3 + channel] as f32 * weight)
+                    .sum();
+            }
+        }
+    }
+    let taps_y = filter_taps(height, height_out);
+    let mut resized = vec![0; width_out * height_out * 3];
+    for (y, (start, weights)) in taps_y.iter().enumerate() {
+        for x in 0..width_out {
+            for channel in 0..3 {
+                resized[(y * width_out + x) * 3 + channel] = clamp(
+                    weights
+                        .iter()
+                        .enumerate()
+                        .map(|(i, weight)| {
+                            horizontal[((start + i) * width_out + x) * 3 + channel] * weight
+                        })
+                        .sum(),
+                );
+            }
+        }
+    }
+    resized
+}
+
+#[cfg(all(test, feature = "reference-scaler"))]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 64;
+    const HEIGHT: u32 = 48;
+
+    fn rgb_gradient() -> RawFrame {
+        let mut frame = RawFrame::new(AvPixel::RGB24, WIDTH, HEIGHT);
+        let stride = frame.stride(0);
+        let data = frame.data_mut(0);
+        for y in 0..HEIGHT as usize {
+            for x in 0..WIDTH as usize {
+                let offset = y * stride + x * 3;
+                data[offset] = (x * 4) as u8;
+                data[offset + 1] = (y * 5) as u8;
+                data[offset + 2] = ((x + y) * 2) as u8;
+            }
+        }
+        frame
+    }
+
+    fn yuv_gradient() -> RawFrame {
+        let mut frame = RawFrame::new(AvPixel::YUV420P, WIDTH, HEIGHT);
+        for plane in 0..3 {
+            let stride = frame.stride(plane);
+            let (width, height) = match plane {
+                0 => (WIDTH as usize, HEIGHT as usize),
+                _ => (WIDTH as usize / 2, HEIGHT as usize / 2),
+            };
+            let data = frame.data_mut(plane);
+            for y in 0..height {
+                for x in 0..width {
+                    data[y * stride + x] = match plane {
+                        0 => 16 + x * 3 + y,
+                        1 => 100 + x,
+                        _ => 110 + y,
+                    } as u8;
+                }
+            }
+        }
+        frame
+    }
+
+    fn nv12_gradient() -> RawFrame {
+        let mut frame = RawFrame::new(AvPixel::NV12, WIDTH, HEIGHT);
+        let stride = frame.stride(0);
+        let data = frame.data_mut(0);
+        for y in 0..HEIGHT as usize {
+            for x in 0..WIDTH as usize {
+                data[y * stride + x] = (16 + x * 3 + y) as u8;
+            }
+        }
+        let stride = frame.stride(1);
+        let data = frame.data_mut(1);
+        for y in 0..HEIGHT as usize / 2 {
+            for x in 0..WIDTH as usize / 2 {
+                data[y * stride + x * 2] = (100 + x) as u8;
+                data[y * stride + x * 2 + 1] = (110 + y) as u8;
+            }
+        }
+        frame
+    }
+
+    /// Convert with both backends and return the mean and maximum difference of the planes.
+    fn compare(input: &RawFrame, output: (AvPixel, u32, u32)) -> (f64, u8) {
+        let convert = |backend| {
+            let mut scaler = Scaler::get(
+                backend,
+                (input.format(), input.width(), input.height()),
+                output,
+                AvScalerFlags::AREA,
+            )
+            .unwrap();
+            let mut frame = RawFrame::empty();
+            scaler.run(input, &mut frame).unwrap();
+            frame
+        };
+        let expected = convert(ScalerBackend::Swscale);
+        let actual = convert(ScalerBackend::Reference);
+        let planes = match output.0 {
+            AvPixel::RGB24 => vec![(0, output.1 as usize * 3, output.2 as usize)],
+            _ => vec![
+                (0, output.1 as usize, output.2 as usize),
+                (1, output.1 as usize / 2, output.2 as usize / 2),
+                (2, output.1 as usize / 2, output.2 as usize / 2),
+            ],
+        };
+        let (mut sum, mut count, mut max) = (0u64, 0u64, 0u8);
+        for (plane, width, height) in planes {
+            for y in 0..height {
+                for x in 0..width {
+                    let expected = expected.data(plane)[y * expected.stride(plane) + x];
+                    let actual = actual.data(plane)[y * actual.stride(plane) + x];
+                    let difference = expected.abs_diff(actual);
+                    sum += difference as u64;
+                    count += 1;
+                    max = max.max(difference);
+                }
+            }
+        }
+        (sum as f64 / count as f64, max)
+    }
+
+    #[test]
+    fn test_yuv_to_rgb_matches_swscale() {
+        let (mean, max) = compare(&yuv_gradient(), (AvPixel::RGB24, WIDTH, HEIGHT));
+        assert!(mean < 2.0, "mean difference {mean}");
+        assert!(max <= 8, "max difference {max}");
+    }
+
+    #[test]
+    fn test_rgb_to_yuv_matches_swscale() {
+        let (mean, max) = compare(&rgb_gradient(), (AvPixel::YUV420P, WIDTH, HEIGHT));
+        assert!(mean < 2.0, "mean difference {mean}");
+        assert!(max <= 8, "max difference {max}");
+    }
+
+    #[test]
+    fn test_downscale_matches_swscale() {
+        let (mean, max) = compare(&rgb_gradient(), (AvPixel::RGB24, WIDTH / 2, HEIGHT / 2));
+        assert!(mean < 2.0, "mean difference {mean}");
+        assert!(max <= 8, "max difference {max}");
+    }
+
+    #[test]
+    fn test_nv12_to_rgb_matches_swscale() {
+        let (mean, max) = compare(&nv12_gradient(), (AvPixel::RGB24, WIDTH, HEIGHT));
+        assert!(mean < 2.0, "mean difference {mean}");
+        assert!(max <= 8, "max difference {max}");
+    }
+
+    #[test]
+    fn test_rgb_to_full_range_yuv_matches_swscale() {
+        let (mean, max) = compare(&rgb_gradient(), (AvPixel::YUVJ420P, WIDTH, HEIGHT));
+        assert!(mean < 2.0, "mean difference {mean}");
+        assert!(max <= 8, "max difference {max}");
+    }
+
+    #[test]
+    fn test_upscale_matches_swscale() {
+        let (mean, max) = compare(&rgb_gradient(), (AvPixel::RGB24, WIDTH * 2, HEIGHT * 2));
+        assert!(mean < 2.0, "mean difference {mean}");
+        assert!(max <= 8, "max difference {max}");
+    }
+
+    #[test]
+    fn test_unsupported_format_falls_back_to_swscale() {
+        let scaler = Scaler::get(
+            ScalerBackend::Reference,
+            (AvPixel::YUV444P, WIDTH, HEIGHT),
+            (AvPixel::RGB24, WIDTH, HEIGHT),
+            AvScalerFlags::AREA,
+        )
+        .unwrap();
+        assert!(matches!(scaler, Scaler::Swscale(_)));
+    }
+}
@@ -0,0 +1,467 @@
+//! Replacing or adding the audio track of a video with audio from a separate file, like a dubbed
+//! soundtrack or a cleaned up voice recording.
+
+use ffmpeg::media::Type as AvMediaType;
+use ffmpeg::{Error as AvError, Rational as AvRational};
+
+use crate::audio::{AudioDecoderSplit, AudioStreamEncoder};
+use crate::error::Error;
+use crate::io::{Reader, Writer};
+use crate::location::Location;
+use crate::mux::{Muxer, MuxerBuilder};
+use crate::packet::Packet;
+use crate::stream::StreamInfo;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// What happens to the audio tracks of the video. See [`AudioReplacement::with_track_mode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AudioTrackMode {
+    /// Drop the audio tracks of the video, the new audio becomes the only track.
+    #[default]
+    Replace,
+    /// Keep the audio tracks of the video and add the new audio as an extra track after them.
+    Add,
+}
+
+/// How the length of the new audio is matched to the video. See
+/// [`AudioReplacement::with_alignment`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurationAlignment {
+    /// The output is as long as the video. Audio beyond the end of the video is trimmed, and audio
+    /// that ends early is padded with silence when it is encoded.
+    #[default]
+    Video,
+    /// The output ends with whichever of the video and the audio ends first.
+    Shortest,
+    /// Both the video and the audio are kept completely.
+    Longest,
+}
+
+/// Whether the new audio is copied or encoded. See [`AudioReplacement::with_coding`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AudioCoding {
+    /// Copy the audio if the output container can store its codec, encode it to the default audio
+    /// codec of the container otherwise.
+    #[default]
+    Auto,
+    /// Always copy the audio. Fails when the output container cannot store its codec.
+    Copy,
+    /// Always encode the audio to the default audio codec of the output container.
+    Encode,
+}
+
+/// Puts the audio of a separate file into a video in one call. The video is stream-copied, the
+/// audio is copied or encoded depending on whether the output container can store it.
+///
+/// Both files are expected to start at the same moment. Trimming happens at packet boundaries
+/// when the audio is copied, and padding with silence only happens when the audio is encoded.
+///
+/// # Example
+///
+/// ```ignore
+/// AudioReplacement::new(Path::new("interview.mp4"), Path::new("interview-cleaned.wav"))
+///     .with_alignment(DurationAlignment::Video)
+///     .run(Path::new("interview-final.mp4"))
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct AudioReplacement {
+    video: Location,
+    audio: Location,
+    track_mode: AudioTrackMode,
+    alignment: DurationAlignment,
+    coding: AudioCoding,
+    bit_rate: Option<u64>,
+}
+
+impl AudioReplacement {
+    /// Create an audio replacement that replaces the audio of the video and keeps its length.
+    ///
+    /// # Arguments
+    ///
+    /// * `video` - Video to put the audio into. Its best video stream is used.
+    /// * `audio` - File with the new audio. Its best audio stream is used.
+    pub fn new(video: impl Into<Location>, audio: impl Into<Location>) -> Self {
+        Self {
+            video: video.into(),
+            audio: audio.into(),
+            track_mode: AudioTrackMode::default(),
+            alignment: DurationAlignment::default(),
+            coding: AudioCoding::default(),
+            bit_rate: None,
+        }
+    }
+
+    /// Set whether the audio tracks of the video are replaced or kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `track_mode` - Track mode.
+    pub fn with_track_mode(mut self, track_mode: AudioTrackMode) -> Self {
+        self.track_mode = track_mode;
+        self
+    }
+
+    /// Set how the length of the audio is matched to the video.
+    ///
+    /// # Arguments
+    ///
+    /// * `alignment` - Duration alignment.
+    pub fn with_alignment(mut self, alignment: DurationAlignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Set whether the audio is copied or encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `coding` - Audio coding.
+    pub fn with_coding(mut self, coding: AudioCoding) -> Self {
+        self.coding = coding;
+        self
+    }
+
+    /// Set the bit rate of the audio when it is encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `bit_rate` - Bit rate in bits per second.
+    pub fn with_bit_rate(mut self, bit_rate: u64) -> Self {
+        self.bit_rate = Some(bit_rate);
+        self
+    }
+
+    /// Write the video with the new audio.
+    ///
+    /// # Arguments
+    ///
+    /// * `destination` - Where to write the output to. The container format follows from it.
+    pub fn run(self, destination: impl Into<Location>) -> Result<()> {
+        let mut video_reader = Reader::new(self.video)?;
+        let mut audio_reader = Reader::new(self.audio)?;
+        let video_index = video_reader.best_video_stream_index()?;
+        let audio_index = audio_reader.best_audio_stream_index()?;
+        let writer = Writer::new(destination)?;
+
+        let video_end = stream_end(&video_reader, video_index);
+        let end = match self.alignment {
+            DurationAlignment::Video => video_end,
+            DurationAlignment::Shortest => {
+                match (video_end, stream_end(&audio_reader, audio_index)) {
+                    (Some(video_end), Some(audio_end)) => {
+                        Some(if video_end.as_secs_f64() <= audio_end.as_secs_f64() {
+                            video_end
+                        } else {
+                            audio_end
+                        })
+                    }
+                    (video_end, audio_end) => video_end.or(audio_end),
+                }
+            }
+            DurationAlignment::Longest => None,
+        };
+
+        // The muxer maps streams by their index in the reader, so the new audio is keyed after the
+        // streams of the video to keep the keys apart.
+        let audio_key = video_reader.input.streams().count();
+        let mut audio_track = AudioTrack::new(
+            &audio_reader,
+            audio_index,
+            &writer,
+            self.coding,
+            self.bit_rate,
+            audio_key,
+        )?;
+
+        let mut builder =
+            MuxerBuilder::new(writer).with_stream(video_reader.stream_info(video_index)?)?;
+        if self.track_mode == AudioTrackMode::Add {
+            let original_audio = video_reader
+                .input
+                .streams()
+                .filter(|stream| stream.parameters().medium() == AvMediaType::Audio)
+                .map(|stream| stream.index())
+                .collect::<Vec<_>>();
+            for index in original_audio {
+                builder = builder.with_stream(video_reader.stream_info(index)?)?;
+            }
+        }
+        let mut muxer = builder
+            .with_stream(audio_track.stream_info(&audio_reader)?)?
+            .interleaved()
+            .build();
+
+        let mut video_packet = next_video_packet(&mut video_reader, &muxer, audio_key)?;
+        let mut audio_packet = next_packet(audio_reader.read(audio_index))?;
+        loop {
+            let take_video = match (&video_packet, &audio_packet) {
+                (Some(video), Some(audio)) => {
+                    packet_time(video).as_secs_f64() <= packet_time(audio).as_secs_f64()
+                }
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_video {
+                let Some(packet) = video_packet.take() else {
+                    break;
+                };
+                if is_past(&packet, end) {
+                    continue;
+                }
+                muxer.mux(packet)?;
+                video_packet = next_video_packet(&mut video_reader, &muxer, audio_key)?;
+            } else {
+                let Some(packet) = audio_packet.take() else {
+                    break;
+                };
+                if is_past(&packet, end) {
+                    continue;
+                }
+                audio_track.mux(&mut muxer, packet)?;
+                audio_packet = next_packet(audio_reader.read(audio_index))?;
+            }
+        }
+
+        let pad_to = match self.alignment {
+            DurationAlignment::Video => video_end,
+            DurationAlignment::Shortest | DurationAlignment::Longest => None,
+        };
+        audio_track.finish(&mut muxer, pad_to)?;
+        muxer.finish()?;
+        Ok(())
+    }
+}
+
+/// Audio of one stream of a reader that goes into a muxer as an extra stream, copied or encoded.
+/// Shared by the helpers that put audio from a separate file into an output.
+pub(crate) struct AudioTrack {
+    stream_index: usize,
+    key: usize,
+    transcoder: Option<(AudioDecoderSplit, AudioStreamEncoder)>,
+}
+
+impl AudioTrack {
+    /// Prepare the audio of a stream for a writer.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader of the audio.
+    /// * `stream_index` - Index of the audio stream in the reader.
+    /// * `writer` - Writer the audio goes to.
+    /// * `coding` - Whether the audio is copied or encoded.
+    /// * `bit_rate` - Bit rate when the audio is encoded, or `None` for the encoder default.
+    /// * `key` - Key of the audio in the muxer, which no other stream of the muxer may use.
+    pub(crate) fn new(
+        reader: &Reader,
+        stream_index: usize,
+        writer: &Writer,
+        coding: AudioCoding,
+        bit_rate: Option<u64>,
+        key: usize,
+    ) -> Result<Self> {
+        let codec_id = reader
+            .input
+            .stream(stream_index)
+            .ok_or(AvError::StreamNotFound)?
+            .parameters()
+            .id();
+        let copy = match coding {
+            AudioCoding::Auto => writer.supports_codec(codec_id).is_supported(),
+            AudioCoding::Copy => true,
+            AudioCoding::Encode => false,
+        };
+        let transcoder = if copy {
+            None
+        } else {
+            let decoder = AudioDecoderSplit::new(reader, stream_index)?;
+            let encoder = AudioStreamEncoder::new(
+                writer,
+                None,
+                decoder.sample_rate(),
+                Some(decoder.sample_format()),
+                decoder.channels(),
+                bit_rate,
+            )?;
+            Some((decoder, encoder))
+        };
+        Ok(Self {
+            stream_index,
+            key,
+            transcoder,
+        })
+    }
+
+    /// Stream information to add the audio to a [`MuxerBuilder`] with.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader of the audio.
+    pub(crate) fn stream_info(&self, reader: &Reader) -> Result<StreamInfo> {
+        match self.transcoder.as_ref() {
+            Some((_, encoder)) => {
+                StreamInfo::from_params(encoder.parameters(), encoder.time_base(), self.key)
+            }
+            None => {
+                let mut stream_info = reader.stream_info(self.stream_index)?;
+                stream_info.index = self.key;
+                Ok(stream_info)
+            }
+        }
+    }
+
+    /// Mux a packet of the audio stream, after encoding it if needed.
+    ///
+    /// # Arguments
+    ///
+    /// * `muxer` - Muxer to mux to.
+    /// * `packet` - Packet read from the audio stream.
+    pub(crate) fn mux(&mut self, muxer: &mut Muxer<Writer>, mut packet: Packet) -> Result<()> {
+        match self.transcoder.as_mut() {
+            Some((decoder, encoder)) => {
+                if let Some(frame) = decoder.decode_raw(packet)? {
+                    let packets = encoder.encode(&frame, decoder.time_base())?;
+                    mux_encoded(muxer, packets, self.key)?;
+                }
+                Ok(())
+            }
+            None => {
+                packet.set_stream_index(self.key);
+                muxer.mux(packet)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Mux what remains of the audio after the last packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `muxer` - Muxer to mux to.
+    /// * `pad_to` - Time to pad the audio up to with silence when it is encoded, if any.
+    pub(crate) fn finish(&mut self, muxer: &mut Muxer<Writer>, pad_to: Option<Time>) -> Result<()> {
+        let Some((mut decoder, mut encoder)) = self.transcoder.take() else {
+            return Ok(());
+        };
+        loop {
+            match decoder.drain_raw() {
+                Ok(Some(frame)) => {
+                    let packets = encoder.encode(&frame, decoder.time_base())?;
+                    mux_encoded(muxer, packets, self.key)?;
+                }
+                Ok(None) | Err(Error::ReadExhausted) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        if let Some(pad_to) = pad_to {
+            let packets = encoder.pad_to(pad_to)?;
+            mux_encoded(muxer, packets, self.key)?;
+        }
+        let packets = encoder.finish()?;
+        mux_encoded(muxer, packets, self.key)
+    }
+}
+
+/// Read the next packet of the video that goes into the output, or `None` at the end.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the video.
+/// * `muxer` - Muxer to check the streams of.
+/// * `audio_key` - Key of the new audio in the muxer, which the video streams are below.
+fn next_video_packet(
+    reader: &mut Reader,
+    muxer: &Muxer<Writer>,
+    audio_key: usize,
+) -> Result<Option<Packet>> {
+    loop {
+        match next_packet(reader.read_any())? {
+            Some(packet)
+                if packet.stream_index() >= audio_key
+                    || !muxer.has_stream(packet.stream_index()) => {}
+            packet => return Ok(packet),
+        }
+    }
+}
+
+/// Turn the end of a reader into `None`.
+///
+/// # Arguments
+///
+/// * `result` - Result of reading a packet.
+pub(crate) fn next_packet(result: Result<Packet>) -> Result<Option<Packet>> {
+    match result {
+        Ok(packet) => Ok(Some(packet)),
+        Err(Error::ReadExhausted) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Mux encoded packets as a stream of the muxer.
+///
+/// # Arguments
+///
+/// * `muxer` - Muxer to mux to.
+/// * `packets` - Encoded packets.
+/// * `key` - Key of the stream in the muxer.
+pub(crate) fn mux_encoded(
+    muxer: &mut Muxer<Writer>,
+    packets: Vec<Packet>,
+    key: usize,
+) -> Result<()> {
+    for mut packet in packets {
+        packet.set_stream_index(key);
+        muxer.mux(packet)?;
+    }
+    Ok(())
+}
+
+/// Timestamp to order and trim a packet by: the decoding timestamp if there is one, since packets
+/// come in decoding order.
+///
+/// # Arguments
+///
+/// * `packet` - Packet to get the timestamp of.
+pub(crate) fn packet_time(packet: &Packet) -> Time {
+    if packet.dts().has_value() {
+        packet.dts()
+    } else {
+        packet.pts()
+    }
+}
+
+/// Whether a packet starts at or after the end of the output.
+///
+/// # Arguments
+///
+/// * `packet` - Packet to check.
+/// * `end` - End of the output, or `None` if the output is not trimmed.
+fn is_past(packet: &Packet, end: Option<Time>) -> bool {
+    end.is_some_and(|end| {
+        let time = packet_time(packet);
+        time.has_value() && time.as_secs_f64() >= end.as_secs_f64()
+    })
+}
+
+/// End of a stream from the duration the container declares, or `None` if it declares none.
+///
+/// # Arguments
+///
+/// * `reader` - Reader of the stream.
+/// * `stream_index` - Index of the stream.
+fn stream_end(reader: &Reader, stream_index: usize) -> Option<Time> {
+    let stream = reader.input.stream(stream_index)?;
+    if stream.duration() > 0 {
+        return Some(Time::new(Some(stream.duration()), stream.time_base()));
+    }
+    let duration = reader.input.duration();
+    (duration > 0).then(|| {
+        Time::new(
+            Some(duration),
+            AvRational::new(1, ffmpeg::ffi::AV_TIME_BASE),
+        )
+    })
+}
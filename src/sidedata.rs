@@ -0,0 +1,417 @@
+//! Side data of packets, frames and streams, like HDR metadata and closed captions: the policy
+//! for it when remuxing or transcoding, and access to the side data of frames.
+
+use ffmpeg::ffi::{AVFrameSideDataType, AVPacketSideDataType};
+use ffmpeg::Rational as AvRational;
+
+use crate::error::Error;
+use crate::ffi;
+use crate::frame::RawFrame;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Kind of side data, for packets, frames and streams alike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SideDataKind {
+    /// HDR10+ dynamic metadata.
+    HdrPlus,
+    /// Dolby Vision configuration and RPU metadata.
+    DolbyVision,
+    /// Mastering display color volume (static HDR metadata).
+    MasteringDisplay,
+    /// Content light level (static HDR metadata).
+    ContentLightLevel,
+    /// ATSC A/53 closed captions.
+    ClosedCaptions,
+    /// Display matrix, which holds the rotation.
+    DisplayMatrix,
+    /// Stereoscopic 3D layout.
+    Stereo3d,
+    /// Spherical (360°) video mapping.
+    Spherical,
+    /// ICC color profile.
+    IccProfile,
+    /// Unregistered SEI messages, like encoder settings.
+    SeiUnregistered,
+    /// Any other side data.
+    Other,
+}
+
+/// What to do with side data when remuxing (see
+/// [`MuxerBuilder::with_side_data_policy`](crate::mux::MuxerBuilder::with_side_data_policy)) or
+/// encoding (see
+/// [`EncoderBuilder::with_side_data_policy`](crate::encode::EncoderBuilder::with_side_data_policy)).
+///
+/// Side data that is needed to decode a stream (like palettes, new extradata and the number of
+/// audio samples to skip) is always kept.
+///
+/// # Example
+///
+/// Strip HDR10+ and Dolby Vision metadata, but keep static HDR metadata and captions:
+///
+/// ```ignore
+/// let policy = SideDataPolicy::Allow(vec![
+///     SideDataKind::MasteringDisplay,
+///     SideDataKind::ContentLightLevel,
+///     SideDataKind::ClosedCaptions,
+/// ]);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SideDataPolicy {
+    /// Keep all side data.
+    #[default]
+    CopyAll,
+    /// Remove all side data.
+    StripAll,
+    /// Keep only side data of the listed kinds.
+    Allow(Vec<SideDataKind>),
+}
+
+impl SideDataPolicy {
+    /// Whether side data of a kind is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of side data.
+    pub fn allows(&self, kind: SideDataKind) -> bool {
+        match self {
+            SideDataPolicy::CopyAll => true,
+            SideDataPolicy::StripAll => false,
+            SideDataPolicy::Allow(kinds) => kinds.contains(&kind),
+        }
+    }
+
+    /// Whether the policy keeps all side data, so it does not need to be applied.
+    pub(crate) fn is_copy_all(&self) -> bool {
+        *self == SideDataPolicy::CopyAll
+    }
+
+    /// Whether packet (or stream) side data of a type is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Type of the side data.
+    pub(crate) fn keeps_packet_side_data(&self, kind: AVPacketSideDataType) -> bool {
+        packet_side_data_kind(kind).is_none_or(|kind| self.allows(kind))
+    }
+
+    /// Whether frame side data of a type is kept.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Type of the side data.
+    pub(crate) fn keeps_frame_side_data(&self, kind: AVFrameSideDataType) -> bool {
+        self.allows(frame_side_data_kind(kind))
+    }
+}
+
+/// Side data of a frame. Read it from decoded frames with [`FrameSideData::from_frame`], and
+/// attach it to frames to encode with [`FrameSideData::attach`] or
+/// [`Encoder::attach_side_data`](crate::encode::Encoder::attach_side_data).
+///
+/// # Example
+///
+/// ```ignore
+/// let frame = decoder.decode_raw()?;
+/// for side_data in FrameSideData::from_frame(&frame) {
+///     if let FrameSideData::ClosedCaptions(captions) = side_data {
+///         // Pass the `cc_data` triplets to a caption decoder...
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameSideData {
+    /// ATSC A/53 closed captions: the `cc_data` triplets of CEA-708.
+    ClosedCaptions(Vec<u8>),
+    /// Mastering display color volume (static HDR metadata).
+    MasteringDisplay(MasteringDisplay),
+    /// Content light level (static HDR metadata).
+    ContentLightLevel(ContentLightLevel),
+    /// Unregistered SEI message (H.264 and H.265), identified by a UUID.
+    SeiUnregistered {
+        /// UUID of the message.
+        uuid: [u8; 16],
+        /// Payload of the message, after the UUID.
+        payload: Vec<u8>,
+    },
+    /// SMPTE 12M timecodes (from H.264 and H.265 picture timing SEI), up to three per frame.
+    Timecodes(Vec<Timecode>),
+}
+
+/// Mastering display color volume, as in SMPTE ST 2086.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MasteringDisplay {
+    /// Primaries of the display, if known.
+    pub primaries: Option<DisplayPrimaries>,
+    /// Minimum and maximum luminance of the display in cd/m², if known.
+    pub luminance: Option<(AvRational, AvRational)>,
+}
+
+/// CIE 1931 xy chromaticity coordinates of the primaries and white point of a display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DisplayPrimaries {
+    /// Red primary.
+    pub red: (AvRational, AvRational),
+    /// Green primary.
+    pub green: (AvRational, AvRational),
+    /// Blue primary.
+    pub blue: (AvRational, AvRational),
+    /// White point.
+    pub white_point: (AvRational, AvRational),
+}
+
+/// Content light level, as in CTA-861.3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLightLevel {
+    /// Maximum content light level (MaxCLL) in cd/m².
+    pub max_content: u32,
+    /// Maximum frame-average light level (MaxFALL) in cd/m².
+    pub max_frame_average: u32,
+}
+
+/// SMPTE 12M timecode. Above 30 frames per second, the frame number counts pairs of frames, like
+/// it is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timecode {
+    /// Hours.
+    pub hours: u8,
+    /// Minutes.
+    pub minutes: u8,
+    /// Seconds.
+    pub seconds: u8,
+    /// Frame number within the second.
+    pub frames: u8,
+    /// Whether the timecode uses drop frame counting (for 29.97 frames per second).
+    pub drop_frame: bool,
+}
+
+impl FrameSideData {
+    /// Read the side data of a frame. Side data of other types than the ones of
+    /// [`FrameSideData`] is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to read side data from.
+    pub fn from_frame(frame: &RawFrame) -> Vec<FrameSideData> {
+        ffi::frame_side_data(frame)
+    }
+
+    /// Attach the side data to a frame, replacing side data of the same type.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame to attach side data to.
+    pub fn attach(&self, frame: &mut RawFrame) -> Result<()> {
+        ffi::set_frame_side_data(frame, self).map_err(Error::BackendError)
+    }
+
+    /// Get the kind of the side data.
+    pub fn kind(&self) -> SideDataKind {
+        match self {
+            FrameSideData::ClosedCaptions(_) => SideDataKind::ClosedCaptions,
+            FrameSideData::MasteringDisplay(_) => SideDataKind::MasteringDisplay,
+            FrameSideData::ContentLightLevel(_) => SideDataKind::ContentLightLevel,
+            FrameSideData::SeiUnregistered { .. } => SideDataKind::SeiUnregistered,
+            FrameSideData::Timecodes(_) => SideDataKind::Other,
+        }
+    }
+
+    /// Parse unregistered SEI side data: a 16 byte UUID followed by the payload.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Side data.
+    pub(crate) fn parse_sei_unregistered(data: &[u8]) -> Option<FrameSideData> {
+        let (uuid, payload) = data.split_first_chunk::<16>()?;
+        Some(FrameSideData::SeiUnregistered {
+            uuid: *uuid,
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Parse SMPTE 12M timecode side data: the number of timecodes followed by the timecodes, as
+    /// 32-bit integers in native byte order.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - Side data.
+    pub(crate) fn parse_timecodes(data: &[u8]) -> Option<FrameSideData> {
+        let mut values = data
+            .chunks_exact(4)
+            .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()));
+        let count = values.next()?.min(3) as usize;
+        let timecodes = values
+            .take(count)
+            .map(Timecode::from_smpte)
+            .collect::<Vec<_>>();
+        (timecodes.len() == count).then_some(FrameSideData::Timecodes(timecodes))
+    }
+
+    /// Serialize the side data of types that are stored as plain bytes, or `None` for side data
+    /// that is stored as a structure.
+    pub(crate) fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            FrameSideData::ClosedCaptions(data) => Some(data.clone()),
+            FrameSideData::SeiUnregistered { uuid, payload } => Some([&uuid[..], payload].concat()),
+            FrameSideData::Timecodes(timecodes) => Some(
+                std::iter::once(timecodes.len().min(3) as u32)
+                    .chain(timecodes.iter().take(3).map(Timecode::to_smpte))
+                    .flat_map(u32::to_ne_bytes)
+                    .collect(),
+            ),
+            FrameSideData::MasteringDisplay(_) | FrameSideData::ContentLightLevel(_) => None,
+        }
+    }
+}
+
+impl Timecode {
+    /// Decode a timecode from the SMPTE 12M binary representation.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Binary timecode.
+    pub(crate) fn from_smpte(value: u32) -> Timecode {
+        let bcd = |value: u32| ((value >> 4) * 10 + (value & 0xf)) as u8;
+        Timecode {
+            hours: bcd(value & 0x3f),
+            minutes: bcd((value >> 8) & 0x7f),
+            seconds: bcd((value >> 16) & 0x7f),
+            frames: bcd((value >> 24) & 0x3f),
+            drop_frame: value & (1 << 30) != 0,
+        }
+    }
+
+    /// Encode the timecode to the SMPTE 12M binary representation.
+    pub(crate) fn to_smpte(&self) -> u32 {
+        let bcd = |value: u8| (u32::from(value / 10) << 4) | u32::from(value % 10);
+        (u32::from(self.drop_frame) << 30)
+            | (bcd(self.frames) << 24)
+            | (bcd(self.seconds) << 16)
+            | (bcd(self.minutes) << 8)
+            | bcd(self.hours)
+    }
+}
+
+impl std::fmt::Display for Timecode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let separator = if self.drop_frame { ';' } else { ':' };
+        write!(
+            f,
+            "{:02}:{:02}:{:02}{separator}{:02}",
+            self.hours, self.minutes, self.seconds, self.frames,
+        )
+    }
+}
+
+/// Kind of a type of packet side data, or `None` for side data that is needed to decode the
+/// stream.
+///
+/// # Arguments
+///
+/// * `kind` - Type of the side data.
+fn packet_side_data_kind(kind: AVPacketSideDataType) -> Option<SideDataKind> {
+    use ffmpeg::ffi::*;
+    match kind {
+        AV_PKT_DATA_PALETTE
+        | AV_PKT_DATA_NEW_EXTRADATA
+        | AV_PKT_DATA_PARAM_CHANGE
+        | AV_PKT_DATA_SKIP_SAMPLES
+        | AV_PKT_DATA_ENCRYPTION_INFO
+        | AV_PKT_DATA_ENCRYPTION_INIT_INFO
+        | AV_PKT_DATA_WEBVTT_IDENTIFIER
+        | AV_PKT_DATA_WEBVTT_SETTINGS => None,
+        AV_PKT_DATA_DYNAMIC_HDR10_PLUS => Some(SideDataKind::HdrPlus),
+        AV_PKT_DATA_DOVI_CONF => Some(SideDataKind::DolbyVision),
+        AV_PKT_DATA_MASTERING_DISPLAY_METADATA => Some(SideDataKind::MasteringDisplay),
+        AV_PKT_DATA_CONTENT_LIGHT_LEVEL => Some(SideDataKind::ContentLightLevel),
+        AV_PKT_DATA_A53_CC => Some(SideDataKind::ClosedCaptions),
+        AV_PKT_DATA_DISPLAYMATRIX => Some(SideDataKind::DisplayMatrix),
+        AV_PKT_DATA_STEREO3D => Some(SideDataKind::Stereo3d),
+        AV_PKT_DATA_SPHERICAL => Some(SideDataKind::Spherical),
+        AV_PKT_DATA_ICC_PROFILE => Some(SideDataKind::IccProfile),
+        _ => Some(SideDataKind::Other),
+    }
+}
+
+/// Kind of a type of frame side data.
+///
+/// # Arguments
+///
+/// * `kind` - Type of the side data.
+fn frame_side_data_kind(kind: AVFrameSideDataType) -> SideDataKind {
+    use ffmpeg::ffi::*;
+    match kind {
+        AV_FRAME_DATA_DYNAMIC_HDR_PLUS => SideDataKind::HdrPlus,
+        AV_FRAME_DATA_DOVI_RPU_BUFFER | AV_FRAME_DATA_DOVI_METADATA => SideDataKind::DolbyVision,
+        AV_FRAME_DATA_MASTERING_DISPLAY_METADATA => SideDataKind::MasteringDisplay,
+        AV_FRAME_DATA_CONTENT_LIGHT_LEVEL => SideDataKind::ContentLightLevel,
+        AV_FRAME_DATA_A53_CC => SideDataKind::ClosedCaptions,
+        AV_FRAME_DATA_DISPLAYMATRIX => SideDataKind::DisplayMatrix,
+        AV_FRAME_DATA_STEREO3D => SideDataKind::Stereo3d,
+        AV_FRAME_DATA_SPHERICAL => SideDataKind::Spherical,
+        AV_FRAME_DATA_ICC_PROFILE => SideDataKind::IccProfile,
+        AV_FRAME_DATA_SEI_UNREGISTERED => SideDataKind::SeiUnregistered,
+        _ => SideDataKind::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ffmpeg::ffi::*;
+
+    #[test]
+    fn test_policy() {
+        let policy = SideDataPolicy::Allow(vec![
+            SideDataKind::MasteringDisplay,
+            SideDataKind::ClosedCaptions,
+        ]);
+        assert!(policy.keeps_frame_side_data(AV_FRAME_DATA_A53_CC));
+        assert!(!policy.keeps_frame_side_data(AV_FRAME_DATA_DYNAMIC_HDR_PLUS));
+        assert!(policy.keeps_packet_side_data(AV_PKT_DATA_MASTERING_DISPLAY_METADATA));
+        assert!(!policy.keeps_packet_side_data(AV_PKT_DATA_DYNAMIC_HDR10_PLUS));
+
+        let policy = SideDataPolicy::StripAll;
+        assert!(!policy.keeps_packet_side_data(AV_PKT_DATA_A53_CC));
+        // Side data that is needed to decode is never stripped.
+        assert!(policy.keeps_packet_side_data(AV_PKT_DATA_NEW_EXTRADATA));
+        assert!(policy.keeps_packet_side_data(AV_PKT_DATA_SKIP_SAMPLES));
+
+        assert!(SideDataPolicy::CopyAll.keeps_frame_side_data(AV_FRAME_DATA_SEI_UNREGISTERED));
+    }
+
+    #[test]
+    fn test_timecode() {
+        let timecode = Timecode {
+            hours: 10,
+            minutes: 59,
+            seconds: 7,
+            frames: 29,
+            drop_frame: true,
+        };
+        assert_eq!(timecode.to_smpte(), 0x6907_5910);
+        assert_eq!(Timecode::from_smpte(timecode.to_smpte()), timecode);
+        assert_eq!(timecode.to_string(), "10:59:07;29");
+    }
+
+    #[test]
+    fn test_side_data_bytes() {
+        let side_data = FrameSideData::SeiUnregistered {
+            uuid: [7; 16],
+            payload: b"x264".to_vec(),
+        };
+        let bytes = side_data.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 20);
+        assert_eq!(
+            FrameSideData::parse_sei_unregistered(&bytes),
+            Some(side_data)
+        );
+        assert_eq!(FrameSideData::parse_sei_unregistered(&[0; 15]), None);
+
+        let timecodes = FrameSideData::Timecodes(vec![Timecode::from_smpte(0x0102_0304); 2]);
+        let bytes = timecodes.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(FrameSideData::parse_timecodes(&bytes), Some(timecodes));
+        assert_eq!(FrameSideData::parse_timecodes(&bytes[..8]), None);
+    }
+}
@@ -0,0 +1,328 @@
+//! Two-pass video stabilization with vid.stab, like for shaky action camera footage.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use ffmpeg::Error as AvError;
+
+use crate::error::Error;
+use crate::options::{escape_filter_graph, escape_filter_option};
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// How the borders that stabilization moves into view are filled.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum StabilizationBorder {
+    /// Keep the image of previous frames.
+    #[default]
+    Keep,
+    /// Fill with black.
+    Black,
+}
+
+/// How stabilized frames are zoomed in to hide the borders that stabilization moves into view.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum StabilizationZoom {
+    /// Do not zoom in.
+    None,
+    /// Zoom in once, just enough to hide the borders in every frame.
+    #[default]
+    Static,
+    /// Zoom in as far as needed for every frame, which keeps more of the image but changes the
+    /// zoom over time.
+    Adaptive,
+}
+
+/// Options of two-pass stabilization with the `vidstabdetect` and `vidstabtransform` filters.
+///
+/// The first pass analyzes the motion of the camera and writes the transforms that cancel it out
+/// to a file. The second pass smooths and applies them. Attach stabilization to a transcoder with
+/// [`Transcoder::with_stabilization`](crate::transcode::Transcoder::with_stabilization), which runs
+/// both passes and manages the file.
+///
+/// The filters are only available when ffmpeg is built with `--enable-libvidstab`. Otherwise,
+/// configuring the filter graph of the first pass fails.
+///
+/// # Example
+///
+/// ```ignore
+/// let stabilization = Stabilization::new().with_shakiness(8).with_smoothing(30);
+/// Transcoder::new(decoder, encoder)
+///     .with_stabilization(stabilization)
+///     .run()?;
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stabilization {
+    shakiness: u32,
+    accuracy: u32,
+    smoothing: u32,
+    tripod: bool,
+    border: StabilizationBorder,
+    zoom: StabilizationZoom,
+    extra_zoom: f32,
+    sharpen: bool,
+}
+
+impl Stabilization {
+    /// Default shakiness.
+    const SHAKINESS: u32 = 5;
+    /// Default accuracy.
+    const ACCURACY: u32 = 15;
+    /// Default smoothing.
+    const SMOOTHING: u32 = 10;
+
+    /// Create options with the defaults of vid.stab, and sharpening after the transform.
+    pub fn new() -> Self {
+        Self {
+            shakiness: Self::SHAKINESS,
+            accuracy: Self::ACCURACY,
+            smoothing: Self::SMOOTHING,
+            tripod: false,
+            border: StabilizationBorder::default(),
+            zoom: StabilizationZoom::default(),
+            extra_zoom: 0.0,
+            sharpen: true,
+        }
+    }
+
+    /// Set how shaky the footage is, which sets how far motion is searched for.
+    ///
+    /// # Arguments
+    ///
+    /// * `shakiness` - Shakiness from 1 (little) to 10 (strong). Values out of range are clamped.
+    pub fn with_shakiness(mut self, shakiness: u32) -> Self {
+        self.shakiness = shakiness.clamp(1, 10);
+        self
+    }
+
+    /// Set the accuracy of the analysis.
+    ///
+    /// # Arguments
+    ///
+    /// * `accuracy` - Accuracy from 1 (fastest) to 15 (most accurate). Values out of range are
+    ///   clamped.
+    pub fn with_accuracy(mut self, accuracy: u32) -> Self {
+        self.accuracy = accuracy.clamp(1, 15);
+        self
+    }
+
+    /// Set how many frames around every frame the camera motion is smoothed over. More frames
+    /// give a steadier image, but follow intended camera motion, like panning, more slowly.
+    ///
+    /// # Arguments
+    ///
+    /// * `smoothing` - Number of frames before and after every frame, or `0` for a static camera.
+    pub fn with_smoothing(mut self, smoothing: u32) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Set whether to hold the camera still relative to the first frame, like on a tripod. The
+    /// smoothing is ignored in this mode.
+    ///
+    /// # Arguments
+    ///
+    /// * `tripod` - Whether to emulate a tripod.
+    pub fn with_tripod(mut self, tripod: bool) -> Self {
+        self.tripod = tripod;
+        self
+    }
+
+    /// Set how the borders that stabilization moves into view are filled.
+    ///
+    /// # Arguments
+    ///
+    /// * `border` - Border fill.
+    pub fn with_border(mut self, border: StabilizationBorder) -> Self {
+        self.border = border;
+        self
+    }
+
+    /// Set how frames are zoomed in to hide the borders.
+    ///
+    /// # Arguments
+    ///
+    /// * `zoom` - Zoom mode.
+    /// * `extra` - Additional zoom in percent, which may be negative to zoom out.
+    pub fn with_zoom(mut self, zoom: StabilizationZoom, extra: f32) -> Self {
+        self.zoom = zoom;
+        self.extra_zoom = extra;
+        self
+    }
+
+    /// Set whether to sharpen the transformed frames, which makes up for the blur of the
+    /// interpolation (the default).
+    ///
+    /// # Arguments
+    ///
+    /// * `sharpen` - Whether to sharpen.
+    pub fn with_sharpening(mut self, sharpen: bool) -> Self {
+        self.sharpen = sharpen;
+        self
+    }
+
+    /// Get the description of the filter of the analysis pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `transforms` - Path of the file to write the transforms to.
+    pub fn detect_spec(&self, transforms: &Path) -> String {
+        format!(
+            "vidstabdetect=shakiness={}:accuracy={}:tripod={}:result={}",
+            self.shakiness,
+            self.accuracy,
+            u8::from(self.tripod),
+            escape_path(transforms),
+        )
+    }
+
+    /// Get the description of the filters of the transform pass.
+    ///
+    /// # Arguments
+    ///
+    /// * `transforms` - Path of the file to read the transforms from.
+    pub fn transform_spec(&self, transforms: &Path) -> String {
+        let mut spec = format!(
+            "vidstabtransform=input={}:smoothing={}:tripod={}:crop={}:optzoom={}:zoom={}",
+            escape_path(transforms),
+            self.smoothing,
+            u8::from(self.tripod),
+            match self.border {
+                StabilizationBorder::Keep => "keep",
+                StabilizationBorder::Black => "black",
+            },
+            match self.zoom {
+                StabilizationZoom::None => 0,
+                StabilizationZoom::Static => 1,
+                StabilizationZoom::Adaptive => 2,
+            },
+            self.extra_zoom,
+        );
+        if self.sharpen {
+            spec.push_str(",unsharp=5:5:0.8:3:3:0.4");
+        }
+        spec
+    }
+}
+
+impl Default for Stabilization {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Temporary file that holds the transforms between the passes, removed when dropped.
+pub(crate) struct TransformsFile {
+    path: PathBuf,
+}
+
+impl TransformsFile {
+    /// Number of random names to try before giving up.
+    const ATTEMPTS: u32 = 16;
+
+    /// Create a new, empty transforms file in the temporary directory. The name is random and the
+    /// file is created exclusively, so a file or symlink that another user planted at the path is
+    /// never written through.
+    pub(crate) fn new() -> Result<Self> {
+        let dir = std::env::temp_dir();
+        let mut last_err = None;
+        for _ in 0..Self::ATTEMPTS {
+            let path = dir.join(format!("rsmedia-vidstab-{:016x}.trf", random_u64()));
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(_) => return Ok(Self { path }),
+                Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => last_err = Some(err),
+                Err(err) => {
+                    last_err = Some(err);
+                    break;
+                }
+            }
+        }
+        if let Some(err) = last_err {
+            tracing::error!(
+                "failed to create transforms file in {}: {err}",
+                dir.display()
+            );
+        }
+        Err(Error::BackendError(AvError::External))
+    }
+
+    /// Get the path of the file.
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TransformsFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Get a random number for a temporary file name, from the randomly seeded hasher of the standard
+/// library mixed with the time, the process and a counter.
+fn random_u64() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u32(std::process::id());
+    if let Ok(elapsed) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(elapsed.as_nanos());
+    }
+    hasher.finish()
+}
+
+/// Escape a path for use as a filter option value in a filter graph description, which takes two
+/// levels of escaping: one for the option and one for the graph.
+///
+/// # Arguments
+///
+/// * `path` - Path to escape.
+fn escape_path(path: &Path) -> String {
+    escape_filter_graph(&escape_filter_option(&path.to_string_lossy()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_specs() {
+        let stabilization = Stabilization::new();
+        let path = Path::new("/tmp/transforms.trf");
+        assert_eq!(
+            stabilization.detect_spec(path),
+            "vidstabdetect=shakiness=5:accuracy=15:tripod=0:result=/tmp/transforms.trf",
+        );
+        assert_eq!(
+            stabilization.transform_spec(path),
+            "vidstabtransform=input=/tmp/transforms.trf:smoothing=10:tripod=0:crop=keep:\
+             optzoom=1:zoom=0,unsharp=5:5:0.8:3:3:0.4",
+        );
+    }
+
+    #[test]
+    fn test_escape_path() {
+        assert_eq!(
+            escape_path(Path::new(r"C:\Temp\shaky, take 1.trf")),
+            r"C\\:\\\\Temp\\\\shaky\, take 1.trf",
+        );
+    }
+
+    #[test]
+    fn test_transforms_file() {
+        let first = TransformsFile::new().unwrap();
+        let second = TransformsFile::new().unwrap();
+        assert_ne!(first.path(), second.path());
+        assert!(first.path().exists());
+        let path = first.path().to_path_buf();
+        drop(first);
+        assert!(!path.exists());
+    }
+}
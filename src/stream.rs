@@ -1,11 +1,18 @@
+use chrono::{DateTime, Utc};
 use ffmpeg::codec::Parameters as AvCodecParameters;
+use ffmpeg::format::stream::{Disposition as AvDisposition, Stream as AvStream};
+use ffmpeg::media::Type as AvMediaType;
 use ffmpeg::{Error as AvError, Rational as AvRational};
 
 use crate::error::Error;
 use crate::io::Reader;
+use crate::time::{parse_date_time, Time};
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// Re-export internal `AvMediaType` as `MediaType` for callers.
+pub type MediaType = AvMediaType;
+
 /// Holds transferable stream information. This can be used to duplicate stream settings for the
 /// purpose of transmuxing or transcoding.
 #[derive(Clone)]
@@ -13,6 +20,8 @@ pub struct StreamInfo {
     pub index: usize,
     codec_parameters: AvCodecParameters,
     time_base: AvRational,
+    metadata: Vec<(String, String)>,
+    disposition: AvDisposition,
 }
 
 impl StreamInfo {
@@ -28,7 +37,15 @@ impl StreamInfo {
             .stream(stream_index)
             .ok_or(AvError::StreamNotFound)?;
 
-        Self::from_params(stream.parameters(), stream.time_base(), stream_index)
+        Ok(Self {
+            metadata: stream
+                .metadata()
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+            disposition: stream.disposition(),
+            ..Self::from_params(stream.parameters(), stream.time_base(), stream_index)?
+        })
     }
 
     pub fn from_params(
@@ -40,9 +57,51 @@ impl StreamInfo {
             index: stream_index,
             codec_parameters: copar,
             time_base: timebase,
+            metadata: Vec::new(),
+            disposition: AvDisposition::empty(),
         })
     }
 
+    /// Get the metadata tags of the stream, like `language` and `title`. Streams created with
+    /// [`StreamInfo::from_params`] have no tags.
+    pub fn metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+
+    /// Get the value of a metadata tag of the stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Key of the tag, matched case-insensitively like ffmpeg does.
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(tag_key, _)| tag_key.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get the language of the stream from the `language` tag, usually an ISO 639-2 code like
+    /// "eng". The "und" (undetermined) code is treated as no language.
+    pub fn language(&self) -> Option<&str> {
+        self.tag("language")
+            .filter(|language| !language.is_empty() && *language != "und")
+    }
+
+    /// Get the title of the stream from the `title` tag.
+    pub fn title(&self) -> Option<&str> {
+        self.tag("title")
+    }
+
+    /// Get the disposition flags of the stream.
+    pub fn disposition(&self) -> StreamDisposition {
+        StreamDisposition::from(self.disposition)
+    }
+
+    /// Get the raw disposition flags of the stream, to copy them to an output stream.
+    pub(crate) fn raw_disposition(&self) -> AvDisposition {
+        self.disposition
+    }
+
     /// Turn information back into parts for usage.
     ///
     /// Note: Consumes stream information object.
@@ -60,3 +119,680 @@ impl StreamInfo {
 
 unsafe impl Send for StreamInfo {}
 unsafe impl Sync for StreamInfo {}
+
+/// Disposition flags of a stream, which tell players how to present it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamDisposition {
+    /// Whether the stream is the default track of its type.
+    pub default: bool,
+    /// Whether the stream is a forced track, like subtitles that only cover foreign language
+    /// dialogue.
+    pub forced: bool,
+    /// Whether the stream is a single picture attached to the file, like the cover art of an
+    /// album, instead of a video.
+    pub attached_pic: bool,
+    /// Whether the stream is a commentary track.
+    pub commentary: bool,
+    /// Whether the stream is meant for the hearing impaired, like subtitles that describe sounds.
+    pub hearing_impaired: bool,
+    /// Whether the stream is meant for the visually impaired, like audio description.
+    pub visual_impaired: bool,
+}
+
+impl From<AvDisposition> for StreamDisposition {
+    fn from(disposition: AvDisposition) -> Self {
+        Self {
+            default: disposition.contains(AvDisposition::DEFAULT),
+            forced: disposition.contains(AvDisposition::FORCED),
+            attached_pic: disposition.contains(AvDisposition::ATTACHED_PIC),
+            commentary: disposition.contains(AvDisposition::COMMENT),
+            hearing_impaired: disposition.contains(AvDisposition::HEARING_IMPAIRED),
+            visual_impaired: disposition.contains(AvDisposition::VISUAL_IMPAIRED),
+        }
+    }
+}
+
+/// Chapter of a source, like a chapter of a film or an audiobook. See [`Reader::chapters`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    /// Identifier of the chapter, unique within the source.
+    pub id: i64,
+    /// Start of the chapter.
+    pub start: Time,
+    /// End of the chapter.
+    pub end: Time,
+    /// Title of the chapter from the `title` tag.
+    pub title: Option<String>,
+    /// All metadata tags of the chapter, including the title.
+    pub metadata: Vec<(String, String)>,
+}
+
+impl Chapter {
+    /// Describe a chapter.
+    ///
+    /// # Arguments
+    ///
+    /// * `chapter` - Chapter to describe.
+    pub(crate) fn from_chapter(chapter: &ffmpeg::format::chapter::Chapter<'_>) -> Self {
+        let metadata = chapter
+            .metadata()
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        let title = metadata
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("title"))
+            .map(|(_, title)| title.clone());
+        Self {
+            id: chapter.id(),
+            start: Time::new(Some(chapter.start()), chapter.time_base()),
+            end: Time::new(Some(chapter.end()), chapter.time_base()),
+            title,
+            metadata,
+        }
+    }
+}
+
+/// Describes a stream (a media description in the SDP for RTSP sources) that is available in a
+/// [`Reader`]. The disposition flags that are not described here are available from
+/// [`StreamInfo::disposition`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MediaDescription {
+    /// Index of the stream in the reader.
+    pub stream_index: usize,
+    /// Type of media in the stream.
+    pub media_type: MediaType,
+    /// Name of the codec, like "h264".
+    pub codec_name: String,
+    /// Width of the video, 0 for other media types or if unknown.
+    pub width: u32,
+    /// Height of the video, 0 for other media types or if unknown.
+    pub height: u32,
+    /// Bit rate announced by the source, 0 if unknown.
+    pub bit_rate: u64,
+    /// Language of the stream from the `language` tag, usually an ISO 639-2 code like "eng".
+    pub language: Option<String>,
+    /// Title of the stream from the `title` tag.
+    pub title: Option<String>,
+    /// Whether the stream is flagged as default track.
+    pub default: bool,
+    /// Whether the stream is flagged as forced track, like subtitles that only cover foreign
+    /// language dialogue.
+    pub forced: bool,
+    /// Whether the stream is flagged as commentary track.
+    pub commentary: bool,
+    /// Whether the stream is an attached picture, like the cover art of a music file, rather than
+    /// a video.
+    pub attached_picture: bool,
+    /// Time the stream was recorded, from the `creation_time` tag.
+    pub creation_time: Option<DateTime<Utc>>,
+}
+
+impl MediaDescription {
+    /// Describe a stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream` - Stream to describe.
+    pub(crate) fn from_stream(stream: &AvStream) -> Self {
+        let parameters = stream.parameters();
+        let (width, height, bit_rate) = unsafe {
+            let codec_parameters = &*parameters.as_ptr();
+            (
+                codec_parameters.width.max(0) as u32,
+                codec_parameters.height.max(0) as u32,
+                codec_parameters.bit_rate.max(0) as u64,
+            )
+        };
+        let codec_name = parameters.id().name().to_string();
+        let media_type = parameters.medium();
+        let metadata = stream.metadata();
+        let language = metadata
+            .get("language")
+            .filter(|language| !language.is_empty() && *language != "und")
+            .map(|language| language.into_owned());
+        let title = metadata.get("title").map(|title| title.into_owned());
+        let creation_time = metadata
+            .get("creation_time")
+            .and_then(|value| parse_date_time(&value));
+        let disposition = stream.disposition();
+        Self {
+            stream_index: stream.index(),
+            media_type,
+            codec_name,
+            width,
+            height,
+            bit_rate,
+            language,
+            title,
+            default: disposition.contains(AvDisposition::DEFAULT),
+            forced: disposition.contains(AvDisposition::FORCED),
+            commentary: disposition.contains(AvDisposition::COMMENT),
+            attached_picture: disposition.contains(AvDisposition::ATTACHED_PIC),
+            creation_time,
+        }
+    }
+
+    /// Get the index of the stream in the reader.
+    pub fn stream_index(&self) -> usize {
+        self.stream_index
+    }
+
+    /// Get the type of media in the stream.
+    pub fn media_type(&self) -> MediaType {
+        self.media_type
+    }
+
+    /// Get the name of the codec, like "h264".
+    pub fn codec_name(&self) -> &str {
+        &self.codec_name
+    }
+
+    /// Get the size of the video as `(width, height)`, `(0, 0)` for other media types or if
+    /// unknown.
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Get the bit rate announced by the source, 0 if unknown.
+    pub fn bit_rate(&self) -> u64 {
+        self.bit_rate
+    }
+
+    /// Get the language of the stream from the `language` tag, if any.
+    pub fn language(&self) -> Option<&str> {
+        self.language.as_deref()
+    }
+
+    /// Get the title of the stream from the `title` tag, if any.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Whether the stream is flagged as default track.
+    pub fn is_default(&self) -> bool {
+        self.default
+    }
+
+    /// Whether the stream is flagged as forced track.
+    pub fn is_forced(&self) -> bool {
+        self.forced
+    }
+
+    /// Whether the stream is flagged as commentary track. Streams of which only the title
+    /// mentions commentary are not.
+    pub fn is_commentary(&self) -> bool {
+        self.commentary
+    }
+
+    /// Whether the stream is an attached picture, like the cover art of a music file.
+    pub fn is_attached_picture(&self) -> bool {
+        self.attached_picture
+    }
+
+    /// Get the time the stream was recorded, from the `creation_time` tag.
+    pub fn creation_time(&self) -> Option<DateTime<Utc>> {
+        self.creation_time
+    }
+
+    /// Check whether the stream is in a language, by its language tag or else by its title.
+    ///
+    /// # Return value
+    ///
+    /// `Some(true)` if the language tag matches, `Some(false)` if only the title mentions the
+    /// language, and `None` if the stream is not in the language.
+    fn matches_language(&self, language: &str) -> Option<bool> {
+        if self
+            .language
+            .as_ref()
+            .is_some_and(|stream_language| stream_language.eq_ignore_ascii_case(language))
+        {
+            Some(true)
+        } else if self
+            .title
+            .as_ref()
+            .is_some_and(|title| title.to_lowercase().contains(&language.to_lowercase()))
+        {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the stream is commentary, by its disposition or else by its title.
+    fn is_likely_commentary(&self) -> bool {
+        self.commentary
+            || self
+                .title
+                .as_ref()
+                .is_some_and(|title| title.to_lowercase().contains("commentary"))
+    }
+
+    /// Number of pixels in a frame, used to compare resolutions.
+    fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+/// Preference for selecting one of multiple video streams with different resolutions, like the
+/// main-stream and sub-stream of an IP camera.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolutionPreference {
+    /// Select the video stream with the highest resolution (usually the main-stream).
+    Highest,
+    /// Select the video stream with the lowest resolution (usually the sub-stream).
+    Lowest,
+    /// Select the video stream with the resolution closest to the given width and height.
+    Closest(u32, u32),
+}
+
+impl ResolutionPreference {
+    /// Select a video stream from the media descriptions according to the preference.
+    ///
+    /// # Arguments
+    ///
+    /// * `media_descriptions` - Available media descriptions.
+    ///
+    /// # Return value
+    ///
+    /// Index of the selected stream, or `None` if there are no video streams. Attached pictures
+    /// are not video streams. Streams of which the size is unknown are only selected if no stream
+    /// has a known size, in which case the first one is.
+    pub(crate) fn select(self, media_descriptions: &[MediaDescription]) -> Option<usize> {
+        let video = media_descriptions.iter().filter(|description| {
+            description.media_type == AvMediaType::Video && !description.attached_picture
+        });
+        let (sized, unknown_size): (Vec<_>, Vec<_>) =
+            video.partition(|description| description.width > 0 && description.height > 0);
+        if sized.is_empty() {
+            return unknown_size
+                .first()
+                .map(|description| description.stream_index);
+        }
+        let video = sized.into_iter();
+        match self {
+            ResolutionPreference::Highest => video.max_by_key(|description| description.area()),
+            ResolutionPreference::Lowest => video.min_by_key(|description| description.area()),
+            ResolutionPreference::Closest(width, height) => video.min_by_key(|description| {
+                (description.width as i64 - width as i64).unsigned_abs()
+                    + (description.height as i64 - height as i64).unsigned_abs()
+            }),
+        }
+        .map(|description| description.stream_index)
+    }
+}
+
+/// Select the track of a media type in a language, like a player picks the audio or subtitle
+/// track for the language of the user.
+///
+/// Streams with a matching language tag are preferred over streams that only mention the language
+/// in their title. Among those, regular tracks are preferred over commentary and forced tracks,
+/// then tracks flagged as default, then the first track.
+///
+/// # Arguments
+///
+/// * `media_descriptions` - Available media descriptions.
+/// * `media_type` - Media type of the track.
+/// * `language` - Language of the track, matched case-insensitively against the language tag
+///   (usually an ISO 639-2 code like "eng") or the title (like "English").
+///
+/// # Return value
+///
+/// Index of the selected stream, or `None` if no track of the media type is in the language.
+pub(crate) fn select_track(
+    media_descriptions: &[MediaDescription],
+    media_type: MediaType,
+    language: &str,
+) -> Option<usize> {
+    media_descriptions
+        .iter()
+        .filter(|description| description.media_type == media_type)
+        .filter_map(|description| {
+            description
+                .matches_language(language)
+                .map(|by_tag| (description, by_tag))
+        })
+        .min_by_key(|(description, by_tag)| {
+            (
+                !by_tag,
+                description.is_likely_commentary(),
+                description.forced,
+                !description.default,
+            )
+        })
+        .map(|(description, _)| description.stream_index)
+}
+
+/// Selects streams of a [`Reader`] with include and exclude rules, like the `-map` option of the
+/// ffmpeg command line tool.
+///
+/// A stream is selected when it matches at least one include rule and no exclude rule. Selected
+/// streams keep the order in which they appear in the source.
+///
+/// # Example
+///
+/// Select the first video stream and all English audio streams, and no data streams:
+///
+/// ```ignore
+/// let reader = Reader::new(Path::new("movie.mkv")).unwrap();
+/// let map = StreamMap::new()
+///     .include(StreamSelector::video().first())
+///     .include(StreamSelector::audio().with_language("eng"))
+///     .exclude(StreamSelector::media_type(MediaType::Data));
+/// let mut muxer = MuxerBuilder::new(writer)
+///     .with_stream_map(&reader, &map)
+///     .unwrap()
+///     .build();
+/// while let Ok(packet) = reader.read_any() {
+///     if muxer.has_stream(packet.stream_index()) {
+///         muxer.mux(packet).unwrap();
+///     }
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct StreamMap {
+    includes: Vec<StreamSelector>,
+    excludes: Vec<StreamSelector>,
+}
+
+impl StreamMap {
+    /// Create an empty [`StreamMap`] that selects no streams.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Select the streams that match a selector.
+    ///
+    /// # Arguments
+    ///
+    /// * `selector` - Streams to select.
+    pub fn include(mut self, selector: StreamSelector) -> Self {
+        self.includes.push(selector);
+        self
+    }
+
+    /// Leave out the streams that match a selector, even if they match an include rule.
+    ///
+    /// # Arguments
+    ///
+    /// * `selector` - Streams to leave out.
+    pub fn exclude(mut self, selector: StreamSelector) -> Self {
+        self.excludes.push(selector);
+        self
+    }
+
+    /// Apply the map to the streams of a reader.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader to select streams from.
+    ///
+    /// # Return value
+    ///
+    /// Indices of the selected streams, in the order of the source.
+    pub fn select(&self, reader: &Reader) -> Vec<usize> {
+        self.select_from(&reader.media_descriptions())
+    }
+
+    /// Apply the map to stream descriptions.
+    ///
+    /// # Arguments
+    ///
+    /// * `media_descriptions` - Descriptions of the available streams.
+    pub(crate) fn select_from(&self, media_descriptions: &[MediaDescription]) -> Vec<usize> {
+        let candidates = media_descriptions
+            .iter()
+            .filter(|description| {
+                !self
+                    .excludes
+                    .iter()
+                    .any(|selector| selector.matches(description))
+            })
+            .collect::<Vec<_>>();
+        let mut selected = vec![false; candidates.len()];
+        for selector in &self.includes {
+            let mut matches = candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, description)| selector.matches(description));
+            if selector.first {
+                if let Some((position, _)) = matches.next() {
+                    selected[position] = true;
+                }
+            } else {
+                matches.for_each(|(position, _)| selected[position] = true);
+            }
+        }
+        candidates
+            .iter()
+            .zip(selected)
+            .filter(|(_, selected)| *selected)
+            .map(|(description, _)| description.stream_index)
+            .collect()
+    }
+}
+
+/// Matches streams by media type, language and index. See [`StreamMap`].
+///
+/// All conditions of a selector must hold for a stream to match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamSelector {
+    media_type: Option<MediaType>,
+    language: Option<String>,
+    index: Option<usize>,
+    first: bool,
+}
+
+impl StreamSelector {
+    /// Match all streams.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Match streams of a media type.
+    ///
+    /// # Arguments
+    ///
+    /// * `media_type` - Media type to match.
+    pub fn media_type(media_type: MediaType) -> Self {
+        Self {
+            media_type: Some(media_type),
+            ..Self::default()
+        }
+    }
+
+    /// Match video streams.
+    pub fn video() -> Self {
+        Self::media_type(MediaType::Video)
+    }
+
+    /// Match audio streams.
+    pub fn audio() -> Self {
+        Self::media_type(MediaType::Audio)
+    }
+
+    /// Match subtitle streams.
+    pub fn subtitle() -> Self {
+        Self::media_type(MediaType::Subtitle)
+    }
+
+    /// Match the stream with an index.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Index of the stream in the source.
+    pub fn index(index: usize) -> Self {
+        Self {
+            index: Some(index),
+            ..Self::default()
+        }
+    }
+
+    /// Only match streams with a language tag, compared case-insensitively.
+    ///
+    /// # Arguments
+    ///
+    /// * `language` - Language to match, usually an ISO 639-2 code like "eng".
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    /// Only match the first stream that matches the other conditions (and is not excluded).
+    pub fn first(mut self) -> Self {
+        self.first = true;
+        self
+    }
+
+    fn matches(&self, description: &MediaDescription) -> bool {
+        self.media_type
+            .is_none_or(|media_type| media_type == description.media_type)
+            && self
+                .index
+                .is_none_or(|index| index == description.stream_index)
+            && self.language.as_ref().is_none_or(|language| {
+                description
+                    .language
+                    .as_ref()
+                    .is_some_and(|stream_language| stream_language.eq_ignore_ascii_case(language))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn description(stream_index: usize, media_type: MediaType, language: &str) -> MediaDescription {
+        MediaDescription {
+            stream_index,
+            media_type,
+            codec_name: String::new(),
+            width: 0,
+            height: 0,
+            bit_rate: 0,
+            language: (!language.is_empty()).then(|| language.to_string()),
+            title: None,
+            default: false,
+            forced: false,
+            commentary: false,
+            attached_picture: false,
+            creation_time: None,
+        }
+    }
+
+    fn video(stream_index: usize, width: u32, height: u32) -> MediaDescription {
+        MediaDescription {
+            width,
+            height,
+            ..description(stream_index, MediaType::Video, "")
+        }
+    }
+
+    #[test]
+    fn test_resolution_preference_skips_pictures_and_unknown_sizes() {
+        let mut cover = video(0, 100, 100);
+        cover.attached_picture = true;
+        let descriptions = [
+            cover,
+            video(1, 0, 0),
+            video(2, 1920, 1080),
+            video(3, 640, 360),
+            description(4, MediaType::Audio, ""),
+        ];
+        assert_eq!(ResolutionPreference::Lowest.select(&descriptions), Some(3));
+        assert_eq!(ResolutionPreference::Highest.select(&descriptions), Some(2));
+        assert_eq!(
+            ResolutionPreference::Closest(200, 100).select(&descriptions),
+            Some(3)
+        );
+        // Without known sizes, the first video stream is selected.
+        assert_eq!(
+            ResolutionPreference::Lowest.select(&descriptions[..2]),
+            Some(1)
+        );
+        assert_eq!(
+            ResolutionPreference::Lowest.select(&descriptions[..1]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stream_map_select() {
+        let descriptions = [
+            description(0, MediaType::Data, ""),
+            description(1, MediaType::Video, ""),
+            description(2, MediaType::Video, ""),
+            description(3, MediaType::Audio, "eng"),
+            description(4, MediaType::Audio, "fre"),
+            description(5, MediaType::Audio, "ENG"),
+        ];
+        let map = StreamMap::new()
+            .include(StreamSelector::video().first())
+            .include(StreamSelector::audio().with_language("eng"))
+            .exclude(StreamSelector::media_type(MediaType::Data));
+        assert_eq!(map.select_from(&descriptions), vec![1, 3, 5]);
+
+        let map = StreamMap::new()
+            .include(StreamSelector::all())
+            .exclude(StreamSelector::index(1))
+            .include(StreamSelector::video().first());
+        assert_eq!(map.select_from(&descriptions), vec![0, 2, 3, 4, 5]);
+
+        assert!(StreamMap::new().select_from(&descriptions).is_empty());
+    }
+
+    #[test]
+    fn test_select_track() {
+        let mut commentary = description(1, MediaType::Audio, "eng");
+        commentary.commentary = true;
+        let mut titled = description(2, MediaType::Audio, "");
+        titled.title = Some("English 5.1".to_string());
+        let mut default = description(4, MediaType::Audio, "eng");
+        default.default = true;
+        let descriptions = [
+            description(0, MediaType::Video, ""),
+            commentary,
+            titled,
+            description(3, MediaType::Audio, "eng"),
+            default,
+            description(5, MediaType::Subtitle, "fre"),
+        ];
+        assert_eq!(
+            select_track(&descriptions, MediaType::Audio, "ENG"),
+            Some(4)
+        );
+        assert_eq!(
+            select_track(&descriptions[..4], MediaType::Audio, "eng"),
+            Some(3)
+        );
+        assert_eq!(
+            select_track(&descriptions[..3], MediaType::Audio, "english"),
+            Some(2)
+        );
+        assert_eq!(
+            select_track(&descriptions, MediaType::Subtitle, "fre"),
+            Some(5)
+        );
+        assert_eq!(
+            select_track(&descriptions, MediaType::Subtitle, "eng"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_stream_disposition() {
+        let disposition = StreamDisposition::from(
+            AvDisposition::DEFAULT | AvDisposition::ATTACHED_PIC | AvDisposition::KARAOKE,
+        );
+        assert_eq!(
+            disposition,
+            StreamDisposition {
+                default: true,
+                attached_pic: true,
+                ..StreamDisposition::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,884 @@
+//! Decoding of subtitle streams, conversion between text subtitle formats, like ASS in Matroska to
+//! `mov_text` in MP4 or SubRip to WebVTT for web players, and export of bitmap subtitles to
+//! images.
+
+use std::collections::VecDeque;
+
+use ffmpeg::codec::packet::{flag::Flags as AvPacketFlags, Packet as AvPacket};
+use ffmpeg::codec::subtitle::{Bitmap as AvSubtitleBitmap, Rect as AvSubtitleRect};
+use ffmpeg::codec::Context as AvContext;
+use ffmpeg::codec::Id as AvCodecId;
+use ffmpeg::codec::Parameters as AvCodecParameters;
+use ffmpeg::decoder::subtitle::Subtitle as AvSubtitleDecoder;
+use ffmpeg::encoder::subtitle::Encoder as AvSubtitleEncoder;
+use ffmpeg::util::format::Pixel as AvPixel;
+use ffmpeg::util::frame::Video as AvFrame;
+use ffmpeg::util::mathematics::rescale::TIME_BASE;
+use ffmpeg::{Error as AvError, Subtitle as AvSubtitle};
+
+use crate::error::Error;
+use crate::ffi;
+use crate::io::Reader;
+use crate::packet::Packet;
+use crate::stream::{MediaType, StreamInfo};
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Decodes packets of a text subtitle stream and encodes them with another text subtitle codec,
+/// optionally shifting and scaling the timestamps.
+///
+/// Supported codecs are the text subtitle codecs that ffmpeg can both decode and encode, like
+/// `AvCodecId::ASS`, `AvCodecId::SUBRIP`, `AvCodecId::WEBVTT` and `AvCodecId::MOV_TEXT`. Bitmap
+/// subtitles (like DVD or PGS subtitles) cannot be converted to text.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut reader = Reader::new(Path::new("movie.mkv")).unwrap();
+/// let subtitle_index = reader.select_subtitle("eng").unwrap();
+/// let mut transcoder =
+///     SubtitleTranscoder::new(reader.stream_info(subtitle_index).unwrap(), AvCodecId::MOV_TEXT)
+///         .unwrap()
+///         .with_offset(Time::from_secs(-1.5));
+/// let mut muxer = MuxerBuilder::new(WriterBuilder::new(Path::new("movie.mp4")).build().unwrap())
+///     .with_stream(reader.stream_info(video_index).unwrap())
+///     .unwrap()
+///     .with_stream(transcoder.stream_info().unwrap())
+///     .unwrap()
+///     .build();
+/// while let Ok(packet) = reader.read_any() {
+///     if packet.stream_index() == subtitle_index {
+///         if let Some(packet) = transcoder.transcode(packet).unwrap() {
+///             muxer.mux(packet).unwrap();
+///         }
+///     } else if packet.stream_index() == video_index {
+///         muxer.mux(packet).unwrap();
+///     }
+/// }
+/// muxer.finish().unwrap();
+/// ```
+pub struct SubtitleTranscoder {
+    stream_index: usize,
+    decoder: AvSubtitleDecoder,
+    encoder: AvSubtitleEncoder,
+    offset: i64,
+    scale: f64,
+}
+
+impl SubtitleTranscoder {
+    /// Create a transcoder for a subtitle stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_info` - Stream information of the source subtitle stream.
+    /// * `codec_id` - Text subtitle codec to encode to.
+    pub fn new(stream_info: StreamInfo, codec_id: AvCodecId) -> Result<Self> {
+        let (stream_index, parameters, time_base) = stream_info.into_parts();
+        if parameters.medium() != MediaType::Subtitle {
+            return Err(Error::UnsupportedCodec);
+        }
+        let mut decoder = AvContext::from_parameters(parameters)?.decoder();
+        // The decoder needs the time base of the packets to compute the timestamps of subtitles.
+        decoder.set_packet_time_base(time_base);
+        let decoder = decoder.subtitle()?;
+
+        let codec = ffmpeg::encoder::find(codec_id).ok_or(AvError::EncoderNotFound)?;
+        if codec.medium() != MediaType::Subtitle {
+            return Err(Error::UnsupportedCodec);
+        }
+        let mut encoder = AvContext::new_with_codec(codec).encoder().subtitle()?;
+        encoder.set_time_base(TIME_BASE);
+        ffi::copy_subtitle_header(&decoder, &mut encoder)?;
+        let encoder = encoder.open_as(codec)?;
+
+        Ok(Self {
+            stream_index,
+            decoder,
+            encoder,
+            offset: 0,
+            scale: 1.0,
+        })
+    }
+
+    /// Shift the timestamps of the subtitles. Subtitles that end before zero after shifting are
+    /// dropped, and subtitles that start before zero are cut off at zero.
+    ///
+    /// # Arguments
+    ///
+    /// * `offset` - Time to add to every timestamp. May be negative.
+    pub fn with_offset(mut self, offset: Time) -> Self {
+        self.offset = offset
+            .aligned_with_rational(TIME_BASE)
+            .into_value()
+            .unwrap_or(0);
+        self
+    }
+
+    /// Scale the timestamps and durations of the subtitles, for instance to follow a frame rate
+    /// change from 25 to 23.976 fps (a scale of `25.0 / 23.976`). Timestamps are scaled before the
+    /// offset is added.
+    ///
+    /// # Arguments
+    ///
+    /// * `scale` - Factor to multiply timestamps with. Must be greater than zero.
+    pub fn with_scale(mut self, scale: f64) -> Self {
+        if scale > 0.0 && scale.is_finite() {
+            self.scale = scale;
+        }
+        self
+    }
+
+    /// Stream information of the converted stream, to add it to a muxer with
+    /// [`MuxerBuilder::with_stream`](crate::mux::MuxerBuilder::with_stream). The stream has the
+    /// index of the source stream.
+    pub fn stream_info(&self) -> Result<StreamInfo> {
+        StreamInfo::from_params(
+            AvCodecParameters::from(&self.encoder),
+            TIME_BASE,
+            self.stream_index,
+        )
+    }
+
+    /// Convert a packet of the source stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet of the source stream.
+    ///
+    /// # Return value
+    ///
+    /// Converted packet, or `None` if the packet did not hold a subtitle that can be shown, like
+    /// packets that only clear the screen or subtitles that were shifted before zero.
+    pub fn transcode(&mut self, packet: Packet) -> Result<Option<Packet>> {
+        let packet = packet.into_inner();
+        let mut subtitle = AvSubtitle::new();
+        if !self.decoder.decode(&packet, &mut subtitle)? {
+            return Ok(None);
+        }
+        let result = self.encode(&mut subtitle);
+        ffi::free_subtitle(&mut subtitle);
+        result
+    }
+
+    fn encode(&mut self, subtitle: &mut AvSubtitle) -> Result<Option<Packet>> {
+        if subtitle
+            .rects()
+            .any(|rect| matches!(rect, AvSubtitleRect::Bitmap(_)))
+        {
+            return Err(Error::UnsupportedCodec);
+        }
+        if subtitle.rects().len() == 0 {
+            return Ok(None);
+        }
+        let Some(pts) = subtitle.pts() else {
+            tracing::warn!(
+                "dropping subtitle of stream {} without timestamp",
+                self.stream_index
+            );
+            return Ok(None);
+        };
+
+        // Display times are in milliseconds relative to the timestamp, which is in microseconds.
+        let start = pts + i64::from(subtitle.start()) * 1000;
+        let duration = i64::from(subtitle.end().saturating_sub(subtitle.start())) * 1000;
+        let Some((start, duration)) = retime(start, duration, self.offset, self.scale) else {
+            return Ok(None);
+        };
+        subtitle.set_pts(Some(start));
+        subtitle.set_start(0);
+        subtitle.set_end((duration / 1000).min(i64::from(u32::MAX)) as u32);
+
+        let data = ffi::encode_subtitle(&mut self.encoder, subtitle)?;
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let mut packet = AvPacket::copy(&data);
+        packet.set_stream(self.stream_index);
+        packet.set_pts(Some(start));
+        packet.set_dts(Some(start));
+        packet.set_duration(duration);
+        packet.set_flags(AvPacketFlags::KEY);
+        Ok(Some(Packet::new(packet, TIME_BASE)))
+    }
+}
+
+unsafe impl Send for SubtitleTranscoder {}
+
+/// Image of a bitmap subtitle, converted from its palette to RGBA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubtitleImage {
+    /// Horizontal position of the image in the video frame.
+    pub x: usize,
+    /// Vertical position of the image in the video frame.
+    pub y: usize,
+    /// Width of the image.
+    pub width: u32,
+    /// Height of the image.
+    pub height: u32,
+    /// Pixels as RGBA, four bytes per pixel and rows without padding.
+    pub rgba: Vec<u8>,
+}
+
+impl SubtitleImage {
+    /// Convert a bitmap rect of a decoded subtitle.
+    ///
+    /// # Return value
+    ///
+    /// The image, or `None` if the bitmap is empty.
+    fn of(bitmap: &AvSubtitleBitmap) -> Option<Self> {
+        if bitmap.width() == 0 || bitmap.height() == 0 {
+            return None;
+        }
+        Some(SubtitleImage {
+            x: bitmap.x(),
+            y: bitmap.y(),
+            width: bitmap.width(),
+            height: bitmap.height(),
+            rgba: indexed_to_rgba(
+                bitmap.indices(),
+                bitmap.stride(),
+                bitmap.width() as usize,
+                bitmap.height() as usize,
+                bitmap.palette(),
+            ),
+        })
+    }
+
+    /// Encode the image as PNG.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let codec = ffmpeg::encoder::find(AvCodecId::PNG).ok_or(AvError::EncoderNotFound)?;
+        let mut encoder = AvContext::new_with_codec(codec).encoder().video()?;
+        encoder.set_width(self.width);
+        encoder.set_height(self.height);
+        encoder.set_format(AvPixel::RGBA);
+        encoder.set_time_base((1, 1));
+        let mut encoder = encoder.open_as(codec)?;
+
+        let mut frame = AvFrame::new(AvPixel::RGBA, self.width, self.height);
+        let stride = frame.stride(0);
+        let row_size = self.width as usize * 4;
+        let data = frame.data_mut(0);
+        for (row, pixels) in self.rgba.chunks_exact(row_size).enumerate() {
+            data[row * stride..row * stride + row_size].copy_from_slice(pixels);
+        }
+        frame.set_pts(Some(0));
+
+        encoder.send_frame(&frame)?;
+        encoder.send_eof()?;
+        let mut packet = AvPacket::empty();
+        encoder.receive_packet(&mut packet)?;
+        Ok(packet.data().unwrap_or_default().to_vec())
+    }
+}
+
+/// A bitmap subtitle and the time it is shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitmapSubtitle {
+    /// Time the subtitle appears.
+    pub start: Time,
+    /// Time the subtitle disappears. Has no value if the stream ended while it was shown.
+    pub end: Time,
+    /// Images of the subtitle, usually one per line or region.
+    pub images: Vec<SubtitleImage>,
+}
+
+/// Decodes bitmap subtitle streams (like PGS, DVB or DVD subtitles) into RGBA images, for OCR or
+/// review tools.
+///
+/// Formats like PGS do not store how long a subtitle is shown, but clear the screen with the next
+/// packet instead. Such subtitles are held back until the next packet, so every subtitle is
+/// returned with its end time.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut reader = Reader::new(Path::new("movie.mkv")).unwrap();
+/// let subtitle_index = reader.select_subtitle("eng").unwrap();
+/// let mut exporter =
+///     BitmapSubtitleExporter::new(reader.stream_info(subtitle_index).unwrap()).unwrap();
+/// let mut manifest = SubtitleManifest::default();
+/// let mut subtitles = Vec::new();
+/// while let Ok(packet) = reader.read(subtitle_index) {
+///     subtitles.extend(exporter.decode(packet).unwrap());
+/// }
+/// subtitles.extend(exporter.flush());
+/// for (number, subtitle) in subtitles.iter().enumerate() {
+///     for (region, image) in subtitle.images.iter().enumerate() {
+///         let file_name = format!("{number:05}-{region}.png");
+///         std::fs::write(&file_name, image.to_png().unwrap()).unwrap();
+///         manifest.push(file_name, subtitle, image);
+///     }
+/// }
+/// std::fs::write("manifest.csv", manifest.to_csv()).unwrap();
+/// ```
+pub struct BitmapSubtitleExporter {
+    stream_index: usize,
+    decoder: AvSubtitleDecoder,
+    pending: Option<BitmapSubtitle>,
+}
+
+impl BitmapSubtitleExporter {
+    /// Create an exporter for a bitmap subtitle stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_info` - Stream information of the subtitle stream.
+    pub fn new(stream_info: StreamInfo) -> Result<Self> {
+        let (stream_index, parameters, time_base) = stream_info.into_parts();
+        if parameters.medium() != MediaType::Subtitle {
+            return Err(Error::UnsupportedCodec);
+        }
+        let mut decoder = AvContext::from_parameters(parameters)?.decoder();
+        decoder.set_packet_time_base(time_base);
+        let decoder = decoder.subtitle()?;
+        Ok(Self {
+            stream_index,
+            decoder,
+            pending: None,
+        })
+    }
+
+    /// Decode a packet of the subtitle stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet of the subtitle stream.
+    ///
+    /// # Return value
+    ///
+    /// Subtitles of which the end time is now known. Usually zero or one.
+    pub fn decode(&mut self, packet: Packet) -> Result<Vec<BitmapSubtitle>> {
+        let packet = packet.into_inner();
+        let mut subtitle = AvSubtitle::new();
+        if !self.decoder.decode(&packet, &mut subtitle)? {
+            return Ok(Vec::new());
+        }
+        let result = self.convert(&subtitle);
+        ffi::free_subtitle(&mut subtitle);
+        let Some(subtitle) = result? else {
+            return Ok(Vec::new());
+        };
+
+        let mut subtitles = Vec::new();
+        // Every subtitle (including one without images) ends the subtitle that is shown.
+        if let Some(mut pending) = self.pending.take() {
+            pending.end = subtitle.start;
+            subtitles.push(pending);
+        }
+        if !subtitle.images.is_empty() {
+            if subtitle.end.has_value() {
+                subtitles.push(subtitle);
+            } else {
+                self.pending = Some(subtitle);
+            }
+        }
+        Ok(subtitles)
+    }
+
+    /// Get the subtitle that was still shown at the end of the stream, if any. Its end time has no
+    /// value.
+    pub fn flush(&mut self) -> Option<BitmapSubtitle> {
+        self.pending.take()
+    }
+
+    fn convert(&self, subtitle: &AvSubtitle) -> Result<Option<BitmapSubtitle>> {
+        let Some((start, end)) = display_times(subtitle) else {
+            tracing::warn!(
+                "dropping subtitle of stream {} without timestamp",
+                self.stream_index
+            );
+            return Ok(None);
+        };
+        let mut images = Vec::new();
+        for rect in subtitle.rects() {
+            match rect {
+                AvSubtitleRect::Bitmap(bitmap) => images.extend(SubtitleImage::of(&bitmap)),
+                AvSubtitleRect::None(_) => {}
+                AvSubtitleRect::Text(_) | AvSubtitleRect::Ass(_) => {
+                    return Err(Error::UnsupportedCodec)
+                }
+            }
+        }
+        Ok(Some(BitmapSubtitle { start, end, images }))
+    }
+}
+
+unsafe impl Send for BitmapSubtitleExporter {}
+
+/// Content of a decoded subtitle. A subtitle can have several, usually one per line or region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubtitleContent {
+    /// Plain text.
+    Text(String),
+    /// ASS dialogue line, with the fields `ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,
+    /// Effect,Text`. The styles it refers to are in the [`SubtitleDecoder::ass_header`].
+    Ass(String),
+    /// Image of a bitmap subtitle.
+    Bitmap(SubtitleImage),
+}
+
+/// A decoded subtitle and the time it is shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedSubtitle {
+    /// Time the subtitle appears.
+    pub start: Time,
+    /// Time the subtitle disappears. Has no value if the stream ended while it was shown.
+    pub end: Time,
+    /// Content of the subtitle.
+    pub content: Vec<SubtitleContent>,
+}
+
+impl DecodedSubtitle {
+    /// Get the text of the subtitle, with the fields and override tags of ASS dialogue lines
+    /// stripped, and one line per text or ASS content. Bitmaps are skipped.
+    pub fn text(&self) -> String {
+        self.content
+            .iter()
+            .filter_map(|content| match content {
+                SubtitleContent::Text(text) => Some(text.trim_end().to_string()),
+                SubtitleContent::Ass(line) => Some(ass_dialogue_text(line)),
+                SubtitleContent::Bitmap(_) => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Decodes a subtitle stream of a [`Reader`], like the text or bitmap subtitles of an MKV file.
+///
+/// Formats like PGS do not store how long a subtitle is shown, but clear the screen with the next
+/// packet instead. Such subtitles are held back until the next packet, so every subtitle is
+/// returned with its end time.
+///
+/// # Example
+///
+/// ```ignore
+/// let reader = Reader::new(Path::new("movie.mkv")).unwrap();
+/// let subtitle_index = reader.select_subtitle("eng").unwrap();
+/// let mut decoder = SubtitleDecoder::new(reader, subtitle_index).unwrap();
+/// let subtitles = decoder.decode_iter().collect::<Result<Vec<_>, _>>().unwrap();
+/// std::fs::write("movie.eng.srt", to_srt(&subtitles)).unwrap();
+/// ```
+pub struct SubtitleDecoder {
+    reader: Reader,
+    stream_index: usize,
+    decoder: AvSubtitleDecoder,
+    ready: VecDeque<DecodedSubtitle>,
+    pending: Option<DecodedSubtitle>,
+}
+
+impl SubtitleDecoder {
+    /// Create a decoder for a subtitle stream.
+    ///
+    /// # Arguments
+    ///
+    /// * `reader` - Reader to read packets from.
+    /// * `stream_index` - Index of the subtitle stream, like from [`Reader::select_subtitle`].
+    pub fn new(reader: Reader, stream_index: usize) -> Result<Self> {
+        let (_, parameters, time_base) = reader.stream_info(stream_index)?.into_parts();
+        if parameters.medium() != MediaType::Subtitle {
+            return Err(Error::UnsupportedCodec);
+        }
+        let mut decoder = AvContext::from_parameters(parameters)?.decoder();
+        decoder.set_packet_time_base(time_base);
+        let decoder = decoder.subtitle()?;
+        Ok(Self {
+            reader,
+            stream_index,
+            decoder,
+            ready: VecDeque::new(),
+            pending: None,
+        })
+    }
+
+    /// Get the index of the subtitle stream.
+    pub fn stream_index(&self) -> usize {
+        self.stream_index
+    }
+
+    /// Get the ASS header of the stream, with the script info and styles that ASS dialogue lines
+    /// refer to. Together with the dialogue lines, it makes up an ASS file.
+    pub fn ass_header(&self) -> Option<String> {
+        ffi::subtitle_header(&self.decoder)
+    }
+
+    /// Decode the next subtitle. [`Error::DecodeExhausted`] is returned when no subtitles are
+    /// left.
+    pub fn decode(&mut self) -> Result<DecodedSubtitle> {
+        loop {
+            if let Some(subtitle) = self.ready.pop_front() {
+                return Ok(subtitle);
+            }
+            match self.reader.read(self.stream_index) {
+                Ok(packet) => self.decode_packet(packet)?,
+                Err(Error::ReadExhausted) => {
+                    return self.pending.take().ok_or(Error::DecodeExhausted)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Get an iterator that decodes subtitles until the end of the stream.
+    pub fn decode_iter(&mut self) -> impl Iterator<Item = Result<DecodedSubtitle>> + '_ {
+        std::iter::from_fn(move || match self.decode() {
+            Ok(subtitle) => Some(Ok(subtitle)),
+            Err(Error::DecodeExhausted) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+
+    /// Get the reader back.
+    pub fn into_reader(self) -> Reader {
+        self.reader
+    }
+
+    fn decode_packet(&mut self, packet: Packet) -> Result<()> {
+        let packet = packet.into_inner();
+        let mut subtitle = AvSubtitle::new();
+        if !self.decoder.decode(&packet, &mut subtitle)? {
+            return Ok(());
+        }
+        let converted = self.convert(&subtitle);
+        ffi::free_subtitle(&mut subtitle);
+        let Some(subtitle) = converted else {
+            return Ok(());
+        };
+
+        // Every subtitle (including one without content) ends the subtitle that is shown.
+        if let Some(mut pending) = self.pending.take() {
+            if !pending.end.has_value() {
+                pending.end = subtitle.start;
+            }
+            self.ready.push_back(pending);
+        }
+        if !subtitle.content.is_empty() {
+            if subtitle.end.has_value() {
+                self.ready.push_back(subtitle);
+            } else {
+                self.pending = Some(subtitle);
+            }
+        }
+        Ok(())
+    }
+
+    fn convert(&self, subtitle: &AvSubtitle) -> Option<DecodedSubtitle> {
+        let Some((start, end)) = display_times(subtitle) else {
+            tracing::warn!(
+                "dropping subtitle of stream {} without timestamp",
+                self.stream_index
+            );
+            return None;
+        };
+        let content = subtitle
+            .rects()
+            .filter_map(|rect| match rect {
+                AvSubtitleRect::Bitmap(bitmap) => {
+                    SubtitleImage::of(&bitmap).map(SubtitleContent::Bitmap)
+                }
+                AvSubtitleRect::Text(text) => Some(SubtitleContent::Text(text.get().into_owned())),
+                AvSubtitleRect::Ass(ass) => Some(SubtitleContent::Ass(ass.get().into_owned())),
+                AvSubtitleRect::None(_) => None,
+            })
+            .collect();
+        Some(DecodedSubtitle {
+            start,
+            end,
+            content,
+        })
+    }
+}
+
+unsafe impl Send for SubtitleDecoder {}
+
+/// Format subtitles as a SubRip (SRT) file. Subtitles without text are skipped, and subtitles
+/// without an end time are shown for five seconds.
+///
+/// # Arguments
+///
+/// * `subtitles` - Subtitles in the order they are shown.
+pub fn to_srt(subtitles: &[DecodedSubtitle]) -> String {
+    const DEFAULT_DURATION: f64 = 5.0;
+    let mut srt = String::new();
+    let mut number = 0;
+    for subtitle in subtitles {
+        let text = subtitle.text();
+        if text.is_empty() || !subtitle.start.has_value() {
+            continue;
+        }
+        let start = subtitle.start.as_secs_f64();
+        let end = if subtitle.end.has_value() {
+            subtitle.end.as_secs_f64()
+        } else {
+            start + DEFAULT_DURATION
+        };
+        number += 1;
+        srt.push_str(&format!(
+            "{number}\n{} --> {}\n{text}\n\n",
+            srt_timestamp(start),
+            srt_timestamp(end),
+        ));
+    }
+    srt
+}
+
+/// Timing manifest of exported subtitle images, written as CSV with the columns `file`, `start`
+/// and `end` (in seconds), `x`, `y`, `width` and `height`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubtitleManifest {
+    lines: Vec<String>,
+}
+
+impl SubtitleManifest {
+    /// Add an exported image.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - Name of the file the image was written to.
+    /// * `subtitle` - Subtitle of the image.
+    /// * `image` - The image.
+    pub fn push(
+        &mut self,
+        file: impl AsRef<str>,
+        subtitle: &BitmapSubtitle,
+        image: &SubtitleImage,
+    ) {
+        let end = if subtitle.end.has_value() {
+            format!("{:.3}", subtitle.end.as_secs_f64())
+        } else {
+            String::new()
+        };
+        self.lines.push(format!(
+            "{},{:.3},{},{},{},{},{}",
+            file.as_ref(),
+            subtitle.start.as_secs_f64(),
+            end,
+            image.x,
+            image.y,
+            image.width,
+            image.height,
+        ));
+    }
+
+    /// Number of images in the manifest.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Whether the manifest is empty.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Format the manifest as CSV, with a header row.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("file,start,end,x,y,width,height\n");
+        for line in &self.lines {
+            csv.push_str(line);
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+/// Convert palette indices to RGBA pixels. Indices outside of the palette become transparent.
+///
+/// # Arguments
+///
+/// * `indices` - Palette index of every pixel.
+/// * `stride` - Number of bytes per row of `indices`.
+/// * `width` - Width of the image.
+/// * `height` - Height of the image.
+/// * `palette` - Colors as ARGB in native byte order.
+fn indexed_to_rgba(
+    indices: &[u8],
+    stride: usize,
+    width: usize,
+    height: usize,
+    palette: &[u32],
+) -> Vec<u8> {
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        let row_indices = indices
+            .get(row * stride..row * stride + width)
+            .unwrap_or(&[]);
+        for column in 0..width {
+            let color = row_indices
+                .get(column)
+                .and_then(|&index| palette.get(index as usize))
+                .copied()
+                .unwrap_or(0);
+            let [alpha, red, green, blue] = color.to_be_bytes();
+            rgba.extend_from_slice(&[red, green, blue, alpha]);
+        }
+    }
+    rgba
+}
+
+/// Get the display times of a decoded subtitle.
+///
+/// # Return value
+///
+/// Time the subtitle appears and disappears, or `None` if the subtitle has no timestamp. The end
+/// time has no value if the decoder does not know it.
+fn display_times(subtitle: &AvSubtitle) -> Option<(Time, Time)> {
+    let pts = subtitle.pts()?;
+    // Display times are in milliseconds relative to the timestamp, which is in microseconds.
+    let start = pts + i64::from(subtitle.start()) * 1000;
+    // Decoders that do not know the end time leave it at zero or set it to the maximum.
+    let end = match subtitle.end() {
+        end if end <= subtitle.start() || end == u32::MAX => None,
+        end => Some(pts + i64::from(end) * 1000),
+    };
+    Some((Time::new(Some(start), TIME_BASE), Time::new(end, TIME_BASE)))
+}
+
+/// Get the text of an ASS dialogue line, without the other fields and override tags (like
+/// `{\i1}`), and with line breaks and hard spaces replaced.
+///
+/// # Arguments
+///
+/// * `line` - Dialogue line with the fields `ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,
+///   Effect,Text`.
+fn ass_dialogue_text(line: &str) -> String {
+    let text = line.splitn(9, ',').nth(8).unwrap_or(line);
+    let mut plain = String::with_capacity(text.len());
+    let mut in_override = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if in_override => {}
+            '\\' => match chars.peek() {
+                Some('N') | Some('n') => {
+                    chars.next();
+                    plain.push('\n');
+                }
+                Some('h') => {
+                    chars.next();
+                    plain.push(' ');
+                }
+                _ => plain.push(c),
+            },
+            _ => plain.push(c),
+        }
+    }
+    plain.trim_end().to_string()
+}
+
+/// Format a time as an SRT timestamp, like `01:02:03,456`.
+///
+/// # Arguments
+///
+/// * `secs` - Time in seconds.
+fn srt_timestamp(secs: f64) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02},{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000,
+    )
+}
+
+/// Scale and shift a subtitle.
+///
+/// # Arguments
+///
+/// * `start` - Start of the subtitle.
+/// * `duration` - Duration of the subtitle.
+/// * `offset` - Time to add after scaling.
+/// * `scale` - Factor to multiply timestamps with.
+///
+/// # Return value
+///
+/// Start and duration of the retimed subtitle, or `None` if it ends before zero.
+fn retime(start: i64, duration: i64, offset: i64, scale: f64) -> Option<(i64, i64)> {
+    let end = ((start + duration) as f64 * scale).round() as i64 + offset;
+    let start = (start as f64 * scale).round() as i64 + offset;
+    if end <= 0 {
+        return None;
+    }
+    let start = start.max(0);
+    Some((start, end - start))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retime() {
+        assert_eq!(retime(1_000, 500, 0, 1.0), Some((1_000, 500)));
+        assert_eq!(retime(1_000, 500, 250, 1.0), Some((1_250, 500)));
+        assert_eq!(retime(1_000, 500, 0, 2.0), Some((2_000, 1_000)));
+        assert_eq!(retime(1_000, 500, -1_200, 1.0), Some((0, 300)));
+        assert_eq!(retime(1_000, 500, -1_500, 1.0), None);
+    }
+
+    #[test]
+    fn test_indexed_to_rgba() {
+        let palette = [0x00000000, 0xff112233, 0x80ffffff];
+        let indices = [1, 2, 0, 0, 7, 1];
+        assert_eq!(
+            indexed_to_rgba(&indices, 3, 2, 2, &palette),
+            vec![0x11, 0x22, 0x33, 0xff, 0xff, 0xff, 0xff, 0x80, 0, 0, 0, 0, 0, 0, 0, 0],
+        );
+    }
+
+    #[test]
+    fn test_ass_dialogue_text() {
+        assert_eq!(
+            ass_dialogue_text("0,0,Default,,0,0,0,,{\\i1}Hello,{\\i0} world\\Nagain\\hnow"),
+            "Hello, world\nagain now",
+        );
+    }
+
+    #[test]
+    fn test_to_srt() {
+        let subtitles = [
+            DecodedSubtitle {
+                start: Time::from_secs_f64(1.5),
+                end: Time::from_secs_f64(3.25),
+                content: vec![SubtitleContent::Text("Hello\n".to_string())],
+            },
+            DecodedSubtitle {
+                start: Time::from_secs_f64(3661.0),
+                end: Time::new(None, TIME_BASE),
+                content: vec![SubtitleContent::Ass(
+                    "1,0,Default,,0,0,0,,World".to_string(),
+                )],
+            },
+        ];
+        assert_eq!(
+            to_srt(&subtitles),
+            "1\n00:00:01,500 --> 00:00:03,250\nHello\n\n\
+             2\n01:01:01,000 --> 01:01:06,000\nWorld\n\n",
+        );
+    }
+
+    #[test]
+    fn test_manifest_to_csv() {
+        let image = SubtitleImage {
+            x: 10,
+            y: 20,
+            width: 2,
+            height: 1,
+            rgba: vec![0; 8],
+        };
+        let subtitle = BitmapSubtitle {
+            start: Time::from_secs_f64(1.5),
+            end: Time::from_secs_f64(3.25),
+            images: vec![image.clone()],
+        };
+        let mut manifest = SubtitleManifest::default();
+        manifest.push("00000-0.png", &subtitle, &image);
+        assert_eq!(
+            manifest.to_csv(),
+            "file,start,end,x,y,width,height\n00000-0.png,1.500,3.250,10,20,2,1\n"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Typed access to common metadata tags, like the location and device tags that phones write to
+//! their recordings.
+
+/// Geographic location where media was recorded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoLocation {
+    /// Latitude in decimal degrees, positive north of the equator.
+    pub latitude: f64,
+    /// Longitude in decimal degrees, positive east of the prime meridian.
+    pub longitude: f64,
+    /// Altitude in meters, if recorded.
+    pub altitude: Option<f64>,
+}
+
+impl GeoLocation {
+    /// Parse a location in ISO 6709 notation, like "+37.3349-122.0090+010.000/". Degrees may also
+    /// be given with minutes and seconds ("+3720.09-12200.54/").
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - Location string.
+    pub fn parse_iso6709(value: &str) -> Option<Self> {
+        // Everything after the terminating slash (like a coordinate reference system) is ignored.
+        let value = value.trim().split('/').next()?;
+        let mut components = Vec::new();
+        let mut start = 0;
+        for (position, character) in value.char_indices().skip(1) {
+            if character == '+' || character == '-' {
+                components.push(&value[start..position]);
+                start = position;
+            }
+        }
+        components.push(&value[start..]);
+
+        let (latitude, longitude, altitude) = match components.as_slice() {
+            [latitude, longitude] => (latitude, longitude, None),
+            [latitude, longitude, altitude] => (latitude, longitude, Some(altitude)),
+            _ => return None,
+        };
+        let latitude = parse_angle(latitude, 2).filter(|latitude| latitude.abs() <= 90.0)?;
+        let longitude = parse_angle(longitude, 3).filter(|longitude| longitude.abs() <= 180.0)?;
+        let altitude = match altitude {
+            Some(altitude) => Some(altitude.parse::<f64>().ok()?),
+            None => None,
+        };
+        Some(Self {
+            latitude,
+            longitude,
+            altitude,
+        })
+    }
+}
+
+/// Device that recorded media.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Manufacturer of the device, like "Apple".
+    pub make: Option<String>,
+    /// Model of the device, like "iPhone 15 Pro".
+    pub model: Option<String>,
+    /// Software or operating system version of the device.
+    pub software: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Whether none of the fields are known.
+    pub fn is_empty(&self) -> bool {
+        self.make.is_none() && self.model.is_none() && self.software.is_none()
+    }
+}
+
+/// Keys of tags with the recording location, in order of preference. The QuickTime demuxer
+/// exposes the Android `©xyz` user data box as `location`.
+pub(crate) const LOCATION_KEYS: [&str; 3] = [
+    "com.apple.quicktime.location.ISO6709",
+    "location",
+    "location-eng",
+];
+
+/// Keys of tags with the device manufacturer, in order of preference.
+pub(crate) const MAKE_KEYS: [&str; 3] = [
+    "com.apple.quicktime.make",
+    "make",
+    "com.android.manufacturer",
+];
+
+/// Keys of tags with the device model, in order of preference.
+pub(crate) const MODEL_KEYS: [&str; 3] =
+    ["com.apple.quicktime.model", "model", "com.android.model"];
+
+/// Keys of tags with the device software, in order of preference.
+pub(crate) const SOFTWARE_KEYS: [&str; 3] = [
+    "com.apple.quicktime.software",
+    "software",
+    "com.android.version",
+];
+
+/// Parse an ISO 6709 angle with a sign, in degrees (`±DD.D`), degrees and minutes (`±DDMM.M`) or
+/// degrees, minutes and seconds (`±DDMMSS.S`).
+///
+/// # Arguments
+///
+/// * `value` - Angle string.
+/// * `degree_digits` - Number of digits of the degrees: 2 for latitude and 3 for longitude.
+fn parse_angle(value: &str, degree_digits: usize) -> Option<f64> {
+    let (sign, value) = match value.split_at_checked(1)? {
+        ("+", value) => (1.0, value),
+        ("-", value) => (-1.0, value),
+        _ => return None,
+    };
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if !integer.bytes().all(|byte| byte.is_ascii_digit())
+        || !fraction.bytes().all(|byte| byte.is_ascii_digit())
+    {
+        return None;
+    }
+    // The last unit carries the fraction.
+    let (units, last) = integer.split_at(integer.len().checked_sub(2)?);
+    let last = if fraction.is_empty() {
+        last.parse::<f64>().ok()?
+    } else {
+        format!("{last}.{fraction}").parse::<f64>().ok()?
+    };
+    let part = |range: std::ops::Range<usize>| units[range].parse::<f64>().ok();
+    let degrees = match integer.len().checked_sub(degree_digits)? {
+        0 => format!("{integer}.{fraction}").parse::<f64>().ok()?,
+        2 => part(0..degree_digits)? + last / 60.0,
+        4 => {
+            part(0..degree_digits)? + part(degree_digits..degree_digits + 2)? / 60.0 + last / 3600.0
+        }
+        _ => return None,
+    };
+    Some(sign * degrees)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_iso6709() {
+        let location = GeoLocation::parse_iso6709("+37.3349-122.0090+010.000/").unwrap();
+        assert_eq!(location.latitude, 37.3349);
+        assert_eq!(location.longitude, -122.009);
+        assert_eq!(location.altitude, Some(10.0));
+
+        let location = GeoLocation::parse_iso6709("-33.8688+151.2093/").unwrap();
+        assert_eq!(location.latitude, -33.8688);
+        assert_eq!(location.longitude, 151.2093);
+        assert_eq!(location.altitude, None);
+
+        let location = GeoLocation::parse_iso6709("+4030-07400/").unwrap();
+        assert_eq!(location.latitude, 40.5);
+        assert_eq!(location.longitude, -74.0);
+
+        assert!(GeoLocation::parse_iso6709("+91.0000+000.0000/").is_none());
+        assert!(GeoLocation::parse_iso6709("somewhere").is_none());
+        assert!(GeoLocation::parse_iso6709("").is_none());
+    }
+}
@@ -0,0 +1,302 @@
+//! Thumbnails of videos, like the poster of a video in a media library, and live previews of
+//! streams, like the tiles of a dashboard that monitors many cameras.
+
+use std::time::Duration;
+
+use ffmpeg::codec::{Context as AvContext, Id as AvCodecId};
+use ffmpeg::software::scaling::context::Context as AvScaler;
+use ffmpeg::software::scaling::flag::Flags as AvScalerFlags;
+use ffmpeg::util::color::Range as AvColorRange;
+use ffmpeg::util::format::Pixel as AvPixel;
+use ffmpeg::{Error as AvError, Packet as AvPacket, Rational as AvRational};
+
+#[cfg(feature = "ndarray")]
+use crate::decode::{Decoder, DecoderBuilder};
+use crate::error::Error;
+use crate::ffi;
+#[cfg(feature = "ndarray")]
+use crate::frame::Frame;
+use crate::frame::RawFrame;
+#[cfg(feature = "ndarray")]
+use crate::location::Location;
+use crate::resize::Resize;
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// JPEG image of a frame, taken by a [`ThumbnailTap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    /// Timestamp of the frame.
+    pub time: Time,
+    /// Width of the image.
+    pub width: u32,
+    /// Height of the image.
+    pub height: u32,
+    /// Image encoded as JPEG.
+    pub jpeg: Vec<u8>,
+}
+
+/// Takes a JPEG thumbnail of a stream every so often and hands it to a callback, next to the main
+/// pipeline. Attach it to a decoder with [`Decoder::attach_thumbnail_tap`], or feed it frames
+/// with [`ThumbnailTap::process`].
+///
+/// Frames are only converted and encoded when a thumbnail is due, so the tap costs next to nothing
+/// between thumbnails. Failures to encode a thumbnail are logged and do not affect decoding.
+///
+/// # Example
+///
+/// ```ignore
+/// let tap = ThumbnailTap::new(Duration::from_secs(5), |thumbnail| {
+///     dashboard.update("camera-1", thumbnail.jpeg);
+/// })
+/// .with_resize(Resize::Fit(320, 180));
+/// let mut decoder = Decoder::new(Url::parse("rtsp://camera-1/stream").unwrap()).unwrap();
+/// decoder.attach_thumbnail_tap(tap);
+/// for frame in decoder.decode_iter() {
+///     // Main pipeline...
+/// }
+/// ```
+///
+/// [`Decoder::attach_thumbnail_tap`]: crate::decode::Decoder::attach_thumbnail_tap
+pub struct ThumbnailTap {
+    interval: f64,
+    resize: Option<Resize>,
+    qscale: u32,
+    callback: Box<dyn FnMut(Thumbnail) + Send>,
+    next: Option<f64>,
+}
+
+impl ThumbnailTap {
+    /// Default JPEG quantizer scale, which gives good looking previews at a small size.
+    const QSCALE: u32 = 5;
+
+    /// Create a tap that takes a thumbnail every interval.
+    ///
+    /// # Arguments
+    ///
+    /// * `interval` - Time between thumbnails, in stream time.
+    /// * `callback` - Function that receives the thumbnails.
+    pub fn new(interval: Duration, callback: impl FnMut(Thumbnail) + Send + 'static) -> Self {
+        Self {
+            interval: interval.as_secs_f64(),
+            resize: None,
+            qscale: Self::QSCALE,
+            callback: Box::new(callback),
+            next: None,
+        }
+    }
+
+    /// Set resizing to apply to thumbnails. By default, thumbnails have the size of the frames.
+    ///
+    /// # Arguments
+    ///
+    /// * `resize` - Resizing to apply.
+    pub fn with_resize(mut self, resize: Resize) -> Self {
+        self.resize = Some(resize);
+        self
+    }
+
+    /// Set the JPEG quality.
+    ///
+    /// # Arguments
+    ///
+    /// * `qscale` - Quantizer scale from 2 (best quality) to 31 (smallest size). Values out of
+    ///   range are clamped.
+    pub fn with_quality(mut self, qscale: u32) -> Self {
+        self.qscale = qscale.clamp(2, 31);
+        self
+    }
+
+    /// Offer a frame to the tap, which takes a thumbnail of it if one is due. Frames without a
+    /// timestamp are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Decoded frame, in any pixel format.
+    /// * `time_base` - Time base of the frame timestamp.
+    pub fn process(&mut self, frame: &RawFrame, time_base: AvRational) -> Result<()> {
+        let time = Time::new(frame.timestamp(), time_base);
+        if !time.has_value() || !self.due(time.as_secs_f64()) {
+            return Ok(());
+        }
+        let thumbnail = self.encode(frame, time)?;
+        (self.callback)(thumbnail);
+        Ok(())
+    }
+
+    /// Whether a thumbnail is due at the specified time. Schedules the next thumbnail if so. Time
+    /// going backwards, like after seeking, restarts the schedule.
+    fn due(&mut self, secs: f64) -> bool {
+        match self.next {
+            Some(next) if secs < next && secs >= next - self.interval => false,
+            _ => {
+                self.next = Some(secs + self.interval);
+                true
+            }
+        }
+    }
+
+    /// Convert a frame to YUV and encode it as JPEG.
+    fn encode(&self, frame: &RawFrame, time: Time) -> Result<Thumbnail> {
+        let size = (frame.width(), frame.height());
+        let (width, height) = match self.resize {
+            Some(resize) => resize
+                .compute_for(size)
+                .ok_or(Error::InvalidResizeParameters)?,
+            None => size,
+        };
+        // The JPEG encoder works with chroma subsampled by two.
+        let (width, height) = ((width & !1).max(2), (height & !1).max(2));
+
+        let mut scaler = AvScaler::get(
+            frame.format(),
+            frame.width(),
+            frame.height(),
+            AvPixel::YUVJ420P,
+            width,
+            height,
+            AvScalerFlags::AREA,
+        )?;
+        let mut image = RawFrame::empty();
+        scaler.run(frame, &mut image)?;
+        image.set_color_range(AvColorRange::JPEG);
+        image.set_pts(Some(0));
+        ffi::set_frame_qscale(&mut image, self.qscale);
+
+        let codec = ffmpeg::encoder::find(AvCodecId::MJPEG).ok_or(AvError::EncoderNotFound)?;
+        let mut encoder = AvContext::new_with_codec(codec).encoder().video()?;
+        encoder.set_width(width);
+        encoder.set_height(height);
+        encoder.set_format(AvPixel::YUVJ420P);
+        encoder.set_color_range(AvColorRange::JPEG);
+        encoder.set_time_base((1, 1));
+        encoder.set_flags(ffmpeg::codec::Flags::QSCALE);
+        encoder.set_global_quality(self.qscale as i32 * ffmpeg::ffi::FF_QP2LAMBDA as i32);
+        let mut encoder = encoder.open_as(codec)?;
+
+        encoder.send_frame(&image)?;
+        encoder.send_eof()?;
+        let mut packet = AvPacket::empty();
+        encoder.receive_packet(&mut packet)?;
+        Ok(Thumbnail {
+            time,
+            width,
+            height,
+            jpeg: packet.data().unwrap_or_default().to_vec(),
+        })
+    }
+}
+
+/// Extract a thumbnail of a video, like a poster for a media library.
+///
+/// The decoder seeks to the keyframe near the timestamp and decodes up to the first frame at or
+/// after it. If the video ends before the timestamp, the last frame is used.
+///
+/// # Arguments
+///
+/// * `source` - Source to extract the thumbnail from.
+/// * `at` - Timestamp of the thumbnail.
+/// * `size` - Size to fit the thumbnail in. The aspect ratio of the video is kept.
+///
+/// # Return value
+///
+/// The thumbnail as RGB24 frame with dimensions `(H, W, C)`.
+///
+/// # Example
+///
+/// ```ignore
+/// let poster = extract_thumbnail(Path::new("movie.mp4"), Time::from_secs(30.0), (320, 180))?;
+/// ```
+#[cfg(feature = "ndarray")]
+pub fn extract_thumbnail(source: impl Into<Location>, at: Time, size: (u32, u32)) -> Result<Frame> {
+    let mut decoder = open_for_thumbnails(source.into(), size)?;
+    decode_at(&mut decoder, at)
+}
+
+/// Extract thumbnails of a video at multiple timestamps, like for a storyboard or a seek bar
+/// preview. The source is opened once for all thumbnails. See [`extract_thumbnail`].
+///
+/// # Arguments
+///
+/// * `source` - Source to extract the thumbnails from.
+/// * `at` - Timestamps of the thumbnails.
+/// * `size` - Size to fit the thumbnails in. The aspect ratio of the video is kept.
+///
+/// # Return value
+///
+/// The thumbnails in the order of the timestamps.
+#[cfg(feature = "ndarray")]
+pub fn extract_thumbnails(
+    source: impl Into<Location>,
+    at: &[Time],
+    size: (u32, u32),
+) -> Result<Vec<Frame>> {
+    let mut decoder = open_for_thumbnails(source.into(), size)?;
+    at.iter()
+        .map(|&time| decode_at(&mut decoder, time))
+        .collect()
+}
+
+/// Open a decoder that scales frames to fit a thumbnail size.
+///
+/// # Arguments
+///
+/// * `source` - Source to decode.
+/// * `size` - Size to fit the frames in.
+#[cfg(feature = "ndarray")]
+fn open_for_thumbnails(source: Location, (width, height): (u32, u32)) -> Result<Decoder> {
+    DecoderBuilder::new(source)
+        .with_resize(Resize::Fit(width, height))
+        .build()
+}
+
+/// Seek near a timestamp and decode up to the first frame at or after it, or the last frame if the
+/// stream ends first.
+///
+/// # Arguments
+///
+/// * `decoder` - Decoder to decode with.
+/// * `at` - Timestamp of the frame.
+#[cfg(feature = "ndarray")]
+fn decode_at(decoder: &mut Decoder, at: Time) -> Result<Frame> {
+    let secs = at.as_secs_f64().max(0.0);
+    decoder.seek((secs * 1000.0) as i64)?;
+    let mut last = None;
+    loop {
+        match decoder.decode() {
+            Ok((time, frame)) if !time.has_value() || time.as_secs_f64() >= secs => {
+                return Ok(frame)
+            }
+            Ok((_, frame)) => last = Some(frame),
+            Err(Error::DecodeExhausted) => return last.ok_or(Error::DecodeExhausted),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_due_every_interval() {
+        let mut tap = ThumbnailTap::new(Duration::from_secs(2), |_| {});
+        let due = (0..10)
+            .map(|secs| tap.due(secs as f64 * 0.5))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            due,
+            [true, false, false, false, true, false, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn test_due_after_seek_back() {
+        let mut tap = ThumbnailTap::new(Duration::from_secs(2), |_| {});
+        assert!(tap.due(10.0));
+        assert!(!tap.due(11.0));
+        assert!(tap.due(3.0));
+        assert!(!tap.due(4.0));
+    }
+}
@@ -1,5 +1,6 @@
 use std::time::Duration;
 
+use chrono::{DateTime, NaiveDateTime, Utc};
 use ffmpeg::util::mathematics::rescale::{Rescale, TIME_BASE};
 use ffmpeg::Rational as AvRational;
 
@@ -258,6 +259,37 @@ impl Aligned {
     }
 }
 
+/// Parse a date and time from a metadata tag like `creation_time`. Accepts RFC 3339 (as written by
+/// ffmpeg, like "2024-05-01T12:34:56.000000Z"), ISO 8601 with a compact offset (as in QuickTime
+/// `com.apple.quicktime.creationdate` tags, like "2024-05-01T14:34:56+0200"), and date and time
+/// without offset, which is taken as UTC.
+///
+/// # Arguments
+///
+/// * `value` - Value of the tag.
+pub(crate) fn parse_date_time(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(value) {
+        return Some(date_time.to_utc());
+    }
+    if let Ok(date_time) = DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z") {
+        return Some(date_time.to_utc());
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|date_time| date_time.and_utc())
+}
+
+/// Format a date and time for a metadata tag like `creation_time`, the way ffmpeg does.
+///
+/// # Arguments
+///
+/// * `date_time` - Date and time to format.
+pub(crate) fn format_date_time(date_time: &DateTime<Utc>) -> String {
+    date_time.format("%Y-%m-%dT%H:%M:%S%.6fZ").to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -423,4 +455,19 @@ mod tests {
         assert_eq!(nopts.into_value(), Some(ffmpeg::ffi::AV_NOPTS_VALUE));
         assert_eq!(Duration::from(nopts).as_secs_f32(), 0.0);
     }
+
+    #[test]
+    fn test_parse_date_time() {
+        let expected = DateTime::parse_from_rfc3339("2024-05-01T12:34:56Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(
+            parse_date_time("2024-05-01T12:34:56.000000Z"),
+            Some(expected)
+        );
+        assert_eq!(parse_date_time("2024-05-01T14:34:56+0200"), Some(expected));
+        assert_eq!(parse_date_time("2024-05-01 12:34:56"), Some(expected));
+        assert_eq!(parse_date_time("yesterday"), None);
+        assert_eq!(format_date_time(&expected), "2024-05-01T12:34:56.000000Z");
+    }
 }
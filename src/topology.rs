@@ -0,0 +1,325 @@
+//! Descriptions of the stages of a decoding or encoding pipeline, for debugging and bug reports.
+
+#[cfg(feature = "filter")]
+use ffmpeg::filter::Graph as AvFilterGraph;
+use ffmpeg::util::format::Pixel as AvPixel;
+use ffmpeg::Rational as AvRational;
+
+use crate::error::Error;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Kind of a pipeline stage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StageKind {
+    /// Demuxer that reads packets from a source.
+    Reader,
+    /// Decoder that turns packets into frames.
+    Decoder,
+    /// Filter graph.
+    Filter,
+    /// Scaler that converts the size or pixel format of frames.
+    Scaler,
+    /// Encoder that turns frames into packets.
+    Encoder,
+    /// Muxer that writes packets to a destination.
+    Writer,
+}
+
+/// What flows between two pipeline stages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Packets,
+    Frames,
+}
+
+impl StageKind {
+    /// What the stage takes, or `None` if it is a source.
+    fn input(self) -> Option<Flow> {
+        match self {
+            StageKind::Reader => None,
+            StageKind::Decoder | StageKind::Writer => Some(Flow::Packets),
+            StageKind::Filter | StageKind::Scaler | StageKind::Encoder => Some(Flow::Frames),
+        }
+    }
+
+    /// What the stage outputs, or `None` if it is a sink.
+    fn output(self) -> Option<Flow> {
+        match self {
+            StageKind::Reader | StageKind::Encoder => Some(Flow::Packets),
+            StageKind::Decoder | StageKind::Filter | StageKind::Scaler => Some(Flow::Frames),
+            StageKind::Writer => None,
+        }
+    }
+}
+
+/// Description of a pipeline stage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Stage {
+    /// Kind of stage.
+    pub kind: StageKind,
+    /// Name of the component, like the container format or the codec.
+    pub name: String,
+    /// Pixel format of the frames that the stage outputs (or takes, for encoders).
+    pub pixel_format: Option<String>,
+    /// Width and height of the frames that the stage outputs (or takes, for encoders).
+    pub size: Option<(u32, u32)>,
+    /// Time base of the timestamps, as numerator and denominator.
+    pub time_base: Option<(i32, i32)>,
+    /// Further details, like the location of a source or the dump of a filter graph.
+    pub detail: Option<String>,
+}
+
+impl Stage {
+    /// Create a description of a stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `kind` - Kind of stage.
+    /// * `name` - Name of the component.
+    pub fn new(kind: StageKind, name: impl Into<String>) -> Self {
+        Self {
+            kind,
+            name: name.into(),
+            pixel_format: None,
+            size: None,
+            time_base: None,
+            detail: None,
+        }
+    }
+
+    /// Create a description of a configured filter graph, with the dump of the graph as detail.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the filter graph.
+    /// * `graph` - Configured filter graph.
+    #[cfg(feature = "filter")]
+    pub fn filter_graph(name: impl Into<String>, graph: &AvFilterGraph) -> Self {
+        Self::new(StageKind::Filter, name).with_detail(graph.dump())
+    }
+
+    /// Set the pixel format.
+    ///
+    /// # Arguments
+    ///
+    /// * `pixel_format` - Pixel format.
+    pub fn with_pixel_format(mut self, pixel_format: AvPixel) -> Self {
+        self.pixel_format = Some(
+            pixel_format
+                .descriptor()
+                .map(|descriptor| descriptor.name().to_string())
+                .unwrap_or_else(|| format!("{pixel_format:?}")),
+        );
+        self
+    }
+
+    /// Set the frame size.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Width and height.
+    pub fn with_size(mut self, size: (u32, u32)) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Set the time base.
+    ///
+    /// # Arguments
+    ///
+    /// * `time_base` - Time base.
+    pub fn with_time_base(mut self, time_base: AvRational) -> Self {
+        self.time_base = Some((time_base.numerator(), time_base.denominator()));
+        self
+    }
+
+    /// Set further details.
+    ///
+    /// # Arguments
+    ///
+    /// * `detail` - Details.
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}({}", self.kind, self.name)?;
+        if let Some(pixel_format) = &self.pixel_format {
+            write!(f, ", {pixel_format}")?;
+        }
+        if let Some((width, height)) = self.size {
+            write!(f, ", {width}x{height}")?;
+        }
+        if let Some((numerator, denominator)) = self.time_base {
+            write!(f, ", {numerator}/{denominator}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Description of the stages of a pipeline, in the order in which media flows through them.
+///
+/// Formats as one line (like `Reader(mov,mp4,m4a,3gp,3g2,mj2) -> Decoder(h264, yuv420p, 1920x1080,
+/// 1/90000) -> Scaler(swscale, rgb24, 1920x1080)`), and can be serialized with the `serde`
+/// feature, to attach to bug reports.
+///
+/// # Example
+///
+/// ```ignore
+/// let decoder = Decoder::new(Path::new("input.mp4")).unwrap();
+/// println!("{}", decoder.describe());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PipelineDescription {
+    /// Stages of the pipeline.
+    pub stages: Vec<Stage>,
+}
+
+impl PipelineDescription {
+    /// Add a stage at the end of the pipeline.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - Stage to add.
+    pub fn with_stage(mut self, stage: Stage) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Add the stages of another pipeline at the end of the pipeline, for example to describe a
+    /// transcode from a decoder to an encoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - Pipeline to append.
+    pub fn then(mut self, other: PipelineDescription) -> Self {
+        self.stages.extend(other.stages);
+        self
+    }
+
+    /// Check that every stage takes what the stage before it outputs. Packets flow from readers
+    /// and encoders into decoders and writers, and frames flow from decoders, filters and scalers
+    /// into filters, scalers and encoders. Nothing flows into a reader or out of a writer, so a
+    /// pipeline that loops back into its reader or continues after its writer is rejected.
+    ///
+    /// Returns [`Error::InvalidConfig`] naming the first invalid link.
+    pub fn validate(&self) -> Result<()> {
+        for link in self.stages.windows(2) {
+            let (from, to) = (&link[0], &link[1]);
+            if from.kind.output().is_none() || from.kind.output() != to.kind.input() {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid pipeline link from {from} to {to}"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PipelineDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (index, stage) in self.stages.iter().enumerate() {
+            if index > 0 {
+                write!(f, " -> ")?;
+            }
+            write!(f, "{stage}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcode() -> PipelineDescription {
+        let decode = PipelineDescription::default()
+            .with_stage(Stage::new(StageKind::Reader, "mov,mp4,m4a,3gp,3g2,mj2"))
+            .with_stage(
+                Stage::new(StageKind::Decoder, "h264")
+                    .with_pixel_format(AvPixel::YUV420P)
+                    .with_size((1280, 720))
+                    .with_time_base(AvRational::new(1, 90_000)),
+            );
+        let encode = PipelineDescription::default()
+            .with_stage(Stage::new(StageKind::Scaler, "swscale").with_size((640, 360)))
+            .with_stage(Stage::new(StageKind::Encoder, "libx264"))
+            .with_stage(Stage::new(StageKind::Writer, "matroska").with_detail("out.mkv"));
+        decode.then(encode)
+    }
+
+    #[test]
+    fn test_build_pipeline() {
+        let description = transcode();
+        let kinds: Vec<_> = description.stages.iter().map(|stage| stage.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                StageKind::Reader,
+                StageKind::Decoder,
+                StageKind::Scaler,
+                StageKind::Encoder,
+                StageKind::Writer,
+            ]
+        );
+        assert_eq!(
+            description.stages[1].pixel_format.as_deref(),
+            Some("yuv420p")
+        );
+        assert_eq!(description.stages[4].detail.as_deref(), Some("out.mkv"));
+        assert_eq!(
+            description.to_string(),
+            "Reader(mov,mp4,m4a,3gp,3g2,mj2) -> Decoder(h264, yuv420p, 1280x720, 1/90000) -> \
+             Scaler(swscale, 640x360) -> Encoder(libx264) -> Writer(matroska)"
+        );
+        assert!(description.validate().is_ok());
+        assert!(PipelineDescription::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_reject_loops() {
+        // Continuing after the writer, back into a reader.
+        let looped = transcode().then(transcode());
+        assert!(matches!(looped.validate(), Err(Error::InvalidConfig(_))));
+
+        // Feeding the output of a decoder back into a reader.
+        let looped = PipelineDescription::default()
+            .with_stage(Stage::new(StageKind::Reader, "mp4"))
+            .with_stage(Stage::new(StageKind::Decoder, "h264"))
+            .with_stage(Stage::new(StageKind::Reader, "mp4"));
+        assert!(matches!(looped.validate(), Err(Error::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_reject_invalid_links() {
+        let invalid = [
+            // Packets into an encoder.
+            (StageKind::Reader, StageKind::Encoder),
+            // Frames into a writer.
+            (StageKind::Decoder, StageKind::Writer),
+            // Frames into a decoder.
+            (StageKind::Scaler, StageKind::Decoder),
+            // Packets into a filter.
+            (StageKind::Encoder, StageKind::Filter),
+        ];
+        for (from, to) in invalid {
+            let description = PipelineDescription::default()
+                .with_stage(Stage::new(from, "from"))
+                .with_stage(Stage::new(to, "to"));
+            let Err(Error::InvalidConfig(message)) = description.validate() else {
+                panic!("{from:?} -> {to:?} should be rejected");
+            };
+            assert_eq!(
+                message,
+                format!("invalid pipeline link from {from:?}(from) to {to:?}(to)")
+            );
+        }
+    }
+}
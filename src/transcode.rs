@@ -0,0 +1,579 @@
+//! Transcoding of a video stream from one file or stream to another, with the read, decode, scale,
+//! encode and write steps wired up.
+
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+
+use ffmpeg::format::pixel::Pixel as AvPixel;
+use ffmpeg::software::scaling::flag::Flags as AvScalerFlags;
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::Rational as AvRational;
+
+use crate::config::{FromConfig, PipelineConfig};
+use crate::decode::{Decoder, DecoderBuilder};
+use crate::encode::{Deadline, Encoder, EncoderBuilder, Settings, ShutdownReport};
+use crate::error::Error;
+use crate::events::{Event, EventKind, EventSink};
+#[cfg(feature = "filter")]
+use crate::filter::Filter;
+use crate::frame::{RawFrame, FRAME_PIXEL_FORMAT};
+use crate::hwaccel::HardwareFrames;
+use crate::interrupt::Interrupt;
+use crate::location::Location;
+use crate::scaler::Scaler;
+#[cfg(feature = "filter")]
+use crate::stabilize::{Stabilization, TransformsFile};
+use crate::time::Time;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Progress of a [`Transcoder`], reported after every encoded frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TranscodeProgress {
+    /// Number of frames encoded so far.
+    pub frames: u64,
+    /// Timestamp of the last encoded frame, in the time base of the encoder.
+    pub pts: Time,
+}
+
+/// Transcodes the video stream of a [`Decoder`] into an [`Encoder`].
+///
+/// Decoded frames are passed through an optional filter graph, scaled to the size of the encoder
+/// if they differ from it, and encoded with their timestamps rescaled to the time base of the
+/// encoder. At the end of the stream, the decoder, the filter graph and the encoder are drained in
+/// turn so that no frames are lost.
+///
+/// Only the video stream is transcoded. Mux other streams next to encoded ones with
+/// [`MuxerBuilder::with_video_encoder`](crate::mux::MuxerBuilder::with_video_encoder).
+///
+/// # Example
+///
+/// ```ignore
+/// let decoder = Decoder::new(Path::new("input.mkv")).unwrap();
+/// let settings = Settings::preset_h264_yuv420p(1280, 720, false);
+/// let encoder = Encoder::new(Path::new("output.mp4"), settings).unwrap();
+/// let progress = Transcoder::new(decoder, encoder)
+///     .with_progress(|progress| println!("{} frames", progress.frames))
+///     .run()
+///     .unwrap();
+/// println!("done at {}", progress.pts);
+/// ```
+pub struct Transcoder {
+    decoder: Decoder,
+    encoder: Encoder,
+    #[cfg(feature = "filter")]
+    filter: Option<Filter>,
+    #[cfg(feature = "filter")]
+    stabilization: Option<Stabilization>,
+    /// Scaler with the formats and sizes it converts between, which is replaced when the frames
+    /// change, like after a resolution change in the middle of the stream.
+    scaler: Option<((AvPixel, u32, u32), (AvPixel, u32, u32), Scaler)>,
+    progress: Option<Box<dyn FnMut(TranscodeProgress) + Send>>,
+    shared_frames: bool,
+    pending: Option<RawFrame>,
+    shutdown_signal: Option<(Interrupt, Duration)>,
+    shutdown_report: Option<ShutdownReport>,
+    events: EventSink,
+    decoded: u64,
+    frames: u64,
+    pts: Time,
+}
+
+impl Transcoder {
+    /// Create a transcoder.
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - Decoder to read frames from.
+    /// * `encoder` - Encoder to write frames to.
+    pub fn new(decoder: Decoder, encoder: Encoder) -> Self {
+        let events = encoder.event_sink().clone();
+        Self {
+            decoder,
+            encoder,
+            #[cfg(feature = "filter")]
+            filter: None,
+            #[cfg(feature = "filter")]
+            stabilization: None,
+            scaler: None,
+            progress: None,
+            shared_frames: false,
+            pending: None,
+            shutdown_signal: None,
+            shutdown_report: None,
+            events,
+            decoded: 0,
+            frames: 0,
+            pts: Time::zero(),
+        }
+    }
+
+    /// Create a transcoder from a configuration, like a job definition received from another
+    /// service.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - Configuration of the decoder and the encoder.
+    pub fn from_config(config: &PipelineConfig) -> Result<Self> {
+        let decoder = DecoderBuilder::from_config(&config.decoder)?.build()?;
+        let encoder = EncoderBuilder::from_config(&config.encoder)?.build()?;
+        Ok(Self::new(decoder, encoder))
+    }
+
+    /// Create a transcoder that keeps the frames on the GPU from decoder to encoder, like from a
+    /// CUDA decoder into `h264_nvenc`. The encoder takes its frames from the pool of hardware
+    /// frames of the decoder, so frames are never downloaded to system memory and uploaded again.
+    ///
+    /// Since the pool is only known once the decoder produced a frame, the first frame is decoded
+    /// here and the encoder is created from it. The decoder must be built with
+    /// [`DecoderBuilder::with_hardware_acceleration`] and
+    /// [`DecoderBuilder::with_hardware_frames`], and the settings must request an encoder of the
+    /// same device type with [`Settings::with_hardware_acceleration`].
+    ///
+    /// If the frames cannot be shared, because the decoder produced frames in system memory, the
+    /// encoder does not take frames of the format of the pool, or the frames are not the size of
+    /// the encoder, a warning is logged and the transcoder falls back to downloading every frame
+    /// and encoding it from system memory. Check [`Transcoder::shares_hardware_frames`] to tell
+    /// the two apart.
+    ///
+    /// The filter graph and stabilization work on frames in system memory, so they cannot be
+    /// combined with shared frames: [`Transcoder::run`] fails with [`Error::InvalidFrameFormat`].
+    ///
+    /// # Arguments
+    ///
+    /// * `decoder` - Decoder to read frames from.
+    /// * `destination` - Where to write the encoded stream to.
+    /// * `settings` - Encoder settings.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let decoder = DecoderBuilder::new(Path::new("input.mkv"))
+    ///     .with_hardware_acceleration(HardwareAccelerationDeviceType::Cuda)
+    ///     .with_hardware_frames()
+    ///     .build()?;
+    /// let (width, height) = decoder.size();
+    /// let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false)
+    ///     .with_hardware_acceleration(HardwareAccelerationDeviceType::Cuda);
+    /// let mut transcoder = Transcoder::new_hardware(decoder, Path::new("output.mp4"), settings)?;
+    /// assert!(transcoder.shares_hardware_frames());
+    /// transcoder.run()?;
+    /// ```
+    ///
+    /// [`DecoderBuilder::with_hardware_acceleration`]: crate::decode::DecoderBuilder::with_hardware_acceleration
+    /// [`DecoderBuilder::with_hardware_frames`]: crate::decode::DecoderBuilder::with_hardware_frames
+    /// [`Settings::with_hardware_acceleration`]: crate::encode::Settings::with_hardware_acceleration
+    pub fn new_hardware(
+        mut decoder: Decoder,
+        destination: impl Into<Location>,
+        mut settings: Settings,
+    ) -> Result<Self> {
+        let pending = match decoder.decode_raw() {
+            Ok(frame) => Some(frame),
+            Err(Error::DecodeExhausted) => None,
+            Err(err) => return Err(err),
+        };
+        let mismatch = match pending.as_ref() {
+            Some(frame) => match HardwareFrames::of(frame) {
+                Some(hardware_frames) => {
+                    let mismatch = settings.hardware_frames_mismatch(
+                        &hardware_frames,
+                        frame.width(),
+                        frame.height(),
+                    );
+                    if mismatch.is_none() {
+                        settings.set_hardware_frames(hardware_frames);
+                    }
+                    mismatch
+                }
+                None => Some("decoder produced frames in system memory".to_string()),
+            },
+            None => Some("decoder produced no frames".to_string()),
+        };
+        if let Some(mismatch) = mismatch.as_ref() {
+            tracing::warn!("cannot share hardware frames, downloading them instead: {mismatch}");
+        }
+        let encoder = Encoder::new(destination, settings)?;
+        let mut transcoder = Self::new(decoder, encoder);
+        transcoder.shared_frames = mismatch.is_none();
+        transcoder.pending = pending;
+        Ok(transcoder)
+    }
+
+    /// Pass the decoded frames through a filter graph, like `hflip` or `fps=30,eq=contrast=1.2`.
+    /// The filtered frames are converted back to RGB24.
+    ///
+    /// # Arguments
+    ///
+    /// * `filter` - Filter to pass the frames through.
+    #[cfg(feature = "filter")]
+    pub fn with_filter(mut self, mut filter: Filter) -> Self {
+        filter.set_output(FRAME_PIXEL_FORMAT, None);
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Stabilize the video in two passes. The first pass decodes the whole stream to analyze the
+    /// motion of the camera, after which the decoder seeks back to the start for the second pass,
+    /// which stabilizes and encodes the frames. The transforms are kept in a temporary file that
+    /// is removed when the run ends. Stabilization is applied before the filter graph, if any.
+    ///
+    /// The decoder must be able to seek, so streams cannot be stabilized.
+    ///
+    /// # Arguments
+    ///
+    /// * `stabilization` - Stabilization options.
+    #[cfg(feature = "filter")]
+    pub fn with_stabilization(mut self, stabilization: Stabilization) -> Self {
+        self.stabilization = Some(stabilization);
+        self
+    }
+
+    /// Report progress after every encoded frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `callback` - Function that receives the progress.
+    pub fn with_progress(
+        mut self,
+        callback: impl FnMut(TranscodeProgress) + Send + 'static,
+    ) -> Self {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Shut the transcoder down gracefully once a signal is cancelled, for instance from the
+    /// `SIGTERM` handler of a service: [`Transcoder::run`] stops reading, then flushes the decoder,
+    /// the filter graph and the encoder for at most `timeout`, and writes the trailer so that the
+    /// output is a complete file. See [`Transcoder::shutdown`].
+    ///
+    /// # Arguments
+    ///
+    /// * `signal` - Handle that triggers the shutdown when it is cancelled.
+    /// * `timeout` - How long to flush the frames in flight for once the shutdown is triggered.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let signal = Interrupt::new();
+    /// let handle = signal.clone();
+    /// std::thread::spawn(move || {
+    ///     wait_for_sigterm();
+    ///     handle.cancel();
+    /// });
+    /// let mut transcoder =
+    ///     Transcoder::new(decoder, encoder).with_shutdown(signal, Duration::from_secs(5));
+    /// transcoder.run()?;
+    /// if let Some(report) = transcoder.shutdown_report() {
+    ///     println!("dropped {} frames", report.frames_dropped);
+    /// }
+    /// ```
+    pub fn with_shutdown(mut self, signal: Interrupt, timeout: Duration) -> Self {
+        self.shutdown_signal = Some((signal, timeout));
+        self
+    }
+
+    /// Subscribe to the events of the transcoder: an [`EventKind::FrameDecoded`] and
+    /// [`EventKind::FrameEncoded`] for every frame, the [`EventKind::PacketWritten`] events of the
+    /// encoder, and finally [`EventKind::Eof`] or [`EventKind::Error`]. Can be called multiple
+    /// times to subscribe multiple receivers. Events are never waited for: a receiver that falls
+    /// behind misses events and gets an [`EventKind::Lagged`] instead.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let mut transcoder = Transcoder::new(decoder, encoder);
+    /// let events = transcoder.events();
+    /// std::thread::spawn(move || {
+    ///     for event in events {
+    ///         if let EventKind::FrameEncoded { frame, timestamp } = event.kind {
+    ///             println!("{:?}: frame {frame} at {timestamp}", event.elapsed);
+    ///         }
+    ///     }
+    /// });
+    /// transcoder.run()?;
+    /// ```
+    pub fn events(&self) -> Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Transcode until the end of the stream, then flush the encoder and write the trailer.
+    ///
+    /// # Return value
+    ///
+    /// Progress at the end of the stream.
+    pub fn run(&mut self) -> Result<TranscodeProgress> {
+        let result = self.transcode();
+        if let Err(err) = result.as_ref() {
+            self.events.emit(EventKind::Error {
+                message: err.to_string(),
+            });
+        }
+        result
+    }
+
+    /// Transcode until the end of the stream. See [`Transcoder::run`].
+    fn transcode(&mut self) -> Result<TranscodeProgress> {
+        #[cfg(feature = "filter")]
+        if self.shared_frames && (self.filter.is_some() || self.stabilization.is_some()) {
+            return Err(Error::InvalidFrameFormat);
+        }
+        // The transforms file must outlive the filter graph of the second pass.
+        #[cfg(feature = "filter")]
+        let _transforms = match self.stabilization.take() {
+            Some(stabilization) => Some(self.analyze(stabilization)?),
+            None => None,
+        };
+        if let Some(frame) = self.pending.take() {
+            self.filter(frame)?;
+        }
+        loop {
+            if let Some((_, timeout)) = self
+                .shutdown_signal
+                .as_ref()
+                .filter(|(signal, _)| signal.is_cancelled())
+            {
+                self.shutdown(Deadline::after(*timeout))?;
+                return Ok(self.progress());
+            }
+            match self.decoder.decode_raw() {
+                Ok(frame) => self.filter(frame)?,
+                Err(Error::DecodeExhausted) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        self.finish_filter()?;
+        self.encoder.finish()?;
+        Ok(self.progress())
+    }
+
+    /// Shut the transcoder down gracefully: stop reading, flush the frames still in the decoder
+    /// through the filter graph into the encoder, flush the encoder, and write the trailer, all
+    /// until the deadline. Frames that are not flushed by then are dropped, but the trailer is
+    /// still written so that the output is a complete file.
+    ///
+    /// This is what [`Transcoder::run`] does when the signal of [`Transcoder::with_shutdown`] is
+    /// cancelled. Call it directly when driving the transcoder otherwise, like after
+    /// [`Transcoder::run`] failed because the source was interrupted.
+    ///
+    /// # Arguments
+    ///
+    /// * `deadline` - When to stop flushing.
+    ///
+    /// # Return value
+    ///
+    /// How many frames were flushed and how many were dropped, over the decoder and the encoder.
+    pub fn shutdown(&mut self, deadline: Deadline) -> Result<ShutdownReport> {
+        let (frames, decoder_report) = self.decoder.shutdown(deadline)?;
+        for frame in self.pending.take().into_iter().chain(frames) {
+            self.filter(frame)?;
+        }
+        self.finish_filter()?;
+        // Frames flushed out of the decoder are in flight in the encoder, so the frames written
+        // are those flushed out of the encoder.
+        let encoder_report = self.encoder.shutdown(deadline)?;
+        let report = ShutdownReport {
+            frames_in_flight: encoder_report.frames_flushed
+                + decoder_report.frames_dropped
+                + encoder_report.frames_dropped,
+            frames_flushed: encoder_report.frames_flushed,
+            frames_dropped: decoder_report.frames_dropped + encoder_report.frames_dropped,
+            trailer_written: encoder_report.trailer_written,
+        };
+        self.shutdown_report = Some(report);
+        Ok(report)
+    }
+
+    /// Get the accounting of the last shutdown, if the transcoder was shut down. See
+    /// [`Transcoder::shutdown`].
+    pub fn shutdown_report(&self) -> Option<ShutdownReport> {
+        self.shutdown_report
+    }
+
+    /// Whether the encoder takes the hardware frames of the decoder as they are. See
+    /// [`Transcoder::new_hardware`].
+    pub fn shares_hardware_frames(&self) -> bool {
+        self.shared_frames
+    }
+
+    /// Get the decoder.
+    pub fn decoder(&self) -> &Decoder {
+        &self.decoder
+    }
+
+    /// Get the encoder.
+    pub fn encoder(&self) -> &Encoder {
+        &self.encoder
+    }
+
+    /// Split the transcoder into its decoder and encoder.
+    pub fn into_parts(self) -> (Decoder, Encoder) {
+        (self.decoder, self.encoder)
+    }
+
+    /// Run the analysis pass of stabilization, seek back to the start, and put the transform pass
+    /// in front of the filter graph.
+    ///
+    /// # Arguments
+    ///
+    /// * `stabilization` - Stabilization options.
+    ///
+    /// # Return value
+    ///
+    /// The file with the transforms, which the transform pass reads when it is configured.
+    #[cfg(feature = "filter")]
+    fn analyze(&mut self, stabilization: Stabilization) -> Result<TransformsFile> {
+        let transforms = TransformsFile::new()?;
+        let time_base = self.decoder.time_base();
+        // The transforms are written when the filter is dropped at the end of this block.
+        {
+            let mut detect = Filter::new(stabilization.detect_spec(transforms.path()));
+            let mut pending = self.pending.take().map(Ok);
+            loop {
+                match pending.take().unwrap_or_else(|| self.decoder.decode_raw()) {
+                    Ok(mut frame) => {
+                        frame.set_pts(frame.timestamp());
+                        detect.push(&frame, time_base)?;
+                    }
+                    Err(Error::DecodeExhausted) => break,
+                    Err(err) => return Err(err),
+                }
+                while detect.pull()?.is_some() {}
+            }
+            detect.finish()?;
+            while detect.pull()?.is_some() {}
+        }
+        self.decoder.seek_to_start()?;
+
+        let spec = stabilization.transform_spec(transforms.path());
+        let spec = match self.filter.as_ref() {
+            Some(filter) => format!("{spec},{}", filter.spec()),
+            None => spec,
+        };
+        let mut filter = Filter::new(spec);
+        filter.set_output(FRAME_PIXEL_FORMAT, None);
+        self.filter = Some(filter);
+        Ok(transforms)
+    }
+
+    /// Pass a decoded frame through the filter graph, if any, and encode the frames that come out.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Decoded frame, with a timestamp in the time base of the decoder.
+    fn filter(&mut self, mut frame: RawFrame) -> Result<()> {
+        frame.set_pts(frame.timestamp());
+        let time_base = self.decoder.time_base();
+        self.events.emit(EventKind::FrameDecoded {
+            frame: self.decoded,
+            timestamp: Time::new(frame.pts(), time_base),
+        });
+        self.decoded += 1;
+        if self.shared_frames {
+            return self.encode(frame, time_base);
+        }
+        if HardwareFrames::of(&frame).is_some() {
+            frame = HardwareFrames::download(&frame)?;
+        }
+        #[cfg(feature = "filter")]
+        if let Some(filter) = self.filter.as_mut() {
+            filter.push(&frame, time_base)?;
+            return self.drain_filter();
+        }
+        self.encode(frame, time_base)
+    }
+
+    /// Signal the end of the stream to the filter graph, if any, and encode the frames it still
+    /// holds.
+    fn finish_filter(&mut self) -> Result<()> {
+        #[cfg(feature = "filter")]
+        if let Some(filter) = self.filter.as_mut() {
+            filter.finish()?;
+            return self.drain_filter();
+        }
+        Ok(())
+    }
+
+    /// Encode the frames that are available from the filter graph.
+    #[cfg(feature = "filter")]
+    fn drain_filter(&mut self) -> Result<()> {
+        let time_base = self.decoder.time_base();
+        while let Some(frame) = self
+            .filter
+            .as_mut()
+            .map(Filter::pull)
+            .transpose()?
+            .flatten()
+        {
+            self.encode(frame, time_base)?;
+        }
+        Ok(())
+    }
+
+    /// Scale a frame to the size of the encoder if needed, rescale its timestamp to the time base
+    /// of the encoder, and encode it.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - Frame in system memory, or a hardware frame if the encoder shares the frames of
+    ///   the decoder.
+    /// * `time_base` - Time base of the timestamp of the frame.
+    fn encode(&mut self, frame: RawFrame, time_base: AvRational) -> Result<()> {
+        let (width, height) = self.encoder.size();
+        let mut frame = if !self.shared_frames
+            && (frame.width(), frame.height(), frame.format())
+                != (width, height, FRAME_PIXEL_FORMAT)
+        {
+            let input = (frame.format(), frame.width(), frame.height());
+            let output = (FRAME_PIXEL_FORMAT, width, height);
+            let scaler = match self.scaler.take() {
+                Some(scaler) if (scaler.0, scaler.1) == (input, output) => scaler,
+                _ => (
+                    input,
+                    output,
+                    Scaler::get(
+                        self.encoder.scaler_backend(),
+                        input,
+                        output,
+                        AvScalerFlags::BICUBIC,
+                    )?,
+                ),
+            };
+            let scaler = &mut self.scaler.insert(scaler).2;
+            let mut scaled = RawFrame::empty();
+            scaler.run(&frame, &mut scaled)?;
+            scaled.set_pts(frame.pts());
+            scaled
+        } else {
+            frame
+        };
+        let encoder_time_base = self.encoder.time_base();
+        let pts = frame
+            .pts()
+            .map(|pts| pts.rescale(time_base, encoder_time_base));
+        frame.set_pts(pts);
+        self.encoder.encode_raw(frame)?;
+
+        self.pts = Time::new(pts, encoder_time_base);
+        self.events.emit(EventKind::FrameEncoded {
+            frame: self.frames,
+            timestamp: self.pts,
+        });
+        self.frames += 1;
+        let progress = self.progress();
+        if let Some(callback) = self.progress.as_mut() {
+            callback(progress);
+        }
+        Ok(())
+    }
+
+    /// Get the progress so far.
+    fn progress(&self) -> TranscodeProgress {
+        TranscodeProgress {
+            frames: self.frames,
+            pts: self.pts,
+        }
+    }
+}
+
+unsafe impl Send for Transcoder {}
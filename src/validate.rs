@@ -0,0 +1,466 @@
+//! Strict validation of packet and frame timestamps, to catch timestamp bugs where they happen
+//! instead of as a file that players refuse.
+
+use std::collections::HashMap;
+
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::Rational as AvRational;
+
+use crate::error::Error;
+use crate::topology::StageKind;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Largest timestamp that is accepted, in seconds. Generous enough for wall clock timestamps, which
+/// count from 1970, while catching overflowed and uninitialized values.
+const MAX_SECONDS: f64 = 1000.0 * 365.0 * 24.0 * 60.0 * 60.0;
+
+/// What is wrong with a timestamp. See [`TimestampViolation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// The time base is zero or negative.
+    InvalidTimeBase,
+    /// The decoding timestamp of a packet is not after the one of the packet before it.
+    NonMonotonicDts,
+    /// The presentation timestamp of a frame is not after the one of the frame before it.
+    NonMonotonicPts,
+    /// The presentation timestamp of a packet is before its decoding timestamp.
+    PtsBeforeDts,
+    /// The duration of a packet is negative.
+    NegativeDuration,
+    /// The timestamp is too large to be a real timestamp, like an overflowed value.
+    OutOfRange,
+    /// Rescaling to another time base moved the timestamp by more than one tick of that time base.
+    RescaleMismatch,
+    /// Rescaling to another time base made the decoding timestamp equal to the one of the packet
+    /// before it, because the time base is too coarse.
+    CollapsedByRescale,
+}
+
+impl std::fmt::Display for ViolationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            ViolationKind::InvalidTimeBase => write!(f, "invalid time base"),
+            ViolationKind::NonMonotonicDts => write!(f, "non-monotonic dts"),
+            ViolationKind::NonMonotonicPts => write!(f, "non-monotonic pts"),
+            ViolationKind::PtsBeforeDts => write!(f, "pts before dts"),
+            ViolationKind::NegativeDuration => write!(f, "negative duration"),
+            ViolationKind::OutOfRange => write!(f, "timestamp out of range"),
+            ViolationKind::RescaleMismatch => write!(f, "rescaled timestamp does not match"),
+            ViolationKind::CollapsedByRescale => {
+                write!(f, "dts collapsed by rescaling to a coarser time base")
+            }
+        }
+    }
+}
+
+/// The first timestamp that failed strict validation, with the context to find the bug. Returned
+/// in [`Error::TimestampViolation`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimestampViolation {
+    /// Pipeline stage that saw the timestamp.
+    pub stage: StageKind,
+    /// Index of the stream in that stage.
+    pub stream_index: usize,
+    /// Number of the packet or frame in the stream, counting from zero.
+    pub number: u64,
+    /// What is wrong.
+    pub kind: ViolationKind,
+    /// The offending timestamp.
+    pub timestamp: Option<i64>,
+    /// The timestamp it was compared with, like the one of the packet before it.
+    pub previous: Option<i64>,
+    /// Time base of the timestamps.
+    pub time_base: AvRational,
+}
+
+impl std::fmt::Display for TimestampViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in {:?} stream {} at #{}: {:?} after {:?} in time base {}",
+            self.kind,
+            self.stage,
+            self.stream_index,
+            self.number,
+            self.timestamp,
+            self.previous,
+            self.time_base,
+        )
+    }
+}
+
+/// Timestamps of a packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PacketTimestamps {
+    pub(crate) pts: Option<i64>,
+    pub(crate) dts: Option<i64>,
+    pub(crate) duration: i64,
+}
+
+impl PacketTimestamps {
+    /// Get the timestamps of a packet.
+    ///
+    /// # Arguments
+    ///
+    /// * `packet` - Packet to get the timestamps of.
+    pub(crate) fn of(packet: &ffmpeg::Packet) -> Self {
+        Self {
+            pts: packet.pts(),
+            dts: packet.dts(),
+            duration: packet.duration(),
+        }
+    }
+}
+
+/// Validates the timestamps of the packets or frames that pass a pipeline stage, per stream. Stops
+/// at the first violation, which is logged and returned as [`Error::TimestampViolation`].
+#[derive(Debug, Clone)]
+pub(crate) struct TimestampValidator {
+    stage: StageKind,
+    streams: HashMap<usize, StreamState>,
+}
+
+impl TimestampValidator {
+    /// Create a validator for a pipeline stage.
+    ///
+    /// # Arguments
+    ///
+    /// * `stage` - Stage the timestamps pass.
+    pub(crate) fn new(stage: StageKind) -> Self {
+        Self {
+            stage,
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Validate the timestamps of a packet: the decoding timestamps must increase, the
+    /// presentation timestamp must not be before the decoding timestamp and the duration must not
+    /// be negative.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream of the packet.
+    /// * `timestamps` - Timestamps of the packet.
+    /// * `time_base` - Time base of the timestamps.
+    pub(crate) fn check_packet(
+        &mut self,
+        stream_index: usize,
+        timestamps: PacketTimestamps,
+        time_base: AvRational,
+    ) -> Result<()> {
+        let state = self.streams.entry(stream_index).or_default();
+        let number = state.packets;
+        state.packets += 1;
+        let previous_dts = state.last_dts;
+        if timestamps.dts.is_some() {
+            state.last_dts = timestamps.dts;
+        }
+        let violation = |kind, timestamp, previous| {
+            self.violation(stream_index, number, kind, timestamp, previous, time_base)
+        };
+
+        check_time_base(time_base).map_err(|kind| violation(kind, timestamps.dts, None))?;
+        for timestamp in [timestamps.pts, timestamps.dts] {
+            check_range(timestamp, time_base).map_err(|kind| violation(kind, timestamp, None))?;
+        }
+        if let (Some(dts), Some(previous_dts)) = (timestamps.dts, previous_dts) {
+            if dts <= previous_dts {
+                return Err(violation(
+                    ViolationKind::NonMonotonicDts,
+                    Some(dts),
+                    Some(previous_dts),
+                ));
+            }
+        }
+        if let (Some(pts), Some(dts)) = (timestamps.pts, timestamps.dts) {
+            if pts < dts {
+                return Err(violation(ViolationKind::PtsBeforeDts, Some(pts), Some(dts)));
+            }
+        }
+        if timestamps.duration < 0 {
+            return Err(violation(
+                ViolationKind::NegativeDuration,
+                Some(timestamps.duration),
+                None,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validate that rescaling the timestamps of a packet to another time base kept them within
+    /// one tick of the new time base, and kept the decoding timestamps increasing. Call this after
+    /// [`TimestampValidator::check_packet`] validated the source timestamps.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream of the packet.
+    /// * `source` - Timestamps before rescaling.
+    /// * `source_time_base` - Time base before rescaling.
+    /// * `destination` - Timestamps after rescaling.
+    /// * `destination_time_base` - Time base after rescaling.
+    pub(crate) fn check_rescaled(
+        &mut self,
+        stream_index: usize,
+        source: PacketTimestamps,
+        source_time_base: AvRational,
+        destination: PacketTimestamps,
+        destination_time_base: AvRational,
+    ) -> Result<()> {
+        let state = self.streams.entry(stream_index).or_default();
+        let number = state.packets.saturating_sub(1);
+        let previous_dts = state.last_rescaled_dts;
+        if destination.dts.is_some() {
+            state.last_rescaled_dts = destination.dts;
+        }
+        let violation = |kind, timestamp, previous| {
+            self.violation(
+                stream_index,
+                number,
+                kind,
+                timestamp,
+                previous,
+                destination_time_base,
+            )
+        };
+
+        check_time_base(destination_time_base)
+            .map_err(|kind| violation(kind, destination.dts, None))?;
+        for (source, destination) in [(source.pts, destination.pts), (source.dts, destination.dts)]
+        {
+            let (Some(source), Some(destination)) = (source, destination) else {
+                if source.is_some() != destination.is_some() {
+                    return Err(violation(
+                        ViolationKind::RescaleMismatch,
+                        destination,
+                        source,
+                    ));
+                }
+                continue;
+            };
+            let expected = source.rescale(source_time_base, destination_time_base);
+            if (destination - expected).abs() > 1 {
+                return Err(violation(
+                    ViolationKind::RescaleMismatch,
+                    Some(destination),
+                    Some(expected),
+                ));
+            }
+        }
+        if let (Some(dts), Some(previous_dts)) = (destination.dts, previous_dts) {
+            if dts <= previous_dts {
+                return Err(violation(
+                    ViolationKind::CollapsedByRescale,
+                    Some(dts),
+                    Some(previous_dts),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate the timestamp of a frame: the presentation timestamps must increase.
+    ///
+    /// # Arguments
+    ///
+    /// * `stream_index` - Index of the stream of the frame.
+    /// * `pts` - Presentation timestamp of the frame.
+    /// * `time_base` - Time base of the timestamp.
+    pub(crate) fn check_frame(
+        &mut self,
+        stream_index: usize,
+        pts: Option<i64>,
+        time_base: AvRational,
+    ) -> Result<()> {
+        let state = self.streams.entry(stream_index).or_default();
+        let number = state.frames;
+        state.frames += 1;
+        let previous_pts = state.last_pts;
+        if pts.is_some() {
+            state.last_pts = pts;
+        }
+        let violation = |kind, timestamp, previous| {
+            self.violation(stream_index, number, kind, timestamp, previous, time_base)
+        };
+
+        check_time_base(time_base).map_err(|kind| violation(kind, pts, None))?;
+        check_range(pts, time_base).map_err(|kind| violation(kind, pts, None))?;
+        if let (Some(pts), Some(previous_pts)) = (pts, previous_pts) {
+            if pts <= previous_pts {
+                return Err(violation(
+                    ViolationKind::NonMonotonicPts,
+                    Some(pts),
+                    Some(previous_pts),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the error for a violation, and log it.
+    fn violation(
+        &self,
+        stream_index: usize,
+        number: u64,
+        kind: ViolationKind,
+        timestamp: Option<i64>,
+        previous: Option<i64>,
+        time_base: AvRational,
+    ) -> Error {
+        let violation = TimestampViolation {
+            stage: self.stage,
+            stream_index,
+            number,
+            kind,
+            timestamp,
+            previous,
+            time_base,
+        };
+        tracing::error!("strict timestamp validation failed: {violation}");
+        Error::TimestampViolation(violation)
+    }
+}
+
+/// Timestamps seen last in a stream.
+#[derive(Debug, Clone, Default)]
+struct StreamState {
+    packets: u64,
+    frames: u64,
+    last_dts: Option<i64>,
+    last_pts: Option<i64>,
+    last_rescaled_dts: Option<i64>,
+}
+
+/// Check that a time base is positive.
+///
+/// # Arguments
+///
+/// * `time_base` - Time base to check.
+fn check_time_base(time_base: AvRational) -> std::result::Result<(), ViolationKind> {
+    if time_base.numerator() > 0 && time_base.denominator() > 0 {
+        Ok(())
+    } else {
+        Err(ViolationKind::InvalidTimeBase)
+    }
+}
+
+/// Check that a timestamp is in the range of real timestamps.
+///
+/// # Arguments
+///
+/// * `timestamp` - Timestamp to check, if any.
+/// * `time_base` - Time base of the timestamp.
+fn check_range(
+    timestamp: Option<i64>,
+    time_base: AvRational,
+) -> std::result::Result<(), ViolationKind> {
+    match timestamp {
+        Some(timestamp) if (timestamp as f64 * f64::from(time_base)).abs() > MAX_SECONDS => {
+            Err(ViolationKind::OutOfRange)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIME_BASE: AvRational = AvRational(1, 90_000);
+
+    fn packet(pts: i64, dts: i64) -> PacketTimestamps {
+        PacketTimestamps {
+            pts: Some(pts),
+            dts: Some(dts),
+            duration: 3000,
+        }
+    }
+
+    fn kind(result: Result<()>) -> Option<ViolationKind> {
+        match result {
+            Ok(()) => None,
+            Err(Error::TimestampViolation(violation)) => Some(violation.kind),
+            Err(err) => panic!("unexpected error: {err}"),
+        }
+    }
+
+    #[test]
+    fn accepts_increasing_dts_with_reordered_pts() {
+        let mut validator = TimestampValidator::new(StageKind::Writer);
+        for (pts, dts) in [(6000, 0), (3000, 3000), (12000, 6000), (9000, 9000)] {
+            validator
+                .check_packet(0, packet(pts, dts), TIME_BASE)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_repeated_dts() {
+        let mut validator = TimestampValidator::new(StageKind::Reader);
+        validator.check_packet(0, packet(0, 0), TIME_BASE).unwrap();
+        assert_eq!(
+            kind(validator.check_packet(0, packet(0, 0), TIME_BASE)),
+            Some(ViolationKind::NonMonotonicDts),
+        );
+    }
+
+    #[test]
+    fn tracks_streams_separately() {
+        let mut validator = TimestampValidator::new(StageKind::Reader);
+        validator
+            .check_packet(0, packet(3000, 3000), TIME_BASE)
+            .unwrap();
+        validator.check_packet(1, packet(0, 0), TIME_BASE).unwrap();
+    }
+
+    #[test]
+    fn rejects_pts_before_dts() {
+        let mut validator = TimestampValidator::new(StageKind::Reader);
+        assert_eq!(
+            kind(validator.check_packet(0, packet(0, 3000), TIME_BASE)),
+            Some(ViolationKind::PtsBeforeDts),
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_time_base_and_range() {
+        let mut validator = TimestampValidator::new(StageKind::Reader);
+        assert_eq!(
+            kind(validator.check_packet(0, packet(0, 0), AvRational(0, 1))),
+            Some(ViolationKind::InvalidTimeBase),
+        );
+        assert_eq!(
+            kind(validator.check_packet(1, packet(i64::MAX / 2, 0), TIME_BASE)),
+            Some(ViolationKind::OutOfRange),
+        );
+    }
+
+    #[test]
+    fn detects_collapse_by_rescale() {
+        let mut validator = TimestampValidator::new(StageKind::Writer);
+        let destination_time_base = AvRational(1, 10);
+        for dts in [0, 3000] {
+            let source = packet(dts, dts);
+            let rescaled = dts.rescale(TIME_BASE, destination_time_base);
+            let destination = packet(rescaled, rescaled);
+            let result = validator.check_packet(0, source, TIME_BASE).and_then(|()| {
+                validator.check_rescaled(0, source, TIME_BASE, destination, destination_time_base)
+            });
+            if dts == 0 {
+                result.unwrap();
+            } else {
+                assert_eq!(kind(result), Some(ViolationKind::CollapsedByRescale));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_repeated_frame_pts() {
+        let mut validator = TimestampValidator::new(StageKind::Encoder);
+        validator.check_frame(0, Some(1), TIME_BASE).unwrap();
+        assert_eq!(
+            kind(validator.check_frame(0, Some(1), TIME_BASE)),
+            Some(ViolationKind::NonMonotonicPts),
+        );
+    }
+}
@@ -0,0 +1,203 @@
+//! Turning audio into video, like a podcast episode shown with its cover image for video
+//! platforms.
+
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::Rational as AvRational;
+
+use crate::decode::Decoder;
+use crate::encode::{Settings, VideoStreamEncoder};
+use crate::error::Error;
+use crate::io::{Reader, Writer};
+use crate::location::Location;
+use crate::mux::MuxerBuilder;
+use crate::options::Options;
+use crate::sidecar::{mux_encoded, next_packet, packet_time, AudioCoding, AudioTrack};
+use crate::stream::StreamInfo;
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Makes a video out of audio.
+///
+/// # Example
+///
+/// ```ignore
+/// AudioVisualizer::still_plus_audio(
+///     Path::new("cover.jpg"),
+///     Path::new("episode-42.mp3"),
+///     Path::new("episode-42.mp4"),
+/// )
+/// .unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct AudioVisualizer {
+    frame_rate: u32,
+    keyframe_interval: u32,
+    coding: AudioCoding,
+    bit_rate: Option<u64>,
+}
+
+impl AudioVisualizer {
+    /// Default frame rate. A still image needs no more than one frame per second.
+    const FRAME_RATE: u32 = 1;
+
+    /// Default time between keyframes in seconds, which keeps seeking responsive.
+    const KEYFRAME_INTERVAL: u32 = 10;
+
+    /// Create a visualizer with the default settings.
+    pub fn new() -> Self {
+        Self {
+            frame_rate: Self::FRAME_RATE,
+            keyframe_interval: Self::KEYFRAME_INTERVAL,
+            coding: AudioCoding::default(),
+            bit_rate: None,
+        }
+    }
+
+    /// Show a single image for the duration of an audio file, with the default settings. See
+    /// [`AudioVisualizer::render_still`].
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Image to show, in any format that can be decoded, like JPEG or PNG.
+    /// * `audio` - Audio file. Its best audio stream is used.
+    /// * `destination` - Where to write the video to. The container format follows from it.
+    pub fn still_plus_audio(
+        image: impl Into<Location>,
+        audio: impl Into<Location>,
+        destination: impl Into<Location>,
+    ) -> Result<()> {
+        Self::new().render_still(image, audio, destination)
+    }
+
+    /// Set the frame rate of the video.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame_rate` - Frame rate in frames per second. Zero is raised to one.
+    pub fn with_frame_rate(mut self, frame_rate: u32) -> Self {
+        self.frame_rate = frame_rate.max(1);
+        self
+    }
+
+    /// Set the time between keyframes of the video.
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - Time between keyframes in seconds. Zero is raised to one.
+    pub fn with_keyframe_interval(mut self, seconds: u32) -> Self {
+        self.keyframe_interval = seconds.max(1);
+        self
+    }
+
+    /// Set whether the audio is copied or encoded. By default, the audio is copied if the output
+    /// container can store its codec.
+    ///
+    /// # Arguments
+    ///
+    /// * `coding` - Audio coding.
+    pub fn with_coding(mut self, coding: AudioCoding) -> Self {
+        self.coding = coding;
+        self
+    }
+
+    /// Set the bit rate of the audio when it is encoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `bit_rate` - Bit rate in bits per second.
+    pub fn with_bit_rate(mut self, bit_rate: u64) -> Self {
+        self.bit_rate = Some(bit_rate);
+        self
+    }
+
+    /// Show a single image for the duration of an audio file.
+    ///
+    /// The image is encoded to H.264 tuned for still images, at the frame rate of the visualizer.
+    /// Since every frame is the same, all frames but the keyframes take next to no space. The
+    /// image is scaled down by a pixel if needed to get an even width and height.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - Image to show, in any format that can be decoded, like JPEG or PNG.
+    /// * `audio` - Audio file. Its best audio stream is used.
+    /// * `destination` - Where to write the video to. The container format follows from it.
+    pub fn render_still(
+        &self,
+        image: impl Into<Location>,
+        audio: impl Into<Location>,
+        destination: impl Into<Location>,
+    ) -> Result<()> {
+        let image = Decoder::new(image)?.decode_raw()?;
+        let mut audio_reader = Reader::new(audio)?;
+        let audio_index = audio_reader.best_audio_stream_index()?;
+        let writer = Writer::new(destination)?;
+
+        // YUV 4:2:0 needs an even width and height.
+        let settings = Settings::preset_h264_custom(
+            (image.width() & !1).max(2) as usize,
+            (image.height() & !1).max(2) as usize,
+            ffmpeg::util::format::Pixel::YUV420P,
+            Options::preset_h264_still_image(),
+        )
+        .with_keyframe_interval(u64::from(self.frame_rate) * u64::from(self.keyframe_interval));
+        let frame_rate = AvRational::new(i32::try_from(self.frame_rate).unwrap_or(i32::MAX), 1);
+        let mut video_encoder = VideoStreamEncoder::new(&writer, &settings, frame_rate)?;
+
+        const VIDEO_KEY: usize = 0;
+        const AUDIO_KEY: usize = 1;
+        let mut audio_track = AudioTrack::new(
+            &audio_reader,
+            audio_index,
+            &writer,
+            self.coding,
+            self.bit_rate,
+            AUDIO_KEY,
+        )?;
+        let mut muxer = MuxerBuilder::new(writer)
+            .with_stream(StreamInfo::from_params(
+                video_encoder.parameters(),
+                video_encoder.time_base(),
+                VIDEO_KEY,
+            )?)?
+            .with_stream(audio_track.stream_info(&audio_reader)?)?
+            .interleaved()
+            .build();
+
+        // Frames are encoded just ahead of the audio, so the muxer does not have to buffer much.
+        let time_base = video_encoder.time_base();
+        let mut frame = image;
+        let mut frame_index = 0_i64;
+        let mut encode_until = |until: f64, inclusive: bool| -> Result<Vec<_>> {
+            let mut packets = Vec::new();
+            loop {
+                let time = frame_index as f64 / f64::from(self.frame_rate);
+                if time > until || (!inclusive && time >= until) {
+                    break Ok(packets);
+                }
+                frame.set_pts(Some(frame_index.rescale(frame_rate.invert(), time_base)));
+                packets.extend(video_encoder.encode(&frame)?);
+                frame_index += 1;
+            }
+        };
+
+        let mut audio_end = 0.0_f64;
+        while let Some(packet) = next_packet(audio_reader.read(audio_index))? {
+            let time = packet_time(&packet).as_secs_f64();
+            audio_end = audio_end.max(packet.pts().as_secs_f64() + packet.duration().as_secs_f64());
+            mux_encoded(&mut muxer, encode_until(time, true)?, VIDEO_KEY)?;
+            audio_track.mux(&mut muxer, packet)?;
+        }
+        audio_track.finish(&mut muxer, None)?;
+        mux_encoded(&mut muxer, encode_until(audio_end, false)?, VIDEO_KEY)?;
+        mux_encoded(&mut muxer, video_encoder.finish()?, VIDEO_KEY)?;
+
+        muxer.finish()?;
+        Ok(())
+    }
+}
+
+impl Default for AudioVisualizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,52 @@
+use ffmpeg::util::channel_layout::ChannelLayout;
+use ffmpeg::util::format::{sample::Type as SampleType, Sample};
+use rsmedia::audio::{AudioDecoder, AudioEncoderBuilder};
+use rsmedia::frame::RawAudioFrame;
+use rsmedia::time::Time;
+use tempfile::TempDir;
+
+#[test]
+fn test_format_change_is_negotiated_again() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.m4a");
+    let mut encoder = AudioEncoderBuilder::new(path.as_path(), 48_000, 2)
+        .with_input_format(Sample::F32(SampleType::Packed))
+        .build()
+        .unwrap();
+
+    // One second of interleaved `f32` samples at the rate of the encoder.
+    encoder
+        .encode_interleaved_f32(&vec![0.0; 2 * 48_000], Time::zero())
+        .unwrap();
+    let first = encoder.negotiation().unwrap();
+    assert_eq!(first.input_format, Sample::F32(SampleType::Packed));
+    assert_eq!(first.input_sample_rate, 48_000);
+
+    // Then one second of `s16` samples at another rate.
+    let mut frame = RawAudioFrame::new(
+        Sample::I16(SampleType::Packed),
+        44_100,
+        ChannelLayout::STEREO,
+    );
+    frame.set_rate(44_100);
+    frame.data_mut(0).fill(0);
+    frame.set_pts(Some(48_000));
+    encoder.encode_raw(&frame).unwrap();
+    let second = encoder.negotiation().unwrap();
+    assert_eq!(second.input_format, Sample::I16(SampleType::Packed));
+    assert_eq!(second.input_sample_rate, 44_100);
+    assert_eq!(second.encoder_format, first.encoder_format);
+    assert!(second.is_converted());
+    encoder.finish().unwrap();
+    drop(encoder);
+
+    // Both seconds were encoded, give or take the priming and padding of the codec.
+    let mut decoder = AudioDecoder::new(path.as_path()).unwrap();
+    let samples = decoder
+        .decode_raw_iter()
+        .take_while(Result::is_ok)
+        .map(|frame| frame.unwrap().samples())
+        .sum::<usize>();
+    assert!(samples.abs_diff(2 * 48_000) <= 2048, "{samples} samples");
+}
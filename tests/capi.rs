@@ -0,0 +1,154 @@
+#![cfg(feature = "capi")]
+
+mod common;
+
+use std::ffi::{CStr, CString};
+use std::path::Path;
+
+use rsmedia::capi::*;
+use rsmedia::decode::Decoder;
+use tempfile::TempDir;
+
+use common::fixture;
+
+fn c_path(path: &Path) -> CString {
+    CString::new(path.to_str().unwrap()).unwrap()
+}
+
+fn last_error() -> String {
+    let error = rsmedia_last_error();
+    assert!(!error.is_null());
+    unsafe { CStr::from_ptr(error) }
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[test]
+fn test_decode_and_encode() {
+    assert_eq!(rsmedia_init(), RSMEDIA_OK);
+    let dir = TempDir::new().unwrap();
+    let output = dir.path().join("output.mp4");
+    unsafe {
+        let decoder = rsmedia_decoder_open(c_path(&fixture()).as_ptr());
+        assert!(!decoder.is_null());
+        let (mut width, mut height) = (0, 0);
+        assert_eq!(
+            rsmedia_decoder_size(decoder, &mut width, &mut height),
+            RSMEDIA_OK
+        );
+        assert!(width > 0 && height > 0);
+        assert!(rsmedia_decoder_frame_rate(decoder) > 0.0);
+
+        let encoder = rsmedia_encoder_open(c_path(&output).as_ptr(), width, height, false);
+        assert!(!encoder.is_null());
+        let mut buffer = vec![0; width as usize * height as usize * 3];
+        let mut timestamp = 0.0;
+        for _ in 0..10 {
+            assert_eq!(
+                rsmedia_decoder_decode(decoder, buffer.as_mut_ptr(), buffer.len(), &mut timestamp),
+                RSMEDIA_OK
+            );
+            assert_eq!(
+                rsmedia_encoder_encode(encoder, buffer.as_ptr(), buffer.len(), timestamp),
+                RSMEDIA_OK
+            );
+        }
+        assert_eq!(rsmedia_encoder_finish(encoder), RSMEDIA_OK);
+        rsmedia_encoder_close(encoder);
+        rsmedia_decoder_close(decoder);
+    }
+
+    let mut decoder = Decoder::new(output).unwrap();
+    let frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(frames, 10);
+}
+
+#[test]
+fn test_decode_until_end() {
+    assert_eq!(rsmedia_init(), RSMEDIA_OK);
+    unsafe {
+        let decoder = rsmedia_decoder_open(c_path(&fixture()).as_ptr());
+        let (mut width, mut height) = (0, 0);
+        rsmedia_decoder_size(decoder, &mut width, &mut height);
+        let mut buffer = vec![0; width as usize * height as usize * 3];
+        let mut frames = 0;
+        loop {
+            match rsmedia_decoder_decode(
+                decoder,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                std::ptr::null_mut(),
+            ) {
+                RSMEDIA_OK => frames += 1,
+                status => {
+                    assert_eq!(status, RSMEDIA_END);
+                    break;
+                }
+            }
+        }
+        assert_eq!(frames, 901);
+        rsmedia_decoder_close(decoder);
+    }
+}
+
+#[test]
+fn test_invalid_arguments() {
+    assert_eq!(rsmedia_init(), RSMEDIA_OK);
+    unsafe {
+        assert!(rsmedia_decoder_open(std::ptr::null()).is_null());
+        assert_eq!(last_error(), "location is null");
+        let missing = c_path(Path::new("/does/not/exist.mp4"));
+        assert!(rsmedia_decoder_open(missing.as_ptr()).is_null());
+        assert!(!last_error().is_empty());
+
+        let (mut width, mut height) = (0, 0);
+        let mut buffer = vec![0; 16];
+        assert_eq!(
+            rsmedia_decoder_size(std::ptr::null(), &mut width, &mut height),
+            RSMEDIA_ERROR
+        );
+        assert_eq!(last_error(), "decoder is null");
+        assert_eq!(rsmedia_decoder_frame_rate(std::ptr::null()), 0.0);
+        assert_eq!(
+            rsmedia_decoder_decode(
+                std::ptr::null_mut(),
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                std::ptr::null_mut(),
+            ),
+            RSMEDIA_ERROR
+        );
+        assert_eq!(
+            rsmedia_encoder_encode(std::ptr::null_mut(), buffer.as_ptr(), buffer.len(), 0.0),
+            RSMEDIA_ERROR
+        );
+        assert_eq!(rsmedia_encoder_finish(std::ptr::null_mut()), RSMEDIA_ERROR);
+        assert_eq!(last_error(), "encoder is null");
+        rsmedia_decoder_close(std::ptr::null_mut());
+        rsmedia_encoder_close(std::ptr::null_mut());
+
+        let decoder = rsmedia_decoder_open(c_path(&fixture()).as_ptr());
+        assert_eq!(
+            rsmedia_decoder_size(decoder, std::ptr::null_mut(), &mut height),
+            RSMEDIA_ERROR
+        );
+        assert_eq!(last_error(), "width is null");
+        assert_eq!(
+            rsmedia_decoder_decode(decoder, std::ptr::null_mut(), 0, std::ptr::null_mut()),
+            RSMEDIA_ERROR
+        );
+        assert_eq!(last_error(), "buffer is null");
+        // Buffers too small for a frame are rejected.
+        assert_eq!(
+            rsmedia_decoder_decode(
+                decoder,
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                std::ptr::null_mut(),
+            ),
+            RSMEDIA_ERROR
+        );
+        rsmedia_decoder_close(decoder);
+    }
+}
@@ -0,0 +1,45 @@
+mod common;
+
+use rsmedia::checksum::HashAlgorithm;
+use rsmedia::io::{BufWriter, Reader};
+use rsmedia::mux::MuxerBuilder;
+use rsmedia::stream::MediaType;
+
+use common::fixture;
+
+fn content_hashes(format: &str) -> Vec<(MediaType, String, u64)> {
+    let mut reader = Reader::new(fixture()).unwrap();
+    let writer = BufWriter::new(format).unwrap();
+    let mut muxer = MuxerBuilder::new(writer)
+        .with_streams(&reader)
+        .unwrap()
+        .with_content_hash(HashAlgorithm::Md5)
+        .build();
+    while let Ok(packet) = reader.read_any() {
+        muxer.mux(packet).unwrap();
+    }
+    muxer.finish().unwrap();
+    muxer
+        .content_hashes()
+        .unwrap()
+        .into_iter()
+        .map(|stream_hash| {
+            assert_eq!(stream_hash.decode_errors, 0);
+            (stream_hash.media_type, stream_hash.hash, stream_hash.frames)
+        })
+        .collect()
+}
+
+#[test]
+fn test_content_hash_decodes_fixture() {
+    rsmedia::init().unwrap();
+    let hashes = content_hashes("mp4");
+    let (_, hash, frames) = hashes
+        .iter()
+        .find(|(media_type, _, _)| *media_type == MediaType::Video)
+        .unwrap();
+    assert_eq!(hash.len(), 32);
+    assert!(*frames > 0);
+    // The hash describes the decoded content, so it does not depend on the container.
+    assert_eq!(hashes, content_hashes("matroska"));
+}
@@ -0,0 +1,40 @@
+#![cfg(feature = "ndarray")]
+
+mod common;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rsmedia::clock::{AudioClock, WallClock};
+use rsmedia::decode::DecoderBuilder;
+use rsmedia::error::Error;
+
+use common::fixture;
+
+#[test]
+fn test_decoder_drops_late_frames() {
+    rsmedia::init().unwrap();
+    // The clock is far past the end of the fixture, so every frame is late.
+    let clock = Arc::new(WallClock::starting_at(Duration::from_secs(3600)));
+    let mut decoder = DecoderBuilder::new(fixture())
+        .with_clock(clock)
+        .build()
+        .unwrap();
+    assert!(matches!(decoder.decode(), Err(Error::DecodeExhausted)));
+}
+
+#[test]
+fn test_decoder_keeps_early_frames() {
+    rsmedia::init().unwrap();
+    // An audio clock that never advances stays at the start, so no frame is late.
+    let mut decoder = DecoderBuilder::new(fixture())
+        .with_clock(Arc::new(AudioClock::new(48_000)))
+        .build()
+        .unwrap();
+    let frames = decoder
+        .decode_iter()
+        .take_while(Result::is_ok)
+        .take(10)
+        .count();
+    assert_eq!(frames, 10);
+}
@@ -0,0 +1,36 @@
+//! Helpers shared by the integration tests. Every test binary compiles this module, but not every
+//! one uses all helpers.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use ffmpeg::util::format::{sample::Type as SampleType, Sample};
+use rsmedia::audio::AudioEncoderBuilder;
+use rsmedia::decode::Decoder;
+use rsmedia::time::Time;
+
+/// Path of the video that the tests read.
+pub fn fixture() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/video.mp4")
+}
+
+/// Write seconds of silence that start at a time, and get the path.
+pub fn write_audio(dir: &Path, seconds: f64, start: f64) -> PathBuf {
+    let path = dir.join(format!("audio-{seconds}-{start}.m4a"));
+    let mut encoder = AudioEncoderBuilder::new(path.as_path(), 48_000, 2)
+        .with_input_format(Sample::F32(SampleType::Packed))
+        .build()
+        .unwrap();
+    let samples = (seconds * 48_000.0) as usize;
+    encoder
+        .encode_interleaved_f32(&vec![0.0; 2 * samples], Time::from_secs_f64(start))
+        .unwrap();
+    encoder.finish().unwrap();
+    path
+}
+
+/// Count the video frames of a file.
+pub fn count_frames(path: &Path) -> usize {
+    let mut decoder = Decoder::new(path).unwrap();
+    decoder.decode_raw_iter().take_while(Result::is_ok).count()
+}
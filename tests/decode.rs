@@ -0,0 +1,281 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use ffmpeg::Error as AvError;
+use rsmedia::decode::{CodecStatus, Decoder, DecoderBuilder};
+use rsmedia::encode::Deadline;
+#[cfg(feature = "ndarray")]
+use rsmedia::encode::{Encoder, Settings};
+use rsmedia::error::Error;
+use rsmedia::interrupt::Interrupt;
+use rsmedia::io::Reader;
+use rsmedia::limits::{ResourceLimit, ResourceLimits};
+#[cfg(feature = "ndarray")]
+use rsmedia::time::Time;
+use tempfile::TempDir;
+
+use common::fixture;
+
+#[test]
+fn test_batch_error_returns_partial_batch() {
+    rsmedia::init().unwrap();
+    let mut decoder = DecoderBuilder::new(fixture())
+        .with_resource_limits(ResourceLimits::new().with_max_packets(20))
+        .build()
+        .unwrap();
+
+    // The frames decoded before the limit is reached are not lost.
+    let batch = decoder.decode_raw_batch(100).unwrap();
+    assert!(!batch.is_empty());
+    assert!(batch.len() < 100);
+    assert!(matches!(
+        decoder.decode_raw_batch(100),
+        Err(Error::ResourceLimitExceeded(ResourceLimit::Packets(20)))
+    ));
+}
+
+#[test]
+fn test_batch_at_end_of_stream() {
+    rsmedia::init().unwrap();
+    let mut decoder = DecoderBuilder::new(fixture()).build().unwrap();
+    let mut frames = 0;
+    loop {
+        match decoder.decode_raw_batch(100) {
+            Ok(batch) => frames += batch.len(),
+            Err(Error::DecodeExhausted) => break,
+            Err(err) => panic!("{err}"),
+        }
+    }
+    assert_eq!(frames, 901);
+}
+
+#[test]
+fn test_shutdown_drains_decoder() {
+    rsmedia::init().unwrap();
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let mut decoded = 0;
+    for _ in 0..100 {
+        decoder.decode_raw().unwrap();
+        decoded += 1;
+    }
+
+    let (frames, report) = decoder.shutdown(Deadline::Unbounded).unwrap();
+    assert_eq!(report.frames_dropped, 0);
+    assert_eq!(frames.len() as u64, report.frames_flushed);
+    assert!(!report.trailer_written);
+    assert!(decoded + frames.len() <= 901);
+
+    // No more packets are read after the shutdown.
+    assert!(matches!(decoder.decode_raw(), Err(Error::DecodeExhausted)));
+    let (frames, report) = decoder.shutdown(Deadline::Unbounded).unwrap();
+    assert!(frames.is_empty());
+    assert_eq!(report.frames_flushed, 0);
+}
+
+#[test]
+fn test_shutdown_past_deadline_drops_frames() {
+    rsmedia::init().unwrap();
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    for _ in 0..100 {
+        decoder.decode_raw().unwrap();
+    }
+
+    let (frames, report) = decoder.shutdown(Deadline::At(Instant::now())).unwrap();
+    assert!(frames.is_empty());
+    assert_eq!(report.frames_flushed, 0);
+    assert_eq!(report.frames_dropped, report.frames_in_flight);
+    assert!(matches!(decoder.decode_raw(), Err(Error::DecodeExhausted)));
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn test_parameter_changes_are_queued() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+
+    // Transport stream segments can be concatenated, which changes the resolution mid-stream.
+    let sizes = [(64, 48), (128, 96), (64, 48)];
+    let mut stream = Vec::new();
+    for (index, (width, height)) in sizes.into_iter().enumerate() {
+        let path = dir.path().join(format!("{index}.ts"));
+        let settings = Settings::preset_h264_yuv420p(width, height, false);
+        let mut encoder = Encoder::new(path.as_path(), settings).unwrap();
+        for frame_index in 0..10 {
+            let frame = ndarray::Array3::from_elem((height, width, 3), 128);
+            let time = Time::from_secs_f64(frame_index as f64 / 25.0);
+            encoder.encode(&frame, time).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+        stream.extend(std::fs::read(&path).unwrap());
+    }
+    let path = dir.path().join("stream.ts");
+    std::fs::write(&path, stream).unwrap();
+
+    let mut decoder = Decoder::new(path.as_path()).unwrap();
+    while decoder.decode_raw().is_ok() {}
+    let changes = std::iter::from_fn(|| decoder.take_parameter_change())
+        .map(|change| (change.previous_size, change.size))
+        .collect::<Vec<_>>();
+    assert_eq!(changes, [((64, 48), (128, 96)), ((128, 96), (64, 48))]);
+}
+
+#[test]
+fn test_decode_from_custom_source() {
+    rsmedia::init().unwrap();
+    let data = std::fs::read(fixture()).unwrap();
+    let mut decoder = DecoderBuilder::from_reader(std::io::Cursor::new(data))
+        .build()
+        .unwrap();
+    let frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(frames, 901);
+}
+
+/// Copy the fixture with the NAL unit length of a few video packets in the middle overwritten, so
+/// that they fail to decode, and get the path.
+fn write_corrupt_fixture(dir: &Path) -> PathBuf {
+    let mut data = std::fs::read(fixture()).unwrap();
+    let mut reader = Reader::new(fixture()).unwrap();
+    let video_stream_index = reader.best_video_stream_index().unwrap();
+    let corrupt = std::iter::from_fn(|| reader.read(video_stream_index).ok())
+        .skip(100)
+        .filter(|packet| !packet.is_key() && packet.size() >= 64)
+        .take(2)
+        .collect::<Vec<_>>();
+    assert_eq!(corrupt.len(), 2);
+    for packet in corrupt {
+        let needle = &packet.data()[..64];
+        let offset = data
+            .windows(needle.len())
+            .position(|window| window == needle)
+            .unwrap();
+        data[offset..offset + 4].copy_from_slice(&[0xff; 4]);
+    }
+    let path = dir.join("corrupt.mp4");
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn test_resync_skips_to_next_keyframe() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = write_corrupt_fixture(dir.path());
+    let mut decoder = DecoderBuilder::new(path.as_path())
+        .with_resync(10)
+        .build()
+        .unwrap();
+
+    // Decoding runs to the end of the stream.
+    let mut frames = 0;
+    loop {
+        match decoder.decode_raw() {
+            Ok(_) => frames += 1,
+            Err(Error::ReadExhausted | Error::DecodeExhausted) => break,
+            Err(err) => panic!("decoding failed after {frames} frames: {err}"),
+        }
+    }
+    assert!(frames > 100 && frames < 901, "{frames} frames");
+
+    let skipped = decoder.take_skipped_ranges();
+    assert!(!skipped.is_empty());
+    for range in &skipped {
+        assert_eq!(range.error, AvError::InvalidData);
+        assert!(range.packets >= 1);
+        assert!(range.start.as_secs_f64() <= range.end.as_secs_f64());
+    }
+    // The ranges are only reported once.
+    assert!(decoder.take_skipped_ranges().is_empty());
+}
+
+#[test]
+fn test_resync_error_limit() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = write_corrupt_fixture(dir.path());
+    let mut decoder = DecoderBuilder::new(path.as_path())
+        .with_resync(0)
+        .build()
+        .unwrap();
+    let error = std::iter::from_fn(|| Some(decoder.decode_raw()))
+        .find_map(Result::err)
+        .unwrap();
+    assert!(matches!(
+        error,
+        Error::ResourceLimitExceeded(ResourceLimit::DecodeErrors(0))
+    ));
+}
+
+#[test]
+fn test_no_skipped_ranges_without_corruption() {
+    rsmedia::init().unwrap();
+    let mut decoder = DecoderBuilder::new(fixture())
+        .with_resync(10)
+        .build()
+        .unwrap();
+    let frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(frames, 901);
+    assert!(decoder.take_skipped_ranges().is_empty());
+}
+
+#[test]
+fn test_resync_does_not_recover_from_interrupt() {
+    rsmedia::init().unwrap();
+    let interrupt = Interrupt::new();
+    let mut decoder = DecoderBuilder::new(fixture())
+        .with_interrupt(interrupt.clone())
+        .with_resync(1000)
+        .build()
+        .unwrap();
+    for _ in 0..10 {
+        decoder.decode_raw().unwrap();
+    }
+
+    // The reader stops once the data that was read before the interrupt is used up.
+    interrupt.cancel();
+    let started = Instant::now();
+    let mut frames = 0;
+    let error = loop {
+        match decoder.decode_raw() {
+            Ok(_) => frames += 1,
+            Err(err) => break err,
+        }
+    };
+    assert!(
+        matches!(error, Error::BackendError(AvError::Exit)),
+        "{error:?}"
+    );
+    assert!(frames < 891, "decoded {frames} frames after the interrupt");
+    assert!(started.elapsed().as_secs() < 5);
+    assert!(decoder.take_skipped_ranges().is_empty());
+}
+
+#[test]
+fn test_drain_returns_buffered_frames() {
+    rsmedia::init().unwrap();
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let mut received = Vec::new();
+    for _ in 0..100 {
+        let packet = decoder.read_packet().unwrap();
+        assert!(matches!(
+            decoder.send_packet(packet),
+            Ok(CodecStatus::Ready(()))
+        ));
+        while let CodecStatus::Ready(frame) = decoder.receive_frame().unwrap() {
+            received.push(frame);
+        }
+    }
+
+    // Every packet decodes to a frame, so the decoder held the frames not received yet.
+    let drained = decoder.drain().unwrap();
+    assert_eq!(received.len() + drained.len(), 100);
+    let last_received = received.last().and_then(|frame| frame.pts());
+    assert!(drained.iter().all(|frame| frame.pts() > last_received));
+
+    // The decoder takes the packets that follow.
+    let decoded = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert!(decoded > 0);
+    assert!(100 + decoded <= 901);
+}
@@ -0,0 +1,22 @@
+mod common;
+
+use rsmedia::decode::Decoder;
+use rsmedia::diff::DiffBuilder;
+use tempfile::TempDir;
+
+use common::fixture;
+
+#[test]
+fn test_diff_complete_inputs() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let frames = Decoder::new(fixture()).unwrap().frames().unwrap();
+
+    let report = DiffBuilder::new(fixture(), fixture())
+        .with_ssim_map()
+        .render(dir.path().join("diff.mp4"))
+        .unwrap();
+    assert!(report.frames.abs_diff(frames) <= 1);
+    assert_eq!(report.max_abs_diff, 0);
+    assert!(report.mean_ssim.unwrap() > 0.999);
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use std::time::Duration;
+
+use rsmedia::decode::Decoder;
+use rsmedia::edl::{Edl, EdlClip, EdlExecutor, Transition};
+use rsmedia::encode::Settings;
+use rsmedia::time::Time;
+use tempfile::TempDir;
+
+use common::fixture;
+
+#[test]
+fn test_render_clips_to_end_of_source() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let output = dir.path().join("output.mp4");
+    let duration = Decoder::new(fixture())
+        .unwrap()
+        .duration()
+        .unwrap()
+        .as_secs_f64();
+
+    // The second clip runs past the end of the source, so its last frame is held.
+    let edl = Edl::new()
+        .with_clip(EdlClip::new(
+            fixture(),
+            Time::from_secs_f64(0.0),
+            Time::from_secs_f64(1.0),
+        ))
+        .with_clip(
+            EdlClip::new(
+                fixture(),
+                Time::from_secs_f64((duration - 1.0).max(0.0)),
+                Time::from_secs_f64(duration + 0.5),
+            )
+            .with_transition(Transition::Dissolve(Duration::from_millis(500))),
+        );
+    EdlExecutor::new(&edl, Settings::preset_h264_yuv420p(320, 240, false))
+        .render(output.as_path())
+        .unwrap();
+
+    let mut decoder = Decoder::new(output).unwrap();
+    let frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    let expected = (edl.duration().as_secs_f64() * 30.0).ceil() as usize;
+    assert!(frames.abs_diff(expected) <= 1);
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use std::path::Path;
+use std::time::Instant;
+
+use rsmedia::decode::{CodecStatus, Decoder};
+use rsmedia::encode::{Deadline, Encoder, Settings};
+use rsmedia::io::Reader;
+use tempfile::TempDir;
+
+use common::fixture;
+
+/// Encode the first frames of the fixture into a new encoder.
+fn encode(path: &Path, frames: usize) -> Encoder {
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    let mut encoder = Encoder::new(path, settings).unwrap();
+    for frame in decoder.decode_raw_iter().take(frames) {
+        encoder.encode_raw(frame.unwrap()).unwrap();
+    }
+    encoder
+}
+
+/// Encode the first frames of the fixture with settings, forcing keyframes at some frames, and get
+/// the packets of the output in decoding order as their timestamp in seconds and whether they are
+/// keyframes.
+fn encode_packets(
+    path: &Path,
+    settings: impl FnOnce(Settings) -> Settings,
+    frames: usize,
+    forced: &[usize],
+) -> Vec<(f64, bool)> {
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let (width, height) = decoder.size();
+    let settings = settings(Settings::preset_h264_yuv420p(
+        width as usize,
+        height as usize,
+        false,
+    ));
+    let mut encoder = Encoder::new(path, settings).unwrap();
+    for (index, frame) in decoder.decode_raw_iter().take(frames).enumerate() {
+        if forced.contains(&index) {
+            encoder.force_keyframe();
+        }
+        encoder.encode_raw(frame.unwrap()).unwrap();
+    }
+    encoder.finish().unwrap();
+    drop(encoder);
+
+    let mut reader = Reader::new(path).unwrap();
+    let stream_index = reader.best_video_stream_index().unwrap();
+    std::iter::from_fn(|| reader.read(stream_index).ok())
+        .map(|packet| (packet.pts().as_secs_f64(), packet.is_key()))
+        .collect()
+}
+
+/// Get the indices of the frames that are keyframes, in presentation order.
+fn keyframe_indices(packets: &[(f64, bool)]) -> Vec<usize> {
+    let mut packets = packets.to_vec();
+    packets.sort_by(|a, b| a.0.total_cmp(&b.0));
+    packets
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, is_key))| *is_key)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+#[test]
+fn test_shutdown_flushes_and_writes_trailer() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let mut encoder = encode(path.as_path(), 50);
+
+    let report = encoder.shutdown(Deadline::Unbounded).unwrap();
+    assert!(report.trailer_written);
+    assert_eq!(report.frames_dropped, 0);
+    assert_eq!(report.frames_flushed, report.frames_in_flight);
+
+    // No more frames are taken after the shutdown.
+    let frame = Decoder::new(fixture()).unwrap().decode_raw().unwrap();
+    assert!(matches!(encoder.send_frame(frame), Ok(CodecStatus::Eof)));
+    drop(encoder);
+
+    let mut decoder = Decoder::new(path).unwrap();
+    let decoded = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(decoded, 50);
+}
+
+#[test]
+fn test_shutdown_past_deadline_still_writes_trailer() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let mut encoder = encode(path.as_path(), 50);
+
+    let report = encoder.shutdown(Deadline::At(Instant::now())).unwrap();
+    assert!(report.trailer_written);
+    assert_eq!(report.frames_flushed, 0);
+    assert_eq!(report.frames_dropped, report.frames_in_flight);
+    drop(encoder);
+
+    let mut decoder = Decoder::new(path).unwrap();
+    let decoded = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(decoded as u64, 50 - report.frames_dropped);
+}
+
+#[test]
+fn test_keyframes_at_interval() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let packets = encode_packets(
+        path.as_path(),
+        |settings| settings.with_keyframe_interval(10),
+        50,
+        &[],
+    );
+    assert_eq!(packets.len(), 50);
+    let keyframes = keyframe_indices(&packets);
+    for index in [0, 10, 20, 30, 40] {
+        assert!(keyframes.contains(&index), "{keyframes:?}");
+    }
+}
+
+#[test]
+fn test_force_keyframe() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let packets = encode_packets(
+        path.as_path(),
+        |settings| settings.with_keyframe_interval(1000),
+        60,
+        &[17, 42],
+    );
+    assert_eq!(packets.len(), 60);
+    let keyframes = keyframe_indices(&packets);
+    for index in [0, 17, 42] {
+        assert!(keyframes.contains(&index), "{keyframes:?}");
+    }
+}
+
+#[test]
+fn test_no_b_frames_keeps_decoding_order() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let packets = encode_packets(
+        path.as_path(),
+        |settings| settings.with_max_b_frames(0).with_closed_gop(),
+        50,
+        &[],
+    );
+    assert_eq!(packets.len(), 50);
+    // Without B-frames no frame is decoded before a frame it is shown after.
+    assert!(packets.windows(2).all(|pair| pair[0].0 < pair[1].0));
+}
+
+#[test]
+fn test_flush_and_keep_encoding() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    let mut encoder = Encoder::new(path.as_path(), settings).unwrap();
+    let mut frames = decoder.decode_raw_iter().map(Result::unwrap);
+    for frame in frames.by_ref().take(50) {
+        encoder.encode_raw(frame).unwrap();
+    }
+
+    // The packets of all frames sent so far come out of the flush.
+    let packets = encoder.flush().unwrap();
+    assert!(!packets.is_empty());
+    for packet in packets {
+        encoder.write_packet(packet).unwrap();
+    }
+    let encoded = if encoder.supports_flush() {
+        for frame in frames.by_ref().take(50) {
+            encoder.encode_raw(frame).unwrap();
+        }
+        100
+    } else {
+        let frame = frames.next().unwrap();
+        assert!(matches!(encoder.send_frame(frame), Ok(CodecStatus::Eof)));
+        50
+    };
+    encoder.finish().unwrap();
+    drop(encoder);
+
+    let mut decoder = Decoder::new(path.as_path()).unwrap();
+    let decoded = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(decoded, encoded);
+    if encoded == 100 {
+        // Encoding restarts with a keyframe after the flush.
+        let mut reader = Reader::new(path.as_path()).unwrap();
+        let stream_index = reader.best_video_stream_index().unwrap();
+        let packets = std::iter::from_fn(|| reader.read(stream_index).ok())
+            .map(|packet| (packet.pts().as_secs_f64(), packet.is_key()))
+            .collect::<Vec<_>>();
+        assert!(keyframe_indices(&packets).contains(&50));
+    }
+}
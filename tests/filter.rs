@@ -0,0 +1,144 @@
+#![cfg(feature = "filter")]
+
+mod common;
+
+use ffmpeg::util::format::Pixel;
+use rsmedia::decode::{Decoder, DecoderBuilder};
+use rsmedia::encode::{EncoderBuilder, Settings};
+use rsmedia::filter::Filter;
+use rsmedia::frame::RawFrame;
+use tempfile::TempDir;
+
+use common::{count_frames, fixture};
+
+/// Black frame of a size, with a timestamp.
+fn frame(width: u32, height: u32, pts: i64) -> RawFrame {
+    let mut frame = RawFrame::new(Pixel::RGB24, width, height);
+    frame.data_mut(0).fill(0);
+    frame.set_pts(Some(pts));
+    frame
+}
+
+fn pull_all(filter: &mut Filter) -> Vec<RawFrame> {
+    std::iter::from_fn(|| filter.pull().unwrap()).collect()
+}
+
+#[test]
+fn test_filter_keeps_timestamps() {
+    rsmedia::init().unwrap();
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let time_base = decoder.time_base();
+    let mut filter = Filter::new("hflip,scale=160:120");
+    let mut input = Vec::new();
+    let mut output = Vec::new();
+    for frame in decoder.decode_raw_iter().take(10) {
+        let frame = frame.unwrap();
+        input.push(frame.pts());
+        filter.push(&frame, time_base).unwrap();
+        output.extend(pull_all(&mut filter));
+    }
+    filter.finish().unwrap();
+    output.extend(pull_all(&mut filter));
+
+    assert_eq!(output.len(), 10);
+    for frame in &output {
+        assert_eq!((frame.width(), frame.height()), (160, 120));
+    }
+    let output = output.iter().map(|frame| frame.pts()).collect::<Vec<_>>();
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_filter_is_reconfigured_when_size_changes() {
+    rsmedia::init().unwrap();
+    let time_base = (1, 25).into();
+    let mut filter = Filter::new("hflip");
+    let mut output = Vec::new();
+    for (index, (width, height)) in [(64, 48), (64, 48), (32, 24), (32, 24)]
+        .into_iter()
+        .enumerate()
+    {
+        filter
+            .push(&frame(width, height, index as i64), time_base)
+            .unwrap();
+        output.extend(pull_all(&mut filter));
+    }
+    filter.finish().unwrap();
+    output.extend(pull_all(&mut filter));
+
+    let sizes = output
+        .iter()
+        .map(|frame| (frame.width(), frame.height(), frame.pts()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sizes,
+        [
+            (64, 48, Some(0)),
+            (64, 48, Some(1)),
+            (32, 24, Some(2)),
+            (32, 24, Some(3)),
+        ]
+    );
+}
+
+#[test]
+fn test_filter_reset_discards_frames() {
+    rsmedia::init().unwrap();
+    let time_base = (1, 25).into();
+    // Reversing holds back all frames until the end of the input.
+    let mut filter = Filter::new("reverse");
+    filter.push(&frame(64, 48, 0), time_base).unwrap();
+    filter.push(&frame(64, 48, 1), time_base).unwrap();
+    filter.reset();
+    filter.push(&frame(32, 24, 2), time_base).unwrap();
+    filter.finish().unwrap();
+
+    let output = pull_all(&mut filter);
+    assert_eq!(output.len(), 1);
+    assert_eq!((output[0].width(), output[0].height()), (32, 24));
+}
+
+#[test]
+fn test_decoder_with_filter() {
+    rsmedia::init().unwrap();
+    let mut decoder = DecoderBuilder::new(fixture())
+        .with_filter(Filter::new("framestep=2,scale=160:120"))
+        .build()
+        .unwrap();
+    let frames = decoder
+        .decode_raw_iter()
+        .take_while(Result::is_ok)
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    assert_eq!(frames.len(), 451);
+    for frame in &frames {
+        assert_eq!(frame.format(), Pixel::RGB24);
+        assert_eq!((frame.width(), frame.height()), (160, 120));
+    }
+}
+
+#[test]
+fn test_encoder_with_filter() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    // The output of the filter is scaled back to the size of the encoder.
+    let mut encoder = EncoderBuilder::new(path.as_path(), settings)
+        .with_filter(Filter::new("framestep=2,scale=160:120"))
+        .build()
+        .unwrap();
+    for frame in decoder.decode_raw_iter().take(50) {
+        encoder.encode_raw(frame.unwrap()).unwrap();
+    }
+    encoder.finish().unwrap();
+    drop(encoder);
+
+    assert_eq!(count_frames(&path), 25);
+    assert_eq!(
+        Decoder::new(path.as_path()).unwrap().size(),
+        (width, height)
+    );
+}
@@ -0,0 +1,109 @@
+#![cfg(feature = "gstreamer")]
+
+mod common;
+
+use ffmpeg::Error as AvError;
+use gstreamer::prelude::*;
+use gstreamer_app::{AppSink, AppSrc};
+use rsmedia::error::Error;
+use rsmedia::gstreamer::{AppSinkReaderBuilder, AppSrcWriter};
+use rsmedia::io::Reader;
+use rsmedia::mux::MuxerBuilder;
+use tempfile::TempDir;
+
+use common::fixture;
+
+/// Launch a pipeline, and get one of its elements by name.
+fn launch<T: IsA<gstreamer::Element>>(description: &str, name: &str) -> (gstreamer::Pipeline, T) {
+    gstreamer::init().unwrap();
+    let pipeline = gstreamer::parse::launch(description)
+        .unwrap()
+        .downcast::<gstreamer::Pipeline>()
+        .unwrap();
+    let element = pipeline.by_name(name).unwrap().downcast::<T>().unwrap();
+    (pipeline, element)
+}
+
+/// Pipeline that produces a Matroska stream of 30 JPEG frames.
+const APPSINK_PIPELINE: &str = "videotestsrc num-buffers=30 ! video/x-raw,width=64,height=48 \
+    ! jpegenc ! matroskamux streamable=true ! appsink name=sink";
+
+#[test]
+fn test_read_from_appsink() {
+    rsmedia::init().unwrap();
+    let (pipeline, appsink) = launch::<AppSink>(APPSINK_PIPELINE, "sink");
+    pipeline.set_state(gstreamer::State::Playing).unwrap();
+    let mut reader = AppSinkReaderBuilder::new(appsink).build().unwrap();
+    assert_eq!(reader.source.to_string(), "appsink://sink");
+
+    let stream = reader.best_video_stream_index().unwrap();
+    let packets = std::iter::from_fn(|| reader.read(stream).ok()).count();
+    assert_eq!(packets, 30);
+    pipeline.set_state(gstreamer::State::Null).unwrap();
+}
+
+#[test]
+fn test_stopped_appsink_is_an_error() {
+    rsmedia::init().unwrap();
+    let (pipeline, appsink) = launch::<AppSink>(
+        "videotestsrc is-live=true ! video/x-raw,width=64,height=48 ! jpegenc \
+            ! matroskamux streamable=true ! appsink name=sink",
+        "sink",
+    );
+    pipeline.set_state(gstreamer::State::Playing).unwrap();
+    let mut reader = AppSinkReaderBuilder::new(appsink).build().unwrap();
+    let stream = reader.best_video_stream_index().unwrap();
+    reader.read(stream).unwrap();
+
+    // The stream is cut off without reaching EOS, so it did not end.
+    pipeline.set_state(gstreamer::State::Null).unwrap();
+    let error = loop {
+        if let Err(err) = reader.read(stream) {
+            break err;
+        }
+    };
+    assert!(
+        matches!(error, Error::BackendError(AvError::External)),
+        "{error:?}"
+    );
+}
+
+#[test]
+fn test_write_to_appsrc() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.ts");
+    let (pipeline, appsrc) = launch::<AppSrc>(
+        &format!("appsrc name=src ! filesink location={}", path.display()),
+        "src",
+    );
+    pipeline.set_state(gstreamer::State::Playing).unwrap();
+
+    let mut reader = Reader::new(fixture()).unwrap();
+    let writer = AppSrcWriter::new(appsrc, "mpegts").unwrap();
+    let mut muxer = MuxerBuilder::new(writer)
+        .with_streams(&reader)
+        .unwrap()
+        .interleaved()
+        .build();
+    while let Ok(packet) = reader.read_any() {
+        muxer.mux(packet).unwrap();
+    }
+    muxer.finish().unwrap();
+
+    // Writing the trailer signals EOS, after which the file is complete.
+    let bus = pipeline.bus().unwrap();
+    let message = bus
+        .timed_pop_filtered(
+            gstreamer::ClockTime::from_seconds(10),
+            &[gstreamer::MessageType::Eos, gstreamer::MessageType::Error],
+        )
+        .unwrap();
+    assert_eq!(message.type_(), gstreamer::MessageType::Eos);
+    pipeline.set_state(gstreamer::State::Null).unwrap();
+
+    let mut reader = Reader::new(path).unwrap();
+    let stream = reader.best_video_stream_index().unwrap();
+    let packets = std::iter::from_fn(|| reader.read(stream).ok()).count();
+    assert_eq!(packets, 901);
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rsmedia::decode::Decoder;
+use rsmedia::hls::{PlaylistType, Segment, SegmentedWriterBuilder};
+use rsmedia::io::Reader;
+use rsmedia::mux::MuxerBuilder;
+use rsmedia::options::Options;
+use tempfile::TempDir;
+
+use common::fixture;
+
+/// Remux the fixture into an HLS playlist, and get the segments that were reported.
+fn remux(builder: SegmentedWriterBuilder, interleaved: bool) -> Vec<Segment> {
+    let segments = Arc::new(Mutex::new(Vec::new()));
+    let writer = builder
+        .with_segment_callback({
+            let segments = segments.clone();
+            move |segment| segments.lock().unwrap().push(segment)
+        })
+        .build()
+        .unwrap();
+    let mut reader = Reader::new(fixture()).unwrap();
+    let muxer = MuxerBuilder::new(writer).with_streams(&reader).unwrap();
+    let mut muxer = if interleaved {
+        muxer.interleaved().build()
+    } else {
+        muxer.build()
+    };
+    while let Ok(packet) = reader.read_any() {
+        muxer.mux(packet).unwrap();
+    }
+    muxer.finish().unwrap();
+    drop(muxer);
+    Arc::try_unwrap(segments).unwrap().into_inner().unwrap()
+}
+
+/// Check that the segments are numbered in order, exist, are listed in the playlist, each have a
+/// duration and together cover the fixture.
+fn check_segments(segments: &[Segment], playlist: &Path) {
+    let duration = Decoder::new(fixture())
+        .unwrap()
+        .duration()
+        .unwrap()
+        .as_secs_f64();
+    let playlist = std::fs::read_to_string(playlist).unwrap();
+    assert!(segments.len() > 1, "{segments:?}");
+    for (index, segment) in segments.iter().enumerate() {
+        assert_eq!(segment.sequence, index as u64);
+        assert!(segment.path.exists(), "{}", segment.path.display());
+        let file_name = segment.path.file_name().unwrap().to_str().unwrap();
+        assert!(playlist.contains(file_name), "{file_name} is not listed");
+        assert!(!segment.duration.is_zero(), "{segment:?}");
+    }
+    let total = segments
+        .iter()
+        .map(|segment| segment.duration)
+        .sum::<Duration>()
+        .as_secs_f64();
+    assert!((total - duration).abs() < 0.5, "{total}s of {duration}s");
+}
+
+#[test]
+fn test_segments_are_reported() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let playlist = dir.path().join("stream.m3u8");
+    for interleaved in [false, true] {
+        let segments = remux(
+            SegmentedWriterBuilder::new(playlist.as_path()).with_playlist_type(PlaylistType::Vod),
+            interleaved,
+        );
+        check_segments(&segments, &playlist);
+    }
+}
+
+#[test]
+fn test_segment_paths_ignore_base_url() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let playlist = dir.path().join("stream.m3u8");
+    let mut options = Options::default();
+    options.set("hls_base_url", "https://cdn.example.com/live/");
+    let segments = remux(
+        SegmentedWriterBuilder::new(playlist.as_path())
+            .with_playlist_type(PlaylistType::Event)
+            .with_options(&options),
+        false,
+    );
+    check_segments(&segments, &playlist);
+    for segment in &segments {
+        assert_eq!(segment.path.parent(), Some(dir.path()));
+    }
+}
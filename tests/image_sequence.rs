@@ -0,0 +1,95 @@
+#![cfg(feature = "ndarray")]
+
+mod common;
+
+use rsmedia::decode::Decoder;
+use rsmedia::encode::{Encoder, Settings};
+use rsmedia::frame::Frame;
+use rsmedia::location::Location;
+use rsmedia::time::Time;
+use tempfile::TempDir;
+
+use common::fixture;
+
+/// Decode the first frames of the fixture.
+fn decode(frames: usize) -> Vec<(Time, Frame)> {
+    Decoder::new(fixture())
+        .unwrap()
+        .decode_iter()
+        .take(frames)
+        .map(Result::unwrap)
+        .collect()
+}
+
+/// Encode frames to an image sequence.
+fn encode(location: Location, settings: Settings, frames: &[(Time, Frame)]) {
+    let mut encoder = Encoder::new(location, settings).unwrap();
+    for (time, frame) in frames {
+        encoder.encode(frame, *time).unwrap();
+    }
+    encoder.finish().unwrap();
+}
+
+#[test]
+fn test_png_sequence_round_trip() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let frames = decode(10);
+    let (height, width, _) = frames[0].1.dim();
+    let location = Location::image_sequence(dir.path().join("frame_%04d.png"), (25, 1));
+    encode(
+        location.clone(),
+        Settings::preset_png(width, height),
+        &frames,
+    );
+
+    // Numbering starts at 1 when writing.
+    assert!(!dir.path().join("frame_0000.png").exists());
+    assert!(dir.path().join("frame_0001.png").exists());
+    assert!(dir.path().join("frame_0010.png").exists());
+    assert!(!dir.path().join("frame_0011.png").exists());
+
+    // The images are lossless, and their timestamps follow from the frame rate.
+    let decoded = Decoder::new(location)
+        .unwrap()
+        .decode_iter()
+        .take_while(Result::is_ok)
+        .map(Result::unwrap)
+        .collect::<Vec<_>>();
+    assert_eq!(decoded.len(), frames.len());
+    for (index, ((time, frame), (_, expected))) in decoded.iter().zip(&frames).enumerate() {
+        assert_eq!(frame, expected);
+        assert!((time.as_secs_f64() - index as f64 / 25.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_jpeg_sequence() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let frames = decode(5);
+    let (height, width, _) = frames[0].1.dim();
+    let location = Location::image_sequence(dir.path().join("%d.jpg"), (30000, 1001));
+    encode(
+        location.clone(),
+        Settings::preset_jpeg(width, height, 2),
+        &frames,
+    );
+
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), frames.len());
+    let mut decoder = Decoder::new(location).unwrap();
+    assert_eq!(decoder.size(), (width as u32, height as u32));
+    assert_eq!(
+        decoder.decode_iter().take_while(Result::is_ok).count(),
+        frames.len()
+    );
+}
+
+#[test]
+fn test_image_preset_survives_snapshot() {
+    rsmedia::init().unwrap();
+    let snapshot = Settings::preset_png(64, 48).snapshot();
+    assert_eq!(snapshot.encoder, "png");
+    let settings = Settings::from_snapshot(&snapshot).unwrap();
+    assert_eq!(settings.snapshot().encoder, "png");
+}
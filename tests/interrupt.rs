@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use ffmpeg::Error as AvError;
+use rsmedia::error::Error;
+use rsmedia::interrupt::Interrupt;
+#[cfg(feature = "tokio")]
+use rsmedia::io::r#async::AsyncReader;
+use rsmedia::io::ReaderBuilder;
+use rsmedia::limits::{ResourceLimit, ResourceLimits};
+use rsmedia::location::Url;
+
+/// Address that is not routed, so connecting to it blocks until the connection times out.
+fn unroutable() -> Url {
+    Url::parse("rtsp://10.255.255.1:554/stream").unwrap()
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_cancel_before_connect() {
+    rsmedia::init().unwrap();
+    let (interrupt_tx, interrupt_rx) = std::sync::mpsc::channel::<Interrupt>();
+    tokio::spawn(async move {
+        let interrupt = tokio::task::spawn_blocking(move || interrupt_rx.recv().unwrap())
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        interrupt.cancel();
+    });
+
+    let started = Instant::now();
+    let result = tokio::time::timeout(
+        Duration::from_secs(10),
+        AsyncReader::open_with(move |interrupt| {
+            interrupt_tx.send(interrupt.clone()).unwrap();
+            ReaderBuilder::new(unroutable())
+                .with_interrupt(interrupt)
+                .build()
+        }),
+    )
+    .await
+    .expect("cancel did not abort connecting");
+
+    assert!(matches!(result, Err(Error::BackendError(AvError::Exit))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_cancelled_interrupt_fails_open() {
+    rsmedia::init().unwrap();
+    let interrupt = Interrupt::new();
+    interrupt.cancel();
+    let started = Instant::now();
+    let result = ReaderBuilder::new(unroutable())
+        .with_interrupt(interrupt)
+        .build();
+    assert!(matches!(result, Err(Error::BackendError(AvError::Exit))));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_wall_time_limit_aborts_connect() {
+    rsmedia::init().unwrap();
+    let max_duration = Duration::from_millis(300);
+    let started = Instant::now();
+    let result = ReaderBuilder::new(unroutable())
+        .with_resource_limits(ResourceLimits::new().with_max_duration(max_duration))
+        .build();
+    assert!(matches!(
+        result,
+        Err(Error::ResourceLimitExceeded(ResourceLimit::Duration(max))) if max == max_duration
+    ));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
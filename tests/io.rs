@@ -0,0 +1,416 @@
+mod common;
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ffmpeg::Error as AvError;
+use rsmedia::bandwidth::FallbackPolicy;
+use rsmedia::error::Error;
+use rsmedia::io::{FlushPolicy, Reader, ReaderBuilder, WriterBuilder};
+use rsmedia::location::{Location, Url};
+use rsmedia::mux::MuxerBuilder;
+use rsmedia::options::Options;
+use rsmedia::stream::MediaType;
+use tempfile::TempDir;
+
+use common::fixture;
+
+/// Count the packets of a file.
+fn count_packets(path: &Path) -> usize {
+    let mut reader = Reader::new(path).unwrap();
+    std::iter::from_fn(|| reader.read_any().ok()).count()
+}
+
+/// Remux the fixture with a writer.
+fn remux(builder: WriterBuilder) {
+    let mut reader = Reader::new(fixture()).unwrap();
+    let mut muxer = MuxerBuilder::new(builder.build().unwrap())
+        .with_streams(&reader)
+        .unwrap()
+        .build();
+    while let Ok(packet) = reader.read_any() {
+        muxer.mux(packet).unwrap();
+    }
+    muxer.finish().unwrap();
+}
+
+#[test]
+fn test_io_buffer_size() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let packets = count_packets(&fixture());
+
+    // Buffers both smaller and larger than the default one, which seeking back to write the
+    // trailer goes back to.
+    for size in [512, 4096, 4 << 20] {
+        let path = dir.path().join(format!("output-{size}.mp4"));
+        remux(WriterBuilder::new(path.as_path()).with_io_buffer_size(size));
+        assert_eq!(count_packets(&path), packets);
+    }
+}
+
+#[test]
+fn test_zero_io_buffer_size_fails() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let result = WriterBuilder::new(dir.path().join("output.mp4").as_path())
+        .with_io_buffer_size(0)
+        .build();
+    assert!(matches!(
+        result,
+        Err(Error::BackendError(AvError::InvalidData))
+    ));
+}
+
+#[test]
+fn test_direct_io_and_flush_policies() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let packets = count_packets(&fixture());
+
+    let path = dir.path().join("direct.mp4");
+    remux(WriterBuilder::new(path.as_path()).with_direct_io());
+    assert_eq!(count_packets(&path), packets);
+
+    for (index, policy) in [
+        FlushPolicy::Packet,
+        FlushPolicy::Keyframe,
+        FlushPolicy::Interval(std::time::Duration::from_secs(1)),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let path = dir.path().join(format!("flush-{index}.mp4"));
+        remux(WriterBuilder::new(path.as_path()).with_flush_policy(policy));
+        assert_eq!(count_packets(&path), packets);
+    }
+}
+
+#[test]
+fn test_switch_from_source_that_stops_sending() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    // MPEG-TS needs no index, so it can be read from a plain TCP connection.
+    let path = dir.path().join("stream.ts");
+    remux(WriterBuilder::new(path.as_path()).with_format("mpegts"));
+    let packets = count_packets(&path);
+
+    // Send the whole stream, then keep the connection open without sending anything more.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("tcp://{}", listener.local_addr().unwrap());
+    let data = std::fs::read(&path).unwrap();
+    let (stop, stopped) = mpsc::channel::<()>();
+    let server = std::thread::spawn(move || {
+        let (mut socket, _) = listener.accept().unwrap();
+        socket.write_all(&data).unwrap();
+        let _ = stopped.recv();
+    });
+
+    let policy = FallbackPolicy::new(Duration::from_millis(100), 3, Duration::from_secs(10));
+    let mut reader = ReaderBuilder::new(url.parse::<Url>().unwrap())
+        .with_input_format("mpegts")
+        .with_fallback_sources([Location::File(path.clone())], policy)
+        .build()
+        .unwrap();
+    let started = Instant::now();
+    let read = std::iter::from_fn(|| reader.read_any().ok()).count();
+    drop(stop);
+    server.join().unwrap();
+
+    // The stalled read is interrupted and reading continues from the fallback source, which is
+    // read to the end. The last packets of the first source may be held back by the demuxer.
+    assert_eq!(reader.receive_stats().switches, 1);
+    assert!(
+        read > packets && read <= 2 * packets,
+        "{read} of {packets} packets"
+    );
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn test_media_description_accessors() {
+    rsmedia::init().unwrap();
+    let reader = Reader::new(fixture()).unwrap();
+    let video_stream_index = reader.best_video_stream_index().unwrap();
+    let descriptions = reader.media_descriptions();
+    assert_eq!(descriptions.len(), reader.input.streams().count());
+    let video = descriptions
+        .iter()
+        .find(|description| description.stream_index() == video_stream_index)
+        .unwrap();
+    assert_eq!(video.media_type(), MediaType::Video);
+    assert!(!video.codec_name().is_empty());
+    let (width, height) = video.size();
+    assert!(width > 0 && height > 0);
+    assert!(!video.is_commentary());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_sequential_writes_into_pipe() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let packets = count_packets(&fixture());
+
+    // A FIFO cannot seek, so the output is only complete if nothing is patched afterwards.
+    for extension in ["mp4", "mkv", "ts"] {
+        let fifo = dir.path().join(format!("fifo.{extension}"));
+        let status = std::process::Command::new("mkfifo")
+            .arg(&fifo)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let receiver = std::thread::spawn({
+            let fifo = fifo.clone();
+            move || std::fs::read(fifo).unwrap()
+        });
+        remux(WriterBuilder::new(fifo.as_path()).sequential());
+        let data = receiver.join().unwrap();
+
+        let path = dir.path().join(format!("received.{extension}"));
+        std::fs::write(&path, data).unwrap();
+        assert_eq!(count_packets(&path), packets, "{extension}");
+    }
+}
+
+#[test]
+fn test_sequential_rejects_formats_that_seek() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let result = WriterBuilder::new(dir.path().join("output.avi").as_path())
+        .sequential()
+        .build();
+    assert!(matches!(
+        result,
+        Err(Error::BackendError(AvError::InvalidData))
+    ));
+
+    let mut options = Options::default();
+    options.set("movflags", "faststart");
+    let result = WriterBuilder::new(dir.path().join("output.mp4").as_path())
+        .with_options(&options)
+        .sequential()
+        .build();
+    assert!(matches!(
+        result,
+        Err(Error::BackendError(AvError::InvalidData))
+    ));
+}
+
+/// Source that cannot seek, like a network stream.
+struct Unseekable<R>(R);
+
+impl<R: std::io::Read> std::io::Read for Unseekable<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl<R> std::io::Seek for Unseekable<R> {
+    fn seek(&mut self, _: std::io::SeekFrom) -> std::io::Result<u64> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[test]
+fn test_read_from_custom_source() {
+    rsmedia::init().unwrap();
+    let packets = count_packets(&fixture());
+    let file = std::fs::File::open(fixture()).unwrap();
+    let mut reader = ReaderBuilder::from_reader(file).build().unwrap();
+    assert_eq!(reader.source.to_string(), "io://reader");
+    assert_eq!(
+        std::iter::from_fn(|| reader.read_any().ok()).count(),
+        packets
+    );
+
+    // The source is seeked through the IO context.
+    reader.seek(0).unwrap();
+    assert!(reader.read_any().is_ok());
+}
+
+#[test]
+fn test_read_from_unseekable_custom_source() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    // MPEG-TS can be read without seeking.
+    let path = dir.path().join("stream.ts");
+    remux(WriterBuilder::new(path.as_path()).with_format("mpegts"));
+    let packets = count_packets(&path);
+
+    let data = std::fs::read(&path).unwrap();
+    let mut reader = ReaderBuilder::from_reader(Unseekable(std::io::Cursor::new(data)))
+        .with_input_format("mpegts")
+        .build()
+        .unwrap();
+    assert_eq!(
+        std::iter::from_fn(|| reader.read_any().ok()).count(),
+        packets
+    );
+}
+
+/// Minimal RTSP server that streams silence as L16 audio (static RTP payload type 11, 44.1 kHz
+/// mono) interleaved over the RTSP connection, and supports `PAUSE`. Like a server of recorded
+/// media, the media time does not advance while paused, and playing resumes at the position it was
+/// paused at. Counts the `PAUSE` requests it receives.
+fn serve_rtsp(listener: TcpListener, pauses: Arc<AtomicUsize>) {
+    /// Samples per RTP packet (10 ms).
+    const SAMPLES: u32 = 441;
+
+    let (socket, _) = listener.accept().unwrap();
+    let mut requests = BufReader::new(socket.try_clone().unwrap());
+    let socket = Arc::new(Mutex::new(socket));
+    let playing = Arc::new(AtomicBool::new(false));
+    let closed = Arc::new(AtomicBool::new(false));
+    let position = Arc::new(AtomicU32::new(0));
+    let sender = std::thread::spawn({
+        let (socket, playing, closed) = (socket.clone(), playing.clone(), closed.clone());
+        let position = position.clone();
+        move || {
+            let mut sequence = 0u16;
+            while !closed.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_millis(10));
+                let mut socket = socket.lock().unwrap();
+                if !playing.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let timestamp = position.load(Ordering::SeqCst);
+                let mut packet = vec![0x80, 11];
+                packet.extend_from_slice(&sequence.to_be_bytes());
+                packet.extend_from_slice(&timestamp.to_be_bytes());
+                packet.extend_from_slice(&1u32.to_be_bytes());
+                packet.resize(12 + 2 * SAMPLES as usize, 0);
+                let mut frame = vec![b'$', 0];
+                frame.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+                frame.extend_from_slice(&packet);
+                if socket.write_all(&frame).is_err() {
+                    break;
+                }
+                sequence = sequence.wrapping_add(1);
+                position.store(timestamp + SAMPLES, Ordering::SeqCst);
+            }
+        }
+    });
+
+    loop {
+        // Skip RTCP packets of the client, which are interleaved like the RTP packets.
+        if requests
+            .fill_buf()
+            .is_ok_and(|buf| buf.first() == Some(&b'$'))
+        {
+            let mut header = [0; 4];
+            requests.read_exact(&mut header).unwrap();
+            let mut packet = vec![0; u16::from_be_bytes([header[2], header[3]]) as usize];
+            requests.read_exact(&mut packet).unwrap();
+            continue;
+        }
+        let mut request = Vec::new();
+        loop {
+            let mut line = String::new();
+            if requests.read_line(&mut line).unwrap_or(0) == 0 {
+                closed.store(true, Ordering::SeqCst);
+                sender.join().unwrap();
+                return;
+            }
+            if line.trim().is_empty() {
+                break;
+            }
+            request.push(line.trim().to_string());
+        }
+        let method = request[0].split(' ').next().unwrap().to_string();
+        let sequence = request
+            .iter()
+            .find_map(|line| line.strip_prefix("CSeq: "))
+            .unwrap()
+            .to_string();
+        let range = format!(
+            "Session: 1\r\nRange: npt={:.3}-\r\n",
+            position.load(Ordering::SeqCst) as f64 / 44100.0
+        );
+        let (headers, body) = match method.as_str() {
+            "DESCRIBE" => (
+                "Content-Type: application/sdp\r\n",
+                "v=0\r\no=- 0 0 IN IP4 127.0.0.1\r\ns=Silence\r\nc=IN IP4 127.0.0.1\r\nt=0 0\r\n\
+                 m=audio 0 RTP/AVP 11\r\na=rtpmap:11 L16/44100/1\r\na=control:trackID=0\r\n",
+            ),
+            "SETUP" => (
+                "Transport: RTP/AVP/TCP;unicast;interleaved=0-1\r\nSession: 1\r\n",
+                "",
+            ),
+            "OPTIONS" => ("Public: DESCRIBE, SETUP, PLAY, PAUSE, TEARDOWN\r\n", ""),
+            "PLAY" => (range.as_str(), ""),
+            _ => ("Session: 1\r\n", ""),
+        };
+        match method.as_str() {
+            "PAUSE" => {
+                playing.store(false, Ordering::SeqCst);
+                pauses.fetch_add(1, Ordering::SeqCst);
+            }
+            "TEARDOWN" => playing.store(false, Ordering::SeqCst),
+            _ => {}
+        }
+        let response = format!(
+            "RTSP/1.0 200 OK\r\nCSeq: {sequence}\r\n{headers}Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        // The client does not wait for the response to `TEARDOWN` before closing the connection.
+        let mut socket = socket.lock().unwrap();
+        let _ = socket.write_all(response.as_bytes());
+        if method == "PLAY" {
+            playing.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[test]
+fn test_pause_and_resume_keep_timestamps_continuous() {
+    rsmedia::init().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("rtsp://{}/silence", listener.local_addr().unwrap());
+    let pauses = Arc::new(AtomicUsize::new(0));
+    let server = std::thread::spawn({
+        let pauses = pauses.clone();
+        move || serve_rtsp(listener, pauses)
+    });
+
+    let options = Options::preset_rtsp_transport_tcp();
+    let mut reader = ReaderBuilder::new(url.parse::<Url>().unwrap())
+        .with_options(&options)
+        .build()
+        .unwrap();
+    let read = |reader: &mut Reader| {
+        (0..20)
+            .map(|_| reader.read_any().unwrap().pts().as_secs_f64())
+            .collect::<Vec<_>>()
+    };
+    let mut timestamps = read(&mut reader);
+    reader.pause().unwrap();
+    std::thread::sleep(Duration::from_millis(500));
+    reader.resume().unwrap();
+    timestamps.extend(read(&mut reader));
+    drop(reader);
+    server.join().unwrap();
+
+    // The packets after resuming follow the packets before pausing, without a gap for the time
+    // spent paused. Packets that arrive while waiting for the response to `PAUSE` are skipped by
+    // the client, so there may be a short gap.
+    assert_eq!(pauses.load(Ordering::SeqCst), 1);
+    for pair in timestamps.windows(2) {
+        let step = pair[1] - pair[0];
+        assert!(step > 0.009 && step < 0.25, "{timestamps:?}");
+    }
+}
+
+#[test]
+fn test_pause_file_is_unsupported() {
+    rsmedia::init().unwrap();
+    let mut reader = Reader::new(fixture()).unwrap();
+    assert!(matches!(
+        reader.pause(),
+        Err(Error::BackendError(AvError::Other { errno })) if errno == ffmpeg::util::error::ENOSYS
+    ));
+}
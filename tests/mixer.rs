@@ -0,0 +1,83 @@
+#![cfg(feature = "filter")]
+
+use std::time::Duration;
+
+use ffmpeg::util::channel_layout::ChannelLayout;
+use ffmpeg::util::format::{sample::Type as SampleType, Sample};
+use ffmpeg::Rational;
+use rsmedia::frame::RawAudioFrame;
+use rsmedia::mixer::AudioMixer;
+
+const SAMPLE_RATE: u32 = 48_000;
+const FRAME_SAMPLES: usize = 1024;
+
+/// Frame of a constant signal, with a timestamp in samples.
+fn frame(index: usize, value: f32) -> RawAudioFrame {
+    let mut frame = RawAudioFrame::new(
+        Sample::F32(SampleType::Planar),
+        FRAME_SAMPLES,
+        ChannelLayout::STEREO,
+    );
+    frame.set_rate(SAMPLE_RATE);
+    for plane in 0..frame.planes() {
+        frame.plane_mut::<f32>(plane).fill(value);
+    }
+    frame.set_pts(Some((index * FRAME_SAMPLES) as i64));
+    frame
+}
+
+fn pull_all(mixer: &mut AudioMixer) -> Vec<RawAudioFrame> {
+    std::iter::from_fn(|| mixer.pull().unwrap()).collect()
+}
+
+fn samples(frames: &[RawAudioFrame]) -> usize {
+    frames.iter().map(|frame| frame.samples()).sum()
+}
+
+#[test]
+fn test_empty_input_is_silence() {
+    rsmedia::init().unwrap();
+    let time_base = Rational::new(1, SAMPLE_RATE as i32);
+    let mut mixer = AudioMixer::new(SAMPLE_RATE, 2);
+    let voice = mixer.add_input(time_base, 1.0).unwrap();
+    let music = mixer.add_input(time_base, 1.0).unwrap();
+    for index in 0..10 {
+        mixer.push(voice, frame(index, 0.5)).unwrap();
+    }
+    mixer.end_input(music).unwrap();
+    mixer.finish().unwrap();
+
+    let mixed = pull_all(&mut mixer);
+    assert_eq!(samples(&mixed), 10 * FRAME_SAMPLES);
+    assert!(mixed
+        .iter()
+        .all(|frame| frame.plane::<f32>(0).iter().all(|sample| *sample == 0.5)));
+}
+
+#[test]
+fn test_late_input_is_padded() {
+    rsmedia::init().unwrap();
+    let time_base = Rational::new(1, SAMPLE_RATE as i32);
+    let mut mixer = AudioMixer::new(SAMPLE_RATE, 2).with_max_delay(Duration::from_millis(100));
+    let voice = mixer.add_input(time_base, 1.0).unwrap();
+    let music = mixer.add_input(time_base, 1.0).unwrap();
+
+    // Without padding, the mix would wait for the first frame of the music.
+    let mut mixed = Vec::new();
+    for index in 0..50 {
+        mixer.push(voice, frame(index, 0.5)).unwrap();
+        mixed.extend(pull_all(&mut mixer));
+    }
+    assert!(samples(&mixed) > 0);
+
+    // The music joins at the current position and is mixed in from there.
+    for index in 50..60 {
+        mixer.push(voice, frame(index, 0.5)).unwrap();
+        mixer.push(music, frame(index, 0.25)).unwrap();
+    }
+    mixer.finish().unwrap();
+    mixed.extend(pull_all(&mut mixer));
+    assert_eq!(samples(&mixed), 60 * FRAME_SAMPLES);
+    let last = mixed.last().unwrap().plane::<f32>(0);
+    assert_eq!(last[last.len() - 1], 0.75);
+}
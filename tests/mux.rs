@@ -0,0 +1,130 @@
+mod common;
+
+use std::time::Duration;
+
+use ffmpeg::util::channel_layout::ChannelLayout;
+use ffmpeg::util::format::{sample::Type as SampleType, Sample};
+use ffmpeg::util::mathematics::rescale::Rescale;
+use ffmpeg::Rational;
+use rsmedia::audio::AudioDecoder;
+use rsmedia::decode::Decoder;
+use rsmedia::encode::Settings;
+use rsmedia::frame::RawAudioFrame;
+use rsmedia::io::{Reader, WriteOutcome, Writer, WriterBuilder};
+use rsmedia::mux::MuxerBuilder;
+use rsmedia::record::RecorderBuilder;
+use rsmedia::AudioSettings;
+use tempfile::TempDir;
+
+use common::fixture;
+
+#[test]
+fn test_write_limit_is_an_outcome() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let mut reader = Reader::new(fixture()).unwrap();
+    let writer = WriterBuilder::new(dir.path().join("output.mp4").as_path())
+        .with_max_duration(Duration::from_millis(100))
+        .build()
+        .unwrap();
+    let mut muxer = MuxerBuilder::new(writer)
+        .with_streams(&reader)
+        .unwrap()
+        .build();
+
+    let mut limit_reached = None;
+    while let Ok(packet) = reader.read_any() {
+        if let WriteOutcome::LimitReached(summary) = muxer.mux(packet).unwrap() {
+            limit_reached = Some(summary);
+            break;
+        }
+    }
+    let summary = limit_reached.expect("the limit is reached before the end of the fixture");
+    assert!(summary.duration >= Duration::from_millis(100));
+    assert!(summary.packets > 0);
+
+    // Finishing after the limit does not write the trailer again.
+    assert!(matches!(
+        muxer.finish().unwrap(),
+        Some(WriteOutcome::LimitReached(_))
+    ));
+}
+
+#[test]
+fn test_clean_start_begins_at_keyframe() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let mut reader = Reader::new(fixture()).unwrap();
+    let video_stream_index = reader.best_video_stream_index().unwrap();
+    // Join the source mid-stream, between two keyframes.
+    for _ in 0..10 {
+        reader.read_any().unwrap();
+    }
+    let mut recorder = RecorderBuilder::new(reader, Writer::new(path.as_path()).unwrap())
+        .clean_start(true)
+        .build()
+        .unwrap();
+    while recorder.record().unwrap() {}
+    recorder.finish().unwrap();
+
+    let mut output = Reader::new(path.as_path()).unwrap();
+    let first = output.read(video_stream_index).unwrap();
+    assert!(first.is_key());
+}
+
+#[test]
+fn test_video_and_audio_encoders() {
+    const VIDEO: usize = 0;
+    const AUDIO: usize = 1;
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("output.mp4");
+    let mut decoder = Decoder::new(fixture()).unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    let audio_settings =
+        AudioSettings::new(48_000, 2).with_input_format(Sample::F32(SampleType::Packed));
+    let mut muxer = MuxerBuilder::new(Writer::new(path.as_path()).unwrap())
+        .with_video_encoder(VIDEO, &settings, Rational::new(25, 1))
+        .unwrap()
+        .with_audio_encoder(AUDIO, &audio_settings)
+        .unwrap()
+        .build();
+
+    // One second of video and of audio, in frames that do not match the encoder frame size.
+    let video_time_base = muxer.encoder_time_base(VIDEO).unwrap();
+    for (index, frame) in decoder.decode_raw_iter().take(25).enumerate() {
+        let mut frame = frame.unwrap();
+        frame.set_pts(Some((index as i64).rescale((1, 25), video_time_base)));
+        muxer.encode_video(VIDEO, &frame).unwrap();
+    }
+    let audio_time_base = muxer.encoder_time_base(AUDIO).unwrap();
+    for index in 0..100_i64 {
+        let mut frame =
+            RawAudioFrame::new(Sample::F32(SampleType::Packed), 480, ChannelLayout::STEREO);
+        frame.set_rate(48_000);
+        frame.data_mut(0).fill(0);
+        frame.set_pts(Some((index * 480).rescale((1, 48_000), audio_time_base)));
+        muxer.encode_audio(AUDIO, &frame).unwrap();
+    }
+    // Keys of streams that are not encoded by the muxer are rejected.
+    assert!(muxer.encode_audio(VIDEO, &RawAudioFrame::empty()).is_err());
+    muxer.finish().unwrap();
+    drop(muxer);
+
+    let reader = Reader::new(path.as_path()).unwrap();
+    assert_eq!(reader.input.streams().count(), 2);
+    let mut decoder = Decoder::new(path.as_path()).unwrap();
+    assert_eq!(
+        decoder.decode_raw_iter().take_while(Result::is_ok).count(),
+        25
+    );
+    let mut decoder = AudioDecoder::new(path.as_path()).unwrap();
+    let samples = decoder
+        .decode_raw_iter()
+        .take_while(Result::is_ok)
+        .map(|frame| frame.unwrap().samples())
+        .sum::<usize>();
+    assert!(samples.abs_diff(48_000) <= 2048, "{samples} samples");
+}
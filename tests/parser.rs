@@ -0,0 +1,51 @@
+mod common;
+
+use std::path::Path;
+
+use ffmpeg::codec::Id as AvCodecId;
+use rsmedia::io::{Reader, WriterBuilder};
+use rsmedia::mux::MuxerBuilder;
+use rsmedia::parser::Parser;
+use tempfile::TempDir;
+
+use common::fixture;
+
+/// Extract the video of the fixture as a raw H.264 Annex B elementary stream.
+fn elementary_stream(dir: &Path) -> Vec<u8> {
+    let path = dir.join("video.h264");
+    let mut reader = Reader::new(fixture()).unwrap();
+    let stream = reader.best_video_stream_index().unwrap();
+    let writer = WriterBuilder::new(path.as_path())
+        .with_format("h264")
+        .build()
+        .unwrap();
+    let mut muxer = MuxerBuilder::new(writer)
+        .with_stream(reader.stream_info(stream).unwrap())
+        .unwrap()
+        .build();
+    while let Ok(packet) = reader.read(stream) {
+        muxer.mux(packet).unwrap();
+    }
+    muxer.finish().unwrap();
+    drop(muxer);
+    std::fs::read(path).unwrap()
+}
+
+#[test]
+fn test_parse_elementary_stream() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let data = elementary_stream(dir.path());
+
+    let mut parser = Parser::new(AvCodecId::H264).unwrap();
+    let mut packets = Vec::new();
+    // Chunks that do not line up with packet boundaries, like reads from a socket.
+    for chunk in data.chunks(4000) {
+        packets.extend(parser.parse(chunk).unwrap());
+    }
+    packets.extend(parser.flush().unwrap());
+
+    assert_eq!(packets.len(), 901);
+    assert!(packets[0].is_key());
+    assert!(parser.dimensions().is_some());
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use ffmpeg::util::mathematics::rescale::TIME_BASE;
+use rsmedia::decode::Decoder;
+use rsmedia::error::Error;
+use rsmedia::io::Reader;
+use rsmedia::render::SmartRenderBuilder;
+use rsmedia::time::Time;
+use tempfile::TempDir;
+
+use common::fixture;
+
+#[test]
+fn test_render_range() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let output = dir.path().join("output.mkv");
+    let report = SmartRenderBuilder::new(
+        fixture(),
+        output.as_path(),
+        Time::from_secs_f64(10.0),
+        Time::from_secs_f64(20.0),
+    )
+    .render()
+    .unwrap();
+    assert!(report.reencoded_frames > 0);
+    assert!(report.copied_packets > 0);
+
+    let reader = Reader::new(output.as_path()).unwrap();
+    let duration = Time::new(Some(reader.input.duration()), TIME_BASE).as_secs_f64();
+    assert!((duration - 10.0).abs() < 0.2, "{duration}");
+    let mut decoder = Decoder::new(output.as_path()).unwrap();
+    let frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert!((295..=305).contains(&frames), "{frames}");
+}
+
+#[test]
+fn test_render_rejects_invalid_ranges() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let output = dir.path().join("output.mkv");
+    for (start, end) in [(20.0, 10.0), (10.0, 10.0), (-1.0, 10.0), (10.0, 60.0)] {
+        let result = SmartRenderBuilder::new(
+            fixture(),
+            output.as_path(),
+            Time::from_secs_f64(start),
+            Time::from_secs_f64(end),
+        )
+        .render();
+        assert!(
+            matches!(result, Err(Error::InvalidRange)),
+            "{start}..{end}: {result:?}"
+        );
+        // Nothing is written for invalid ranges.
+        assert!(!output.exists());
+    }
+}
@@ -0,0 +1,115 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use ffmpeg::Error as AvError;
+use rsmedia::error::Error;
+use rsmedia::io::Reader;
+use rsmedia::mux::MuxerBuilder;
+use rsmedia::options::Options;
+use rsmedia::rtmp::{ReconnectPolicy, RtmpWriter, RtmpWriterBuilder};
+use tempfile::TempDir;
+
+use common::fixture;
+
+/// Create a FIFO that stands in for the connection to the server: the connection is lost when the
+/// receiving end is closed.
+fn make_fifo(dir: &Path) -> PathBuf {
+    let fifo = dir.join("stream.flv");
+    let status = std::process::Command::new("mkfifo")
+        .arg(&fifo)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    fifo
+}
+
+/// Receive the start of the stream and close the receiving end, so that writing fails with a
+/// broken pipe.
+fn receive_and_disconnect(fifo: &Path) {
+    let mut data = vec![0; 64 * 1024];
+    std::fs::File::open(fifo)
+        .unwrap()
+        .read_exact(&mut data)
+        .unwrap();
+}
+
+/// Remux the fixture into the writer, and get the first error.
+fn publish(writer: RtmpWriter) -> Result<(), Error> {
+    let mut reader = Reader::new(fixture()).unwrap();
+    let mut muxer = MuxerBuilder::new(writer)
+        .with_streams(&reader)
+        .unwrap()
+        .interleaved()
+        .build();
+    while let Ok(packet) = reader.read_any() {
+        muxer.mux(packet)?;
+    }
+    muxer.finish().map(|_| ())
+}
+
+#[test]
+fn test_reconnect_after_connection_lost() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let fifo = make_fifo(dir.path());
+    let options = Options::default();
+    let receiver = std::thread::spawn({
+        let fifo = fifo.clone();
+        move || {
+            receive_and_disconnect(&fifo);
+            // Accept the connection again once the writer noticed that the first one was lost.
+            std::thread::sleep(Duration::from_millis(500));
+            std::fs::read(fifo).unwrap()
+        }
+    });
+    let writer = RtmpWriterBuilder::new(fifo.as_path())
+        .with_options(&options)
+        .with_reconnect(
+            ReconnectPolicy::new(3)
+                .with_delay(Duration::from_millis(10), Duration::from_millis(10)),
+        )
+        .build()
+        .unwrap();
+    publish(writer).unwrap();
+
+    // The streams are published again from the start, with a header of their own.
+    let data = receiver.join().unwrap();
+    assert!(data.starts_with(b"FLV"));
+}
+
+#[test]
+fn test_interrupt_while_waiting_to_reconnect() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let fifo = make_fifo(dir.path());
+    let options = Options::default();
+    std::thread::spawn({
+        let fifo = fifo.clone();
+        move || receive_and_disconnect(&fifo)
+    });
+    let writer = RtmpWriterBuilder::new(fifo.as_path())
+        .with_options(&options)
+        .with_reconnect(
+            ReconnectPolicy::new(3).with_delay(Duration::from_secs(60), Duration::from_secs(60)),
+        )
+        .build()
+        .unwrap();
+    let interrupt = writer.interrupt();
+    std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_secs(1));
+        interrupt.cancel();
+    });
+
+    let started = Instant::now();
+    let result = publish(writer);
+    assert!(
+        matches!(result, Err(Error::BackendError(AvError::Exit))),
+        "{result:?}"
+    );
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use std::path::Path;
+
+use rsmedia::audio::AudioDecoder;
+use rsmedia::decode::Decoder;
+use rsmedia::sidecar::{AudioCoding, AudioReplacement, DurationAlignment};
+use rsmedia::time::Time;
+use tempfile::TempDir;
+
+use common::{count_frames, fixture, write_audio};
+
+/// Duration of the fixture in seconds.
+fn video_duration() -> f64 {
+    Decoder::new(fixture())
+        .unwrap()
+        .duration()
+        .unwrap()
+        .as_secs_f64()
+}
+
+/// Get the start and the end of the audio of a file in seconds.
+fn audio_span(path: &Path) -> (f64, f64) {
+    let mut decoder = AudioDecoder::new(path).unwrap();
+    let time_base = decoder.time_base();
+    let sample_rate = f64::from(decoder.sample_rate());
+    let mut start = None;
+    let mut samples = 0;
+    for frame in decoder.decode_raw_iter().take_while(Result::is_ok) {
+        let frame = frame.unwrap();
+        start.get_or_insert_with(|| Time::new(frame.pts(), time_base).as_secs_f64());
+        samples += frame.samples();
+    }
+    let start = start.unwrap();
+    (start, start + samples as f64 / sample_rate)
+}
+
+#[test]
+fn test_shorter_audio_is_padded_to_video() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let audio = write_audio(dir.path(), 10.0, 0.0);
+    let output = dir.path().join("output.mp4");
+    AudioReplacement::new(fixture(), audio.as_path())
+        .with_coding(AudioCoding::Encode)
+        .run(output.as_path())
+        .unwrap();
+
+    let (start, end) = audio_span(&output);
+    assert!(start.abs() < 0.1, "audio starts at {start}s");
+    assert!((end - video_duration()).abs() < 0.1, "audio ends at {end}s");
+    assert_eq!(count_frames(&output), 901);
+}
+
+#[test]
+fn test_shorter_audio_is_not_padded_when_copied() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let audio = write_audio(dir.path(), 10.0, 0.0);
+    let output = dir.path().join("output.mp4");
+    AudioReplacement::new(fixture(), audio.as_path())
+        .with_coding(AudioCoding::Copy)
+        .run(output.as_path())
+        .unwrap();
+
+    let (_, end) = audio_span(&output);
+    assert!((end - 10.0).abs() < 0.1, "audio ends at {end}s");
+    assert_eq!(count_frames(&output), 901);
+}
+
+#[test]
+fn test_shorter_audio_ends_output_with_shortest() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let audio = write_audio(dir.path(), 10.0, 0.0);
+    let output = dir.path().join("output.mp4");
+    AudioReplacement::new(fixture(), audio.as_path())
+        .with_alignment(DurationAlignment::Shortest)
+        .run(output.as_path())
+        .unwrap();
+
+    // The video is cut at the end of the audio, give or take a frame.
+    let video_end = Decoder::new(output.as_path())
+        .unwrap()
+        .duration()
+        .unwrap()
+        .as_secs_f64();
+    assert!((video_end - 10.0).abs() < 0.1, "video ends at {video_end}s");
+}
+
+#[test]
+fn test_longer_audio_is_trimmed_to_video() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let audio = write_audio(dir.path(), 40.0, 0.0);
+    let output = dir.path().join("output.mp4");
+    AudioReplacement::new(fixture(), audio.as_path())
+        .run(output.as_path())
+        .unwrap();
+
+    // Copied audio is trimmed at a packet boundary.
+    let (_, end) = audio_span(&output);
+    assert!((end - video_duration()).abs() < 0.1, "audio ends at {end}s");
+    assert_eq!(count_frames(&output), 901);
+}
+
+#[test]
+fn test_longer_audio_is_kept_with_longest() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let audio = write_audio(dir.path(), 40.0, 0.0);
+    let output = dir.path().join("output.mp4");
+    AudioReplacement::new(fixture(), audio.as_path())
+        .with_alignment(DurationAlignment::Longest)
+        .run(output.as_path())
+        .unwrap();
+
+    let (_, end) = audio_span(&output);
+    assert!((end - 40.0).abs() < 0.1, "audio ends at {end}s");
+    assert_eq!(count_frames(&output), 901);
+}
+
+#[test]
+fn test_offset_audio_keeps_its_position() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    // Audio that starts two seconds into the video, like a recording that was started late.
+    let audio = write_audio(dir.path(), 10.0, 2.0);
+    for coding in [AudioCoding::Copy, AudioCoding::Encode] {
+        let output = dir.path().join(format!("output-{coding:?}.mp4"));
+        AudioReplacement::new(fixture(), audio.as_path())
+            .with_coding(coding)
+            .with_alignment(DurationAlignment::Longest)
+            .run(output.as_path())
+            .unwrap();
+
+        let (start, end) = audio_span(&output);
+        assert!(
+            (start - 2.0).abs() < 0.1,
+            "{coding:?} audio starts at {start}s"
+        );
+        assert!((end - 12.0).abs() < 0.1, "{coding:?} audio ends at {end}s");
+    }
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use rsmedia::decode::Decoder;
+use rsmedia::encode::{Encoder, Settings};
+use rsmedia::frame::PixelFormat;
+use rsmedia::options::Options;
+use tempfile::TempDir;
+
+use common::fixture;
+
+fn settings() -> Settings {
+    let (width, height) = Decoder::new(fixture()).unwrap().size();
+    Settings::preset_h264_yuv420p(width as usize, height as usize, false)
+}
+
+/// Settings with every field that a snapshot keeps. The number of threads is set, since snapshots
+/// always set it.
+fn full_settings() -> Settings {
+    let (width, height) = Decoder::new(fixture()).unwrap().size();
+    let mut options = Options::preset_h264();
+    options.set("threads", "2");
+    Settings::preset_h264_custom(
+        width as usize,
+        height as usize,
+        PixelFormat::YUV420P,
+        options,
+    )
+    .with_keyframe_interval(48)
+    .with_crf(20)
+    .with_max_bitrate(4_000_000)
+    .with_max_b_frames(2)
+    .with_closed_gop()
+}
+
+fn threads(options: &[(String, String)]) -> Vec<&str> {
+    options
+        .iter()
+        .filter(|(key, _)| key == "threads")
+        .map(|(_, value)| value.as_str())
+        .collect()
+}
+
+#[test]
+fn test_snapshot_sets_threads() {
+    rsmedia::init().unwrap();
+    let snapshot = settings().snapshot();
+    assert_eq!(threads(&snapshot.options), ["1"]);
+}
+
+#[test]
+fn test_encoder_snapshot_reopens() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let encoder = Encoder::new(dir.path().join("first.mp4").as_path(), settings()).unwrap();
+    let snapshot = encoder.settings_snapshot().clone();
+    assert_eq!(threads(&snapshot.options).len(), 1);
+    assert_ne!(threads(&snapshot.options)[0], "0");
+
+    // The effective options are all accepted when the encoder is opened again.
+    let settings = Settings::from_snapshot(&snapshot).unwrap();
+    let encoder = Encoder::new(dir.path().join("second.mp4").as_path(), settings).unwrap();
+    assert!(encoder.unused_options().is_empty());
+    assert_eq!(encoder.settings_snapshot().options, snapshot.options);
+}
+
+#[test]
+fn test_snapshot_round_trip() {
+    rsmedia::init().unwrap();
+    let full = full_settings();
+    assert_eq!(Settings::from_snapshot(&full.snapshot()).unwrap(), full);
+
+    // Without a number of threads, the restored settings have the one the snapshot pinned.
+    let settings = settings();
+    let restored = Settings::from_snapshot(&settings.snapshot()).unwrap();
+    assert_ne!(restored, settings);
+    assert_eq!(restored.snapshot(), settings.snapshot());
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_snapshot_serde_round_trip() {
+    rsmedia::init().unwrap();
+    let settings = full_settings();
+    let json = serde_json::to_string(&settings.snapshot()).unwrap();
+    let snapshot = serde_json::from_str(&json).unwrap();
+    assert_eq!(Settings::from_snapshot(&snapshot).unwrap(), settings);
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "ndarray")]
+
+mod common;
+
+use rsmedia::decode::DecoderBuilder;
+use rsmedia::frame::Frame;
+use rsmedia::resize::Resize;
+use rsmedia::thumbnail::{extract_thumbnail, extract_thumbnails};
+use rsmedia::time::Time;
+
+use common::fixture;
+
+const SIZE: (u32, u32) = (160, 120);
+
+/// Decode every frame of the fixture at the thumbnail size, with its timestamp in seconds.
+fn decode_all() -> Vec<(f64, Frame)> {
+    DecoderBuilder::new(fixture())
+        .with_resize(Resize::Fit(SIZE.0, SIZE.1))
+        .build()
+        .unwrap()
+        .decode_iter()
+        .take_while(Result::is_ok)
+        .map(Result::unwrap)
+        .map(|(time, frame)| (time.as_secs_f64(), frame))
+        .collect()
+}
+
+#[test]
+fn test_thumbnail_is_first_frame_at_timestamp() {
+    rsmedia::init().unwrap();
+    let frames = decode_all();
+    let at = frames[frames.len() / 2].0 - 0.001;
+    let expected = &frames.iter().find(|(time, _)| *time >= at).unwrap().1;
+    let thumbnail = extract_thumbnail(fixture(), Time::from_secs_f64(at), SIZE).unwrap();
+    assert_eq!(thumbnail.shape(), expected.shape());
+    assert_eq!(&thumbnail, expected);
+}
+
+#[test]
+fn test_thumbnail_past_end_is_last_frame() {
+    rsmedia::init().unwrap();
+    let frames = decode_all();
+    let (end, last) = frames.last().unwrap();
+    let thumbnail = extract_thumbnail(fixture(), Time::from_secs_f64(end + 10.0), SIZE).unwrap();
+    assert_eq!(&thumbnail, last);
+}
+
+#[test]
+fn test_thumbnails_keep_timestamp_order() {
+    rsmedia::init().unwrap();
+    let frames = decode_all();
+    let (end, last) = frames.last().unwrap();
+    let first = &frames[0].1;
+    let thumbnails = extract_thumbnails(
+        fixture(),
+        &[Time::from_secs_f64(end + 10.0), Time::from_secs_f64(0.0)],
+        SIZE,
+    )
+    .unwrap();
+    assert_eq!(thumbnails.len(), 2);
+    assert_eq!(&thumbnails[0], last);
+    assert_eq!(&thumbnails[1], first);
+}
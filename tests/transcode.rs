@@ -0,0 +1,164 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ffmpeg::util::format::Pixel;
+use ffmpeg::util::mathematics::rescale::Rescale;
+use rsmedia::decode::Decoder;
+use rsmedia::encode::{Deadline, Encoder, Settings};
+use rsmedia::events::EventKind;
+use rsmedia::frame::RawFrame;
+use rsmedia::interrupt::Interrupt;
+use rsmedia::transcode::Transcoder;
+use tempfile::TempDir;
+
+use common::fixture;
+
+#[test]
+fn test_transcode_to_end_of_stream() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let output = dir.path().join("output.mp4");
+
+    let decoder = Decoder::new(fixture()).unwrap();
+    let frames = decoder.frames().unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    let encoder = Encoder::new(output.as_path(), settings).unwrap();
+
+    let mut transcoder = Transcoder::new(decoder, encoder);
+    let events = transcoder.events();
+    let progress = transcoder.run().unwrap();
+    drop(transcoder);
+
+    assert!(progress.frames > 0);
+    assert!(progress.frames.abs_diff(frames) <= 1);
+    let events = events
+        .try_iter()
+        .map(|event| event.kind)
+        .collect::<Vec<_>>();
+    assert!(matches!(events.last(), Some(EventKind::Eof)));
+    assert!(!events
+        .iter()
+        .any(|kind| matches!(kind, EventKind::Error { .. })));
+
+    // The trailer was written, so the output can be read back to the end.
+    let mut decoder = Decoder::new(output).unwrap();
+    let decoded = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(decoded as u64, progress.frames);
+}
+
+#[test]
+fn test_transcode_hardware_falls_back_to_system_memory() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+
+    let decoder = Decoder::new(fixture()).unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    let mut transcoder =
+        Transcoder::new_hardware(decoder, dir.path().join("output.mp4"), settings).unwrap();
+    assert!(!transcoder.shares_hardware_frames());
+    assert!(transcoder.run().unwrap().frames > 0);
+}
+
+#[test]
+fn test_shutdown_signal_writes_trailer() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let output = dir.path().join("output.mp4");
+
+    let decoder = Decoder::new(fixture()).unwrap();
+    let frames = decoder.frames().unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    let encoder = Encoder::new(output.as_path(), settings).unwrap();
+
+    let signal = Interrupt::new();
+    let handle = signal.clone();
+    let mut transcoder = Transcoder::new(decoder, encoder)
+        .with_shutdown(signal, Duration::from_secs(60))
+        .with_progress(move |progress| {
+            if progress.frames == 100 {
+                handle.cancel();
+            }
+        });
+    let progress = transcoder.run().unwrap();
+    let report = transcoder.shutdown_report().unwrap();
+    drop(transcoder);
+
+    assert!(progress.frames >= 100);
+    assert!(progress.frames < frames);
+    assert!(report.trailer_written);
+    assert_eq!(report.frames_dropped, 0);
+    assert_eq!(report.frames_flushed, report.frames_in_flight);
+
+    // All frames that were in flight made it into a complete output.
+    let mut decoder = Decoder::new(output).unwrap();
+    let decoded = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(decoded as u64, progress.frames);
+}
+
+#[test]
+fn test_shutdown_without_signal_is_never_triggered() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+
+    let decoder = Decoder::new(fixture()).unwrap();
+    let (width, height) = decoder.size();
+    let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+    let encoder = Encoder::new(dir.path().join("output.mp4").as_path(), settings).unwrap();
+    let mut transcoder = Transcoder::new(decoder, encoder);
+    transcoder.run().unwrap();
+    assert!(transcoder.shutdown_report().is_none());
+
+    // Shutting down after the end of the stream has nothing left to flush.
+    let report = transcoder.shutdown(Deadline::Unbounded).unwrap();
+    assert_eq!(report.frames_flushed, 0);
+    assert_eq!(report.frames_dropped, 0);
+}
+
+/// Write a transport stream in which the resolution changes, by concatenating segments of
+/// different sizes, and get its path.
+fn write_stream_with_size_changes(dir: &Path, sizes: &[(u32, u32)]) -> PathBuf {
+    let mut stream = Vec::new();
+    for (index, &(width, height)) in sizes.iter().enumerate() {
+        let path = dir.join(format!("{index}.ts"));
+        let settings = Settings::preset_h264_yuv420p(width as usize, height as usize, false);
+        let mut encoder = Encoder::new(path.as_path(), settings).unwrap();
+        let time_base = encoder.time_base();
+        for frame_index in 0..10_i64 {
+            let mut frame = RawFrame::new(Pixel::RGB24, width, height);
+            frame.data_mut(0).fill(128);
+            frame.set_pts(Some(frame_index.rescale((1, 25), time_base)));
+            encoder.encode_raw(frame).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(encoder);
+        stream.extend(std::fs::read(&path).unwrap());
+    }
+    let path = dir.join("stream.ts");
+    std::fs::write(&path, stream).unwrap();
+    path
+}
+
+#[test]
+fn test_transcode_stream_with_size_changes() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let input = write_stream_with_size_changes(dir.path(), &[(64, 48), (128, 96), (64, 48)]);
+    let output = dir.path().join("output.mp4");
+
+    // Every part of the input is scaled to the size of the encoder with a scaler of its own.
+    let decoder = Decoder::new(input.as_path()).unwrap();
+    let settings = Settings::preset_h264_yuv420p(96, 72, false);
+    let encoder = Encoder::new(output.as_path(), settings).unwrap();
+    let progress = Transcoder::new(decoder, encoder).run().unwrap();
+    assert_eq!(progress.frames, 30);
+
+    let mut decoder = Decoder::new(output).unwrap();
+    assert_eq!(decoder.size(), (96, 72));
+    let decoded = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert_eq!(decoded, 30);
+}
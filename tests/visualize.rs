@@ -0,0 +1,59 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use rsmedia::decode::Decoder;
+use rsmedia::encode::{Encoder, Settings};
+use rsmedia::io::Reader;
+use rsmedia::location::Location;
+use rsmedia::visualize::AudioVisualizer;
+use tempfile::TempDir;
+
+use common::{fixture, write_audio};
+
+/// Write an image of a size from the first frame of the fixture, and get its path.
+fn write_image(dir: &Path, width: usize, height: usize) -> PathBuf {
+    let frame = Decoder::new(fixture()).unwrap().decode_raw().unwrap();
+    let location = Location::image_sequence(dir.join("cover%d.png"), (1, 1));
+    let mut encoder = Encoder::new(location, Settings::preset_png(width, height)).unwrap();
+    encoder.encode_raw(frame).unwrap();
+    encoder.finish().unwrap();
+    dir.join("cover1.png")
+}
+
+#[test]
+fn test_still_plus_audio() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let image = write_image(dir.path(), 320, 240);
+    let audio = write_audio(dir.path(), 3.0, 0.0);
+    let output = dir.path().join("output.mp4");
+    AudioVisualizer::still_plus_audio(image.as_path(), audio.as_path(), output.as_path()).unwrap();
+
+    let reader = Reader::new(output.as_path()).unwrap();
+    assert!(reader.best_audio_stream_index().is_ok());
+    let mut decoder = Decoder::new(output.as_path()).unwrap();
+    assert_eq!(decoder.size(), (320, 240));
+    // One frame per second, for as long as the audio lasts.
+    let frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert!((3..=4).contains(&frames), "{frames} frames");
+}
+
+#[test]
+fn test_still_with_odd_size_is_scaled_to_even_size() {
+    rsmedia::init().unwrap();
+    let dir = TempDir::new().unwrap();
+    let image = write_image(dir.path(), 321, 241);
+    let audio = write_audio(dir.path(), 1.0, 0.0);
+    let output = dir.path().join("output.mp4");
+    AudioVisualizer::new()
+        .with_frame_rate(4)
+        .with_keyframe_interval(2)
+        .render_still(image.as_path(), audio.as_path(), output.as_path())
+        .unwrap();
+
+    let mut decoder = Decoder::new(output.as_path()).unwrap();
+    assert_eq!(decoder.size(), (320, 240));
+    let frames = decoder.decode_raw_iter().take_while(Result::is_ok).count();
+    assert!((4..=5).contains(&frames), "{frames} frames");
+}